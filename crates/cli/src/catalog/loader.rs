@@ -17,26 +17,58 @@ use std::{collections::HashMap, fs, path::Path};
 
 use console::style;
 use moneymq_types::{
-    Currency, ExperimentConfig, Price, PricingType, Product, ProductFeature,
-    merge_product_with_variant,
+    Currency, Decimal, ExperimentConfig, Meter, Price, PriceTier, PriceTierSchema, PricingType,
+    Product, ProductFeature, TiersMode, iac::dollars_to_cents, merge_product_with_variant,
+    round_cents, x402::STABLECOIN_DECIMALS,
 };
 use serde_json::Value as JsonValue;
 
 /// Load all products from a catalog directory
 ///
-/// Supports both legacy flat files and variant-based directories
+/// Supports both legacy flat files and variant-based directories. Files that fail to load
+/// are skipped with a warning.
 pub fn load_products_from_directory(
     catalog_dir: &Path,
 ) -> Result<HashMap<String, Product>, String> {
+    let (products, errors) = load_products_with_errors(catalog_dir)?;
+    for error in errors {
+        eprintln!("{} {}", style("Warning:").yellow(), error);
+    }
+    Ok(products)
+}
+
+/// Load all products from a catalog directory, along with the errors of the files that
+/// failed to load (which are skipped) and of duplicate product IDs
+pub fn load_products_with_errors(
+    catalog_dir: &Path,
+) -> Result<(HashMap<String, Product>, Vec<String>), String> {
     let mut products = HashMap::new();
+    let mut errors = Vec::new();
 
     if !catalog_dir.exists() {
-        return Ok(products);
+        return Ok((products, errors));
     }
 
     let entries = fs::read_dir(catalog_dir)
         .map_err(|e| format!("Failed to read catalog directory: {}", e))?;
 
+    let mut insert = |product: Product, errors: &mut Vec<String>| {
+        // Products with a price that can't be charged, or settled in stablecoins, are skipped
+        if let Some(e) = product.prices.iter().find_map(|price| {
+            price
+                .validate_pricing()
+                .and_then(|_| price.validate_token_decimals(STABLECOIN_DECIMALS))
+                .err()
+        }) {
+            errors.push(format!("Product '{}': {}", product.id, e));
+            return;
+        }
+        if products.contains_key(&product.id) {
+            errors.push(format!("Duplicate product ID '{}'", product.id));
+        }
+        products.insert(product.id.clone(), product);
+    };
+
     for entry in entries {
         let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
         let path = entry.path();
@@ -53,38 +85,56 @@ pub fn load_products_from_directory(
                 match load_product_with_variants(&path, dir_name) {
                     Ok(loaded_products) => {
                         for product in loaded_products {
-                            products.insert(product.id.clone(), product);
+                            insert(product, &mut errors);
                         }
                     }
-                    Err(e) => {
-                        eprintln!(
-                            "{} Failed to parse product directory {}: {}",
-                            style("Warning:").yellow(),
-                            path.display(),
-                            e
-                        );
-                    }
+                    Err(e) => errors.push(format!(
+                        "Failed to parse product directory {}: {}",
+                        path.display(),
+                        e
+                    )),
                 }
             }
         } else if path.extension().and_then(|s| s.to_str()) == Some("yaml") {
             // Legacy flat file format
             match load_legacy_product(&path) {
-                Ok(product) => {
-                    products.insert(product.id.clone(), product);
-                }
-                Err(e) => {
-                    eprintln!(
-                        "{} Failed to parse {}: {}",
-                        style("Warning:").yellow(),
-                        path.display(),
-                        e
-                    );
-                }
+                Ok(product) => insert(product, &mut errors),
+                Err(e) => errors.push(format!("Failed to parse {}: {}", path.display(), e)),
             }
         }
     }
 
-    Ok(products)
+    Ok((products, errors))
+}
+
+/// Load all meters from a catalog's `meters` directory, along with the errors of the files
+/// that failed to load (which are skipped)
+pub fn load_meters_with_errors(meters_dir: &Path) -> Result<(Vec<Meter>, Vec<String>), String> {
+    let mut meters = Vec::new();
+    let mut errors = Vec::new();
+
+    if !meters_dir.exists() {
+        return Ok((meters, errors));
+    }
+
+    let entries =
+        fs::read_dir(meters_dir).map_err(|e| format!("Failed to read meters directory: {}", e))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
+        let path = entry.path();
+
+        if path.extension().and_then(|s| s.to_str()) == Some("yaml") {
+            let content = fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            match serde_yml::from_str::<Meter>(&content) {
+                Ok(meter) => meters.push(meter),
+                Err(e) => errors.push(format!("Failed to parse {}: {}", path.display(), e)),
+            }
+        }
+    }
+
+    Ok((meters, errors))
 }
 
 /// Load a legacy flat product YAML file
@@ -370,7 +420,7 @@ pub fn json_to_product(json: JsonValue) -> Result<Product, String> {
     let features = extract_features(obj.get("features"));
 
     // Extract and convert price (singular)
-    let prices = extract_price(obj.get("price"))?;
+    let mut prices = extract_price(obj.get("price"))?;
 
     // Extract experiment config (for A/B testing variants that inherit features from parent)
     let experiment = obj.get("experiment").and_then(|v| {
@@ -406,6 +456,18 @@ pub fn json_to_product(json: JsonValue) -> Result<Product, String> {
         id
     };
 
+    // A price without an ID gets the same one on every load, so edits stored in the
+    // catalog DB apply to it after a restart or reload
+    let has_price_id = obj
+        .get("price")
+        .and_then(|price| price.get("id"))
+        .is_some_and(|price_id| price_id.is_string());
+    if !has_price_id {
+        for price in &mut prices {
+            price.id = format!("price_{}", id);
+        }
+    }
+
     // Build Product using Product::new() for proper timestamps
     let mut product = Product::new()
         .with_some_name(name)
@@ -498,25 +560,82 @@ fn extract_price(price_value: Option<&JsonValue>) -> Result<Vec<Price>, String>
 fn json_to_price(json: &JsonValue) -> Result<Price, String> {
     let obj = json.as_object().ok_or("Expected price to be an object")?;
 
+    // Convert dollars to exact cents, which may have a fraction of a cent
+    let cents = |amount: &JsonValue| {
+        serde_json::from_value::<Decimal>(amount.clone())
+            .ok()
+            .and_then(dollars_to_cents)
+    };
+
+    // Amounts in other currencies than the price's own - `amounts: { usd: 49.00, eur: 45.00 }`
+    let mut currency_options = indexmap::IndexMap::new();
+
+    // Tiers charged instead of amounts -
+    // `tiers: [{ up_to: 1000, unit_amounts: { usd: 0.01 } }, { unit_amounts: { usd: 0.008 } }]`
+    let tiers = match obj.get("tiers") {
+        Some(tiers) => serde_json::from_value::<Vec<PriceTierSchema>>(tiers.clone())
+            .map_err(|e| format!("Invalid price tiers: {}", e))?,
+        None => Vec::new(),
+    };
+
     // Extract amounts map - new format uses `amounts: { usd: 49.00 }`
     let (currency, unit_amount) = if let Some(JsonValue::Object(amounts)) = obj.get("amounts") {
-        // Get the first currency/amount pair
-        if let Some((currency_str, amount_value)) = amounts.iter().next() {
+        // The USD amount is the price's own when there is one, and the first otherwise
+        let primary = amounts
+            .keys()
+            .find(|currency| currency.eq_ignore_ascii_case("usd"))
+            .or_else(|| amounts.keys().next());
+        if let Some(currency_str) = primary {
             let currency = Currency::parse(currency_str).unwrap_or(Currency::Usd);
-            // Convert float dollars to integer cents
-            let amount = amount_value.as_f64().map(|a| (a * 100.0).round() as i64);
+            let amount = amounts.get(currency_str.as_str()).and_then(cents);
+            for (other, amount_value) in amounts {
+                if other != currency_str
+                    && let Some(amount) = cents(amount_value)
+                {
+                    // Only the price's own amount can be below a cent
+                    if !amount.fract().is_zero() {
+                        return Err(format!(
+                            "Amount {} {} has a fraction of a cent, only supported in {}",
+                            amount_value, other, currency_str
+                        ));
+                    }
+                    currency_options.insert(other.to_lowercase(), round_cents(amount));
+                }
+            }
             (currency, amount)
         } else {
             (Currency::Usd, None)
         }
     } else {
+        // Tiered prices are in the currency of their tiers, USD when they have it
+        let tier_currencies = tiers
+            .iter()
+            .flat_map(|tier| tier.unit_amounts.keys().chain(tier.flat_amounts.keys()))
+            .collect::<Vec<_>>();
+        let tier_currency = tier_currencies
+            .iter()
+            .find(|currency| currency.eq_ignore_ascii_case("usd"))
+            .or(tier_currencies.first())
+            .map(|currency| currency.as_str());
+
         // Fallback to legacy format for backwards compatibility
         let currency_str = obj
             .get("currency")
             .and_then(|v| v.as_str())
+            .or(tier_currency)
             .unwrap_or("usd");
         let currency = Currency::parse(currency_str).unwrap_or(Currency::Usd);
-        let amount = obj.get("unit_amount").and_then(|v| v.as_i64());
+        // Cents, with a fraction of a cent as `unit_amount_decimal: "0.1"`
+        let amount = match obj.get("unit_amount_decimal") {
+            Some(decimal) => Some(
+                serde_json::from_value::<Decimal>(decimal.clone())
+                    .map_err(|e| format!("Invalid unit_amount_decimal: {}", e))?,
+            ),
+            None => obj
+                .get("unit_amount")
+                .and_then(|v| v.as_i64())
+                .map(Decimal::from),
+        };
         (currency, amount)
     };
 
@@ -531,7 +650,10 @@ fn json_to_price(json: &JsonValue) -> Result<Price, String> {
     };
 
     // Build price using Price::new() for proper timestamps
-    let mut price = Price::new(currency, pricing_type).with_some_amount(unit_amount);
+    let mut price = Price::new(currency, pricing_type).with_currency_options(currency_options);
+    if let Some(cents) = unit_amount {
+        price = price.with_exact_amount(cents);
+    }
 
     // Extract optional fields
     if let Some(id) = obj.get("id").and_then(|v| v.as_str()) {
@@ -572,6 +694,37 @@ fn json_to_price(json: &JsonValue) -> Result<Price, String> {
         }
     }
 
+    // Overage billed against a meter - `overage: { meter: api_calls, amounts: { usd: 0.01 } }`
+    if let Some(overage) = obj.get("overage") {
+        price.overage = serde_json::from_value(overage.clone()).ok();
+    }
+
+    // How quantities are charged across the tiers - `tiers_mode: graduated`
+    let tiers_mode = match obj.get("tiers_mode").and_then(|v| v.as_str()) {
+        Some(mode) => Some(TiersMode::parse(mode).ok_or_else(|| {
+            format!(
+                "Invalid tiers_mode '{}', expected one of {}",
+                mode,
+                TiersMode::valid_values()
+            )
+        })?),
+        None => None,
+    };
+    price = price.with_tiers(tiers.into_iter().map(PriceTier::from).collect(), tiers_mode);
+
+    // Package pricing - `transform_quantity: { divide_by: 1000, round: up }`
+    if let Some(transform) = obj.get("transform_quantity") {
+        price.transform_quantity = Some(
+            serde_json::from_value(transform.clone())
+                .map_err(|e| format!("Invalid transform_quantity: {}", e))?,
+        );
+    }
+
+    // Whether the amount includes tax - `tax_behavior: inclusive`
+    if let Some(tax_behavior) = obj.get("tax_behavior").and_then(|v| v.as_str()) {
+        price.tax_behavior = moneymq_types::TaxBehavior::parse(tax_behavior);
+    }
+
     Ok(price)
 }
 
@@ -744,6 +897,7 @@ price:
         let pro = products.get("surfnet-pro").unwrap();
         assert_eq!(pro.name, Some("Surfnet Pro".to_string()));
         assert_eq!(pro.prices[0].unit_amount, Some(999)); // 9.99 * 100
+        assert_eq!(pro.prices[0].id, "price_surfnet-pro");
     }
 
     #[test]
@@ -1216,4 +1370,101 @@ price:
         // experiment should default to None
         assert!(pro.experiment.is_none());
     }
+
+    #[test]
+    fn test_price_amounts_in_several_currencies() {
+        let price = json_to_price(&serde_json::json!({
+            "amounts": { "eur": 45.0, "usd": 49.0, "gbp": 39.5 }
+        }))
+        .unwrap();
+
+        // The USD amount is the price's own even when not listed first
+        assert_eq!(price.currency, Currency::Usd);
+        assert_eq!(price.unit_amount, Some(4900));
+        assert_eq!(price.amount_in("EUR"), Some(4500));
+        assert_eq!(price.amount_in("gbp"), Some(3950));
+        assert_eq!(price.amount_in("usd"), Some(4900));
+        assert_eq!(price.currency_options.len(), 2);
+
+        let price = json_to_price(&serde_json::json!({ "amounts": { "eur": 45.0 } })).unwrap();
+        assert_eq!(price.currency, Currency::Eur);
+        assert!(price.currency_options.is_empty());
+    }
+
+    #[test]
+    fn test_tiered_and_package_prices() {
+        let price = json_to_price(&serde_json::json!({
+            "tiers_mode": "graduated",
+            "tiers": [
+                { "up_to": 1000, "unit_amounts": { "usd": 0.02 } },
+                { "unit_amounts": { "usd": 0.01 }, "flat_amounts": { "usd": 5.0 } }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(price.currency, Currency::Usd);
+        assert_eq!(price.unit_amount, None);
+        assert!(price.is_tiered());
+        assert_eq!(price.amount_for(1500, "usd"), Some(2000 + 500 + 500));
+
+        let price = json_to_price(&serde_json::json!({
+            "amounts": { "usd": 15.0 },
+            "transform_quantity": { "divide_by": 1000 }
+        }))
+        .unwrap();
+        assert_eq!(price.amount_for(2001, "usd"), Some(4500));
+
+        assert!(
+            json_to_price(&serde_json::json!({
+                "tiers_mode": "stairstep",
+                "tiers": [{ "unit_amounts": { "usd": 1.0 } }]
+            }))
+            .is_err()
+        );
+
+        // Products with tiers that can't be charged are reported and skipped
+        let temp_dir = TempDir::new().unwrap();
+        let surfnet_dir = temp_dir.path().join("surfnet");
+        fs::create_dir_all(surfnet_dir.join("variants")).unwrap();
+        fs::write(surfnet_dir.join("product.yaml"), "product_type: service\n").unwrap();
+        fs::write(
+            surfnet_dir.join("variants/api.yaml"),
+            "price:\n  tiers_mode: volume\n  tiers:\n    - unit_amounts:\n        usd: 0.01\n    - up_to: 10\n      unit_amounts:\n        usd: 0.02\n",
+        )
+        .unwrap();
+        let (products, errors) = load_products_with_errors(temp_dir.path()).unwrap();
+        assert!(products.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("surfnet-api"));
+    }
+
+    #[test]
+    fn test_sub_cent_price_amounts() {
+        let price = json_to_price(&serde_json::json!({ "amounts": { "usd": 0.001 } })).unwrap();
+        assert_eq!(price.unit_amount, None);
+        assert_eq!(price.unit_amount_decimal, Some(Decimal::new(1, 1)));
+        assert_eq!(price.amount_for(1000, "usd"), Some(100));
+
+        let price = json_to_price(&serde_json::json!({
+            "currency": "usd",
+            "unit_amount_decimal": "0.25"
+        }))
+        .unwrap();
+        assert_eq!(price.unit_amount_decimal, Some(Decimal::new(25, 2)));
+
+        // Amounts with more digits than a float holds are read exactly from strings
+        let price = json_to_price(&serde_json::json!({
+            "amounts": { "usd": "0.00012345678901234567" }
+        }))
+        .unwrap();
+        assert_eq!(
+            price.unit_amount_decimal,
+            Some(Decimal::new(12345678901234567, 18))
+        );
+
+        // Other currencies need whole cents
+        assert!(
+            json_to_price(&serde_json::json!({ "amounts": { "usd": 0.001, "eur": 0.0009 } }))
+                .is_err()
+        );
+    }
 }
@@ -2,6 +2,7 @@ pub mod examples;
 mod fetch;
 pub mod loader;
 mod sync;
+pub mod writer;
 
 pub use fetch::FetchCommand;
 pub use sync::SyncCommand;
@@ -5,7 +5,7 @@ use console::style;
 use dialoguer::Confirm;
 use indicatif::{ProgressBar, ProgressStyle};
 use moneymq_core::api::catalog::stripe;
-use moneymq_types::Product;
+use moneymq_types::{Price, Product};
 
 use crate::Context;
 
@@ -58,6 +58,14 @@ fn determine_sync_action(local: Option<&Product>, remote: &Product) -> SyncActio
     }
 }
 
+/// Whether two prices charge quantities alike: same decimal amount, tiers and package pricing
+fn same_quantity_pricing(a: &Price, b: &Price) -> bool {
+    a.unit_amount_decimal == b.unit_amount_decimal
+        && a.tiers == b.tiers
+        && a.tiers_mode == b.tiers_mode
+        && a.transform_quantity == b.transform_quantity
+}
+
 /// Check if two products have different content (excluding timestamps)
 fn products_differ(local: &Product, remote: &Product) -> bool {
     use moneymq_types::normalize_metadata_for_comparison;
@@ -90,6 +98,7 @@ fn products_differ(local: &Product, remote: &Product) -> bool {
         let matching_remote_price = remote.prices.iter().find(|rp| {
             rp.currency == local_price.currency
                 && rp.unit_amount == local_price.unit_amount
+                && same_quantity_pricing(rp, local_price)
                 && rp.recurring_interval == local_price.recurring_interval
                 && rp.recurring_interval_count == local_price.recurring_interval_count
                 && rp.pricing_type == local_price.pricing_type
@@ -388,6 +397,7 @@ fn display_diff(local: &Product, remote: &Product) {
             let found_in_local = local.prices.iter().any(|lp| {
                 lp.currency == remote_price.currency
                     && lp.unit_amount == remote_price.unit_amount
+                    && same_quantity_pricing(lp, remote_price)
                     && lp.recurring_interval == remote_price.recurring_interval
                     && lp.recurring_interval_count == remote_price.recurring_interval_count
                     && lp.pricing_type == remote_price.pricing_type
@@ -421,6 +431,7 @@ fn display_diff(local: &Product, remote: &Product) {
             let found_in_remote = remote.prices.iter().any(|rp| {
                 rp.currency == local_price.currency
                     && rp.unit_amount == local_price.unit_amount
+                    && same_quantity_pricing(rp, local_price)
                     && rp.recurring_interval == local_price.recurring_interval
                     && rp.recurring_interval_count == local_price.recurring_interval_count
                     && rp.pricing_type == local_price.pricing_type
@@ -454,6 +465,7 @@ fn display_diff(local: &Product, remote: &Product) {
             if let Some(remote_price) = remote.prices.iter().find(|rp| {
                 rp.currency == local_price.currency
                     && rp.unit_amount == local_price.unit_amount
+                    && same_quantity_pricing(rp, local_price)
                     && rp.recurring_interval == local_price.recurring_interval
                     && rp.recurring_interval_count == local_price.recurring_interval_count
                     && rp.pricing_type == local_price.pricing_type
@@ -643,6 +655,7 @@ impl SyncCommand {
                                         sandbox_product.prices.iter().find(|sp| {
                                             sp.currency == prod_price.currency
                                                 && sp.unit_amount == prod_price.unit_amount
+                                                && same_quantity_pricing(sp, prod_price)
                                                 && sp.recurring_interval
                                                     == prod_price.recurring_interval
                                                 && sp.recurring_interval_count
@@ -773,6 +786,7 @@ impl SyncCommand {
                             if let Some(local_price) = local.prices.iter().find(|lp| {
                                 lp.currency == price.currency
                                     && lp.unit_amount == price.unit_amount
+                                    && same_quantity_pricing(lp, price)
                                     && lp.recurring_interval == price.recurring_interval
                                     && lp.recurring_interval_count == price.recurring_interval_count
                                     && lp.pricing_type == price.pricing_type
@@ -843,6 +857,7 @@ impl SyncCommand {
                                     .find(|rp| {
                                         rp.currency == local_price.currency
                                             && rp.unit_amount == local_price.unit_amount
+                                            && same_quantity_pricing(rp, local_price)
                                             && rp.recurring_interval
                                                 == local_price.recurring_interval
                                             && rp.recurring_interval_count
@@ -884,6 +899,7 @@ impl SyncCommand {
                                 if let Some(local_price) = local.prices.iter().find(|lp| {
                                     lp.currency == price.currency
                                         && lp.unit_amount == price.unit_amount
+                                        && same_quantity_pricing(lp, price)
                                         && lp.recurring_interval == price.recurring_interval
                                         && lp.recurring_interval_count
                                             == price.recurring_interval_count
@@ -1682,6 +1698,7 @@ impl SyncCommand {
                             if let Some(local_price) = local.prices.iter().find(|lp| {
                                 lp.currency == price.currency
                                     && lp.unit_amount == price.unit_amount
+                                    && same_quantity_pricing(lp, price)
                                     && lp.recurring_interval == price.recurring_interval
                                     && lp.recurring_interval_count == price.recurring_interval_count
                                     && lp.pricing_type == price.pricing_type
@@ -0,0 +1,295 @@
+//! Write-back of products edited through the API to the catalog files
+//!
+//! Products created through `POST /v1/products` are written as legacy flat files,
+//! `products/{id}.yaml`. Updates go to the file the product was loaded from: legacy files
+//! are rewritten, while the fields the API edits are merged into variant files with
+//! [`merge_product_update`], leaving their other fields as they are.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use indexmap::IndexMap;
+use moneymq_core::api::catalog::store::CatalogWriter;
+use moneymq_types::{
+    Decimal, Product,
+    iac::{PriceSchema, PriceTierSchema, ProductSchema, RecurringConfig, cents_to_dollars},
+};
+
+use crate::{iac::sync::merge_product_update, yaml_util::to_pretty_yaml_with_header};
+
+/// Catalog file a product was loaded from
+#[derive(Debug, PartialEq)]
+enum ProductFile {
+    /// `products/{file}.yaml`, a whole product
+    Legacy(PathBuf),
+    /// A variant of a `products/{product}/product.yaml` base product
+    Variant(PathBuf),
+}
+
+/// Writes products edited through the API back to a catalog's `products/` directory
+pub struct YamlCatalogWriter {
+    products_dir: PathBuf,
+}
+
+impl YamlCatalogWriter {
+    pub fn new(products_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            products_dir: products_dir.into(),
+        }
+    }
+
+    /// Find the file `product_id` is loaded from
+    fn find_product_file(&self, product_id: &str) -> Option<ProductFile> {
+        let entries = fs::read_dir(&self.products_dir).ok()?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                if !path.join("product.yaml").exists() {
+                    continue;
+                }
+                let dir_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+                if let Some(variant) =
+                    find_variant_file(&path.join("variants"), dir_name, "", product_id)
+                {
+                    return Some(ProductFile::Variant(variant));
+                }
+            } else if path.extension().and_then(|s| s.to_str()) == Some("yaml")
+                && fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|content| serde_yml::from_str::<Product>(&content).ok())
+                    .is_some_and(|product| product.id == product_id)
+            {
+                return Some(ProductFile::Legacy(path));
+            }
+        }
+        None
+    }
+}
+
+/// Find the variant file of `product_id` under `variants_dir`, following the IDs the loader
+/// gives variants: the `id` of the variant file, or `{product}-{variant}`
+fn find_variant_file(
+    variants_dir: &Path,
+    product_dir_name: &str,
+    id_prefix: &str,
+    product_id: &str,
+) -> Option<PathBuf> {
+    // Experiment variants are served as `parent#suffix`
+    let product_id = product_id.replace('#', "-");
+    let entries = fs::read_dir(variants_dir).ok()?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let (file, variant_name) = if path.is_dir() {
+            (
+                path.join("product.yaml"),
+                path.file_name().and_then(|n| n.to_str()),
+            )
+        } else if path.extension().and_then(|s| s.to_str()) == Some("yaml") {
+            (path.clone(), path.file_stem().and_then(|n| n.to_str()))
+        } else {
+            continue;
+        };
+        let Some(variant_name) = variant_name else {
+            continue;
+        };
+        let variant_id = if id_prefix.is_empty() {
+            variant_name.to_string()
+        } else {
+            format!("{}-{}", id_prefix, variant_name)
+        };
+
+        let nested_dir = path.join("variants");
+        if path.is_dir() && nested_dir.exists() {
+            if let Some(found) =
+                find_variant_file(&nested_dir, product_dir_name, &variant_id, &product_id)
+            {
+                return Some(found);
+            }
+            continue;
+        }
+
+        let explicit_id = fs::read_to_string(&file)
+            .ok()
+            .and_then(|content| serde_yml::from_str::<serde_yml::Value>(&content).ok())
+            .and_then(|value| value.get("id")?.as_str().map(str::to_string));
+        let id = explicit_id.unwrap_or_else(|| format!("{}-{}", product_dir_name, variant_id));
+        if id == product_id {
+            return Some(file);
+        }
+    }
+    None
+}
+
+/// The fields of `product` a variant file can hold, its price being the first one
+fn product_schema(product: &Product) -> ProductSchema {
+    let dollars = |amounts: &IndexMap<String, i64>| -> IndexMap<String, Decimal> {
+        amounts
+            .iter()
+            .map(|(currency, amount)| (currency.clone(), cents_to_dollars(Decimal::from(*amount))))
+            .collect()
+    };
+    let price = product.prices.first().map(|price| {
+        let mut amounts = IndexMap::new();
+        if let Some(amount) = price.exact_unit_amount() {
+            amounts.insert(
+                price.currency.as_str().to_string(),
+                cents_to_dollars(amount),
+            );
+        }
+        amounts.extend(dollars(&price.currency_options));
+        PriceSchema {
+            id: Some(price.id.clone()),
+            amounts,
+            pricing_type: Some(price.pricing_type),
+            recurring: price.recurring_interval.map(|interval| RecurringConfig {
+                interval,
+                interval_count: price.recurring_interval_count,
+            }),
+            overage: price.overage.clone(),
+            trial: None,
+            tax_behavior: price.tax_behavior,
+            tiers: price
+                .tiers
+                .iter()
+                .map(|tier| PriceTierSchema {
+                    up_to: tier.up_to,
+                    unit_amounts: dollars(&tier.unit_amounts),
+                    flat_amounts: dollars(&tier.flat_amounts),
+                })
+                .collect(),
+            tiers_mode: price.tiers_mode,
+            transform_quantity: price.transform_quantity,
+            active: Some(price.active),
+            nickname: price.nickname.clone(),
+            metadata: None,
+        }
+    });
+
+    ProductSchema {
+        id: product.id.clone(),
+        name: product.name.clone().unwrap_or_else(|| product.id.clone()),
+        // Empty values remove the field from the file
+        description: Some(product.description.clone().unwrap_or_default()),
+        active: Some(product.active),
+        product_type: product.product_type.clone(),
+        statement_descriptor: None,
+        unit_label: Some(product.unit_label.clone().unwrap_or_default()),
+        images: None,
+        metadata: Some(
+            product
+                .metadata
+                .iter()
+                .map(|(key, value)| (key.clone(), serde_json::Value::String(value.clone())))
+                .collect(),
+        ),
+        features: None,
+        price,
+        _source_file: None,
+        _product_dir: None,
+        _variant: None,
+    }
+}
+
+fn write_legacy_product(path: &Path, product: &Product) -> Result<(), String> {
+    let content = to_pretty_yaml_with_header(product, Some("Product"), Some("v1"))?;
+    fs::write(path, content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+impl CatalogWriter for YamlCatalogWriter {
+    fn create_product(&self, product: &Product) -> Result<(), String> {
+        let path = self.products_dir.join(format!("{}.yaml", product.id));
+        if path.exists() || self.find_product_file(&product.id).is_some() {
+            return Err(format!(
+                "A catalog file already defines product '{}'",
+                product.id
+            ));
+        }
+        fs::create_dir_all(&self.products_dir)
+            .map_err(|e| format!("Failed to create products directory: {}", e))?;
+        write_legacy_product(&path, product)
+    }
+
+    fn update_product(&self, product: &Product) -> Result<(), String> {
+        match self.find_product_file(&product.id) {
+            Some(ProductFile::Legacy(path)) => write_legacy_product(&path, product),
+            Some(ProductFile::Variant(path)) => {
+                let existing = fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                let result = merge_product_update(&existing, &product_schema(product));
+                if !result.changed {
+                    return Err(result.message);
+                }
+                fs::write(&path, result.content)
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+            }
+            None => Err(format!(
+                "No catalog file found for product '{}'",
+                product.id
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use moneymq_types::{Currency, Price, PricingType};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::catalog::loader::load_products_from_directory;
+
+    fn write(dir: &Path, file: &str, content: &str) {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_write_back_products() {
+        let temp_dir = TempDir::new().unwrap();
+        let products_dir = temp_dir.path().join("products");
+        write(
+            &products_dir,
+            "surfnet/product.yaml",
+            "name: Surfnet\nproduct_type: service\n",
+        );
+        write(
+            &products_dir,
+            "surfnet/variants/pro.yaml",
+            "name: Surfnet Pro\nprice:\n  amounts:\n    usd: 49.0\n",
+        );
+        let writer = YamlCatalogWriter::new(&products_dir);
+
+        let mut product = Product::new().with_some_name(Some("Team".to_string()));
+        product.id = "prod_team".to_string();
+        product = product.add_price(
+            Price::new(Currency::Usd, PricingType::OneTime).with_some_amount(Some(9900)),
+        );
+        writer.create_product(&product).unwrap();
+        assert!(writer.create_product(&product).is_err());
+        assert_eq!(
+            writer.find_product_file("prod_team"),
+            Some(ProductFile::Legacy(products_dir.join("prod_team.yaml")))
+        );
+
+        let mut products = load_products_from_directory(&products_dir).unwrap();
+        assert_eq!(products["prod_team"].prices[0].unit_amount, Some(9900));
+
+        let mut pro = products.remove("surfnet-pro").unwrap();
+        pro.name = Some("Surfnet Pro (2026)".to_string());
+        pro.prices[0].unit_amount = Some(5900);
+        writer.update_product(&pro).unwrap();
+
+        let products = load_products_from_directory(&products_dir).unwrap();
+        let pro = &products["surfnet-pro"];
+        assert_eq!(pro.name.as_deref(), Some("Surfnet Pro (2026)"));
+        assert_eq!(pro.product_type.as_deref(), Some("service"));
+        assert_eq!(pro.prices[0].unit_amount, Some(5900));
+
+        let mut unknown = Product::new();
+        unknown.id = "prod_unknown".to_string();
+        assert!(writer.update_product(&unknown).is_err());
+    }
+}
@@ -10,6 +10,7 @@ use std::path::Path;
 
 use clap::Parser;
 use console::style;
+use moneymq_types::{Price, x402::STABLECOIN_DECIMALS};
 use serde::{Deserialize, Serialize};
 
 use crate::Context;
@@ -324,8 +325,16 @@ fn lint_products(products_dir: &Path) -> Vec<Diagnostic> {
                     );
                 }
 
-                // Check unit_amount
-                if price.get("unit_amount").is_none() {
+                // Check unit_amount (or unit_amount_decimal below a cent), tiered prices
+                // being charged by their tiers instead
+                let has_tiers = price
+                    .get("tiers")
+                    .and_then(|v| v.as_sequence())
+                    .is_some_and(|tiers| !tiers.is_empty());
+                if price.get("unit_amount").is_none()
+                    && price.get("unit_amount_decimal").is_none()
+                    && !has_tiers
+                {
                     diagnostics.push(
                         Diagnostic::error(
                             "price-requires-unit-amount",
@@ -339,6 +348,22 @@ fn lint_products(products_dir: &Path) -> Vec<Diagnostic> {
                     );
                 }
 
+                // Check tiers, package pricing and amounts below a cent can be charged
+                if let Ok(parsed) = serde_yml::from_value::<Price>(price.clone())
+                    && let Err(e) = parsed
+                        .validate_pricing()
+                        .and_then(|_| parsed.validate_token_decimals(STABLECOIN_DECIMALS))
+                {
+                    diagnostics.push(
+                        Diagnostic::error(
+                            "price-invalid-pricing",
+                            format!("{} (product '{}')", e, product_name),
+                        )
+                        .with_file(file_name.to_string())
+                        .with_entity("price", &price_id),
+                    );
+                }
+
                 // Check pricing_type
                 if price.get("pricing_type").is_none() {
                     diagnostics.push(
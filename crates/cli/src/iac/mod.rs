@@ -10,7 +10,7 @@
 //! - `GET /iac/schema` - Get JSON schema for the IAC structure
 
 pub mod lint;
-mod sync;
+pub(crate) mod sync;
 
 use std::{
     path::{Path, PathBuf},
@@ -156,6 +156,10 @@ pub struct SelfHostedNetworkSchema {
     /// Solana RPC endpoint URL (required)
     pub rpc_url: String,
 
+    /// Fallback Solana RPC endpoint URLs, in order of preference
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_rpc_urls: Vec<String>,
+
     /// Solana WebSocket endpoint URL
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ws_url: Option<String>,
@@ -352,6 +356,10 @@ pub struct IacNetworkEnvConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rpc_url: Option<String>,
 
+    /// Fallback RPC URLs for external validator (SelfHosted only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_rpc_urls: Option<Vec<String>>,
+
     /// WebSocket URL for external validator (SelfHosted only)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ws_url: Option<String>,
@@ -982,6 +990,8 @@ fn get_catalog_path(content: &str) -> String {
 
 #[cfg(test)]
 mod tests {
+    use moneymq_types::Decimal;
+
     use super::*;
 
     #[test]
@@ -1165,6 +1175,7 @@ mod tests {
                 chain: Some(Chain::Solana),
                 recipient: "wallet123".to_string(),
                 rpc_url: "https://api.mainnet-beta.solana.com".to_string(),
+                fallback_rpc_urls: vec![],
                 ws_url: Some("wss://api.mainnet-beta.solana.com".to_string()),
             },
         });
@@ -1309,7 +1320,7 @@ network:
     #[test]
     fn test_yaml_format_one_time_price() {
         let mut amounts = indexmap::IndexMap::new();
-        amounts.insert("usd".to_string(), 99.00);
+        amounts.insert("usd".to_string(), Decimal::new(9900, 2));
 
         let price = PriceSchema {
             id: Some("price_123".to_string()),
@@ -1318,6 +1329,10 @@ network:
             recurring: None,
             overage: None,
             trial: None,
+            tax_behavior: None,
+            tiers: Vec::new(),
+            tiers_mode: None,
+            transform_quantity: None,
             active: Some(true),
             nickname: Some("Lifetime access".to_string()),
             metadata: None,
@@ -1332,7 +1347,7 @@ network:
     #[test]
     fn test_yaml_format_recurring_price() {
         let mut amounts = indexmap::IndexMap::new();
-        amounts.insert("usd".to_string(), 29.00);
+        amounts.insert("usd".to_string(), Decimal::new(2900, 2));
 
         let price = PriceSchema {
             id: Some("price_456".to_string()),
@@ -1344,6 +1359,10 @@ network:
             }),
             overage: None,
             trial: None,
+            tax_behavior: None,
+            tiers: Vec::new(),
+            tiers_mode: None,
+            transform_quantity: None,
             active: Some(true),
             nickname: Some("Monthly Pro".to_string()),
             metadata: None,
@@ -1385,7 +1404,7 @@ network:
     #[test]
     fn test_yaml_format_catalog() {
         let mut amounts = indexmap::IndexMap::new();
-        amounts.insert("usd".to_string(), 9.99);
+        amounts.insert("usd".to_string(), Decimal::new(999, 2));
 
         let catalog = CatalogSchema {
             description: Some("Main product catalog".to_string()),
@@ -1412,6 +1431,10 @@ network:
                     }),
                     overage: None,
                     trial: None,
+                    tax_behavior: None,
+                    tiers: Vec::new(),
+                    tiers_mode: None,
+                    transform_quantity: None,
                     active: None,
                     nickname: None,
                     metadata: None,
@@ -1460,7 +1483,7 @@ nickname: One-time purchase
 
         let price: PriceSchema = serde_yml::from_str(yaml).unwrap();
         assert_eq!(price.pricing_type, Some(PricingType::OneTime));
-        assert_eq!(price.amounts.get("usd"), Some(&49.99));
+        assert_eq!(price.amounts.get("usd"), Some(&Decimal::new(4999, 2)));
         assert_eq!(price.nickname, Some("One-time purchase".to_string()));
     }
 
@@ -1477,7 +1500,7 @@ recurring:
 
         let price: PriceSchema = serde_yml::from_str(yaml).unwrap();
         assert_eq!(price.pricing_type, Some(PricingType::Recurring));
-        assert_eq!(price.amounts.get("eur"), Some(&19.99));
+        assert_eq!(price.amounts.get("eur"), Some(&Decimal::new(1999, 2)));
         let recurring = price.recurring.unwrap();
         assert_eq!(recurring.interval, RecurringInterval::Year);
         assert_eq!(recurring.interval_count, Some(1));
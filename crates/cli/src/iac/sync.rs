@@ -94,6 +94,12 @@ fn append_network_yaml(lines: &mut Vec<String>, network: &IacNetworkEnvConfig) {
     if let Some(rpc_url) = &network.rpc_url {
         lines.push(format!("      rpc_url: {}", rpc_url));
     }
+    if let Some(fallback_rpc_urls) = network.fallback_rpc_urls.as_ref().filter(|u| !u.is_empty()) {
+        lines.push("      fallback_rpc_urls:".to_string());
+        for url in fallback_rpc_urls {
+            lines.push(format!("        - {}", url));
+        }
+    }
     if let Some(ws_url) = &network.ws_url {
         lines.push(format!("      ws_url: {}", ws_url));
     }
@@ -268,6 +274,7 @@ mod tests {
                 rpc_port: Some(8899),
                 ws_port: Some(8900),
                 rpc_url: None,
+                fallback_rpc_urls: None,
                 ws_url: None,
             }),
         };
@@ -303,6 +310,7 @@ mod tests {
                 rpc_port: None,
                 ws_port: None,
                 rpc_url: Some("https://api.mainnet-beta.solana.com".to_string()),
+                fallback_rpc_urls: Some(vec!["https://solana-backup.example.com".to_string()]),
                 ws_url: Some("wss://api.mainnet-beta.solana.com".to_string()),
             }),
         };
@@ -312,6 +320,11 @@ mod tests {
         assert!(yaml.contains("  production:\n"));
         assert!(yaml.contains("    deployment: SelfHosted\n"));
         assert!(yaml.contains("      rpc_url: https://api.mainnet-beta.solana.com\n"));
+        assert!(
+            yaml.contains(
+                "      fallback_rpc_urls:\n        - https://solana-backup.example.com\n"
+            )
+        );
         assert!(yaml.contains("      ws_url: wss://api.mainnet-beta.solana.com\n"));
         assert!(yaml.contains("      recipient: abc123\n"));
     }
@@ -826,8 +839,8 @@ pub fn merge_product_update(existing_content: &str, update: &ProductSchema) -> S
 
     // Update price (singular) - preserve extra fields from existing price if present
     if let Some(price) = &update.price {
-        if price.amounts.is_empty() {
-            // Remove price if empty amounts
+        if price.amounts.is_empty() && price.tiers.is_empty() {
+            // Remove price if empty amounts and no tiers
             existing_map.remove(serde_yml::Value::String("price".to_string()));
         } else if let Ok(update_price_val) = serde_yml::to_value(price) {
             // Try to merge with existing price structure
@@ -839,6 +852,13 @@ pub fn merge_product_update(existing_content: &str, update: &ProductSchema) -> S
             if let Some(mut existing_price_map) = existing_price {
                 // Merge update values into existing
                 if let Some(update_map) = update_price_val.as_mapping() {
+                    // Amounts, tiers and package pricing replace each other
+                    for key in ["amounts", "tiers", "tiers_mode", "transform_quantity"] {
+                        let key = serde_yml::Value::String(key.to_string());
+                        if !update_map.contains_key(&key) {
+                            existing_price_map.remove(&key);
+                        }
+                    }
                     for (key, value) in update_map.iter() {
                         if let Some(existing_val) = existing_price_map.get_mut(key) {
                             *existing_val = value.clone();
@@ -906,9 +926,9 @@ mod product_tests {
     }
 
     /// Create a price schema with the given amount in dollars
-    fn make_price(amount: f64) -> super::super::PriceSchema {
+    fn make_price(amount: &str) -> super::super::PriceSchema {
         let mut amounts = IndexMap::new();
-        amounts.insert("usd".to_string(), amount);
+        amounts.insert("usd".to_string(), amount.parse().unwrap());
         super::super::PriceSchema {
             id: None,
             amounts,
@@ -916,6 +936,10 @@ mod product_tests {
             recurring: None,
             overage: None,
             trial: None,
+            tax_behavior: None,
+            tiers: Vec::new(),
+            tiers_mode: None,
+            transform_quantity: None,
             active: Some(true),
             nickname: None,
             metadata: None,
@@ -996,7 +1020,7 @@ price:
     default: price_test_xyz
 "#;
         let mut update = make_product("prod_123", "Test Product");
-        update.price = Some(make_price(10.00));
+        update.price = Some(make_price("10.00"));
 
         let result = merge_product_update(existing, &update);
 
@@ -1017,7 +1041,7 @@ price:
   deployed_id: price_existing
 "#;
         let mut update = make_product("prod_123", "Test Product");
-        update.price = Some(make_price(20.00)); // Update amount
+        update.price = Some(make_price("20.00")); // Update amount
 
         let result = merge_product_update(existing, &update);
 
@@ -1172,7 +1196,7 @@ price:
   created_at: 1704067200
 "#;
         let mut update = make_product("prod_123", "Test Product");
-        update.price = Some(make_price(20.00)); // Changed amount
+        update.price = Some(make_price("20.00")); // Changed amount
 
         let result = merge_product_update(existing, &update);
 
@@ -1236,7 +1260,7 @@ price:
         let mut update = make_product("prod_123", "Test Product");
         // Add recurring config to price
         let mut amounts = IndexMap::new();
-        amounts.insert("usd".to_string(), 10.0);
+        amounts.insert("usd".to_string(), moneymq_types::Decimal::TEN);
         update.price = Some(super::super::PriceSchema {
             id: None,
             amounts,
@@ -1247,6 +1271,10 @@ price:
             }),
             overage: None,
             trial: None,
+            tax_behavior: None,
+            tiers: Vec::new(),
+            tiers_mode: None,
+            transform_quantity: None,
             active: Some(true),
             nickname: None,
             metadata: None,
@@ -1274,7 +1302,7 @@ price:
   created_at: 1704067200
 "#;
         let mut update = make_product("prod_123", "Test Product");
-        update.price = Some(make_price(20.00)); // Changed amount
+        update.price = Some(make_price("20.00")); // Changed amount
 
         let result = merge_product_update(existing, &update);
 
@@ -239,6 +239,7 @@ impl InitCommand {
                                 sandboxes: IndexMap::new(),
                             },
                         )),
+                        write_back: false,
                     };
 
                     // Add sandbox if available
@@ -322,6 +322,8 @@ impl Default for SandboxNetworkConfig {
 ///     chain: Solana
 ///     recipient: HNohduvBpF...
 ///     rpc_url: https://api.mainnet-beta.solana.com
+///     fallback_rpc_urls:
+///       - https://solana-backup.example.com
 ///     ws_url: wss://api.mainnet-beta.solana.com
 /// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -358,6 +360,8 @@ pub struct SelfHostedEnvironment {
 ///   chain: Solana
 ///   recipient: HNohduvBpF...
 ///   rpc_url: https://api.mainnet-beta.solana.com
+///   fallback_rpc_urls:
+///     - https://solana-backup.example.com
 ///   ws_url: wss://api.mainnet-beta.solana.com
 /// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -382,6 +386,13 @@ pub struct SelfHostedNetworkConfig {
     /// - Custom: `https://your-rpc-provider.com`
     pub rpc_url: String,
 
+    /// Fallback RPC URLs, tried in order when `rpc_url` is unhealthy.
+    ///
+    /// Each endpoint has its own circuit breaker, so a failing provider is
+    /// skipped until it recovers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_rpc_urls: Vec<String>,
+
     /// WebSocket URL for real-time subscriptions.
     ///
     /// If not specified, derived from `rpc_url` by replacing
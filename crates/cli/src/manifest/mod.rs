@@ -123,6 +123,11 @@ pub struct CatalogConfig {
     /// The source/provider for this catalog (defaults to Stripe if not specified)
     #[serde(flatten, skip_serializing_if = "Option::is_none")]
     pub source: Option<CatalogSourceType>,
+
+    /// Write products created or updated through the API back to the catalog files
+    /// (defaults to false: edits are only stored in the catalog database)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub write_back: bool,
 }
 
 impl CatalogConfig {
@@ -335,6 +340,36 @@ environments:
         }
     }
 
+    #[test]
+    fn test_parse_payments_fx() {
+        let yaml = r#"
+payments:
+  networks:
+    stablecoins:
+      - USDC
+      - EURC
+  fx:
+    rates:
+      eur: 1.08
+      gbp: 1.27
+"#;
+
+        let manifest: Manifest = serde_yml::from_str(yaml).expect("Failed to parse manifest");
+        assert_eq!(manifest.payments.stablecoins(), ["USDC", "EURC"]);
+        let fx = manifest.payments.fx.expect("fx not found");
+        assert_eq!(fx.provider, payments::FxProvider::Static);
+        assert_eq!(fx.rates.get("eur"), Some(&1.08));
+        assert_eq!(fx.rates.len(), 2);
+
+        let manifest: Manifest =
+            serde_yml::from_str("payments:\n  fx:\n    provider: Mock\n").unwrap();
+        assert_eq!(
+            manifest.payments.fx.map(|fx| fx.provider),
+            Some(payments::FxProvider::Mock)
+        );
+        assert!(Manifest::default().payments.fx.is_none());
+    }
+
     #[test]
     fn test_manifest_save_and_load() {
         let mut manifest = Manifest::default();
@@ -345,6 +380,7 @@ environments:
                 description: Some("Test catalog".to_string()),
                 catalog_path: "billing/v1".to_string(),
                 source: None,
+                write_back: false,
             },
         );
 
@@ -31,7 +31,29 @@
 //! | Symbol | Name | Mint Address |
 //! |--------|------|--------------|
 //! | USDC | USD Coin | `EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v` |
+//! | EURC | Euro Coin | `HzwqbKZw8HxMN6bF2yFZNrht3c2iXXzpKcFu7uBEDKtr` |
+//!
+//! # Exchange Rates
+//!
+//! Prices can be declared in several currencies. A payment settles in an accepted
+//! stablecoin tracking its currency (EURC for EUR) when there is one. Otherwise it is
+//! converted at the rates of `fx`, and the rate applied and the original amount are
+//! recorded on the transaction and receipt:
+//!
+//! ```yaml
+//! payments:
+//!   networks:
+//!     chain: Solana
+//!     stablecoins:
+//!       - USDC
+//!   fx:
+//!     provider: Static
+//!     rates:
+//!       eur: 1.08
+//!       gbp: 1.27
+//! ```
 
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
 use crate::manifest::environments::Chain;
@@ -73,6 +95,12 @@ pub struct PaymentsConfig {
     /// Specifies which blockchain and stablecoins to accept.
     #[serde(default)]
     pub networks: NetworksPaymentConfig,
+
+    /// Exchange rates converting prices into the accepted stablecoins.
+    ///
+    /// Without it, prices can only be paid in a stablecoin tracking their currency.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fx: Option<FxConfig>,
 }
 
 impl PaymentsConfig {
@@ -160,3 +188,34 @@ impl Default for NetworksPaymentConfig {
 fn default_stablecoins() -> Vec<String> {
     vec!["USDC".to_string()]
 }
+
+/// Exchange rate configuration.
+///
+/// # Example
+///
+/// ```yaml
+/// fx:
+///   provider: Static
+///   rates:
+///     eur: 1.08
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FxConfig {
+    /// Where rates come from.
+    #[serde(default)]
+    pub provider: FxProvider,
+
+    /// US dollars per unit of each currency (e.g., `eur: 1.08`), for the `Static` provider.
+    #[serde(default)]
+    pub rates: IndexMap<String, f64>,
+}
+
+/// Source of exchange rates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FxProvider {
+    /// Fixed rates from `rates`
+    #[default]
+    Static,
+    /// Built-in fixed rates for local development, ignoring `rates`
+    Mock,
+}
@@ -2,18 +2,24 @@ use std::{fs, path::PathBuf};
 
 use console::{StyledObject, style};
 use indexmap::IndexMap;
-use moneymq_core::api::{NetworksConfig, NetworksConfigError, payment::PaymentApiConfig};
+use moneymq_core::api::{
+    NetworksConfig, NetworksConfigError,
+    catalog::{db::CatalogDbManager, fx::StaticFxRates},
+    payment::PaymentApiConfig,
+};
 use moneymq_types::{
-    Meter, Product,
+    Coupon, Meter, PaymentLink, Product,
     x402::{MoneyMqNetwork, config::facilitator::ValidatorsConfig},
 };
 use url::Url;
 
 use crate::{
     Context,
-    manifest::{EnvironmentConfig, Manifest, PaymentsConfig},
+    catalog::writer::YamlCatalogWriter,
+    manifest::{EnvironmentConfig, Manifest, PaymentsConfig, payments::FxProvider},
 };
 
+mod reload;
 mod run;
 mod sandbox;
 
@@ -90,7 +96,7 @@ pub trait ServiceCommand {
         &self,
         ctx: &Context,
         port: u16,
-    ) -> Result<(Vec<Product>, Vec<Meter>), RunCommandError> {
+    ) -> Result<(Vec<Product>, Vec<Meter>, Vec<Coupon>, Vec<PaymentLink>), RunCommandError> {
         // Get catalog path from first catalog (or default to "billing/v1")
         let catalog_base_path = ctx
             .manifest
@@ -167,6 +173,48 @@ pub trait ServiceCommand {
                 println!("{}", style(format!("✓ {} meters", meters.len())).green());
             }
         }
+
+        // Load coupons from {catalog_path}/coupons directory (optional)
+        let coupons_dir = ctx.manifest_path.join(catalog_base_path).join("coupons");
+        let coupons = if coupons_dir.exists() {
+            print!("{} ", style("Loading coupons").dim());
+            match moneymq_types::load_coupons_from_dir(&coupons_dir) {
+                Ok(coupons) => {
+                    println!("{}", style(format!("✓ {} coupons", coupons.len())).green());
+                    coupons
+                }
+                Err(e) => {
+                    eprintln!("\n{} Failed to load coupons: {}", style("✗").red(), e);
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        // Load payment links from {catalog_path}/payment_links directory (optional)
+        let payment_links_dir = ctx
+            .manifest_path
+            .join(catalog_base_path)
+            .join("payment_links");
+        let payment_links = if payment_links_dir.exists() {
+            print!("{} ", style("Loading payment links").dim());
+            match moneymq_types::load_payment_links_from_dir(&payment_links_dir) {
+                Ok(payment_links) => {
+                    println!(
+                        "{}",
+                        style(format!("✓ {} payment links", payment_links.len())).green()
+                    );
+                    payment_links
+                }
+                Err(e) => {
+                    eprintln!("\n{} Failed to load payment links: {}", style("✗").red(), e);
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
         println!();
 
         println!(
@@ -188,7 +236,7 @@ pub trait ServiceCommand {
         );
         println!(" {}", style(" ...").dim());
 
-        Ok((products, meters))
+        Ok((products, meters, coupons, payment_links))
     }
 
     /// Execute with optional pre-loaded products (e.g., from embedded examples)
@@ -216,15 +264,18 @@ pub trait ServiceCommand {
             })
             .ok_or_else(|| RunCommandError::EnvironmentNotFound(env_name.to_string()))?;
 
+        // Catalogs loaded from disk are reloaded when their files change
+        let watch_catalog = example_products.is_none() && !ctx.is_default_manifest;
+
         // Use example products if provided, otherwise load from disk
-        let (products, meters) = if let Some(products) = example_products {
-            (products, Vec::new())
+        let (products, meters, coupons, payment_links) = if let Some(products) = example_products {
+            (products, Vec::new(), Vec::new(), Vec::new())
         } else if !ctx.is_default_manifest {
             // If we're using the default manifest, there are no products/meters configured,
             // so the associated warnings are noisy and not helpful. Skip loading catalog in that case.
             self.load_catalog(ctx, port)?
         } else {
-            (Vec::new(), Vec::new())
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new())
         };
 
         // Initialize tracing only if --log-level is set or RUST_LOG env var is present
@@ -369,7 +420,7 @@ pub trait ServiceCommand {
         let payment_api_url = format!("http://localhost:{}/payment/v1/", port)
             .parse::<url::Url>()
             .expect("Failed to parse payment API URL");
-        let catalog_state = moneymq_core::api::catalog::CatalogState::new(
+        let mut catalog_state = moneymq_core::api::catalog::CatalogState::new(
             products,
             meters,
             is_sandbox,
@@ -381,11 +432,128 @@ pub trait ServiceCommand {
             ctx.manifest_path.clone(),
         );
 
+        // Customers prove who they are with the receipts of their payments
+        if let Some(key_pair) = &jwt_key_pair {
+            catalog_state = catalog_state.with_receipt_key(key_pair.clone());
+        }
+
+        // Share the quote signing key across instances when configured
+        if let Ok(secret) = std::env::var("MONEYMQ_QUOTE_SECRET") {
+            catalog_state = catalog_state.with_quote_secret(&secret);
+        }
+        catalog_state = catalog_state
+            .with_coupons(coupons)
+            .with_payment_links(payment_links);
+
+        // Convert prices into the accepted stablecoins at the configured rates
+        if let Some(fx) = &ctx.manifest.payments.fx {
+            let rates = match fx.provider {
+                FxProvider::Static => StaticFxRates::new(fx.rates.clone()),
+                FxProvider::Mock => StaticFxRates::mock(),
+            };
+            catalog_state = catalog_state.with_fx_rates(std::sync::Arc::new(rates));
+        }
+
+        // Store the products and prices created or updated through the API
+        match CatalogDbManager::new("sqlite://catalog.sqlite") {
+            Ok(db) => {
+                catalog_state = catalog_state.with_catalog_db(std::sync::Arc::new(db));
+                let write_back = ctx
+                    .manifest
+                    .catalogs
+                    .values()
+                    .next()
+                    .is_some_and(|c| c.write_back);
+                if write_back && watch_catalog {
+                    println!(
+                        "  {} Catalog edits: {}",
+                        style("✓").green(),
+                        style("written back to the catalog files").green()
+                    );
+                    catalog_state = catalog_state.with_catalog_writer(std::sync::Arc::new(
+                        YamlCatalogWriter::new(
+                            ctx.manifest_path.join(catalog_base_path).join("products"),
+                        ),
+                    ));
+                }
+            }
+            Err(e) => println!(
+                "  {} Catalog edits: {}",
+                style("⚠").yellow(),
+                style(format!("disabled ({})", e)).dim()
+            ),
+        }
+        // Products and prices are only edited through the API with the admin key
+        match std::env::var("MONEYMQ_ADMIN_KEY") {
+            Ok(admin_key) => catalog_state = catalog_state.with_admin_key(&admin_key),
+            Err(_) => println!(
+                "  {} Catalog edits: {}",
+                style("⚠").yellow(),
+                style("disabled (MONEYMQ_ADMIN_KEY is not set)").dim()
+            ),
+        }
+
+        // Deliver Stripe-format events to the catalog's webhook endpoint
+        if let Some(stripe) = ctx
+            .manifest
+            .catalogs
+            .values()
+            .next()
+            .and_then(|c| c.stripe_config())
+        {
+            let sandbox = stripe.get_default_sandbox().filter(|_| is_sandbox);
+            let (endpoint, secret_env, api_version) = match sandbox {
+                Some(sandbox) => (
+                    sandbox.webhook_endpoint.as_ref(),
+                    sandbox.webhook_secret_env.as_ref(),
+                    sandbox.api_version.clone(),
+                ),
+                None => (
+                    stripe.webhook_endpoint.as_ref(),
+                    stripe.webhook_secret_env.as_ref(),
+                    stripe.api_version.clone(),
+                ),
+            };
+            if let Some(endpoint) = endpoint {
+                let url = endpoint.parse::<url::Url>();
+                let secret = secret_env.and_then(|name| std::env::var(name).ok());
+                match (url, secret) {
+                    (Ok(url), Some(secret)) => {
+                        println!(
+                            "  {} Webhook events: {}",
+                            style("✓").green(),
+                            style(&url).green()
+                        );
+                        catalog_state =
+                            catalog_state.with_webhook_endpoint(url, secret, api_version);
+                    }
+                    (Err(e), _) => println!(
+                        "  {} Webhook events: {}",
+                        style("⚠").yellow(),
+                        style(format!("disabled (invalid endpoint: {})", e)).dim()
+                    ),
+                    (_, None) => println!(
+                        "  {} Webhook events: {}",
+                        style("⚠").yellow(),
+                        style("disabled (webhook_secret_env is not set)").dim()
+                    ),
+                }
+            }
+        }
+
         // Create IAC router for manifest management endpoints
         let manifest_file = ctx.manifest_path.join("moneymq.yaml");
         let iac_state = crate::iac::IacState::new(manifest_file);
         let iac_router = crate::iac::create_router(iac_state);
 
+        if watch_catalog {
+            reload::spawn_catalog_watcher(
+                ctx.manifest_path.join(catalog_base_path),
+                catalog_state.clone(),
+                payment_api_state.clone(),
+            );
+        }
+
         // Start the combined server with both catalog and payment APIs
         moneymq_core::api::start_server(catalog_state, payment_api_state, Some(iac_router), port)
             .await
@@ -0,0 +1,501 @@
+//! Hot reload of the catalog and actors
+//!
+//! While the server runs, the files under the catalog's `products/`, `meters/`, `coupons/`,
+//! `payment_links/` and `actors/` directories are polled for changes. When one changes, the
+//! whole catalog is loaded again: if every file loads and the catalog is consistent, the new
+//! products, meters, coupons, payment links and actors are swapped in at once and a
+//! `catalog:reloaded` event is published on the `catalog` channel. Otherwise the running
+//! catalog is kept and the errors are reported.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use console::style;
+use moneymq_core::api::{
+    catalog::{Catalog, CatalogState},
+    payment::PaymentApiConfig,
+};
+use moneymq_types::{ActorsConfig, ChannelEvent, event_types};
+use tokio::task::JoinHandle;
+
+use crate::catalog::loader::{load_meters_with_errors, load_products_with_errors};
+
+/// How often the catalog files are checked for changes
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Channel `catalog:reloaded` events are published on
+pub const CATALOG_CHANNEL: &str = "catalog";
+
+/// Catalog directories whose files are watched
+const WATCHED_DIRS: &[&str] = &["products", "meters", "coupons", "payment_links", "actors"];
+
+/// Path, modification time and size of a catalog file
+type FileStamp = (PathBuf, Option<SystemTime>, u64);
+
+/// Stamps of every file in the watched directories, sorted by path
+fn snapshot(catalog_dir: &Path) -> Vec<FileStamp> {
+    fn walk(dir: &Path, stamps: &mut Vec<FileStamp>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                walk(&path, stamps);
+            } else {
+                stamps.push((path, metadata.modified().ok(), metadata.len()));
+            }
+        }
+    }
+
+    let mut stamps = Vec::new();
+    for dir in WATCHED_DIRS {
+        walk(&catalog_dir.join(dir), &mut stamps);
+    }
+    stamps.sort();
+    stamps
+}
+
+/// Load the catalog and actors from `catalog_dir`, failing with every error found
+///
+/// Unlike at startup, a file that doesn't load fails the whole catalog, as do duplicate
+/// product IDs, overages billed against unknown meters and payment links selling unknown
+/// prices or products.
+pub fn load_catalog(catalog_dir: &Path) -> Result<(Catalog, ActorsConfig), Vec<String>> {
+    let mut errors = Vec::new();
+
+    let products = match load_products_with_errors(&catalog_dir.join("products")) {
+        Ok((products, product_errors)) => {
+            errors.extend(product_errors);
+            products.into_values().collect::<Vec<_>>()
+        }
+        Err(e) => {
+            errors.push(e);
+            Vec::new()
+        }
+    };
+    let meters = match load_meters_with_errors(&catalog_dir.join("meters")) {
+        Ok((meters, meter_errors)) => {
+            errors.extend(meter_errors);
+            meters
+        }
+        Err(e) => {
+            errors.push(e);
+            Vec::new()
+        }
+    };
+    let coupons = moneymq_types::load_coupons_from_dir(&catalog_dir.join("coupons"))
+        .unwrap_or_else(|e| {
+            errors.push(e);
+            Vec::new()
+        });
+    let payment_links =
+        moneymq_types::load_payment_links_from_dir(&catalog_dir.join("payment_links"))
+            .unwrap_or_else(|e| {
+                errors.push(e);
+                Vec::new()
+            });
+    let actors =
+        moneymq_types::load_actors_from_dir(&catalog_dir.join("actors")).unwrap_or_else(|e| {
+            errors.push(e);
+            ActorsConfig::default()
+        });
+
+    for product in &products {
+        for price in &product.prices {
+            if let Some(overage) = &price.overage
+                && !meters
+                    .iter()
+                    .any(|meter| meter.id == overage.meter || meter.event_name == overage.meter)
+            {
+                errors.push(format!(
+                    "Price {} of product {} bills overage on unknown meter '{}'",
+                    price.id, product.id, overage.meter
+                ));
+            }
+        }
+    }
+
+    for link in &payment_links {
+        for item in &link.line_items {
+            let unknown = match (&item.price, &item.product) {
+                (Some(price), _) => (!products
+                    .iter()
+                    .any(|product| product.prices.iter().any(|p| &p.id == price)))
+                .then(|| format!("price '{}'", price)),
+                (None, Some(product)) => (!products.iter().any(|p| &p.id == product))
+                    .then(|| format!("product '{}'", product)),
+                (None, None) => None,
+            };
+            if let Some(unknown) = unknown {
+                errors.push(format!(
+                    "Payment link {} sells unknown {}",
+                    link.id, unknown
+                ));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok((
+        Catalog {
+            products,
+            meters,
+            coupons,
+            payment_links,
+        },
+        actors,
+    ))
+}
+
+/// Load the catalog again and swap it into `catalog_state`, with the products stored in
+/// the catalog DB overlaid, handing the reloaded actors to `swap_actors` under the same
+/// lock as the catalog edits. Returns a summary of what was loaded.
+fn swap_catalog(
+    catalog_dir: &Path,
+    catalog_state: &CatalogState,
+    swap_actors: impl FnOnce(ActorsConfig),
+) -> Result<serde_json::Value, Vec<String>> {
+    let (catalog, actors) = load_catalog(catalog_dir)?;
+    let summary = serde_json::json!({
+        "products": catalog.products.len(),
+        "meters": catalog.meters.len(),
+        "coupons": catalog.coupons.len(),
+        "payment_links": catalog.payment_links.len(),
+        "actors": actors.len(),
+    });
+    catalog_state.replace_catalog_with(catalog, || swap_actors(actors));
+    Ok(summary)
+}
+
+/// Load the catalog again and swap it in, or report why it was kept
+fn reload(catalog_dir: &Path, catalog_state: &CatalogState, payment_state: &PaymentApiConfig) {
+    let swap_actors = |actors| payment_state.replace_actors(actors);
+    match swap_catalog(catalog_dir, catalog_state, swap_actors) {
+        Ok(summary) => {
+            println!(
+                "{} Reloaded catalog ({} products, {} meters, {} coupons, {} payment links, {} actors)",
+                style("✓").green(),
+                summary["products"],
+                summary["meters"],
+                summary["coupons"],
+                summary["payment_links"],
+                summary["actors"]
+            );
+
+            if let Some(channel_manager) = &payment_state.channel_manager {
+                channel_manager.publish(
+                    CATALOG_CHANNEL,
+                    ChannelEvent::custom(event_types::CATALOG_RELOADED, summary),
+                );
+            }
+        }
+        Err(errors) => {
+            eprintln!(
+                "{} Catalog not reloaded, keeping the running catalog:",
+                style("✗").red()
+            );
+            for error in errors {
+                eprintln!("  {} {}", style("-").yellow(), error);
+            }
+        }
+    }
+}
+
+/// Watch the catalog files in `catalog_dir` and reload the catalog and actors of
+/// `catalog_state` and `payment_state` when they change
+pub fn spawn_catalog_watcher(
+    catalog_dir: PathBuf,
+    catalog_state: CatalogState,
+    payment_state: PaymentApiConfig,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut last = snapshot(&catalog_dir);
+        let mut interval = tokio::time::interval(DEFAULT_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let current = snapshot(&catalog_dir);
+            if current != last {
+                last = current;
+                reload(&catalog_dir, &catalog_state, &payment_state);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc};
+
+    use moneymq_core::api::catalog::db::{CatalogDbManager, PriceRecord};
+
+    use super::*;
+
+    fn write(dir: &Path, file: &str, content: &str) {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// Catalog state of `catalog_dir` with an in-memory catalog DB and no catalog writer
+    fn catalog_state(catalog_dir: &Path) -> CatalogState {
+        CatalogState::new(
+            Vec::new(),
+            Vec::new(),
+            true,
+            "http://localhost:8488".parse().unwrap(),
+            Default::default(),
+            catalog_dir.to_path_buf(),
+            None,
+            None,
+            Default::default(),
+        )
+        .with_catalog_db(Arc::new(CatalogDbManager::new(":memory:").unwrap()))
+    }
+
+    #[test]
+    fn test_load_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog_dir = dir.path();
+        write(
+            catalog_dir,
+            "products/pro.yaml",
+            r#"---
+id: prod_pro
+name: Pro
+active: true
+metadata: {}
+created_at: 1700000000
+updated_at: null
+product_type: service
+images: []
+prices:
+  - id: price_pro
+    active: true
+    currency: usd
+    unit_amount: 4900
+    pricing_type: one_time
+    metadata: {}
+    created_at: 1700000000
+"#,
+        );
+        write(
+            catalog_dir,
+            "meters/api_calls.yaml",
+            r#"---
+id: api_calls
+event_name: api_calls
+created_at: 1700000000
+updated_at: null
+"#,
+        );
+
+        let before = snapshot(catalog_dir);
+        assert_eq!(before.len(), 2);
+        let (catalog, actors) = load_catalog(catalog_dir).unwrap();
+        assert_eq!(catalog.products.len(), 1);
+        assert_eq!(catalog.meters.len(), 1);
+        assert!(catalog.coupons.is_empty() && actors.is_empty());
+
+        // A file that doesn't parse fails the whole catalog
+        write(catalog_dir, "meters/broken.yaml", "id: [");
+        assert_ne!(snapshot(catalog_dir), before);
+        let errors = load_catalog(catalog_dir).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("broken.yaml"));
+
+        // Payment links must sell prices of the catalog
+        fs::remove_file(catalog_dir.join("meters/broken.yaml")).unwrap();
+        write(
+            catalog_dir,
+            "payment_links/pro.yaml",
+            "line_items:\n  - price: price_pro\n",
+        );
+        let (catalog, _) = load_catalog(catalog_dir).unwrap();
+        assert_eq!(catalog.payment_links[0].id, "pro");
+        write(
+            catalog_dir,
+            "payment_links/team.yaml",
+            "line_items:\n  - price: price_team\n",
+        );
+        let errors = load_catalog(catalog_dir).unwrap_err();
+        assert_eq!(
+            errors,
+            vec!["Payment link team sells unknown price 'price_team'"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_edits_keep_catalog_file_fields() {
+        use axum::{Extension, body::Bytes, extract::Path as UrlPath, http::StatusCode};
+        use moneymq_core::api::catalog::stripe::endpoints::{
+            prices::update_price, products::update_product,
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let catalog_dir = dir.path();
+        write(
+            catalog_dir,
+            "products/pro/product.yaml",
+            "product_type: service\nfeatures:\n  seats:\n    name: Seats\n",
+        );
+        write(
+            catalog_dir,
+            "products/pro/variants/monthly/product.yaml",
+            "name: Pro\nfeatures:\n  seats:\n    value: 5\nprice:\n  amounts:\n    usd: 49.0\n    eur: 45.0\n",
+        );
+        write(
+            catalog_dir,
+            "products/api/product.yaml",
+            "product_type: service\n",
+        );
+        write(
+            catalog_dir,
+            "products/api/variants/metered/product.yaml",
+            "name: API\nprice:\n  tiers_mode: graduated\n  tiers:\n    - up_to: 1000\n      unit_amounts:\n        usd: 0.02\n    - unit_amounts:\n        usd: 0.01\n",
+        );
+
+        let (catalog, _) = load_catalog(catalog_dir).unwrap();
+        let state = catalog_state(catalog_dir);
+        state.replace_catalog(catalog);
+
+        let response = update_product(
+            Extension(state.clone()),
+            UrlPath("pro-monthly".to_string()),
+            Bytes::from("name=Pro+2026"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = update_price(
+            Extension(state.clone()),
+            UrlPath("price_api-metered".to_string()),
+            Bytes::from("metadata[plan]=usage"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Reloading the files overlays the edits without losing what the DB doesn't store
+        let (catalog, _) = load_catalog(catalog_dir).unwrap();
+        state.replace_catalog(catalog);
+        let catalog = state.catalog();
+        let pro = catalog
+            .products
+            .iter()
+            .find(|product| product.id == "pro-monthly")
+            .unwrap();
+        assert_eq!(pro.name.as_deref(), Some("Pro 2026"));
+        assert_eq!(pro.features["seats"].value, Some(serde_json::json!(5)));
+        assert_eq!(pro.prices.len(), 1);
+        assert_eq!(pro.prices[0].amount_in("usd"), Some(4900));
+        assert_eq!(pro.prices[0].amount_in("eur"), Some(4500));
+
+        let api = catalog
+            .products
+            .iter()
+            .find(|product| product.id == "api-metered")
+            .unwrap();
+        assert_eq!(api.prices.len(), 1);
+        assert_eq!(
+            api.prices[0].metadata.get("plan").map(String::as_str),
+            Some("usage")
+        );
+        assert_eq!(api.prices[0].tiers.len(), 2);
+        assert_eq!(api.prices[0].amount_for(1500, "usd"), Some(2000 + 500));
+    }
+
+    #[test]
+    fn test_reload_overlays_catalog_db_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog_dir = dir.path();
+        write(
+            catalog_dir,
+            "products/pro/product.yaml",
+            "product_type: service\n",
+        );
+        write(
+            catalog_dir,
+            "products/pro/variants/monthly/product.yaml",
+            "name: Pro\nprice:\n  amounts:\n    usd: 49.0\n",
+        );
+        let state = catalog_state(catalog_dir);
+        let mut reloaded_actors = None;
+        let summary = swap_catalog(catalog_dir, &state, |actors| {
+            reloaded_actors = Some(actors);
+        })
+        .unwrap();
+        assert_eq!(summary["products"], 1);
+        // The actors are swapped in with the catalog
+        assert!(reloaded_actors.is_some_and(|actors| actors.is_empty()));
+        assert_eq!(
+            state.catalog().products[0].prices[0].unit_amount,
+            Some(4900)
+        );
+
+        // Edit the product in the catalog DB, then add a product to the files
+        state
+            .catalog_db
+            .as_ref()
+            .unwrap()
+            .sync_product_with_prices(
+                &state.payment_stack_id,
+                "pro-monthly",
+                "Pro (2026)",
+                None,
+                "service",
+                None,
+                true,
+                None,
+                true,
+                vec![PriceRecord {
+                    price_id: Some("price_pro-monthly".to_string()),
+                    pricing_type: "one_time".to_string(),
+                    currency: "usd".to_string(),
+                    unit_amount: 5900,
+                    active: true,
+                    ..Default::default()
+                }],
+            )
+            .unwrap();
+        write(
+            catalog_dir,
+            "products/pro/variants/yearly/product.yaml",
+            "name: Pro yearly\nprice:\n  amounts:\n    usd: 490.0\n",
+        );
+
+        let summary = swap_catalog(catalog_dir, &state, |_| {}).unwrap();
+        assert_eq!(summary["products"], 2);
+        let catalog = state.catalog();
+        let monthly = catalog
+            .products
+            .iter()
+            .find(|product| product.id == "pro-monthly")
+            .unwrap();
+        assert_eq!(monthly.name.as_deref(), Some("Pro (2026)"));
+        assert_eq!(monthly.prices.len(), 1);
+        assert_eq!(monthly.prices[0].unit_amount, Some(5900));
+        assert!(catalog.products.iter().any(|p| p.id == "pro-yearly"));
+
+        // A catalog that fails to load leaves the running one, edits included
+        write(
+            catalog_dir,
+            "products/pro/variants/broken/product.yaml",
+            "name: [",
+        );
+        assert!(swap_catalog(catalog_dir, &state, |_| unreachable!()).is_err());
+        assert_eq!(state.catalog().products.len(), 2);
+        assert!(
+            state
+                .catalog()
+                .products
+                .iter()
+                .any(|p| p.name.as_deref() == Some("Pro (2026)"))
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Admin key guarding catalog edits
+//!
+//! Creating and updating products and prices writes the catalog database (and the catalog
+//! files when written back), so these requests must carry the admin key configured with
+//! [`CatalogState::with_admin_key`] as `Authorization: Bearer <key>`. Without a configured
+//! key the catalog can't be edited through the API.
+
+use axum::{
+    Extension, Json,
+    body::Body,
+    handler::Handler,
+    http::{HeaderMap, Request, StatusCode, header::AUTHORIZATION},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{MethodRouter, post},
+};
+
+use crate::api::catalog::CatalogState;
+
+fn admin_error(status: StatusCode, code: &str, message: &str) -> Response {
+    (
+        status,
+        Json(serde_json::json!({
+            "error": {
+                "code": code,
+                "message": message,
+                "type": "invalid_request_error",
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// Whether both keys are equal, comparing every byte so the time taken doesn't tell how
+/// much of the key was guessed
+fn keys_match(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Check the admin key of a request
+fn check_admin_key(state: &CatalogState, headers: &HeaderMap) -> Result<(), Response> {
+    let Some(admin_key) = state.admin_key.as_deref() else {
+        return Err(admin_error(
+            StatusCode::FORBIDDEN,
+            "catalog_edits_disabled",
+            "Catalog edits are disabled: no admin key is configured",
+        ));
+    };
+    let provided = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match provided {
+        Some(provided) if keys_match(admin_key, provided) => Ok(()),
+        Some(_) => Err(admin_error(
+            StatusCode::UNAUTHORIZED,
+            "invalid_api_key",
+            "Invalid admin key provided",
+        )),
+        None => Err(admin_error(
+            StatusCode::UNAUTHORIZED,
+            "api_key_required",
+            "Catalog edits require the admin key as `Authorization: Bearer <key>`",
+        )),
+    }
+}
+
+/// Middleware rejecting requests without the admin key
+pub async fn admin_key_middleware(
+    Extension(state): Extension<CatalogState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    match check_admin_key(&state, req.headers()) {
+        Ok(()) => next.run(req).await,
+        Err(response) => response,
+    }
+}
+
+/// Helper function to create a POST route requiring the admin key
+pub fn admin_post<H, T>(handler: H) -> MethodRouter<()>
+where
+    H: Handler<T, ()>,
+    T: 'static,
+{
+    post(handler).layer(middleware::from_fn(admin_key_middleware))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> CatalogState {
+        CatalogState::new(
+            Vec::new(),
+            Vec::new(),
+            true,
+            "http://localhost:8488".parse().unwrap(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+    }
+
+    fn bearer(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, format!("Bearer {}", key).parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_catalog_edits_require_the_admin_key() {
+        let status = |state: &CatalogState, headers: &HeaderMap| {
+            check_admin_key(state, headers).err().map(|r| r.status())
+        };
+
+        // Without a configured key nobody can edit the catalog
+        let state = state();
+        assert_eq!(
+            status(&state, &bearer("sk_admin")),
+            Some(StatusCode::FORBIDDEN)
+        );
+
+        let state = state.with_admin_key("sk_admin");
+        assert_eq!(status(&state, &bearer("sk_admin")), None);
+        assert_eq!(
+            status(&state, &bearer("sk_admio")),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            status(&state, &bearer("sk_admin_")),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            status(&state, &HeaderMap::new()),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+    }
+}
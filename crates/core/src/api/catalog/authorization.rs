@@ -0,0 +1,372 @@
+//! Authorized-but-not-captured payments for manual-capture payment intents.
+//!
+//! When a payment intent is created with `capture_method=manual`, confirming it verifies
+//! the x402 payment with the facilitator but does not settle it. The signed payload is
+//! kept here until the intent is captured (settled on-chain), canceled (discarded), or
+//! the authorization expires, and stored in the payment database with the payment intent
+//! so that a restart doesn't drop it.
+//!
+//! The customer signs a transfer of the full authorized amount, so a partial capture settles
+//! it in full and refunds the uncaptured part from the payment recipient. That needs the
+//! recipient's keypair: only MoneyMQ-managed recipients can capture less than authorized.
+
+use kora_lib::transaction::TransactionUtil;
+use moneymq_types::x402::{
+    Currency, ExactPaymentPayload, MoneyMqManagedRecipient, PaymentPayload, PaymentRequirements,
+    Recipient, SolanaCurrency,
+};
+use solana_keypair::Keypair;
+use tracing::{error, info};
+
+use crate::api::{
+    catalog::{
+        CatalogState,
+        stripe::types::{CaptureMethod, PaymentIntentStatus, StripePaymentIntent},
+    },
+    payment::{
+        db::{PaymentAuthorizationModel, payment_authorization::NewPaymentAuthorization},
+        networks::solana::{
+            extract_customer_from_transaction, transfer_from_token_account, uses_durable_nonce,
+        },
+    },
+};
+
+/// How long an authorization signed against a recent blockhash can be held.
+///
+/// A Solana blockhash is valid for 150 slots (~60 seconds). Authorizations expire well
+/// before that so that a capture never submits a transaction the cluster will reject.
+pub const BLOCKHASH_AUTHORIZATION_TTL_SECONDS: i64 = 45;
+
+/// How long an authorization signed against a durable nonce can be held (7 days, as Stripe)
+pub const DURABLE_NONCE_AUTHORIZATION_TTL_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+/// A verified payment reserved for later capture
+#[derive(Debug, Clone)]
+pub struct PaymentAuthorization {
+    pub payment_intent_id: String,
+    /// Signed payload presented by the customer
+    pub payment_payload: PaymentPayload,
+    /// Requirements the payload was verified against
+    pub payment_requirements: PaymentRequirements,
+    /// Unix timestamp (seconds) of verification
+    pub authorized_at: i64,
+    /// Unix timestamp (seconds) after which the authorization can no longer be captured
+    pub expires_at: i64,
+}
+
+impl PaymentAuthorization {
+    pub fn new(
+        payment_intent_id: String,
+        payment_payload: PaymentPayload,
+        payment_requirements: PaymentRequirements,
+        now: i64,
+    ) -> Self {
+        let ExactPaymentPayload::Solana(solana_payload) = &payment_payload.payload;
+        let durable = TransactionUtil::decode_b64_transaction(&solana_payload.transaction)
+            .map(|tx| uses_durable_nonce(&tx))
+            .unwrap_or(false);
+        let ttl = if durable {
+            DURABLE_NONCE_AUTHORIZATION_TTL_SECONDS
+        } else {
+            BLOCKHASH_AUTHORIZATION_TTL_SECONDS
+        };
+
+        Self {
+            payment_intent_id,
+            payment_payload,
+            payment_requirements,
+            authorized_at: now,
+            expires_at: now + ttl,
+        }
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        now > self.expires_at
+    }
+}
+
+/// Check whether a payment intent was created with `capture_method=manual`
+pub fn is_manual_capture(state: &CatalogState, payment_intent_id: &str) -> bool {
+    state
+        .payment_intents
+        .lock()
+        .ok()
+        .and_then(|intents| {
+            intents
+                .get(payment_intent_id)
+                .map(|intent| intent.capture_method == CaptureMethod::Manual)
+        })
+        .unwrap_or(false)
+}
+
+/// Hold a verified payment until its payment intent is captured or canceled
+pub fn hold_authorization(state: &CatalogState, authorization: PaymentAuthorization) {
+    if let Err(e) = store_authorization(state, &authorization) {
+        error!(
+            "Failed to store the authorization of payment intent {}: {}",
+            authorization.payment_intent_id, e
+        );
+    }
+    state
+        .authorizations
+        .lock()
+        .unwrap()
+        .insert(authorization.payment_intent_id.clone(), authorization);
+}
+
+/// Stop holding the payment authorized for a payment intent; returns whether one was held
+pub fn remove_authorization(state: &CatalogState, payment_intent_id: &str) -> bool {
+    let removed = state
+        .authorizations
+        .lock()
+        .unwrap()
+        .remove(payment_intent_id)
+        .is_some();
+    forget_authorization(state, payment_intent_id);
+    removed
+}
+
+/// Hold again the authorizations stored in the payment database, with their payment
+/// intents, after a restart
+pub fn restore_authorizations(state: &CatalogState) {
+    let Some(db) = state.payment_db.as_ref() else {
+        return;
+    };
+    let stored = match db.list_payment_authorizations(&state.payment_stack_id, state.use_sandbox) {
+        Ok(stored) => stored,
+        Err(e) => {
+            error!("Failed to load payment authorizations: {}", e);
+            return;
+        }
+    };
+    for stored in stored {
+        let (payment_intent, authorization) = match restored_authorization(&stored) {
+            Ok(restored) => restored,
+            Err(e) => {
+                error!(
+                    "Failed to restore the authorization of payment intent {}: {}",
+                    stored.payment_intent_id, e
+                );
+                continue;
+            }
+        };
+        state
+            .payment_intents
+            .lock()
+            .unwrap()
+            .entry(payment_intent.id.clone())
+            .or_insert(payment_intent);
+        state
+            .authorizations
+            .lock()
+            .unwrap()
+            .insert(authorization.payment_intent_id.clone(), authorization);
+    }
+}
+
+/// Store an authorization with its payment intent in the payment database, if there is one
+fn store_authorization(
+    state: &CatalogState,
+    authorization: &PaymentAuthorization,
+) -> anyhow::Result<()> {
+    let Some(db) = state.payment_db.as_ref() else {
+        return Ok(());
+    };
+    let payment_intent = state
+        .payment_intents
+        .lock()
+        .unwrap()
+        .get(&authorization.payment_intent_id)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("no such payment intent"))?;
+    db.upsert_payment_authorization(&NewPaymentAuthorization {
+        payment_intent_id: authorization.payment_intent_id.clone(),
+        payment_intent: serde_json::to_string(&payment_intent)?,
+        payment_payload: serde_json::to_string(&authorization.payment_payload)?,
+        payment_requirements: serde_json::to_string(&authorization.payment_requirements)?,
+        authorized_at: authorization.authorized_at,
+        expires_at: authorization.expires_at,
+        payment_stack_id: state.payment_stack_id.clone(),
+        is_sandbox: state.use_sandbox,
+    })?;
+    Ok(())
+}
+
+fn restored_authorization(
+    stored: &PaymentAuthorizationModel,
+) -> serde_json::Result<(StripePaymentIntent, PaymentAuthorization)> {
+    let payment_intent = serde_json::from_str(&stored.payment_intent)?;
+    let authorization = PaymentAuthorization {
+        payment_intent_id: stored.payment_intent_id.clone(),
+        payment_payload: serde_json::from_str(&stored.payment_payload)?,
+        payment_requirements: serde_json::from_str(&stored.payment_requirements)?,
+        authorized_at: stored.authorized_at,
+        expires_at: stored.expires_at,
+    };
+    Ok((payment_intent, authorization))
+}
+
+/// Delete the authorization stored for a payment intent from the payment database
+pub(crate) fn forget_authorization(state: &CatalogState, payment_intent_id: &str) {
+    let Some(db) = state.payment_db.as_ref() else {
+        return;
+    };
+    if let Err(e) = db.delete_payment_authorization(
+        payment_intent_id,
+        &state.payment_stack_id,
+        state.use_sandbox,
+    ) {
+        error!(
+            "Failed to delete the authorization of payment intent {}: {}",
+            payment_intent_id, e
+        );
+    }
+}
+
+/// Drop expired authorizations and cancel their payment intents, like Stripe does when an
+/// uncaptured authorization lapses. Returns the IDs of the canceled payment intents.
+pub fn expire_authorizations(state: &CatalogState, now: i64) -> Vec<String> {
+    let expired: Vec<String> = {
+        let mut authorizations = state.authorizations.lock().unwrap();
+        let expired = authorizations
+            .values()
+            .filter(|auth| auth.is_expired(now))
+            .map(|auth| auth.payment_intent_id.clone())
+            .collect::<Vec<_>>();
+        for id in &expired {
+            authorizations.remove(id);
+        }
+        expired
+    };
+    for id in &expired {
+        forget_authorization(state, id);
+    }
+
+    if !expired.is_empty() {
+        let mut payment_intents = state.payment_intents.lock().unwrap();
+        for id in &expired {
+            if let Some(intent) = payment_intents.get_mut(id) {
+                if intent.status == PaymentIntentStatus::RequiresCapture {
+                    info!("Authorization for payment intent {} expired", id);
+                    intent.status = PaymentIntentStatus::Canceled;
+                    intent.amount_capturable = 0;
+                    intent.cancellation_reason = Some("automatic".to_string());
+                }
+            }
+        }
+    }
+
+    expired
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RefundError {
+    #[error("the payment recipient is not a MoneyMQ-managed wallet")]
+    UnmanagedRecipient,
+    #[error("no Solana RPC endpoint is configured")]
+    NetworkUnavailable,
+    #[error("the authorized payment can't be refunded: {0}")]
+    InvalidAuthorization(String),
+    #[error("the refund transfer failed: {0}")]
+    TransferFailed(anyhow::Error),
+}
+
+/// Keypair of the recipient the authorized transfer pays, and the token it transfers
+fn refund_source(
+    state: &CatalogState,
+    authorization: &PaymentAuthorization,
+) -> Result<(Keypair, SolanaCurrency), RefundError> {
+    let requirements = &authorization.payment_requirements;
+    let network_config = state
+        .networks_config
+        .get_config_for_network(&requirements.network)
+        .ok_or(RefundError::NetworkUnavailable)?;
+    let Recipient::MoneyMqManaged(MoneyMqManagedRecipient::Local(recipient)) =
+        network_config.recipient()
+    else {
+        return Err(RefundError::UnmanagedRecipient);
+    };
+    if recipient.address != requirements.pay_to {
+        return Err(RefundError::UnmanagedRecipient);
+    }
+    let keypair = Keypair::try_from(&recipient.keypair_bytes[..])
+        .map_err(|e| RefundError::InvalidAuthorization(e.to_string()))?;
+    let currency = network_config
+        .currencies()
+        .iter()
+        .find(|currency| currency.address() == requirements.asset)
+        .and_then(Currency::solana_currency)
+        .cloned()
+        .ok_or_else(|| {
+            RefundError::InvalidAuthorization(format!("unknown asset {}", requirements.asset))
+        })?;
+    Ok((keypair, currency))
+}
+
+/// Check that the uncaptured part of `authorization` could be refunded
+pub fn check_refundable(
+    state: &CatalogState,
+    authorization: &PaymentAuthorization,
+) -> Result<(), RefundError> {
+    state
+        .rpc_pool
+        .as_ref()
+        .ok_or(RefundError::NetworkUnavailable)?;
+    refund_source(state, authorization).map(|_| ())
+}
+
+/// Base units of the authorized transfer refunded when `amount` of the `amount_capturable`
+/// authorized is not captured; dust below a base unit stays with the recipient
+pub fn refund_units(
+    authorization: &PaymentAuthorization,
+    amount: i64,
+    amount_capturable: i64,
+) -> Result<u64, RefundError> {
+    let authorized = authorization
+        .payment_requirements
+        .max_amount_required
+        .0
+        .parse::<u128>()
+        .map_err(|e| RefundError::InvalidAuthorization(e.to_string()))?;
+    if amount < 0 || amount_capturable <= 0 || amount > amount_capturable {
+        return Err(RefundError::InvalidAuthorization(format!(
+            "can't refund {} of {}",
+            amount, amount_capturable
+        )));
+    }
+    let refund = authorized * amount as u128 / amount_capturable as u128;
+    u64::try_from(refund).map_err(|e| RefundError::InvalidAuthorization(e.to_string()))
+}
+
+/// Refund `amount` of the `amount_capturable` authorized back to the payer, once the full
+/// authorized transfer is settled. Returns the signature of the refund transaction.
+pub async fn refund_uncaptured(
+    state: &CatalogState,
+    authorization: &PaymentAuthorization,
+    amount: i64,
+    amount_capturable: i64,
+) -> Result<String, RefundError> {
+    let pool = state
+        .rpc_pool
+        .as_ref()
+        .ok_or(RefundError::NetworkUnavailable)?;
+    let (recipient, currency) = refund_source(state, authorization)?;
+    let ExactPaymentPayload::Solana(payload) = &authorization.payment_payload.payload;
+    let payer = extract_customer_from_transaction(&payload.transaction)
+        .map_err(|e| RefundError::InvalidAuthorization(e.to_string()))?;
+    let refund = refund_units(authorization, amount, amount_capturable)?;
+
+    // Each attempt would build a new transaction, so refunds are never retried on another RPC
+    let endpoint = pool.preferred();
+    let result = transfer_from_token_account(
+        endpoint.client(),
+        &recipient,
+        &currency.mint,
+        &currency.token_program,
+        currency.decimals,
+        &[(payer, refund)],
+    )
+    .await;
+    pool.record_outcome(&endpoint, &result);
+    result.map_err(RefundError::TransferFailed)
+}
@@ -0,0 +1,670 @@
+//! Hosted checkout page served at a checkout session's `url`
+//!
+//! The page is rendered on the server with the stack's branding (`stack_name`,
+//! `stack_image_url` and the colors of the catalog's `assets/style.json`), the session's
+//! line items and totals, and the x402 payment requirements of its payment intent. Wallets
+//! that don't speak x402 can pay from the Solana Pay QR code or deep link. A small script
+//! polls the session and redirects to `success_url` once it is complete.
+
+use std::path::PathBuf;
+
+use axum::{
+    Extension,
+    extract::{OriginalUri, Path},
+    http::{HeaderMap, StatusCode, header::HOST},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use moneymq_types::{
+    Decimal,
+    x402::{Network, PaymentRequirements, Scheme, TokenAmount},
+};
+use qrcode::{QrCode, render::svg};
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+use crate::api::catalog::{
+    CatalogState,
+    fx::settlement_amount,
+    middleware::payment_intent_charge,
+    stripe::{
+        endpoints::checkout_sessions::current_checkout_session,
+        types::{CheckoutSessionStatus, StripeCheckoutSession},
+    },
+};
+
+/// How often the page polls the session status (milliseconds)
+const STATUS_POLL_INTERVAL_MS: u64 = 3000;
+
+/// Colors of the catalog's `assets/style.json`
+#[derive(Debug, Default, Deserialize)]
+pub struct CheckoutStyle {
+    #[serde(default)]
+    pub primary_color: Option<String>,
+    #[serde(default)]
+    pub secondary_color: Option<String>,
+}
+
+impl CheckoutStyle {
+    /// Load the style of the catalog, defaulting when it has none
+    pub fn load(state: &CatalogState) -> Self {
+        std::fs::read_to_string(style_path(state))
+            .ok()
+            .and_then(|style| serde_json::from_str(&style).ok())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn primary(&self) -> &str {
+        css_color(self.primary_color.as_deref()).unwrap_or("#0a7cff")
+    }
+
+    pub(crate) fn secondary(&self) -> &str {
+        css_color(self.secondary_color.as_deref()).unwrap_or("#1a1f36")
+    }
+}
+
+fn style_path(state: &CatalogState) -> PathBuf {
+    state
+        .manifest_path
+        .join(&state.catalog_path)
+        .join("assets")
+        .join("style.json")
+}
+
+/// A hex color (`#rgb`, `#rrggbb` or `#rrggbbaa`), safe to put in a stylesheet
+fn css_color(color: Option<&str>) -> Option<&str> {
+    color.filter(|color| {
+        color.strip_prefix('#').is_some_and(|hex| {
+            matches!(hex.len(), 3 | 6 | 8) && hex.bytes().all(|b| b.is_ascii_hexdigit())
+        })
+    })
+}
+
+/// Escape text for HTML content and attribute values
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format an amount in cents as a decimal (e.g. `1250` as `12.50`)
+pub fn format_decimal(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+}
+
+fn format_amount(cents: i64, currency: &str) -> String {
+    format!("{} {}", format_decimal(cents), currency.to_uppercase())
+}
+
+/// Absolute URL of `path` on the host the request was made to
+pub(crate) fn absolute_url(state: &CatalogState, headers: &HeaderMap, path: &str) -> Url {
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("http");
+
+    headers
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| Url::parse(&format!("{}://{}{}", scheme, host, path)).ok())
+        .or_else(|| state.facilitator_url.join(path).ok())
+        .unwrap_or_else(|| state.facilitator_url.clone())
+}
+
+/// URL of the hosted checkout page of a session created at `sessions_path`
+/// (`.../checkout/sessions`)
+pub(crate) fn checkout_page_url(
+    state: &CatalogState,
+    headers: &HeaderMap,
+    sessions_path: &str,
+    session_id: &str,
+) -> Url {
+    let prefix = sessions_path
+        .trim_end_matches('/')
+        .strip_suffix("/checkout/sessions")
+        .unwrap_or("");
+    absolute_url(
+        state,
+        headers,
+        &format!("{}/checkout/pay/{}", prefix, session_id),
+    )
+}
+
+/// SVG QR code of `data`
+fn qr_code_svg(data: &str) -> Option<String> {
+    let code = QrCode::new(data.as_bytes()).ok()?;
+    Some(
+        code.render::<svg::Color>()
+            .min_dimensions(220, 220)
+            .quiet_zone(true)
+            .build(),
+    )
+}
+
+/// x402 payment requirements of the session's payment intent, one per accepted currency
+///
+/// They are the requirements the payment middleware issues when the intent is confirmed at
+/// `confirm_url`, each with its signed quote, so that wallets can pay with them directly.
+fn payment_requirements(
+    state: &CatalogState,
+    session: &StripeCheckoutSession,
+    confirm_url: &Url,
+    now: i64,
+) -> Vec<PaymentRequirements> {
+    let network = Network::Solana;
+    let Some(network_config) = state.networks_config.get_config_for_network(&network) else {
+        return vec![];
+    };
+    let Some((amount, currency, basket)) = session
+        .payment_intent
+        .as_deref()
+        .and_then(|payment_intent_id| payment_intent_charge(state, payment_intent_id))
+    else {
+        return vec![];
+    };
+
+    // Stablecoins the session's currency can't be converted into are not offered
+    network_config
+        .currencies()
+        .iter()
+        .filter_map(|stablecoin| {
+            let settlement = settlement_amount(
+                state.fx_rates.as_deref(),
+                &currency,
+                Decimal::from(amount),
+                stablecoin,
+            )?;
+            let token_amount = settlement.token_amount(stablecoin.decimals())?;
+            Some((stablecoin, token_amount, settlement))
+        })
+        .map(|(stablecoin, token_amount, settlement)| {
+            let mut requirements = PaymentRequirements {
+                scheme: Scheme::Exact,
+                network: network.clone(),
+                max_amount_required: TokenAmount(token_amount.to_string()),
+                resource: confirm_url.clone(),
+                description: format!("Payment for checkout session {}", session.id),
+                mime_type: "application/json".to_string(),
+                output_schema: None,
+                pay_to: network_config.recipient().address(),
+                max_timeout_seconds: 300,
+                asset: stablecoin.address(),
+                extra: Some(json!({
+                    "product": basket,
+                    "paymentIntentId": session.payment_intent,
+                    "fx": settlement.fx,
+                })),
+            };
+            // Signed like the middleware's, so the confirm route accepts them
+            let quote = state.quote_signer.issue("POST", &requirements, now);
+            if let Some(serde_json::Value::Object(extra)) = requirements.extra.as_mut() {
+                extra.insert("quote".to_string(), json!(quote));
+            }
+            requirements
+        })
+        .collect()
+}
+
+fn not_found_page(session_id: &str) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Html(format!(
+            "<!doctype html><html><head><meta charset=\"utf-8\"><title>Checkout not found</title></head>\
+             <body><p>No such checkout session: {}</p></body></html>",
+            escape_html(session_id)
+        )),
+    )
+        .into_response()
+}
+
+/// URL to send the buyer to once the session is complete, with Stripe's
+/// `{CHECKOUT_SESSION_ID}` template filled in
+fn success_redirect(session: &StripeCheckoutSession) -> Option<String> {
+    session
+        .success_url
+        .as_ref()
+        .map(|url| url.replace("{CHECKOUT_SESSION_ID}", &session.id))
+}
+
+/// GET /checkout/pay/:id - Hosted checkout page of a session
+pub async fn hosted_checkout_page(
+    Extension(state): Extension<CatalogState>,
+    Path(session_id): Path<String>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+) -> Response {
+    let Some(session) = current_checkout_session(&state, &session_id) else {
+        return not_found_page(&session_id);
+    };
+    if session.status == CheckoutSessionStatus::Complete
+        && let Some(success_url) = success_redirect(&session)
+    {
+        return Redirect::to(&success_url).into_response();
+    }
+
+    // Routes are nested under the catalog prefix (e.g. /catalog/v1)
+    let page_path = uri.path().to_string();
+    let prefix = page_path
+        .strip_suffix(&format!("/checkout/pay/{}", session_id))
+        .unwrap_or("");
+    let status_url = format!("{}/checkout/sessions/{}", prefix, session.id);
+    let confirm_url = absolute_url(
+        &state,
+        &headers,
+        &format!(
+            "{}/payment_intents/{}/confirm",
+            prefix,
+            session.payment_intent.as_deref().unwrap_or_default()
+        ),
+    );
+
+    let style = CheckoutStyle::load(&state);
+    let requirements = payment_requirements(
+        &state,
+        &session,
+        &confirm_url,
+        chrono::Utc::now().timestamp(),
+    );
+    Html(render_page(
+        &state,
+        &session,
+        &style,
+        &requirements,
+        &status_url,
+    ))
+    .into_response()
+}
+
+/// Render the page of a session
+pub fn render_page(
+    state: &CatalogState,
+    session: &StripeCheckoutSession,
+    style: &CheckoutStyle,
+    requirements: &[PaymentRequirements],
+    status_url: &str,
+) -> String {
+    let stack_name = state
+        .stack_name
+        .as_deref()
+        .or(state.catalog_name.as_deref())
+        .unwrap_or("Checkout");
+
+    let logo = state
+        .stack_image_url
+        .as_deref()
+        .map(|url| {
+            format!(
+                "<img class=\"logo\" src=\"{}\" alt=\"{}\">",
+                escape_html(url),
+                escape_html(stack_name)
+            )
+        })
+        .unwrap_or_default();
+
+    let lines = session
+        .line_items
+        .data
+        .iter()
+        .map(|item| {
+            let name = item
+                .description
+                .as_deref()
+                .or(item.price.product.as_deref())
+                .unwrap_or(&item.id);
+            format!(
+                "<tr><td>{}<span class=\"muted\"> &times; {}</span></td><td class=\"amount\">{}</td></tr>",
+                escape_html(name),
+                item.quantity,
+                escape_html(&format_amount(item.amount_subtotal, &item.currency))
+            )
+        })
+        .collect::<String>();
+
+    let mut totals = format!(
+        "<tr><td>Subtotal</td><td class=\"amount\">{}</td></tr>",
+        escape_html(&format_amount(session.amount_subtotal, &session.currency))
+    );
+    if session.total_details.amount_discount > 0 {
+        totals.push_str(&format!(
+            "<tr><td>Discount</td><td class=\"amount\">-{}</td></tr>",
+            escape_html(&format_amount(
+                session.total_details.amount_discount,
+                &session.currency
+            ))
+        ));
+    }
+    if session.total_details.amount_tax > 0 {
+        totals.push_str(&format!(
+            "<tr><td>Tax</td><td class=\"amount\">{}</td></tr>",
+            escape_html(&format_amount(
+                session.total_details.amount_tax,
+                &session.currency
+            ))
+        ));
+    }
+    totals.push_str(&format!(
+        "<tr class=\"total\"><td>Total</td><td class=\"amount\">{}</td></tr>",
+        escape_html(&format_amount(session.amount_total, &session.currency))
+    ));
+
+    let payment = match session.status {
+        CheckoutSessionStatus::Open => render_payment(session, requirements),
+        CheckoutSessionStatus::Complete => "<p class=\"status\">Payment received.</p>".to_string(),
+        CheckoutSessionStatus::Expired => {
+            let back = session
+                .cancel_url
+                .as_deref()
+                .map(|url| format!(" <a href=\"{}\">Go back</a>", escape_html(url)))
+                .unwrap_or_default();
+            format!(
+                "<p class=\"status\">This checkout session has expired.{}</p>",
+                back
+            )
+        }
+    };
+    let cancel = match (&session.status, session.cancel_url.as_deref()) {
+        (CheckoutSessionStatus::Open, Some(url)) => {
+            format!(
+                "<a class=\"cancel\" href=\"{}\">Cancel</a>",
+                escape_html(url)
+            )
+        }
+        _ => String::new(),
+    };
+
+    let success_url = success_redirect(session).unwrap_or_default();
+    let script = format!(
+        "<script>\
+         (function () {{\
+           var statusUrl = {status_url};\
+           var successUrl = {success_url};\
+           function poll() {{\
+             fetch(statusUrl).then(function (r) {{ return r.json(); }}).then(function (s) {{\
+               if (s.status === 'complete') {{\
+                 if (successUrl) {{ window.location.assign(successUrl); return; }}\
+                 window.location.reload(); return;\
+               }}\
+               if (s.status === 'expired') {{ window.location.reload(); return; }}\
+               setTimeout(poll, {interval});\
+             }}).catch(function () {{ setTimeout(poll, {interval}); }});\
+           }}\
+           setTimeout(poll, {interval});\
+         }})();\
+         </script>",
+        status_url = script_string(status_url),
+        success_url = script_string(&success_url),
+        interval = STATUS_POLL_INTERVAL_MS,
+    );
+    let script = if session.status == CheckoutSessionStatus::Open {
+        script
+    } else {
+        String::new()
+    };
+
+    format!(
+        "<!doctype html>\
+<html lang=\"en\">\
+<head>\
+<meta charset=\"utf-8\">\
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+<title>{title}</title>\
+<style>\
+:root {{ --primary: {primary}; --secondary: {secondary}; }}\
+body {{ margin: 0; font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif; color: var(--secondary); background: #f6f8fa; }}\
+main {{ max-width: 880px; margin: 0 auto; padding: 32px 16px; display: flex; flex-wrap: wrap; gap: 32px; }}\
+section {{ flex: 1 1 360px; }}\
+header {{ display: flex; align-items: center; gap: 12px; margin-bottom: 24px; }}\
+.logo {{ width: 40px; height: 40px; border-radius: 8px; object-fit: cover; }}\
+h1 {{ font-size: 18px; margin: 0; }}\
+table {{ width: 100%; border-collapse: collapse; }}\
+td {{ padding: 8px 0; border-bottom: 1px solid #e3e8ee; }}\
+.amount {{ text-align: right; white-space: nowrap; }}\
+.total td {{ font-weight: 600; font-size: 18px; border-bottom: none; }}\
+.muted {{ color: #697386; }}\
+.card {{ background: #fff; border-radius: 12px; padding: 24px; box-shadow: 0 1px 3px rgba(0,0,0,.08); }}\
+.qr svg {{ display: block; margin: 0 auto 16px; max-width: 100%; height: auto; }}\
+.button {{ display: block; text-align: center; padding: 12px; border-radius: 8px; background: var(--primary); color: #fff; text-decoration: none; font-weight: 600; }}\
+.cancel {{ display: inline-block; margin-top: 16px; color: #697386; }}\
+details {{ margin-top: 16px; font-size: 13px; }}\
+pre {{ overflow-x: auto; background: #f6f8fa; padding: 12px; border-radius: 8px; }}\
+.status {{ font-weight: 600; }}\
+</style>\
+</head>\
+<body>\
+<main>\
+<section>\
+<header>{logo}<h1>{title}</h1></header>\
+<table>{lines}</table>\
+<table>{totals}</table>\
+{cancel}\
+</section>\
+<section class=\"card\">{payment}</section>\
+</main>\
+{script}\
+</body>\
+</html>",
+        title = escape_html(stack_name),
+        primary = style.primary(),
+        secondary = style.secondary(),
+        logo = logo,
+        lines = lines,
+        totals = totals,
+        cancel = cancel,
+        payment = payment,
+        script = script,
+    )
+}
+
+/// Payment options of an open session: the Solana Pay QR code and deep link of its payment
+/// intent, and the x402 payment requirements
+fn render_payment(session: &StripeCheckoutSession, requirements: &[PaymentRequirements]) -> String {
+    let Some(requirement) = requirements.first() else {
+        return "<p class=\"status\">Payments are not configured for this stack.</p>".to_string();
+    };
+    let wallet = session
+        .solana_pay
+        .as_ref()
+        .map(|transfer| {
+            format!(
+                "<p class=\"muted\">Scan with a Solana wallet to pay</p>\
+                 <div class=\"qr\">{qr}</div>\
+                 <a class=\"button\" href=\"{pay_url}\">Open in wallet</a>",
+                qr = qr_code_svg(&transfer.url).unwrap_or_default(),
+                pay_url = escape_html(&transfer.url),
+            )
+        })
+        .unwrap_or_default();
+    let accepts = serde_json::to_string_pretty(&json!({
+        "x402Version": 1,
+        "accepts": requirements,
+    }))
+    .unwrap_or_default();
+
+    format!(
+        "{wallet}\
+         <details><summary>x402 payment requirements</summary>\
+         <p class=\"muted\">x402 clients pay by posting the payment to <code>{resource}</code>.</p>\
+         <pre>{accepts}</pre></details>",
+        wallet = wallet,
+        resource = escape_html(requirement.resource.as_str()),
+        accepts = escape_html(&accepts),
+    )
+}
+
+/// `value` as a JavaScript string literal, safe inside a `<script>` element
+fn script_string(value: &str) -> String {
+    serde_json::to_string(value)
+        .unwrap_or_else(|_| "\"\"".to_string())
+        .replace("</", "<\\/")
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, Router, routing::post};
+    use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+    use indexmap::IndexMap;
+    use moneymq_types::{
+        Currency, Price, PricingType, Product,
+        x402::{MixedAddress, MoneyMqNetwork, SupportedResponse, VerifyResponse},
+    };
+    use solana_pubkey::Pubkey;
+
+    use super::*;
+    use crate::api::{
+        catalog::{
+            middleware::x402_post,
+            stripe::{
+                endpoints::{
+                    checkout_sessions::open_checkout_session,
+                    payment_intents::confirm_payment_intent,
+                },
+                types::{CreateCheckoutSessionRequest, PaymentIntentStatus},
+            },
+        },
+        sandbox::NetworksConfig,
+    };
+
+    /// Serve a facilitator verifying and settling every payment, returning its URL
+    async fn facilitator() -> Url {
+        let payer = MixedAddress::Solana(Pubkey::new_from_array([3; 32]));
+        let verify = move || async move { Json(VerifyResponse::Valid { payer }) };
+        let supported = || async { Json(SupportedResponse { kinds: Vec::new() }) };
+        let settle = || async {
+            Json(json!({
+                "success": true,
+                "payer": Pubkey::new_from_array([3; 32]).to_string(),
+                "network": "solana",
+            }))
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let router = Router::new()
+            .route("/supported", axum::routing::get(supported))
+            .route("/verify", post(verify))
+            .route("/settle", post(settle));
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        format!("http://{}/", address).parse().unwrap()
+    }
+
+    async fn state() -> CatalogState {
+        let mut price = Price::new(Currency::Usd, PricingType::OneTime).with_some_amount(Some(900));
+        price.id = "price_ebook".to_string();
+        let mut product = Product::new()
+            .with_some_name(Some("Ebook".to_string()))
+            .add_price(price);
+        product.id = "ebook".to_string();
+        let networks_config = NetworksConfig::initialize(
+            IndexMap::from([(
+                "solana".to_string(),
+                (
+                    MoneyMqNetwork::SolanaMainnet,
+                    Some(Pubkey::new_from_array([1; 32]).to_string()),
+                    vec!["USDC".to_string()],
+                ),
+            )]),
+            false,
+        )
+        .unwrap();
+        CatalogState::new(
+            vec![product],
+            Vec::new(),
+            false,
+            facilitator().await,
+            networks_config,
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_pay_with_checkout_page_requirements() {
+        let state = state().await;
+        let request =
+            CreateCheckoutSessionRequest::parse(b"line_items[0][price]=price_ebook&mode=payment")
+                .unwrap();
+        let session =
+            open_checkout_session(&state, &HeaderMap::new(), "/checkout/sessions", request)
+                .unwrap();
+        let payment_intent_id = session.payment_intent.clone().unwrap();
+
+        // Serve the confirm route behind the payment middleware
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let router = Router::new()
+            .route(
+                "/payment_intents/{id}/confirm",
+                x402_post(confirm_payment_intent, None),
+            )
+            .layer(axum::Extension(state.clone()));
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        let confirm_url: Url = format!(
+            "http://{}/payment_intents/{}/confirm",
+            address, payment_intent_id
+        )
+        .parse()
+        .unwrap();
+
+        let requirements = payment_requirements(
+            &state,
+            &session,
+            &confirm_url,
+            chrono::Utc::now().timestamp(),
+        );
+        assert_eq!(requirements.len(), 1);
+        let quote = &requirements[0].extra.as_ref().unwrap()["quote"];
+        assert_eq!(quote["method"], "POST");
+
+        // The wallet pays with the requirements shown on the page
+        let payment = json!({
+            "x402Version": 1,
+            "scheme": "exact",
+            "network": "solana",
+            "payload": { "transaction": "tx" },
+            "accepted": requirements[0],
+        });
+        let response = reqwest::Client::new()
+            .post(confirm_url)
+            .header("X-Payment", BASE64.encode(payment.to_string()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let payment_intents = state.payment_intents.lock().unwrap();
+        assert_eq!(
+            payment_intents[&payment_intent_id].status,
+            PaymentIntentStatus::Succeeded
+        );
+    }
+
+    #[test]
+    fn test_qr_code() {
+        let svg = qr_code_svg("solana:9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin?amount=12.50")
+            .unwrap();
+        assert!(svg.starts_with("<?xml"));
+        assert!(svg.contains("<svg"));
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(
+            escape_html("<b>\"Tom\" & 'Jerry'</b>"),
+            "&lt;b&gt;&quot;Tom&quot; &amp; &#39;Jerry&#39;&lt;/b&gt;"
+        );
+        assert_eq!(script_string("</script>"), "\"<\\/script>\"");
+        assert_eq!(css_color(Some("#12abEF")), Some("#12abEF"));
+        assert_eq!(css_color(Some("red; background: url(x)")), None);
+        assert_eq!(format_decimal(-5), "-0.05");
+    }
+}
@@ -0,0 +1,4 @@
+ALTER TABLE prices DROP COLUMN transform_quantity;
+ALTER TABLE prices DROP COLUMN tiers_mode;
+ALTER TABLE prices DROP COLUMN tiers;
+ALTER TABLE prices DROP COLUMN unit_amount_decimal;
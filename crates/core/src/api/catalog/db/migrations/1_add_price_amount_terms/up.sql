@@ -0,0 +1,9 @@
+-- Exact unit amount in cents with a fraction of a cent (e.g., "0.1"), for micropayment
+-- prices; unit_amount is 0 for them
+ALTER TABLE prices ADD COLUMN unit_amount_decimal TEXT;
+-- Tiers charged instead of the unit amount (JSON: [{up_to, unit_amounts, flat_amounts}])
+ALTER TABLE prices ADD COLUMN tiers TEXT;
+-- How quantities are charged across the tiers: "graduated" or "volume"
+ALTER TABLE prices ADD COLUMN tiers_mode TEXT;
+-- Package pricing (JSON: {divide_by, round})
+ALTER TABLE prices ADD COLUMN transform_quantity TEXT;
@@ -5,7 +5,9 @@ use tracing::debug;
 pub mod models;
 pub mod schema;
 
-pub use models::{NewPrice, NewProduct, PriceModel, ProductModel, UpdatePrice, UpdateProduct};
+pub use models::{
+    NewPrice, NewProduct, PriceModel, PriceRecord, ProductModel, UpdatePrice, UpdateProduct,
+};
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./src/api/catalog/db/migrations");
 
@@ -109,36 +111,13 @@ impl CatalogDbManager {
     }
 
     /// Insert a price for a product
-    pub fn insert_price(
-        &self,
-        product_db_id: i32,
-        price_id: Option<&str>,
-        pricing_type: &str,
-        currency: &str,
-        unit_amount: i64,
-        recurring_interval: Option<&str>,
-        recurring_interval_count: Option<i32>,
-        active: bool,
-        metadata: Option<&str>,
-    ) -> DbResult<i32> {
+    pub fn insert_price(&self, product_db_id: i32, price: PriceRecord) -> DbResult<i32> {
         let mut conn = self
             .db_conn
             .get()
             .map_err(|e| DbError::ConnectionError(e.to_string()))?;
 
-        let new_price = NewPrice::new(
-            product_db_id,
-            price_id.map(|s| s.to_string()),
-            pricing_type.to_string(),
-            currency.to_string(),
-            unit_amount,
-            recurring_interval.map(|s| s.to_string()),
-            recurring_interval_count,
-            active,
-            metadata.map(|s| s.to_string()),
-        );
-
-        new_price
+        NewPrice::new(product_db_id, price)
             .insert(&mut conn)
             .map_err(DbError::InsertPriceError)
     }
@@ -222,16 +201,7 @@ impl CatalogDbManager {
         active: bool,
         metadata: Option<&str>,
         is_sandbox: bool,
-        prices: Vec<(
-            Option<&str>, // price_id
-            &str,         // pricing_type
-            &str,         // currency
-            i64,          // unit_amount
-            Option<&str>, // recurring_interval
-            Option<i32>,  // recurring_interval_count
-            bool,         // active
-            Option<&str>, // metadata
-        )>,
+        prices: Vec<PriceRecord>,
     ) -> DbResult<i32> {
         // Upsert the product
         let product_db_id = self.upsert_product(
@@ -250,28 +220,8 @@ impl CatalogDbManager {
         self.delete_prices_for_product(product_db_id)?;
 
         // Insert new prices
-        for (
-            price_id,
-            pricing_type,
-            currency,
-            unit_amount,
-            recurring_interval,
-            recurring_interval_count,
-            price_active,
-            price_metadata,
-        ) in prices
-        {
-            self.insert_price(
-                product_db_id,
-                price_id,
-                pricing_type,
-                currency,
-                unit_amount,
-                recurring_interval,
-                recurring_interval_count,
-                price_active,
-                price_metadata,
-            )?;
+        for price in prices {
+            self.insert_price(product_db_id, price)?;
         }
 
         Ok(product_db_id)
@@ -1,5 +1,5 @@
 pub mod price;
 pub mod product;
 
-pub use price::{NewPrice, PriceModel, UpdatePrice};
+pub use price::{NewPrice, PriceModel, PriceRecord, UpdatePrice};
 pub use product::{NewProduct, ProductModel, UpdateProduct};
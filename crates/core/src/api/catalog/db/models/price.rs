@@ -22,6 +22,10 @@ pub struct PriceModel {
     pub recurring_interval_count: Option<i32>,
     pub active: bool,
     pub metadata: Option<String>,
+    pub unit_amount_decimal: Option<String>,
+    pub tiers: Option<String>,
+    pub tiers_mode: Option<String>,
+    pub transform_quantity: Option<String>,
 }
 
 impl PriceModel {
@@ -69,6 +73,28 @@ impl PriceModel {
     }
 }
 
+/// Fields of a price stored for a product
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PriceRecord {
+    pub price_id: Option<String>,
+    pub pricing_type: String,
+    pub currency: String,
+    /// Amount in cents; 0 for prices with a decimal amount or tiers
+    pub unit_amount: i64,
+    /// Amount in cents with a fraction of a cent
+    pub unit_amount_decimal: Option<String>,
+    /// Tiers as JSON
+    pub tiers: Option<String>,
+    pub tiers_mode: Option<String>,
+    /// Package pricing as JSON
+    pub transform_quantity: Option<String>,
+    pub recurring_interval: Option<String>,
+    pub recurring_interval_count: Option<i32>,
+    pub active: bool,
+    /// Metadata as JSON
+    pub metadata: Option<String>,
+}
+
 #[derive(Insertable, Debug)]
 #[diesel(table_name = prices)]
 pub struct NewPrice {
@@ -79,6 +105,10 @@ pub struct NewPrice {
     pub pricing_type: String,
     pub currency: String,
     pub unit_amount: i64,
+    pub unit_amount_decimal: Option<String>,
+    pub tiers: Option<String>,
+    pub tiers_mode: Option<String>,
+    pub transform_quantity: Option<String>,
     pub recurring_interval: Option<String>,
     pub recurring_interval_count: Option<i32>,
     pub active: bool,
@@ -86,30 +116,24 @@ pub struct NewPrice {
 }
 
 impl NewPrice {
-    pub fn new(
-        product_id: i32,
-        price_id: Option<String>,
-        pricing_type: String,
-        currency: String,
-        unit_amount: i64,
-        recurring_interval: Option<String>,
-        recurring_interval_count: Option<i32>,
-        active: bool,
-        metadata: Option<String>,
-    ) -> Self {
+    pub fn new(product_id: i32, price: PriceRecord) -> Self {
         let timestamp = chrono::Utc::now().timestamp_millis();
         Self {
             created_at: timestamp,
             updated_at: timestamp,
             product_id,
-            price_id,
-            pricing_type,
-            currency,
-            unit_amount,
-            recurring_interval,
-            recurring_interval_count,
-            active,
-            metadata,
+            price_id: price.price_id,
+            pricing_type: price.pricing_type,
+            currency: price.currency,
+            unit_amount: price.unit_amount,
+            unit_amount_decimal: price.unit_amount_decimal,
+            tiers: price.tiers,
+            tiers_mode: price.tiers_mode,
+            transform_quantity: price.transform_quantity,
+            recurring_interval: price.recurring_interval,
+            recurring_interval_count: price.recurring_interval_count,
+            active: price.active,
+            metadata: price.metadata,
         }
     }
 
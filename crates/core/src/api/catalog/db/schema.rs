@@ -29,6 +29,10 @@ diesel::table! {
         recurring_interval_count -> Nullable<Int4>,
         active -> Bool,
         metadata -> Nullable<Text>,
+        unit_amount_decimal -> Nullable<Text>,
+        tiers -> Nullable<Text>,
+        tiers_mode -> Nullable<Text>,
+        transform_quantity -> Nullable<Text>,
     }
 }
 
@@ -0,0 +1,387 @@
+//! Pricing experiments: deterministic assignment of variants and their results
+//!
+//! An experiment is a catalog product whose variants are products with an `experiment`
+//! config and the product as `parent_id`. A subject (customer, wallet or anonymous id)
+//! hashed with the experiment ID falls in a bucket of [0, 1): the variants, in ID order,
+//! take consecutive ranges as wide as their `exposure`, and the rest is the control group,
+//! shown the parent product.
+//!
+//! Exposures are recorded when products are listed for a subject, once per subject and
+//! experiment. Conversions are recorded when a payment for a variant (or for the parent
+//! product, converting the control group) settles, with the amount paid as revenue.
+
+use std::collections::BTreeMap;
+
+use axum::{
+    Extension, Json,
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use moneymq_types::{LineItem, Product};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{error, warn};
+
+use crate::api::{
+    catalog::{CatalogState, stripe::endpoints::entitlements::basket_product_ids},
+    payment::db::experiment_event::{KIND_CONVERSION, KIND_EXPOSURE, NewExperimentEvent},
+};
+
+/// Results of one variant (or of the control group) of an experiment
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VariantResults {
+    /// Variant product, or the experiment's product for the control group
+    pub variant_id: String,
+    pub control: bool,
+    /// Configured share of subjects assigned to the variant
+    pub exposure: f64,
+    /// Subjects shown the variant
+    pub exposures: i64,
+    /// Settled payments for the variant
+    pub conversions: i64,
+    pub conversion_rate: f64,
+    /// Amount paid by the conversions per currency (cents)
+    pub revenue: BTreeMap<String, i64>,
+}
+
+/// Response of GET /v1/experiments/:id/results
+#[derive(Debug, Serialize)]
+pub struct ExperimentResults {
+    pub object: String,
+    pub experiment: String,
+    pub livemode: bool,
+    pub variants: Vec<VariantResults>,
+}
+
+/// Variants of the experiment on `experiment_id`, in ID order
+pub fn experiment_variants<'a>(products: &'a [Product], experiment_id: &str) -> Vec<&'a Product> {
+    let mut variants = products
+        .iter()
+        .filter(|p| p.experiment.is_some() && p.parent_id.as_deref() == Some(experiment_id))
+        .collect::<Vec<_>>();
+    variants.sort_by(|a, b| a.id.cmp(&b.id));
+    variants
+}
+
+/// Bucket of a subject in an experiment, in [0, 1)
+pub fn bucket(experiment_id: &str, subject: &str) -> f64 {
+    let digest = Sha256::digest(format!("{}:{}", experiment_id, subject));
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Variant shown to `subject`, or None for the control group
+pub fn assign_variant<'a>(
+    variants: &[&'a Product],
+    experiment_id: &str,
+    subject: &str,
+) -> Option<&'a Product> {
+    let bucket = bucket(experiment_id, subject);
+    let mut cumulative = 0.0;
+    variants.iter().copied().find(|variant| {
+        cumulative += variant
+            .experiment
+            .as_ref()
+            .map_or(0.0, |e| e.exposure.max(0.0));
+        bucket < cumulative
+    })
+}
+
+/// Record that `subject` was shown `variant_id` (the experiment's product for the control
+/// group). Anonymous exposures, without a subject, are each counted.
+pub(crate) fn record_exposure(
+    state: &CatalogState,
+    experiment_id: &str,
+    variant_id: &str,
+    subject: Option<&str>,
+) {
+    let Some(db) = state.payment_db.as_ref() else {
+        return;
+    };
+    let exposure = NewExperimentEvent::exposure(
+        experiment_id.to_string(),
+        variant_id.to_string(),
+        subject.map(str::to_string),
+        state.payment_stack_id.clone(),
+        state.use_sandbox,
+    );
+    if let Err(e) = db.insert_experiment_event(&exposure) {
+        warn!(
+            "Failed to record exposure to {} of experiment {}: {}",
+            variant_id, experiment_id, e
+        );
+    }
+}
+
+/// Experiments converted by buying `product_ids`, as (experiment, variant) pairs
+fn converted_variants(products: &[Product], product_ids: &[String]) -> Vec<(String, String)> {
+    product_ids
+        .iter()
+        .filter_map(|id| {
+            let product = products.iter().find(|p| &p.id == id)?;
+            match (&product.experiment, &product.parent_id) {
+                (Some(_), Some(parent_id)) => Some((parent_id.clone(), product.id.clone())),
+                _ if !experiment_variants(products, &product.id).is_empty() => {
+                    Some((product.id.clone(), product.id.clone()))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Record the conversions of the experiments whose products a settled payment bought
+pub(crate) fn record_conversions(
+    state: &CatalogState,
+    product_ids: &[String],
+    reference: &str,
+    subject: Option<&str>,
+    amount: i64,
+    currency: &str,
+) {
+    let Some(db) = state.payment_db.as_ref() else {
+        return;
+    };
+    for (experiment_id, variant_id) in converted_variants(&state.catalog().products, product_ids) {
+        let conversion = NewExperimentEvent::conversion(
+            experiment_id.clone(),
+            variant_id.clone(),
+            subject.map(str::to_string),
+            reference.to_string(),
+            amount,
+            currency.to_lowercase(),
+            state.payment_stack_id.clone(),
+            state.use_sandbox,
+        );
+        if let Err(e) = db.insert_experiment_event(&conversion) {
+            warn!(
+                "Failed to record conversion of {} in experiment {}: {}",
+                variant_id, experiment_id, e
+            );
+        }
+    }
+}
+
+/// Record the conversions of a payment intent that succeeded, from its line items
+pub(crate) fn record_payment_intent_conversions(state: &CatalogState, payment_intent_id: &str) {
+    let (product_ids, customer, amount, currency) = {
+        let payment_intents = state.payment_intents.lock().unwrap();
+        let Some(intent) = payment_intents.get(payment_intent_id) else {
+            return;
+        };
+        let product_ids = match intent
+            .metadata
+            .get("line_items")
+            .and_then(|items| serde_json::from_str::<Vec<LineItem>>(items).ok())
+        {
+            Some(items) => items
+                .into_iter()
+                .map(|item| {
+                    (item.price.experiment_id)
+                        .filter(|id| !id.is_empty())
+                        .unwrap_or(item.price.product)
+                })
+                .collect(),
+            None => intent
+                .metadata
+                .get("product_id")
+                .map(String::as_str)
+                .map(basket_product_ids)
+                .unwrap_or_default(),
+        };
+        (
+            product_ids,
+            intent.customer.clone(),
+            intent.amount,
+            intent.currency.clone(),
+        )
+    };
+    record_conversions(
+        state,
+        &product_ids,
+        payment_intent_id,
+        customer.as_deref(),
+        amount,
+        &currency,
+    );
+}
+
+fn experiment_error(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(serde_json::json!({
+            "error": {
+                "code": code,
+                "message": message.into(),
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// GET /v1/experiments/:id/results - Exposures, conversions and revenue per variant
+///
+/// The experiment ID is the ID of the product the variants are experiments of.
+pub async fn experiment_results(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let catalog = state.catalog();
+    let variants = experiment_variants(&catalog.products, &id);
+    if variants.is_empty() {
+        return experiment_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such experiment: '{}'", id),
+        );
+    }
+    let Some(db) = state.payment_db.as_ref() else {
+        return experiment_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "experiments_not_configured",
+            "Experiment results require a database",
+        );
+    };
+    let events = match db.list_experiment_events(&id, &state.payment_stack_id, state.use_sandbox) {
+        Ok(events) => events,
+        Err(e) => {
+            error!("Failed to list events of experiment {}: {}", id, e);
+            return experiment_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "api_error",
+                e.to_string(),
+            );
+        }
+    };
+
+    let variant_exposure = variants
+        .iter()
+        .map(|v| v.experiment.as_ref().map_or(0.0, |e| e.exposure.max(0.0)))
+        .sum::<f64>();
+    let mut results = vec![VariantResults {
+        variant_id: id.clone(),
+        control: true,
+        exposure: (1.0 - variant_exposure).max(0.0),
+        exposures: 0,
+        conversions: 0,
+        conversion_rate: 0.0,
+        revenue: BTreeMap::new(),
+    }];
+    results.extend(variants.iter().map(|variant| VariantResults {
+        variant_id: variant.id.clone(),
+        control: false,
+        exposure: variant.experiment.as_ref().map_or(0.0, |e| e.exposure),
+        exposures: 0,
+        conversions: 0,
+        conversion_rate: 0.0,
+        revenue: BTreeMap::new(),
+    }));
+
+    for event in &events {
+        let Some(result) = results
+            .iter_mut()
+            .find(|r| r.variant_id == event.variant_id)
+        else {
+            continue;
+        };
+        match event.kind.as_str() {
+            KIND_EXPOSURE => result.exposures += 1,
+            KIND_CONVERSION => {
+                result.conversions += 1;
+                if let Some(currency) = &event.currency {
+                    *result.revenue.entry(currency.clone()).or_default() += event.amount;
+                }
+            }
+            _ => {}
+        }
+    }
+    for result in &mut results {
+        if result.exposures > 0 {
+            result.conversion_rate = result.conversions as f64 / result.exposures as f64;
+        }
+    }
+
+    (
+        StatusCode::OK,
+        Json(ExperimentResults {
+            object: "experiment_results".to_string(),
+            experiment: id,
+            livemode: !state.use_sandbox,
+            variants: results,
+        }),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use moneymq_types::ExperimentConfig;
+
+    use super::*;
+
+    fn product(id: &str) -> Product {
+        let mut product = Product::new();
+        product.id = id.to_string();
+        product
+    }
+
+    fn variant(id: &str, parent_id: &str, exposure: f64) -> Product {
+        let mut product = product(id);
+        product.experiment = Some(ExperimentConfig { exposure });
+        product.parent_id = Some(parent_id.to_string());
+        product
+    }
+
+    #[test]
+    fn test_assign_variant() {
+        let products = vec![
+            product("pro"),
+            variant("pro#b", "pro", 0.25),
+            variant("pro#a", "pro", 0.25),
+        ];
+        let variants = experiment_variants(&products, "pro");
+        assert_eq!(variants[0].id, "pro#a");
+
+        let mut counts = BTreeMap::new();
+        for i in 0..4000 {
+            let subject = format!("cus_{}", i);
+            let assigned = assign_variant(&variants, "pro", &subject);
+            // The same subject always sees the same variant
+            assert_eq!(
+                assigned.map(|v| &v.id),
+                assign_variant(&variants, "pro", &subject).map(|v| &v.id)
+            );
+            *counts
+                .entry(assigned.map_or("pro", |v| v.id.as_str()))
+                .or_insert(0) += 1;
+        }
+        assert!((1800..2200).contains(&counts["pro"]));
+        assert!((800..1200).contains(&counts["pro#a"]));
+        assert!((800..1200).contains(&counts["pro#b"]));
+
+        // Bucketing is independent between experiments
+        assert_ne!(bucket("pro", "cus_1"), bucket("basic", "cus_1"));
+        assert!(assign_variant(&[], "pro", "cus_1").is_none());
+    }
+
+    #[test]
+    fn test_converted_variants() {
+        let products = vec![
+            product("pro"),
+            variant("pro#a", "pro", 0.5),
+            product("basic"),
+        ];
+        let ids = ["pro#a", "pro", "basic", "unknown"].map(str::to_string);
+        assert_eq!(
+            converted_variants(&products, &ids),
+            vec![
+                ("pro".to_string(), "pro#a".to_string()),
+                ("pro".to_string(), "pro".to_string()),
+            ]
+        );
+    }
+}
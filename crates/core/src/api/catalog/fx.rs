@@ -0,0 +1,341 @@
+//! Currency conversion into the settlement stablecoin
+//!
+//! Prices may be declared in several currencies (`amounts: { usd: 49, eur: 45 }`) and
+//! buyers pay in the one they ask for. A payment settles in an accepted stablecoin tracking
+//! its currency when there is one (EURC for EUR). Otherwise its amount is converted into the
+//! currency of an accepted stablecoin at the rate of the catalog's [`FxRateProvider`], and
+//! the rate applied and the original amount are recorded as a [`PaymentFx`] on the
+//! transaction and receipt.
+
+use std::collections::HashMap;
+
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use moneymq_types::{PaymentFx, Price, round_cents, x402::Currency};
+use rust_decimal::{
+    Decimal, RoundingStrategy,
+    prelude::{FromPrimitive, ToPrimitive},
+};
+
+/// Rates of the currencies prices are declared in
+pub trait FxRateProvider: Send + Sync {
+    /// Name recorded with the rates applied (e.g., "static")
+    fn name(&self) -> &str;
+
+    /// Units of `to` per unit of `from`, both lowercase currency codes
+    fn rate(&self, from: &str, to: &str) -> Option<f64>;
+}
+
+/// Rates from a fixed table of US dollars per unit of each currency
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaticFxRates {
+    name: String,
+    usd_rates: HashMap<String, f64>,
+}
+
+impl StaticFxRates {
+    /// Rates from a table of US dollars per unit of each currency (e.g., `eur: 1.08`).
+    /// Rates that aren't positive are ignored.
+    pub fn new(usd_rates: impl IntoIterator<Item = (String, f64)>) -> Self {
+        Self {
+            name: "static".to_string(),
+            usd_rates: usd_rates
+                .into_iter()
+                .filter(|(_, rate)| rate.is_finite() && *rate > 0.0)
+                .map(|(currency, rate)| (currency.to_lowercase(), rate))
+                .collect(),
+        }
+    }
+
+    /// Fixed rates for local development and tests, so prices in every supported currency
+    /// can be paid without configuring a rate table
+    pub fn mock() -> Self {
+        Self {
+            name: "mock".to_string(),
+            ..Self::new([("eur".to_string(), 1.08), ("gbp".to_string(), 1.27)])
+        }
+    }
+
+    fn usd_rate(&self, currency: &str) -> Option<f64> {
+        if currency == "usd" {
+            return Some(1.0);
+        }
+        self.usd_rates.get(currency).copied()
+    }
+}
+
+impl FxRateProvider for StaticFxRates {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+        Some(self.usd_rate(from)? / self.usd_rate(to)?)
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum FxError {
+    #[error(
+        "Payments in {0} can't be settled: no accepted stablecoin tracks it and no exchange rate is available."
+    )]
+    Unsupported(String),
+    #[error("Line items must all be priced in {0}.")]
+    MixedCurrencies(String),
+    #[error(
+        "{0} cents can't be settled: the accepted stablecoins have fewer decimals than the amount."
+    )]
+    TooPrecise(Decimal),
+}
+
+impl IntoResponse for FxError {
+    fn into_response(self) -> Response {
+        let (code, param) = match &self {
+            FxError::Unsupported(_) => ("currency_not_supported", "currency"),
+            FxError::MixedCurrencies(_) => ("parameter_invalid", "currency"),
+            FxError::TooPrecise(_) => ("amount_too_precise", "price"),
+        };
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": {
+                    "code": code,
+                    "message": self.to_string(),
+                    "param": param,
+                    "type": "invalid_request_error"
+                }
+            })),
+        )
+            .into_response()
+    }
+}
+
+/// Amount due in a stablecoin for a payment
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettlementAmount {
+    /// Amount in the currency the stablecoin tracks (cents, with a fraction of a cent for
+    /// micropayments)
+    pub amount: Decimal,
+    /// Conversion applied, when the payment is priced in another currency
+    pub fx: Option<PaymentFx>,
+}
+
+impl SettlementAmount {
+    /// Amount in base units of a token with `decimals` decimals, None when it would have a
+    /// fraction of a base unit
+    pub fn token_amount(&self, decimals: u8) -> Option<i64> {
+        let base_units_per_cent =
+            Decimal::from(10_i64.checked_pow(decimals as u32)?) / Decimal::ONE_HUNDRED;
+        let base_units = self.amount.checked_mul(base_units_per_cent)?;
+        if !base_units.fract().is_zero() {
+            return None;
+        }
+        base_units.to_i64()
+    }
+}
+
+/// Amount due in `stablecoin` for `amount` cents of `currency`, converted at the provider's
+/// rate when the stablecoin tracks another currency. None without a rate.
+pub fn settlement_amount(
+    provider: Option<&dyn FxRateProvider>,
+    currency: &str,
+    amount: Decimal,
+    stablecoin: &Currency,
+) -> Option<SettlementAmount> {
+    let currency = currency.to_lowercase();
+    let settlement_currency = stablecoin.fiat_currency();
+    if currency == settlement_currency {
+        return Some(SettlementAmount { amount, fx: None });
+    }
+    let provider = provider?;
+    let rate = provider
+        .rate(&currency, settlement_currency)
+        .filter(|rate| rate.is_finite() && *rate > 0.0)?;
+    // Converted amounts keep the precision of the amount converted
+    let settlement = amount
+        .checked_mul(Decimal::from_f64(rate)?)?
+        .round_dp_with_strategy(amount.scale(), RoundingStrategy::MidpointAwayFromZero);
+    Some(SettlementAmount {
+        amount: settlement,
+        fx: Some(PaymentFx {
+            currency,
+            amount: round_cents(amount),
+            settlement_currency: settlement_currency.to_string(),
+            settlement_amount: round_cents(settlement),
+            rate,
+            source: provider.name().to_string(),
+        }),
+    })
+}
+
+/// Unit amount and currency a buyer asking for `currency` pays for `price`: the price's
+/// amount in that currency when it has one, and its own amount and currency otherwise
+pub fn price_amount(price: &Price, currency: Option<&str>) -> (i64, String) {
+    if let Some(currency) = currency
+        && let Some(amount) = price.amount_in(currency)
+    {
+        return (amount, currency.to_lowercase());
+    }
+    (
+        price.unit_amount.unwrap_or(0),
+        price.currency.as_str().to_string(),
+    )
+}
+
+/// Exact amount (cents, with a fraction of a cent for decimal amounts) and currency a buyer
+/// asking for `currency` pays for `quantity` units of `price`, charged by its tiers or
+/// packages when it has some, as [`price_amount`] picks the currency. None when the price
+/// can't charge the quantity in its own currency, or the amount overflows.
+pub fn exact_price_total(
+    price: &Price,
+    quantity: i64,
+    currency: Option<&str>,
+) -> Option<(Decimal, String)> {
+    if let Some(currency) = currency
+        && let Some(amount) = price.exact_amount_for(quantity, currency)
+    {
+        return Some((amount, currency.to_lowercase()));
+    }
+    let currency = price.currency.as_str();
+    let amount = price.exact_amount_for(quantity, currency)?;
+    Some((amount, currency.to_string()))
+}
+
+/// [`exact_price_total`] rounded to the cent
+pub fn price_total(price: &Price, quantity: i64, currency: Option<&str>) -> Option<(i64, String)> {
+    let (amount, currency) = exact_price_total(price, quantity, currency)?;
+    Some((round_cents(amount), currency))
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+    use moneymq_types::{
+        Currency as PriceCurrency, PricingType,
+        x402::{EURC_MINT, Network},
+    };
+
+    use super::*;
+
+    fn stablecoin(symbol: &str) -> Currency {
+        Currency::from_symbol_and_network(symbol, &Network::Solana).unwrap()
+    }
+
+    #[test]
+    fn test_static_rates_cross_through_usd() {
+        let rates = StaticFxRates::new([("EUR".to_string(), 1.10), ("gbp".to_string(), 1.32)]);
+        assert_eq!(rates.rate("usd", "usd"), Some(1.0));
+        assert_eq!(rates.rate("eur", "usd"), Some(1.10));
+        assert!((rates.rate("gbp", "eur").unwrap() - 1.2).abs() < 1e-9);
+        assert_eq!(rates.rate("jpy", "usd"), None);
+        assert_eq!(
+            StaticFxRates::new([("eur".to_string(), 0.0)]).rate("eur", "usd"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_settlement_amount() {
+        let usdc = stablecoin("USDC");
+        let eurc = stablecoin("EURC");
+        assert_eq!(eurc.solana_currency().unwrap().mint, EURC_MINT);
+        assert_eq!(eurc.fiat_currency(), "eur");
+        assert_eq!(usdc.fiat_currency(), "usd");
+
+        // A stablecoin tracking the price currency settles without conversion
+        let native = settlement_amount(None, "EUR", Decimal::from(4500), &eurc).unwrap();
+        assert_eq!(
+            native,
+            SettlementAmount {
+                amount: Decimal::from(4500),
+                fx: None
+            }
+        );
+        assert_eq!(native.token_amount(6), Some(45_000_000));
+
+        // Other stablecoins need a rate
+        assert_eq!(
+            settlement_amount(None, "eur", Decimal::from(4500), &usdc),
+            None
+        );
+        let mock = StaticFxRates::mock();
+        let converted = settlement_amount(Some(&mock), "eur", Decimal::from(4500), &usdc).unwrap();
+        assert_eq!(converted.amount, Decimal::from(4860));
+        assert_eq!(
+            converted.fx,
+            Some(PaymentFx {
+                currency: "eur".to_string(),
+                amount: 4500,
+                settlement_currency: "usd".to_string(),
+                settlement_amount: 4860,
+                rate: 1.08,
+                source: "mock".to_string(),
+            })
+        );
+        assert_eq!(
+            settlement_amount(Some(&mock), "jpy", Decimal::from(4500), &usdc),
+            None
+        );
+
+        // A tenth of a cent is 1,000 base units of a 6-decimal token, and can't be paid
+        // with fewer than 3 decimals
+        let micro = settlement_amount(None, "usd", Decimal::new(1, 1), &usdc).unwrap();
+        assert_eq!(micro.token_amount(6), Some(1_000));
+        assert_eq!(micro.token_amount(2), None);
+        let converted = settlement_amount(Some(&mock), "eur", Decimal::new(1, 1), &usdc).unwrap();
+        assert_eq!(converted.amount, Decimal::new(1, 1));
+    }
+
+    #[test]
+    fn test_price_amount() {
+        let mut currency_options = IndexMap::new();
+        currency_options.insert("eur".to_string(), 4500);
+        let price = moneymq_types::Price::new(PriceCurrency::Usd, PricingType::OneTime)
+            .with_some_amount(Some(4900))
+            .with_currency_options(currency_options);
+
+        assert_eq!(price_amount(&price, None), (4900, "usd".to_string()));
+        assert_eq!(price_amount(&price, Some("EUR")), (4500, "eur".to_string()));
+        assert_eq!(price_amount(&price, Some("gbp")), (4900, "usd".to_string()));
+        assert_eq!(
+            price_total(&price, 3, Some("eur")),
+            Some((13500, "eur".to_string()))
+        );
+        assert_eq!(
+            price_total(&price, 2, Some("gbp")),
+            Some((9800, "usd".to_string()))
+        );
+
+        // $0.001 per call is charged exactly, and rounded to the cent for cent totals
+        let micro = moneymq_types::Price::new(PriceCurrency::Usd, PricingType::OneTime)
+            .with_exact_amount(Decimal::new(1, 1));
+        assert_eq!(
+            exact_price_total(&micro, 3, None),
+            Some((Decimal::new(3, 1), "usd".to_string()))
+        );
+        assert_eq!(
+            price_total(&micro, 1000, Some("eur")),
+            Some((100, "usd".to_string()))
+        );
+
+        // Tiers without an amount in the price's currency and overflowing quantities
+        // aren't charged at all
+        let tiered = moneymq_types::Price::new(PriceCurrency::Usd, PricingType::OneTime)
+            .with_tiers(
+                vec![moneymq_types::PriceTier {
+                    up_to: None,
+                    unit_amounts: IndexMap::new(),
+                    flat_amounts: IndexMap::new(),
+                }],
+                None,
+            );
+        assert_eq!(price_total(&tiered, 1, Some("eur")), None);
+        assert_eq!(price_total(&price, i64::MAX, None), None);
+    }
+}
@@ -1,15 +1,16 @@
 use axum::{
     Extension,
     body::Body,
+    extract::OriginalUri,
     handler::Handler,
-    http::{HeaderMap, Request, StatusCode},
+    http::{HeaderMap, Request, StatusCode, header::HOST},
     middleware::{self, Next},
     response::{IntoResponse, Response},
     routing::{MethodRouter, get, post},
 };
 use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
 use moneymq_types::{
-    LineItem,
+    BasketDiscount, Decimal, LineItem, PaymentTax, Price, Product, round_cents,
     x402::{
         ExactPaymentPayload, FacilitatorErrorReason, PaymentPayload, PaymentRequirements,
         SupportedResponse,
@@ -17,17 +18,41 @@ use moneymq_types::{
 };
 use serde_json::json;
 use tracing::{debug, error, info, warn};
+use url::Url;
 
 use crate::api::{
     catalog::{
         CatalogState,
-        stripe::endpoints::{
-            billing::BillingMeterEventRequest, subscriptions::SubscriptionRequest,
+        authorization::{PaymentAuthorization, hold_authorization, is_manual_capture},
+        experiments::record_conversions,
+        fx::{FxError, exact_price_total, settlement_amount},
+        quote::{PaymentQuote, QuoteError},
+        stripe::{
+            endpoints::{
+                billing::{BillingMeterEventRequest, is_duplicate_meter_event},
+                coupons::{
+                    COUPON_METADATA_KEY, DISCOUNT_METADATA_KEY, DiscountError,
+                    PROMOTION_CODE_METADATA_KEY, record_redemption, resolve_discount,
+                },
+                entitlements::{RECEIPT_HEADER, basket_product_ids, product_entitlements},
+                invoices::open_invoice_payment_intent,
+                subscriptions::{SubscriptionRequest, find_catalog_price},
+                tax::{TaxError, calculate_tax, metadata_tax, price_tax_behavior, tax_location},
+                test_clocks::current_time,
+            },
+            types::AppliedDiscount,
         },
     },
     payment::{
         channel_id_from_transaction,
-        endpoints::{FacilitatorExtraContext, channels::BasketItem},
+        endpoints::{
+            FacilitatorExtraContext,
+            channels::BasketItem,
+            payment_channels::{
+                CHANNEL_SCHEME, ChannelPaymentPayload, PaymentChannelVoucher,
+                PaymentChannelVoucherRequest,
+            },
+        },
         networks::solana::extract_customer_from_transaction,
     },
 };
@@ -44,6 +69,8 @@ pub enum X402FacilitatorRequestError {
     PaymentVerificationFailed(FacilitatorErrorReason),
     #[error("Payment settlement failed{}", .0.as_ref().map(|r| format!(": {:?}", r)).unwrap_or_default())]
     PaymentSettlementFailed(Option<FacilitatorErrorReason>),
+    #[error("Payment channel voucher rejected: {0}")]
+    VoucherRejected(String),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -58,6 +85,8 @@ pub enum X402MiddlewareError {
     PaymentRequired(Vec<PaymentRequirements>),
     #[error("Invalid X-Payment header: {0}")]
     InvalidPaymentHeader(String),
+    #[error("Invalid payment quote: {0}")]
+    InvalidQuote(QuoteError),
 }
 
 impl From<X402MiddlewareError> for Response {
@@ -94,6 +123,21 @@ impl From<X402MiddlewareError> for Response {
                 "invalid_payment_header",
                 "invalid_request_error",
             ),
+            X402MiddlewareError::InvalidQuote(QuoteError::Missing) => (
+                StatusCode::PAYMENT_REQUIRED,
+                "payment_quote_required",
+                "invalid_request_error",
+            ),
+            X402MiddlewareError::InvalidQuote(QuoteError::Expired(_)) => (
+                StatusCode::PAYMENT_REQUIRED,
+                "payment_quote_expired",
+                "invalid_request_error",
+            ),
+            X402MiddlewareError::InvalidQuote(_) => (
+                StatusCode::PAYMENT_REQUIRED,
+                "payment_quote_mismatch",
+                "invalid_request_error",
+            ),
         };
 
         let message = val.to_string();
@@ -140,10 +184,13 @@ async fn fetch_supported(
 }
 
 /// Extract and validate payment payload from request headers
+///
+/// Clients echo the quote they were issued with the requirements they accepted, as in x402
+/// v2 payloads (`accepted.extra.quote`); it is returned alongside the payload when present.
 async fn extract_payment_payload(
     headers: &HeaderMap,
     payment_requirements: &[PaymentRequirements],
-) -> Result<PaymentPayload, X402MiddlewareError> {
+) -> Result<(PaymentPayload, Option<PaymentQuote>), X402MiddlewareError> {
     // Check for X-Payment header
     let payment_header = headers.get("X-Payment");
     debug!("Payment Header => {:?}", payment_header);
@@ -171,7 +218,7 @@ async fn extract_payment_payload(
                 X402MiddlewareError::InvalidPaymentHeader("Header is not valid base64".to_string())
             })?;
 
-            let payment_payload: PaymentPayload =
+            let mut payment_json: serde_json::Value =
                 serde_json::from_slice(&decoded).map_err(|e| {
                     X402MiddlewareError::InvalidPaymentHeader(format!(
                         "Failed to parse payment payload: {}",
@@ -179,11 +226,48 @@ async fn extract_payment_payload(
                     ))
                 })?;
 
-            Ok(payment_payload)
+            let echoed_quote = payment_json
+                .pointer_mut("/accepted/extra/quote")
+                .map(serde_json::Value::take)
+                .map(serde_json::from_value::<PaymentQuote>)
+                .transpose()
+                .map_err(|e| {
+                    X402MiddlewareError::InvalidPaymentHeader(format!(
+                        "Failed to parse payment quote: {}",
+                        e
+                    ))
+                })?;
+
+            let payment_payload: PaymentPayload =
+                serde_json::from_value(payment_json).map_err(|e| {
+                    X402MiddlewareError::InvalidPaymentHeader(format!(
+                        "Failed to parse payment payload: {}",
+                        e
+                    ))
+                })?;
+
+            Ok((payment_payload, echoed_quote))
         }
     }
 }
 
+/// Check the quote echoed with a payment: it must be present, issued for this exact request
+/// and still fresh, so a payment can't be replayed against another resource or after expiry
+fn check_payment_quote(
+    state: &CatalogState,
+    quote: Option<PaymentQuote>,
+    method: &str,
+    requirements: &PaymentRequirements,
+    now: i64,
+) -> Result<PaymentQuote, X402MiddlewareError> {
+    let quote = quote.ok_or(X402MiddlewareError::InvalidQuote(QuoteError::Missing))?;
+    state
+        .quote_signer
+        .verify(&quote, method, requirements, now)
+        .map_err(X402MiddlewareError::InvalidQuote)?;
+    Ok(quote)
+}
+
 /// Verify payment with the facilitator by calling its /verify endpoint
 async fn verify_payment_with_facilitator(
     state: &CatalogState,
@@ -237,7 +321,7 @@ async fn verify_payment_with_facilitator(
 }
 
 /// Settle payment with the facilitator by calling its /settle endpoint
-async fn settle_payment_with_facilitator(
+pub(crate) async fn settle_payment_with_facilitator(
     state: &CatalogState,
     payment_payload: &PaymentPayload,
     payment_requirements: &PaymentRequirements,
@@ -292,29 +376,177 @@ async fn settle_payment_with_facilitator(
     }
 }
 
+/// Extract a payment channel voucher from the `X-Payment` header, if it uses the `channel` scheme
+///
+/// Returns `Ok(None)` for any other payload so that it goes through the regular x402 flow.
+fn extract_channel_voucher(
+    headers: &HeaderMap,
+) -> Result<Option<PaymentChannelVoucher>, X402MiddlewareError> {
+    let Some(payment_json) = headers
+        .get("X-Payment")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| BASE64.decode(header.as_bytes()).ok())
+        .and_then(|decoded| serde_json::from_slice::<serde_json::Value>(&decoded).ok())
+    else {
+        return Ok(None);
+    };
+    if payment_json.get("scheme").and_then(|s| s.as_str()) != Some(CHANNEL_SCHEME) {
+        return Ok(None);
+    }
+
+    let payload: ChannelPaymentPayload = serde_json::from_value(payment_json).map_err(|e| {
+        X402MiddlewareError::InvalidPaymentHeader(format!(
+            "Failed to parse payment channel voucher: {}",
+            e
+        ))
+    })?;
+    Ok(Some(payload.payload))
+}
+
+/// Check or redeem a payment channel voucher with the facilitator
+///
+/// With `verify_only`, the voucher is checked without being recorded.
+async fn submit_voucher_to_facilitator(
+    state: &CatalogState,
+    request: &PaymentChannelVoucherRequest,
+    verify_only: bool,
+) -> Result<(), X402FacilitatorRequestError> {
+    let voucher_url = format!(
+        "{}payment_channels/{}/vouchers{}",
+        state.facilitator_url,
+        urlencoding::encode(&request.voucher.channel_id),
+        if verify_only { "/verify" } else { "" }
+    );
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(&voucher_url)
+        .json(request)
+        .send()
+        .await
+        .map_err(X402FacilitatorRequestError::FailedToContactFacilitator)?;
+
+    if response.status().is_success() {
+        return Ok(());
+    }
+
+    // Surface the facilitator's explanation (insufficient amount, stale voucher, ...)
+    let body: serde_json::Value = response
+        .json()
+        .await
+        .map_err(X402FacilitatorRequestError::FacilitatorResponseParseError)?;
+    let message = body
+        .pointer("/error/message")
+        .and_then(|m| m.as_str())
+        .unwrap_or("unknown error")
+        .to_string();
+    Err(X402FacilitatorRequestError::VoucherRejected(message))
+}
+
+/// Pay for a request with a payment channel voucher instead of an on-chain transaction
+///
+/// The voucher is checked before the handler runs and only redeemed once it succeeds,
+/// mirroring the verify/settle split of the regular flow.
+async fn pay_with_channel_voucher(
+    state: &CatalogState,
+    req: Request<Body>,
+    next: Next,
+    voucher: PaymentChannelVoucher,
+    payment_requirements: PaymentRequirements,
+) -> Response {
+    let request = PaymentChannelVoucherRequest {
+        voucher,
+        payment_requirements,
+    };
+
+    if let Err(e) = submit_voucher_to_facilitator(state, &request, true).await {
+        warn!("Payment channel voucher rejected: {}", e);
+        return X402MiddlewareError::VerifyError(e).into();
+    }
+    info!(
+        "Verified - Voucher accepted for payment channel {}",
+        request.voucher.channel_id
+    );
+
+    let response = next.run(req).await;
+
+    if response.status().is_success() {
+        match submit_voucher_to_facilitator(state, &request, false).await {
+            Ok(_) => info!(
+                "Redeemed - Voucher recorded on payment channel {}",
+                request.voucher.channel_id
+            ),
+            Err(e) => {
+                error!("Voucher Redemption Failed - {}", e);
+                // Note: We don't fail the request here since the service was already provided
+            }
+        }
+    }
+
+    response
+}
+
+/// x402 routes, told apart by their path before anything is read from the body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum X402Route {
+    /// POST /payment_intents/{id}/confirm and POST /invoices/{id}/pay
+    PaymentIntent,
+    /// POST /subscriptions
+    Subscription,
+    /// POST /billing/meter_events
+    MeterEvent,
+    /// GET /products/{id}/access
+    ProductAccess,
+    Other,
+}
+
+fn x402_route(req_path: &str) -> X402Route {
+    // Paths are nested under /catalog/v1, or carry the legacy /v1 prefix
+    let path = req_path.strip_prefix("/v1").unwrap_or(req_path);
+    let parts = path.split('/').collect::<Vec<_>>();
+    match parts.as_slice() {
+        ["", "payment_intents", _, "confirm"] | ["", "invoices", _, "pay"] => {
+            X402Route::PaymentIntent
+        }
+        ["", "subscriptions"] => X402Route::Subscription,
+        ["", "billing", "meter_events"] => X402Route::MeterEvent,
+        ["", "products", _, "access"] => X402Route::ProductAccess,
+        _ => X402Route::Other,
+    }
+}
+
 /// Extract payment amount and description from request
-/// Returns (amount, description, product_quantities, payment_intent_id)
+/// Returns (amount, currency, description, product_quantities, payment_intent_id)
 fn extract_payment_details(
     state: &CatalogState,
     req_path: &str,
-) -> Option<(i64, String, String, Option<String>)> {
+) -> Option<(i64, String, String, String, Option<String>)> {
     // Check if this is a payment intent confirm request
     // Support both /payment_intents/{id}/confirm (nested under /catalog/v1)
     // and /v1/payment_intents/{id}/confirm (legacy)
     let is_confirm_request = req_path.ends_with("/confirm")
         && (req_path.starts_with("/payment_intents/")
             || req_path.starts_with("/v1/payment_intents/"));
+    // Paying an open invoice pays the payment intent created when it was finalized
+    let is_invoice_payment = req_path.ends_with("/pay")
+        && (req_path.starts_with("/invoices/") || req_path.starts_with("/v1/invoices/"));
 
-    if is_confirm_request {
-        // Extract payment intent ID from path
+    if is_confirm_request || is_invoice_payment {
+        // Extract the payment intent or invoice ID from path
         let parts: Vec<&str> = req_path.split('/').collect();
         // For /payment_intents/{id}/confirm -> parts = ["", "payment_intents", "{id}", "confirm"]
         // For /v1/payment_intents/{id}/confirm -> parts = ["", "v1", "payment_intents", "{id}", "confirm"]
-        let payment_intent_id = if req_path.starts_with("/v1/") {
+        let resource_id = if req_path.starts_with("/v1/") {
             parts.get(3).copied()
         } else {
             parts.get(2).copied()
         };
+        let payment_intent_id = if is_invoice_payment {
+            resource_id.and_then(|invoice_id| open_invoice_payment_intent(state, invoice_id))
+        } else {
+            resource_id.map(str::to_string)
+        };
+        let payment_intent_id = payment_intent_id.as_deref();
 
         if let Some(payment_intent_id) = payment_intent_id {
             // Look up the payment intent from state
@@ -324,6 +556,18 @@ fn extract_payment_details(
                         .description
                         .clone()
                         .unwrap_or_else(|| format!("Payment intent {}", payment_intent_id));
+                    // Discount recorded when the intent was priced
+                    let discount = |amount: Option<i64>| {
+                        let coupon = intent.metadata.get(COUPON_METADATA_KEY)?;
+                        Some(BasketDiscount {
+                            coupon: coupon.clone(),
+                            promotion_code: intent
+                                .metadata
+                                .get(PROMOTION_CODE_METADATA_KEY)
+                                .cloned(),
+                            amount: amount.filter(|amount| *amount > 0)?,
+                        })
+                    };
 
                     // Build basket from line_items with productId, experimentId, quantity
                     let product_quantities = intent
@@ -340,6 +584,7 @@ fn extract_payment_details(
                                             experiment_id: item.price.experiment_id,
                                             features: Default::default(),
                                             quantity: item.quantity,
+                                            discount: discount(item.amount_discount),
                                         })
                                         .collect();
                                     serde_json::to_string(&basket)
@@ -354,6 +599,12 @@ fn extract_payment_details(
                                     experiment_id: None,
                                     features: Default::default(),
                                     quantity: 1,
+                                    discount: discount(
+                                        intent
+                                            .metadata
+                                            .get(DISCOUNT_METADATA_KEY)
+                                            .and_then(|amount| amount.parse().ok()),
+                                    ),
                                 }])
                                 .unwrap_or_else(|_| "[]".to_string())
                             })
@@ -365,6 +616,12 @@ fn extract_payment_details(
                                 experiment_id: None,
                                 features: Default::default(),
                                 quantity: 1,
+                                discount: discount(
+                                    intent
+                                        .metadata
+                                        .get(DISCOUNT_METADATA_KEY)
+                                        .and_then(|amount| amount.parse().ok()),
+                                ),
                             }])
                             .unwrap_or_else(|_| "[]".to_string())
                         });
@@ -372,6 +629,7 @@ fn extract_payment_details(
                     // Return amount in cents - the middleware will do the conversion to token amount
                     return Some((
                         intent.amount,
+                        intent.currency.clone(),
                         description,
                         product_quantities,
                         Some(payment_intent_id.to_string()),
@@ -388,6 +646,53 @@ fn extract_payment_details(
     None
 }
 
+/// Normalize a product ID or basket to basket array format
+///
+/// A JSON array is kept as is: `[{"productId": "x", "experimentId": "y", "quantity": 1}]`,
+/// and a simple product ID becomes a single-item basket: `[{"productId": "x", "quantity": 1}]`.
+fn basket_items(product_id: &str) -> Vec<serde_json::Value> {
+    serde_json::from_str(product_id)
+        .unwrap_or_else(|_| vec![json!({"productId": product_id, "quantity": 1})])
+}
+
+/// Amount (in cents), currency and basket (as the JSON of `extra.product`) charged when
+/// confirming a payment intent, as priced by the middleware on its confirm route
+pub(crate) fn payment_intent_charge(
+    state: &CatalogState,
+    payment_intent_id: &str,
+) -> Option<(i64, String, String)> {
+    let (amount, currency, _, product_id, _) = extract_payment_details(
+        state,
+        &format!("/payment_intents/{}/confirm", payment_intent_id),
+    )?;
+    let basket = serde_json::to_string(&basket_items(&product_id)).unwrap_or_default();
+    Some((amount, currency, basket))
+}
+
+/// Build the absolute URL of the resource being paid for
+///
+/// Uses the original (un-nested) request path so that quotes issued for
+/// `/catalog/v1/products/a/access` can't be presented for `/catalog/v1/products/b/access`.
+fn requested_resource_url(state: &CatalogState, req: &Request<Body>) -> Url {
+    let path = req
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|uri| uri.0.path())
+        .unwrap_or_else(|| req.uri().path());
+    let scheme = req
+        .headers()
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("http");
+
+    req.headers()
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| Url::parse(&format!("{}://{}{}", scheme, host, path)).ok())
+        .or_else(|| state.facilitator_url.join(path).ok())
+        .unwrap_or_else(|| state.facilitator_url.clone())
+}
+
 /// Middleware to handle payment requirements for meter events
 pub async fn payment_middleware(
     Extension(state): Extension<CatalogState>,
@@ -402,125 +707,283 @@ pub async fn payment_middleware(
         .unwrap_or_default();
 
     let mut req = Request::from_parts(parts, Body::from(request_bytes.clone()));
-
-    // (description, amount, is_margin, product_id, payment_intent_id)
-    let (description, amount, _is_margin, product_id, payment_intent_id) = {
-        let billing_event = BillingMeterEventRequest::parse(&request_bytes);
-        if let Some(event_name) = billing_event.event_name {
-            debug!("Parsed billing event name from request: {}", event_name);
-
-            let billing_event = state.meters.iter().find(|m| m.event_name == event_name);
-            if let Some(billing_event) = billing_event {
-                (
-                    billing_event
-                        .display_name
-                        .clone()
-                        .unwrap_or_else(|| billing_event.event_name.clone()),
-                    // TODO: need to figure out price for billing events
-                    100,
-                    false,
-                    // Use meter ID for tracking
-                    billing_event.id.clone(),
-                    None, // No payment intent for billing events
-                )
-            } else {
-                // Unknown billing event, default amount
+    let catalog = state.catalog();
+
+    // Coupon redeemed by a direct purchase, counted once it's settled
+    let mut redemption: Option<(BasketDiscount, String)> = None;
+    // Tax included in the amount, for the buyer's address
+    let mut tax: Option<PaymentTax> = None;
+
+    // (description, amount, currency, is_margin, product_id, payment_intent_id)
+    let (description, amount, currency, _is_margin, product_id, payment_intent_id) =
+        match x402_route(req.uri().path()) {
+            X402Route::PaymentIntent => {
+                // Only the intent named in the path is charged, whatever the body says
+                let Some((price, currency, description, product_id, pi_id)) =
+                    extract_payment_details(&state, req.uri().path())
+                else {
+                    // Unknown intents and invoices that aren't open are reported by the handler
+                    return next.run(req).await;
+                };
+                // Tax was computed when the intent was priced
+                tax = pi_id.as_deref().and_then(|pi_id| {
+                    let payment_intents = state.payment_intents.lock().unwrap();
+                    metadata_tax(&payment_intents.get(pi_id)?.metadata)
+                });
                 (
-                    "Meter Event".into(),
-                    100,
+                    description,
+                    Decimal::from(price),
+                    currency,
                     false,
-                    "unknown-meter".into(),
-                    None,
+                    product_id,
+                    pi_id,
                 )
             }
-        } else {
-            let subscription_req = SubscriptionRequest::parse(&request_bytes);
-            if subscription_req.customer.is_some() {
+            X402Route::Subscription => {
+                let subscription_req = match SubscriptionRequest::parse(&request_bytes) {
+                    Ok(subscription_req) => subscription_req,
+                    Err(e) => return e.into_response(),
+                };
+                if subscription_req.customer.is_none() {
+                    // The handler reports the missing customer
+                    return next.run(req).await;
+                }
                 debug!(
                     "Parsed subscription request from request, price IDs: {:?}",
-                    subscription_req.price_ids
+                    subscription_req.price_ids()
                 );
 
-                let active_price = state
-                    .products
+                // Trials start without a charge; the renewal task charges the first period
+                if subscription_req.has_trial() {
+                    return next.run(req).await;
+                }
+
+                // The first period is charged for every item, at its quantity
+                let single_item = subscription_req.items.len() == 1;
+                let subscribed = subscription_req
+                    .items
                     .iter()
-                    .flat_map(|product| {
-                        product.prices.iter().filter_map(|price| {
-                            let price_id = if state.use_sandbox {
-                                price.sandboxes.get("default")
-                            } else {
-                                price.deployed_id.as_ref()
+                    .filter_map(|item| {
+                        let (product, price) =
+                            find_catalog_price(&state, &catalog, item.price.as_deref()?)?;
+                        let quantity = item
+                            .quantity
+                            .or(subscription_req.quantity.filter(|_| single_item))
+                            .unwrap_or(1);
+                        let amount = price.amount_for(quantity, price.currency.as_str());
+                        Some(
+                            amount
+                                .map(|amount| (product, price, amount))
+                                .ok_or(quantity),
+                        )
+                    })
+                    .collect::<Result<Vec<_>, i64>>();
+                // Quantities whose amount overflows aren't charged at all
+                let subscribed = match subscribed {
+                    Ok(subscribed) => subscribed,
+                    Err(quantity) => {
+                        let body = json!({
+                            "error": {
+                                "code": "parameter_invalid_integer",
+                                "message": format!("Invalid quantity: {}", quantity),
+                                "param": "quantity",
+                                "type": "invalid_request_error",
                             }
-                            .unwrap_or(&price.id);
-                            if subscription_req.price_ids.contains(price_id) && price.active {
-                                // Note: "margin" pricing type is not currently supported
-                                let is_margin = false;
-                                let price = price.unit_amount.unwrap_or(1);
-                                let description =
-                                    product.statement_descriptor.clone().unwrap_or_else(|| {
-                                        product.name.clone().unwrap_or("Product".to_string())
-                                    });
-                                // Use product ID for tracking, not the display name
-                                let product_id = product.id.clone();
-                                Some((description, price, is_margin, product_id))
-                            } else {
-                                None
+                        });
+                        return (StatusCode::BAD_REQUEST, axum::Json(body)).into_response();
+                    }
+                };
+
+                match subscribed.first() {
+                    Some((product, price, _)) => {
+                        // Note: "margin" pricing type is not currently supported
+                        let description =
+                            product.statement_descriptor.clone().unwrap_or_else(|| {
+                                product.name.clone().unwrap_or("Product".to_string())
+                            });
+                        // The first period is charged net of the discount; the subscription
+                        // handler counts the redemption
+                        let line_discounts =
+                            match subscription_discounts(&state, &subscription_req, &subscribed) {
+                                Ok(line_discounts) => line_discounts,
+                                Err(e) => return e.into_response(),
+                            };
+                        let lines = subscribed
+                            .iter()
+                            .zip(&line_discounts)
+                            .map(|((_, price, amount), discount)| {
+                                (amount - discount, price_tax_behavior(price))
+                            })
+                            .collect::<Vec<_>>();
+                        let mut amount = lines.iter().map(|(amount, _)| amount).sum::<i64>();
+                        // Tax on tax-exclusive prices is added on top
+                        if let Some(location) = subscription_req.tax_location() {
+                            match calculate_tax(&state, &location, &lines) {
+                                Ok(calculation) => {
+                                    amount += calculation.amount_exclusive();
+                                    tax = Some(calculation.payment_tax());
+                                }
+                                Err(e) => return e.into_response(),
                             }
-                        })
-                    })
-                    .collect::<Vec<_>>();
-                let (description, price, is_margin, product_id) =
-                    active_price.first().cloned().unwrap_or((
+                        }
+                        // Use product ID for tracking, not the display name
+                        let currency = price.currency.as_str().to_string();
+                        (
+                            description,
+                            Decimal::from(amount),
+                            currency,
+                            false,
+                            product.id.clone(),
+                            None,
+                        ) // No payment intent for subscriptions
+                    }
+                    None => (
                         "Unknown Product".to_string(),
-                        1,
+                        Decimal::ONE,
+                        "usd".to_string(),
                         false,
                         "unknown".to_string(),
-                    )); // TODO: handle multiple prices properly
+                        None,
+                    ),
+                }
+            }
+            X402Route::MeterEvent => {
+                let billing_event = match BillingMeterEventRequest::parse(&request_bytes) {
+                    Ok(billing_event) => billing_event,
+                    Err(e) => return e.into_response(),
+                };
+                // The handler reports a missing event name or an unknown meter
+                let Some(meter) = billing_event.event_name.as_deref().and_then(|event_name| {
+                    catalog.meters.iter().find(|m| m.event_name == event_name)
+                }) else {
+                    return next.run(req).await;
+                };
+                // An event resent by its customer was already paid for; the handler returns
+                // the recorded one
+                if is_duplicate_meter_event(&state, meter, &billing_event) {
+                    debug!(
+                        "Meter event {:?} already recorded, skipping payment",
+                        billing_event.identifier
+                    );
+                    return next.run(req).await;
+                }
 
-                (description, price, is_margin, product_id, None) // No payment intent for subscriptions
-            } else {
-                // Try payment intent first, then product access path
-                if let Some((price, description, product_id, pi_id)) =
-                    extract_payment_details(&state, req.uri().path())
-                {
-                    (description, price, false, product_id, pi_id)
-                } else {
-                    // Check for product access path (e.g., /products/{id}/access)
-                    match extract_product_from_path(&state, req.uri().path()) {
-                        ProductAccessResult::Found {
-                            amount,
-                            description,
-                            product_id,
-                        } => (description, amount, false, product_id, None), // No payment intent for direct product access
-                        ProductAccessResult::ProductNotFound(product_id) => {
-                            let body = json!({
-                                "error": {
-                                    "code": "resource_missing",
-                                    "message": format!("No such product: '{}'", product_id),
-                                    "type": "invalid_request_error",
-                                }
-                            });
-                            return (StatusCode::NOT_FOUND, axum::Json(body)).into_response();
-                        }
-                        ProductAccessResult::NoPriceFound(product_id) => {
-                            let body = json!({
-                                "error": {
-                                    "code": "resource_missing",
-                                    "message": format!("Product '{}' has no active price", product_id),
-                                    "type": "invalid_request_error",
-                                }
-                            });
-                            return (StatusCode::NOT_FOUND, axum::Json(body)).into_response();
-                        }
-                        ProductAccessResult::NotApplicable => {
-                            // No payment context found, pass through without gating
+                debug!(
+                    "Parsed billing event name from request: {}",
+                    meter.event_name
+                );
+                (
+                    meter
+                        .display_name
+                        .clone()
+                        .unwrap_or_else(|| meter.event_name.clone()),
+                    // TODO: need to figure out price for billing events
+                    Decimal::from(100),
+                    "usd".to_string(),
+                    false,
+                    // Use meter ID for tracking
+                    meter.id.clone(),
+                    None, // No payment intent for billing events
+                )
+            }
+            X402Route::ProductAccess => {
+                match extract_product_from_path(&state, req.uri().path(), req.uri().query()) {
+                    ProductAccessResult::Found {
+                        amount,
+                        description,
+                        product_id,
+                        currency,
+                    } => {
+                        // Customers entitled to the product's features access it without paying,
+                        // proving who they are with a receipt of one of their payments
+                        let receipt = req
+                            .headers()
+                            .get(RECEIPT_HEADER)
+                            .and_then(|value| value.to_str().ok());
+                        if let Some(customer) = access_customer(req.uri().query())
+                            && product_entitlements(&state, &customer, receipt, &product_id)
+                                .is_some()
+                        {
+                            debug!("Customer {} is entitled to {}", customer, product_id);
                             return next.run(req).await;
                         }
+                        // Discounts and tax apply to whole cents
+                        let (amount, basket) = match product_access_discount(
+                            &state,
+                            req.uri().query(),
+                            &product_id,
+                            round_cents(amount),
+                            &currency,
+                        ) {
+                            Ok(Some((basket_json, discount))) => {
+                                let amount = amount - Decimal::from(discount.amount);
+                                redemption = Some((discount, currency.clone()));
+                                (amount, basket_json)
+                            }
+                            Ok(None) => (amount, product_id.clone()),
+                            Err(e) => return e.into_response(),
+                        };
+                        // No payment intent for direct product access
+                        match product_access_tax(
+                            &state,
+                            req.uri().query(),
+                            &product_id,
+                            round_cents(amount),
+                        ) {
+                            Ok(Some(product_tax)) => {
+                                let amount = amount
+                                    + Decimal::from(
+                                        product_tax.amount - product_tax.amount_inclusive,
+                                    );
+                                tax = Some(product_tax);
+                                (description, amount, currency, false, basket, None)
+                            }
+                            Ok(None) => (description, amount, currency, false, basket, None),
+                            Err(e) => return e.into_response(),
+                        }
+                    }
+                    ProductAccessResult::ProductNotFound(product_id) => {
+                        let body = json!({
+                            "error": {
+                                "code": "resource_missing",
+                                "message": format!("No such product: '{}'", product_id),
+                                "type": "invalid_request_error",
+                            }
+                        });
+                        return (StatusCode::NOT_FOUND, axum::Json(body)).into_response();
+                    }
+                    ProductAccessResult::NoPriceFound(product_id) => {
+                        let body = json!({
+                            "error": {
+                                "code": "resource_missing",
+                                "message": format!("Product '{}' has no active price", product_id),
+                                "type": "invalid_request_error",
+                            }
+                        });
+                        return (StatusCode::NOT_FOUND, axum::Json(body)).into_response();
+                    }
+                    ProductAccessResult::InvalidQuantity(quantity) => {
+                        let body = json!({
+                            "error": {
+                                "code": "parameter_invalid_integer",
+                                "message": format!("Invalid quantity: {}", quantity),
+                                "param": "quantity",
+                                "type": "invalid_request_error",
+                            }
+                        });
+                        return (StatusCode::BAD_REQUEST, axum::Json(body)).into_response();
+                    }
+                    ProductAccessResult::NotApplicable => {
+                        // No payment context found, pass through without gating
+                        return next.run(req).await;
                     }
                 }
             }
-        }
-    };
+            X402Route::Other => {
+                // No payment context found, pass through without gating
+                return next.run(req).await;
+            }
+        };
 
     debug!(
         "Creating payment requirements for resource: {}, Amount: {}",
@@ -549,22 +1012,45 @@ pub async fn payment_middleware(
     };
 
     // TODO: probably need some sort of filtering here based on product being accessed
-    let assets = network_config
+    // Stablecoins tracking the price currency come first; others are offered when the
+    // amount can be converted into their currency
+    let settlements = network_config
         .currencies()
         .iter()
-        .map(|currency| (currency.address(), currency.decimals()))
+        .filter_map(|stablecoin| {
+            let settlement =
+                settlement_amount(state.fx_rates.as_deref(), &currency, amount, stablecoin)?;
+            Some((stablecoin.address(), stablecoin.decimals(), settlement))
+        })
+        .collect::<Vec<_>>();
+    if settlements.is_empty() {
+        return FxError::Unsupported(currency).into_response();
+    }
+    // Amounts below a cent are only offered in stablecoins with enough decimals to pay them
+    let mut assets = settlements
+        .into_iter()
+        .filter_map(|(asset, decimals, settlement)| {
+            let token_amount = settlement.token_amount(decimals)?;
+            Some((asset, token_amount, settlement))
+        })
         .collect::<Vec<_>>();
+    assets.sort_by_key(|(_, _, settlement)| settlement.fx.is_some());
+    if assets.is_empty() {
+        return FxError::TooPrecise(amount).into_response();
+    }
 
     let recipient = network_config.recipient();
+    let resource = requested_resource_url(&state, &req);
+    let method = req.method().as_str().to_string();
+    let now = chrono::Utc::now().timestamp();
 
     // TODO: allow pay to to be overridden by product
     // TODO: consider allowing the assets allowed to be overridden by product
 
     let payment_requirements = assets
         .into_iter()
-        .map(|(asset, decimals)| {
-            let token_amount =
-                TokenAmount((amount * 10_i64.pow((decimals as u32).saturating_sub(2))).to_string());
+        .map(|(asset, token_amount, settlement)| {
+            let token_amount = TokenAmount(token_amount.to_string());
             debug!(
                 "  Payment Requirement - Asset: {}, Amount (raw): {}",
                 asset, token_amount.0
@@ -573,9 +1059,7 @@ pub async fn payment_middleware(
                 scheme: Scheme::Exact,
                 network: network.clone(),
                 max_amount_required: token_amount,
-                resource: state.facilitator_url.clone(), // TODO: I think this should actually be the resource being accessed
-                // TODO: our price is in cents, but USDC has 6 decimals.
-                // We need a consistent conversion between these rather than assuming 2 decimals
+                resource: resource.clone(),
                 description: format!("Payment for {}", description),
                 mime_type: "application/json".to_string(),
                 output_schema: None,
@@ -583,18 +1067,11 @@ pub async fn payment_middleware(
                 max_timeout_seconds: 300,
                 asset,
                 extra: Some({
-                    // Normalize product_id to basket array format
-                    // If already JSON array: [{"productId": "x", "experimentId": "y", "quantity": 1}]
-                    // If simple string: convert to [{"productId": "x", "quantity": 1}]
-                    let basket: Vec<serde_json::Value> = serde_json::from_str(&product_id)
-                        .unwrap_or_else(|_| {
-                            // Simple string - convert to single-item basket
-                            vec![json!({"productId": product_id, "quantity": 1})]
-                        });
+                    let basket = basket_items(&product_id);
 
                     // Look up product features from basket
                     let mut merged_features = serde_json::Map::new();
-                    let available_ids: Vec<&str> = state.products.iter().map(|p| p.id.as_str()).collect();
+                    let available_ids: Vec<&str> = catalog.products.iter().map(|p| p.id.as_str()).collect();
 
                     for item in basket.iter() {
                         // Use experimentId for lookup if present, otherwise productId
@@ -611,14 +1088,14 @@ pub async fn payment_middleware(
                         );
 
                         if let Some(pid) = lookup_id {
-                            if let Some(product) = state.products.iter().find(|p| p.id == pid) {
+                            if let Some(product) = catalog.products.iter().find(|p| p.id == pid) {
                                 debug!(
                                     "Found product '{}' (experiment: {:?}, parent: {:?}) with {} features",
                                     pid, product.experiment, product.parent_id, product.features.len()
                                 );
                                 // Use helper to resolve features (handles experiment variants)
                                 if let Some(serde_json::Value::Object(product_features)) =
-                                    get_product_features(&state.products, product)
+                                    get_product_features(&catalog.products, product)
                                 {
                                     for (k, v) in product_features {
                                         merged_features.insert(k, v);
@@ -648,20 +1125,60 @@ pub async fn payment_middleware(
                         "product": basket_json,
                         "paymentIntentId": payment_intent_id,
                         "features": features,
+                        "tax": tax,
+                        "fx": settlement.fx,
                     })
                 }),
             }
         })
+        .map(|mut requirements| {
+            // Sign the issued requirements so they can only be redeemed for this request
+            let quote = state.quote_signer.issue(&method, &requirements, now);
+            if let Some(serde_json::Value::Object(extra)) = requirements.extra.as_mut() {
+                extra.insert("quote".to_string(), json!(quote));
+            }
+            requirements
+        })
         .collect::<Vec<_>>();
 
     let mut selected_payment_requirement = payment_requirements[0].clone(); // For now, just use the first one
 
+    // Payment channel vouchers are redeemed off-chain instead of settling a transaction
+    match extract_channel_voucher(req.headers()) {
+        Ok(Some(voucher)) => {
+            return pay_with_channel_voucher(
+                &state,
+                req,
+                next,
+                voucher,
+                selected_payment_requirement,
+            )
+            .await;
+        }
+        Ok(None) => {}
+        Err(error) => return error.into(),
+    }
+
     let headers = req.headers();
 
     match extract_payment_payload(headers, &payment_requirements).await {
-        Ok(payment_payload) => {
+        Ok((payment_payload, echoed_quote)) => {
             // Payment header found and valid
             info!("Received - Valid payment payload received");
+
+            let quote = match check_payment_quote(
+                &state,
+                echoed_quote,
+                &method,
+                &selected_payment_requirement,
+                now,
+            ) {
+                Ok(quote) => quote,
+                Err(e) => {
+                    warn!("Rejecting payment quote: {}", e);
+                    return e.into();
+                }
+            };
             debug!(
                 "  Payment scheme: {:?}, network: {:?}",
                 payment_payload.scheme, payment_payload.network
@@ -718,6 +1235,8 @@ pub async fn payment_middleware(
             new_extra.customer_address = customer_pubkey.as_ref().map(|c| c.to_string());
             new_extra.customer_label = customer_label.clone();
             new_extra.currency = Some(currency.clone());
+            // The facilitator checks the expiry of the quote the client paid for
+            new_extra.quote = Some(quote);
             // Compute transaction ID from payment hash for channel-based event routing
             // This allows frontend to compute the same ID and subscribe early
             new_extra.transaction_id = match &payment_payload.payload {
@@ -741,6 +1260,7 @@ pub async fn payment_middleware(
                 "Middleware: Setting extra context with transaction_id: {:?}",
                 new_extra.transaction_id
             );
+            let transaction_id = new_extra.transaction_id.clone();
             selected_payment_requirement.extra = Some(serde_json::to_value(new_extra).unwrap());
 
             // Verify payment with facilitator
@@ -761,6 +1281,23 @@ pub async fn payment_middleware(
                     // Continue to the handler
                     let response = next.run(req).await;
 
+                    // Manual-capture payment intents only reserve the payment here;
+                    // settlement happens on POST /payment_intents/{id}/capture
+                    if let Some(pi_id) = payment_intent_id.as_ref()
+                        && response.status().is_success()
+                        && is_manual_capture(&state, pi_id)
+                    {
+                        info!("Authorized - Payment reserved for payment intent {}", pi_id);
+                        let authorization = PaymentAuthorization::new(
+                            pi_id.clone(),
+                            payment_payload,
+                            selected_payment_requirement,
+                            chrono::Utc::now().timestamp(),
+                        );
+                        hold_authorization(&state, authorization);
+                        return response;
+                    }
+
                     // Post-process the response
                     if response.status().is_success() {
                         println!("\x1b[32m$ Success\x1b[0m - Payment completed successfully");
@@ -775,6 +1312,32 @@ pub async fn payment_middleware(
                         {
                             Ok(_) => {
                                 info!("Settled - Payment settled on-chain");
+                                // Payment intents record their conversions when they succeed
+                                if payment_intent_id.is_none()
+                                    && let Some(transaction_id) = &transaction_id
+                                {
+                                    record_conversions(
+                                        &state,
+                                        &basket_product_ids(&product_id),
+                                        transaction_id,
+                                        customer_pubkey.map(|pubkey| pubkey.to_string()).as_deref(),
+                                        round_cents(amount),
+                                        &currency,
+                                    );
+                                }
+                                if let Some((discount, currency)) = &redemption
+                                    && let Some(transaction_id) = &transaction_id
+                                {
+                                    record_redemption(
+                                        &state,
+                                        &discount.coupon,
+                                        discount.promotion_code.as_deref(),
+                                        customer_label.as_deref(),
+                                        transaction_id,
+                                        discount.amount,
+                                        currency,
+                                    );
+                                }
                             }
                             Err(error_message) => {
                                 error!("Settlement Failed - {}", error_message);
@@ -845,17 +1408,21 @@ pub enum ProductAccessResult {
     ProductNotFound(String),
     /// Product has no active price - return 404
     NoPriceFound(String),
+    /// Requested quantity is invalid or its amount can't be charged - return 400
+    InvalidQuantity(String),
     /// Found product with price - gate with 402
     Found {
-        amount: i64,
+        /// Cents, with a fraction of a cent for micropayment prices
+        amount: Decimal,
         description: String,
         product_id: String,
+        currency: String,
     },
 }
 
 /// Get features for a product, merging parent features with experiment overrides
 /// For experiment variants: parent features as base, experiment features override
-fn get_product_features(
+pub(crate) fn get_product_features(
     products: &[moneymq_types::Product],
     product: &moneymq_types::Product,
 ) -> Option<serde_json::Value> {
@@ -917,8 +1484,14 @@ fn get_product_features(
     }
 }
 
-/// Extract product info from path like /products/{product_id}/access
-fn extract_product_from_path(state: &CatalogState, path: &str) -> ProductAccessResult {
+/// Extract product info from path like /products/{product_id}/access, priced in the
+/// currency requested with `?currency=` when the price has an amount in it, for the
+/// `?quantity=` units requested (1 by default) that tiered and package prices charge by
+fn extract_product_from_path(
+    state: &CatalogState,
+    path: &str,
+    query: Option<&str>,
+) -> ProductAccessResult {
     // Match pattern: /products/{product_id}/access
     let parts: Vec<&str> = path.split('/').collect();
 
@@ -938,20 +1511,21 @@ fn extract_product_from_path(state: &CatalogState, path: &str) -> ProductAccessR
         None => return ProductAccessResult::NotApplicable,
     };
 
+    let catalog = state.catalog();
     debug!(
         "Looking up product '{}' in {} products",
         product_id,
-        state.products.len()
+        catalog.products.len()
     );
 
     // Look up the product
-    let product = match state.products.iter().find(|p| p.id == product_id) {
+    let product = match catalog.products.iter().find(|p| p.id == product_id) {
         Some(p) => p,
         None => {
             debug!(
                 "Product '{}' not found. Available products: {:?}",
                 product_id,
-                state.products.iter().map(|p| &p.id).collect::<Vec<_>>()
+                catalog.products.iter().map(|p| &p.id).collect::<Vec<_>>()
             );
             return ProductAccessResult::ProductNotFound(product_id);
         }
@@ -966,21 +1540,150 @@ fn extract_product_from_path(state: &CatalogState, path: &str) -> ProductAccessR
         }
     };
 
-    let amount = price.unit_amount.unwrap_or(0);
+    let params =
+        url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()).collect::<Vec<_>>();
+    let requested_currency = params
+        .iter()
+        .find(|(key, _)| key == "currency")
+        .map(|(_, value)| value.to_string());
+    let quantity = match params.iter().find(|(key, _)| key == "quantity") {
+        Some((_, value)) => match value.parse::<i64>() {
+            Ok(quantity) if quantity > 0 => quantity,
+            _ => return ProductAccessResult::InvalidQuantity(value.to_string()),
+        },
+        None => 1,
+    };
+    // Quantities whose amount overflows aren't charged at all
+    let Some((amount, currency)) =
+        exact_price_total(price, quantity, requested_currency.as_deref())
+    else {
+        return ProductAccessResult::InvalidQuantity(quantity.to_string());
+    };
     let description = product.name.clone().unwrap_or_else(|| product_id.clone());
 
-    debug!("Found product '{}' with price {} cents", product_id, amount);
+    debug!(
+        "Found product '{}' with price {} cents ({})",
+        product_id, amount, currency
+    );
 
     ProductAccessResult::Found {
         amount,
         description,
         product_id,
+        currency,
     }
 }
 
+/// Customer accessing a product, from the `customer` query parameter
+fn access_customer(query: Option<&str>) -> Option<String> {
+    url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .find(|(key, _)| key == "customer")
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Discount requested with `?coupon=` or `?promotion_code=` on a product access request.
+///
+/// Returns the basket of the discounted purchase (as JSON) and its discount.
+fn product_access_discount(
+    state: &CatalogState,
+    query: Option<&str>,
+    product_id: &str,
+    amount: i64,
+    currency: &str,
+) -> Result<Option<(String, BasketDiscount)>, DiscountError> {
+    let mut requested = AppliedDiscount::default();
+    for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+        match key.as_ref() {
+            "coupon" => requested.coupon = Some(value.into_owned()),
+            "promotion_code" => requested.promotion_code = Some(value.into_owned()),
+            _ => {}
+        }
+    }
+    let now = chrono::Utc::now().timestamp();
+    let catalog = state.catalog();
+    let Some(discount) = resolve_discount(state, &catalog, &requested, None, now)? else {
+        return Ok(None);
+    };
+    let amount_discount = discount
+        .apply(&[(Some(product_id), amount)], currency)?
+        .iter()
+        .sum::<i64>();
+    let basket_discount = discount.basket_discount(amount_discount);
+    let basket = vec![BasketItem {
+        product_id: product_id.to_string(),
+        experiment_id: None,
+        features: Default::default(),
+        quantity: 1,
+        discount: Some(basket_discount.clone()),
+    }];
+    let basket_json = serde_json::to_string(&basket).unwrap_or_else(|_| "[]".to_string());
+    Ok(Some((basket_json, basket_discount)))
+}
+
+/// Tax requested with `?country=` (and `?state=`) on a product access request, on the
+/// amount left after discounts
+fn product_access_tax(
+    state: &CatalogState,
+    query: Option<&str>,
+    product_id: &str,
+    amount: i64,
+) -> Result<Option<PaymentTax>, TaxError> {
+    let (mut country, mut subdivision) = (None, None);
+    for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+        match key.as_ref() {
+            "country" => country = Some(value.into_owned()),
+            "state" => subdivision = Some(value.into_owned()),
+            _ => {}
+        }
+    }
+    let Some(location) = tax_location(country.as_deref(), subdivision.as_deref()) else {
+        return Ok(None);
+    };
+    let tax_behavior = state
+        .catalog()
+        .products
+        .iter()
+        .find(|p| p.id == product_id)
+        .and_then(|product| product.prices.iter().find(|p| p.active))
+        .map(price_tax_behavior)
+        .unwrap_or_default();
+    let calculation = calculate_tax(state, &location, &[(amount, tax_behavior)])?;
+    Ok(Some(calculation.payment_tax()))
+}
+
+/// Amount taken off each item of a subscription's first period by the discount it
+/// requests
+fn subscription_discounts(
+    state: &CatalogState,
+    request: &SubscriptionRequest,
+    subscribed: &[(&Product, &Price, i64)],
+) -> Result<Vec<i64>, DiscountError> {
+    let (now, _) = current_time(state, request.customer.as_deref());
+    let catalog = state.catalog();
+    let Some(discount) = resolve_discount(
+        state,
+        &catalog,
+        &request.discount,
+        request.customer.as_deref(),
+        now,
+    )?
+    else {
+        return Ok(vec![0; subscribed.len()]);
+    };
+    let currency = subscribed
+        .first()
+        .map(|(_, price, _)| price.currency.as_str().to_string())
+        .unwrap_or_default();
+    let lines = subscribed
+        .iter()
+        .map(|(product, _, amount)| (Some(product.id.as_str()), *amount))
+        .collect::<Vec<_>>();
+    discount.apply(&lines, &currency)
+}
+
 #[cfg(test)]
 mod tests {
-    use moneymq_types::{Product, ProductFeature};
+    use moneymq_types::ProductFeature;
 
     use super::*;
 
@@ -1008,6 +1711,153 @@ mod tests {
         product
     }
 
+    fn make_state(products: Vec<Product>) -> CatalogState {
+        CatalogState::new(
+            products,
+            Vec::new(),
+            true,
+            "http://localhost:8488".parse().unwrap(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+    }
+
+    fn make_requirements() -> PaymentRequirements {
+        use moneymq_types::x402::{MixedAddress, Network, Scheme, TokenAmount};
+        use solana_pubkey::Pubkey;
+
+        PaymentRequirements {
+            scheme: Scheme::Exact,
+            network: Network::Solana,
+            max_amount_required: TokenAmount("1000".to_string()),
+            resource: "http://localhost:8488/products/api/access".parse().unwrap(),
+            description: "API".to_string(),
+            mime_type: "application/json".to_string(),
+            output_schema: None,
+            pay_to: MixedAddress::Solana(Pubkey::new_from_array([1; 32])),
+            max_timeout_seconds: 300,
+            asset: MixedAddress::Solana(Pubkey::new_from_array([2; 32])),
+            extra: Some(json!({ "product": "api" })),
+        }
+    }
+
+    fn payment_header(payload: serde_json::Value) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Payment",
+            BASE64.encode(payload.to_string()).parse().unwrap(),
+        );
+        headers
+    }
+
+    const NOW: i64 = 1_700_000_000;
+
+    #[test]
+    fn test_payment_without_quote_is_rejected() {
+        let state = make_state(Vec::new());
+        let requirements = make_requirements();
+        let quote = state.quote_signer.issue("GET", &requirements, NOW);
+
+        let checked = check_payment_quote(&state, Some(quote.clone()), "GET", &requirements, NOW);
+        assert_eq!(checked.unwrap(), quote);
+
+        // Leaving the quote out doesn't skip the expiry and resource checks
+        let error = check_payment_quote(&state, None, "GET", &requirements, NOW).unwrap_err();
+        assert!(matches!(
+            error,
+            X402MiddlewareError::InvalidQuote(QuoteError::Missing)
+        ));
+        let response: Response = error.into();
+        assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+
+        let expired = check_payment_quote(&state, Some(quote), "GET", &requirements, NOW + 301);
+        assert!(matches!(
+            expired,
+            Err(X402MiddlewareError::InvalidQuote(QuoteError::Expired(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_quote_is_read_from_accepted_requirements() {
+        let state = make_state(Vec::new());
+        let requirements = make_requirements();
+        let quote = state.quote_signer.issue("GET", &requirements, NOW);
+        let payload = json!({
+            "x402Version": 1,
+            "scheme": "exact",
+            "network": "solana",
+            "payload": { "transaction": "tx" },
+        });
+
+        let mut accepted = payload.clone();
+        accepted["accepted"] = json!({ "extra": { "product": "api", "quote": quote } });
+        let (_, echoed) = extract_payment_payload(&payment_header(accepted), &[])
+            .await
+            .unwrap();
+        assert_eq!(echoed, Some(quote.clone()));
+
+        // A quote outside the accepted requirements is not read
+        let mut top_level = payload;
+        top_level["quote"] = json!(quote);
+        let (_, echoed) = extract_payment_payload(&payment_header(top_level), &[])
+            .await
+            .unwrap();
+        assert_eq!(echoed, None);
+    }
+
+    #[test]
+    fn test_x402_route() {
+        for path in [
+            "/payment_intents/pi_1/confirm",
+            "/v1/payment_intents/pi_1/confirm",
+            "/invoices/in_1/pay",
+        ] {
+            assert_eq!(x402_route(path), X402Route::PaymentIntent, "{}", path);
+        }
+        assert_eq!(x402_route("/subscriptions"), X402Route::Subscription);
+        assert_eq!(x402_route("/v1/subscriptions"), X402Route::Subscription);
+        assert_eq!(x402_route("/billing/meter_events"), X402Route::MeterEvent);
+        assert_eq!(x402_route("/products/api/access"), X402Route::ProductAccess);
+        // Trials and meter events sent to other routes don't price them
+        for path in [
+            "/subscriptions/sub_1",
+            "/payment_intents/pi_1/confirm/subscriptions",
+            "/payment_intents/pi_1/billing/meter_events",
+        ] {
+            assert_eq!(x402_route(path), X402Route::Other, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_product_access_quantity() {
+        let mut product = make_product("api", vec![]).add_price(
+            moneymq_types::Price::new(
+                moneymq_types::iac::Currency::Usd,
+                moneymq_types::iac::PricingType::OneTime,
+            )
+            .with_some_amount(Some(2)),
+        );
+        product.name = Some("API".to_string());
+        let state = make_state(vec![product]);
+        let access =
+            |query: &str| extract_product_from_path(&state, "/products/api/access", Some(query));
+
+        assert!(matches!(
+            access("quantity=3"),
+            ProductAccessResult::Found { amount, .. } if amount == Decimal::from(6)
+        ));
+        // Huge quantities are rejected rather than charged a wrapped amount
+        for quantity in [u64::MAX.to_string(), i64::MAX.to_string(), "0".to_string()] {
+            assert!(matches!(
+                access(&format!("quantity={}", quantity)),
+                ProductAccessResult::InvalidQuantity(_)
+            ));
+        }
+    }
+
     #[test]
     fn test_get_product_features_non_experiment() {
         let product = make_product(
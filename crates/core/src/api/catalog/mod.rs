@@ -1,36 +1,81 @@
 use std::{
     collections::HashMap,
     path::PathBuf,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, RwLock},
 };
 
 use axum::{
     Extension, Router,
     http::StatusCode,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post},
 };
 use moneymq_studio_ui::serve_studio_static_files;
-use moneymq_types::{Meter, Product, x402::transactions::FacilitatedTransaction};
-use stripe::types::{StripeCheckoutSession, StripePaymentIntent};
+use moneymq_types::{
+    Coupon, Meter, PaymentLink, Product, x402::transactions::FacilitatedTransaction,
+};
+use stripe::types::{StripeCheckoutSession, StripePaymentIntent, StripeTestClock};
+use tracing::error;
 use url::Url;
 
-use crate::api::sandbox::NetworksConfig;
+use crate::{
+    api::{
+        payment::{db::DbManager, endpoints::jwt::JwtKeyPair, rpc::RpcPool},
+        sandbox::NetworksConfig,
+    },
+    events::{CloudEvent, CloudEventEnvelope, create_event_at},
+    taxes::TaxData,
+};
 
+pub mod admin;
+pub mod authorization;
+pub mod checkout_page;
 pub mod db;
+pub mod experiments;
+pub mod fx;
 pub mod middleware;
+pub mod payment_link_page;
+pub mod quote;
+pub mod solana_pay;
+pub mod store;
 pub mod stripe;
+pub mod webhooks;
 
+use admin::admin_post;
+use authorization::PaymentAuthorization;
+use db::CatalogDbManager;
+use fx::FxRateProvider;
 use middleware::{x402_get, x402_post};
+use quote::QuoteSigner;
+use store::CatalogWriter;
+use webhooks::WebhookEndpoint;
+
+/// Products, meters, coupons and payment links declared in the catalog files
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    pub products: Vec<Product>,
+    pub meters: Vec<Meter>,
+    /// Coupons, with their promotion codes
+    pub coupons: Vec<Coupon>,
+    pub payment_links: Vec<PaymentLink>,
+}
 
 /// Application state
 #[derive(Clone)]
 pub struct CatalogState {
     pub facilitator_url: Url,
-    pub products: Arc<Vec<Product>>,
-    pub meters: Arc<Vec<Meter>>,
+    /// Catalog being served, swapped as a whole when the catalog files are reloaded
+    catalog: Arc<RwLock<Arc<Catalog>>>,
+    /// Held while an edit made through the API is applied to the catalog
+    edit_lock: Arc<Mutex<()>>,
+    /// Tax rates of every supported jurisdiction
+    pub tax_data: Arc<TaxData>,
     pub payment_intents: Arc<Mutex<HashMap<String, StripePaymentIntent>>>,
     pub checkout_sessions: Arc<Mutex<HashMap<String, StripeCheckoutSession>>>,
+    /// Payment links created through the API, in creation order
+    pub payment_links: Arc<Mutex<Vec<PaymentLink>>>,
+    /// Verified but uncaptured payments, keyed by payment intent ID
+    pub authorizations: Arc<Mutex<HashMap<String, PaymentAuthorization>>>,
     pub transactions: Arc<Mutex<Vec<FacilitatedTransaction>>>,
     pub networks_config: NetworksConfig,
     pub catalog_name: Option<String>,
@@ -38,6 +83,39 @@ pub struct CatalogState {
     pub manifest_path: PathBuf,
     pub catalog_path: PathBuf,
     pub use_sandbox: bool,
+    /// Signs x402 payment quotes so payments are bound to the resource they were issued for
+    pub quote_signer: QuoteSigner,
+    /// Database persisting subscriptions; subscription endpoints are unavailable without it
+    pub payment_db: Option<Arc<DbManager>>,
+    /// Payment stack the persisted records belong to
+    pub payment_stack_id: String,
+    /// Sandbox test clocks, keyed by test clock ID
+    pub test_clocks: Arc<Mutex<HashMap<String, StripeTestClock>>>,
+    /// Test clock each attached customer is on, keyed by customer ID
+    pub customer_test_clocks: Arc<Mutex<HashMap<String, String>>>,
+    /// Name of the payment stack, shown on hosted pages
+    pub stack_name: Option<String>,
+    /// Logo of the payment stack, shown on hosted pages
+    pub stack_image_url: Option<String>,
+    /// Endpoint Stripe-format events are delivered to
+    pub webhook: Option<WebhookEndpoint>,
+    /// Rates converting prices into the currency of the settlement stablecoin; payments in
+    /// currencies no accepted stablecoin tracks are refused without it
+    pub fx_rates: Option<Arc<dyn FxRateProvider>>,
+    /// Database persisting products and prices edited through the API; the catalog is
+    /// read-only without it
+    pub catalog_db: Option<Arc<CatalogDbManager>>,
+    /// Writes products edited through the API back to the catalog files
+    pub catalog_writer: Option<Arc<dyn CatalogWriter>>,
+    /// Key required to create and update products and prices; the catalog can't be
+    /// edited through the API without it
+    pub admin_key: Option<Arc<str>>,
+    /// Key signing the payment receipts, which customers present to access the products
+    /// their entitlements cover without paying again
+    pub receipt_key: Option<Arc<JwtKeyPair>>,
+    /// RPC endpoints of the Solana network, refunding the uncaptured part of partial
+    /// captures; payment intents can only be captured in full without it
+    pub rpc_pool: Option<Arc<RpcPool>>,
 }
 
 /// Application state
@@ -61,8 +139,14 @@ impl CatalogState {
         manifest_path: PathBuf,
     ) -> Self {
         Self {
-            products: Arc::new(products),
-            meters: Arc::new(meters),
+            catalog: Arc::new(RwLock::new(Arc::new(Catalog {
+                products,
+                meters,
+                coupons: Vec::new(),
+                payment_links: Vec::new(),
+            }))),
+            edit_lock: Arc::new(Mutex::new(())),
+            tax_data: Arc::new(TaxData::load().expect("Embedded tax data is valid")),
             use_sandbox,
             facilitator_url,
             networks_config,
@@ -72,7 +156,183 @@ impl CatalogState {
             transactions: Arc::new(Mutex::new(Vec::new())),
             payment_intents: Arc::new(Mutex::new(HashMap::new())),
             checkout_sessions: Arc::new(Mutex::new(HashMap::new())),
+            payment_links: Arc::new(Mutex::new(Vec::new())),
+            authorizations: Arc::new(Mutex::new(HashMap::new())),
             manifest_path,
+            quote_signer: QuoteSigner::random(),
+            payment_db: None,
+            payment_stack_id: "local".to_string(),
+            test_clocks: Arc::new(Mutex::new(HashMap::new())),
+            customer_test_clocks: Arc::new(Mutex::new(HashMap::new())),
+            stack_name: None,
+            stack_image_url: None,
+            webhook: None,
+            fx_rates: None,
+            catalog_db: None,
+            catalog_writer: None,
+            admin_key: None,
+            receipt_key: None,
+            rpc_pool: None,
+        }
+    }
+
+    /// Sign payment quotes with a shared secret instead of a random per-process key
+    pub fn with_quote_secret(mut self, secret: &str) -> Self {
+        self.quote_signer = QuoteSigner::from_secret(secret.as_bytes());
+        self
+    }
+
+    /// Let requests carrying `admin_key` create and update products and prices
+    pub fn with_admin_key(mut self, admin_key: &str) -> Self {
+        self.admin_key = Some(Arc::from(admin_key));
+        self
+    }
+
+    /// Accept payment receipts signed with `key` as proof of the customer accessing a product
+    pub fn with_receipt_key(mut self, key: Arc<JwtKeyPair>) -> Self {
+        self.receipt_key = Some(key);
+        self
+    }
+
+    /// Offer the coupons declared in the catalog
+    pub fn with_coupons(self, coupons: Vec<Coupon>) -> Self {
+        let catalog = Catalog {
+            coupons,
+            ..Catalog::clone(&self.catalog())
+        };
+        self.replace_catalog(catalog);
+        self
+    }
+
+    /// Serve the payment links declared in the catalog
+    pub fn with_payment_links(self, payment_links: Vec<PaymentLink>) -> Self {
+        let catalog = Catalog {
+            payment_links,
+            ..Catalog::clone(&self.catalog())
+        };
+        self.replace_catalog(catalog);
+        self
+    }
+
+    /// Persist subscriptions and authorized payments in the payment database of
+    /// `payment_stack_id`, holding again the payments it kept authorized
+    pub fn with_payment_db(mut self, db_manager: Arc<DbManager>, payment_stack_id: &str) -> Self {
+        self.payment_db = Some(db_manager);
+        self.payment_stack_id = payment_stack_id.to_string();
+        authorization::restore_authorizations(&self);
+        self
+    }
+
+    /// Brand hosted pages with the payment stack's name and logo
+    pub fn with_stack_branding(mut self, name: Option<String>, image_url: Option<String>) -> Self {
+        self.stack_name = name;
+        self.stack_image_url = image_url;
+        self
+    }
+
+    /// Deliver Stripe-format events to `url`, signed with the endpoint's `secret`
+    pub fn with_webhook_endpoint(
+        mut self,
+        url: Url,
+        secret: String,
+        api_version: Option<String>,
+    ) -> Self {
+        self.webhook = Some(WebhookEndpoint::new(url, secret, api_version));
+        self
+    }
+
+    /// Convert prices in currencies no accepted stablecoin tracks at `provider`'s rates
+    pub fn with_fx_rates(mut self, provider: Arc<dyn FxRateProvider>) -> Self {
+        self.fx_rates = Some(provider);
+        self
+    }
+
+    /// Persist products and prices edited through the API in `db`
+    pub fn with_catalog_db(mut self, db: Arc<CatalogDbManager>) -> Self {
+        self.catalog_db = Some(db);
+        self
+    }
+
+    /// Write products edited through the API back to the catalog files with `writer`
+    pub fn with_catalog_writer(mut self, writer: Arc<dyn CatalogWriter>) -> Self {
+        self.catalog_writer = Some(writer);
+        self
+    }
+
+    /// Send transactions of the catalog, such as partial capture refunds, through `pool`
+    pub fn with_rpc_pool(mut self, pool: Arc<RpcPool>) -> Self {
+        self.rpc_pool = Some(pool);
+        self
+    }
+
+    /// Catalog being served. A request keeps the catalog it started with when the catalog
+    /// is replaced meanwhile.
+    pub fn catalog(&self) -> Arc<Catalog> {
+        self.catalog.read().unwrap().clone()
+    }
+
+    /// Serve `catalog` from now on, from this state and all its clones
+    ///
+    /// Without a catalog writer, the products stored in the catalog DB are overlaid onto it.
+    /// Waits for the edit in progress, if any, so that it isn't overwritten by a catalog
+    /// loaded before it was stored.
+    pub fn replace_catalog(&self, catalog: Catalog) {
+        self.replace_catalog_with(catalog, || {});
+    }
+
+    /// Serve `catalog` like [`CatalogState::replace_catalog`], running `swap` under the same
+    /// lock so that state loaded along with the catalog, such as the actors, is swapped in
+    /// with it
+    pub fn replace_catalog_with(&self, catalog: Catalog, swap: impl FnOnce()) {
+        let _guard = self.edit_lock.lock().unwrap();
+        self.serve_catalog(catalog);
+        swap();
+    }
+
+    fn serve_catalog(&self, mut catalog: Catalog) {
+        if self.catalog_writer.is_none() {
+            store::overlay_stored_products(self, &mut catalog.products);
+        }
+        *self.catalog.write().unwrap() = Arc::new(catalog);
+    }
+
+    /// Apply `edit` to a copy of the catalog being served and serve it if the edit succeeds,
+    /// one edit at a time
+    pub(crate) fn edit_catalog<T, E>(
+        &self,
+        edit: impl FnOnce(&mut Catalog) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let _guard = self.edit_lock.lock().unwrap();
+        let mut catalog = Catalog::clone(&self.catalog());
+        let edited = edit(&mut catalog)?;
+        self.serve_catalog(catalog);
+        Ok(edited)
+    }
+
+    /// Persist a CloudEvent that occurred at `at` (Unix seconds) so it is replayed on the
+    /// event stream
+    pub(crate) fn record_event(&self, event: CloudEvent, at: i64) {
+        let Some(db) = self.payment_db.as_ref() else {
+            return;
+        };
+        let time = chrono::DateTime::from_timestamp(at, 0).unwrap_or_else(chrono::Utc::now);
+        let event = create_event_at(event, time);
+        let Some(envelope) = CloudEventEnvelope::from_sdk_event(&event) else {
+            return;
+        };
+        let Ok(json_str) = serde_json::to_string(&envelope) else {
+            return;
+        };
+        if let Err(e) = db.insert_cloud_event(
+            envelope.id.clone(),
+            envelope.ty.clone(),
+            envelope.source.clone(),
+            time.timestamp_millis(),
+            json_str,
+            &self.payment_stack_id,
+            self.use_sandbox,
+        ) {
+            error!("Failed to persist {} CloudEvent to DB: {}", envelope.ty, e);
         }
     }
 }
@@ -93,21 +353,83 @@ impl CatalogState {
 pub fn create_routes() -> Router<()> {
     Router::new()
         // Product endpoints
-        .route("/products", get(stripe::list_products))
+        .route(
+            "/products",
+            get(stripe::list_products).merge(admin_post(stripe::create_product)),
+        )
+        .route(
+            "/products/{id}",
+            get(stripe::retrieve_product).merge(admin_post(stripe::update_product)),
+        )
         .route(
             "/products/{id}/access",
             x402_get(stripe::get_product_access, None),
         )
-        .route("/prices", get(stripe::list_prices))
+        .route(
+            "/prices",
+            get(stripe::list_prices).merge(admin_post(stripe::create_price)),
+        )
+        .route(
+            "/prices/{id}",
+            get(stripe::retrieve_price).merge(admin_post(stripe::update_price)),
+        )
         // Billing endpoints
         .route("/billing/meters", get(stripe::list_meters))
+        .route("/billing/meters/{id}", get(stripe::retrieve_meter))
+        .route(
+            "/billing/meters/{id}/event_summaries",
+            get(stripe::list_meter_event_summaries),
+        )
         .route(
             "/billing/meter_events",
             x402_post(stripe::create_meter_event, None),
         )
+        // Coupon endpoints
+        .route("/coupons", get(stripe::list_coupons))
+        .route("/coupons/{id}", get(stripe::retrieve_coupon))
+        .route("/promotion_codes", get(stripe::list_promotion_codes))
+        .route(
+            "/promotion_codes/{id}",
+            get(stripe::retrieve_promotion_code),
+        )
+        // Tax endpoints
+        .route("/tax/calculations", post(stripe::create_tax_calculation))
         // Customer endpoints
-        .route("/customers", post(stripe::create_customer))
-        .route("/customers/{id}", post(stripe::update_customer))
+        .route(
+            "/customers",
+            post(stripe::create_customer).get(stripe::list_customers),
+        )
+        .route("/customers/search", get(stripe::search_customers))
+        .route(
+            "/customers/{id}",
+            get(stripe::retrieve_customer)
+                .post(stripe::update_customer)
+                .delete(stripe::delete_customer),
+        )
+        .route(
+            "/customers/{id}/wallets",
+            post(stripe::attach_customer_wallet),
+        )
+        .route(
+            "/customers/{id}/wallets/{address}",
+            delete(stripe::detach_customer_wallet),
+        )
+        // Entitlement endpoints
+        .route(
+            "/entitlements/features",
+            get(stripe::list_entitlement_features),
+        )
+        .route(
+            "/entitlements/features/{id}",
+            get(stripe::retrieve_entitlement_feature),
+        )
+        .route(
+            "/entitlements/active_entitlements",
+            get(stripe::list_active_entitlements),
+        )
+        // Event endpoints
+        .route("/events", get(stripe::list_events))
+        .route("/events/{id}", get(stripe::retrieve_event))
         // Payment method endpoints
         .route("/payment_methods", post(stripe::create_payment_method))
         .route(
@@ -124,6 +446,10 @@ pub fn create_routes() -> Router<()> {
             "/payment_intents/{id}/confirm",
             x402_post(stripe::confirm_payment_intent, None),
         )
+        .route(
+            "/payment_intents/{id}/capture",
+            post(stripe::capture_payment_intent),
+        )
         .route(
             "/payment_intents/{id}/cancel",
             post(stripe::cancel_payment_intent),
@@ -131,7 +457,54 @@ pub fn create_routes() -> Router<()> {
         // Subscription endpoints
         .route(
             "/subscriptions",
-            x402_post(stripe::create_subscription, None),
+            x402_post(stripe::create_subscription, None).get(stripe::list_subscriptions),
+        )
+        .route(
+            "/subscriptions/{id}",
+            get(stripe::retrieve_subscription)
+                .post(stripe::update_subscription)
+                .delete(stripe::cancel_subscription),
+        )
+        .route(
+            "/subscriptions/{id}/pause",
+            post(stripe::pause_subscription),
+        )
+        .route(
+            "/subscriptions/{id}/resume",
+            post(stripe::resume_subscription),
+        )
+        // Invoice endpoints
+        .route(
+            "/invoices",
+            post(stripe::create_invoice).get(stripe::list_invoices),
+        )
+        .route(
+            "/invoices/{id}",
+            get(stripe::retrieve_invoice).post(stripe::update_invoice),
+        )
+        .route("/invoices/{id}/finalize", post(stripe::finalize_invoice))
+        .route("/invoices/{id}/pay", x402_post(stripe::pay_invoice, None))
+        .route("/invoices/{id}/void", post(stripe::void_invoice))
+        .route(
+            "/invoiceitems",
+            post(stripe::create_invoice_item).get(stripe::list_invoice_items),
+        )
+        .route(
+            "/invoiceitems/{id}",
+            get(stripe::retrieve_invoice_item).delete(stripe::delete_invoice_item),
+        )
+        // Test clock endpoints (sandbox only)
+        .route(
+            "/test_helpers/test_clocks",
+            post(stripe::create_test_clock).get(stripe::list_test_clocks),
+        )
+        .route(
+            "/test_helpers/test_clocks/{id}",
+            get(stripe::retrieve_test_clock).delete(stripe::delete_test_clock),
+        )
+        .route(
+            "/test_helpers/test_clocks/{id}/advance",
+            post(stripe::advance_test_clock),
         )
         // Checkout session endpoints (Stripe Checkout API)
         .route("/checkout/sessions", post(stripe::create_checkout_session))
@@ -147,6 +520,32 @@ pub fn create_routes() -> Router<()> {
             "/checkout/sessions/{id}/expire",
             post(stripe::expire_checkout_session),
         )
+        // Experiment endpoints
+        .route(
+            "/experiments/{id}/results",
+            get(experiments::experiment_results),
+        )
+        // Payment link endpoints
+        .route(
+            "/payment_links",
+            post(stripe::create_payment_link).get(stripe::list_payment_links),
+        )
+        .route(
+            "/payment_links/{id}",
+            get(stripe::retrieve_payment_link).post(stripe::update_payment_link),
+        )
+        .route(
+            "/payment_links/{id}/line_items",
+            get(stripe::list_payment_link_line_items),
+        )
+        .route("/payment_links/{id}/stats", get(stripe::payment_link_stats))
+        // Hosted checkout page (the session's `url`)
+        .route(
+            "/checkout/pay/{id}",
+            get(checkout_page::hosted_checkout_page),
+        )
+        // Payment link page (the link's `url`), opening a checkout session per visit
+        .route("/pay/{id}", get(payment_link_page::visit_payment_link))
 }
 
 /// Create the catalog router with all catalog-related routes and state.
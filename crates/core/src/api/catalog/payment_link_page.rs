@@ -0,0 +1,518 @@
+//! Page served at a payment link's `url`
+//!
+//! Every visit opens a fresh checkout session for the link's line items, tagged with the
+//! link, and redirects to its hosted checkout page. Links letting buyers adjust quantities
+//! or asking for custom fields first render a form, submitted back to the same URL with
+//! `confirm=1`.
+
+use std::collections::HashMap;
+
+use axum::{
+    Extension,
+    extract::{OriginalUri, Path, RawQuery},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use moneymq_types::{CustomFieldType, PaymentLink, Price, Product};
+
+use crate::api::catalog::{
+    CatalogState,
+    checkout_page::{CheckoutStyle, absolute_url, checkout_page_url, escape_html, format_decimal},
+    fx::price_total,
+    stripe::{
+        endpoints::{
+            checkout_sessions::open_checkout_session,
+            payment_links::{find_payment_link, resolve_line_item},
+        },
+        types::{CheckoutCustomField, CreateCheckoutSessionRequest, CreateLineItem},
+    },
+};
+
+/// Shareable URL of payment link `id`, for a request made at `request_path`
+/// (`.../payment_links...`)
+pub(crate) fn payment_link_url(
+    state: &CatalogState,
+    headers: &HeaderMap,
+    request_path: &str,
+    id: &str,
+) -> String {
+    // Routes are nested under the catalog prefix (e.g. /catalog/v1)
+    let prefix = request_path
+        .find("/payment_links")
+        .map(|index| &request_path[..index])
+        .unwrap_or("");
+    absolute_url(state, headers, &format!("{}/pay/{}", prefix, id)).to_string()
+}
+
+/// Quantities and custom field values a buyer picked for a link
+#[derive(Debug)]
+struct BuyerInput {
+    quantities: Vec<i64>,
+    custom_fields: Vec<CheckoutCustomField>,
+}
+
+/// Check the quantities (`quantity[i]`) and custom field values (`custom_fields[key]`)
+/// submitted for `link`, returning the messages to show when some are invalid
+fn buyer_input(
+    link: &PaymentLink,
+    params: &HashMap<String, String>,
+) -> Result<BuyerInput, Vec<String>> {
+    let mut errors = Vec::new();
+
+    let mut quantities = Vec::with_capacity(link.line_items.len());
+    for (index, item) in link.line_items.iter().enumerate() {
+        let Some(adjustable) = item.adjustable_quantity.filter(|a| a.enabled) else {
+            quantities.push(item.quantity);
+            continue;
+        };
+        let quantity = params
+            .get(&format!("quantity[{}]", index))
+            .and_then(|quantity| quantity.trim().parse::<i64>().ok());
+        match quantity {
+            Some(quantity)
+                if quantity >= adjustable.minimum() && quantity <= adjustable.maximum() =>
+            {
+                quantities.push(quantity)
+            }
+            _ => errors.push(format!(
+                "Quantity must be between {} and {}",
+                adjustable.minimum(),
+                adjustable.maximum()
+            )),
+        }
+    }
+
+    let mut custom_fields = Vec::with_capacity(link.custom_fields.len());
+    for field in &link.custom_fields {
+        let value = params
+            .get(&format!("custom_fields[{}]", field.key))
+            .map(String::as_str);
+        match field.check_value(value) {
+            Ok(value) => custom_fields.push(CheckoutCustomField {
+                key: field.key.clone(),
+                label: field.label.clone(),
+                field_type: field.field_type.as_str().to_string(),
+                optional: field.optional,
+                value,
+            }),
+            Err(message) => errors.push(message),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(BuyerInput {
+        quantities,
+        custom_fields,
+    })
+}
+
+fn message_page(status: StatusCode, title: &str, message: &str) -> Response {
+    (
+        status,
+        Html(format!(
+            "<!doctype html><html><head><meta charset=\"utf-8\"><title>{}</title></head>\
+             <body><p>{}</p></body></html>",
+            escape_html(title),
+            escape_html(message)
+        )),
+    )
+        .into_response()
+}
+
+/// GET /pay/:id - Open a checkout session for a payment link
+pub async fn visit_payment_link(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> Response {
+    let catalog = state.catalog();
+    let Some((link, _)) = find_payment_link(&state, &catalog, &id) else {
+        return message_page(
+            StatusCode::NOT_FOUND,
+            "Payment link not found",
+            &format!("No such payment link: {}", id),
+        );
+    };
+    if !link.active {
+        return message_page(
+            StatusCode::GONE,
+            "Payment link inactive",
+            "This payment link is no longer active.",
+        );
+    }
+    let Some(items) = link
+        .line_items
+        .iter()
+        .map(|item| resolve_line_item(&state, &catalog, item))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return message_page(
+            StatusCode::GONE,
+            "Payment link unavailable",
+            "This payment link sells prices that are no longer available.",
+        );
+    };
+
+    let page_path = uri.path().to_string();
+    let params = url::form_urlencoded::parse(query.as_deref().unwrap_or_default().as_bytes())
+        .into_owned()
+        .collect::<HashMap<_, _>>();
+    let confirmed = params.get("confirm").map(String::as_str) == Some("1");
+    if link.needs_buyer_input() && !confirmed {
+        let style = CheckoutStyle::load(&state);
+        return Html(render_form(
+            &state,
+            &link,
+            &items,
+            &params,
+            &[],
+            &style,
+            &page_path,
+        ))
+        .into_response();
+    }
+    let input = match buyer_input(&link, &params) {
+        Ok(input) => input,
+        Err(errors) => {
+            let style = CheckoutStyle::load(&state);
+            return (
+                StatusCode::BAD_REQUEST,
+                Html(render_form(
+                    &state, &link, &items, &params, &errors, &style, &page_path,
+                )),
+            )
+                .into_response();
+        }
+    };
+
+    let request = CreateCheckoutSessionRequest {
+        line_items: items
+            .iter()
+            .zip(&input.quantities)
+            .map(|((product, price), quantity)| CreateLineItem {
+                product_id: product.id.clone(),
+                experiment_id: None,
+                price: Some(price.id.clone()),
+                quantity: *quantity,
+            })
+            .collect(),
+        customer: None,
+        customer_email: None,
+        customer_details: None,
+        success_url: link.success_url.clone(),
+        cancel_url: Some(absolute_url(&state, &headers, &page_path).to_string()),
+        metadata: link
+            .metadata
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        mode: "payment".to_string(),
+        currency: link.currency.clone(),
+        discounts: Vec::new(),
+        payment_link: Some(link.id.clone()),
+        custom_fields: input.custom_fields,
+    };
+
+    let prefix = page_path
+        .strip_suffix(&format!("/pay/{}", id))
+        .unwrap_or("");
+    let sessions_path = format!("{}/checkout/sessions", prefix);
+    match open_session(&state, &headers, &sessions_path, request) {
+        Ok(url) => Redirect::to(&url).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Open a checkout session, returning the URL of its hosted page
+fn open_session(
+    state: &CatalogState,
+    headers: &HeaderMap,
+    sessions_path: &str,
+    request: CreateCheckoutSessionRequest,
+) -> Result<String, Response> {
+    let session = open_checkout_session(state, headers, sessions_path, request)?;
+    Ok(session
+        .url
+        .unwrap_or_else(|| checkout_page_url(state, headers, sessions_path, &session.id).into()))
+}
+
+/// Render the form buyers pick quantities and fill in custom fields with
+fn render_form(
+    state: &CatalogState,
+    link: &PaymentLink,
+    items: &[(&Product, &Price)],
+    params: &HashMap<String, String>,
+    errors: &[String],
+    style: &CheckoutStyle,
+    action: &str,
+) -> String {
+    let stack_name = state
+        .stack_name
+        .as_deref()
+        .or(state.catalog_name.as_deref())
+        .unwrap_or("Checkout");
+
+    let lines = link
+        .line_items
+        .iter()
+        .zip(items)
+        .enumerate()
+        .map(|(index, (item, (product, price)))| {
+            // Tiered and package prices show the average unit amount of the default quantity
+            let amount = match price_total(price, item.quantity, link.currency.as_deref()) {
+                Some((amount, currency)) => format!(
+                    "{} {}",
+                    format_decimal(amount.checked_div(item.quantity).unwrap_or(amount)),
+                    escape_html(&currency.to_uppercase())
+                ),
+                None => "&mdash;".to_string(),
+            };
+            let name = product.name.as_deref().unwrap_or(&product.id);
+            let quantity = match item.adjustable_quantity.filter(|a| a.enabled) {
+                Some(adjustable) => {
+                    let name = format!("quantity[{}]", index);
+                    let value = params
+                        .get(&name)
+                        .cloned()
+                        .unwrap_or_else(|| item.quantity.to_string());
+                    format!(
+                        "<input type=\"number\" name=\"{}\" value=\"{}\" min=\"{}\" max=\"{}\" required>",
+                        escape_html(&name),
+                        escape_html(&value),
+                        adjustable.minimum(),
+                        adjustable.maximum()
+                    )
+                }
+                None => format!("<span class=\"muted\">&times; {}</span>", item.quantity),
+            };
+            format!(
+                "<tr><td>{}</td><td>{}</td><td class=\"amount\">{}</td></tr>",
+                escape_html(name),
+                quantity,
+                amount
+            )
+        })
+        .collect::<String>();
+
+    let fields = link
+        .custom_fields
+        .iter()
+        .map(|field| {
+            let name = format!("custom_fields[{}]", field.key);
+            let value = params.get(&name).map(String::as_str).unwrap_or_default();
+            let required = if field.optional { "" } else { " required" };
+            let input = match field.field_type {
+                CustomFieldType::Dropdown => {
+                    let options = field
+                        .options
+                        .iter()
+                        .map(|option| {
+                            format!(
+                                "<option value=\"{}\"{}>{}</option>",
+                                escape_html(&option.value),
+                                if option.value == value { " selected" } else { "" },
+                                escape_html(&option.label)
+                            )
+                        })
+                        .collect::<String>();
+                    format!(
+                        "<select name=\"{}\"{}><option value=\"\"></option>{}</select>",
+                        escape_html(&name),
+                        required,
+                        options
+                    )
+                }
+                CustomFieldType::Numeric => format!(
+                    "<input type=\"text\" inputmode=\"numeric\" pattern=\"[0-9]*\" name=\"{}\" value=\"{}\"{}>",
+                    escape_html(&name),
+                    escape_html(value),
+                    required
+                ),
+                CustomFieldType::Text => format!(
+                    "<input type=\"text\" name=\"{}\" value=\"{}\"{}>",
+                    escape_html(&name),
+                    escape_html(value),
+                    required
+                ),
+            };
+            format!(
+                "<label>{}{}{}</label>",
+                escape_html(&field.label),
+                if field.optional {
+                    " <span class=\"muted\">(optional)</span>"
+                } else {
+                    ""
+                },
+                input
+            )
+        })
+        .collect::<String>();
+
+    let errors = if errors.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "<ul class=\"errors\">{}</ul>",
+            errors
+                .iter()
+                .map(|error| format!("<li>{}</li>", escape_html(error)))
+                .collect::<String>()
+        )
+    };
+
+    format!(
+        "<!doctype html>\
+<html lang=\"en\">\
+<head>\
+<meta charset=\"utf-8\">\
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+<title>{title}</title>\
+<style>\
+:root {{ --primary: {primary}; --secondary: {secondary}; }}\
+body {{ margin: 0; font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif; color: var(--secondary); background: #f6f8fa; }}\
+main {{ max-width: 480px; margin: 0 auto; padding: 32px 16px; }}\
+h1 {{ font-size: 18px; margin: 0 0 24px; }}\
+table {{ width: 100%; border-collapse: collapse; margin-bottom: 16px; }}\
+td {{ padding: 8px 0; border-bottom: 1px solid #e3e8ee; }}\
+.amount {{ text-align: right; white-space: nowrap; }}\
+.muted {{ color: #697386; }}\
+.card {{ background: #fff; border-radius: 12px; padding: 24px; box-shadow: 0 1px 3px rgba(0,0,0,.08); }}\
+label {{ display: block; margin-bottom: 16px; font-weight: 600; }}\
+input, select {{ display: block; width: 100%; box-sizing: border-box; margin-top: 6px; padding: 8px; border: 1px solid #e3e8ee; border-radius: 8px; font: inherit; }}\
+td input {{ width: 80px; margin: 0; }}\
+.errors {{ color: #df1b41; padding-left: 20px; }}\
+button {{ width: 100%; padding: 12px; border: none; border-radius: 8px; background: var(--primary); color: #fff; font: inherit; font-weight: 600; cursor: pointer; }}\
+</style>\
+</head>\
+<body>\
+<main>\
+<h1>{title}</h1>\
+<form class=\"card\" method=\"get\" action=\"{action}\">\
+{errors}\
+<table>{lines}</table>\
+{fields}\
+<input type=\"hidden\" name=\"confirm\" value=\"1\">\
+<button type=\"submit\">Continue to payment</button>\
+</form>\
+</main>\
+</body>\
+</html>",
+        title = escape_html(stack_name),
+        primary = style.primary(),
+        secondary = style.secondary(),
+        action = escape_html(action),
+        errors = errors,
+        lines = lines,
+        fields = fields,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use moneymq_types::{AdjustableQuantity, CustomField, CustomFieldOption, PaymentLinkLineItem};
+
+    use super::*;
+
+    fn link() -> PaymentLink {
+        PaymentLink {
+            id: "workshop".to_string(),
+            active: true,
+            line_items: vec![PaymentLinkLineItem {
+                price: None,
+                product: Some("workshop".to_string()),
+                quantity: 1,
+                adjustable_quantity: Some(AdjustableQuantity {
+                    enabled: true,
+                    minimum: None,
+                    maximum: Some(10),
+                }),
+            }],
+            custom_fields: vec![
+                CustomField {
+                    key: "company".to_string(),
+                    label: "Company".to_string(),
+                    field_type: CustomFieldType::Text,
+                    optional: true,
+                    options: Vec::new(),
+                },
+                CustomField {
+                    key: "size".to_string(),
+                    label: "T-shirt size".to_string(),
+                    field_type: CustomFieldType::Dropdown,
+                    optional: false,
+                    options: vec![CustomFieldOption {
+                        label: "Large".to_string(),
+                        value: "l".to_string(),
+                    }],
+                },
+            ],
+            success_url: None,
+            currency: None,
+            created: None,
+            metadata: Default::default(),
+        }
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_buyer_input() {
+        let link = link();
+        let input = buyer_input(
+            &link,
+            &params(&[("quantity[0]", "3"), ("custom_fields[size]", "l")]),
+        )
+        .unwrap();
+        assert_eq!(input.quantities, vec![3]);
+        assert_eq!(input.custom_fields[0].value, None);
+        assert_eq!(input.custom_fields[1].value.as_deref(), Some("l"));
+
+        let errors = buyer_input(
+            &link,
+            &params(&[("quantity[0]", "11"), ("custom_fields[size]", "xl")]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                "Quantity must be between 1 and 10".to_string(),
+                "T-shirt size must be one of its options".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_payment_link_url() {
+        let state = CatalogState::new(
+            Vec::new(),
+            Vec::new(),
+            true,
+            "http://localhost:8488".parse().unwrap(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert("host", "pay.example.com".parse().unwrap());
+        assert_eq!(
+            payment_link_url(
+                &state,
+                &headers,
+                "/catalog/v1/payment_links/plink_1",
+                "plink_1"
+            ),
+            "http://pay.example.com/catalog/v1/pay/plink_1"
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Signed x402 payment quotes.
+//!
+//! Every set of `PaymentRequirements` issued by the payment middleware carries a quote in
+//! `extra.quote`: a random nonce, an expiry and an HMAC-SHA256 signature over the resource
+//! URL, HTTP method, basket, network, asset, amount and recipient. Payments must echo the
+//! quote back in the requirements they accepted (`accepted.extra.quote` of the `X-Payment`
+//! payload), and it is only accepted for the exact request it was issued for and only until
+//! it expires.
+
+use std::sync::Arc;
+
+use hmac::{Hmac, Mac};
+use moneymq_types::x402::PaymentRequirements;
+use sha2::{Digest, Sha256};
+
+pub use crate::api::payment::endpoints::PaymentQuote;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Domain separator mixed into every signed quote payload
+const QUOTE_DOMAIN: &str = "moneymq-x402-quote-v1";
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum QuoteError {
+    #[error("Payment must echo the quote it was issued in accepted.extra.quote")]
+    Missing,
+    #[error("Payment quote expired at {0}")]
+    Expired(i64),
+    #[error("Payment quote was issued for {0} {1}")]
+    MethodMismatch(String, String),
+    #[error("Payment quote signature does not match the requested resource")]
+    InvalidSignature,
+}
+
+/// Issues and verifies HMAC-signed payment quotes
+#[derive(Clone)]
+pub struct QuoteSigner {
+    key: Arc<[u8; 32]>,
+}
+
+impl std::fmt::Debug for QuoteSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QuoteSigner").finish_non_exhaustive()
+    }
+}
+
+impl QuoteSigner {
+    /// Create a signer with a random per-process key.
+    ///
+    /// Quotes issued by this signer do not survive a restart, and are not accepted by other
+    /// instances. Use [`QuoteSigner::from_secret`] when several instances serve the same catalog.
+    pub fn random() -> Self {
+        Self {
+            key: Arc::new(rand::random::<[u8; 32]>()),
+        }
+    }
+
+    /// Derive a signer from a shared secret
+    pub fn from_secret(secret: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(QUOTE_DOMAIN.as_bytes());
+        hasher.update(secret);
+        Self {
+            key: Arc::new(hasher.finalize().into()),
+        }
+    }
+
+    /// Issue a quote for `requirements`, valid for `requirements.max_timeout_seconds` from `now`
+    pub fn issue(
+        &self,
+        method: &str,
+        requirements: &PaymentRequirements,
+        now: i64,
+    ) -> PaymentQuote {
+        let nonce = hex::encode(rand::random::<[u8; 16]>());
+        let expires_at = now.saturating_add(requirements.max_timeout_seconds as i64);
+        let method = method.to_ascii_uppercase();
+        let signature = self.sign(&method, requirements, &nonce, expires_at);
+        PaymentQuote {
+            nonce,
+            method,
+            expires_at,
+            signature,
+        }
+    }
+
+    /// Check that `quote` was issued by this signer for `method` and `requirements`,
+    /// and that it has not expired at `now`
+    pub fn verify(
+        &self,
+        quote: &PaymentQuote,
+        method: &str,
+        requirements: &PaymentRequirements,
+        now: i64,
+    ) -> Result<(), QuoteError> {
+        if !quote.method.eq_ignore_ascii_case(method) {
+            return Err(QuoteError::MethodMismatch(
+                quote.method.clone(),
+                method.to_ascii_uppercase(),
+            ));
+        }
+
+        let signature = hex::decode(&quote.signature).map_err(|_| QuoteError::InvalidSignature)?;
+        let mut mac = self.mac();
+        mac.update(&signing_payload(
+            &quote.method.to_ascii_uppercase(),
+            requirements,
+            &quote.nonce,
+            quote.expires_at,
+        ));
+        mac.verify_slice(&signature)
+            .map_err(|_| QuoteError::InvalidSignature)?;
+
+        if quote.is_expired(now) {
+            return Err(QuoteError::Expired(quote.expires_at));
+        }
+        Ok(())
+    }
+
+    fn sign(
+        &self,
+        method: &str,
+        requirements: &PaymentRequirements,
+        nonce: &str,
+        expires_at: i64,
+    ) -> String {
+        let mut mac = self.mac();
+        mac.update(&signing_payload(method, requirements, nonce, expires_at));
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(self.key.as_slice()).expect("HMAC accepts keys of any length")
+    }
+}
+
+/// Build the canonical byte string covered by a quote signature.
+///
+/// The basket is read from `extra.product` so that two products sold at the same price on the
+/// same route still produce different signatures.
+fn signing_payload(
+    method: &str,
+    requirements: &PaymentRequirements,
+    nonce: &str,
+    expires_at: i64,
+) -> Vec<u8> {
+    let product = requirements
+        .extra
+        .as_ref()
+        .and_then(|extra| extra.get("product"))
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+
+    [
+        QUOTE_DOMAIN,
+        method,
+        requirements.resource.as_str(),
+        product,
+        &requirements.network.to_string(),
+        &requirements.asset.to_string(),
+        &requirements.max_amount_required.0,
+        &requirements.pay_to.to_string(),
+        &expires_at.to_string(),
+        nonce,
+    ]
+    .join("\n")
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use moneymq_types::x402::{MixedAddress, Network, Scheme, TokenAmount};
+    use serde_json::json;
+    use solana_pubkey::Pubkey;
+
+    use super::*;
+
+    fn make_requirements(resource: &str, product: &str, amount: &str) -> PaymentRequirements {
+        PaymentRequirements {
+            scheme: Scheme::Exact,
+            network: Network::Solana,
+            max_amount_required: TokenAmount(amount.to_string()),
+            resource: resource.parse().unwrap(),
+            description: "Payment for test".to_string(),
+            mime_type: "application/json".to_string(),
+            output_schema: None,
+            pay_to: MixedAddress::Solana(Pubkey::new_from_array([1; 32])),
+            max_timeout_seconds: 300,
+            asset: MixedAddress::Solana(Pubkey::new_from_array([2; 32])),
+            extra: Some(json!({ "product": product })),
+        }
+    }
+
+    const NOW: i64 = 1_700_000_000;
+
+    #[test]
+    fn test_issued_quote_verifies() {
+        let signer = QuoteSigner::random();
+        let reqs = make_requirements("http://localhost/products/basic/access", "basic", "1000");
+        let quote = signer.issue("get", &reqs, NOW);
+
+        assert_eq!(quote.method, "GET");
+        assert_eq!(quote.expires_at, NOW + 300);
+        assert_eq!(signer.verify(&quote, "GET", &reqs, NOW + 10), Ok(()));
+    }
+
+    #[test]
+    fn test_expired_quote_is_rejected() {
+        let signer = QuoteSigner::random();
+        let reqs = make_requirements("http://localhost/products/basic/access", "basic", "1000");
+        let quote = signer.issue("GET", &reqs, NOW);
+
+        assert_eq!(
+            signer.verify(&quote, "GET", &reqs, NOW + 301),
+            Err(QuoteError::Expired(NOW + 300))
+        );
+    }
+
+    #[test]
+    fn test_quote_is_bound_to_resource() {
+        let signer = QuoteSigner::random();
+        let cheap = make_requirements("http://localhost/products/basic/access", "basic", "1000");
+        let expensive = make_requirements("http://localhost/products/pro/access", "basic", "1000");
+        let quote = signer.issue("GET", &cheap, NOW);
+
+        assert_eq!(
+            signer.verify(&quote, "GET", &expensive, NOW),
+            Err(QuoteError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_quote_is_bound_to_product_and_amount() {
+        let signer = QuoteSigner::random();
+        let cheap = make_requirements(
+            "http://localhost/payment_intents/pi_1/confirm",
+            "basic",
+            "1000",
+        );
+        let other_product = make_requirements(
+            "http://localhost/payment_intents/pi_1/confirm",
+            "pro",
+            "1000",
+        );
+        let other_amount = make_requirements(
+            "http://localhost/payment_intents/pi_1/confirm",
+            "basic",
+            "9000",
+        );
+        let quote = signer.issue("POST", &cheap, NOW);
+
+        assert_eq!(
+            signer.verify(&quote, "POST", &other_product, NOW),
+            Err(QuoteError::InvalidSignature)
+        );
+        assert_eq!(
+            signer.verify(&quote, "POST", &other_amount, NOW),
+            Err(QuoteError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_quote_is_bound_to_method() {
+        let signer = QuoteSigner::random();
+        let reqs = make_requirements("http://localhost/products/basic/access", "basic", "1000");
+        let quote = signer.issue("GET", &reqs, NOW);
+
+        assert_eq!(
+            signer.verify(&quote, "POST", &reqs, NOW),
+            Err(QuoteError::MethodMismatch("GET".into(), "POST".into()))
+        );
+    }
+
+    #[test]
+    fn test_quote_from_other_signer_is_rejected() {
+        let reqs = make_requirements("http://localhost/products/basic/access", "basic", "1000");
+        let quote = QuoteSigner::random().issue("GET", &reqs, NOW);
+
+        assert_eq!(
+            QuoteSigner::random().verify(&quote, "GET", &reqs, NOW),
+            Err(QuoteError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_shared_secret_signers_agree() {
+        let reqs = make_requirements("http://localhost/products/basic/access", "basic", "1000");
+        let quote = QuoteSigner::from_secret(b"shared").issue("GET", &reqs, NOW);
+
+        assert_eq!(
+            QuoteSigner::from_secret(b"shared").verify(&quote, "GET", &reqs, NOW),
+            Ok(())
+        );
+    }
+}
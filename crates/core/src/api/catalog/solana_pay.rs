@@ -0,0 +1,681 @@
+//! Solana Pay transfer requests
+//!
+//! Payment intents, and the checkout sessions and invoices they charge, can be paid from
+//! any wallet with a Solana Pay `solana:` transfer request, outside x402. Each intent has
+//! its own random `reference` key that the wallet adds to the transfer, stored in the
+//! payment database so open invoices keep theirs across restarts.
+//!
+//! The watcher looks up the transactions mentioning the reference of every pending intent
+//! and checks that they mention no other reference and moved at least the amount due of
+//! the requested mint to the payout address. The intent then succeeds like a confirmed one
+//! (paying its checkout session or invoice) and the settlement events and receipt are
+//! emitted as for x402 payments. The transaction's signature is recorded with the payment,
+//! so a transaction pays a single intent.
+//!
+//! Transfers are requested in an accepted stablecoin tracking the intent's currency. There
+//! are none for other currencies: the amount is only checked once the transfer lands, so an
+//! exchange rate couldn't be locked when the request is made.
+
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use moneymq_types::{defaults, x402::Network};
+use serde_json::{Value, json};
+use solana_client::rpc_request::RpcRequest;
+use solana_keypair::{Keypair, Signer};
+use solana_pubkey::Pubkey;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::{
+    api::{
+        catalog::{
+            CatalogState,
+            checkout_page::format_decimal,
+            stripe::{
+                endpoints::{
+                    invoices::load_open_invoice_payment_intents,
+                    payment_intents::record_payment_intent_success,
+                },
+                types::{
+                    CaptureMethod, PaymentIntentStatus, SolanaPayTransfer, StripePaymentIntent,
+                },
+                utils::generate_stripe_id,
+            },
+        },
+        payment::{
+            PaymentApiConfig,
+            db::solana_pay_payment::NewSolanaPayPayment,
+            endpoints::{
+                channels::{ChannelEvent, PaymentSettledData},
+                jwt::PaymentReceiptClaims,
+            },
+            rpc::RpcPool,
+        },
+    },
+    events::{CloudEvent, PaymentFlow, PaymentSettlementSucceededData, TransactionCompletedData},
+};
+
+/// Default interval between two lookups of the pending payment intents' references
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// New random reference key, the public key of a throwaway keypair
+pub fn new_reference() -> Pubkey {
+    Keypair::new().pubkey()
+}
+
+/// Reference key of a payment intent's transfer requests, generated once and stored in the
+/// payment database
+fn payment_reference(state: &CatalogState, payment_intent_id: &str) -> Option<String> {
+    let reference = new_reference().to_string();
+    let Some(db) = state.payment_db.as_ref() else {
+        return Some(reference);
+    };
+    let new_payment = NewSolanaPayPayment::new(
+        payment_intent_id.to_string(),
+        reference,
+        state.payment_stack_id.clone(),
+        state.use_sandbox,
+    );
+    match db.insert_solana_pay_reference(&new_payment) {
+        Ok(payment) => Some(payment.reference),
+        Err(e) => {
+            error!(
+                "Failed to store Solana Pay reference of {}: {}",
+                payment_intent_id, e
+            );
+            None
+        }
+    }
+}
+
+/// Solana Pay transfer request URL for `amount` cents of `spl_token` to `recipient`
+pub fn transfer_request_url(
+    recipient: &str,
+    amount: i64,
+    spl_token: &str,
+    reference: &str,
+    label: Option<&str>,
+    message: Option<&str>,
+) -> String {
+    let mut url = format!(
+        "solana:{}?amount={}&spl-token={}&reference={}",
+        recipient,
+        format_decimal(amount),
+        spl_token,
+        reference
+    );
+    if let Some(label) = label {
+        url.push_str(&format!("&label={}", urlencoding::encode(label)));
+    }
+    if let Some(message) = message {
+        url.push_str(&format!("&message={}", urlencoding::encode(message)));
+    }
+    url
+}
+
+/// Payout address and token transfer requests are paid with
+struct TransferTarget {
+    recipient: String,
+    mint: String,
+    decimals: u8,
+}
+
+impl TransferTarget {
+    /// Target of transfers paying in `currency`, when an accepted stablecoin tracks it
+    fn of(state: &CatalogState, currency: &str) -> Option<Self> {
+        let network_config = state
+            .networks_config
+            .get_config_for_network(&Network::Solana)?;
+        let currency = network_config
+            .currencies()
+            .iter()
+            .find(|stablecoin| stablecoin.fiat_currency().eq_ignore_ascii_case(currency))?;
+        Some(Self {
+            recipient: network_config.recipient().address().to_string(),
+            mint: currency.address().to_string(),
+            decimals: currency.decimals(),
+        })
+    }
+
+    /// `cents` in base units of the token
+    fn token_amount(&self, cents: i64) -> anyhow::Result<u64> {
+        10_u64
+            .checked_pow((self.decimals as u32).saturating_sub(2))
+            .and_then(|scale| (cents.max(0) as u64).checked_mul(scale))
+            .ok_or_else(|| {
+                anyhow::anyhow!("{} cents overflow the base units of {}", cents, self.mint)
+            })
+    }
+}
+
+/// Transfer request paying `amount` cents of `currency` to a payment intent, when Solana
+/// payments in that currency are configured
+pub(crate) fn transfer_request(
+    state: &CatalogState,
+    payment_intent_id: &str,
+    amount: i64,
+    currency: &str,
+    message: Option<&str>,
+) -> Option<SolanaPayTransfer> {
+    let target = TransferTarget::of(state, currency)?;
+    let reference = payment_reference(state, payment_intent_id)?;
+    let label = state
+        .stack_name
+        .as_deref()
+        .or(state.catalog_name.as_deref());
+    Some(SolanaPayTransfer {
+        url: transfer_request_url(
+            &target.recipient,
+            amount,
+            &target.mint,
+            &reference,
+            label,
+            message,
+        ),
+        reference,
+        recipient: target.recipient,
+        amount: format_decimal(amount),
+        spl_token: target.mint,
+        signature: None,
+    })
+}
+
+/// Transfer request of a new payment intent; manual capture intents have none since a
+/// plain transfer can't be held until capture
+pub(crate) fn payment_intent_transfer_request(
+    state: &CatalogState,
+    payment_intent: &StripePaymentIntent,
+) -> Option<SolanaPayTransfer> {
+    if payment_intent.capture_method == CaptureMethod::Manual {
+        return None;
+    }
+    transfer_request(
+        state,
+        &payment_intent.id,
+        payment_intent.amount,
+        &payment_intent.currency,
+        payment_intent.description.as_deref(),
+    )
+}
+
+fn is_pending(payment_intent: &StripePaymentIntent) -> bool {
+    payment_intent.solana_pay.is_some()
+        && matches!(
+            payment_intent.status,
+            PaymentIntentStatus::RequiresPaymentMethod | PaymentIntentStatus::RequiresConfirmation
+        )
+}
+
+/// Transfer paying a payment intent
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Transfer {
+    signature: String,
+    payer: String,
+    /// Base units of the token received by the payout address
+    amount: u64,
+}
+
+/// Base units of `mint` received by `recipient` in a transaction (`json` encoding)
+fn received_amount(transaction: &Value, recipient: &str, mint: &str) -> u64 {
+    let Some(meta) = transaction.get("meta") else {
+        return 0;
+    };
+    let balances = |field: &str| {
+        meta.get(field)
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter(|balance| balance["owner"] == recipient && balance["mint"] == mint)
+            .filter_map(|balance| {
+                Some((
+                    balance["accountIndex"].as_u64()?,
+                    balance["uiTokenAmount"]["amount"]
+                        .as_str()?
+                        .parse::<u64>()
+                        .ok()?,
+                ))
+            })
+            .collect::<HashMap<_, _>>()
+    };
+    let (pre, post) = (balances("preTokenBalances"), balances("postTokenBalances"));
+    post.iter()
+        .map(|(index, after)| after.saturating_sub(pre.get(index).copied().unwrap_or(0)))
+        .sum()
+}
+
+/// Transfer of at least `amount` base units of `mint` to `recipient` made by a successful
+/// transaction (`json` encoding) that mentions a single one of `references`
+fn verify_transfer(
+    signature: &str,
+    transaction: &Value,
+    references: &HashSet<String>,
+    recipient: &str,
+    mint: &str,
+    amount: u64,
+) -> Option<Transfer> {
+    if !transaction["meta"]["err"].is_null() {
+        return None;
+    }
+    // A transfer can't pay several payment intents at once
+    let account_keys = transaction["transaction"]["message"]["accountKeys"].as_array()?;
+    let mentioned = account_keys
+        .iter()
+        .filter_map(Value::as_str)
+        .filter(|key| references.contains(*key))
+        .count();
+    if mentioned != 1 {
+        return None;
+    }
+    let received = received_amount(transaction, recipient, mint);
+    if received < amount {
+        return None;
+    }
+    // The wallet paying the transfer signs it as fee payer
+    let payer = account_keys.first()?.as_str()?;
+    Some(Transfer {
+        signature: signature.to_string(),
+        payer: payer.to_string(),
+        amount: received,
+    })
+}
+
+/// Confirmed transactions mentioning `reference` that pay `amount` base units, oldest
+/// first
+async fn find_transfers(
+    pool: &RpcPool,
+    reference: &Pubkey,
+    references: &HashSet<String>,
+    target: &TransferTarget,
+    amount: u64,
+) -> anyhow::Result<Vec<Transfer>> {
+    let params = json!([reference.to_string(), { "commitment": "confirmed" }]);
+    let signatures: Vec<Value> = pool
+        .call("getSignaturesForAddress", |rpc_client| {
+            let params = params.clone();
+            async move {
+                Ok(rpc_client
+                    .send(RpcRequest::GetSignaturesForAddress, params)
+                    .await?)
+            }
+        })
+        .await?;
+
+    // Signatures come newest first
+    let mut transfers = Vec::new();
+    for status in signatures.iter().rev() {
+        let Some(signature) = status["signature"].as_str() else {
+            continue;
+        };
+        if !status["err"].is_null() {
+            continue;
+        }
+        let params = json!([signature, {
+            "encoding": "json",
+            "commitment": "confirmed",
+            "maxSupportedTransactionVersion": 0,
+        }]);
+        let transaction: Value = pool
+            .call("getTransaction", |rpc_client| {
+                let params = params.clone();
+                async move { Ok(rpc_client.send(RpcRequest::GetTransaction, params).await?) }
+            })
+            .await?;
+        transfers.extend(verify_transfer(
+            signature,
+            &transaction,
+            references,
+            &target.recipient,
+            &target.mint,
+            amount,
+        ));
+    }
+    Ok(transfers)
+}
+
+/// Record `transfer` as paying the payment intent of `reference`. Returns false when its
+/// transaction already paid a payment intent, or the reference was already paid.
+fn claim_transfer(state: &CatalogState, reference: &str, transfer: &Transfer) -> bool {
+    let reused = state
+        .payment_intents
+        .lock()
+        .unwrap()
+        .values()
+        .filter_map(|payment_intent| payment_intent.solana_pay.as_ref())
+        .any(|solana_pay| solana_pay.signature.as_ref() == Some(&transfer.signature));
+    if reused {
+        return false;
+    }
+    let Some(db) = state.payment_db.as_ref() else {
+        return true;
+    };
+    match db.record_solana_pay_signature(reference, &transfer.signature) {
+        Ok(recorded) => recorded,
+        Err(e) => {
+            error!(
+                "Failed to record Solana Pay transaction {}: {}",
+                transfer.signature, e
+            );
+            false
+        }
+    }
+}
+
+/// Mark a payment intent paid by `transfer` and emit the settlement events and receipt
+fn complete_payment(
+    state: &CatalogState,
+    config: &PaymentApiConfig,
+    payment_intent_id: &str,
+    reference: &str,
+    transfer: &Transfer,
+) {
+    let payment_intent = {
+        let mut payment_intents = state.payment_intents.lock().unwrap();
+        let Some(payment_intent) = payment_intents.get_mut(payment_intent_id) else {
+            return;
+        };
+        if !is_pending(payment_intent) {
+            return;
+        }
+        payment_intent.status = PaymentIntentStatus::Succeeded;
+        payment_intent.amount_received = payment_intent.amount;
+        payment_intent.latest_charge = Some(generate_stripe_id("ch"));
+        if let Some(solana_pay) = payment_intent.solana_pay.as_mut() {
+            solana_pay.signature = Some(transfer.signature.clone());
+        }
+        payment_intent.clone()
+    };
+    info!(
+        payment_intent = %payment_intent_id,
+        signature = %transfer.signature,
+        "Payment intent paid with Solana Pay"
+    );
+    record_payment_intent_success(state, payment_intent_id);
+
+    let now = chrono::Utc::now().timestamp();
+    let amount = transfer.amount.to_string();
+    let currency = payment_intent.currency.to_uppercase();
+    let network = format!("{:?}", Network::Solana);
+    let product_id = payment_intent.metadata.get("product_id").cloned();
+
+    state.record_event(
+        CloudEvent::PaymentSettlementSucceeded(PaymentSettlementSucceededData {
+            payer: transfer.payer.clone(),
+            amount: amount.clone(),
+            network: network.clone(),
+            transaction_signature: Some(transfer.signature.clone()),
+            product_id: product_id.clone(),
+            payment_flow: PaymentFlow::SolanaPay {
+                intent_id: payment_intent_id.to_string(),
+                reference: reference.to_string(),
+            },
+            transaction_id: Some(transfer.signature.clone()),
+        }),
+        now,
+    );
+
+    // Payout dashboards track balance updates on the recipient's channel
+    if let (Some(channel_manager), Some(recipient)) =
+        (&config.channel_manager, &config.payout_recipient_address)
+    {
+        channel_manager.publish(
+            recipient,
+            ChannelEvent::payment_settled(PaymentSettledData {
+                payer: transfer.payer.clone(),
+                amount: amount.clone(),
+                currency: currency.clone(),
+                network: network.clone(),
+                transaction_signature: Some(transfer.signature.clone()),
+                product_id: product_id.clone(),
+            }),
+        );
+    }
+
+    let Some(jwt_key_pair) = &config.jwt_key_pair else {
+        info!("No JWT key pair configured, skipping Solana Pay receipt");
+        return;
+    };
+    let claims = PaymentReceiptClaims::new(
+        transfer.signature.clone(),
+        transfer.payer.clone(),
+        amount.clone(),
+        currency.clone(),
+        network.clone(),
+        product_id.clone(),
+        None,
+        Some(transfer.signature.clone()),
+        state.payment_stack_id.clone(),
+        defaults::JWT_EXPIRATION_HOURS,
+    );
+    match jwt_key_pair.sign(&claims) {
+        Ok(receipt) => state.record_event(
+            CloudEvent::TransactionCompleted(TransactionCompletedData {
+                transaction_id: transfer.signature.clone(),
+                receipt,
+                payer: transfer.payer.clone(),
+                amount,
+                currency,
+                network,
+                transaction_signature: Some(transfer.signature.clone()),
+                product_id,
+            }),
+            now,
+        ),
+        Err(e) => error!("Failed to sign Solana Pay receipt JWT: {}", e),
+    }
+}
+
+/// Look up the transfers of every pending payment intent once
+pub async fn run_watch_round(state: &CatalogState, config: &PaymentApiConfig, pool: &RpcPool) {
+    // Invoice payment intents are only kept in memory
+    load_open_invoice_payment_intents(state);
+
+    let (references, pending) = {
+        let payment_intents = state.payment_intents.lock().unwrap();
+        let references = payment_intents
+            .values()
+            .filter_map(|payment_intent| payment_intent.solana_pay.as_ref())
+            .map(|solana_pay| solana_pay.reference.clone())
+            .collect::<HashSet<_>>();
+        let pending = payment_intents
+            .values()
+            .filter(|payment_intent| is_pending(payment_intent))
+            .filter_map(|payment_intent| {
+                Some((
+                    payment_intent.id.clone(),
+                    payment_intent.amount,
+                    payment_intent.currency.clone(),
+                    payment_intent.solana_pay.as_ref()?.reference.clone(),
+                ))
+            })
+            .collect::<Vec<_>>();
+        (references, pending)
+    };
+
+    for (payment_intent_id, amount, currency, reference) in pending {
+        let (Some(target), Ok(reference_key)) = (
+            TransferTarget::of(state, &currency),
+            Pubkey::from_str(&reference),
+        ) else {
+            continue;
+        };
+        let transfers = match target.token_amount(amount) {
+            Ok(amount) => find_transfers(pool, &reference_key, &references, &target, amount).await,
+            Err(e) => Err(e),
+        };
+        match transfers {
+            Ok(transfers) => {
+                if let Some(transfer) = transfers
+                    .iter()
+                    .find(|transfer| claim_transfer(state, &reference, transfer))
+                {
+                    complete_payment(state, config, &payment_intent_id, &reference, transfer)
+                }
+            }
+            Err(e) => warn!(
+                payment_intent = %payment_intent_id,
+                "Failed to look up Solana Pay transfers: {}", e
+            ),
+        }
+    }
+}
+
+/// Spawn the background task watching for Solana Pay transfers. Returns None when no
+/// Solana network is configured.
+pub fn spawn_watcher(state: CatalogState, config: PaymentApiConfig) -> Option<JoinHandle<()>> {
+    state
+        .networks_config
+        .get_config_for_network(&Network::Solana)?;
+    let pool: Arc<RpcPool> = config
+        .facilitator_config
+        .networks
+        .iter()
+        .find(|(_, network_config)| network_config.network() == Network::Solana)
+        .and_then(|(name, _)| config.rpc_pools.get(name))?;
+
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(DEFAULT_WATCH_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            run_watch_round(&state, &config, &pool).await;
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::payment::db::DbManager;
+
+    use super::*;
+
+    const RECIPIENT: &str = "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin";
+    const MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    const PAYER: &str = "4Nd1mBQtrMJVYVfKf2PJy9NZUZdTAsp7D4xWLs4gDB4T";
+    const REFERENCE: &str = "FcdmNTeqmmcH1kBufvPk3TJNmsQCXL8xzXE8T9fZxj1j";
+
+    fn transaction(mint: &str, pre: &str, post: &str, err: Value) -> Value {
+        json!({
+            "meta": {
+                "err": err,
+                "preTokenBalances": [
+                    { "accountIndex": 1, "mint": mint, "owner": PAYER, "uiTokenAmount": { "amount": "20000000" } },
+                    { "accountIndex": 2, "mint": mint, "owner": RECIPIENT, "uiTokenAmount": { "amount": pre } }
+                ],
+                "postTokenBalances": [
+                    { "accountIndex": 1, "mint": mint, "owner": PAYER, "uiTokenAmount": { "amount": "7500000" } },
+                    { "accountIndex": 2, "mint": mint, "owner": RECIPIENT, "uiTokenAmount": { "amount": post } }
+                ]
+            },
+            "transaction": { "message": { "accountKeys": [PAYER, "ata1", "ata2", REFERENCE] } }
+        })
+    }
+
+    fn transfer(signature: &str) -> Transfer {
+        Transfer {
+            signature: signature.to_string(),
+            payer: PAYER.to_string(),
+            amount: 12_500_000,
+        }
+    }
+
+    #[test]
+    fn test_transfer_request_url() {
+        let reference = new_reference();
+        assert_ne!(reference, new_reference());
+
+        let url = transfer_request_url(
+            RECIPIENT,
+            1250,
+            MINT,
+            &reference.to_string(),
+            Some("Acme Inc"),
+            Some("Order 42"),
+        );
+        assert_eq!(
+            url,
+            format!(
+                "solana:{}?amount=12.50&spl-token={}&reference={}&label=Acme%20Inc&message=Order%2042",
+                RECIPIENT, MINT, reference
+            )
+        );
+    }
+
+    #[test]
+    fn test_verify_transfer() {
+        let references = HashSet::from([REFERENCE.to_string()]);
+        let verify = |transaction: &Value, references: &HashSet<String>, amount: u64| {
+            verify_transfer("sig", transaction, references, RECIPIENT, MINT, amount)
+        };
+        let paid = transaction(MINT, "1000", "12501000", Value::Null);
+        assert_eq!(
+            verify(&paid, &references, 12_500_000),
+            Some(transfer("sig"))
+        );
+        // Short, wrong mint or failed transfers don't pay the intent
+        assert!(verify(&paid, &references, 12_500_001).is_none());
+        let other_mint = transaction(PAYER, "0", "12500000", Value::Null);
+        assert!(verify(&other_mint, &references, 12_500_000).is_none());
+        let failed = transaction(
+            MINT,
+            "0",
+            "12500000",
+            json!({ "InstructionError": [0, "Custom"] }),
+        );
+        assert!(verify(&failed, &references, 12_500_000).is_none());
+
+        // A transfer mentioning the references of two payment intents pays neither
+        let mut both = paid.clone();
+        both["transaction"]["message"]["accountKeys"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!(RECIPIENT));
+        let references = HashSet::from([REFERENCE.to_string(), RECIPIENT.to_string()]);
+        assert!(verify(&both, &references, 12_500_000).is_none());
+    }
+
+    #[test]
+    fn test_token_amount() {
+        let target = TransferTarget {
+            recipient: RECIPIENT.to_string(),
+            mint: MINT.to_string(),
+            decimals: 6,
+        };
+        assert_eq!(target.token_amount(1250).unwrap(), 12_500_000);
+        assert_eq!(target.token_amount(-5).unwrap(), 0);
+        // Amounts past the range of base units are an error rather than a wrapped amount
+        assert!(target.token_amount(i64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_transactions_pay_a_single_payment_intent() {
+        let state = CatalogState::new(
+            Vec::new(),
+            Vec::new(),
+            true,
+            "http://localhost:8488".parse().unwrap(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+        .with_payment_db(Arc::new(DbManager::local(":memory:").unwrap()), "test");
+
+        // References are random, and stored so they're kept across restarts
+        let first = payment_reference(&state, "pi_1").unwrap();
+        assert_eq!(payment_reference(&state, "pi_1").unwrap(), first);
+        let second = payment_reference(&state, "pi_2").unwrap();
+        assert_ne!(first, second);
+
+        assert!(claim_transfer(&state, &first, &transfer("sig_1")));
+        // The transaction was used by the first intent, which was already paid
+        assert!(!claim_transfer(&state, &second, &transfer("sig_1")));
+        assert!(!claim_transfer(&state, &first, &transfer("sig_2")));
+        assert!(claim_transfer(&state, &second, &transfer("sig_2")));
+    }
+}
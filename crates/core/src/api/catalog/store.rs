@@ -0,0 +1,428 @@
+//! Products and prices edited through the API
+//!
+//! Products and prices created or updated with `POST /v1/products` and `POST /v1/prices`
+//! are persisted in the catalog DB. When the catalog has a [`CatalogWriter`], they are also
+//! written back to the catalog files, which stay the source of truth and are picked up by
+//! the next reload. Otherwise the stored products are overlaid onto the products loaded
+//! from the catalog files, so edits survive restarts and reloads.
+//!
+//! The catalog DB has no columns for features, currency options, overages and tax
+//! behavior: stored products keep the ones of the catalog files.
+
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::DateTime;
+use indexmap::IndexMap;
+use moneymq_types::{Currency, Decimal, Price, PricingType, Product, RecurringInterval, TiersMode};
+use tracing::error;
+
+use crate::api::catalog::{
+    CatalogState,
+    db::{CatalogDbManager, DbError, PriceModel, PriceRecord, ProductModel},
+};
+
+/// Writes products edited through the API back to the catalog files
+pub trait CatalogWriter: Send + Sync {
+    /// Add `product`, with its prices, to the catalog files
+    fn create_product(&self, product: &Product) -> Result<(), String>;
+
+    /// Update the catalog file `product` was loaded from
+    fn update_product(&self, product: &Product) -> Result<(), String>;
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum StoreError {
+    #[error("Editing the catalog requires a database")]
+    NotConfigured,
+    #[error("Failed to store product: {0}")]
+    Db(#[from] DbError),
+    #[error("Failed to write the catalog files: {0}")]
+    Write(String),
+}
+
+impl IntoResponse for StoreError {
+    fn into_response(self) -> Response {
+        let (status, code) = match &self {
+            StoreError::NotConfigured => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "catalog_writes_not_configured",
+            ),
+            StoreError::Db(_) | StoreError::Write(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "api_error")
+            }
+        };
+        (
+            status,
+            Json(serde_json::json!({
+                "error": {
+                    "code": code,
+                    "message": self.to_string(),
+                    "type": "api_error"
+                }
+            })),
+        )
+            .into_response()
+    }
+}
+
+/// Persist `product` and its prices, writing it back to the catalog files first when the
+/// catalog has a writer
+pub(crate) fn save_product(
+    state: &CatalogState,
+    product: &Product,
+    created: bool,
+) -> Result<(), StoreError> {
+    let db = state.catalog_db.as_ref().ok_or(StoreError::NotConfigured)?;
+
+    if let Some(writer) = &state.catalog_writer {
+        let written = if created {
+            writer.create_product(product)
+        } else {
+            writer.update_product(product)
+        };
+        written.map_err(StoreError::Write)?;
+    }
+
+    let metadata = metadata_json(&product.metadata);
+    let prices = product.prices.iter().map(price_record).collect();
+    db.sync_product_with_prices(
+        &state.payment_stack_id,
+        &product.id,
+        product.name.as_deref().unwrap_or(&product.id),
+        product.description.as_deref(),
+        product.product_type.as_deref().unwrap_or("service"),
+        product.unit_label.as_deref(),
+        product.active,
+        metadata.as_deref(),
+        state.use_sandbox,
+        prices,
+    )?;
+    Ok(())
+}
+
+fn price_record(price: &Price) -> PriceRecord {
+    PriceRecord {
+        price_id: Some(price.id.clone()),
+        pricing_type: price.pricing_type.as_str().to_string(),
+        currency: price.currency.as_str().to_string(),
+        unit_amount: price.unit_amount.unwrap_or(0),
+        unit_amount_decimal: price.unit_amount_decimal.map(|amount| amount.to_string()),
+        tiers: (!price.tiers.is_empty())
+            .then(|| serde_json::to_string(&price.tiers).ok())
+            .flatten(),
+        tiers_mode: price.tiers_mode.map(|mode| mode.as_str().to_string()),
+        transform_quantity: price
+            .transform_quantity
+            .and_then(|transform| serde_json::to_string(&transform).ok()),
+        recurring_interval: price
+            .recurring_interval
+            .map(|interval| interval.as_str().to_string()),
+        recurring_interval_count: price.recurring_interval_count.map(|count| count as i32),
+        active: price.active,
+        metadata: metadata_json(&price.metadata),
+    }
+}
+
+fn metadata_json(metadata: &IndexMap<String, String>) -> Option<String> {
+    if metadata.is_empty() {
+        return None;
+    }
+    serde_json::to_string(metadata).ok()
+}
+
+fn metadata_from_json(metadata: Option<&str>) -> IndexMap<String, String> {
+    metadata
+        .and_then(|metadata| serde_json::from_str(metadata).ok())
+        .unwrap_or_default()
+}
+
+fn timestamp(millis: i64) -> DateTime<chrono::Utc> {
+    DateTime::from_timestamp_millis(millis).unwrap_or_else(chrono::Utc::now)
+}
+
+fn price_from_model(model: PriceModel) -> Option<Price> {
+    let currency = Currency::parse(&model.currency)?;
+    let pricing_type = PricingType::parse(&model.pricing_type).unwrap_or(PricingType::OneTime);
+    let tiers = model
+        .tiers
+        .as_deref()
+        .and_then(|tiers| serde_json::from_str(tiers).ok())
+        .unwrap_or_default();
+    let mut price = Price::new(currency, pricing_type)
+        .with_tiers(
+            tiers,
+            model.tiers_mode.as_deref().and_then(TiersMode::parse),
+        )
+        .with_transform_quantity(
+            model
+                .transform_quantity
+                .as_deref()
+                .and_then(|transform| serde_json::from_str(transform).ok()),
+        )
+        .with_some_interval(
+            model
+                .recurring_interval
+                .as_deref()
+                .and_then(RecurringInterval::parse),
+        )
+        .with_some_interval_count(model.recurring_interval_count.map(i64::from));
+    // Tiered prices have no unit amount, decimal amounts are stored apart
+    let decimal = model
+        .unit_amount_decimal
+        .as_deref()
+        .and_then(|amount| amount.parse::<Decimal>().ok());
+    if let Some(amount) = decimal {
+        price = price.with_exact_amount(amount);
+    } else if price.tiers.is_empty() {
+        price = price.with_some_amount(Some(model.unit_amount));
+    }
+    price.id = model
+        .price_id
+        .unwrap_or_else(|| format!("price_{}", model.id));
+    price.active = model.active;
+    price.metadata = metadata_from_json(model.metadata.as_deref());
+    price.created_at = timestamp(model.created_at);
+    Some(price)
+}
+
+fn product_from_model(model: ProductModel, mut prices: Vec<PriceModel>) -> Product {
+    let mut product = Product::new()
+        .with_some_name(Some(model.name))
+        .with_some_description(model.description)
+        .with_some_product_type(Some(model.product_type))
+        .with_some_unit_label(model.unit_label);
+    product.id = model.product_id;
+    product.active = model.active;
+    product.metadata = metadata_from_json(model.metadata.as_deref());
+    product.created_at = timestamp(model.created_at);
+    product.updated_at = Some(timestamp(model.updated_at));
+    // In the order they were stored
+    prices.sort_by_key(|price| price.id);
+    product.prices = prices.into_iter().filter_map(price_from_model).collect();
+    product
+}
+
+/// Products stored in the catalog DB for a payment stack
+pub fn stored_products(
+    db: &CatalogDbManager,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> Result<Vec<Product>, DbError> {
+    let mut models = db.list_products(payment_stack_id, is_sandbox, false)?;
+    models.sort_by_key(|model| model.id);
+    models
+        .into_iter()
+        .map(|model| {
+            let prices = db.list_prices(model.id, false)?;
+            Ok(product_from_model(model, prices))
+        })
+        .collect()
+}
+
+/// Apply the stored products onto the products of the catalog files: stored fields
+/// override the files' ones, and products and prices only stored are added
+pub fn overlay_products(products: &mut Vec<Product>, stored: Vec<Product>) {
+    for stored in stored {
+        let Some(product) = products.iter_mut().find(|p| p.id == stored.id) else {
+            products.push(stored);
+            continue;
+        };
+        product.name = stored.name;
+        product.description = stored.description;
+        product.product_type = stored.product_type;
+        product.unit_label = stored.unit_label;
+        product.active = stored.active;
+        product.metadata = stored.metadata;
+        product.updated_at = stored.updated_at;
+
+        for stored_price in stored.prices {
+            match product.prices.iter_mut().find(|p| p.id == stored_price.id) {
+                Some(price) => {
+                    price.active = stored_price.active;
+                    price.currency = stored_price.currency;
+                    price.unit_amount = stored_price.unit_amount;
+                    price.unit_amount_decimal = stored_price.unit_amount_decimal;
+                    price.tiers = stored_price.tiers;
+                    price.tiers_mode = stored_price.tiers_mode;
+                    price.transform_quantity = stored_price.transform_quantity;
+                    price.pricing_type = stored_price.pricing_type;
+                    price.recurring_interval = stored_price.recurring_interval;
+                    price.recurring_interval_count = stored_price.recurring_interval_count;
+                    price.metadata = stored_price.metadata;
+                }
+                None => product.prices.push(stored_price),
+            }
+        }
+    }
+}
+
+/// Overlay the products stored for `state`'s payment stack onto `products`
+pub(crate) fn overlay_stored_products(state: &CatalogState, products: &mut Vec<Product>) {
+    let Some(db) = state.catalog_db.as_ref() else {
+        return;
+    };
+    match stored_products(db, &state.payment_stack_id, state.use_sandbox) {
+        Ok(stored) => overlay_products(products, stored),
+        Err(e) => error!("Failed to load stored products: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use moneymq_types::{PriceTier, QuantityRounding, TransformQuantity};
+
+    use super::*;
+
+    fn product(id: &str, amount: i64) -> Product {
+        let mut price =
+            Price::new(Currency::Usd, PricingType::OneTime).with_some_amount(Some(amount));
+        price.id = format!("price_{}", id);
+        let mut product = Product::new()
+            .with_some_name(Some(id.to_string()))
+            .add_price(price);
+        product.id = id.to_string();
+        product
+    }
+
+    #[test]
+    fn test_overlay_products() {
+        let mut products = vec![product("pro", 4900)];
+        products[0].unit_label = Some("seat".to_string());
+
+        let mut edited = product("pro", 5900);
+        edited.name = Some("Pro (2026)".to_string());
+        edited.prices[0].active = false;
+        let mut added =
+            Price::new(Currency::Eur, PricingType::OneTime).with_some_amount(Some(5400));
+        added.id = "price_pro_eur".to_string();
+        edited.prices.push(added);
+
+        overlay_products(&mut products, vec![edited, product("team", 9900)]);
+
+        assert_eq!(products.len(), 2);
+        let pro = &products[0];
+        assert_eq!(pro.name.as_deref(), Some("Pro (2026)"));
+        assert_eq!(pro.unit_label, None);
+        assert_eq!(pro.prices.len(), 2);
+        assert!(!pro.prices[0].active);
+        assert_eq!(pro.prices[0].unit_amount, Some(5900));
+        assert_eq!(pro.prices[1].currency, Currency::Eur);
+        assert_eq!(products[1].id, "team");
+    }
+
+    #[test]
+    fn test_store_and_load_products() {
+        let db = CatalogDbManager::new(":memory:").unwrap();
+        let mut state = CatalogState::new(
+            Vec::new(),
+            Vec::new(),
+            true,
+            "http://localhost:8488".parse().unwrap(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+        .with_catalog_db(std::sync::Arc::new(db));
+        state.payment_stack_id = "test".to_string();
+
+        let mut pro = product("pro", 4900);
+        pro.metadata.insert("tier".to_string(), "2".to_string());
+        pro.prices[0].recurring_interval = Some(RecurringInterval::Month);
+        save_product(&state, &pro, true).unwrap();
+
+        let stored = stored_products(state.catalog_db.as_ref().unwrap(), "test", true).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].id, "pro");
+        assert_eq!(
+            stored[0].metadata.get("tier").map(String::as_str),
+            Some("2")
+        );
+        assert_eq!(stored[0].prices[0].id, "price_pro");
+        assert_eq!(stored[0].prices[0].unit_amount, Some(4900));
+        assert_eq!(
+            stored[0].prices[0].recurring_interval,
+            Some(RecurringInterval::Month)
+        );
+        assert!(
+            stored_products(state.catalog_db.as_ref().unwrap(), "test", false)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_store_decimal_and_tiered_prices() {
+        let db = CatalogDbManager::new(":memory:").unwrap();
+        let state = CatalogState::new(
+            Vec::new(),
+            Vec::new(),
+            true,
+            "http://localhost:8488".parse().unwrap(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+        .with_catalog_db(std::sync::Arc::new(db));
+
+        // $0.001 per call, and $0.02 then $0.01 per call by packages of 10
+        let mut api = product("api", 0);
+        api.prices[0] =
+            Price::new(Currency::Usd, PricingType::OneTime).with_exact_amount(Decimal::new(1, 1));
+        api.prices[0].id = "price_api".to_string();
+        let mut tiered = Price::new(Currency::Usd, PricingType::OneTime)
+            .with_tiers(
+                vec![
+                    PriceTier {
+                        up_to: Some(1000),
+                        unit_amounts: IndexMap::from([("usd".to_string(), 2)]),
+                        flat_amounts: IndexMap::new(),
+                    },
+                    PriceTier {
+                        up_to: None,
+                        unit_amounts: IndexMap::from([("usd".to_string(), 1)]),
+                        flat_amounts: IndexMap::new(),
+                    },
+                ],
+                Some(TiersMode::Graduated),
+            )
+            .with_transform_quantity(Some(TransformQuantity {
+                divide_by: 10,
+                round: QuantityRounding::Up,
+            }));
+        tiered.id = "price_api_tiered".to_string();
+        api.prices.push(tiered.clone());
+        save_product(&state, &api, true).unwrap();
+
+        let stored = stored_products(state.catalog_db.as_ref().unwrap(), "local", true).unwrap();
+        let prices = &stored[0].prices;
+        assert_eq!(prices[0].unit_amount, None);
+        assert_eq!(prices[0].unit_amount_decimal, Some(Decimal::new(1, 1)));
+        assert_eq!(prices[0].amount_for(1000, "usd"), Some(100));
+        assert_eq!(prices[1].unit_amount, None);
+        assert_eq!(prices[1].tiers, tiered.tiers);
+        assert_eq!(prices[1].tiers_mode, Some(TiersMode::Graduated));
+        assert_eq!(prices[1].transform_quantity, tiered.transform_quantity);
+        assert_eq!(
+            prices[1].amount_for(20_000, "usd"),
+            tiered.amount_for(20_000, "usd")
+        );
+
+        // Overlaying them keeps the amounts instead of zeroing them
+        let mut products = vec![api];
+        overlay_products(&mut products, stored);
+        assert_eq!(products[0].prices[0].unit_amount, None);
+        assert_eq!(
+            products[0].prices[0].unit_amount_decimal,
+            Some(Decimal::new(1, 1))
+        );
+        assert_eq!(products[0].prices[1].unit_amount, None);
+        assert_eq!(products[0].prices[1].tiers, tiered.tiers);
+    }
+}
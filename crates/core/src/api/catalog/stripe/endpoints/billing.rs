@@ -1,48 +1,67 @@
-use axum::{Extension, Json, body::Bytes, http::StatusCode, response::IntoResponse};
+use std::collections::HashMap;
 
-use crate::api::catalog::{
-    CatalogState,
-    stripe::{
-        types::{ListParams, ListResponse, StripeBillingMeter, StripeMeterEvent},
-        utils::generate_stripe_id,
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::{Path, RawQuery},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use moneymq_types::{Meter, iac::AggregationFormula};
+use serde::Deserialize;
+use tracing::{error, info};
+
+use crate::api::{
+    catalog::{
+        Catalog, CatalogState,
+        stripe::{
+            endpoints::test_clocks::current_time,
+            form::{FormError, StripeForm, StripeQuery},
+            list::CatalogQuery,
+            types::{ListResponse, StripeBillingMeter, StripeMeterEvent, StripeMeterEventSummary},
+            utils::generate_stripe_id,
+        },
     },
+    payment::db::{DbManager, MeterEventModel, meter_event::NewMeterEvent},
 };
 
+/// Payload key holding the customer when a meter has no customer mapping
+pub const DEFAULT_CUSTOMER_PAYLOAD_KEY: &str = "stripe_customer_id";
+
+/// Payload key holding the value when a meter has no value settings
+pub const DEFAULT_VALUE_PAYLOAD_KEY: &str = "value";
+
+/// Most summary windows computed for a single request
+pub const MAX_SUMMARY_WINDOWS: i64 = 10_000;
+
 /// GET /v1/billing/meters - List billing meters
+///
+/// Meters can be filtered by `status` and `created`.
 pub async fn list_meters(
     Extension(state): Extension<CatalogState>,
-    axum::extract::Query(params): axum::extract::Query<ListParams>,
+    RawQuery(query): RawQuery,
 ) -> impl IntoResponse {
-    let limit = params.limit.unwrap_or(10).min(100) as usize;
-
-    // Find starting position
-    let start_idx = if let Some(starting_after) = params.starting_after {
-        state
-            .meters
-            .iter()
-            .position(|m| {
-                let external_id = if state.use_sandbox {
-                    m.sandboxes.get("default")
-                } else {
-                    m.deployed_id.as_ref()
-                };
-                external_id.map(|id| id == &starting_after).unwrap_or(false)
-            })
-            .map(|idx| idx + 1)
-            .unwrap_or(0)
-    } else {
-        0
+    let query = match CatalogQuery::parse(query.as_deref(), &[]) {
+        Ok(query) => query,
+        Err(e) => return e.into_response(),
     };
-
-    let end_idx = (start_idx + limit).min(state.meters.len());
-    let meters_slice = &state.meters[start_idx..end_idx];
-
-    let stripe_meters: Vec<StripeBillingMeter> = meters_slice
+    let meters = state
+        .catalog()
+        .meters
         .iter()
+        .filter(|m| {
+            query
+                .param("status")
+                .is_none_or(|status| m.status.as_deref().unwrap_or("active") == status)
+                && query.created.contains(m.created_at.timestamp())
+        })
         .map(|m| StripeBillingMeter::from_meter(m, state.use_sandbox))
-        .collect();
-
-    let has_more = end_idx < state.meters.len();
+        .collect::<Vec<_>>();
+    let (stripe_meters, has_more) = match query.paginate(meters, "billing.meter", |m| m.id.as_str())
+    {
+        Ok(page) => page,
+        Err(e) => return e.into_response(),
+    };
 
     Json(ListResponse {
         object: "list".to_string(),
@@ -53,45 +72,619 @@ pub async fn list_meters(
     .into_response()
 }
 
+/// GET /v1/billing/meters/:id - Retrieve a billing meter
+pub async fn retrieve_meter(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    RawQuery(query): RawQuery,
+) -> impl IntoResponse {
+    if let Err(e) = CatalogQuery::parse(query.as_deref(), &[]) {
+        return e.into_response();
+    }
+    let catalog = state.catalog();
+    match catalog
+        .meters
+        .iter()
+        .find(|m| m.id == id || meter_external_id(m, state.use_sandbox) == id)
+    {
+        Some(meter) => (
+            StatusCode::OK,
+            Json(StripeBillingMeter::from_meter(meter, state.use_sandbox)),
+        )
+            .into_response(),
+        None => billing_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such billing meter: '{}'", id),
+        ),
+    }
+}
+
+/// Form-encoded meter event request
+#[derive(Debug, Default)]
 pub struct BillingMeterEventRequest {
     pub event_name: Option<String>,
+    /// Unique identifier of the event, used for dedup
+    pub identifier: Option<String>,
+    /// When the usage occurred (Unix seconds)
+    pub timestamp: Option<i64>,
+    pub payload: HashMap<String, String>,
 }
 
 impl BillingMeterEventRequest {
-    pub const DEFAULT_EVENT: &str = "unknown_event";
-    pub fn parse(body: &Bytes) -> BillingMeterEventRequest {
-        let body_str = String::from_utf8_lossy(body);
-        // Extract event_name field
-        let event_name = body_str
-            .split('&')
-            .find(|part| part.starts_with("event_name="))
-            .and_then(|part| part.strip_prefix("event_name="))
-            .map(|name| urlencoding::decode(name).unwrap_or_default().to_string());
+    pub fn parse(body: &Bytes) -> Result<BillingMeterEventRequest, FormError> {
+        let form = StripeForm::decode(body)?;
+        Ok(BillingMeterEventRequest {
+            event_name: form.string("event_name")?.filter(|name| !name.is_empty()),
+            identifier: form.string("identifier")?.filter(|id| !id.is_empty()),
+            timestamp: form.integer("timestamp")?,
+            payload: form.map("payload")?,
+        })
+    }
+}
 
-        BillingMeterEventRequest { event_name }
+/// ID clients use for a meter (deployed or sandbox ID), falling back to the catalog ID
+fn meter_external_id(meter: &Meter, use_sandbox: bool) -> &str {
+    if use_sandbox {
+        meter.sandboxes.get("default")
+    } else {
+        meter.deployed_id.as_ref()
     }
+    .unwrap_or(&meter.id)
+}
+
+/// Aggregation formula declared by a meter (sum by default)
+fn meter_formula(meter: &Meter) -> AggregationFormula {
+    meter
+        .default_aggregation
+        .as_ref()
+        .and_then(|aggregation| AggregationFormula::parse(&aggregation.formula))
+        .unwrap_or(AggregationFormula::Sum)
+}
+
+/// Aggregate event values, oldest first, with a meter formula
+pub fn aggregate(formula: AggregationFormula, values: &[f64]) -> f64 {
+    match formula {
+        AggregationFormula::Sum => values.iter().sum(),
+        AggregationFormula::Count => values.len() as f64,
+        AggregationFormula::Max => values.iter().copied().reduce(f64::max).unwrap_or(0.0),
+        AggregationFormula::Last => values.last().copied().unwrap_or(0.0),
+    }
+}
+
+/// Find a catalog meter by the name a price's overage refers to it with: its ID, the
+/// ID clients use for it, or its event name
+pub(crate) fn find_meter<'a>(
+    state: &CatalogState,
+    catalog: &'a Catalog,
+    name: &str,
+) -> Option<&'a Meter> {
+    catalog.meters.iter().find(|meter| {
+        meter.id == name
+            || meter.event_name == name
+            || meter_external_id(meter, state.use_sandbox) == name
+    })
+}
+
+/// A customer's usage of a meter with `start_time <= timestamp < end_time`, aggregated
+/// with the meter's formula
+pub(crate) fn customer_usage(
+    state: &CatalogState,
+    db: &DbManager,
+    meter: &Meter,
+    customer: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<f64, String> {
+    let events = db
+        .list_customer_meter_events(
+            &meter.id,
+            customer,
+            start_time,
+            end_time,
+            &state.payment_stack_id,
+            state.use_sandbox,
+        )
+        .map_err(|e| e.to_string())?;
+    let values = events.iter().map(|event| event.value).collect::<Vec<_>>();
+    Ok(aggregate(meter_formula(meter), &values))
+}
+
+fn billing_error(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(serde_json::json!({
+            "error": {
+                "code": code,
+                "message": message.into(),
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+impl From<&MeterEventModel> for StripeMeterEvent {
+    fn from(model: &MeterEventModel) -> Self {
+        StripeMeterEvent {
+            id: format!("bmes_{}", model.id),
+            object: "billing.meter_event".to_string(),
+            event_name: model.event_name.clone(),
+            created: model.created_at / 1000,
+            identifier: Some(model.identifier.clone()),
+            timestamp: model.timestamp,
+            payload: model.payload(),
+        }
+    }
+}
+
+/// Payload key holding the customer of a meter's events
+fn meter_customer_key(meter: &Meter) -> &str {
+    meter
+        .customer_mapping
+        .as_ref()
+        .map(|mapping| mapping.event_payload_key.as_str())
+        .unwrap_or(DEFAULT_CUSTOMER_PAYLOAD_KEY)
+}
+
+/// Whether the customer of `request` already recorded an event with its identifier for
+/// the meter
+pub(crate) fn is_duplicate_meter_event(
+    state: &CatalogState,
+    meter: &Meter,
+    request: &BillingMeterEventRequest,
+) -> bool {
+    let (Some(identifier), Some(customer)) = (
+        request.identifier.as_deref(),
+        request.payload.get(meter_customer_key(meter)),
+    ) else {
+        return false;
+    };
+    state.payment_db.as_ref().is_some_and(|db| {
+        matches!(
+            db.find_meter_event(identifier, &state.payment_stack_id, state.use_sandbox),
+            Ok(Some(event)) if event.meter_id == meter.id && &event.customer == customer
+        )
+    })
 }
 
 /// POST /v1/billing/meter_events - Record a meter event
+///
+/// The customer and value are read from the payload keys declared by the meter. Events are
+/// deduplicated on `identifier`: resending one returns the event already recorded.
 pub async fn create_meter_event(
-    Extension(_state): Extension<CatalogState>,
+    Extension(state): Extension<CatalogState>,
     body: Bytes,
 ) -> impl IntoResponse {
-    let BillingMeterEventRequest { event_name } = BillingMeterEventRequest::parse(&body);
-    let event_name =
-        event_name.unwrap_or_else(|| BillingMeterEventRequest::DEFAULT_EVENT.to_string());
+    let request = match BillingMeterEventRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    let Some(event_name) = request.event_name else {
+        return FormError::Missing("event_name".to_string()).into_response();
+    };
+    let catalog = state.catalog();
+    let Some(meter) = catalog
+        .meters
+        .iter()
+        .find(|meter| meter.event_name == event_name)
+    else {
+        return billing_error(
+            StatusCode::BAD_REQUEST,
+            "resource_missing",
+            format!("No meter found for event_name '{}'", event_name),
+        );
+    };
 
-    // Generate a mock meter event ID
-    let event_id = generate_stripe_id("bmes");
-    let created = chrono::Utc::now().timestamp();
+    let customer_key = meter_customer_key(meter);
+    let Some(customer) = request.payload.get(customer_key).cloned() else {
+        return FormError::Missing(format!("payload[{}]", customer_key)).into_response();
+    };
 
-    let meter_event = StripeMeterEvent {
-        id: event_id,
-        object: "billing.meter_event".to_string(),
+    let formula = meter_formula(meter);
+    let value_key = meter
+        .value_settings
+        .as_ref()
+        .map(|settings| settings.event_payload_key.as_str())
+        .unwrap_or(DEFAULT_VALUE_PAYLOAD_KEY);
+    let value = match request.payload.get(value_key) {
+        Some(value) => match value.parse::<f64>() {
+            Ok(value) if value.is_finite() => value,
+            _ => {
+                return FormError::Invalid {
+                    param: format!("payload[{}]", value_key),
+                    message: format!("payload[{}] must be a number", value_key),
+                }
+                .into_response();
+            }
+        },
+        // Counting meters don't need a value
+        None if formula == AggregationFormula::Count => 1.0,
+        None => {
+            return FormError::Missing(format!("payload[{}]", value_key)).into_response();
+        }
+    };
+
+    let (now, _) = current_time(&state, Some(&customer));
+    let timestamp = request.timestamp.unwrap_or(now);
+    let identifier = request
+        .identifier
+        .unwrap_or_else(|| generate_stripe_id("mev"));
+
+    let Some(db) = state.payment_db.as_ref() else {
+        // Without a database the event is acknowledged but not stored
+        let meter_event = StripeMeterEvent {
+            id: generate_stripe_id("bmes"),
+            object: "billing.meter_event".to_string(),
+            event_name,
+            created: now,
+            identifier: Some(identifier),
+            timestamp,
+            payload: request.payload,
+        };
+        return (StatusCode::OK, Json(meter_event)).into_response();
+    };
+
+    let new_event = NewMeterEvent::new(
+        identifier,
+        meter.id.clone(),
         event_name,
-        created,
-        identifier: None,
+        customer,
+        value,
+        timestamp,
+        &request.payload,
+        state.payment_stack_id.clone(),
+        state.use_sandbox,
+    );
+    match db.record_meter_event(&new_event) {
+        // Identifiers are only deduplicated for the customer and meter that used them
+        Ok((meter_event, false))
+            if meter_event.meter_id != new_event.meter_id
+                || meter_event.customer != new_event.customer =>
+        {
+            billing_error(
+                StatusCode::BAD_REQUEST,
+                "idempotency_error",
+                format!(
+                    "Meter event identifier '{}' was already used for another event",
+                    meter_event.identifier
+                ),
+            )
+        }
+        Ok((meter_event, created)) => {
+            if created {
+                info!(
+                    "Recorded meter event {} for {} ({} = {})",
+                    meter_event.identifier, meter_event.customer, value_key, meter_event.value
+                );
+            } else {
+                info!("Meter event {} already recorded", meter_event.identifier);
+            }
+            (StatusCode::OK, Json(StripeMeterEvent::from(&meter_event))).into_response()
+        }
+        Err(e) => {
+            error!("Failed to record meter event: {}", e);
+            billing_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "api_error",
+                e.to_string(),
+            )
+        }
+    }
+}
+
+/// Size of the windows usage is grouped into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueGroupingWindow {
+    Hour,
+    Day,
+}
+
+impl ValueGroupingWindow {
+    fn seconds(&self) -> i64 {
+        match self {
+            ValueGroupingWindow::Hour => 60 * 60,
+            ValueGroupingWindow::Day => 24 * 60 * 60,
+        }
+    }
+}
+
+/// Query parameters of GET /v1/billing/meters/:id/event_summaries
+#[derive(Debug, Deserialize)]
+pub struct MeterEventSummaryParams {
+    pub customer: String,
+    pub start_time: i64,
+    pub end_time: i64,
+    /// Group usage into hourly or daily windows instead of one summary for the whole range
+    #[serde(default)]
+    pub value_grouping_window: Option<ValueGroupingWindow>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub starting_after: Option<String>,
+}
+
+/// Split `[start_time, end_time)` into summary windows
+fn summary_windows(
+    start_time: i64,
+    end_time: i64,
+    window: Option<ValueGroupingWindow>,
+) -> Vec<(i64, i64)> {
+    let Some(window) = window else {
+        return vec![(start_time, end_time)];
+    };
+    let step = window.seconds();
+    let mut windows = Vec::new();
+    let mut window_start = start_time;
+    while window_start < end_time {
+        let window_end = (window_start + step).min(end_time);
+        windows.push((window_start, window_end));
+        window_start = window_end;
+    }
+    windows
+}
+
+/// Aggregate events into one summary value per window
+pub fn summarize(
+    formula: AggregationFormula,
+    events: &[MeterEventModel],
+    windows: &[(i64, i64)],
+) -> Vec<f64> {
+    windows
+        .iter()
+        .map(|(start, end)| {
+            let values = events
+                .iter()
+                .filter(|event| event.timestamp >= *start && event.timestamp < *end)
+                .map(|event| event.value)
+                .collect::<Vec<_>>();
+            aggregate(formula, &values)
+        })
+        .collect()
+}
+
+/// GET /v1/billing/meters/:id/event_summaries - A customer's aggregated usage
+///
+/// Events with `start_time <= timestamp < end_time` are aggregated with the meter's
+/// formula, in a single summary or in hourly/daily windows.
+pub async fn list_meter_event_summaries(
+    Extension(state): Extension<CatalogState>,
+    Path(meter_id): Path<String>,
+    StripeQuery(params): StripeQuery<MeterEventSummaryParams>,
+) -> impl IntoResponse {
+    let catalog = state.catalog();
+    let Some(meter) = catalog.meters.iter().find(|meter| {
+        meter_external_id(meter, state.use_sandbox) == meter_id || meter.id == meter_id
+    }) else {
+        return billing_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such billing meter: '{}'", meter_id),
+        );
+    };
+    let Some(db) = state.payment_db.as_ref() else {
+        return billing_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "meter_events_not_configured",
+            "Meter event summaries require a database",
+        );
     };
 
-    (StatusCode::OK, Json(meter_event)).into_response()
+    if params.end_time <= params.start_time {
+        return billing_error(
+            StatusCode::BAD_REQUEST,
+            "parameter_invalid",
+            "end_time must be after start_time",
+        );
+    }
+    if let Some(window) = params.value_grouping_window
+        && (params.end_time - params.start_time) / window.seconds() > MAX_SUMMARY_WINDOWS
+    {
+        return billing_error(
+            StatusCode::BAD_REQUEST,
+            "parameter_invalid",
+            format!(
+                "The time range spans more than {} windows",
+                MAX_SUMMARY_WINDOWS
+            ),
+        );
+    }
+
+    let events = match db.list_customer_meter_events(
+        &meter.id,
+        &params.customer,
+        params.start_time,
+        params.end_time,
+        &state.payment_stack_id,
+        state.use_sandbox,
+    ) {
+        Ok(events) => events,
+        Err(e) => {
+            error!("Failed to list meter events: {}", e);
+            return billing_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "api_error",
+                e.to_string(),
+            );
+        }
+    };
+
+    let external_id = meter_external_id(meter, state.use_sandbox).to_string();
+    let windows = summary_windows(
+        params.start_time,
+        params.end_time,
+        params.value_grouping_window,
+    );
+    let summaries = summarize(meter_formula(meter), &events, &windows)
+        .into_iter()
+        .zip(windows)
+        .map(
+            |(aggregated_value, (start_time, end_time))| StripeMeterEventSummary {
+                id: format!("mtrusg_{}_{}", params.customer, start_time),
+                object: "billing.meter_event_summary".to_string(),
+                aggregated_value,
+                start_time,
+                end_time,
+                livemode: !state.use_sandbox,
+                meter: external_id.clone(),
+            },
+        )
+        .collect::<Vec<_>>();
+
+    let start_idx = params
+        .starting_after
+        .as_ref()
+        .and_then(|cursor| summaries.iter().position(|summary| &summary.id == cursor))
+        .map(|idx| idx + 1)
+        .unwrap_or(0);
+    let limit = params.limit.unwrap_or(10).clamp(1, 100) as usize;
+    let has_more = summaries.len() > start_idx + limit;
+    let data = summaries
+        .into_iter()
+        .skip(start_idx)
+        .take(limit)
+        .collect::<Vec<_>>();
+
+    Json(ListResponse {
+        object: "list".to_string(),
+        data,
+        has_more,
+        url: format!("/v1/billing/meters/{}/event_summaries", meter_id),
+    })
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// Catalog counting `api_call` events, with an in-memory payment database
+    fn db_state() -> CatalogState {
+        let meter = Meter {
+            id: "meter".to_string(),
+            deployed_id: None,
+            sandboxes: Default::default(),
+            display_name: Some("API calls".to_string()),
+            event_name: "api_call".to_string(),
+            status: Some("active".to_string()),
+            customer_mapping: None,
+            default_aggregation: None,
+            value_settings: None,
+            created_at: chrono::DateTime::from_timestamp(0, 0).unwrap(),
+            updated_at: None,
+        };
+        CatalogState::new(
+            Vec::new(),
+            vec![meter],
+            true,
+            "http://localhost:8488".parse().unwrap(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+        .with_payment_db(Arc::new(DbManager::local(":memory:").unwrap()), "test")
+    }
+
+    fn meter_event_request(customer: &str) -> Bytes {
+        Bytes::from(format!(
+            "event_name=api_call&identifier=req_1&payload[stripe_customer_id]={}&payload[value]=1",
+            customer
+        ))
+    }
+
+    fn event(timestamp: i64, value: f64) -> MeterEventModel {
+        MeterEventModel {
+            id: 0,
+            identifier: format!("evt_{}", timestamp),
+            meter_id: "meter".to_string(),
+            event_name: "api_call".to_string(),
+            customer: "cus_1".to_string(),
+            value,
+            timestamp,
+            payload: "{}".to_string(),
+            payment_stack_id: "local".to_string(),
+            is_sandbox: true,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse_meter_event_request() {
+        let body = Bytes::from(
+            "event_name=api_call&identifier=req_1&timestamp=1700000000\
+             &payload%5Bstripe_customer_id%5D=cus_1&payload[value]=25",
+        );
+        let request = BillingMeterEventRequest::parse(&body).unwrap();
+
+        assert_eq!(request.event_name.as_deref(), Some("api_call"));
+        assert_eq!(request.identifier.as_deref(), Some("req_1"));
+        assert_eq!(request.timestamp, Some(1_700_000_000));
+        assert_eq!(
+            request
+                .payload
+                .get("stripe_customer_id")
+                .map(String::as_str),
+            Some("cus_1")
+        );
+        assert_eq!(request.payload.get("value").map(String::as_str), Some("25"));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_meter_events_are_scoped_to_the_customer() {
+        let state = db_state();
+        let catalog = state.catalog();
+        let meter = &catalog.meters[0];
+        let ours = meter_event_request("cus_1");
+        let theirs = meter_event_request("cus_2");
+        let duplicate = |body: &Bytes| {
+            let request = BillingMeterEventRequest::parse(body).unwrap();
+            is_duplicate_meter_event(&state, meter, &request)
+        };
+        assert!(!duplicate(&ours));
+
+        let response = create_meter_event(Extension(state.clone()), ours.clone())
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(duplicate(&ours));
+        // Another customer reusing the identifier pays and doesn't get the recorded event
+        assert!(!duplicate(&theirs));
+        let response = create_meter_event(Extension(state.clone()), theirs)
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_aggregate_formulas() {
+        let values = [3.0, 10.0, 2.0];
+        assert_eq!(aggregate(AggregationFormula::Sum, &values), 15.0);
+        assert_eq!(aggregate(AggregationFormula::Count, &values), 3.0);
+        assert_eq!(aggregate(AggregationFormula::Max, &values), 10.0);
+        assert_eq!(aggregate(AggregationFormula::Last, &values), 2.0);
+        assert_eq!(aggregate(AggregationFormula::Last, &[]), 0.0);
+    }
+
+    #[test]
+    fn test_summarize_by_window() {
+        let events = [event(0, 1.0), event(3_599, 2.0), event(3_600, 5.0)];
+
+        let windows = summary_windows(0, 7_200, Some(ValueGroupingWindow::Hour));
+        assert_eq!(windows, vec![(0, 3_600), (3_600, 7_200)]);
+        assert_eq!(
+            summarize(AggregationFormula::Sum, &events, &windows),
+            vec![3.0, 5.0]
+        );
+
+        let windows = summary_windows(0, 7_200, None);
+        assert_eq!(
+            summarize(AggregationFormula::Last, &events, &windows),
+            vec![5.0]
+        );
+    }
 }
@@ -1,26 +1,67 @@
-use std::time::{SystemTime, UNIX_EPOCH};
-
-use axum::{Extension, Json, extract::Path, http::StatusCode, response::IntoResponse};
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::{OriginalUri, Path},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use tracing::info;
 use uuid::Uuid;
 
-use crate::api::catalog::{
-    CatalogState,
-    stripe::types::{
-        CheckoutLineItem, CheckoutLineItemList, CheckoutLineItemPrice, CheckoutSessionStatus,
-        CreateCheckoutSessionRequest, PaymentIntentStatus, PaymentStatus, StripeCheckoutSession,
-        StripePaymentIntent,
+use crate::{
+    api::catalog::{
+        CatalogState,
+        checkout_page::checkout_page_url,
+        fx::{FxError, price_total},
+        solana_pay::payment_intent_transfer_request,
+        stripe::{
+            endpoints::{
+                coupons::resolve_discount,
+                tax::{calculate_tax, insert_tax_metadata, price_tax_behavior, tax_location},
+                test_clocks::{current_time, customer_test_clock},
+            },
+            types::{
+                self, CaptureMethod, CheckoutLineItem, CheckoutLineItemList, CheckoutLineItemPrice,
+                CheckoutSessionStatus, CreateCheckoutSessionRequest, PaymentIntentStatus,
+                PaymentStatus, StripeCheckoutSession, StripePaymentIntent, TaxAddress,
+                TaxCustomerDetails, TotalDetails,
+            },
+        },
+        webhooks::emit_stripe_event,
     },
+    events::{CheckoutSessionExpiredData, CloudEvent},
 };
 
 /// POST /checkout/sessions - Create a new checkout session
 pub async fn create_checkout_session(
     Extension(state): Extension<CatalogState>,
-    Json(request): Json<CreateCheckoutSessionRequest>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> impl IntoResponse {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64;
+    let request = match CreateCheckoutSessionRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    match open_checkout_session(&state, &headers, uri.path(), request) {
+        Ok(checkout_session) => (StatusCode::OK, Json(checkout_session)).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Price and open a checkout session, with its payment intent, for `request`
+///
+/// `sessions_path` is the path of the checkout sessions endpoint (`.../checkout/sessions`)
+/// under the prefix the catalog routes are served at, which the session's hosted page is
+/// served at too.
+pub(crate) fn open_checkout_session(
+    state: &CatalogState,
+    headers: &HeaderMap,
+    sessions_path: &str,
+    request: CreateCheckoutSessionRequest,
+) -> Result<StripeCheckoutSession, Response> {
+    // Sessions of customers on a test clock live in the clock's time
+    let (now, _) = current_time(state, request.customer.as_deref());
 
     // Generate session ID
     let session_id = format!("cs_{}", &Uuid::new_v4().to_string().replace("-", "")[..24]);
@@ -29,31 +70,49 @@ pub async fn create_checkout_session(
     let mut line_items: Vec<CheckoutLineItem> = Vec::new();
     let mut amount_subtotal: i64 = 0;
     let mut currency = "usdc".to_string();
+    let mut tax_behaviors = Vec::with_capacity(request.line_items.len());
 
+    let catalog = state.catalog();
     for item in request.line_items.iter() {
         let line_item_id = format!("li_{}", &Uuid::new_v4().to_string().replace("-", "")[..24]);
 
         // Look up product from catalog by product_id
         // If experiment_id is provided, use that variant; otherwise use base product
         let lookup_id = item.experiment_id.as_ref().unwrap_or(&item.product_id);
-        let product = state.products.iter().find(|p| p.id == *lookup_id);
+        let product = catalog.products.iter().find(|p| p.id == *lookup_id);
 
-        let (unit_amount, item_currency, product_description, product_id, experiment_id) =
+        let (amount, item_currency, product_description, product_id, experiment_id) =
             if let Some(product) = product {
-                let price = product.prices.first();
-                let unit_amount = price.and_then(|p| p.unit_amount).unwrap_or(0);
-                let item_currency = price
-                    .map(|p| p.currency.as_str().to_string())
-                    .unwrap_or_else(|| "usdc".to_string());
+                let price = match &item.price {
+                    Some(price_id) => Some(
+                        product
+                            .prices
+                            .iter()
+                            .find(|p| {
+                                p.id == *price_id
+                                    || types::price_id(p, state.use_sandbox) == *price_id
+                            })
+                            .ok_or_else(|| no_such_line_item_price(price_id, lookup_id))?,
+                    ),
+                    None => product.prices.first(),
+                };
+                // Prices with an amount in the requested currency are charged in it, tiered
+                // and package prices for the whole quantity
+                let (amount, item_currency) = match price {
+                    Some(p) => price_total(p, item.quantity, request.currency.as_deref())
+                        .ok_or_else(|| uncharged_line_item(lookup_id, item.quantity))?,
+                    None => (0, "usdc".to_string()),
+                };
+                tax_behaviors.push(price.map(price_tax_behavior).unwrap_or_default());
                 (
-                    unit_amount,
+                    amount,
                     item_currency,
                     product.description.clone(),
                     item.product_id.clone(),
                     item.experiment_id.clone(),
                 )
             } else {
-                return (
+                return Err((
                     StatusCode::BAD_REQUEST,
                     Json(serde_json::json!({
                         "error": {
@@ -62,29 +121,37 @@ pub async fn create_checkout_session(
                         }
                     })),
                 )
-                    .into_response();
+                    .into_response());
             };
 
         // Set currency from first item
         if line_items.is_empty() {
             currency = item_currency.clone();
+        } else if item_currency != currency {
+            return Err(FxError::MixedCurrencies(currency).into_response());
         }
 
         let quantity = item.quantity;
-        let subtotal = unit_amount * quantity;
-        amount_subtotal += subtotal;
+        let subtotal = amount;
+        // Tiered and package prices have no single unit amount, their line shows the average
+        let unit_amount = amount.checked_div(quantity).unwrap_or(amount);
+        amount_subtotal = amount_subtotal
+            .checked_add(subtotal)
+            .ok_or_else(|| uncharged_line_item(lookup_id, quantity))?;
 
-        // Generate price ID from product
-        let price_id = format!(
-            "price_{}",
-            &Uuid::new_v4().to_string().replace("-", "")[..24]
-        );
+        // The price charged, or an ID generated for the product's first price
+        let price_id = item.price.clone().unwrap_or_else(|| {
+            format!(
+                "price_{}",
+                &Uuid::new_v4().to_string().replace("-", "")[..24]
+            )
+        });
 
         line_items.push(CheckoutLineItem {
             id: line_item_id,
             object: "item".to_string(),
             amount_subtotal: subtotal,
-            amount_total: subtotal, // No tax/discount for now
+            amount_total: subtotal,
             currency: item_currency.clone(),
             quantity,
             description: product_description,
@@ -97,13 +164,85 @@ pub async fn create_checkout_session(
                 experiment_id: experiment_id.clone(),
                 nickname: None,
                 price_type: "one_time".to_string(),
+                tax_behavior: tax_behaviors.last().map(|b| b.as_str().to_string()),
             },
             amount_discount: None,
             amount_tax: None,
         });
     }
 
-    let amount_total = amount_subtotal; // No tax/discount for now
+    // Take the coupon off the lines it applies to
+    if request.discounts.len() > 1 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": {
+                    "code": "parameter_invalid",
+                    "message": "You may only apply one discount to a checkout session.",
+                    "type": "invalid_request_error"
+                }
+            })),
+        )
+            .into_response());
+    }
+    let requested_discount = request.discounts.first().cloned().unwrap_or_default();
+    let discount = match resolve_discount(
+        state,
+        &catalog,
+        &requested_discount,
+        request.customer.as_deref(),
+        now,
+    ) {
+        Ok(discount) => discount,
+        Err(e) => return Err(e.into_response()),
+    };
+    let mut amount_discount = 0;
+    if let Some(discount) = &discount {
+        let lines = line_items
+            .iter()
+            .map(|li| (li.price.product.as_deref(), li.amount_subtotal))
+            .collect::<Vec<_>>();
+        let discounts = match discount.apply(&lines, &currency) {
+            Ok(discounts) => discounts,
+            Err(e) => return Err(e.into_response()),
+        };
+        for (line_item, line_discount) in line_items.iter_mut().zip(discounts) {
+            if line_discount > 0 {
+                line_item.amount_discount = Some(line_discount);
+                line_item.amount_total -= line_discount;
+                amount_discount += line_discount;
+            }
+        }
+    }
+
+    // Tax what remains of each line for the customer's address
+    let location = request.customer_details.as_ref().and_then(|details| {
+        tax_location(
+            Some(details.address.country.as_str()),
+            details.address.state.as_deref(),
+        )
+    });
+    let mut tax = None;
+    if let Some(location) = &location {
+        let lines = line_items
+            .iter()
+            .zip(&tax_behaviors)
+            .map(|(li, tax_behavior)| (li.amount_total, *tax_behavior))
+            .collect::<Vec<_>>();
+        let calculation = match calculate_tax(state, location, &lines) {
+            Ok(calculation) => calculation,
+            Err(e) => return Err(e.into_response()),
+        };
+        for (line_item, line_tax) in line_items.iter_mut().zip(&calculation.lines) {
+            line_item.amount_tax = Some(line_tax.amount_tax);
+            line_item.amount_total = line_tax.amount;
+        }
+        tax = Some(calculation.payment_tax());
+    }
+    let amount_tax = tax.as_ref().map(|tax| tax.amount).unwrap_or(0);
+
+    // Tax on tax-exclusive prices is added on top of the discounted subtotal
+    let amount_total = line_items.iter().map(|li| li.amount_total).sum::<i64>();
 
     // Create the underlying payment intent
     let payment_intent_id = format!("pi_{}", &Uuid::new_v4().to_string().replace("-", "")[..24]);
@@ -120,12 +259,15 @@ pub async fn create_checkout_session(
         .collect::<Vec<_>>()
         .join(", ");
 
-    let payment_intent = StripePaymentIntent {
+    let mut payment_intent = StripePaymentIntent {
         id: payment_intent_id.clone(),
         object: "payment_intent".to_string(),
         amount: amount_total,
         currency: currency.clone(),
         status: PaymentIntentStatus::RequiresConfirmation,
+        capture_method: CaptureMethod::Automatic,
+        amount_capturable: 0,
+        amount_received: 0,
         created: now,
         customer: request.customer.clone(),
         payment_method: None,
@@ -134,15 +276,28 @@ pub async fn create_checkout_session(
             let mut meta = request.metadata.clone();
             // Store line items as JSON for the middleware to extract
             meta.insert("checkout_session_id".to_string(), session_id.clone());
+            if let Some(payment_link) = &request.payment_link {
+                meta.insert("payment_link".to_string(), payment_link.clone());
+            }
             meta.insert(
                 "line_items".to_string(),
                 serde_json::to_string(&line_items).unwrap_or_default(),
             );
+            if let Some(discount) = &discount {
+                discount.insert_metadata(&mut meta, amount_discount);
+            }
+            if let Some(tax) = &tax {
+                insert_tax_metadata(&mut meta, tax);
+            }
             meta
         },
         latest_charge: None,
         client_secret: Some(client_secret.clone()),
+        cancellation_reason: None,
+        solana_pay: None,
     };
+    payment_intent.solana_pay = payment_intent_transfer_request(state, &payment_intent);
+    let solana_pay = payment_intent.solana_pay.clone();
 
     // Store the payment intent
     state
@@ -160,10 +315,25 @@ pub async fn create_checkout_session(
         currency: currency.clone(),
         amount_total,
         amount_subtotal,
+        discounts: discount.iter().map(|d| d.applied()).collect(),
+        total_details: TotalDetails {
+            amount_discount,
+            amount_tax,
+        },
         created: now,
         expires_at: Some(now + 1800), // 30 minutes
         customer: request.customer,
         customer_email: request.customer_email,
+        customer_details: location.map(|location| TaxCustomerDetails {
+            address: TaxAddress {
+                country: location.country,
+                state: location.state,
+                postal_code: request
+                    .customer_details
+                    .and_then(|details| details.address.postal_code),
+            },
+            address_source: "billing".to_string(),
+        }),
         payment_intent: Some(payment_intent_id),
         client_secret: Some(client_secret),
         line_items: CheckoutLineItemList {
@@ -175,6 +345,10 @@ pub async fn create_checkout_session(
         metadata: request.metadata,
         success_url: request.success_url,
         cancel_url: request.cancel_url,
+        url: Some(checkout_page_url(state, headers, sessions_path, &session_id).to_string()),
+        solana_pay,
+        payment_link: request.payment_link,
+        custom_fields: request.custom_fields,
     };
 
     // Store the checkout session
@@ -184,7 +358,67 @@ pub async fn create_checkout_session(
         .unwrap()
         .insert(session_id.clone(), checkout_session.clone());
 
-    (StatusCode::OK, Json(checkout_session)).into_response()
+    Ok(checkout_session)
+}
+
+fn no_such_line_item_price(price_id: &str, product_id: &str) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({
+            "error": {
+                "code": "resource_missing",
+                "message": format!("No such price: '{}' for product {}", price_id, product_id),
+                "param": "line_items[price]",
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+fn uncharged_line_item(product_id: &str, quantity: i64) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({
+            "error": {
+                "code": "parameter_invalid_integer",
+                "message": format!(
+                    "Product {} can't be charged for a quantity of {}",
+                    product_id, quantity
+                ),
+                "param": "line_items[quantity]",
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// Checkout session with its status brought up to date with its payment intent
+pub(crate) fn current_checkout_session(
+    state: &CatalogState,
+    session_id: &str,
+) -> Option<StripeCheckoutSession> {
+    let mut session = state
+        .checkout_sessions
+        .lock()
+        .unwrap()
+        .get(session_id)
+        .cloned()?;
+
+    if let Some(pi_id) = &session.payment_intent
+        && let Some(pi) = state.payment_intents.lock().unwrap().get(pi_id)
+    {
+        session.payment_status = match pi.status {
+            PaymentIntentStatus::Succeeded => PaymentStatus::Paid,
+            _ => PaymentStatus::Unpaid,
+        };
+        if session.payment_status == PaymentStatus::Paid {
+            session.status = CheckoutSessionStatus::Complete;
+        }
+    }
+
+    Some(session)
 }
 
 /// GET /checkout/sessions/:id - Retrieve a checkout session
@@ -192,24 +426,7 @@ pub async fn retrieve_checkout_session(
     Extension(state): Extension<CatalogState>,
     Path(session_id): Path<String>,
 ) -> impl IntoResponse {
-    let sessions = state.checkout_sessions.lock().unwrap();
-
-    if let Some(session) = sessions.get(&session_id) {
-        // Check if we need to update status based on payment intent
-        let mut session = session.clone();
-
-        if let Some(pi_id) = &session.payment_intent
-            && let Some(pi) = state.payment_intents.lock().unwrap().get(pi_id)
-        {
-            session.payment_status = match pi.status {
-                PaymentIntentStatus::Succeeded => PaymentStatus::Paid,
-                _ => PaymentStatus::Unpaid,
-            };
-            if session.payment_status == PaymentStatus::Paid {
-                session.status = CheckoutSessionStatus::Complete;
-            }
-        }
-
+    if let Some(session) = current_checkout_session(&state, &session_id) {
         (StatusCode::OK, Json(session)).into_response()
     } else {
         (
@@ -270,7 +487,15 @@ pub async fn expire_checkout_session(
         }
 
         session.status = CheckoutSessionStatus::Expired;
-        (StatusCode::OK, Json(session.clone())).into_response()
+        let session = session.clone();
+        drop(sessions);
+        emit_stripe_event(
+            &state,
+            "checkout.session.expired",
+            &session,
+            chrono::Utc::now().timestamp(),
+        );
+        (StatusCode::OK, Json(session)).into_response()
     } else {
         (
             StatusCode::NOT_FOUND,
@@ -284,3 +509,61 @@ pub async fn expire_checkout_session(
             .into_response()
     }
 }
+
+/// Expire the open checkout sessions whose `expires_at` is at or before `now`.
+///
+/// Only sessions of customers on `test_clock` are expired, or sessions of customers without
+/// a test clock when it is None. Returns the IDs of the expired sessions.
+pub(crate) fn expire_checkout_sessions(
+    state: &CatalogState,
+    now: i64,
+    test_clock: Option<&str>,
+) -> Vec<String> {
+    let mut expired = Vec::new();
+    {
+        let mut sessions = state.checkout_sessions.lock().unwrap();
+        for session in sessions.values_mut() {
+            let session_clock = session
+                .customer
+                .as_deref()
+                .and_then(|customer| customer_test_clock(state, customer));
+            if session.status != CheckoutSessionStatus::Open
+                || session.expires_at.is_none_or(|expires_at| expires_at > now)
+                || session_clock.as_deref() != test_clock
+            {
+                continue;
+            }
+            session.status = CheckoutSessionStatus::Expired;
+            expired.push(session.clone());
+        }
+    }
+
+    for session in &expired {
+        // The session's payment intent can no longer be paid
+        if let Some(pi_id) = &session.payment_intent
+            && let Some(payment_intent) = state.payment_intents.lock().unwrap().get_mut(pi_id)
+            && payment_intent.status != PaymentIntentStatus::Succeeded
+        {
+            payment_intent.status = PaymentIntentStatus::Canceled;
+            payment_intent.cancellation_reason = Some("abandoned".to_string());
+        }
+        info!("Checkout session {} expired", session.id);
+        state.record_event(
+            CloudEvent::CheckoutSessionExpired(CheckoutSessionExpiredData {
+                session_id: session.id.clone(),
+                customer: session.customer.clone(),
+                payment_intent_id: session.payment_intent.clone(),
+                test_clock: test_clock.map(str::to_string),
+            }),
+            session.expires_at.unwrap_or(now),
+        );
+        emit_stripe_event(
+            state,
+            "checkout.session.expired",
+            session,
+            session.expires_at.unwrap_or(now),
+        );
+    }
+
+    expired.into_iter().map(|session| session.id).collect()
+}
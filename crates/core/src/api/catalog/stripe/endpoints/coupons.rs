@@ -0,0 +1,816 @@
+//! Coupons and the promotion codes customers redeem them with
+//!
+//! Coupons are declared in the catalog (`coupons/*.yaml`) and are read-only through the
+//! API. A discount requested with a coupon ID or a promotion code is checked when a
+//! purchase is priced (checkout sessions, payment intents, x402 payment requirements and
+//! subscriptions), and counted as a redemption once the purchase is paid.
+
+use std::collections::HashMap;
+
+use axum::{
+    Extension, Json,
+    extract::{Path, RawQuery},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use moneymq_types::{BasketDiscount, Coupon, PromotionCode};
+use tracing::{error, info, warn};
+
+use crate::api::{
+    catalog::{
+        Catalog, CatalogState,
+        stripe::{
+            list::CatalogQuery,
+            types::{
+                AppliedDiscount, CouponAppliesTo, ListResponse, StripeCoupon, StripePromotionCode,
+            },
+        },
+    },
+    payment::db::coupon_redemption::{NewCouponRedemption, Redemption},
+};
+
+/// Metadata key of the coupon a payment intent is discounted with
+pub const COUPON_METADATA_KEY: &str = "coupon";
+/// Metadata key of the promotion code the coupon was redeemed with
+pub const PROMOTION_CODE_METADATA_KEY: &str = "promotion_code";
+/// Metadata key of the amount taken off a payment intent (cents)
+pub const DISCOUNT_METADATA_KEY: &str = "amount_discount";
+
+/// Coupon redeemed for a purchase, directly or through one of its promotion codes
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Discount<'a> {
+    pub coupon: &'a Coupon,
+    pub promotion_code: Option<&'a PromotionCode>,
+}
+
+/// Why a requested discount can't be applied
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DiscountError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl DiscountError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for DiscountError {
+    fn into_response(self) -> Response {
+        coupon_error(StatusCode::BAD_REQUEST, self.code, self.message)
+    }
+}
+
+impl Discount<'_> {
+    /// Amount taken off each line of a purchase in `currency` (see
+    /// [`Coupon::discount_amounts`]), failing when the coupon can't discount any line
+    pub fn apply(
+        &self,
+        lines: &[(Option<&str>, i64)],
+        currency: &str,
+    ) -> Result<Vec<i64>, DiscountError> {
+        if !self.coupon.applies_to_currency(currency) {
+            return Err(DiscountError::new(
+                "coupon_currency_mismatch",
+                format!(
+                    "Coupon '{}' only applies to purchases in {}",
+                    self.coupon.id,
+                    self.coupon.currency.as_deref().unwrap_or_default()
+                ),
+            ));
+        }
+        if !lines
+            .iter()
+            .any(|(product_id, _)| self.coupon.applies_to_product(*product_id))
+        {
+            return Err(DiscountError::new(
+                "coupon_not_applicable",
+                format!(
+                    "Coupon '{}' does not apply to this purchase",
+                    self.coupon.id
+                ),
+            ));
+        }
+        Ok(self.coupon.discount_amounts(lines, currency))
+    }
+
+    /// The coupon and promotion code, as shown on checkout sessions
+    pub fn applied(&self) -> AppliedDiscount {
+        AppliedDiscount {
+            coupon: Some(self.coupon.id.clone()),
+            promotion_code: self.promotion_code.map(|p| p.id.clone()),
+        }
+    }
+
+    /// Discount recorded on a receipt basket item
+    pub fn basket_discount(&self, amount: i64) -> BasketDiscount {
+        BasketDiscount {
+            coupon: self.coupon.id.clone(),
+            promotion_code: self.promotion_code.map(|p| p.id.clone()),
+            amount,
+        }
+    }
+
+    /// Record the discount on a payment intent's metadata, so the redemption is counted
+    /// once it succeeds
+    pub fn insert_metadata(&self, metadata: &mut HashMap<String, String>, amount: i64) {
+        metadata.insert(COUPON_METADATA_KEY.to_string(), self.coupon.id.clone());
+        if let Some(promotion_code) = self.promotion_code {
+            metadata.insert(
+                PROMOTION_CODE_METADATA_KEY.to_string(),
+                promotion_code.id.clone(),
+            );
+        }
+        metadata.insert(DISCOUNT_METADATA_KEY.to_string(), amount.to_string());
+    }
+}
+
+pub(crate) fn find_coupon<'a>(catalog: &'a Catalog, coupon_id: &str) -> Option<&'a Coupon> {
+    catalog.coupons.iter().find(|c| c.id == coupon_id)
+}
+
+/// Find a promotion code by ID or code, with its coupon
+fn find_promotion_code<'a>(
+    catalog: &'a Catalog,
+    id_or_code: &str,
+) -> Option<(&'a Coupon, &'a PromotionCode)> {
+    catalog.coupons.iter().find_map(|coupon| {
+        coupon
+            .find_promotion_code(id_or_code)
+            .map(|promotion_code| (coupon, promotion_code))
+    })
+}
+
+/// Times a coupon was redeemed (0 without a payment database)
+fn coupon_redemptions(state: &CatalogState, coupon: &Coupon) -> i64 {
+    let Some(db) = state.payment_db.as_ref() else {
+        return 0;
+    };
+    db.count_coupon_redemptions(&coupon.id, &state.payment_stack_id, state.use_sandbox)
+        .unwrap_or_else(|e| {
+            error!("Failed to count redemptions of coupon {}: {}", coupon.id, e);
+            0
+        })
+}
+
+/// Times a promotion code was redeemed (0 without a payment database)
+fn promotion_code_redemptions(state: &CatalogState, promotion_code: &PromotionCode) -> i64 {
+    let Some(db) = state.payment_db.as_ref() else {
+        return 0;
+    };
+    db.count_promotion_code_redemptions(
+        &promotion_code.id,
+        &state.payment_stack_id,
+        state.use_sandbox,
+    )
+    .unwrap_or_else(|e| {
+        error!(
+            "Failed to count redemptions of promotion code {}: {}",
+            promotion_code.code, e
+        );
+        0
+    })
+}
+
+/// Check that a coupon can be redeemed at `now` (Unix seconds)
+fn check_coupon(coupon: &Coupon, times_redeemed: i64, now: i64) -> Result<(), DiscountError> {
+    if !coupon.active || coupon.redeem_by.is_some_and(|redeem_by| now > redeem_by) {
+        return Err(DiscountError::new(
+            "coupon_expired",
+            format!("Coupon '{}' is no longer valid", coupon.id),
+        ));
+    }
+    if coupon
+        .max_redemptions
+        .is_some_and(|max| times_redeemed >= max)
+    {
+        return Err(DiscountError::new(
+            "coupon_expired",
+            format!("Coupon '{}' has reached its maximum redemptions", coupon.id),
+        ));
+    }
+    Ok(())
+}
+
+/// Check that a promotion code can be redeemed by `customer` at `now` (Unix seconds)
+fn check_promotion_code(
+    promotion_code: &PromotionCode,
+    times_redeemed: i64,
+    customer: Option<&str>,
+    now: i64,
+) -> Result<(), DiscountError> {
+    if !promotion_code.active
+        || promotion_code
+            .expires_at
+            .is_some_and(|expires_at| now >= expires_at)
+    {
+        return Err(DiscountError::new(
+            "promotion_code_expired",
+            format!(
+                "Promotion code '{}' is no longer valid",
+                promotion_code.code
+            ),
+        ));
+    }
+    if promotion_code
+        .max_redemptions
+        .is_some_and(|max| times_redeemed >= max)
+    {
+        return Err(DiscountError::new(
+            "promotion_code_expired",
+            format!(
+                "Promotion code '{}' has reached its maximum redemptions",
+                promotion_code.code
+            ),
+        ));
+    }
+    if let Some(restricted_to) = &promotion_code.customer
+        && customer != Some(restricted_to.as_str())
+    {
+        return Err(DiscountError::new(
+            "promotion_code_customer_not_eligible",
+            format!(
+                "Promotion code '{}' cannot be redeemed by this customer",
+                promotion_code.code
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Resolve the discount requested with a coupon ID or a promotion code (ID or code) for
+/// `customer` at `now` (Unix seconds). Returns None when no discount was requested.
+pub(crate) fn resolve_discount<'a>(
+    state: &CatalogState,
+    catalog: &'a Catalog,
+    requested: &AppliedDiscount,
+    customer: Option<&str>,
+    now: i64,
+) -> Result<Option<Discount<'a>>, DiscountError> {
+    let discount = match (
+        requested.coupon.as_deref().filter(|c| !c.is_empty()),
+        requested
+            .promotion_code
+            .as_deref()
+            .filter(|p| !p.is_empty()),
+    ) {
+        (None, None) => return Ok(None),
+        (Some(_), Some(_)) => {
+            return Err(DiscountError::new(
+                "parameter_invalid",
+                "You may only specify one of these parameters: coupon, promotion_code.",
+            ));
+        }
+        (Some(coupon_id), None) => Discount {
+            coupon: find_coupon(catalog, coupon_id).ok_or_else(|| {
+                DiscountError::new(
+                    "resource_missing",
+                    format!("No such coupon: '{}'", coupon_id),
+                )
+            })?,
+            promotion_code: None,
+        },
+        (None, Some(code)) => {
+            let (coupon, promotion_code) = find_promotion_code(catalog, code).ok_or_else(|| {
+                DiscountError::new(
+                    "resource_missing",
+                    format!("No such promotion code: '{}'", code),
+                )
+            })?;
+            check_promotion_code(
+                promotion_code,
+                promotion_code_redemptions(state, promotion_code),
+                customer,
+                now,
+            )?;
+            Discount {
+                coupon,
+                promotion_code: Some(promotion_code),
+            }
+        }
+    };
+    check_coupon(
+        discount.coupon,
+        coupon_redemptions(state, discount.coupon),
+        now,
+    )?;
+    Ok(Some(discount))
+}
+
+/// Count a redemption of a coupon by the purchase `reference` (payment intent,
+/// subscription or x402 transaction); a purchase only redeems a coupon once
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn record_redemption(
+    state: &CatalogState,
+    coupon: &str,
+    promotion_code: Option<&str>,
+    customer: Option<&str>,
+    reference: &str,
+    amount_discount: i64,
+    currency: &str,
+) {
+    let Some(db) = state.payment_db.as_ref() else {
+        return;
+    };
+    let redemption = NewCouponRedemption::new(
+        coupon.to_string(),
+        promotion_code.map(str::to_string),
+        customer.map(str::to_string),
+        reference.to_string(),
+        amount_discount,
+        currency.to_string(),
+        state.payment_stack_id.clone(),
+        state.use_sandbox,
+    );
+    let catalog = state.catalog();
+    let coupon_max_redemptions = find_coupon(&catalog, coupon).and_then(|c| c.max_redemptions);
+    let promotion_code_max_redemptions = promotion_code
+        .and_then(|id| find_promotion_code(&catalog, id))
+        .and_then(|(_, promotion_code)| promotion_code.max_redemptions);
+    match db.redeem_coupon(
+        &redemption,
+        coupon_max_redemptions,
+        promotion_code_max_redemptions,
+    ) {
+        Ok(Redemption::Recorded) => info!("Coupon {} redeemed by {}", coupon, reference),
+        Ok(Redemption::AlreadyRecorded) => {}
+        Ok(Redemption::MaxRedemptionsReached) => warn!(
+            "Coupon {} reached its maximum redemptions before {} redeemed it",
+            coupon, reference
+        ),
+        Err(e) => error!("Failed to record redemption of coupon {}: {}", coupon, e),
+    }
+}
+
+/// Count the redemption of the coupon a succeeded payment intent was discounted with
+pub(crate) fn record_payment_intent_redemption(state: &CatalogState, payment_intent_id: &str) {
+    let Some(payment_intent) = state
+        .payment_intents
+        .lock()
+        .unwrap()
+        .get(payment_intent_id)
+        .cloned()
+    else {
+        return;
+    };
+    let Some(coupon) = payment_intent.metadata.get(COUPON_METADATA_KEY) else {
+        return;
+    };
+    let amount_discount = payment_intent
+        .metadata
+        .get(DISCOUNT_METADATA_KEY)
+        .and_then(|amount| amount.parse().ok())
+        .unwrap_or(0);
+    record_redemption(
+        state,
+        coupon,
+        payment_intent
+            .metadata
+            .get(PROMOTION_CODE_METADATA_KEY)
+            .map(String::as_str),
+        payment_intent.customer.as_deref(),
+        payment_intent_id,
+        amount_discount,
+        &payment_intent.currency,
+    );
+}
+
+// ==================== Responses ====================
+
+fn stripe_coupon(state: &CatalogState, coupon: &Coupon, now: i64) -> StripeCoupon {
+    let times_redeemed = coupon_redemptions(state, coupon);
+    StripeCoupon {
+        id: coupon.id.clone(),
+        object: "coupon".to_string(),
+        name: coupon.name.clone(),
+        percent_off: coupon.percent_off,
+        amount_off: coupon.amount_off,
+        currency: coupon.currency.clone(),
+        duration: coupon.duration.as_str().to_string(),
+        duration_in_months: coupon.duration_in_months,
+        applies_to: (!coupon.applies_to.is_empty()).then(|| CouponAppliesTo {
+            products: coupon.applies_to.clone(),
+        }),
+        max_redemptions: coupon.max_redemptions,
+        times_redeemed,
+        redeem_by: coupon.redeem_by,
+        valid: check_coupon(coupon, times_redeemed, now).is_ok(),
+        metadata: coupon.metadata.clone().into_iter().collect(),
+        livemode: !state.use_sandbox,
+    }
+}
+
+fn stripe_promotion_code(
+    state: &CatalogState,
+    coupon: &Coupon,
+    promotion_code: &PromotionCode,
+    now: i64,
+) -> StripePromotionCode {
+    StripePromotionCode {
+        id: promotion_code.id.clone(),
+        object: "promotion_code".to_string(),
+        code: promotion_code.code.clone(),
+        coupon: stripe_coupon(state, coupon, now),
+        active: promotion_code.active
+            && promotion_code
+                .expires_at
+                .is_none_or(|expires_at| now < expires_at),
+        customer: promotion_code.customer.clone(),
+        expires_at: promotion_code.expires_at,
+        max_redemptions: promotion_code.max_redemptions,
+        times_redeemed: promotion_code_redemptions(state, promotion_code),
+        metadata: promotion_code.metadata.clone().into_iter().collect(),
+        livemode: !state.use_sandbox,
+    }
+}
+
+fn coupon_error(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(serde_json::json!({
+            "error": {
+                "code": code,
+                "message": message.into(),
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+// ==================== Handlers ====================
+
+/// GET /v1/coupons - List the catalog's coupons
+pub async fn list_coupons(
+    Extension(state): Extension<CatalogState>,
+    RawQuery(query): RawQuery,
+) -> impl IntoResponse {
+    let query = match CatalogQuery::parse(query.as_deref(), &[]) {
+        Ok(query) => query,
+        Err(e) => return e.into_response(),
+    };
+    let now = chrono::Utc::now().timestamp();
+    let catalog = state.catalog();
+    let (coupons, has_more) = match query.paginate(
+        catalog.coupons.iter().collect::<Vec<_>>(),
+        "coupon",
+        |coupon| coupon.id.as_str(),
+    ) {
+        Ok(page) => page,
+        Err(e) => return e.into_response(),
+    };
+    let response = ListResponse {
+        object: "list".to_string(),
+        data: coupons
+            .into_iter()
+            .map(|coupon| stripe_coupon(&state, coupon, now))
+            .collect(),
+        has_more,
+        url: "/v1/coupons".to_string(),
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// GET /v1/coupons/:id - Retrieve a coupon
+pub async fn retrieve_coupon(
+    Extension(state): Extension<CatalogState>,
+    Path(coupon_id): Path<String>,
+) -> impl IntoResponse {
+    let catalog = state.catalog();
+    match find_coupon(&catalog, &coupon_id) {
+        Some(coupon) => (
+            StatusCode::OK,
+            Json(stripe_coupon(
+                &state,
+                coupon,
+                chrono::Utc::now().timestamp(),
+            )),
+        )
+            .into_response(),
+        None => coupon_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such coupon: '{}'", coupon_id),
+        ),
+    }
+}
+
+/// GET /v1/promotion_codes - List the promotion codes of the catalog's coupons
+///
+/// Promotion codes can be filtered by `code` (case-insensitive), `coupon`, `customer`
+/// and `active`.
+pub async fn list_promotion_codes(
+    Extension(state): Extension<CatalogState>,
+    RawQuery(query): RawQuery,
+) -> impl IntoResponse {
+    let query = match CatalogQuery::parse(query.as_deref(), &[]) {
+        Ok(query) => query,
+        Err(e) => return e.into_response(),
+    };
+    let active = match query.bool_param("active") {
+        Ok(active) => active,
+        Err(e) => return e.into_response(),
+    };
+    let now = chrono::Utc::now().timestamp();
+    let state = &state;
+    let catalog = state.catalog();
+    let promotion_codes = catalog
+        .coupons
+        .iter()
+        .filter(|coupon| query.param("coupon").is_none_or(|id| coupon.id == id))
+        .flat_map(|coupon| {
+            coupon.promotion_codes.iter().map(move |promotion_code| {
+                stripe_promotion_code(state, coupon, promotion_code, now)
+            })
+        })
+        .filter(|promotion_code| {
+            query
+                .param("code")
+                .is_none_or(|code| promotion_code.code.eq_ignore_ascii_case(code))
+                && query
+                    .param("customer")
+                    .is_none_or(|customer| promotion_code.customer.as_deref() == Some(customer))
+                && active.is_none_or(|active| promotion_code.active == active)
+        })
+        .collect::<Vec<_>>();
+    let (promotion_codes, has_more) =
+        match query.paginate(promotion_codes, "promotion_code", |promotion_code| {
+            promotion_code.id.as_str()
+        }) {
+            Ok(page) => page,
+            Err(e) => return e.into_response(),
+        };
+
+    let response = ListResponse {
+        object: "list".to_string(),
+        data: promotion_codes,
+        has_more,
+        url: "/v1/promotion_codes".to_string(),
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// GET /v1/promotion_codes/:id - Retrieve a promotion code
+pub async fn retrieve_promotion_code(
+    Extension(state): Extension<CatalogState>,
+    Path(promotion_code_id): Path<String>,
+) -> impl IntoResponse {
+    let catalog = state.catalog();
+    let found = catalog.coupons.iter().find_map(|coupon| {
+        coupon
+            .promotion_codes
+            .iter()
+            .find(|promotion_code| promotion_code.id == promotion_code_id)
+            .map(|promotion_code| (coupon, promotion_code))
+    });
+    match found {
+        Some((coupon, promotion_code)) => (
+            StatusCode::OK,
+            Json(stripe_promotion_code(
+                &state,
+                coupon,
+                promotion_code,
+                chrono::Utc::now().timestamp(),
+            )),
+        )
+            .into_response(),
+        None => coupon_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such promotion code: '{}'", promotion_code_id),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::api::payment::db::DbManager;
+
+    fn state_with_coupons() -> CatalogState {
+        let coupons = serde_yml::from_str::<Vec<Coupon>>(
+            r#"
+- id: launch
+  percent_off: 20
+  applies_to: [pro]
+  promotion_codes:
+    - code: LAUNCH
+    - code: VIP
+      customer: cus_vip
+    - code: OLD
+      expires_at: 1000
+- id: retired
+  amount_off: 500
+  currency: usd
+  redeem_by: 1000
+- id: limited
+  amount_off: 100
+  currency: usd
+  max_redemptions: 2
+  promotion_codes:
+    - code: ONCE
+      max_redemptions: 1
+"#,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|coupon| coupon.with_id_from_filename("unused"))
+        .collect();
+        CatalogState::new(
+            vec![],
+            vec![],
+            true,
+            "http://localhost:8080".parse().unwrap(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+        .with_coupons(coupons)
+    }
+
+    fn requested(coupon: Option<&str>, promotion_code: Option<&str>) -> AppliedDiscount {
+        AppliedDiscount {
+            coupon: coupon.map(str::to_string),
+            promotion_code: promotion_code.map(str::to_string),
+        }
+    }
+
+    fn error_code(result: Result<Option<Discount<'_>>, DiscountError>) -> &'static str {
+        result.unwrap_err().code
+    }
+
+    #[test]
+    fn test_resolve_discount() {
+        let state = state_with_coupons();
+        let catalog = state.catalog();
+
+        assert!(
+            resolve_discount(&state, &catalog, &requested(None, None), None, 500)
+                .unwrap()
+                .is_none()
+        );
+
+        let discount = resolve_discount(
+            &state,
+            &catalog,
+            &requested(None, Some("launch")),
+            None,
+            500,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(discount.coupon.id, "launch");
+        assert_eq!(discount.promotion_code.unwrap().id, "promo_launch");
+        assert_eq!(
+            discount.applied(),
+            requested(Some("launch"), Some("promo_launch"))
+        );
+
+        let vip = requested(None, Some("VIP"));
+        assert!(resolve_discount(&state, &catalog, &vip, Some("cus_vip"), 500).is_ok());
+        assert_eq!(
+            error_code(resolve_discount(
+                &state,
+                &catalog,
+                &vip,
+                Some("cus_other"),
+                500
+            )),
+            "promotion_code_customer_not_eligible"
+        );
+        assert_eq!(
+            error_code(resolve_discount(
+                &state,
+                &catalog,
+                &requested(None, Some("OLD")),
+                None,
+                1000
+            )),
+            "promotion_code_expired"
+        );
+        assert_eq!(
+            error_code(resolve_discount(
+                &state,
+                &catalog,
+                &requested(Some("retired"), None),
+                None,
+                1001
+            )),
+            "coupon_expired"
+        );
+        assert_eq!(
+            error_code(resolve_discount(
+                &state,
+                &catalog,
+                &requested(Some("nope"), None),
+                None,
+                500
+            )),
+            "resource_missing"
+        );
+        assert_eq!(
+            error_code(resolve_discount(
+                &state,
+                &catalog,
+                &requested(Some("launch"), Some("LAUNCH")),
+                None,
+                500
+            )),
+            "parameter_invalid"
+        );
+    }
+
+    #[test]
+    fn test_apply_discount() {
+        let state = state_with_coupons();
+        let catalog = state.catalog();
+        let discount = resolve_discount(
+            &state,
+            &catalog,
+            &requested(Some("launch"), None),
+            None,
+            500,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            discount.apply(&[(Some("pro"), 1000), (Some("lite"), 1000)], "usd"),
+            Ok(vec![200, 0])
+        );
+        assert_eq!(
+            discount
+                .apply(&[(Some("lite"), 1000)], "usd")
+                .unwrap_err()
+                .code,
+            "coupon_not_applicable"
+        );
+
+        let retired = Discount {
+            coupon: find_coupon(&catalog, "retired").unwrap(),
+            promotion_code: None,
+        };
+        assert_eq!(
+            retired.apply(&[(None, 1000)], "eur").unwrap_err().code,
+            "coupon_currency_mismatch"
+        );
+    }
+
+    #[test]
+    fn test_redemptions_stop_at_max_redemptions() {
+        let state = state_with_coupons()
+            .with_payment_db(Arc::new(DbManager::local(":memory:").unwrap()), "test");
+        let counts = |state: &CatalogState| {
+            let catalog = state.catalog();
+            let (coupon, promotion_code) = find_promotion_code(&catalog, "ONCE").unwrap();
+            (
+                coupon_redemptions(state, coupon),
+                promotion_code_redemptions(state, promotion_code),
+            )
+        };
+        let redeem = |promotion_code: Option<&str>, reference: &str| {
+            record_redemption(
+                &state,
+                "limited",
+                promotion_code,
+                None,
+                reference,
+                100,
+                "usd",
+            )
+        };
+
+        redeem(Some("promo_once"), "pi_1");
+        assert_eq!(counts(&state), (1, 1));
+        // Redeeming twice by the same purchase counts once
+        redeem(Some("promo_once"), "pi_1");
+        assert_eq!(counts(&state), (1, 1));
+        // The promotion code is used up, so the coupon isn't counted either
+        redeem(Some("promo_once"), "pi_2");
+        assert_eq!(counts(&state), (1, 1));
+
+        redeem(None, "pi_3");
+        assert_eq!(counts(&state), (2, 1));
+        redeem(None, "pi_4");
+        assert_eq!(counts(&state), (2, 1));
+
+        let catalog = state.catalog();
+        assert_eq!(
+            error_code(resolve_discount(
+                &state,
+                &catalog,
+                &requested(Some("limited"), None),
+                None,
+                500
+            )),
+            "coupon_expired"
+        );
+    }
+}
@@ -1,74 +1,1082 @@
+//! Customers of a payment stack and the wallets their payments come from
+//!
+//! Wallet addresses attached to a customer attribute the payments made from them to that
+//! customer: they are listed with its transactions and receipts on retrieve, and tagged
+//! with the customer on `/v1/transactions`. A wallet belongs to at most one customer.
+
+use std::{collections::HashMap, str::FromStr};
+
 use axum::{
-    Extension, Form, Json, body::Bytes, extract::Path, http::StatusCode, response::IntoResponse,
+    Extension, Json,
+    body::Bytes,
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
 };
+use serde::Deserialize;
+use tracing::error;
 
-use crate::api::catalog::{
-    CatalogState,
-    stripe::{
-        types::{CreateCustomerRequest, StripeCustomer},
-        utils::generate_stripe_id,
+use crate::{
+    api::{
+        catalog::{
+            CatalogState,
+            stripe::{
+                endpoints::test_clocks::{attach_customer, current_time},
+                form::{FormError, StripeForm, StripeQuery},
+                types::{
+                    CustomerReceipt, DeletedCustomer, ListResponse, SearchResponse, StripeCustomer,
+                },
+                utils::generate_stripe_id,
+            },
+            webhooks::{emit_stripe_event, emit_stripe_update},
+        },
+        payment::db::{
+            CustomerModel, DbManager,
+            customer::{
+                CustomerSearchClause, CustomerSearchField, NewCustomer, NewCustomerWallet,
+                UpdateCustomer,
+            },
+        },
     },
+    events::{CloudEventEnvelope, TransactionCompletedData},
 };
 
-/// POST /v1/customers - Create a new customer
+/// Transactions and receipts embedded in a retrieved customer
+const CUSTOMER_HISTORY_LIMIT: usize = 100;
+
+/// CloudEvent type receipts are read from
+const TRANSACTION_COMPLETED_EVENT: &str = "mq.money.transaction.completed";
+
+fn customer_error(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(serde_json::json!({
+            "error": {
+                "code": code,
+                "message": message.into(),
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+fn api_error(message: impl Into<String>) -> Response {
+    customer_error(StatusCode::INTERNAL_SERVER_ERROR, "api_error", message)
+}
+
+fn payment_db(state: &CatalogState) -> Result<&DbManager, Response> {
+    state.payment_db.as_deref().ok_or_else(|| {
+        customer_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "customers_not_configured",
+            "Customers require a database",
+        )
+    })
+}
+
+fn find_customer_or_404(
+    state: &CatalogState,
+    db: &DbManager,
+    id: &str,
+) -> Result<CustomerModel, Response> {
+    match db.find_customer(id, &state.payment_stack_id, state.use_sandbox) {
+        Ok(Some(customer)) => Ok(customer),
+        Ok(None) => Err(customer_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such customer: '{}'", id),
+        )),
+        Err(e) => {
+            error!("Failed to find customer {}: {}", id, e);
+            Err(api_error(e.to_string()))
+        }
+    }
+}
+
+fn stripe_customer(state: &CatalogState, customer: &CustomerModel) -> StripeCustomer {
+    StripeCustomer {
+        id: customer.customer_id.clone(),
+        object: "customer".to_string(),
+        email: customer.email.clone(),
+        name: customer.name.clone(),
+        metadata: customer.metadata(),
+        created: customer.created_at / 1000,
+        description: customer.description.clone(),
+        phone: customer.phone.clone(),
+        test_clock: customer.test_clock.clone(),
+        livemode: !state.use_sandbox,
+        wallets: vec![],
+        transactions: None,
+        receipts: None,
+    }
+}
+
+/// Respond with a customer and its wallets
+fn customer_response(state: &CatalogState, db: &DbManager, customer: &CustomerModel) -> Response {
+    match db.list_customer_wallets(&customer.customer_id) {
+        Ok(wallets) => {
+            let mut response = stripe_customer(state, customer);
+            response.wallets = wallets;
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            error!("Failed to list wallets of {}: {}", customer.customer_id, e);
+            api_error(e.to_string())
+        }
+    }
+}
+
+/// Receipts of the payments completed from `wallets`, newest first
+pub(crate) fn customer_receipts(
+    state: &CatalogState,
+    db: &DbManager,
+    wallets: &[String],
+) -> Result<Vec<CustomerReceipt>, String> {
+    let events = db
+        .list_events_mentioning(
+            TRANSACTION_COMPLETED_EVENT,
+            wallets,
+            &state.payment_stack_id,
+            state.use_sandbox,
+            CUSTOMER_HISTORY_LIMIT as i64,
+        )
+        .map_err(|e| e.to_string())?;
+
+    Ok(events
+        .into_iter()
+        .filter_map(|event| {
+            let envelope: CloudEventEnvelope = serde_json::from_str(&event.data_json).ok()?;
+            let data: TransactionCompletedData = serde_json::from_value(envelope.data).ok()?;
+            wallets.contains(&data.payer).then(|| CustomerReceipt {
+                transaction_id: data.transaction_id,
+                receipt: data.receipt,
+                payer: data.payer,
+                amount: data.amount,
+                currency: data.currency,
+                network: data.network,
+                transaction_signature: data.transaction_signature,
+                product_id: data.product_id,
+                created: event.event_time / 1000,
+            })
+        })
+        .collect())
+}
+
+/// Check that `wallets` are Solana addresses not attached to another customer
+fn check_wallets(
+    state: &CatalogState,
+    db: &DbManager,
+    customer_id: Option<&str>,
+    wallets: &[String],
+) -> Result<(), Response> {
+    for address in wallets {
+        if solana_pubkey::Pubkey::from_str(address).is_err() {
+            return Err(customer_error(
+                StatusCode::BAD_REQUEST,
+                "parameter_invalid",
+                format!("Invalid wallet address: '{}'", address),
+            ));
+        }
+        match db.find_wallet_customer(address, &state.payment_stack_id, state.use_sandbox) {
+            Ok(Some(owner)) if Some(owner.as_str()) != customer_id => {
+                return Err(customer_error(
+                    StatusCode::BAD_REQUEST,
+                    "wallet_already_attached",
+                    format!(
+                        "The wallet '{}' is already attached to another customer",
+                        address
+                    ),
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => return Err(api_error(e.to_string())),
+        }
+    }
+    Ok(())
+}
+
+/// Attach `wallets` to a customer, after [`check_wallets`]
+fn attach_wallets(
+    state: &CatalogState,
+    db: &DbManager,
+    customer_id: &str,
+    wallets: &[String],
+) -> Result<(), Response> {
+    for address in wallets {
+        let wallet = NewCustomerWallet::new(
+            customer_id.to_string(),
+            address.clone(),
+            state.payment_stack_id.clone(),
+            state.use_sandbox,
+        );
+        match db.attach_customer_wallet(&wallet) {
+            Ok(true) => {}
+            // Attached concurrently, or already attached to this customer
+            Ok(false) => {
+                check_wallets(state, db, Some(customer_id), std::slice::from_ref(address))?
+            }
+            Err(e) => {
+                error!(
+                    "Failed to attach wallet {} to {}: {}",
+                    address, customer_id, e
+                );
+                return Err(api_error(e.to_string()));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn encode_metadata(metadata: &HashMap<String, String>) -> Option<String> {
+    (!metadata.is_empty())
+        .then(|| serde_json::to_string(metadata).ok())
+        .flatten()
+}
+
+/// Form-encoded customer create/update request
+#[derive(Debug, Default)]
+pub struct CustomerRequest {
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub phone: Option<String>,
+    pub metadata: HashMap<String, String>,
+    /// Sandbox test clock to attach the customer to (create only)
+    pub test_clock: Option<String>,
+    /// Wallet addresses to attach, as `wallets[]` or `wallets[N]`
+    pub wallets: Vec<String>,
+}
+
+impl CustomerRequest {
+    pub fn parse(body: &Bytes) -> Result<CustomerRequest, FormError> {
+        let form = StripeForm::decode(body)?;
+        let mut metadata = form.map("metadata")?;
+        // Kept in metadata, payment methods aren't persisted
+        if let Some(payment_method) = form.string("invoice_settings[default_payment_method]")? {
+            metadata.insert("default_payment_method".to_string(), payment_method);
+        }
+        Ok(CustomerRequest {
+            email: form.string("email")?,
+            name: form.string("name")?,
+            description: form.string("description")?,
+            phone: form.string("phone")?,
+            metadata,
+            test_clock: form.string("test_clock")?.filter(|clock| !clock.is_empty()),
+            wallets: form
+                .strings("wallets")?
+                .into_iter()
+                .filter(|wallet| !wallet.is_empty())
+                .collect(),
+        })
+    }
+}
+
+/// POST /v1/customers - Create a customer
 pub async fn create_customer(
-    Extension(_state): Extension<CatalogState>,
-    Form(request): Form<CreateCustomerRequest>,
+    Extension(state): Extension<CatalogState>,
+    body: Bytes,
 ) -> impl IntoResponse {
-    // Generate a mock customer ID
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let request = match CustomerRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(response) = check_wallets(&state, db, None, &request.wallets) {
+        return response;
+    }
+
     let customer_id = generate_stripe_id("cus");
-    let created = chrono::Utc::now().timestamp();
+    let metadata: HashMap<String, String> = request
+        .metadata
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .collect();
+    let mut new_customer = NewCustomer::new(
+        customer_id.clone(),
+        state.payment_stack_id.clone(),
+        state.use_sandbox,
+    )
+    .with_email(request.email.filter(|e| !e.is_empty()))
+    .with_name(request.name.filter(|n| !n.is_empty()))
+    .with_description(request.description.filter(|d| !d.is_empty()))
+    .with_phone(request.phone.filter(|p| !p.is_empty()))
+    .with_metadata(encode_metadata(&metadata));
 
-    let metadata = request.metadata.and_then(|m| serde_json::to_value(m).ok());
+    if let Some(test_clock) = request.test_clock {
+        match attach_customer(&state, &customer_id, &test_clock) {
+            Some(frozen_time) => {
+                new_customer = new_customer.with_test_clock(test_clock, frozen_time)
+            }
+            None => {
+                return customer_error(
+                    StatusCode::BAD_REQUEST,
+                    "resource_missing",
+                    format!("No such test clock: '{}'", test_clock),
+                );
+            }
+        }
+    }
 
-    let customer = StripeCustomer {
-        id: customer_id,
-        object: "customer".to_string(),
-        email: request.email,
-        name: request.name,
-        metadata,
-        created,
-        description: None,
-        phone: None,
+    let customer = match db.insert_customer(&new_customer) {
+        Ok(customer) => customer,
+        Err(e) => {
+            error!("Failed to create customer: {}", e);
+            return api_error(e.to_string());
+        }
+    };
+    if let Err(response) = attach_wallets(&state, db, &customer_id, &request.wallets) {
+        return response;
+    }
+    emit_stripe_event(
+        &state,
+        "customer.created",
+        &stripe_customer(&state, &customer),
+        customer.created_at / 1000,
+    );
+    customer_response(&state, db, &customer)
+}
+
+/// GET /v1/customers/:id - Retrieve a customer, with the transactions and receipts of
+/// the payments made from its wallets
+pub async fn retrieve_customer(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let customer = match find_customer_or_404(&state, db, &id) {
+        Ok(customer) => customer,
+        Err(response) => return response,
+    };
+    let wallets = match db.list_customer_wallets(&id) {
+        Ok(wallets) => wallets,
+        Err(e) => return api_error(e.to_string()),
+    };
+
+    let (transactions, receipts) = if wallets.is_empty() {
+        (vec![], vec![])
+    } else {
+        let transactions = match db.list_transactions(
+            CUSTOMER_HISTORY_LIMIT,
+            None,
+            Some(&wallets),
+            &state.payment_stack_id,
+            state.use_sandbox,
+        ) {
+            Ok((transactions, _)) => transactions,
+            Err(e) => {
+                error!("Failed to list transactions of {}: {}", id, e);
+                return api_error(e.to_string());
+            }
+        };
+        let receipts = match customer_receipts(&state, db, &wallets) {
+            Ok(receipts) => receipts,
+            Err(e) => {
+                error!("Failed to list receipts of {}: {}", id, e);
+                return api_error(e);
+            }
+        };
+        (transactions, receipts)
     };
 
-    (StatusCode::OK, Json(customer)).into_response()
+    let mut response = stripe_customer(&state, &customer);
+    response.wallets = wallets;
+    response.transactions = Some(transactions);
+    response.receipts = Some(receipts);
+    (StatusCode::OK, Json(response)).into_response()
 }
 
 /// POST /v1/customers/:id - Update a customer
+///
+/// `wallets` are attached in addition to the customer's current wallets.
 pub async fn update_customer(
-    Extension(_state): Extension<CatalogState>,
-    Path(customer_id): Path<String>,
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
     body: Bytes,
 ) -> impl IntoResponse {
-    let created = chrono::Utc::now().timestamp();
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let customer = match find_customer_or_404(&state, db, &id) {
+        Ok(customer) => customer,
+        Err(response) => return response,
+    };
+    let request = match CustomerRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(response) = check_wallets(&state, db, Some(&id), &request.wallets) {
+        return response;
+    }
 
-    // Parse form-encoded body manually to handle nested structures like invoice_settings[default_payment_method]
-    let body_str = String::from_utf8_lossy(&body);
+    // Empty strings unset fields
+    let mut changes = UpdateCustomer {
+        email: request.email.map(|e| Some(e).filter(|e| !e.is_empty())),
+        name: request.name.map(|n| Some(n).filter(|n| !n.is_empty())),
+        description: request
+            .description
+            .map(|d| Some(d).filter(|d| !d.is_empty())),
+        phone: request.phone.map(|p| Some(p).filter(|p| !p.is_empty())),
+        ..Default::default()
+    };
+    if !request.metadata.is_empty() {
+        let mut metadata = customer.metadata();
+        for (key, value) in request.metadata {
+            // Stripe removes metadata keys set to an empty string
+            if value.is_empty() {
+                metadata.remove(&key);
+            } else {
+                metadata.insert(key, value);
+            }
+        }
+        changes.metadata = Some(encode_metadata(&metadata));
+    }
 
-    // Extract default_payment_method from invoice_settings[default_payment_method]
-    let default_pm = body_str
-        .split('&')
-        .find(|part| part.contains("invoice_settings") && part.contains("default_payment_method"))
-        .and_then(|part| part.split('=').nth(1))
-        .map(|pm| urlencoding::decode(pm).unwrap_or_default().to_string());
+    let updated = match db.update_customer(&id, changes) {
+        Ok(Some(updated)) => updated,
+        Ok(None) => {
+            return customer_error(
+                StatusCode::NOT_FOUND,
+                "resource_missing",
+                format!("No such customer: '{}'", id),
+            );
+        }
+        Err(e) => {
+            error!("Failed to update customer {}: {}", id, e);
+            return api_error(e.to_string());
+        }
+    };
+    if let Err(response) = attach_wallets(&state, db, &id, &request.wallets) {
+        return response;
+    }
+    emit_stripe_update(
+        &state,
+        "customer.updated",
+        &stripe_customer(&state, &customer),
+        &stripe_customer(&state, &updated),
+        current_time(&state, Some(&id)).0,
+    );
+    customer_response(&state, db, &updated)
+}
 
-    // Return a mock updated customer with the payment method set
-    let mut metadata = std::collections::HashMap::new();
-    if let Some(pm) = default_pm {
-        metadata.insert("default_payment_method".to_string(), pm);
+/// DELETE /v1/customers/:id - Delete a customer and detach its wallets
+///
+/// Payments already made from the wallets are kept, unattributed.
+pub async fn delete_customer(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let customer = match find_customer_or_404(&state, db, &id) {
+        Ok(customer) => customer,
+        Err(response) => return response,
+    };
+    let (now, _) = current_time(&state, Some(&id));
+    if let Err(e) = db.delete_customer(&id) {
+        error!("Failed to delete customer {}: {}", id, e);
+        return api_error(e.to_string());
     }
+    state.customer_test_clocks.lock().unwrap().remove(&id);
+    emit_stripe_event(
+        &state,
+        "customer.deleted",
+        &stripe_customer(&state, &customer),
+        now,
+    );
 
-    let customer = StripeCustomer {
-        id: customer_id,
-        object: "customer".to_string(),
-        email: "john.doe@example.com".to_string(),
-        name: Some("John Doe".to_string()),
-        metadata: Some(serde_json::to_value(metadata).unwrap()),
-        created,
-        description: None,
-        phone: None,
+    (
+        StatusCode::OK,
+        Json(DeletedCustomer {
+            id,
+            object: "customer".to_string(),
+            deleted: true,
+        }),
+    )
+        .into_response()
+}
+
+/// Query parameters of GET /v1/customers
+#[derive(Debug, Deserialize)]
+pub struct ListCustomersParams {
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub starting_after: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+/// Respond with a page of customers and their wallets
+fn customers_page(
+    state: &CatalogState,
+    db: &DbManager,
+    customers: &[CustomerModel],
+) -> Result<Vec<StripeCustomer>, Response> {
+    customers
+        .iter()
+        .map(|customer| {
+            let mut response = stripe_customer(state, customer);
+            response.wallets = db
+                .list_customer_wallets(&customer.customer_id)
+                .map_err(|e| api_error(e.to_string()))?;
+            Ok(response)
+        })
+        .collect()
+}
+
+/// GET /v1/customers - List customers
+pub async fn list_customers(
+    Extension(state): Extension<CatalogState>,
+    StripeQuery(params): StripeQuery<ListCustomersParams>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let limit = params.limit.unwrap_or(10).clamp(1, 100) as usize;
+
+    let starting_after = match params.starting_after.as_deref() {
+        Some(cursor) => match find_customer_or_404(&state, db, cursor) {
+            Ok(customer) => Some(customer.id),
+            Err(response) => return response,
+        },
+        None => None,
+    };
+
+    let (customers, has_more) = match db.list_customers(
+        limit,
+        starting_after,
+        params.email.as_deref(),
+        &state.payment_stack_id,
+        state.use_sandbox,
+    ) {
+        Ok(page) => page,
+        Err(e) => {
+            error!("Failed to list customers: {}", e);
+            return api_error(e.to_string());
+        }
+    };
+    let data = match customers_page(&state, db, &customers) {
+        Ok(data) => data,
+        Err(response) => return response,
+    };
+
+    let response = ListResponse {
+        object: "list".to_string(),
+        data,
+        has_more,
+        url: "/v1/customers".to_string(),
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// Parse a Stripe search query over customers.
+///
+/// Supports `email`, `name`, `phone` and `metadata['key']` clauses, matched exactly with
+/// `:` or as a substring with `~`, joined with `AND`.
+pub fn parse_search_query(query: &str) -> Result<Vec<CustomerSearchClause>, String> {
+    let mut clauses = Vec::new();
+    for clause in query.split(" AND ") {
+        let clause = clause.trim();
+        if clause.split_whitespace().any(|word| word == "OR") {
+            return Err("OR queries aren't supported, use AND".to_string());
+        }
+        let split = clause
+            .find([':', '~'])
+            .ok_or_else(|| format!("Invalid search clause: '{}'", clause))?;
+        let (field, rest) = clause.split_at(split);
+        let substring = rest.starts_with('~');
+        let value = unquote(&rest[1..])
+            .ok_or_else(|| format!("Search values must be quoted: '{}'", clause))?;
+
+        let field = match field.trim() {
+            "email" => CustomerSearchField::Email,
+            "name" => CustomerSearchField::Name,
+            "phone" => CustomerSearchField::Phone,
+            other => {
+                let key = other
+                    .strip_prefix("metadata[")
+                    .and_then(|k| k.strip_suffix(']'))
+                    .and_then(unquote)
+                    .ok_or_else(|| format!("Unsupported search field: '{}'", other))?;
+                if substring {
+                    return Err("Metadata can only be matched exactly".to_string());
+                }
+                CustomerSearchField::Metadata(key)
+            }
+        };
+        clauses.push(CustomerSearchClause {
+            field,
+            value,
+            substring,
+        });
+    }
+    Ok(clauses)
+}
+
+/// Value between single or double quotes
+fn unquote(value: &str) -> Option<String> {
+    let value = value.trim();
+    ['\'', '"'].into_iter().find_map(|quote| {
+        value
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+            .map(str::to_string)
+    })
+}
+
+/// Query parameters of GET /v1/customers/search
+#[derive(Debug, Deserialize)]
+pub struct SearchCustomersParams {
+    pub query: String,
+    #[serde(default)]
+    pub limit: Option<i64>,
+    /// `next_page` of the previous page
+    #[serde(default)]
+    pub page: Option<String>,
+}
+
+/// GET /v1/customers/search - Search customers
+pub async fn search_customers(
+    Extension(state): Extension<CatalogState>,
+    StripeQuery(params): StripeQuery<SearchCustomersParams>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let clauses = match parse_search_query(&params.query) {
+        Ok(clauses) => clauses,
+        Err(message) => {
+            return customer_error(StatusCode::BAD_REQUEST, "parameter_invalid", message);
+        }
     };
+    let limit = params.limit.unwrap_or(10).clamp(1, 100) as usize;
+
+    // Pages continue after the last customer of the previous one
+    let starting_after = match params.page.as_deref() {
+        Some(page) => match find_customer_or_404(&state, db, page) {
+            Ok(customer) => Some(customer.id),
+            Err(response) => return response,
+        },
+        None => None,
+    };
+
+    let (customers, has_more) = match db.search_customers(
+        &clauses,
+        limit,
+        starting_after,
+        &state.payment_stack_id,
+        state.use_sandbox,
+    ) {
+        Ok(page) => page,
+        Err(e) => {
+            error!("Failed to search customers: {}", e);
+            return api_error(e.to_string());
+        }
+    };
+    let next_page = has_more
+        .then(|| customers.last().map(|c| c.customer_id.clone()))
+        .flatten();
+    let data = match customers_page(&state, db, &customers) {
+        Ok(data) => data,
+        Err(response) => return response,
+    };
+
+    let response = SearchResponse {
+        object: "search_result".to_string(),
+        data,
+        has_more,
+        next_page,
+        url: "/v1/customers/search".to_string(),
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// POST /v1/customers/:id/wallets - Attach a wallet `address` to a customer
+pub async fn attach_customer_wallet(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let customer = match find_customer_or_404(&state, db, &id) {
+        Ok(customer) => customer,
+        Err(response) => return response,
+    };
+    let address = match StripeForm::decode(&body).and_then(|form| form.required_string("address")) {
+        Ok(address) => address,
+        Err(e) => return e.into_response(),
+    };
+
+    let wallets = [address];
+    if let Err(response) = check_wallets(&state, db, Some(&id), &wallets)
+        .and_then(|_| attach_wallets(&state, db, &id, &wallets))
+    {
+        return response;
+    }
+    customer_response(&state, db, &customer)
+}
+
+/// DELETE /v1/customers/:id/wallets/:address - Detach a wallet from a customer
+pub async fn detach_customer_wallet(
+    Extension(state): Extension<CatalogState>,
+    Path((id, address)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let customer = match find_customer_or_404(&state, db, &id) {
+        Ok(customer) => customer,
+        Err(response) => return response,
+    };
+    match db.detach_customer_wallet(&id, &address) {
+        Ok(true) => customer_response(&state, db, &customer),
+        Ok(false) => customer_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such wallet on customer '{}': '{}'", id, address),
+        ),
+        Err(e) => {
+            error!("Failed to detach wallet {} from {}: {}", address, id, e);
+            api_error(e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::Value;
+    use solana_pubkey::Pubkey;
+
+    use super::*;
+
+    fn state() -> CatalogState {
+        CatalogState::new(
+            Vec::new(),
+            Vec::new(),
+            true,
+            "http://localhost:8488".parse().unwrap(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+    }
+
+    fn db_state() -> CatalogState {
+        state().with_payment_db(Arc::new(DbManager::local(":memory:").unwrap()), "test")
+    }
 
-    (StatusCode::OK, Json(customer)).into_response()
+    fn wallet(seed: u8) -> String {
+        Pubkey::new_from_array([seed; 32]).to_string()
+    }
+
+    async fn body(response: Response) -> (StatusCode, Value) {
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    async fn create(state: &CatalogState, form: String) -> (StatusCode, Value) {
+        body(
+            create_customer(Extension(state.clone()), Bytes::from(form))
+                .await
+                .into_response(),
+        )
+        .await
+    }
+
+    async fn update(state: &CatalogState, id: &str, form: &'static str) -> (StatusCode, Value) {
+        body(
+            update_customer(
+                Extension(state.clone()),
+                Path(id.to_string()),
+                Bytes::from(form),
+            )
+            .await
+            .into_response(),
+        )
+        .await
+    }
+
+    async fn retrieve(state: &CatalogState, id: &str) -> (StatusCode, Value) {
+        body(
+            retrieve_customer(Extension(state.clone()), Path(id.to_string()))
+                .await
+                .into_response(),
+        )
+        .await
+    }
+
+    async fn delete(state: &CatalogState, id: &str) -> (StatusCode, Value) {
+        body(
+            delete_customer(Extension(state.clone()), Path(id.to_string()))
+                .await
+                .into_response(),
+        )
+        .await
+    }
+
+    async fn list(
+        state: &CatalogState,
+        limit: Option<i64>,
+        starting_after: Option<&str>,
+        email: Option<&str>,
+    ) -> (StatusCode, Value) {
+        let params = ListCustomersParams {
+            limit,
+            starting_after: starting_after.map(str::to_string),
+            email: email.map(str::to_string),
+        };
+        body(
+            list_customers(Extension(state.clone()), StripeQuery(params))
+                .await
+                .into_response(),
+        )
+        .await
+    }
+
+    #[test]
+    fn test_parse_search_query() {
+        let clauses =
+            parse_search_query("email:'jane@example.com' AND metadata['plan']:\"pro\"").unwrap();
+        assert_eq!(clauses.len(), 2);
+        assert_eq!(clauses[0].field, CustomerSearchField::Email);
+        assert_eq!(clauses[0].value, "jane@example.com");
+        assert!(!clauses[0].substring);
+        assert_eq!(
+            clauses[1].field,
+            CustomerSearchField::Metadata("plan".to_string())
+        );
+        assert_eq!(clauses[1].value, "pro");
+
+        let clauses = parse_search_query("name~'Jan'").unwrap();
+        assert_eq!(clauses[0].field, CustomerSearchField::Name);
+        assert!(clauses[0].substring);
+
+        assert!(parse_search_query("email:jane@example.com").is_err());
+        assert!(parse_search_query("email:'a' OR email:'b'").is_err());
+        assert!(parse_search_query("address:'x'").is_err());
+    }
+
+    #[test]
+    fn test_customer_request_parse() {
+        let body = Bytes::from(
+            "email=jane%40example.com&metadata[plan]=pro&wallets[]=addr1&wallets[1]=addr2\
+             &invoice_settings[default_payment_method]=pm_123",
+        );
+        let request = CustomerRequest::parse(&body).unwrap();
+        assert_eq!(request.email.as_deref(), Some("jane@example.com"));
+        assert_eq!(request.wallets, vec!["addr1", "addr2"]);
+        assert_eq!(
+            request.metadata.get("plan").map(String::as_str),
+            Some("pro")
+        );
+        assert_eq!(
+            request
+                .metadata
+                .get("default_payment_method")
+                .map(String::as_str),
+            Some("pm_123")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_update_delete_customer() {
+        let state = db_state();
+        let (status, customer) = create(
+            &state,
+            format!(
+                "email=jane%40example.com&name=Jane&metadata[plan]=pro&metadata[team]=core\
+                 &wallets[]={}",
+                wallet(1)
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let id = customer["id"].as_str().unwrap().to_string();
+        assert!(id.starts_with("cus_"));
+        assert_eq!(customer["email"], "jane@example.com");
+        assert_eq!(customer["metadata"]["plan"], "pro");
+        assert_eq!(customer["wallets"], serde_json::json!([wallet(1)]));
+        assert_eq!(customer["livemode"], false);
+
+        // Empty strings unset fields and remove metadata keys
+        let (status, updated) = update(
+            &state,
+            &id,
+            "name=&phone=%2B33600000000&metadata[plan]=&metadata[seats]=3",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated["email"], "jane@example.com");
+        assert_eq!(updated["name"], Value::Null);
+        assert_eq!(updated["phone"], "+33600000000");
+        assert_eq!(
+            updated["metadata"],
+            serde_json::json!({ "team": "core", "seats": "3" })
+        );
+
+        let (status, retrieved) = retrieve(&state, &id).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(retrieved["phone"], "+33600000000");
+        assert_eq!(retrieved["wallets"], serde_json::json!([wallet(1)]));
+        assert_eq!(retrieved["transactions"], serde_json::json!([]));
+        assert_eq!(retrieved["receipts"], serde_json::json!([]));
+
+        let (status, deleted) = delete(&state, &id).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            deleted,
+            serde_json::json!({ "id": id, "object": "customer", "deleted": true })
+        );
+        let (status, error) = retrieve(&state, &id).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error["error"]["code"], "resource_missing");
+        assert_eq!(
+            update(&state, &id, "name=Jane").await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(delete(&state, &id).await.0, StatusCode::NOT_FOUND);
+
+        // Its wallet was detached and can be attached to another customer
+        let (status, _) = create(&state, format!("wallets[]={}", wallet(1))).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_list_customers_pagination() {
+        let state = db_state();
+        let mut ids = Vec::new();
+        for email in ["a%40example.com", "b%40example.com", "c%40example.com"] {
+            let (_, customer) = create(&state, format!("email={}", email)).await;
+            ids.push(customer["id"].as_str().unwrap().to_string());
+        }
+
+        // Newest first
+        let (status, page) = list(&state, Some(2), None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["object"], "list");
+        assert_eq!(page["url"], "/v1/customers");
+        assert_eq!(page["has_more"], true);
+        let page_ids = page["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|customer| customer["id"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(page_ids, vec![ids[2].as_str(), ids[1].as_str()]);
+
+        let (_, page) = list(&state, Some(2), Some(&ids[1]), None).await;
+        assert_eq!(page["has_more"], false);
+        assert_eq!(page["data"].as_array().unwrap().len(), 1);
+        assert_eq!(page["data"][0]["id"], ids[0].as_str());
+
+        let (_, page) = list(&state, None, None, Some("b@example.com")).await;
+        assert_eq!(page["data"].as_array().unwrap().len(), 1);
+        assert_eq!(page["data"][0]["id"], ids[1].as_str());
+
+        // Limits are clamped to 1..=100
+        let (_, page) = list(&state, Some(0), None, None).await;
+        assert_eq!(page["data"].as_array().unwrap().len(), 1);
+
+        let (status, error) = list(&state, None, Some("cus_unknown"), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error["error"]["code"], "resource_missing");
+    }
+
+    #[tokio::test]
+    async fn test_customer_errors() {
+        // Customers need a payment database
+        let (status, error) = create(&state(), "email=jane%40example.com".to_string()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error["error"]["code"], "customers_not_configured");
+
+        let state = db_state();
+        let (status, error) = create(&state, "wallets[]=not-a-wallet".to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"]["code"], "parameter_invalid");
+
+        let (status, error) = create(&state, "test_clock=clock_unknown".to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"]["code"], "resource_missing");
+
+        // A wallet belongs to one customer
+        let (_, jane) = create(&state, format!("wallets[]={}", wallet(1))).await;
+        let (_, john) = create(&state, String::new()).await;
+        let john = john["id"].as_str().unwrap();
+        let (status, error) = body(
+            attach_customer_wallet(
+                Extension(state.clone()),
+                Path(john.to_string()),
+                Bytes::from(format!("address={}", wallet(1))),
+            )
+            .await
+            .into_response(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"]["code"], "wallet_already_attached");
+        let (status, error) = create(&state, format!("wallets[]={}", wallet(1))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"]["code"], "wallet_already_attached");
+
+        // Attaching a wallet again to its customer is accepted
+        let jane = jane["id"].as_str().unwrap();
+        let (status, customer) = body(
+            attach_customer_wallet(
+                Extension(state.clone()),
+                Path(jane.to_string()),
+                Bytes::from(format!("address={}", wallet(1))),
+            )
+            .await
+            .into_response(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(customer["wallets"], serde_json::json!([wallet(1)]));
+
+        let detach = |id: &str, address: String| {
+            detach_customer_wallet(Extension(state.clone()), Path((id.to_string(), address)))
+        };
+        let (status, error) = body(detach(john, wallet(1)).await.into_response()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error["error"]["code"], "resource_missing");
+        let (status, customer) = body(detach(jane, wallet(1)).await.into_response()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(customer["wallets"], serde_json::json!([]));
+
+        // A missing wallet address is a form error
+        let (status, _) = body(
+            attach_customer_wallet(
+                Extension(state.clone()),
+                Path(jane.to_string()),
+                Bytes::new(),
+            )
+            .await
+            .into_response(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
 }
@@ -0,0 +1,608 @@
+//! Entitlements of customers to the features of catalog products
+//!
+//! Entitlement features are the keys of the products' `features`; an experiment variant
+//! grants its parent's features with its own overrides. A customer is entitled to the
+//! features of the products it subscribes to until the end of the current period, and to
+//! those of the products paid from its wallets until their receipt expires.
+
+use std::collections::HashMap;
+
+use axum::{
+    Extension, Json,
+    extract::{Path, RawQuery},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use moneymq_types::Product;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{debug, error, warn};
+
+use crate::api::{
+    catalog::{
+        Catalog, CatalogState,
+        middleware::get_product_features,
+        stripe::{
+            endpoints::{
+                customers::customer_receipts, subscriptions::find_catalog_price,
+                test_clocks::current_time,
+            },
+            form::{FormError, StripeQuery},
+            list::CatalogQuery,
+            types::{ListResponse, StripeActiveEntitlement, StripeEntitlementFeature},
+        },
+    },
+    payment::db::{DbManager, subscription::RENEWABLE_STATUSES},
+};
+
+/// Prefix of entitlement feature IDs, followed by the feature's lookup key
+const FEATURE_ID_PREFIX: &str = "feat_";
+
+fn entitlement_error(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(serde_json::json!({
+            "error": {
+                "code": code,
+                "message": message.into(),
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+fn payment_db(state: &CatalogState) -> Result<&DbManager, Response> {
+    state.payment_db.as_deref().ok_or_else(|| {
+        entitlement_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "entitlements_not_configured",
+            "Active entitlements require a database",
+        )
+    })
+}
+
+/// Features defined by the catalog's products, in catalog order
+pub(crate) fn entitlement_features(state: &CatalogState) -> Vec<StripeEntitlementFeature> {
+    let catalog = state.catalog();
+    let mut features: Vec<StripeEntitlementFeature> = Vec::new();
+    for product in catalog.products.iter() {
+        let Some(serde_json::Value::Object(product_features)) =
+            get_product_features(&catalog.products, product)
+        else {
+            continue;
+        };
+        for (lookup_key, feature) in product_features {
+            if let Some(existing) = features.iter_mut().find(|f| f.lookup_key == lookup_key) {
+                existing.products.push(product.id.clone());
+                continue;
+            }
+            let text = |field: &str| {
+                feature
+                    .get(field)
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string)
+            };
+            features.push(StripeEntitlementFeature {
+                id: format!("{}{}", FEATURE_ID_PREFIX, lookup_key),
+                object: "entitlements.feature".to_string(),
+                active: product.active,
+                livemode: !state.use_sandbox,
+                name: text("name").unwrap_or_else(|| lookup_key.clone()),
+                description: text("description"),
+                lookup_key,
+                metadata: HashMap::new(),
+                products: vec![product.id.clone()],
+            });
+        }
+    }
+    features
+}
+
+/// Catalog product with the given ID or deployed ID
+fn find_catalog_product<'a>(catalog: &'a Catalog, id: &str) -> Option<&'a Product> {
+    catalog.products.iter().find(|product| {
+        product.id == id
+            || product.deployed_id.as_deref() == Some(id)
+            || product
+                .sandboxes
+                .values()
+                .any(|sandbox_id| sandbox_id == id)
+    })
+}
+
+/// Products paid for by a receipt, from its `productId`: either a single ID or a basket
+/// of `{"productId", "experimentId"}` items, where the experiment variant is the product
+pub(crate) fn basket_product_ids(product_id: &str) -> Vec<String> {
+    match serde_json::from_str::<Vec<serde_json::Value>>(product_id) {
+        Ok(items) => items
+            .iter()
+            .filter_map(|item| {
+                item.get("experimentId")
+                    .and_then(|v| v.as_str())
+                    .filter(|s| !s.is_empty())
+                    .or_else(|| item.get("productId").and_then(|v| v.as_str()))
+                    .map(str::to_string)
+            })
+            .collect(),
+        Err(_) if !product_id.is_empty() => vec![product_id.to_string()],
+        Err(_) => vec![],
+    }
+}
+
+/// Expiry (Unix seconds) of a receipt JWT, read from its claims
+fn receipt_expiry(receipt: &str) -> Option<i64> {
+    let payload = URL_SAFE_NO_PAD.decode(receipt.split('.').nth(1)?).ok()?;
+    serde_json::from_slice::<serde_json::Value>(&payload)
+        .ok()?
+        .get("exp")?
+        .as_i64()
+}
+
+/// Deterministic ID of a customer's entitlement to a feature
+fn entitlement_id(customer: &str, lookup_key: &str) -> String {
+    let digest = Sha256::digest(format!("{}:{}", customer, lookup_key));
+    format!("ent_{}", &hex::encode(digest)[..24])
+}
+
+/// Source granting a product's features
+struct Grant<'a> {
+    product: &'a Product,
+    expires_at: i64,
+    subscription: Option<String>,
+    receipt: Option<String>,
+}
+
+/// Add the features granted by `grant`, keeping the latest expiry of each feature
+fn add_grant(
+    state: &CatalogState,
+    customer: &str,
+    entitlements: &mut Vec<StripeActiveEntitlement>,
+    grant: Grant<'_>,
+) {
+    let Some(serde_json::Value::Object(features)) =
+        get_product_features(&state.catalog().products, grant.product)
+    else {
+        return;
+    };
+    for (lookup_key, feature) in features {
+        let entitlement = StripeActiveEntitlement {
+            id: entitlement_id(customer, &lookup_key),
+            object: "entitlements.active_entitlement".to_string(),
+            feature: format!("{}{}", FEATURE_ID_PREFIX, lookup_key),
+            livemode: !state.use_sandbox,
+            value: feature.get("value").filter(|v| !v.is_null()).cloned(),
+            product: grant.product.id.clone(),
+            expires_at: grant.expires_at,
+            subscription: grant.subscription.clone(),
+            receipt: grant.receipt.clone(),
+            lookup_key,
+        };
+        match entitlements
+            .iter_mut()
+            .find(|e| e.lookup_key == entitlement.lookup_key)
+        {
+            Some(existing) if existing.expires_at >= entitlement.expires_at => {}
+            Some(existing) => *existing = entitlement,
+            None => entitlements.push(entitlement),
+        }
+    }
+}
+
+/// Features `customer` is entitled to now, from its active subscriptions and the
+/// unexpired receipts of its wallets' payments
+pub(crate) fn active_entitlements(
+    state: &CatalogState,
+    db: &DbManager,
+    customer: &str,
+) -> Result<Vec<StripeActiveEntitlement>, String> {
+    let (now, _) = current_time(state, Some(customer));
+    let catalog = state.catalog();
+    let mut entitlements = Vec::new();
+
+    let mut starting_after = None;
+    loop {
+        let (subscriptions, has_more) = db
+            .list_subscriptions(
+                100,
+                starting_after,
+                Some(customer),
+                None,
+                &state.payment_stack_id,
+                state.use_sandbox,
+            )
+            .map_err(|e| e.to_string())?;
+        for subscription in subscriptions
+            .iter()
+            .filter(|s| RENEWABLE_STATUSES.contains(&s.status.as_str()))
+        {
+            for item in subscription.items() {
+                if let Some((product, _)) = find_catalog_price(state, &catalog, &item.price) {
+                    let grant = Grant {
+                        product,
+                        expires_at: subscription.current_period_end,
+                        subscription: Some(subscription.subscription_id.clone()),
+                        receipt: None,
+                    };
+                    add_grant(state, customer, &mut entitlements, grant);
+                }
+            }
+        }
+        match subscriptions.last() {
+            Some(last) if has_more => starting_after = Some(last.id),
+            _ => break,
+        }
+    }
+
+    let wallets = db
+        .list_customer_wallets(customer)
+        .map_err(|e| e.to_string())?;
+    if !wallets.is_empty() {
+        for receipt in customer_receipts(state, db, &wallets)? {
+            let Some(expires_at) = receipt_expiry(&receipt.receipt) else {
+                warn!("Unreadable receipt for {}", receipt.transaction_id);
+                continue;
+            };
+            let product_ids = basket_product_ids(receipt.product_id.as_deref().unwrap_or(""));
+            for product in product_ids
+                .iter()
+                .filter_map(|id| find_catalog_product(&catalog, id))
+            {
+                let grant = Grant {
+                    product,
+                    expires_at,
+                    subscription: None,
+                    receipt: Some(receipt.transaction_id.clone()),
+                };
+                add_grant(state, customer, &mut entitlements, grant);
+            }
+        }
+    }
+
+    entitlements.retain(|e| e.expires_at > now);
+    Ok(entitlements)
+}
+
+/// Header carrying a payment receipt of the customer accessing a product
+pub const RECEIPT_HEADER: &str = "X-Payment-Receipt";
+
+/// Whether `receipt` is a receipt we signed for a payment from one of `customer`'s wallets
+fn receipt_proves_customer(
+    state: &CatalogState,
+    db: &DbManager,
+    customer: &str,
+    receipt: &str,
+) -> bool {
+    let Some(receipt_key) = &state.receipt_key else {
+        return false;
+    };
+    let claims = match receipt_key.verify(receipt) {
+        Ok(claims) => claims,
+        Err(e) => {
+            debug!("Rejected receipt presented for {}: {}", customer, e);
+            return false;
+        }
+    };
+    match db.list_customer_wallets(customer) {
+        Ok(wallets) => wallets.contains(&claims.payment.payer),
+        Err(e) => {
+            error!("Failed to list wallets of {}: {}", customer, e);
+            false
+        }
+    }
+}
+
+/// Active entitlements of `customer` covering every feature of a product, or None if the
+/// product has no features, the customer lacks any of them, or `receipt` isn't a valid
+/// receipt of one of the customer's payments
+pub(crate) fn product_entitlements(
+    state: &CatalogState,
+    customer: &str,
+    receipt: Option<&str>,
+    product_id: &str,
+) -> Option<Vec<StripeActiveEntitlement>> {
+    let db = state.payment_db.as_deref()?;
+    if !receipt.is_some_and(|receipt| receipt_proves_customer(state, db, customer, receipt)) {
+        return None;
+    }
+    let catalog = state.catalog();
+    let product = catalog.products.iter().find(|p| p.id == product_id)?;
+    let Some(serde_json::Value::Object(features)) =
+        get_product_features(&catalog.products, product)
+    else {
+        return None;
+    };
+    let entitlements = match active_entitlements(state, db, customer) {
+        Ok(entitlements) => entitlements,
+        Err(e) => {
+            error!("Failed to compute entitlements of {}: {}", customer, e);
+            return None;
+        }
+    };
+    features
+        .keys()
+        .map(|key| entitlements.iter().find(|e| &e.lookup_key == key).cloned())
+        .collect()
+}
+
+/// GET /v1/entitlements/features - List the features defined by the catalog
+///
+/// Features can be filtered by `lookup_key`.
+pub async fn list_entitlement_features(
+    Extension(state): Extension<CatalogState>,
+    RawQuery(query): RawQuery,
+) -> impl IntoResponse {
+    let query = match CatalogQuery::parse(query.as_deref(), &[]) {
+        Ok(query) => query,
+        Err(e) => return e.into_response(),
+    };
+    let features = entitlement_features(&state)
+        .into_iter()
+        .filter(|f| {
+            query
+                .param("lookup_key")
+                .is_none_or(|key| f.lookup_key == key)
+        })
+        .collect::<Vec<_>>();
+    let (data, has_more) = match query.paginate(features, "entitlements.feature", |f| f.id.as_str())
+    {
+        Ok(page) => page,
+        Err(e) => return e.into_response(),
+    };
+
+    Json(ListResponse {
+        object: "list".to_string(),
+        data,
+        has_more,
+        url: "/v1/entitlements/features".to_string(),
+    })
+    .into_response()
+}
+
+/// GET /v1/entitlements/features/:id - Retrieve a feature by ID or lookup key
+pub async fn retrieve_entitlement_feature(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match entitlement_features(&state)
+        .into_iter()
+        .find(|f| f.id == id || f.lookup_key == id)
+    {
+        Some(feature) => (StatusCode::OK, Json(feature)).into_response(),
+        None => entitlement_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such feature: '{}'", id),
+        ),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListActiveEntitlementsParams {
+    pub customer: Option<String>,
+    pub limit: Option<i64>,
+    pub starting_after: Option<String>,
+}
+
+/// GET /v1/entitlements/active_entitlements - List a customer's active entitlements
+pub async fn list_active_entitlements(
+    Extension(state): Extension<CatalogState>,
+    StripeQuery(params): StripeQuery<ListActiveEntitlementsParams>,
+) -> impl IntoResponse {
+    let Some(customer) = params.customer.filter(|c| !c.is_empty()) else {
+        return FormError::Missing("customer".to_string()).into_response();
+    };
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let entitlements = match active_entitlements(&state, db, &customer) {
+        Ok(entitlements) => entitlements,
+        Err(e) => {
+            error!("Failed to compute entitlements of {}: {}", customer, e);
+            return entitlement_error(StatusCode::INTERNAL_SERVER_ERROR, "api_error", e);
+        }
+    };
+
+    let limit = params.limit.unwrap_or(10).clamp(1, 100) as usize;
+    let start = params
+        .starting_after
+        .as_ref()
+        .and_then(|id| entitlements.iter().position(|e| &e.id == id))
+        .map(|idx| idx + 1)
+        .unwrap_or(0);
+    let page = entitlements.iter().skip(start);
+
+    (
+        StatusCode::OK,
+        Json(ListResponse {
+            object: "list".to_string(),
+            data: page.clone().take(limit).cloned().collect(),
+            has_more: page.count() > limit,
+            url: "/v1/entitlements/active_entitlements".to_string(),
+        }),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use moneymq_types::{ExperimentConfig, ProductFeature};
+
+    use super::*;
+
+    fn product(id: &str, features: &[(&str, serde_json::Value)]) -> Product {
+        let mut product = Product::new();
+        product.id = id.to_string();
+        for (key, value) in features {
+            product.features.insert(
+                key.to_string(),
+                ProductFeature::new(key.to_uppercase(), "").with_value(value.clone()),
+            );
+        }
+        product
+    }
+
+    fn state_with_products(products: Vec<Product>) -> CatalogState {
+        CatalogState::new(
+            products,
+            vec![],
+            true,
+            "http://localhost:8080".parse().unwrap(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+    }
+
+    #[test]
+    fn test_basket_product_ids() {
+        assert_eq!(basket_product_ids("pro"), vec!["pro"]);
+        assert!(basket_product_ids("").is_empty());
+        assert_eq!(
+            basket_product_ids(
+                r#"[{"productId":"pro","quantity":1},{"productId":"pro","experimentId":"pro#a"}]"#
+            ),
+            vec!["pro", "pro#a"]
+        );
+    }
+
+    #[test]
+    fn test_receipt_expiry() {
+        let claims = URL_SAFE_NO_PAD.encode(r#"{"sub":"tx_1","exp":1700000000}"#);
+        assert_eq!(
+            receipt_expiry(&format!("header.{}.signature", claims)),
+            Some(1_700_000_000)
+        );
+        assert_eq!(receipt_expiry("not-a-jwt"), None);
+    }
+
+    #[test]
+    fn test_entitlement_features() {
+        let mut variant = product("pro#a", &[("seats", serde_json::json!(10))]);
+        variant.experiment = Some(ExperimentConfig { exposure: 1.0 });
+        variant.parent_id = Some("pro".to_string());
+        let state = state_with_products(vec![
+            product("basic", &[("api", serde_json::json!(true))]),
+            product(
+                "pro",
+                &[
+                    ("api", serde_json::json!(true)),
+                    ("seats", serde_json::json!(5)),
+                ],
+            ),
+            variant,
+        ]);
+
+        let features = entitlement_features(&state);
+        let keys = features
+            .iter()
+            .map(|f| f.lookup_key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["api", "seats"]);
+        assert_eq!(features[0].id, "feat_api");
+        assert_eq!(features[0].name, "API");
+        assert_eq!(features[0].products, vec!["basic", "pro", "pro#a"]);
+        assert_eq!(features[1].products, vec!["pro", "pro#a"]);
+    }
+
+    #[test]
+    fn test_receipts_prove_the_customer() {
+        use crate::api::payment::{
+            db::customer::NewCustomerWallet,
+            endpoints::jwt::{JwtKeyPair, PaymentReceiptClaims, defaults},
+        };
+
+        let key = Arc::new(JwtKeyPair::from_secret("receipts"));
+        let db = Arc::new(DbManager::local(":memory:").unwrap());
+        let state = state_with_products(vec![product("pro", &[("api", serde_json::json!(true))])])
+            .with_payment_db(db.clone(), "local")
+            .with_receipt_key(key.clone());
+        db.attach_customer_wallet(&NewCustomerWallet::new(
+            "cus_1".to_string(),
+            "wallet_1".to_string(),
+            "local".to_string(),
+            true,
+        ))
+        .unwrap();
+        let receipt = |key: &JwtKeyPair, payer: &str| {
+            key.sign(&PaymentReceiptClaims::new(
+                "tx_1".to_string(),
+                payer.to_string(),
+                "1000".to_string(),
+                defaults::CURRENCY.to_string(),
+                defaults::NETWORK.to_string(),
+                Some("pro".to_string()),
+                None,
+                None,
+                "local".to_string(),
+                defaults::JWT_EXPIRATION_HOURS,
+            ))
+            .unwrap()
+        };
+
+        assert!(receipt_proves_customer(
+            &state,
+            &db,
+            "cus_1",
+            &receipt(&key, "wallet_1")
+        ));
+        // Receipts of another customer's payments
+        assert!(!receipt_proves_customer(
+            &state,
+            &db,
+            "cus_1",
+            &receipt(&key, "wallet_2")
+        ));
+        // Receipts we didn't sign
+        let forger = JwtKeyPair::from_secret("forger");
+        assert!(!receipt_proves_customer(
+            &state,
+            &db,
+            "cus_1",
+            &receipt(&forger, "wallet_1")
+        ));
+        // Without a receipt, naming the customer grants nothing
+        assert_eq!(product_entitlements(&state, "cus_1", None, "pro"), None);
+    }
+
+    #[test]
+    fn test_add_grant_keeps_latest_expiry() {
+        let state = state_with_products(vec![
+            product("basic", &[("api", serde_json::json!(true))]),
+            product(
+                "pro",
+                &[
+                    ("api", serde_json::json!(true)),
+                    ("seats", serde_json::json!(5)),
+                ],
+            ),
+        ]);
+        let catalog = state.catalog();
+        let grant = |id: &str, expires_at: i64| Grant {
+            product: catalog.products.iter().find(|p| p.id == id).unwrap(),
+            expires_at,
+            subscription: None,
+            receipt: Some(format!("tx_{}", id)),
+        };
+
+        let mut entitlements = Vec::new();
+        add_grant(&state, "cus_1", &mut entitlements, grant("pro", 100));
+        add_grant(&state, "cus_1", &mut entitlements, grant("basic", 200));
+        add_grant(&state, "cus_1", &mut entitlements, grant("pro", 50));
+
+        assert_eq!(entitlements.len(), 2);
+        assert_eq!(entitlements[0].lookup_key, "api");
+        assert_eq!(entitlements[0].product, "basic");
+        assert_eq!(entitlements[0].expires_at, 200);
+        assert_eq!(entitlements[1].value, Some(serde_json::json!(5)));
+        assert_eq!(entitlements[1].expires_at, 100);
+        assert_eq!(entitlements[0].id, entitlement_id("cus_1", "api"));
+        assert_ne!(entitlements[0].id, entitlement_id("cus_2", "api"));
+    }
+}
@@ -0,0 +1,156 @@
+//! Stripe-format events recorded by the catalog, newest first
+//!
+//! These are the events delivered to the webhook endpoint (see
+//! [`crate::api::catalog::webhooks`]); they are listed even when no endpoint is configured.
+
+use axum::{
+    Extension, Json,
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use tracing::error;
+
+use crate::api::{
+    catalog::{
+        CatalogState,
+        stripe::{
+            form::StripeQuery,
+            types::{ListResponse, StripeEvent},
+        },
+    },
+    payment::db::{DbManager, StripeEventModel},
+};
+
+fn event_error(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(serde_json::json!({
+            "error": {
+                "code": code,
+                "message": message.into(),
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+fn api_error(message: impl Into<String>) -> Response {
+    event_error(StatusCode::INTERNAL_SERVER_ERROR, "api_error", message)
+}
+
+fn payment_db(state: &CatalogState) -> Result<&DbManager, Response> {
+    state.payment_db.as_deref().ok_or_else(|| {
+        event_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "events_not_configured",
+            "Events require a database",
+        )
+    })
+}
+
+fn find_event_or_404(
+    state: &CatalogState,
+    db: &DbManager,
+    id: &str,
+) -> Result<StripeEventModel, Response> {
+    match db.find_stripe_event(id, &state.payment_stack_id, state.use_sandbox) {
+        Ok(Some(event)) => Ok(event),
+        Ok(None) => Err(event_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such event: '{}'", id),
+        )),
+        Err(e) => {
+            error!("Failed to find event {}: {}", id, e);
+            Err(api_error(e.to_string()))
+        }
+    }
+}
+
+fn stripe_event(event: &StripeEventModel) -> Result<StripeEvent, Response> {
+    serde_json::from_str(&event.data_json).map_err(|e| {
+        error!("Failed to parse event {}: {}", event.event_id, e);
+        api_error(e.to_string())
+    })
+}
+
+/// Query parameters of GET /v1/events
+#[derive(Debug, Deserialize)]
+pub struct ListEventsParams {
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub starting_after: Option<String>,
+    /// Only list events of this type
+    #[serde(default, rename = "type")]
+    pub event_type: Option<String>,
+}
+
+/// GET /v1/events - List events, newest first
+pub async fn list_events(
+    Extension(state): Extension<CatalogState>,
+    StripeQuery(params): StripeQuery<ListEventsParams>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let limit = params.limit.unwrap_or(10).clamp(1, 100) as usize;
+
+    let starting_after = match params.starting_after.as_deref() {
+        Some(cursor) => match find_event_or_404(&state, db, cursor) {
+            Ok(event) => Some(event.id),
+            Err(response) => return response,
+        },
+        None => None,
+    };
+
+    let (events, has_more) = match db.list_stripe_events(
+        limit,
+        starting_after,
+        params.event_type.as_deref(),
+        &state.payment_stack_id,
+        state.use_sandbox,
+    ) {
+        Ok(page) => page,
+        Err(e) => {
+            error!("Failed to list events: {}", e);
+            return api_error(e.to_string());
+        }
+    };
+    let data = match events
+        .iter()
+        .map(stripe_event)
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(data) => data,
+        Err(response) => return response,
+    };
+
+    let response = ListResponse {
+        object: "list".to_string(),
+        data,
+        has_more,
+        url: "/v1/events".to_string(),
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// GET /v1/events/:id - Retrieve an event
+pub async fn retrieve_event(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let event = match find_event_or_404(&state, db, &id).and_then(|e| stripe_event(&e)) {
+        Ok(event) => event,
+        Err(response) => return response,
+    };
+    (StatusCode::OK, Json(event)).into_response()
+}
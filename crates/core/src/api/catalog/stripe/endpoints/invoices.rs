@@ -0,0 +1,1939 @@
+//! Invoices for subscription periods, metered usage and one-off items
+//!
+//! Invoices are created as `draft` with their lines and totals, then finalized to `open`,
+//! which creates the payment intent charging the amount due. They become `paid` once that
+//! intent succeeds, through x402 on `/v1/invoices/:id/pay`, a Solana Pay transfer or by
+//! confirming the intent, or are voided.
+//!
+//! Renewals invoice the new period of a subscription in advance, with the usage of the
+//! period that just ended above each price's included units (its `overage`). Pending
+//! invoice items are added to the customer's next invoice.
+
+use std::collections::HashMap;
+
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use moneymq_types::{Price, TaxBehavior, iac::OverageConfig};
+use serde::Deserialize;
+use tracing::{error, info, warn};
+
+use crate::{
+    api::{
+        catalog::{
+            CatalogState,
+            solana_pay::{payment_intent_transfer_request, transfer_request},
+            stripe::{
+                endpoints::{
+                    billing::{customer_usage, find_meter},
+                    coupons::find_coupon,
+                    payment_intents::emit_payment_intent_succeeded,
+                    subscriptions::{
+                        SUBSCRIPTION_METADATA_KEY, find_catalog_price, record_subscription_payment,
+                    },
+                    tax::{
+                        calculate_tax, check_tax_location, insert_tax_metadata, price_tax_behavior,
+                        tax_location, tax_rate_details,
+                    },
+                    test_clocks::{clock_time, current_time},
+                },
+                form::{FormError, StripeForm, StripeQuery},
+                types::{
+                    CaptureMethod, DeletedInvoiceItem, InvoiceDiscountAmount, InvoiceLines,
+                    InvoicePeriod, InvoiceStatusTransitions, InvoiceTaxAmount, ListResponse,
+                    PaymentIntentStatus, StripeInvoice, StripeInvoiceItem, StripeInvoiceLineItem,
+                    StripePaymentIntent, SubscriptionPrice, TaxAddress,
+                },
+                utils::generate_stripe_id,
+            },
+            webhooks::emit_stripe_event,
+        },
+        payment::db::{
+            DbManager, InvoiceItemModel, InvoiceModel, SubscriptionModel,
+            invoice::{
+                BILLING_REASON_MANUAL, KIND_INVOICE_ITEM, KIND_OVERAGE, KIND_SUBSCRIPTION,
+                NewInvoice, NewInvoiceItem, STATUS_DRAFT, STATUS_OPEN, STATUS_PAID, STATUS_VOID,
+                UpdateInvoice,
+            },
+        },
+    },
+    events::{CloudEvent, InvoiceEventData},
+    taxes::TaxCalculation,
+};
+
+/// Metadata key linking a payment intent to the invoice it charges
+pub const INVOICE_METADATA_KEY: &str = "invoice";
+
+/// Amounts of an invoice, in cents
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InvoiceTotals {
+    /// Sum of the lines
+    pub subtotal: i64,
+    pub discount: i64,
+    pub tax: i64,
+    pub total: i64,
+}
+
+impl InvoiceTotals {
+    /// Totals of lines summing to `line_amounts`: the discount is taken off the subtotal,
+    /// then tax is added on what remains
+    pub fn compute(line_amounts: &[i64], discount: i64, tax_percent: Option<f64>) -> Self {
+        let subtotal = line_amounts.iter().sum::<i64>();
+        let discount = discount.clamp(0, subtotal.max(0));
+        let taxable = subtotal - discount;
+        let tax = tax_percent
+            .filter(|_| taxable > 0)
+            .map(|percent| (taxable as f64 * percent / 100.0).round() as i64)
+            .unwrap_or(0);
+        InvoiceTotals {
+            subtotal,
+            discount,
+            tax,
+            total: taxable + tax,
+        }
+    }
+
+    /// Totals of lines summing to `line_amounts` with automatic tax computed on the
+    /// discounted lines: tax on tax-exclusive lines is added to the total, tax on
+    /// tax-inclusive lines is already part of the subtotal
+    pub fn compute_with_tax(line_amounts: &[i64], discount: i64, tax: &TaxCalculation) -> Self {
+        let subtotal = line_amounts.iter().sum::<i64>();
+        let discount = discount.clamp(0, subtotal.max(0));
+        InvoiceTotals {
+            subtotal,
+            discount,
+            tax: tax.amount_tax(),
+            total: subtotal - discount + tax.amount_exclusive(),
+        }
+    }
+
+    fn changes(&self) -> UpdateInvoice {
+        UpdateInvoice {
+            subtotal: Some(self.subtotal),
+            discount: Some(self.discount),
+            tax: Some(self.tax),
+            total: Some(self.total),
+            ..Default::default()
+        }
+    }
+}
+
+/// Units billed for `usage` above the included units of an overage (partial units are
+/// billed as a whole unit)
+pub fn overage_units(overage: &OverageConfig, usage: f64) -> i64 {
+    let included = overage.included.unwrap_or(0) as f64;
+    (usage - included).ceil().max(0.0) as i64
+}
+
+/// Amount per overage unit in `currency`, in cents (fractional below a cent)
+pub fn overage_unit_amount(overage: &OverageConfig, currency: &str) -> Option<f64> {
+    overage
+        .amounts
+        .iter()
+        .find(|(amount_currency, _)| amount_currency.eq_ignore_ascii_case(currency))
+        .map(|(_, amount)| amount * 100.0)
+}
+
+/// Unit amount (cents) of `quantity` units of `price`, tiered and package prices being
+/// charged for the whole quantity
+fn quantity_unit_amount(price: &Price, quantity: i64) -> f64 {
+    let amount = price
+        .amount_for(quantity, price.currency.as_str())
+        .unwrap_or(0);
+    amount as f64 / quantity.max(1) as f64
+}
+
+/// Lines billing every item of a subscription for `[period_start, period_end)`
+fn recurring_lines(
+    state: &CatalogState,
+    subscription: &SubscriptionModel,
+    period_start: i64,
+    period_end: i64,
+) -> Vec<NewInvoiceItem> {
+    let catalog = state.catalog();
+    subscription
+        .items()
+        .into_iter()
+        .filter_map(|item| {
+            let (product, price) = find_catalog_price(state, &catalog, &item.price)?;
+            let line = NewInvoiceItem::new(
+                generate_stripe_id("il"),
+                subscription.customer.clone(),
+                KIND_SUBSCRIPTION,
+                item.quantity,
+                quantity_unit_amount(price, item.quantity),
+                subscription.currency.clone(),
+                period_start,
+                period_end,
+                state.payment_stack_id.clone(),
+                state.use_sandbox,
+            )
+            .with_subscription(Some(subscription.subscription_id.clone()))
+            .with_price(Some(item.price.clone()))
+            .with_description(Some(format!(
+                "{} × {}",
+                item.quantity,
+                product.name.as_deref().unwrap_or(&product.id)
+            )));
+            Some(line)
+        })
+        .collect()
+}
+
+/// Lines billing the usage above each price's included units during the subscription
+/// period starting at `period_start`, measured until `usage_end`.
+///
+/// Units already billed for the same period (by an invoice created on demand) are
+/// deducted, so usage is billed once.
+fn overage_lines(
+    state: &CatalogState,
+    db: &DbManager,
+    subscription: &SubscriptionModel,
+    period_start: i64,
+    usage_end: i64,
+) -> Result<Vec<NewInvoiceItem>, String> {
+    let catalog = state.catalog();
+    let mut lines = Vec::new();
+    for item in subscription.items() {
+        let Some((product, price)) = find_catalog_price(state, &catalog, &item.price) else {
+            continue;
+        };
+        let Some(overage) = &price.overage else {
+            continue;
+        };
+        let Some(unit_amount) = overage_unit_amount(overage, &subscription.currency) else {
+            warn!(
+                "Price {} has no overage amount in {}",
+                item.price, subscription.currency
+            );
+            continue;
+        };
+        let Some(meter) = find_meter(state, &catalog, &overage.meter) else {
+            warn!(
+                "Price {} bills overage on unknown meter '{}'",
+                item.price, overage.meter
+            );
+            continue;
+        };
+
+        let usage = customer_usage(
+            state,
+            db,
+            meter,
+            &subscription.customer,
+            period_start,
+            usage_end,
+        )?;
+        let billed = db
+            .billed_overage_units(&subscription.subscription_id, &item.price, period_start)
+            .map_err(|e| e.to_string())?;
+        let units = overage_units(overage, usage) - billed;
+        if units <= 0 {
+            continue;
+        }
+
+        let meter_name = meter.display_name.as_deref().unwrap_or(&meter.event_name);
+        lines.push(
+            NewInvoiceItem::new(
+                generate_stripe_id("il"),
+                subscription.customer.clone(),
+                KIND_OVERAGE,
+                units,
+                unit_amount,
+                subscription.currency.clone(),
+                period_start,
+                usage_end,
+                state.payment_stack_id.clone(),
+                state.use_sandbox,
+            )
+            .with_subscription(Some(subscription.subscription_id.clone()))
+            .with_price(Some(item.price.clone()))
+            .with_description(Some(format!(
+                "{} × {} ({} above {} included)",
+                units,
+                product.name.as_deref().unwrap_or(&product.id),
+                meter_name,
+                overage.included.unwrap_or(0)
+            ))),
+        );
+    }
+    Ok(lines)
+}
+
+/// Create a draft invoice with `lines` (and the customer's pending items when
+/// `include_pending` is set), and compute its totals
+fn create_draft(
+    state: &CatalogState,
+    db: &DbManager,
+    new_invoice: &NewInvoice,
+    lines: &[NewInvoiceItem],
+    include_pending: bool,
+) -> Result<InvoiceModel, String> {
+    let (invoice, lines) = db
+        .insert_invoice(new_invoice, lines, include_pending)
+        .map_err(|e| e.to_string())?;
+    update_totals(state, db, &invoice, &lines)
+}
+
+/// Recompute the totals of a draft invoice from its lines.
+///
+/// Invoices with a tax location are taxed line by line at the rate of that jurisdiction,
+/// after discounts; others apply their `tax_percent` to the discounted subtotal.
+fn update_totals(
+    state: &CatalogState,
+    db: &DbManager,
+    invoice: &InvoiceModel,
+    lines: &[InvoiceItemModel],
+) -> Result<InvoiceModel, String> {
+    let amounts = lines.iter().map(|line| line.amount).collect::<Vec<_>>();
+    let line_discounts = match invoice.coupon.as_deref() {
+        Some(coupon_id) => coupon_discounts(state, coupon_id, &invoice.currency, lines),
+        None => allocate_discount(invoice.discount, &amounts),
+    };
+    let discount = line_discounts.iter().sum::<i64>();
+
+    let location = tax_location(invoice.tax_country.as_deref(), invoice.tax_state.as_deref());
+    let calculation = location.and_then(|location| {
+        let taxed_lines = lines
+            .iter()
+            .zip(&line_discounts)
+            .map(|(line, line_discount)| {
+                (line.amount - line_discount, line_tax_behavior(state, line))
+            })
+            .collect::<Vec<_>>();
+        calculate_tax(state, &location, &taxed_lines)
+            .inspect_err(|e| warn!("Not taxing invoice {}: {}", invoice.invoice_id, e.message))
+            .ok()
+    });
+
+    let mut changes = match &calculation {
+        Some(calculation) => {
+            let line_taxes = lines
+                .iter()
+                .zip(&calculation.lines)
+                .map(|(line, tax)| (line.item_id.clone(), tax.amount_tax, tax.inclusive))
+                .collect::<Vec<_>>();
+            db.update_invoice_line_taxes(&line_taxes)
+                .map_err(|e| e.to_string())?;
+            InvoiceTotals::compute_with_tax(&amounts, discount, calculation).changes()
+        }
+        None => InvoiceTotals::compute(&amounts, discount, invoice.tax_percent).changes(),
+    };
+    changes.tax_details = Some(
+        calculation
+            .map(|calculation| calculation.payment_tax())
+            .and_then(|tax| serde_json::to_string(&tax).ok()),
+    );
+    db.update_invoice(&invoice.invoice_id, STATUS_DRAFT, changes)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Invoice {} is no longer a draft", invoice.invoice_id))
+}
+
+/// Tax behavior of an invoice line: its catalog price's, exclusive for one-off items
+fn line_tax_behavior(state: &CatalogState, line: &InvoiceItemModel) -> TaxBehavior {
+    let catalog = state.catalog();
+    line.price
+        .as_deref()
+        .and_then(|price| find_catalog_price(state, &catalog, price))
+        .map(|(_, price)| price_tax_behavior(price))
+        .unwrap_or_default()
+}
+
+/// Split `discount` across lines in proportion to their (positive) amounts
+fn allocate_discount(discount: i64, amounts: &[i64]) -> Vec<i64> {
+    let base = amounts.iter().map(|amount| (*amount).max(0)).sum::<i64>();
+    let discount = discount.clamp(0, base);
+    if discount == 0 {
+        return vec![0; amounts.len()];
+    }
+    let mut allocated = amounts
+        .iter()
+        .map(|amount| ((*amount).max(0) as i128 * discount as i128 / base as i128) as i64)
+        .collect::<Vec<_>>();
+    // Rounding leftovers go to the largest line
+    let leftover = discount - allocated.iter().sum::<i64>();
+    if let Some(index) = (0..amounts.len()).max_by_key(|index| amounts[*index]) {
+        allocated[index] += leftover;
+    }
+    allocated
+}
+
+/// Amount a coupon takes off each line of an invoice in `currency`; a coupon removed
+/// from the catalog no longer discounts new invoices
+fn coupon_discounts(
+    state: &CatalogState,
+    coupon_id: &str,
+    currency: &str,
+    lines: &[InvoiceItemModel],
+) -> Vec<i64> {
+    let catalog = state.catalog();
+    let Some(coupon) = find_coupon(&catalog, coupon_id) else {
+        warn!("Coupon {} not found in catalog, not discounting", coupon_id);
+        return vec![0; lines.len()];
+    };
+    let lines = lines
+        .iter()
+        .map(|line| {
+            let product_id = line
+                .price
+                .as_deref()
+                .and_then(|price| find_catalog_price(state, &catalog, price))
+                .map(|(product, _)| product.id.as_str());
+            (product_id, line.amount)
+        })
+        .collect::<Vec<_>>();
+    coupon.discount_amounts(&lines, currency)
+}
+
+/// Lifecycle event payload for an invoice
+fn invoice_event_data(invoice: &InvoiceModel) -> InvoiceEventData {
+    InvoiceEventData {
+        invoice_id: invoice.invoice_id.clone(),
+        customer: invoice.customer.clone(),
+        subscription_id: invoice.subscription.clone(),
+        status: invoice.status.clone(),
+        currency: invoice.currency.clone(),
+        total: invoice.total,
+        amount_due: invoice.amount_due(),
+        payment_intent_id: invoice.payment_intent.clone(),
+        test_clock: invoice.test_clock.clone(),
+    }
+}
+
+/// Payment intent `payment_intent_id` charging the amount due on an invoice
+fn invoice_payment_intent(
+    invoice: &InvoiceModel,
+    payment_intent_id: String,
+    at: i64,
+) -> StripePaymentIntent {
+    let mut metadata =
+        HashMap::from([(INVOICE_METADATA_KEY.to_string(), invoice.invoice_id.clone())]);
+    if let Some(subscription) = &invoice.subscription {
+        metadata.insert(SUBSCRIPTION_METADATA_KEY.to_string(), subscription.clone());
+    }
+    if let Some(tax) = invoice.tax_details() {
+        insert_tax_metadata(&mut metadata, &tax);
+    }
+    StripePaymentIntent {
+        id: payment_intent_id.clone(),
+        object: "payment_intent".to_string(),
+        amount: invoice.amount_due(),
+        currency: invoice.currency.clone(),
+        status: PaymentIntentStatus::RequiresPaymentMethod,
+        capture_method: CaptureMethod::Automatic,
+        amount_capturable: 0,
+        amount_received: 0,
+        created: at,
+        customer: Some(invoice.customer.clone()),
+        payment_method: None,
+        description: Some(
+            invoice
+                .description
+                .clone()
+                .unwrap_or_else(|| format!("Invoice {}", invoice.invoice_id)),
+        ),
+        metadata,
+        latest_charge: None,
+        client_secret: Some(format!(
+            "{}_secret_{}",
+            payment_intent_id,
+            generate_stripe_id("")
+        )),
+        cancellation_reason: None,
+        solana_pay: None,
+    }
+}
+
+/// Create the payment intent charging the amount due on an invoice
+fn create_invoice_payment_intent(
+    state: &CatalogState,
+    invoice: &InvoiceModel,
+    at: i64,
+) -> StripePaymentIntent {
+    let mut payment_intent = invoice_payment_intent(invoice, generate_stripe_id("pi"), at);
+    payment_intent.solana_pay = payment_intent_transfer_request(state, &payment_intent);
+    state
+        .payment_intents
+        .lock()
+        .unwrap()
+        .insert(payment_intent.id.clone(), payment_intent.clone());
+    payment_intent
+}
+
+/// Finalize a draft invoice at `at` (Unix seconds).
+///
+/// The invoice becomes `open` with a payment intent charging it, or `paid` straight away
+/// when nothing is due. Returns None if the invoice is no longer a draft.
+pub(crate) fn finalize_draft(
+    state: &CatalogState,
+    db: &DbManager,
+    invoice: &InvoiceModel,
+    at: i64,
+) -> Result<Option<InvoiceModel>, String> {
+    let changes = if invoice.amount_due() == 0 {
+        UpdateInvoice {
+            status: Some(STATUS_PAID.to_string()),
+            finalized_at: Some(Some(at)),
+            paid_at: Some(Some(at)),
+            ..Default::default()
+        }
+    } else {
+        let payment_intent = create_invoice_payment_intent(state, invoice, at);
+        UpdateInvoice {
+            status: Some(STATUS_OPEN.to_string()),
+            payment_intent: Some(Some(payment_intent.id)),
+            finalized_at: Some(Some(at)),
+            ..Default::default()
+        }
+    };
+    let finalized = db
+        .update_invoice(&invoice.invoice_id, STATUS_DRAFT, changes)
+        .map_err(|e| e.to_string())?;
+    if let Some(finalized) = &finalized {
+        info!(
+            "Finalized invoice {} for {} ({} {})",
+            finalized.invoice_id, finalized.customer, finalized.total, finalized.currency
+        );
+        let data = invoice_event_data(finalized);
+        let event = if finalized.status == STATUS_PAID {
+            CloudEvent::InvoicePaid(data)
+        } else {
+            CloudEvent::InvoiceFinalized(data)
+        };
+        state.record_event(event, at);
+        if finalized.status == STATUS_PAID {
+            emit_invoice_events(state, db, finalized, PAID_EVENT_TYPES, at);
+        } else {
+            emit_invoice_events(state, db, finalized, &["invoice.finalized"], at);
+        }
+    }
+    Ok(finalized)
+}
+
+/// Mark an open invoice paid at `at` (Unix seconds)
+fn mark_invoice_paid(
+    state: &CatalogState,
+    db: &DbManager,
+    invoice: &InvoiceModel,
+    at: i64,
+) -> Result<Option<InvoiceModel>, String> {
+    let changes = UpdateInvoice {
+        status: Some(STATUS_PAID.to_string()),
+        amount_paid: Some(invoice.total),
+        paid_at: Some(Some(at)),
+        ..Default::default()
+    };
+    let paid = db
+        .update_invoice(&invoice.invoice_id, STATUS_OPEN, changes)
+        .map_err(|e| e.to_string())?;
+    if let Some(paid) = &paid {
+        info!("Invoice {} paid", paid.invoice_id);
+        state.record_event(CloudEvent::InvoicePaid(invoice_event_data(paid)), at);
+        emit_invoice_events(state, db, paid, PAID_EVENT_TYPES, at);
+    }
+    Ok(paid)
+}
+
+/// Void an open invoice at `at` (Unix seconds), canceling its unpaid payment intent
+pub(crate) fn void_open_invoice(
+    state: &CatalogState,
+    db: &DbManager,
+    invoice: &InvoiceModel,
+    at: i64,
+) -> Result<Option<InvoiceModel>, String> {
+    let changes = UpdateInvoice {
+        status: Some(STATUS_VOID.to_string()),
+        voided_at: Some(Some(at)),
+        ..Default::default()
+    };
+    let voided = db
+        .update_invoice(&invoice.invoice_id, STATUS_OPEN, changes)
+        .map_err(|e| e.to_string())?;
+    if let Some(voided) = &voided {
+        if let Some(payment_intent_id) = &voided.payment_intent {
+            let mut payment_intents = state.payment_intents.lock().unwrap();
+            if let Some(payment_intent) = payment_intents.get_mut(payment_intent_id)
+                && payment_intent.status != PaymentIntentStatus::Succeeded
+            {
+                payment_intent.status = PaymentIntentStatus::Canceled;
+                payment_intent.cancellation_reason = Some("void_invoice".to_string());
+            }
+        }
+        info!("Invoice {} voided", voided.invoice_id);
+        state.record_event(CloudEvent::InvoiceVoided(invoice_event_data(voided)), at);
+        emit_invoice_events(state, db, voided, &["invoice.voided"], at);
+    }
+    Ok(voided)
+}
+
+/// Stripe events of an invoice getting paid
+const PAID_EVENT_TYPES: &[&str] = &["invoice.paid", "invoice.payment_succeeded"];
+
+/// Emit Stripe-format events about an invoice, with its lines
+fn emit_invoice_events(
+    state: &CatalogState,
+    db: &DbManager,
+    invoice: &InvoiceModel,
+    event_types: &[&str],
+    at: i64,
+) {
+    let lines = match db.list_invoice_lines(&invoice.invoice_id) {
+        Ok(lines) => lines,
+        Err(e) => {
+            error!("Failed to list lines of {}: {}", invoice.invoice_id, e);
+            return;
+        }
+    };
+    let invoice = stripe_invoice(state, invoice, &lines);
+    for event_type in event_types {
+        emit_stripe_event(state, event_type, &invoice, at);
+    }
+}
+
+/// Invoice a subscription's period `[period_start, period_end)` and finalize it.
+///
+/// The usage of the subscription's current period is billed on the same invoice, along
+/// with the customer's pending invoice items. `at` is when the invoice is issued.
+pub(crate) fn invoice_subscription_period(
+    state: &CatalogState,
+    db: &DbManager,
+    subscription: &SubscriptionModel,
+    billing_reason: &str,
+    period_start: i64,
+    period_end: i64,
+    at: i64,
+) -> Result<InvoiceModel, String> {
+    let mut lines = recurring_lines(state, subscription, period_start, period_end);
+    let usage_end = subscription.current_period_end.min(period_start);
+    if usage_end > subscription.current_period_start {
+        lines.extend(overage_lines(
+            state,
+            db,
+            subscription,
+            subscription.current_period_start,
+            usage_end,
+        )?);
+    }
+
+    let new_invoice = NewInvoice::new(
+        generate_stripe_id("in"),
+        subscription.customer.clone(),
+        billing_reason,
+        subscription.currency.clone(),
+        period_start,
+        period_end,
+        state.payment_stack_id.clone(),
+        state.use_sandbox,
+    )
+    .with_subscription(Some(subscription.subscription_id.clone()))
+    .with_test_clock(subscription.test_clock.clone())
+    .with_tax_location(
+        subscription.tax_country.clone(),
+        subscription.tax_state.clone(),
+    )
+    .with_coupon(
+        subscription
+            .coupon_for_period(period_start)
+            .map(str::to_string),
+    );
+
+    let invoice = create_draft(state, db, &new_invoice, &lines, true)?;
+    finalize_draft(state, db, &invoice, at)?
+        .ok_or_else(|| format!("Invoice {} was modified concurrently", invoice.invoice_id))
+}
+
+/// Record the invoice for the first period of a subscription, already paid through x402
+/// when the subscription was created
+pub(crate) fn record_first_period_invoice(
+    state: &CatalogState,
+    db: &DbManager,
+    subscription: &SubscriptionModel,
+    billing_reason: &str,
+) -> Result<InvoiceModel, String> {
+    let at = subscription.current_period_start;
+    let lines = recurring_lines(
+        state,
+        subscription,
+        subscription.current_period_start,
+        subscription.current_period_end,
+    );
+    let new_invoice = NewInvoice::new(
+        generate_stripe_id("in"),
+        subscription.customer.clone(),
+        billing_reason,
+        subscription.currency.clone(),
+        subscription.current_period_start,
+        subscription.current_period_end,
+        state.payment_stack_id.clone(),
+        state.use_sandbox,
+    )
+    .with_subscription(Some(subscription.subscription_id.clone()))
+    .with_test_clock(subscription.test_clock.clone())
+    .with_tax_location(
+        subscription.tax_country.clone(),
+        subscription.tax_state.clone(),
+    )
+    .with_coupon(
+        subscription
+            .coupon_for_period(subscription.current_period_start)
+            .map(str::to_string),
+    );
+
+    let invoice = create_draft(state, db, &new_invoice, &lines, false)?;
+    let changes = UpdateInvoice {
+        status: Some(STATUS_PAID.to_string()),
+        amount_paid: Some(invoice.total),
+        finalized_at: Some(Some(at)),
+        paid_at: Some(Some(at)),
+        ..Default::default()
+    };
+    db.update_invoice(&invoice.invoice_id, STATUS_DRAFT, changes)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Invoice {} was modified concurrently", invoice.invoice_id))
+}
+
+/// Mark the invoice charged by `payment_intent_id` as paid
+pub(crate) fn record_invoice_payment(state: &CatalogState, payment_intent_id: &str) {
+    let Some(db) = state.payment_db.as_ref() else {
+        return;
+    };
+    let invoice = match db.find_invoice_by_payment_intent(payment_intent_id) {
+        Ok(Some(invoice)) if invoice.status == STATUS_OPEN => invoice,
+        Ok(_) => return,
+        Err(e) => {
+            error!("Failed to look up invoice for {}: {}", payment_intent_id, e);
+            return;
+        }
+    };
+    let at = clock_time(state, invoice.test_clock.as_deref());
+    if let Err(e) = mark_invoice_paid(state, db, &invoice, at) {
+        error!("Failed to mark invoice {} paid: {}", invoice.invoice_id, e);
+    }
+}
+
+/// Payment intent charging an open invoice, for x402 payments of `/invoices/:id/pay`.
+///
+/// Payment intents are kept in memory: one lost on restart is recreated from the invoice.
+pub(crate) fn open_invoice_payment_intent(
+    state: &CatalogState,
+    invoice_id: &str,
+) -> Option<String> {
+    let db = state.payment_db.as_ref()?;
+    let invoice = db
+        .find_invoice(invoice_id, &state.payment_stack_id, state.use_sandbox)
+        .ok()
+        .flatten()
+        .filter(|invoice| invoice.status == STATUS_OPEN)?;
+    let payment_intent_id = invoice.payment_intent.clone()?;
+    state
+        .payment_intents
+        .lock()
+        .unwrap()
+        .entry(payment_intent_id.clone())
+        .or_insert_with(|| {
+            let at = invoice.finalized_at.unwrap_or(invoice.created_at / 1000);
+            let mut payment_intent =
+                invoice_payment_intent(&invoice, payment_intent_id.clone(), at);
+            payment_intent.solana_pay = payment_intent_transfer_request(state, &payment_intent);
+            payment_intent
+        });
+    Some(payment_intent_id)
+}
+
+/// Recreate the payment intents of open invoices lost on restart, so their Solana Pay
+/// transfers are watched
+pub(crate) fn load_open_invoice_payment_intents(state: &CatalogState) {
+    let Some(db) = state.payment_db.as_ref() else {
+        return;
+    };
+    let mut starting_after = None;
+    loop {
+        let (invoices, has_more) = match db.list_invoices(
+            100,
+            starting_after,
+            None,
+            None,
+            Some(STATUS_OPEN),
+            &state.payment_stack_id,
+            state.use_sandbox,
+        ) {
+            Ok(page) => page,
+            Err(e) => {
+                error!("Failed to list open invoices: {}", e);
+                return;
+            }
+        };
+        for invoice in &invoices {
+            open_invoice_payment_intent(state, &invoice.invoice_id);
+        }
+        match invoices.last() {
+            Some(last) if has_more => starting_after = Some(last.id),
+            _ => return,
+        }
+    }
+}
+
+// ==================== Responses ====================
+
+fn price_ref(price: &Option<String>) -> Option<SubscriptionPrice> {
+    price.as_ref().map(|id| SubscriptionPrice {
+        id: id.clone(),
+        object: "price".to_string(),
+    })
+}
+
+impl From<&InvoiceItemModel> for StripeInvoiceLineItem {
+    fn from(line: &InvoiceItemModel) -> Self {
+        let is_invoice_item = line.kind == KIND_INVOICE_ITEM;
+        StripeInvoiceLineItem {
+            id: line.item_id.clone(),
+            object: "line_item".to_string(),
+            amount: line.amount,
+            currency: line.currency.clone(),
+            description: line.description.clone(),
+            invoice_item: is_invoice_item.then(|| line.item_id.clone()),
+            subscription: line.subscription.clone(),
+            price: price_ref(&line.price),
+            quantity: line.quantity,
+            unit_amount_decimal: line.unit_amount_decimal.to_string(),
+            period: InvoicePeriod {
+                start: line.period_start,
+                end: line.period_end,
+            },
+            // Metered usage is part of the subscription, as on Stripe
+            line_type: if is_invoice_item {
+                KIND_INVOICE_ITEM
+            } else {
+                KIND_SUBSCRIPTION
+            }
+            .to_string(),
+            tax_amounts: Vec::new(),
+            metadata: line.metadata(),
+        }
+    }
+}
+
+impl From<&InvoiceItemModel> for StripeInvoiceItem {
+    fn from(item: &InvoiceItemModel) -> Self {
+        StripeInvoiceItem {
+            id: item.item_id.clone(),
+            object: "invoiceitem".to_string(),
+            customer: item.customer.clone(),
+            amount: item.amount,
+            currency: item.currency.clone(),
+            date: item.created_at / 1000,
+            description: item.description.clone(),
+            invoice: item.invoice.clone(),
+            subscription: item.subscription.clone(),
+            price: price_ref(&item.price),
+            quantity: item.quantity,
+            unit_amount_decimal: item.unit_amount_decimal.to_string(),
+            period: InvoicePeriod {
+                start: item.period_start,
+                end: item.period_end,
+            },
+            metadata: item.metadata(),
+            livemode: !item.is_sandbox,
+        }
+    }
+}
+
+/// Tax amounts of an invoice taxed for its customer's address, one per behavior
+fn total_tax_amounts(invoice: &InvoiceModel, lines: &[InvoiceItemModel]) -> Vec<InvoiceTaxAmount> {
+    let Some(tax) = invoice.tax_details() else {
+        return Vec::new();
+    };
+    [false, true]
+        .into_iter()
+        .filter(|inclusive| lines.iter().any(|line| line.tax_inclusive == *inclusive))
+        .map(|inclusive| InvoiceTaxAmount {
+            amount: lines
+                .iter()
+                .filter(|line| line.tax_inclusive == inclusive)
+                .map(|line| line.amount_tax)
+                .sum(),
+            inclusive,
+            tax_rate_details: tax_rate_details(&tax),
+        })
+        .collect()
+}
+
+fn stripe_invoice(
+    state: &CatalogState,
+    invoice: &InvoiceModel,
+    lines: &[InvoiceItemModel],
+) -> StripeInvoice {
+    let tax = invoice.tax_details();
+    let solana_pay = invoice
+        .payment_intent
+        .as_ref()
+        .filter(|_| invoice.status == STATUS_OPEN)
+        .and_then(|payment_intent_id| {
+            transfer_request(
+                state,
+                payment_intent_id,
+                invoice.amount_due(),
+                &invoice.currency,
+                Some(
+                    &invoice
+                        .description
+                        .clone()
+                        .unwrap_or_else(|| format!("Invoice {}", invoice.invoice_id)),
+                ),
+            )
+        });
+    StripeInvoice {
+        id: invoice.invoice_id.clone(),
+        object: "invoice".to_string(),
+        customer: invoice.customer.clone(),
+        subscription: invoice.subscription.clone(),
+        billing_reason: invoice.billing_reason.clone(),
+        status: invoice.status.clone(),
+        currency: invoice.currency.clone(),
+        created: invoice.created_at / 1000,
+        subtotal: invoice.subtotal,
+        total_discount_amounts: (invoice.discount > 0)
+            .then(|| InvoiceDiscountAmount {
+                amount: invoice.discount,
+                discount: invoice.coupon.clone(),
+            })
+            .into_iter()
+            .collect(),
+        total_excluding_tax: invoice.total - invoice.tax,
+        tax: invoice.tax,
+        tax_percent: invoice.tax_percent,
+        total_tax_amounts: total_tax_amounts(invoice, lines),
+        customer_address: invoice.tax_country.clone().map(|country| TaxAddress {
+            country,
+            state: invoice.tax_state.clone(),
+            postal_code: None,
+        }),
+        total: invoice.total,
+        amount_due: invoice.amount_due(),
+        amount_paid: invoice.amount_paid,
+        amount_remaining: if invoice.status == STATUS_VOID {
+            0
+        } else {
+            invoice.amount_due()
+        },
+        period_start: invoice.period_start,
+        period_end: invoice.period_end,
+        lines: InvoiceLines {
+            object: "list".to_string(),
+            data: lines
+                .iter()
+                .map(|line| StripeInvoiceLineItem {
+                    tax_amounts: tax
+                        .iter()
+                        .map(|tax| InvoiceTaxAmount {
+                            amount: line.amount_tax,
+                            inclusive: line.tax_inclusive,
+                            tax_rate_details: tax_rate_details(tax),
+                        })
+                        .collect(),
+                    ..StripeInvoiceLineItem::from(line)
+                })
+                .collect(),
+            has_more: false,
+            url: format!("/v1/invoices/{}/lines", invoice.invoice_id),
+        },
+        payment_intent: invoice.payment_intent.clone(),
+        solana_pay,
+        status_transitions: InvoiceStatusTransitions {
+            finalized_at: invoice.finalized_at,
+            paid_at: invoice.paid_at,
+            voided_at: invoice.voided_at,
+        },
+        description: invoice.description.clone(),
+        metadata: invoice.metadata(),
+        livemode: !invoice.is_sandbox,
+        test_clock: invoice.test_clock.clone(),
+    }
+}
+
+// ==================== Handlers ====================
+
+fn invoice_error(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(serde_json::json!({
+            "error": {
+                "code": code,
+                "message": message.into(),
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+fn api_error(message: impl Into<String>) -> Response {
+    invoice_error(StatusCode::INTERNAL_SERVER_ERROR, "api_error", message)
+}
+
+fn payment_db(state: &CatalogState) -> Result<&DbManager, Response> {
+    state.payment_db.as_deref().ok_or_else(|| {
+        invoice_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "invoices_not_configured",
+            "Invoices require a database",
+        )
+    })
+}
+
+fn find_invoice_or_404(
+    state: &CatalogState,
+    db: &DbManager,
+    id: &str,
+) -> Result<InvoiceModel, Response> {
+    match db.find_invoice(id, &state.payment_stack_id, state.use_sandbox) {
+        Ok(Some(invoice)) => Ok(invoice),
+        Ok(None) => Err(invoice_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such invoice: '{}'", id),
+        )),
+        Err(e) => {
+            error!("Failed to find invoice {}: {}", id, e);
+            Err(api_error(e.to_string()))
+        }
+    }
+}
+
+/// Respond with an invoice and its lines
+fn invoice_response(state: &CatalogState, db: &DbManager, invoice: &InvoiceModel) -> Response {
+    match db.list_invoice_lines(&invoice.invoice_id) {
+        Ok(lines) => (StatusCode::OK, Json(stripe_invoice(state, invoice, &lines))).into_response(),
+        Err(e) => {
+            error!("Failed to list lines of {}: {}", invoice.invoice_id, e);
+            api_error(e.to_string())
+        }
+    }
+}
+
+/// Respond with the updated invoice, or a conflict if it changed concurrently
+fn updated_response(
+    state: &CatalogState,
+    db: &DbManager,
+    result: Result<Option<InvoiceModel>, String>,
+) -> Response {
+    match result {
+        Ok(Some(invoice)) => invoice_response(state, db, &invoice),
+        Ok(None) => invoice_error(
+            StatusCode::CONFLICT,
+            "lock_timeout",
+            "The invoice was modified by another request, please retry",
+        ),
+        Err(e) => {
+            error!("Failed to update invoice: {}", e);
+            api_error(e)
+        }
+    }
+}
+
+fn invalid_state(invoice: &InvoiceModel, action: &str) -> Response {
+    invoice_error(
+        StatusCode::BAD_REQUEST,
+        "invoice_unexpected_state",
+        format!(
+            "An invoice with status {} can't be {}",
+            invoice.status, action
+        ),
+    )
+}
+
+fn encode_metadata(metadata: &HashMap<String, String>) -> Option<String> {
+    (!metadata.is_empty())
+        .then(|| serde_json::to_string(metadata).ok())
+        .flatten()
+}
+
+/// Form-encoded invoice create/update request
+#[derive(Debug, Default)]
+pub struct InvoiceRequest {
+    pub customer: Option<String>,
+    pub subscription: Option<String>,
+    pub currency: Option<String>,
+    pub description: Option<String>,
+    pub metadata: HashMap<String, String>,
+    /// Tax applied to the discounted subtotal, in percent (empty to remove it)
+    pub tax_percent: Option<Option<f64>>,
+    /// Country of the customer's address, to charge automatic tax for (empty to stop)
+    pub tax_country: Option<String>,
+    pub tax_state: Option<String>,
+    /// Finalize the invoice as soon as it is created
+    pub auto_advance: Option<bool>,
+    /// `include` (default) or `exclude` the customer's pending invoice items
+    pub pending_invoice_items_behavior: Option<String>,
+}
+
+impl InvoiceRequest {
+    pub fn parse(body: &Bytes) -> Result<InvoiceRequest, FormError> {
+        let form = StripeForm::decode(body)?;
+        // An empty tax_percent removes the tax
+        let tax_percent = match form.string("tax_percent")? {
+            Some(value) if value.is_empty() => Some(None),
+            Some(_) => Some(form.decimal("tax_percent")?),
+            None => None,
+        };
+        Ok(InvoiceRequest {
+            customer: form.string("customer")?.filter(|c| !c.is_empty()),
+            subscription: form.string("subscription")?.filter(|s| !s.is_empty()),
+            currency: form.string("currency")?.map(|c| c.to_lowercase()),
+            description: form.string("description")?,
+            metadata: form.map("metadata")?,
+            tax_percent,
+            tax_country: form.string("customer_details[address][country]")?,
+            tax_state: form.string("customer_details[address][state]")?,
+            auto_advance: form.boolean("auto_advance")?,
+            pending_invoice_items_behavior: form.string("pending_invoice_items_behavior")?,
+        })
+    }
+}
+
+/// Form-encoded invoice item request
+#[derive(Debug, Default)]
+pub struct InvoiceItemRequest {
+    pub customer: Option<String>,
+    /// Total amount of the item in cents, negative for a credit
+    pub amount: Option<i64>,
+    pub unit_amount_decimal: Option<f64>,
+    pub quantity: Option<i64>,
+    pub currency: Option<String>,
+    pub price: Option<String>,
+    pub description: Option<String>,
+    /// Draft invoice to add the item to, instead of the customer's next invoice
+    pub invoice: Option<String>,
+    pub subscription: Option<String>,
+    pub period_start: Option<i64>,
+    pub period_end: Option<i64>,
+    pub metadata: HashMap<String, String>,
+}
+
+impl InvoiceItemRequest {
+    pub fn parse(body: &Bytes) -> Result<InvoiceItemRequest, FormError> {
+        let form = StripeForm::decode(body)?;
+        let unit_amount_decimal = match form.decimal("unit_amount_decimal")? {
+            Some(unit_amount) => Some(unit_amount),
+            None => form.decimal("unit_amount")?,
+        };
+        Ok(InvoiceItemRequest {
+            customer: form.string("customer")?.filter(|c| !c.is_empty()),
+            amount: form.integer("amount")?,
+            unit_amount_decimal,
+            quantity: form.integer("quantity")?,
+            currency: form.string("currency")?.map(|c| c.to_lowercase()),
+            price: form.string("price")?.filter(|p| !p.is_empty()),
+            description: form.string("description")?,
+            invoice: form.string("invoice")?.filter(|i| !i.is_empty()),
+            subscription: form.string("subscription")?.filter(|s| !s.is_empty()),
+            period_start: form.integer("period[start]")?,
+            period_end: form.integer("period[end]")?,
+            metadata: form.map("metadata")?,
+        })
+    }
+}
+
+/// POST /v1/invoices - Create a draft invoice
+///
+/// The invoice bills the customer's pending invoice items. With `subscription`, it also
+/// bills the usage of the subscription's current period so far; that usage is not billed
+/// again at renewal. `auto_advance=true` finalizes it right away.
+pub async fn create_invoice(
+    Extension(state): Extension<CatalogState>,
+    body: Bytes,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let request = match InvoiceRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    let Some(customer) = request.customer.clone() else {
+        return FormError::Missing("customer".to_string()).into_response();
+    };
+    let include_pending = match request.pending_invoice_items_behavior.as_deref() {
+        None | Some("include") => true,
+        Some("exclude") => false,
+        Some(other) => {
+            return invoice_error(
+                StatusCode::BAD_REQUEST,
+                "parameter_invalid",
+                format!("Invalid pending_invoice_items_behavior: '{}'", other),
+            );
+        }
+    };
+
+    let subscription = match request.subscription.as_deref() {
+        Some(id) => match db.find_subscription(id, &state.payment_stack_id, state.use_sandbox) {
+            Ok(Some(subscription)) if subscription.customer == customer => Some(subscription),
+            Ok(Some(_)) => {
+                return invoice_error(
+                    StatusCode::BAD_REQUEST,
+                    "parameter_invalid",
+                    format!("Subscription '{}' belongs to another customer", id),
+                );
+            }
+            Ok(None) => {
+                return invoice_error(
+                    StatusCode::NOT_FOUND,
+                    "resource_missing",
+                    format!("No such subscription: '{}'", id),
+                );
+            }
+            Err(e) => return api_error(e.to_string()),
+        },
+        None => None,
+    };
+
+    let (currency, period_start, now, test_clock, lines) = match &subscription {
+        Some(subscription) => {
+            let now = clock_time(&state, subscription.test_clock.as_deref());
+            let usage_end = now.min(subscription.current_period_end);
+            let lines = match overage_lines(
+                &state,
+                db,
+                subscription,
+                subscription.current_period_start,
+                usage_end,
+            ) {
+                Ok(lines) => lines,
+                Err(e) => return api_error(e),
+            };
+            (
+                subscription.currency.clone(),
+                subscription.current_period_start,
+                now,
+                subscription.test_clock.clone(),
+                lines,
+            )
+        }
+        None => {
+            let (now, test_clock) = current_time(&state, Some(&customer));
+            (
+                request
+                    .currency
+                    .clone()
+                    .unwrap_or_else(|| "usd".to_string()),
+                now,
+                now,
+                test_clock,
+                Vec::new(),
+            )
+        }
+    };
+
+    // Automatic tax for the address given, or the subscription's
+    let location = match request.tax_country.as_deref() {
+        Some(country) => tax_location(Some(country), request.tax_state.as_deref()),
+        None => subscription.as_ref().and_then(|subscription| {
+            tax_location(
+                subscription.tax_country.as_deref(),
+                subscription.tax_state.as_deref(),
+            )
+        }),
+    };
+    if let Some(location) = &location
+        && let Err(e) = check_tax_location(&state, location)
+    {
+        return e.into_response();
+    }
+
+    let new_invoice = NewInvoice::new(
+        generate_stripe_id("in"),
+        customer,
+        BILLING_REASON_MANUAL,
+        currency,
+        period_start,
+        now,
+        state.payment_stack_id.clone(),
+        state.use_sandbox,
+    )
+    .with_subscription(subscription.map(|s| s.subscription_id))
+    .with_tax_percent(request.tax_percent.flatten())
+    .with_tax_location(
+        location.as_ref().map(|location| location.country.clone()),
+        location.and_then(|location| location.state),
+    )
+    .with_description(request.description)
+    .with_metadata(encode_metadata(&request.metadata))
+    .with_test_clock(test_clock);
+
+    let invoice = match create_draft(&state, db, &new_invoice, &lines, include_pending) {
+        Ok(invoice) => invoice,
+        Err(e) => {
+            error!("Failed to create invoice: {}", e);
+            return api_error(e);
+        }
+    };
+    info!(
+        "Created invoice {} for {}",
+        invoice.invoice_id, invoice.customer
+    );
+
+    if request.auto_advance == Some(true) {
+        let at = clock_time(&state, invoice.test_clock.as_deref());
+        return updated_response(&state, db, finalize_draft(&state, db, &invoice, at));
+    }
+    invoice_response(&state, db, &invoice)
+}
+
+/// GET /v1/invoices/:id - Retrieve an invoice
+pub async fn retrieve_invoice(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    match find_invoice_or_404(&state, db, &id) {
+        Ok(invoice) => invoice_response(&state, db, &invoice),
+        Err(response) => response,
+    }
+}
+
+/// POST /v1/invoices/:id - Update a draft invoice
+pub async fn update_invoice(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let invoice = match find_invoice_or_404(&state, db, &id) {
+        Ok(invoice) => invoice,
+        Err(response) => return response,
+    };
+    let request = match InvoiceRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+
+    let mut changes = UpdateInvoice::default();
+    if let Some(description) = request.description {
+        changes.description = Some(Some(description).filter(|d| !d.is_empty()));
+    }
+    if !request.metadata.is_empty() {
+        let mut metadata = invoice.metadata();
+        for (key, value) in request.metadata {
+            // Stripe removes metadata keys set to an empty string
+            if value.is_empty() {
+                metadata.remove(&key);
+            } else {
+                metadata.insert(key, value);
+            }
+        }
+        changes.metadata = Some(encode_metadata(&metadata));
+    }
+    if let Some(tax_percent) = request.tax_percent {
+        // Amounts are fixed once the invoice is finalized
+        if invoice.status != STATUS_DRAFT {
+            return invalid_state(&invoice, "taxed again");
+        }
+        changes.tax_percent = Some(tax_percent);
+    }
+    if let Some(country) = request.tax_country.as_deref() {
+        if invoice.status != STATUS_DRAFT {
+            return invalid_state(&invoice, "taxed again");
+        }
+        // An empty country stops charging automatic tax
+        let location = tax_location(Some(country), request.tax_state.as_deref());
+        if let Some(location) = &location
+            && let Err(e) = check_tax_location(&state, location)
+        {
+            return e.into_response();
+        }
+        changes.tax_country = Some(location.as_ref().map(|location| location.country.clone()));
+        changes.tax_state = Some(location.and_then(|location| location.state));
+    }
+
+    let updated = match db.update_invoice(&id, &invoice.status, changes) {
+        Ok(Some(updated)) => updated,
+        result => {
+            return updated_response(&state, db, result.map_err(|e| e.to_string()));
+        }
+    };
+    if updated.status != STATUS_DRAFT {
+        return invoice_response(&state, db, &updated);
+    }
+    let result = db
+        .list_invoice_lines(&id)
+        .map_err(|e| e.to_string())
+        .and_then(|lines| update_totals(&state, db, &updated, &lines))
+        .map(Some);
+    updated_response(&state, db, result)
+}
+
+/// Query parameters of GET /v1/invoices
+#[derive(Debug, Deserialize)]
+pub struct ListInvoicesParams {
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub starting_after: Option<String>,
+    #[serde(default)]
+    pub customer: Option<String>,
+    #[serde(default)]
+    pub subscription: Option<String>,
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+/// GET /v1/invoices - List invoices
+pub async fn list_invoices(
+    Extension(state): Extension<CatalogState>,
+    StripeQuery(params): StripeQuery<ListInvoicesParams>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let limit = params.limit.unwrap_or(10).clamp(1, 100) as usize;
+
+    let starting_after = match params.starting_after.as_deref() {
+        Some(cursor) => match find_invoice_or_404(&state, db, cursor) {
+            Ok(invoice) => Some(invoice.id),
+            Err(response) => return response,
+        },
+        None => None,
+    };
+
+    let (invoices, has_more) = match db.list_invoices(
+        limit,
+        starting_after,
+        params.customer.as_deref(),
+        params.subscription.as_deref(),
+        params.status.as_deref(),
+        &state.payment_stack_id,
+        state.use_sandbox,
+    ) {
+        Ok(page) => page,
+        Err(e) => {
+            error!("Failed to list invoices: {}", e);
+            return api_error(e.to_string());
+        }
+    };
+
+    let mut data = Vec::with_capacity(invoices.len());
+    for invoice in &invoices {
+        match db.list_invoice_lines(&invoice.invoice_id) {
+            Ok(lines) => data.push(stripe_invoice(&state, invoice, &lines)),
+            Err(e) => return api_error(e.to_string()),
+        }
+    }
+    let response = ListResponse {
+        object: "list".to_string(),
+        data,
+        has_more,
+        url: "/v1/invoices".to_string(),
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// POST /v1/invoices/:id/finalize - Finalize a draft invoice
+pub async fn finalize_invoice(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let invoice = match find_invoice_or_404(&state, db, &id) {
+        Ok(invoice) => invoice,
+        Err(response) => return response,
+    };
+    if invoice.status != STATUS_DRAFT {
+        return invalid_state(&invoice, "finalized");
+    }
+    let at = clock_time(&state, invoice.test_clock.as_deref());
+    updated_response(&state, db, finalize_draft(&state, db, &invoice, at))
+}
+
+/// POST /v1/invoices/:id/pay - Pay an open invoice
+///
+/// The amount due is paid through x402 before this handler runs; the payment intent of
+/// the invoice is marked succeeded with it.
+pub async fn pay_invoice(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let invoice = match find_invoice_or_404(&state, db, &id) {
+        Ok(invoice) => invoice,
+        Err(response) => return response,
+    };
+    if invoice.status != STATUS_OPEN {
+        return invalid_state(&invoice, "paid");
+    }
+    let Some(payment_intent_id) = invoice.payment_intent.clone() else {
+        return api_error(format!("Invoice {} has no payment intent", id));
+    };
+
+    // The payment intent was loaded when the payment was requested; without it, the
+    // request was not gated and nothing was paid
+    let mut payment_intents = state.payment_intents.lock().unwrap();
+    let Some(payment_intent) = payment_intents.get_mut(&payment_intent_id) else {
+        return invoice_error(
+            StatusCode::PAYMENT_REQUIRED,
+            "payment_intent_missing",
+            format!("Invoice {} must be paid through x402", id),
+        );
+    };
+    payment_intent.status = PaymentIntentStatus::Succeeded;
+    payment_intent.amount_received = payment_intent.amount;
+    payment_intent.latest_charge = Some(generate_stripe_id("ch"));
+    drop(payment_intents);
+    emit_payment_intent_succeeded(&state, &payment_intent_id);
+
+    let at = clock_time(&state, invoice.test_clock.as_deref());
+    let result = mark_invoice_paid(&state, db, &invoice, at);
+    if matches!(result, Ok(Some(_))) {
+        record_subscription_payment(&state, &payment_intent_id);
+    }
+    updated_response(&state, db, result)
+}
+
+/// POST /v1/invoices/:id/void - Void an open invoice
+pub async fn void_invoice(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let invoice = match find_invoice_or_404(&state, db, &id) {
+        Ok(invoice) => invoice,
+        Err(response) => return response,
+    };
+    if invoice.status != STATUS_OPEN {
+        return invalid_state(&invoice, "voided");
+    }
+    let at = clock_time(&state, invoice.test_clock.as_deref());
+    updated_response(&state, db, void_open_invoice(&state, db, &invoice, at))
+}
+
+// ==================== Invoice items ====================
+
+fn find_invoice_item_or_404(
+    state: &CatalogState,
+    db: &DbManager,
+    id: &str,
+) -> Result<InvoiceItemModel, Response> {
+    match db.find_invoice_item(id, &state.payment_stack_id, state.use_sandbox) {
+        Ok(Some(item)) if item.kind == KIND_INVOICE_ITEM => Ok(item),
+        Ok(_) => Err(invoice_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such invoiceitem: '{}'", id),
+        )),
+        Err(e) => {
+            error!("Failed to find invoice item {}: {}", id, e);
+            Err(api_error(e.to_string()))
+        }
+    }
+}
+
+/// POST /v1/invoiceitems - Create an invoice item
+///
+/// The item is billed on the customer's next invoice, or added to the draft `invoice`.
+/// Its amount is `amount`, `quantity` × `unit_amount_decimal`, or `quantity` × the unit
+/// amount of `price`.
+pub async fn create_invoice_item(
+    Extension(state): Extension<CatalogState>,
+    body: Bytes,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let request = match InvoiceItemRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    let Some(customer) = request.customer.clone() else {
+        return FormError::Missing("customer".to_string()).into_response();
+    };
+    let quantity = request.quantity.unwrap_or(1);
+    if quantity < 1 {
+        return invoice_error(
+            StatusCode::BAD_REQUEST,
+            "parameter_invalid",
+            "quantity must be at least 1",
+        );
+    }
+
+    let (unit_amount, price_currency) = match (&request.price, request.unit_amount_decimal) {
+        (Some(price_id), _) => match find_catalog_price(&state, &state.catalog(), price_id) {
+            Some((_, price)) => (
+                quantity_unit_amount(price, quantity),
+                Some(price.currency.as_str().to_string()),
+            ),
+            None => {
+                return invoice_error(
+                    StatusCode::BAD_REQUEST,
+                    "resource_missing",
+                    format!("No such price: '{}'", price_id),
+                );
+            }
+        },
+        (None, Some(unit_amount)) if unit_amount.is_finite() => (unit_amount, None),
+        (None, _) => match request.amount {
+            Some(amount) => (amount as f64 / quantity as f64, None),
+            None => {
+                return invoice_error(
+                    StatusCode::BAD_REQUEST,
+                    "parameter_missing",
+                    "One of amount, unit_amount_decimal or price is required.",
+                );
+            }
+        },
+    };
+
+    let invoice = match request.invoice.as_deref() {
+        Some(invoice_id) => match find_invoice_or_404(&state, db, invoice_id) {
+            Ok(invoice) if invoice.status != STATUS_DRAFT => {
+                return invalid_state(&invoice, "given new items");
+            }
+            Ok(invoice) if invoice.customer != customer => {
+                return invoice_error(
+                    StatusCode::BAD_REQUEST,
+                    "parameter_invalid",
+                    format!("Invoice '{}' belongs to another customer", invoice_id),
+                );
+            }
+            Ok(invoice) => Some(invoice),
+            Err(response) => return response,
+        },
+        None => None,
+    };
+
+    let currency = price_currency
+        .or_else(|| invoice.as_ref().map(|invoice| invoice.currency.clone()))
+        .or(request.currency.clone())
+        .unwrap_or_else(|| "usd".to_string());
+    if let Some(invoice) = &invoice
+        && invoice.currency != currency
+    {
+        return invoice_error(
+            StatusCode::BAD_REQUEST,
+            "parameter_invalid",
+            format!(
+                "Invoice '{}' is in {}",
+                invoice.invoice_id, invoice.currency
+            ),
+        );
+    }
+
+    // A total amount is only used as is when no unit amount was given
+    let explicit_amount = request
+        .amount
+        .filter(|_| request.price.is_none() && request.unit_amount_decimal.is_none());
+    let (now, _) = current_time(&state, Some(&customer));
+    let mut new_item = NewInvoiceItem::new(
+        generate_stripe_id("ii"),
+        customer,
+        KIND_INVOICE_ITEM,
+        quantity,
+        unit_amount,
+        currency,
+        request.period_start.unwrap_or(now),
+        request.period_end.unwrap_or(now),
+        state.payment_stack_id.clone(),
+        state.use_sandbox,
+    )
+    .with_invoice(invoice.as_ref().map(|invoice| invoice.invoice_id.clone()))
+    .with_subscription(request.subscription)
+    .with_price(request.price)
+    .with_description(request.description)
+    .with_metadata(encode_metadata(&request.metadata));
+    if let Some(amount) = explicit_amount {
+        new_item = new_item.with_amount(amount);
+    }
+
+    let item = match db.insert_invoice_item(&new_item) {
+        Ok(item) => item,
+        Err(e) => {
+            error!("Failed to create invoice item: {}", e);
+            return api_error(e.to_string());
+        }
+    };
+    if let Some(invoice) = &invoice {
+        let refreshed = db
+            .list_invoice_lines(&invoice.invoice_id)
+            .map_err(|e| e.to_string())
+            .and_then(|lines| update_totals(&state, db, invoice, &lines));
+        if let Err(e) = refreshed {
+            warn!("Failed to update totals of {}: {}", invoice.invoice_id, e);
+        }
+    }
+    (StatusCode::OK, Json(StripeInvoiceItem::from(&item))).into_response()
+}
+
+/// GET /v1/invoiceitems/:id - Retrieve an invoice item
+pub async fn retrieve_invoice_item(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    match find_invoice_item_or_404(&state, db, &id) {
+        Ok(item) => (StatusCode::OK, Json(StripeInvoiceItem::from(&item))).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// DELETE /v1/invoiceitems/:id - Delete an invoice item that is pending or on a draft
+/// invoice
+pub async fn delete_invoice_item(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let item = match find_invoice_item_or_404(&state, db, &id) {
+        Ok(item) => item,
+        Err(response) => return response,
+    };
+    match db.delete_invoice_item(&id) {
+        Ok(true) => {}
+        Ok(false) => {
+            return invoice_error(
+                StatusCode::BAD_REQUEST,
+                "invoice_unexpected_state",
+                "Items of a finalized invoice can't be deleted",
+            );
+        }
+        Err(e) => return api_error(e.to_string()),
+    }
+
+    // Keep the totals of the draft the item was on up to date
+    if let Some(invoice_id) = &item.invoice
+        && let Ok(Some(invoice)) =
+            db.find_invoice(invoice_id, &state.payment_stack_id, state.use_sandbox)
+        && invoice.status == STATUS_DRAFT
+    {
+        let refreshed = db
+            .list_invoice_lines(invoice_id)
+            .map_err(|e| e.to_string())
+            .and_then(|lines| update_totals(&state, db, &invoice, &lines));
+        if let Err(e) = refreshed {
+            warn!("Failed to update totals of {}: {}", invoice_id, e);
+        }
+    }
+
+    (
+        StatusCode::OK,
+        Json(DeletedInvoiceItem {
+            id,
+            object: "invoiceitem".to_string(),
+            deleted: true,
+        }),
+    )
+        .into_response()
+}
+
+/// Query parameters of GET /v1/invoiceitems
+#[derive(Debug, Deserialize)]
+pub struct ListInvoiceItemsParams {
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub starting_after: Option<String>,
+    #[serde(default)]
+    pub customer: Option<String>,
+    #[serde(default)]
+    pub invoice: Option<String>,
+    /// Only items not billed on an invoice yet (or only billed ones when false)
+    #[serde(default)]
+    pub pending: Option<bool>,
+}
+
+/// GET /v1/invoiceitems - List invoice items
+pub async fn list_invoice_items(
+    Extension(state): Extension<CatalogState>,
+    StripeQuery(params): StripeQuery<ListInvoiceItemsParams>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let limit = params.limit.unwrap_or(10).clamp(1, 100) as usize;
+
+    let starting_after = match params.starting_after.as_deref() {
+        Some(cursor) => match find_invoice_item_or_404(&state, db, cursor) {
+            Ok(item) => Some(item.id),
+            Err(response) => return response,
+        },
+        None => None,
+    };
+
+    match db.list_invoice_items(
+        limit,
+        starting_after,
+        params.customer.as_deref(),
+        params.invoice.as_deref(),
+        params.pending,
+        &state.payment_stack_id,
+        state.use_sandbox,
+    ) {
+        Ok((items, has_more)) => {
+            let response = ListResponse {
+                object: "list".to_string(),
+                data: items
+                    .iter()
+                    .map(StripeInvoiceItem::from)
+                    .collect::<Vec<_>>(),
+                has_more,
+                url: "/v1/invoiceitems".to_string(),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            error!("Failed to list invoice items: {}", e);
+            api_error(e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use super::*;
+
+    fn overage(included: Option<i64>) -> OverageConfig {
+        OverageConfig {
+            meter: "api_calls".to_string(),
+            amounts: IndexMap::from([("usd".to_string(), 0.002)]),
+            included,
+        }
+    }
+
+    #[test]
+    fn test_invoice_totals() {
+        // Discount first, then tax on what remains
+        let totals = InvoiceTotals::compute(&[2000, 500], 500, Some(10.0));
+        assert_eq!(
+            totals,
+            InvoiceTotals {
+                subtotal: 2500,
+                discount: 500,
+                tax: 200,
+                total: 2200,
+            }
+        );
+
+        // Credits reduce the subtotal; discounts never make it negative
+        let totals = InvoiceTotals::compute(&[1000, -400], 5000, None);
+        assert_eq!(totals.subtotal, 600);
+        assert_eq!(totals.discount, 600);
+        assert_eq!(totals.total, 0);
+    }
+
+    #[test]
+    fn test_invoice_totals_with_tax() {
+        let tax_data = crate::taxes::TaxData::load().unwrap();
+        let location = crate::taxes::TaxLocation::new("DE", None);
+        // 19% VAT on top of the exclusive line, out of the inclusive one
+        let calculation = tax_data
+            .calculate(
+                &location,
+                &[
+                    (1000, TaxBehavior::Exclusive),
+                    (1190, TaxBehavior::Inclusive),
+                ],
+            )
+            .unwrap();
+        let totals = InvoiceTotals::compute_with_tax(&[1000, 1190], 0, &calculation);
+        assert_eq!(
+            totals,
+            InvoiceTotals {
+                subtotal: 2190,
+                discount: 0,
+                tax: 380,
+                total: 2380,
+            }
+        );
+    }
+
+    #[test]
+    fn test_allocate_discount() {
+        assert_eq!(allocate_discount(300, &[1000, 2000]), vec![100, 200]);
+        // Leftover cents go to the largest line, credits take no share
+        assert_eq!(allocate_discount(100, &[1000, 2000, -500]), vec![33, 67, 0]);
+        assert_eq!(allocate_discount(0, &[1000]), vec![0]);
+        assert_eq!(allocate_discount(5000, &[1000]), vec![1000]);
+    }
+
+    #[test]
+    fn test_overage_units() {
+        let overage = overage(Some(1000));
+        assert_eq!(overage_units(&overage, 800.0), 0);
+        assert_eq!(overage_units(&overage, 1000.0), 0);
+        assert_eq!(overage_units(&overage, 1250.0), 250);
+        // Partial units are billed as a whole unit
+        assert_eq!(overage_units(&overage, 1000.5), 1);
+    }
+
+    #[test]
+    fn test_overage_line_amount() {
+        let overage = overage(None);
+        let unit_amount = overage_unit_amount(&overage, "USD").unwrap();
+        assert!((unit_amount - 0.2).abs() < 1e-9);
+        assert_eq!(overage_unit_amount(&overage, "eur"), None);
+
+        // Sub-cent unit amounts are rounded once, on the line amount
+        let line = NewInvoiceItem::new(
+            "il_1".to_string(),
+            "cus_1".to_string(),
+            KIND_OVERAGE,
+            1234,
+            unit_amount,
+            "usd".to_string(),
+            0,
+            1,
+            "local".to_string(),
+            true,
+        );
+        assert_eq!(line.amount, 247);
+    }
+
+    #[test]
+    fn test_parse_invoice_item_request() {
+        let body = Bytes::from(
+            "customer=cus_1&amount=-500&currency=USD&description=Goodwill+credit\
+             &period%5Bstart%5D=10&period%5Bend%5D=20&metadata[reason]=outage",
+        );
+        let request = InvoiceItemRequest::parse(&body).unwrap();
+        assert_eq!(request.customer.as_deref(), Some("cus_1"));
+        assert_eq!(request.amount, Some(-500));
+        assert_eq!(request.currency.as_deref(), Some("usd"));
+        assert_eq!(request.description.as_deref(), Some("Goodwill credit"));
+        assert_eq!(
+            (request.period_start, request.period_end),
+            (Some(10), Some(20))
+        );
+        assert_eq!(
+            request.metadata.get("reason").map(String::as_str),
+            Some("outage")
+        );
+    }
+}
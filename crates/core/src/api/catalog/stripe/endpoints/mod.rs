@@ -1,23 +1,57 @@
 pub mod billing;
 pub mod checkout_sessions;
+pub mod coupons;
 pub mod customers;
+pub mod entitlements;
+pub mod events;
+pub mod invoices;
 pub mod payment_intents;
+pub mod payment_links;
 pub mod payment_methods;
 pub mod prices;
 pub mod products;
 pub mod subscriptions;
+pub mod tax;
+pub mod test_clocks;
 
 // Re-export handlers for convenience
-pub use billing::{create_meter_event, list_meters};
+pub use billing::{create_meter_event, list_meter_event_summaries, list_meters, retrieve_meter};
 pub use checkout_sessions::{
     create_checkout_session, expire_checkout_session, list_checkout_session_line_items,
     retrieve_checkout_session,
 };
-pub use customers::{create_customer, update_customer};
+pub use coupons::{list_coupons, list_promotion_codes, retrieve_coupon, retrieve_promotion_code};
+pub use customers::{
+    attach_customer_wallet, create_customer, delete_customer, detach_customer_wallet,
+    list_customers, retrieve_customer, search_customers, update_customer,
+};
+pub use entitlements::{
+    list_active_entitlements, list_entitlement_features, retrieve_entitlement_feature,
+};
+pub use events::{list_events, retrieve_event};
+pub use invoices::{
+    create_invoice, create_invoice_item, delete_invoice_item, finalize_invoice, list_invoice_items,
+    list_invoices, pay_invoice, retrieve_invoice, retrieve_invoice_item, update_invoice,
+    void_invoice,
+};
 pub use payment_intents::{
-    cancel_payment_intent, confirm_payment_intent, create_payment_intent, retrieve_payment_intent,
+    cancel_payment_intent, capture_payment_intent, confirm_payment_intent, create_payment_intent,
+    retrieve_payment_intent,
+};
+pub use payment_links::{
+    create_payment_link, list_payment_link_line_items, list_payment_links, payment_link_stats,
+    retrieve_payment_link, update_payment_link,
 };
 pub use payment_methods::{attach_payment_method, create_payment_method};
-pub use prices::list_prices;
-pub use products::{get_product_access, list_products};
-pub use subscriptions::create_subscription;
+pub use prices::{create_price, list_prices, retrieve_price, update_price};
+pub use products::{
+    create_product, get_product_access, list_products, retrieve_product, update_product,
+};
+pub use subscriptions::{
+    cancel_subscription, create_subscription, list_subscriptions, pause_subscription,
+    resume_subscription, retrieve_subscription, update_subscription,
+};
+pub use tax::create_tax_calculation;
+pub use test_clocks::{
+    advance_test_clock, create_test_clock, delete_test_clock, list_test_clocks, retrieve_test_clock,
+};
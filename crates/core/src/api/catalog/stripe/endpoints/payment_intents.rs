@@ -2,12 +2,31 @@ use std::collections::HashMap;
 
 use axum::{Extension, Json, body::Bytes, extract::Path, http::StatusCode, response::IntoResponse};
 
+use tracing::{error, info};
+
 use crate::api::catalog::{
     CatalogState,
+    authorization::{
+        check_refundable, expire_authorizations, forget_authorization, refund_uncaptured,
+        remove_authorization,
+    },
+    experiments::record_payment_intent_conversions,
+    middleware::settle_payment_with_facilitator,
+    solana_pay::payment_intent_transfer_request,
     stripe::{
-        types::{PaymentIntentStatus, StripePaymentIntent},
+        endpoints::{
+            checkout_sessions::current_checkout_session,
+            coupons::{record_payment_intent_redemption, resolve_discount},
+            invoices::record_invoice_payment,
+            subscriptions::record_subscription_payment,
+        },
+        types::{
+            CaptureMethod, CapturePaymentIntentRequest, ConfirmPaymentIntentRequest,
+            CreatePaymentIntentRequest, PaymentIntentStatus, StripePaymentIntent,
+        },
         utils::generate_stripe_id,
     },
+    webhooks::emit_stripe_event,
 };
 
 /// POST /v1/payment_intents - Create a payment intent
@@ -15,134 +34,52 @@ pub async fn create_payment_intent(
     Extension(state): Extension<CatalogState>,
     body: Bytes,
 ) -> impl IntoResponse {
-    // Try to parse as JSON first, then fall back to form-encoded
-    let body_str = String::from_utf8_lossy(&body);
-
-    let (amount, currency, customer, payment_method, description, confirm, metadata) =
-        if body_str.trim().starts_with('{') {
-            // Parse as JSON
-            let json_value: serde_json::Value = match serde_json::from_slice(&body) {
-                Ok(v) => v,
-                Err(e) => {
-                    println!("ERROR: Failed to parse JSON body: {}", e);
-                    return (
-                        StatusCode::BAD_REQUEST,
-                        Json(serde_json::json!({
-                            "error": {
-                                "message": format!("Invalid JSON: {}", e),
-                                "type": "invalid_request_error"
-                            }
-                        })),
-                    )
-                        .into_response();
-                }
-            };
-
-            let amount = json_value
-                .get("amount")
-                .and_then(|v| v.as_i64())
-                .unwrap_or(0);
-            let currency = json_value
-                .get("currency")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| "usd".to_string());
-            let customer = json_value
-                .get("customer")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            let payment_method = json_value
-                .get("payment_method")
-                .or_else(|| json_value.get("paymentMethod"))
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            let description = json_value
-                .get("description")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            let confirm = json_value
-                .get("confirm")
-                .and_then(|v| v.as_bool())
-                .unwrap_or(false);
-            let metadata = json_value
-                .get("metadata")
-                .and_then(|v| v.as_object())
-                .map(|obj| {
-                    obj.iter()
-                        .filter_map(|(k, v)| v.as_str().map(|s| (k.clone(), s.to_string())))
-                        .collect()
-                })
-                .unwrap_or_default();
-
-            (
-                amount,
-                currency,
-                customer,
-                payment_method,
-                description,
-                confirm,
-                metadata,
-            )
-        } else {
-            // Parse as form-encoded
-            let mut params: HashMap<String, String> = HashMap::new();
-
-            for part in body_str.split('&') {
-                if let Some((key, value)) = part.split_once('=') {
-                    let decoded_key = urlencoding::decode(key).unwrap_or_default().to_string();
-                    let decoded_value = urlencoding::decode(value).unwrap_or_default().to_string();
-                    params.insert(decoded_key, decoded_value);
-                }
-            }
-
-            let amount = params
-                .get("amount")
-                .and_then(|s| s.parse::<i64>().ok())
-                .unwrap_or(0);
-            let currency = params
-                .get("currency")
-                .cloned()
-                .unwrap_or_else(|| "usd".to_string());
-            let customer = params.get("customer").cloned();
-            let payment_method = params.get("payment_method").cloned();
-            let description = params.get("description").cloned();
-            let confirm = params
-                .get("confirm")
-                .and_then(|s| s.parse::<bool>().ok())
-                .unwrap_or(false);
-
-            // Extract metadata (metadata[key]=value format)
-            let metadata: HashMap<String, String> = params
-                .iter()
-                .filter_map(|(k, v)| {
-                    if k.starts_with("metadata[") && k.ends_with(']') {
-                        let key = k
-                            .strip_prefix("metadata[")
-                            .and_then(|s| s.strip_suffix(']'))
-                            .unwrap_or(k);
-                        Some((key.to_string(), v.clone()))
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-
-            (
-                amount,
-                currency,
-                customer,
-                payment_method,
-                description,
-                confirm,
-                metadata,
-            )
-        };
+    let CreatePaymentIntentRequest {
+        amount,
+        currency,
+        customer,
+        payment_method,
+        description,
+        confirm,
+        metadata,
+        capture_method,
+        discount: requested_discount,
+    } = match CreatePaymentIntentRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
 
     let payment_intent_id = generate_stripe_id("pi");
     let created = chrono::Utc::now().timestamp();
 
+    // A coupon is taken off the amount; only coupons not restricted to products apply,
+    // unless the intent names its product in metadata
+    let catalog = state.catalog();
+    let (amount, metadata) = match resolve_discount(
+        &state,
+        &catalog,
+        &requested_discount,
+        customer.as_deref(),
+        created,
+    ) {
+        Ok(None) => (amount, metadata),
+        Ok(Some(discount)) => {
+            let product_id = metadata.get("product_id").map(String::as_str);
+            let amount_discount = match discount.apply(&[(product_id, amount)], &currency) {
+                Ok(discounts) => discounts.iter().sum::<i64>(),
+                Err(e) => return e.into_response(),
+            };
+            let mut metadata = metadata;
+            discount.insert_metadata(&mut metadata, amount_discount);
+            (amount - amount_discount, metadata)
+        }
+        Err(e) => return e.into_response(),
+    };
+
     // Determine initial status
-    let status = if confirm {
+    let status = if confirm && capture_method == CaptureMethod::Manual {
+        PaymentIntentStatus::RequiresCapture
+    } else if confirm {
         PaymentIntentStatus::Succeeded
     } else if payment_method.is_some() {
         PaymentIntentStatus::RequiresConfirmation
@@ -162,12 +99,26 @@ pub async fn create_payment_intent(
         None
     };
 
-    let payment_intent = StripePaymentIntent {
+    let amount_capturable = if status == PaymentIntentStatus::RequiresCapture {
+        amount
+    } else {
+        0
+    };
+    let amount_received = if status == PaymentIntentStatus::Succeeded {
+        amount
+    } else {
+        0
+    };
+
+    let mut payment_intent = StripePaymentIntent {
         id: payment_intent_id.clone(),
         object: "payment_intent".to_string(),
         amount,
         currency,
         status,
+        capture_method,
+        amount_capturable,
+        amount_received,
         created,
         customer,
         payment_method,
@@ -175,14 +126,32 @@ pub async fn create_payment_intent(
         metadata,
         latest_charge,
         client_secret,
+        cancellation_reason: None,
+        solana_pay: None,
     };
 
+    // Unpaid intents can also be paid with a plain wallet transfer
+    if payment_intent.status != PaymentIntentStatus::Succeeded {
+        payment_intent.solana_pay = payment_intent_transfer_request(&state, &payment_intent);
+    }
+
     // Store payment intent in state
     state
         .payment_intents
         .lock()
         .unwrap()
-        .insert(payment_intent_id, payment_intent.clone());
+        .insert(payment_intent_id.clone(), payment_intent.clone());
+
+    emit_stripe_event(
+        &state,
+        "payment_intent.created",
+        &payment_intent,
+        payment_intent.created,
+    );
+    if payment_intent.status == PaymentIntentStatus::Succeeded {
+        record_payment_intent_redemption(&state, &payment_intent_id);
+        emit_payment_intent_succeeded(&state, &payment_intent_id);
+    }
 
     (StatusCode::OK, Json(payment_intent)).into_response()
 }
@@ -192,6 +161,8 @@ pub async fn retrieve_payment_intent(
     Extension(state): Extension<CatalogState>,
     Path(id): Path<String>,
 ) -> impl IntoResponse {
+    expire_authorizations(&state, chrono::Utc::now().timestamp());
+
     let payment_intents = state.payment_intents.lock().unwrap();
     let payment_intent = payment_intents.get(&id).cloned().unwrap_or_else(|| {
         // Fallback for backward compatibility
@@ -201,6 +172,9 @@ pub async fn retrieve_payment_intent(
             amount: 4990,
             currency: "usd".to_string(),
             status: PaymentIntentStatus::RequiresPaymentMethod,
+            capture_method: CaptureMethod::Automatic,
+            amount_capturable: 0,
+            amount_received: 0,
             created: chrono::Utc::now().timestamp(),
             customer: None,
             payment_method: None,
@@ -208,6 +182,8 @@ pub async fn retrieve_payment_intent(
             metadata: HashMap::new(),
             latest_charge: None,
             client_secret: Some(format!("{}_secret_{}", id, generate_stripe_id(""))),
+            cancellation_reason: None,
+            solana_pay: None,
         }
     });
 
@@ -220,18 +196,10 @@ pub async fn confirm_payment_intent(
     Path(id): Path<String>,
     body: Bytes,
 ) -> impl IntoResponse {
-    // Parse form-encoded body
-    let body_str = String::from_utf8_lossy(&body);
-    let mut params: HashMap<String, String> = HashMap::new();
-
-    for part in body_str.split('&') {
-        if let Some((key, value)) = part.split_once('=') {
-            let decoded_value = urlencoding::decode(value).unwrap_or_default().to_string();
-            params.insert(key.to_string(), decoded_value);
-        }
-    }
-
-    let payment_method = params.get("payment_method").cloned();
+    let payment_method = match ConfirmPaymentIntentRequest::parse(&body) {
+        Ok(request) => request.payment_method,
+        Err(e) => return e.into_response(),
+    };
 
     // Look up the payment intent from state
     let mut payment_intents = state.payment_intents.lock().unwrap();
@@ -243,6 +211,9 @@ pub async fn confirm_payment_intent(
             amount: 4990,
             currency: "usd".to_string(),
             status: PaymentIntentStatus::RequiresConfirmation,
+            capture_method: CaptureMethod::Automatic,
+            amount_capturable: 0,
+            amount_received: 0,
             created: chrono::Utc::now().timestamp(),
             customer: None,
             payment_method: None,
@@ -250,33 +221,327 @@ pub async fn confirm_payment_intent(
             metadata: HashMap::new(),
             latest_charge: None,
             client_secret: Some(format!("{}_secret_{}", id, generate_stripe_id(""))),
+            cancellation_reason: None,
+            solana_pay: None,
         }
     });
 
-    // Update the payment intent status to succeeded
-    payment_intent.status = PaymentIntentStatus::Succeeded;
-    payment_intent.latest_charge = Some(generate_stripe_id("ch"));
+    // Manual capture only authorizes the payment: the middleware holds the signed
+    // payload until the intent is captured or canceled
+    if payment_intent.capture_method == CaptureMethod::Manual {
+        payment_intent.status = PaymentIntentStatus::RequiresCapture;
+        payment_intent.amount_capturable = payment_intent.amount;
+    } else {
+        payment_intent.status = PaymentIntentStatus::Succeeded;
+        payment_intent.amount_received = payment_intent.amount;
+        payment_intent.latest_charge = Some(generate_stripe_id("ch"));
+    }
     if let Some(pm) = payment_method {
         payment_intent.payment_method = Some(pm);
     }
 
     // Store the updated payment intent
-    payment_intents.insert(id, payment_intent.clone());
+    payment_intents.insert(id.clone(), payment_intent.clone());
+    drop(payment_intents);
+
+    if payment_intent.status == PaymentIntentStatus::Succeeded {
+        record_payment_intent_success(&state, &id);
+    }
 
     (StatusCode::OK, Json(payment_intent)).into_response()
 }
 
+/// POST /v1/payment_intents/:id/capture - Capture an authorized payment intent
+///
+/// Settles the payment reserved when a `capture_method=manual` intent was confirmed.
+/// `amount_to_capture` defaults to the full `amount_capturable`; the customer signed a
+/// transfer of the full amount, so a partial capture settles it and refunds the rest.
+pub async fn capture_payment_intent(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> impl IntoResponse {
+    let amount_to_capture = match CapturePaymentIntentRequest::parse(&body) {
+        Ok(request) => request.amount_to_capture,
+        Err(e) => return e.into_response(),
+    };
+
+    expire_authorizations(&state, chrono::Utc::now().timestamp());
+
+    let Some(payment_intent) = state.payment_intents.lock().unwrap().get(&id).cloned() else {
+        return payment_intent_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such payment_intent: '{}'", id),
+        );
+    };
+
+    if payment_intent.status != PaymentIntentStatus::RequiresCapture {
+        return capture_unexpected_state(&payment_intent.status);
+    }
+
+    let amount_capturable = payment_intent.amount_capturable;
+    let amount_to_capture = amount_to_capture.unwrap_or(amount_capturable);
+    if amount_to_capture > amount_capturable {
+        return payment_intent_error(
+            StatusCode::BAD_REQUEST,
+            "amount_too_large",
+            format!(
+                "amount_to_capture ({}) cannot exceed amount_capturable ({})",
+                amount_to_capture, amount_capturable
+            ),
+        );
+    }
+    if amount_to_capture < 1 {
+        return payment_intent_error(
+            StatusCode::BAD_REQUEST,
+            "amount_too_small",
+            format!(
+                "amount_to_capture ({}) must be at least 1",
+                amount_to_capture
+            ),
+        );
+    }
+    let amount_to_refund = amount_capturable - amount_to_capture;
+
+    // Move the intent to processing while it settles, so that a concurrent capture or
+    // cancel can't act on the same authorization
+    let authorization = {
+        let mut payment_intents = state.payment_intents.lock().unwrap();
+        let Some(payment_intent) = payment_intents.get_mut(&id) else {
+            return capture_unexpected_state(&PaymentIntentStatus::Canceled);
+        };
+        if payment_intent.status != PaymentIntentStatus::RequiresCapture {
+            return capture_unexpected_state(&payment_intent.status);
+        }
+        let Some(authorization) = state.authorizations.lock().unwrap().get(&id).cloned() else {
+            return payment_intent_error(
+                StatusCode::BAD_REQUEST,
+                "payment_intent_authorization_missing",
+                format!("No authorized payment found for payment_intent '{}'", id),
+            );
+        };
+        if amount_to_refund > 0
+            && let Err(e) = check_refundable(&state, &authorization)
+        {
+            return payment_intent_error(
+                StatusCode::BAD_REQUEST,
+                "amount_too_small",
+                format!(
+                    "This PaymentIntent can only be captured in full ({}): {}",
+                    amount_capturable, e
+                ),
+            );
+        }
+        payment_intent.status = PaymentIntentStatus::Processing;
+        authorization
+    };
+
+    if let Err(e) = settle_payment_with_facilitator(
+        &state,
+        &authorization.payment_payload,
+        &authorization.payment_requirements,
+    )
+    .await
+    {
+        error!("Capture of payment intent {} failed: {}", id, e);
+        release_capture(&state, &id, chrono::Utc::now().timestamp());
+        return payment_intent_error(
+            StatusCode::PAYMENT_REQUIRED,
+            "payment_settlement_failed",
+            format!("Failed to capture payment: {}", e),
+        );
+    }
+    remove_authorization(&state, &id);
+    info!("Captured payment intent {}", id);
+
+    // The full authorized amount was settled: give back what isn't captured
+    let refund_error = if amount_to_refund > 0 {
+        match refund_uncaptured(&state, &authorization, amount_to_refund, amount_capturable).await {
+            Ok(signature) => {
+                info!(
+                    "Refunded {} uncaptured of payment intent {}: {}",
+                    amount_to_refund, id, signature
+                );
+                None
+            }
+            Err(e) => {
+                error!(
+                    "Refund of {} uncaptured of payment intent {} failed: {}",
+                    amount_to_refund, id, e
+                );
+                Some(e)
+            }
+        }
+    } else {
+        None
+    };
+    let amount_received = if refund_error.is_some() {
+        amount_capturable
+    } else {
+        amount_to_capture
+    };
+
+    let mut payment_intents = state.payment_intents.lock().unwrap();
+    let mut payment_intent = payment_intents.get(&id).cloned().unwrap_or(payment_intent);
+    payment_intent.status = PaymentIntentStatus::Succeeded;
+    payment_intent.amount_received = amount_received;
+    payment_intent.amount_capturable = 0;
+    payment_intent.latest_charge = Some(generate_stripe_id("ch"));
+    payment_intents.insert(id.clone(), payment_intent.clone());
+    drop(payment_intents);
+
+    record_payment_intent_success(&state, &id);
+
+    if let Some(e) = refund_error {
+        return payment_intent_error(
+            StatusCode::BAD_GATEWAY,
+            "capture_refund_failed",
+            format!(
+                "The full amount ({}) was captured, but refunding the uncaptured {} failed: {}",
+                amount_capturable, amount_to_refund, e
+            ),
+        );
+    }
+
+    (StatusCode::OK, Json(payment_intent)).into_response()
+}
+
+/// Return a payment intent whose capture failed to settle to `requires_capture`, so that
+/// it can be retried, or cancel it if its authorization lapsed in the meantime
+fn release_capture(state: &CatalogState, payment_intent_id: &str, now: i64) {
+    let mut payment_intents = state.payment_intents.lock().unwrap();
+    let Some(payment_intent) = payment_intents.get_mut(payment_intent_id) else {
+        return;
+    };
+    if payment_intent.status != PaymentIntentStatus::Processing {
+        return;
+    }
+    let mut authorizations = state.authorizations.lock().unwrap();
+    if authorizations
+        .get(payment_intent_id)
+        .is_some_and(|authorization| !authorization.is_expired(now))
+    {
+        payment_intent.status = PaymentIntentStatus::RequiresCapture;
+    } else {
+        info!(
+            "Authorization for payment intent {} expired",
+            payment_intent_id
+        );
+        authorizations.remove(payment_intent_id);
+        forget_authorization(state, payment_intent_id);
+        payment_intent.status = PaymentIntentStatus::Canceled;
+        payment_intent.amount_capturable = 0;
+        payment_intent.cancellation_reason = Some("automatic".to_string());
+    }
+}
+
+fn capture_unexpected_state(status: &PaymentIntentStatus) -> axum::response::Response {
+    payment_intent_error(
+        StatusCode::BAD_REQUEST,
+        "payment_intent_unexpected_state",
+        format!(
+            "This PaymentIntent could not be captured because it has a status of {}.",
+            status_name(status)
+        ),
+    )
+}
+
+fn status_name(status: &PaymentIntentStatus) -> String {
+    serde_json::to_value(status)
+        .ok()
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .unwrap_or_default()
+}
+
+/// Record what a succeeded payment intent pays for: its coupon redemption, invoice,
+/// subscription and experiment conversions
+pub(crate) fn record_payment_intent_success(state: &CatalogState, payment_intent_id: &str) {
+    record_payment_intent_redemption(state, payment_intent_id);
+    emit_payment_intent_succeeded(state, payment_intent_id);
+    record_invoice_payment(state, payment_intent_id);
+    record_subscription_payment(state, payment_intent_id);
+    record_payment_intent_conversions(state, payment_intent_id);
+}
+
+/// Emit `payment_intent.succeeded`, and `checkout.session.completed` for the checkout
+/// session the payment intent was created for
+pub(crate) fn emit_payment_intent_succeeded(state: &CatalogState, payment_intent_id: &str) {
+    let Some(payment_intent) = state
+        .payment_intents
+        .lock()
+        .unwrap()
+        .get(payment_intent_id)
+        .cloned()
+    else {
+        return;
+    };
+    let now = chrono::Utc::now().timestamp();
+    emit_stripe_event(state, "payment_intent.succeeded", &payment_intent, now);
+
+    let session_id = state
+        .checkout_sessions
+        .lock()
+        .unwrap()
+        .values()
+        .find(|session| session.payment_intent.as_deref() == Some(payment_intent_id))
+        .map(|session| session.id.clone());
+    if let Some(session) = session_id.and_then(|id| current_checkout_session(state, &id)) {
+        emit_stripe_event(state, "checkout.session.completed", &session, now);
+    }
+}
+
 /// POST /v1/payment_intents/:id/cancel - Cancel a payment intent
+///
+/// Any payment reserved by a manual-capture confirmation is discarded without settling.
 pub async fn cancel_payment_intent(
-    Extension(_state): Extension<CatalogState>,
+    Extension(state): Extension<CatalogState>,
     Path(id): Path<String>,
 ) -> impl IntoResponse {
+    expire_authorizations(&state, chrono::Utc::now().timestamp());
+
+    let mut payment_intents = state.payment_intents.lock().unwrap();
+    if let Some(payment_intent) = payment_intents.get_mut(&id) {
+        // A capture being settled can't be called off anymore
+        if matches!(
+            payment_intent.status,
+            PaymentIntentStatus::Succeeded | PaymentIntentStatus::Processing
+        ) {
+            return payment_intent_error(
+                StatusCode::BAD_REQUEST,
+                "payment_intent_unexpected_state",
+                format!(
+                    "You cannot cancel this PaymentIntent because it has a status of {}.",
+                    status_name(&payment_intent.status)
+                ),
+            );
+        }
+        payment_intent.status = PaymentIntentStatus::Canceled;
+        payment_intent.amount_capturable = 0;
+        payment_intent.cancellation_reason = Some("requested_by_customer".to_string());
+        let payment_intent = payment_intent.clone();
+        drop(payment_intents);
+        release_authorization(&state, &id);
+        emit_stripe_event(
+            &state,
+            "payment_intent.canceled",
+            &payment_intent,
+            chrono::Utc::now().timestamp(),
+        );
+        return (StatusCode::OK, Json(payment_intent)).into_response();
+    }
+    drop(payment_intents);
+    release_authorization(&state, &id);
+
     let payment_intent = StripePaymentIntent {
         id: id.clone(),
         object: "payment_intent".to_string(),
         amount: 4990, // Would come from storage
         currency: "usd".to_string(),
         status: PaymentIntentStatus::Canceled,
+        capture_method: CaptureMethod::Automatic,
+        amount_capturable: 0,
+        amount_received: 0,
         created: chrono::Utc::now().timestamp(),
         customer: None,
         payment_method: None,
@@ -284,7 +549,310 @@ pub async fn cancel_payment_intent(
         metadata: HashMap::new(),
         latest_charge: None,
         client_secret: Some(format!("{}_secret_{}", id, generate_stripe_id(""))),
+        cancellation_reason: None,
+        solana_pay: None,
     };
 
     (StatusCode::OK, Json(payment_intent)).into_response()
 }
+
+fn release_authorization(state: &CatalogState, payment_intent_id: &str) {
+    if remove_authorization(state, payment_intent_id) {
+        info!(
+            "Released authorization for payment intent {}",
+            payment_intent_id
+        );
+    }
+}
+
+fn payment_intent_error(
+    status: StatusCode,
+    code: &str,
+    message: String,
+) -> axum::response::Response {
+    (
+        status,
+        Json(serde_json::json!({
+            "error": {
+                "code": code,
+                "message": message,
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{Router, routing::post};
+    use moneymq_types::x402::{
+        ExactPaymentPayload, ExactSolanaPayload, MixedAddress, Network, PaymentPayload,
+        PaymentRequirements, Scheme, TokenAmount, X402Version,
+    };
+    use serde_json::{Value, json};
+    use solana_pubkey::Pubkey;
+
+    use super::*;
+    use crate::api::{
+        catalog::authorization::{PaymentAuthorization, hold_authorization, refund_units},
+        payment::db::DbManager,
+    };
+
+    /// Serve a facilitator whose `/settle` succeeds or fails, returning its URL
+    async fn facilitator(success: bool) -> url::Url {
+        let settle = move || async move {
+            Json(json!({
+                "success": success,
+                "payer": Pubkey::new_from_array([3; 32]).to_string(),
+                "network": "solana",
+            }))
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/settle", post(settle)))
+                .await
+                .unwrap()
+        });
+        format!("http://{}/", address).parse().unwrap()
+    }
+
+    async fn state(settles: bool) -> CatalogState {
+        CatalogState::new(
+            Vec::new(),
+            Vec::new(),
+            true,
+            facilitator(settles).await,
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+    }
+
+    async fn body(response: axum::response::Response) -> (StatusCode, Value) {
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    /// Create a manual-capture payment intent of 1000 and reserve a payment for it
+    async fn authorized_intent(state: &CatalogState, expires_at: i64) -> String {
+        let response = create_payment_intent(
+            Extension(state.clone()),
+            Bytes::from("amount=1000&currency=usd&confirm=true&capture_method=manual"),
+        )
+        .await
+        .into_response();
+        let (_, intent) = body(response).await;
+        let id = intent["id"].as_str().unwrap().to_string();
+
+        let authorization = PaymentAuthorization {
+            payment_intent_id: id.clone(),
+            payment_payload: PaymentPayload {
+                x402_version: X402Version::V1,
+                scheme: Scheme::Exact,
+                network: Network::Solana,
+                payload: ExactPaymentPayload::Solana(ExactSolanaPayload {
+                    transaction: "signed".to_string(),
+                }),
+            },
+            payment_requirements: PaymentRequirements {
+                scheme: Scheme::Exact,
+                network: Network::Solana,
+                max_amount_required: TokenAmount("10000000".to_string()),
+                resource: "http://localhost:8488/v1/payment_intents".parse().unwrap(),
+                description: "Payment intent".to_string(),
+                mime_type: "application/json".to_string(),
+                output_schema: None,
+                pay_to: MixedAddress::Solana(Pubkey::new_from_array([1; 32])),
+                max_timeout_seconds: 300,
+                asset: MixedAddress::Solana(Pubkey::new_from_array([2; 32])),
+                extra: None,
+            },
+            authorized_at: chrono::Utc::now().timestamp(),
+            expires_at,
+        };
+        hold_authorization(state, authorization);
+        id
+    }
+
+    async fn capture(state: &CatalogState, id: &str, form: &'static str) -> (StatusCode, Value) {
+        let response = capture_payment_intent(
+            Extension(state.clone()),
+            Path(id.to_string()),
+            Bytes::from(form),
+        )
+        .await
+        .into_response();
+        body(response).await
+    }
+
+    fn in_an_hour() -> i64 {
+        chrono::Utc::now().timestamp() + 3600
+    }
+
+    #[tokio::test]
+    async fn test_create_manual_capture_requires_capture() {
+        let state = state(true).await;
+        let response = create_payment_intent(
+            Extension(state.clone()),
+            Bytes::from("amount=1000&currency=usd&confirm=true&capture_method=manual"),
+        )
+        .await
+        .into_response();
+        let (status, intent) = body(response).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(intent["status"], "requires_capture");
+        assert_eq!(intent["capture_method"], "manual");
+        assert_eq!(intent["amount_capturable"], 1000);
+        assert_eq!(intent["amount_received"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_capture_in_full() {
+        let state = state(true).await;
+        let id = authorized_intent(&state, in_an_hour()).await;
+
+        let (status, intent) = capture(&state, &id, "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(intent["status"], "succeeded");
+        assert_eq!(intent["amount_received"], 1000);
+        assert_eq!(intent["amount_capturable"], 0);
+        assert!(intent["latest_charge"].is_string());
+        assert!(state.authorizations.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_double_capture_is_refused() {
+        let state = state(true).await;
+        let id = authorized_intent(&state, in_an_hour()).await;
+
+        // A capture still settling holds the intent in processing
+        state
+            .payment_intents
+            .lock()
+            .unwrap()
+            .get_mut(&id)
+            .unwrap()
+            .status = PaymentIntentStatus::Processing;
+        let (status, error) = capture(&state, &id, "").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"]["code"], "payment_intent_unexpected_state");
+        assert!(state.authorizations.lock().unwrap().contains_key(&id));
+
+        state
+            .payment_intents
+            .lock()
+            .unwrap()
+            .get_mut(&id)
+            .unwrap()
+            .status = PaymentIntentStatus::RequiresCapture;
+        let (status, _) = capture(&state, &id, "").await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, error) = capture(&state, &id, "").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"]["code"], "payment_intent_unexpected_state");
+        let intent = state.payment_intents.lock().unwrap()[&id].clone();
+        assert_eq!(intent.amount_received, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_releases_authorization() {
+        let state = state(true).await;
+        let id = authorized_intent(&state, in_an_hour()).await;
+
+        let response = cancel_payment_intent(Extension(state.clone()), Path(id.clone()))
+            .await
+            .into_response();
+        let (status, intent) = body(response).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(intent["status"], "canceled");
+        assert_eq!(intent["amount_capturable"], 0);
+        assert!(state.authorizations.lock().unwrap().is_empty());
+
+        let (status, error) = capture(&state, &id, "").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"]["code"], "payment_intent_unexpected_state");
+    }
+
+    #[tokio::test]
+    async fn test_capture_after_expiry() {
+        let state = state(true).await;
+        let id = authorized_intent(&state, chrono::Utc::now().timestamp() - 1).await;
+
+        let (status, error) = capture(&state, &id, "").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"]["code"], "payment_intent_unexpected_state");
+        let intent = state.payment_intents.lock().unwrap()[&id].clone();
+        assert_eq!(intent.status, PaymentIntentStatus::Canceled);
+        assert_eq!(intent.cancellation_reason.as_deref(), Some("automatic"));
+        assert!(state.authorizations.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_authorization_survives_restart() {
+        let db = Arc::new(DbManager::local(":memory:").unwrap());
+        let before = state(true).await.with_payment_db(db.clone(), "test");
+        let id = authorized_intent(&before, in_an_hour()).await;
+
+        // A server restarted on the same database holds the payment and its intent again
+        let after = state(true).await.with_payment_db(db.clone(), "test");
+        assert!(after.authorizations.lock().unwrap().contains_key(&id));
+        let intent = after.payment_intents.lock().unwrap()[&id].clone();
+        assert_eq!(intent.status, PaymentIntentStatus::RequiresCapture);
+        assert_eq!(intent.amount_capturable, 1000);
+
+        let (status, intent) = capture(&after, &id, "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(intent["status"], "succeeded");
+        let after = state(true).await.with_payment_db(db, "test");
+        assert!(after.authorizations.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_settlement_keeps_authorization() {
+        let state = state(false).await;
+        let id = authorized_intent(&state, in_an_hour()).await;
+
+        let (status, error) = capture(&state, &id, "").await;
+        assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+        assert_eq!(error["error"]["code"], "payment_settlement_failed");
+        let intent = state.payment_intents.lock().unwrap()[&id].clone();
+        assert_eq!(intent.status, PaymentIntentStatus::RequiresCapture);
+        assert_eq!(intent.amount_capturable, 1000);
+        assert!(state.authorizations.lock().unwrap().contains_key(&id));
+    }
+
+    #[tokio::test]
+    async fn test_partial_capture() {
+        let state = state(true).await;
+        let id = authorized_intent(&state, in_an_hour()).await;
+
+        let (status, error) = capture(&state, &id, "amount_to_capture=1001").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"]["code"], "amount_too_large");
+        let (status, error) = capture(&state, &id, "amount_to_capture=0").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"]["code"], "amount_too_small");
+
+        // The difference can't be refunded without the recipient's key and an RPC endpoint
+        let (status, error) = capture(&state, &id, "amount_to_capture=400").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"]["code"], "amount_too_small");
+        let intent = state.payment_intents.lock().unwrap()[&id].clone();
+        assert_eq!(intent.status, PaymentIntentStatus::RequiresCapture);
+        assert!(state.authorizations.lock().unwrap().contains_key(&id));
+
+        let authorization = state.authorizations.lock().unwrap()[&id].clone();
+        assert_eq!(refund_units(&authorization, 600, 1000).unwrap(), 6_000_000);
+        assert_eq!(refund_units(&authorization, 1, 3).unwrap(), 3_333_333);
+        assert!(refund_units(&authorization, 4, 3).is_err());
+    }
+}
@@ -0,0 +1,491 @@
+//! Payment links: reusable URLs selling a fixed set of prices
+//!
+//! Links are declared in the catalog (`payment_links/*.yaml`) or created with
+//! `POST /v1/payment_links`; links created through the API live in memory. Every visit of a
+//! link's `url` opens a fresh checkout session (see [`payment_link_page`]), tagged with the
+//! link, which the link's stats are counted from.
+//!
+//! [`payment_link_page`]: crate::api::catalog::payment_link_page
+
+use std::collections::BTreeMap;
+
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::{OriginalUri, Path, RawQuery},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use moneymq_types::{PaymentLink, PaymentLinkLineItem, Price, Product};
+
+use crate::api::catalog::{
+    Catalog, CatalogState,
+    payment_link_page::payment_link_url,
+    stripe::{
+        endpoints::{
+            checkout_sessions::current_checkout_session, prices::find_price,
+            products::apply_metadata,
+        },
+        list::CatalogQuery,
+        types::{
+            AfterCompletion, CheckoutSessionStatus, CreatePaymentLinkRequest, ListResponse,
+            PaymentLinkLineItems, PaymentLinkStats, StripePaymentLink, StripePaymentLinkLineItem,
+            UpdatePaymentLinkRequest, product_id,
+        },
+        utils::generate_stripe_id,
+    },
+    webhooks::{emit_stripe_event, emit_stripe_update},
+};
+
+fn payment_link_error(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(serde_json::json!({
+            "error": {
+                "code": code,
+                "message": message.into(),
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+pub(crate) fn no_such_payment_link(id: &str) -> Response {
+    payment_link_error(
+        StatusCode::NOT_FOUND,
+        "resource_missing",
+        format!("No such payment link: '{}'", id),
+    )
+}
+
+/// Product and price a payment link's line item sells: its price, or the first active
+/// price of its product
+pub(crate) fn resolve_line_item<'a>(
+    state: &CatalogState,
+    catalog: &'a Catalog,
+    item: &PaymentLinkLineItem,
+) -> Option<(&'a Product, &'a Price)> {
+    if let Some(price) = &item.price {
+        return find_price(state, catalog, price).map(|(price, product)| (product, price));
+    }
+    let id = item.product.as_deref()?;
+    let product = catalog
+        .products
+        .iter()
+        .find(|p| p.id == id || product_id(p, state.use_sandbox) == id)?;
+    let price = product.prices.iter().find(|price| price.active)?;
+    Some((product, price))
+}
+
+/// Payment link `id`, declared in the catalog or created through the API, and whether it
+/// is declared
+pub(crate) fn find_payment_link(
+    state: &CatalogState,
+    catalog: &Catalog,
+    id: &str,
+) -> Option<(PaymentLink, bool)> {
+    if let Some(link) = catalog.payment_links.iter().find(|link| link.id == id) {
+        return Some((link.clone(), true));
+    }
+    state
+        .payment_links
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|link| link.id == id)
+        .map(|link| (link.clone(), false))
+}
+
+fn stripe_payment_link(
+    state: &CatalogState,
+    catalog: &Catalog,
+    link: &PaymentLink,
+    declared: bool,
+    url: String,
+) -> StripePaymentLink {
+    let data = link
+        .line_items
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| {
+            let (product, price) = resolve_line_item(state, catalog, item)?;
+            Some(StripePaymentLinkLineItem {
+                id: format!("li_{}_{}", link.id, index),
+                object: "item".to_string(),
+                price: item.price.clone().unwrap_or_else(|| price.id.clone()),
+                product: product_id(product, state.use_sandbox),
+                description: product.name.clone(),
+                currency: price.currency.as_str().to_string(),
+                unit_amount: price.unit_amount,
+                unit_amount_decimal: price.exact_unit_amount().map(|amount| amount.to_string()),
+                quantity: item.quantity,
+                adjustable_quantity: item.adjustable_quantity,
+            })
+        })
+        .collect();
+
+    StripePaymentLink {
+        id: link.id.clone(),
+        object: "payment_link".to_string(),
+        active: link.active,
+        url,
+        currency: link.currency.clone(),
+        line_items: PaymentLinkLineItems {
+            object: "list".to_string(),
+            data,
+            has_more: false,
+            url: format!("/v1/payment_links/{}/line_items", link.id),
+        },
+        custom_fields: link.custom_fields.clone(),
+        after_completion: AfterCompletion::new(link.success_url.as_deref()),
+        declared,
+        created: link.created,
+        metadata: link
+            .metadata
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        livemode: !state.use_sandbox,
+    }
+}
+
+/// POST /v1/payment_links - Create a payment link
+pub async fn create_payment_link(
+    Extension(state): Extension<CatalogState>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let request = match CreatePaymentLinkRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    let catalog = state.catalog();
+    for (index, item) in request.line_items.iter().enumerate() {
+        if resolve_line_item(&state, &catalog, item).is_none() {
+            let id = item.price.as_deref().or(item.product.as_deref());
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": {
+                        "code": "resource_missing",
+                        "message": format!("No such price: '{}'", id.unwrap_or_default()),
+                        "param": format!("line_items[{}][price]", index),
+                        "type": "invalid_request_error"
+                    }
+                })),
+            )
+                .into_response();
+        }
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let link = request.payment_link(generate_stripe_id("plink"), now);
+    state.payment_links.lock().unwrap().push(link.clone());
+
+    let url = payment_link_url(&state, &headers, uri.path(), &link.id);
+    let payment_link = stripe_payment_link(&state, &catalog, &link, false, url);
+    emit_stripe_event(&state, "payment_link.created", &payment_link, now);
+    (StatusCode::OK, Json(payment_link)).into_response()
+}
+
+/// GET /v1/payment_links - List payment links, declared ones first
+///
+/// Links can be filtered by `active`.
+pub async fn list_payment_links(
+    Extension(state): Extension<CatalogState>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> Response {
+    let query = match CatalogQuery::parse(query.as_deref(), &[]) {
+        Ok(query) => query,
+        Err(e) => return e.into_response(),
+    };
+    let active = match query.bool_param("active") {
+        Ok(active) => active,
+        Err(e) => return e.into_response(),
+    };
+    let catalog = state.catalog();
+    let links = catalog
+        .payment_links
+        .iter()
+        .cloned()
+        .map(|link| (link, true))
+        .chain(
+            state
+                .payment_links
+                .lock()
+                .unwrap()
+                .iter()
+                .cloned()
+                .map(|link| (link, false)),
+        )
+        .filter(|(link, _)| active.is_none_or(|active| link.active == active))
+        .collect::<Vec<_>>();
+    let (links, has_more) =
+        match query.paginate(links, "payment_link", |(link, _)| link.id.as_str()) {
+            Ok(page) => page,
+            Err(e) => return e.into_response(),
+        };
+
+    let response = ListResponse {
+        object: "list".to_string(),
+        data: links
+            .into_iter()
+            .map(|(link, declared)| {
+                let url = payment_link_url(&state, &headers, uri.path(), &link.id);
+                stripe_payment_link(&state, &catalog, &link, declared, url)
+            })
+            .collect(),
+        has_more,
+        url: "/v1/payment_links".to_string(),
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// GET /v1/payment_links/:id - Retrieve a payment link
+pub async fn retrieve_payment_link(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+) -> Response {
+    let catalog = state.catalog();
+    let Some((link, declared)) = find_payment_link(&state, &catalog, &id) else {
+        return no_such_payment_link(&id);
+    };
+    let url = payment_link_url(&state, &headers, uri.path(), &link.id);
+    (
+        StatusCode::OK,
+        Json(stripe_payment_link(&state, &catalog, &link, declared, url)),
+    )
+        .into_response()
+}
+
+/// POST /v1/payment_links/:id - Update a payment link
+///
+/// Deactivate a link with `active=false`; `metadata[key]=` removes a key. Links declared
+/// in the catalog are updated in their file.
+pub async fn update_payment_link(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let request = match UpdatePaymentLinkRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    let catalog = state.catalog();
+    if catalog.payment_links.iter().any(|link| link.id == id) {
+        return payment_link_error(
+            StatusCode::BAD_REQUEST,
+            "payment_link_declared",
+            format!(
+                "Payment link '{}' is declared in the catalog files, update it there",
+                id
+            ),
+        );
+    }
+
+    let (before, after) = {
+        let mut links = state.payment_links.lock().unwrap();
+        let Some(link) = links.iter_mut().find(|link| link.id == id) else {
+            return no_such_payment_link(&id);
+        };
+        let before = link.clone();
+        if let Some(active) = request.active {
+            link.active = active;
+        }
+        apply_metadata(&mut link.metadata, request.metadata);
+        (before, link.clone())
+    };
+
+    let url = payment_link_url(&state, &headers, uri.path(), &id);
+    let after = stripe_payment_link(&state, &catalog, &after, false, url.clone());
+    emit_stripe_update(
+        &state,
+        "payment_link.updated",
+        &stripe_payment_link(&state, &catalog, &before, false, url),
+        &after,
+        chrono::Utc::now().timestamp(),
+    );
+    (StatusCode::OK, Json(after)).into_response()
+}
+
+/// GET /v1/payment_links/:id/line_items - Prices sold by a payment link
+pub async fn list_payment_link_line_items(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+) -> Response {
+    let catalog = state.catalog();
+    let Some((link, declared)) = find_payment_link(&state, &catalog, &id) else {
+        return no_such_payment_link(&id);
+    };
+    let url = payment_link_url(&state, &headers, uri.path(), &link.id);
+    let payment_link = stripe_payment_link(&state, &catalog, &link, declared, url);
+    (StatusCode::OK, Json(payment_link.line_items)).into_response()
+}
+
+/// Visits, payments and revenue of payment link `id`, from the checkout sessions opened
+/// from it
+pub(crate) fn link_stats(state: &CatalogState, id: &str) -> PaymentLinkStats {
+    let session_ids = state
+        .checkout_sessions
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|session| session.payment_link.as_deref() == Some(id))
+        .map(|session| session.id.clone())
+        .collect::<Vec<_>>();
+
+    let mut payments = 0;
+    let mut revenue = BTreeMap::new();
+    for session_id in &session_ids {
+        let Some(session) = current_checkout_session(state, session_id) else {
+            continue;
+        };
+        if session.status == CheckoutSessionStatus::Complete {
+            payments += 1;
+            *revenue.entry(session.currency.clone()).or_default() += session.amount_total;
+        }
+    }
+    let visits = session_ids.len() as i64;
+
+    PaymentLinkStats {
+        object: "payment_link_stats".to_string(),
+        payment_link: id.to_string(),
+        visits,
+        payments,
+        conversion_rate: if visits > 0 {
+            payments as f64 / visits as f64
+        } else {
+            0.0
+        },
+        revenue,
+        livemode: !state.use_sandbox,
+    }
+}
+
+/// GET /v1/payment_links/:id/stats - Visits, payments and revenue of a payment link
+///
+/// Every visit opens a checkout session; a payment is a session that was paid.
+pub async fn payment_link_stats(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> Response {
+    if find_payment_link(&state, &state.catalog(), &id).is_none() {
+        return no_such_payment_link(&id);
+    }
+    (StatusCode::OK, Json(link_stats(&state, &id))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use moneymq_types::{Currency, PricingType};
+
+    use super::*;
+    use crate::api::catalog::stripe::types::{
+        CheckoutLineItemList, PaymentStatus, StripeCheckoutSession,
+    };
+
+    fn state() -> CatalogState {
+        let mut price = Price::new(Currency::Usd, PricingType::OneTime).with_some_amount(Some(900));
+        price.id = "price_ebook".to_string();
+        let mut archived =
+            Price::new(Currency::Usd, PricingType::OneTime).with_some_amount(Some(500));
+        archived.active = false;
+        let mut product = Product::new()
+            .with_some_name(Some("Ebook".to_string()))
+            .add_price(archived)
+            .add_price(price);
+        product.id = "ebook".to_string();
+        CatalogState::new(
+            vec![product],
+            Vec::new(),
+            true,
+            "http://localhost:8488".parse().unwrap(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+    }
+
+    fn session(id: &str, link: &str, status: CheckoutSessionStatus) -> StripeCheckoutSession {
+        StripeCheckoutSession {
+            id: id.to_string(),
+            object: "checkout.session".to_string(),
+            status,
+            payment_status: PaymentStatus::Unpaid,
+            currency: "usd".to_string(),
+            amount_total: 900,
+            amount_subtotal: 900,
+            discounts: Vec::new(),
+            total_details: Default::default(),
+            created: 1_000,
+            expires_at: None,
+            customer: None,
+            customer_email: None,
+            customer_details: None,
+            payment_intent: None,
+            client_secret: None,
+            line_items: CheckoutLineItemList::default(),
+            metadata: Default::default(),
+            success_url: None,
+            cancel_url: None,
+            url: None,
+            solana_pay: None,
+            payment_link: Some(link.to_string()),
+            custom_fields: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_line_item() {
+        let state = state();
+        let catalog = state.catalog();
+        let item = |price: Option<&str>, product: Option<&str>| PaymentLinkLineItem {
+            price: price.map(str::to_string),
+            product: product.map(str::to_string),
+            quantity: 1,
+            adjustable_quantity: None,
+        };
+
+        // Products are sold at their first active price
+        let (_, price) = resolve_line_item(&state, &catalog, &item(None, Some("ebook"))).unwrap();
+        assert_eq!(price.id, "price_ebook");
+        let (product, _) =
+            resolve_line_item(&state, &catalog, &item(Some("price_ebook"), None)).unwrap();
+        assert_eq!(product.id, "ebook");
+        assert!(resolve_line_item(&state, &catalog, &item(Some("price_unknown"), None)).is_none());
+    }
+
+    #[test]
+    fn test_link_stats() {
+        let state = state();
+        {
+            let mut sessions = state.checkout_sessions.lock().unwrap();
+            for (id, link, status) in [
+                ("cs_1", "plink_1", CheckoutSessionStatus::Complete),
+                ("cs_2", "plink_1", CheckoutSessionStatus::Open),
+                ("cs_3", "plink_1", CheckoutSessionStatus::Expired),
+                ("cs_4", "plink_2", CheckoutSessionStatus::Complete),
+            ] {
+                sessions.insert(id.to_string(), session(id, link, status));
+            }
+        }
+
+        let stats = link_stats(&state, "plink_1");
+        assert_eq!((stats.visits, stats.payments), (3, 1));
+        assert!((stats.conversion_rate - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.revenue.get("usd"), Some(&900));
+        assert_eq!(link_stats(&state, "plink_3").visits, 0);
+    }
+}
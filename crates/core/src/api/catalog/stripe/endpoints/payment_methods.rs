@@ -1,4 +1,4 @@
-use axum::{Extension, Form, Json, extract::Path, http::StatusCode, response::IntoResponse};
+use axum::{Extension, Json, body::Bytes, extract::Path, http::StatusCode, response::IntoResponse};
 
 use crate::api::catalog::{
     CatalogState,
@@ -13,8 +13,13 @@ use crate::api::catalog::{
 /// POST /v1/payment_methods - Create a payment method
 pub async fn create_payment_method(
     Extension(_state): Extension<CatalogState>,
-    Form(request): Form<CreatePaymentMethodRequest>,
+    body: Bytes,
 ) -> impl IntoResponse {
+    let request = match CreatePaymentMethodRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+
     // Generate a mock payment method ID
     let pm_id = generate_stripe_id("pm");
     let created = chrono::Utc::now().timestamp();
@@ -47,8 +52,12 @@ pub async fn create_payment_method(
 pub async fn attach_payment_method(
     Extension(_state): Extension<CatalogState>,
     Path(payment_method_id): Path<String>,
-    Form(request): Form<AttachPaymentMethodRequest>,
+    body: Bytes,
 ) -> impl IntoResponse {
+    let request = match AttachPaymentMethodRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
     let created = chrono::Utc::now().timestamp();
 
     // Return a mock attached payment method
@@ -1,85 +1,443 @@
-use axum::{Extension, Json, response::IntoResponse};
-use moneymq_types::Product;
+use std::collections::HashMap;
+
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::{Path, RawQuery},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use moneymq_types::{Currency, Decimal, Price, PricingType, Product, RecurringInterval};
 
 use crate::api::catalog::{
-    CatalogState,
-    stripe::types::{ListParams, ListResponse, StripePrice},
+    Catalog, CatalogState,
+    store::save_product,
+    stripe::{
+        endpoints::products::{apply_metadata, find_product_index, no_such_product},
+        form::{FormError, StripeForm},
+        list::CatalogQuery,
+        types::{ListResponse, StripePrice, StripeProduct, price_id, product_id},
+        utils::generate_stripe_id,
+    },
+    webhooks::{emit_stripe_event, emit_stripe_update},
 };
 
-/// GET /v1/prices - List prices
+/// Expansions supported by GET /v1/prices
+const LIST_EXPANDABLE: &[&str] = &["data.product"];
+
+/// Expansions supported by GET /v1/prices/:id
+const RETRIEVE_EXPANDABLE: &[&str] = &["product"];
+
+/// Stripe-compatible price, with its product expanded into the product object on request
+fn stripe_price(
+    price: &Price,
+    product: &Product,
+    use_sandbox: bool,
+    expand_product: bool,
+) -> serde_json::Value {
+    let stripe_price = StripePrice::from_price_and_product(price, product, use_sandbox);
+    let mut value = serde_json::to_value(stripe_price).unwrap_or_default();
+    if expand_product
+        && let Ok(product) = serde_json::to_value(StripeProduct::from_product(product, use_sandbox))
+    {
+        value["product"] = product;
+    }
+    value
+}
+
+/// Catalog price with the ID clients use for it, or its catalog ID, and its product
+pub(crate) fn find_price<'a>(
+    state: &CatalogState,
+    catalog: &'a Catalog,
+    id: &str,
+) -> Option<(&'a Price, &'a Product)> {
+    catalog.products.iter().find_map(|product| {
+        product
+            .prices
+            .iter()
+            .find(|price| price.id == id || price_id(price, state.use_sandbox) == id)
+            .map(|price| (price, product))
+    })
+}
+
+/// GET /v1/prices - List prices (sorted by unit amount ASC)
+///
+/// Prices can be filtered by `active`, `product`, `type`, `currency`,
+/// `recurring[interval]` and `created`, and their product expanded with
+/// `expand[]=data.product`.
 pub async fn list_prices(
     Extension(state): Extension<CatalogState>,
-    axum::extract::Query(params): axum::extract::Query<ListParams>,
+    RawQuery(query): RawQuery,
 ) -> impl IntoResponse {
-    let limit = params.limit.unwrap_or(10).min(100) as usize;
+    let query = match CatalogQuery::parse(query.as_deref(), LIST_EXPANDABLE) {
+        Ok(query) => query,
+        Err(e) => return e.into_response(),
+    };
+    let active = match query.bool_param("active") {
+        Ok(active) => active,
+        Err(e) => return e.into_response(),
+    };
 
     // Collect all prices from all products
-    let mut all_prices: Vec<(moneymq_types::Price, Product)> = Vec::new();
+    let catalog = state.catalog();
+    let mut all_prices: Vec<(&Price, &Product)> = Vec::new();
 
-    for product in state.products.iter() {
+    for product in catalog.products.iter() {
         // If product filter is specified, only include prices for that product
-        if let Some(ref product_filter) = params.product {
-            let product_external_id = if state.use_sandbox {
-                product.sandboxes.get("default")
-            } else {
-                product.deployed_id.as_ref()
-            };
-
-            if product_external_id
-                .map(|id| id != product_filter)
-                .unwrap_or(true)
-            {
-                continue;
-            }
+        if let Some(product_filter) = query.param("product")
+            && product.id != product_filter
+            && product_id(product, state.use_sandbox) != product_filter
+        {
+            continue;
         }
 
         for price in &product.prices {
-            all_prices.push((price.clone(), product.clone()));
+            let matches = active.is_none_or(|active| price.active == active)
+                && query
+                    .param("type")
+                    .is_none_or(|t| price.pricing_type.as_str() == t)
+                && query
+                    .param("currency")
+                    .is_none_or(|c| price.currency.as_str().eq_ignore_ascii_case(c))
+                && query.param("recurring[interval]").is_none_or(|interval| {
+                    price
+                        .recurring_interval
+                        .as_ref()
+                        .is_some_and(|i| i.as_str() == interval)
+                })
+                && query.created.contains(price.created_at.timestamp());
+            if matches {
+                all_prices.push((price, product));
+            }
         }
     }
 
     // Sort by unit_amount ascending (prices without amount go last)
-    all_prices.sort_by(|(a, _), (b, _)| match (a.unit_amount, b.unit_amount) {
-        (Some(a_amt), Some(b_amt)) => a_amt.cmp(&b_amt),
-        (Some(_), None) => std::cmp::Ordering::Less,
-        (None, Some(_)) => std::cmp::Ordering::Greater,
-        (None, None) => std::cmp::Ordering::Equal,
-    });
+    all_prices.sort_by(
+        |(a, _), (b, _)| match (a.exact_unit_amount(), b.exact_unit_amount()) {
+            (Some(a_amt), Some(b_amt)) => a_amt.cmp(&b_amt),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        },
+    );
 
-    // Find starting position
-    let start_idx = if let Some(starting_after) = params.starting_after {
-        all_prices
-            .iter()
-            .position(|(price, _)| {
-                let external_id = if state.use_sandbox {
-                    price.sandboxes.get("default")
-                } else {
-                    price.deployed_id.as_ref()
-                };
-                external_id.map(|id| id == &starting_after).unwrap_or(false)
-            })
-            .map(|idx| idx + 1)
-            .unwrap_or(0)
-    } else {
-        0
+    let all_prices = all_prices
+        .into_iter()
+        .map(|(price, product)| (price_id(price, state.use_sandbox), price, product))
+        .collect::<Vec<_>>();
+    let (page, has_more) = match query.paginate(all_prices, "price", |(id, _, _)| id.as_str()) {
+        Ok(page) => page,
+        Err(e) => return e.into_response(),
     };
 
-    let end_idx = (start_idx + limit).min(all_prices.len());
-    let prices_slice = &all_prices[start_idx..end_idx];
-
-    let stripe_prices: Vec<StripePrice> = prices_slice
-        .iter()
-        .map(|(price, product)| {
-            StripePrice::from_price_and_product(price, product, state.use_sandbox)
-        })
+    let expand_product = query.expands("data.product");
+    let stripe_prices: Vec<serde_json::Value> = page
+        .into_iter()
+        .map(|(_, price, product)| stripe_price(price, product, state.use_sandbox, expand_product))
         .collect();
 
-    let has_more = end_idx < all_prices.len();
-
     Json(ListResponse {
         object: "list".to_string(),
         data: stripe_prices,
         has_more,
         url: "/v1/prices".to_string(),
     })
+    .into_response()
+}
+
+/// GET /v1/prices/:id - Retrieve a price
+///
+/// Its product is expanded with `expand[]=product`.
+pub async fn retrieve_price(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    RawQuery(query): RawQuery,
+) -> impl IntoResponse {
+    let query = match CatalogQuery::parse(query.as_deref(), RETRIEVE_EXPANDABLE) {
+        Ok(query) => query,
+        Err(e) => return e.into_response(),
+    };
+    let catalog = state.catalog();
+    let Some((price, product)) = find_price(&state, &catalog, &id) else {
+        return no_such_price(&id);
+    };
+    (
+        StatusCode::OK,
+        Json(stripe_price(
+            price,
+            product,
+            state.use_sandbox,
+            query.expands("product"),
+        )),
+    )
+        .into_response()
+}
+
+/// Amount and billing terms of a new price, from the params of POST /v1/prices or a
+/// product's `default_price_data`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceData {
+    pub currency: Currency,
+    /// Cents, with a fraction of a cent when set with `unit_amount_decimal`
+    pub unit_amount: Decimal,
+    pub recurring_interval: Option<RecurringInterval>,
+    pub recurring_interval_count: Option<i64>,
+}
+
+impl PriceData {
+    pub fn parse(form: &StripeForm) -> Result<PriceData, FormError> {
+        let currency = form.required_string("currency")?;
+        let currency = Currency::parse(&currency).ok_or_else(|| {
+            form.invalid(
+                "currency",
+                format!(
+                    "Invalid currency: {}. Must be one of {}",
+                    currency,
+                    Currency::valid_values()
+                ),
+            )
+        })?;
+        // Micropayment prices are set in cents with a fraction of a cent
+        let (param, unit_amount) = match (
+            form.integer("unit_amount")?,
+            form.parse::<Decimal>("unit_amount_decimal")?,
+        ) {
+            (Some(_), Some(_)) => {
+                return Err(form.invalid(
+                    "unit_amount_decimal",
+                    "You may only specify one of these parameters: unit_amount, unit_amount_decimal.",
+                ));
+            }
+            (Some(amount), None) => ("unit_amount", Decimal::from(amount)),
+            (None, Some(amount)) => ("unit_amount_decimal", amount),
+            (None, None) => return Err(form.missing("unit_amount")),
+        };
+        if unit_amount < Decimal::ZERO {
+            return Err(form.invalid(param, "This value must be greater than or equal to 0."));
+        }
+        if unit_amount > Decimal::from(i64::MAX) {
+            return Err(form.invalid(param, format!("Invalid amount: {}", unit_amount)));
+        }
+        let recurring_interval = match form.string("recurring[interval]")? {
+            Some(interval) if !interval.is_empty() => {
+                Some(RecurringInterval::parse(&interval).ok_or_else(|| {
+                    form.invalid(
+                        "recurring[interval]",
+                        format!(
+                            "Invalid interval: {}. Must be one of {}",
+                            interval,
+                            RecurringInterval::valid_values()
+                        ),
+                    )
+                })?)
+            }
+            _ => None,
+        };
+        let recurring_interval_count = form.integer("recurring[interval_count]")?;
+        if recurring_interval_count.is_some_and(|count| count < 1) {
+            return Err(form.invalid(
+                "recurring[interval_count]",
+                "This value must be greater than or equal to 1.",
+            ));
+        }
+        Ok(PriceData {
+            currency,
+            unit_amount,
+            recurring_interval,
+            recurring_interval_count: recurring_interval.and(recurring_interval_count),
+        })
+    }
+
+    /// New active price with these terms
+    pub fn into_price(self) -> Price {
+        let pricing_type = match self.recurring_interval {
+            Some(_) => PricingType::Recurring,
+            None => PricingType::OneTime,
+        };
+        let mut price = Price::new(self.currency, pricing_type)
+            .with_exact_amount(self.unit_amount)
+            .with_some_interval(self.recurring_interval)
+            .with_some_interval_count(self.recurring_interval_count);
+        price.id = generate_stripe_id("price");
+        price
+    }
+}
+
+/// Params of POST /v1/prices
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatePriceRequest {
+    pub product: String,
+    pub data: PriceData,
+    pub active: Option<bool>,
+    pub metadata: HashMap<String, String>,
+}
+
+impl CreatePriceRequest {
+    pub fn parse(body: &Bytes) -> Result<CreatePriceRequest, FormError> {
+        let form = StripeForm::decode(body)?;
+        Ok(CreatePriceRequest {
+            product: form.required_string("product")?,
+            data: PriceData::parse(&form)?,
+            active: form.boolean("active")?,
+            metadata: form.map("metadata")?,
+        })
+    }
+}
+
+/// Params of POST /v1/prices/:id. A price's amount and billing terms can't change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdatePriceRequest {
+    pub active: Option<bool>,
+    pub metadata: HashMap<String, String>,
+}
+
+impl UpdatePriceRequest {
+    pub fn parse(body: &Bytes) -> Result<UpdatePriceRequest, FormError> {
+        let form = StripeForm::decode(body)?;
+        Ok(UpdatePriceRequest {
+            active: form.boolean("active")?,
+            metadata: form.map("metadata")?,
+        })
+    }
+}
+
+/// POST /v1/prices - Create a price for a product
+pub async fn create_price(Extension(state): Extension<CatalogState>, body: Bytes) -> Response {
+    let request = match CreatePriceRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+
+    let created = state.edit_catalog(|catalog| {
+        let index = find_product_index(&state, catalog, &request.product)
+            .ok_or_else(|| no_such_product(&request.product, "product"))?;
+        let product = &mut catalog.products[index];
+        let mut price = request.data.into_price();
+        price.active = request.active.unwrap_or(true);
+        apply_metadata(&mut price.metadata, request.metadata);
+        product.prices.push(price.clone());
+        save_product(&state, product, false).map_err(IntoResponse::into_response)?;
+        Ok::<_, Response>((price, product.clone()))
+    });
+
+    match created {
+        Ok((price, product)) => {
+            let stripe_price =
+                StripePrice::from_price_and_product(&price, &product, state.use_sandbox);
+            emit_stripe_event(
+                &state,
+                "price.created",
+                &stripe_price,
+                price.created_at.timestamp(),
+            );
+            (StatusCode::OK, Json(stripe_price)).into_response()
+        }
+        Err(response) => response,
+    }
+}
+
+/// POST /v1/prices/:id - Update a price
+///
+/// Archive a price with `active=false`. `metadata[key]=` removes a key.
+pub async fn update_price(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Response {
+    let request = match UpdatePriceRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+
+    let updated = state.edit_catalog(|catalog| {
+        let Some((product_index, price_index)) =
+            catalog
+                .products
+                .iter()
+                .enumerate()
+                .find_map(|(i, product)| {
+                    product
+                        .prices
+                        .iter()
+                        .position(|price| {
+                            price.id == id || price_id(price, state.use_sandbox) == id
+                        })
+                        .map(|j| (i, j))
+                })
+        else {
+            return Err(no_such_price(&id));
+        };
+        let product = &mut catalog.products[product_index];
+        let before = product.prices[price_index].clone();
+        let price = &mut product.prices[price_index];
+        if let Some(active) = request.active {
+            price.active = active;
+        }
+        apply_metadata(&mut price.metadata, request.metadata);
+        save_product(&state, product, false).map_err(IntoResponse::into_response)?;
+        Ok((before, product.prices[price_index].clone(), product.clone()))
+    });
+
+    match updated {
+        Ok((before, price, product)) => {
+            let after = StripePrice::from_price_and_product(&price, &product, state.use_sandbox);
+            emit_stripe_update(
+                &state,
+                "price.updated",
+                &StripePrice::from_price_and_product(&before, &product, state.use_sandbox),
+                &after,
+                chrono::Utc::now().timestamp(),
+            );
+            (StatusCode::OK, Json(after)).into_response()
+        }
+        Err(response) => response,
+    }
+}
+
+fn no_such_price(id: &str) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(serde_json::json!({
+            "error": {
+                "code": "resource_missing",
+                "message": format!("No such price: '{}'", id),
+                "param": "id",
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(body: &str) -> Result<PriceData, FormError> {
+        PriceData::parse(&StripeForm::decode(body.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn test_price_data_parse() {
+        let data = parse("currency=usd&unit_amount=4900&recurring[interval]=month").unwrap();
+        assert_eq!(data.unit_amount, Decimal::from(4900));
+        assert_eq!(data.recurring_interval, Some(RecurringInterval::Month));
+        let price = data.into_price();
+        assert_eq!(price.unit_amount, Some(4900));
+        assert_eq!(price.pricing_type, PricingType::Recurring);
+
+        // A tenth of a cent per call
+        let price = parse("currency=usd&unit_amount_decimal=0.1")
+            .unwrap()
+            .into_price();
+        assert_eq!(price.unit_amount, None);
+        assert_eq!(price.unit_amount_decimal, Some(Decimal::new(1, 1)));
+
+        assert!(parse("currency=usd").is_err());
+        assert!(parse("currency=usd&unit_amount=100&unit_amount_decimal=100").is_err());
+        assert!(parse("currency=usd&unit_amount_decimal=-0.1").is_err());
+        assert!(parse("currency=usd&unit_amount_decimal=abc").is_err());
+        assert!(parse("currency=usd&unit_amount=-1").is_err());
+    }
 }
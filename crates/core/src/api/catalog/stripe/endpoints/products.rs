@@ -1,158 +1,418 @@
 use std::collections::HashMap;
 
-use axum::{Extension, Json, extract::Path, response::IntoResponse};
-use rand::Rng;
-use serde::Serialize;
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::{Path, RawQuery},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use indexmap::IndexMap;
+use moneymq_types::{Price, Product, round_cents};
+use serde::{Deserialize, Serialize};
 
 use crate::api::catalog::{
-    CatalogState,
-    stripe::types::{ListParams, ListResponse, StripeProduct},
+    Catalog, CatalogState,
+    experiments::{assign_variant, experiment_variants, record_exposure},
+    store::save_product,
+    stripe::{
+        endpoints::{
+            entitlements::{RECEIPT_HEADER, product_entitlements},
+            prices::PriceData,
+        },
+        form::{FormError, StripeForm, StripeQuery},
+        list::CatalogQuery,
+        types::{
+            Expandable, ListResponse, StripeActiveEntitlement, StripePrice, StripeProduct,
+            product_id,
+        },
+        utils::generate_stripe_id,
+    },
+    webhooks::{emit_stripe_event, emit_stripe_update},
 };
 
 /// Get the minimum price for a product (used for sorting)
-fn get_min_price(product: &moneymq_types::Product) -> i64 {
+fn get_min_price(product: &Product) -> i64 {
     product
         .prices
         .iter()
-        .filter_map(|p| p.unit_amount)
+        .filter_map(|p| p.exact_unit_amount())
         .min()
-        .unwrap_or(i64::MAX) // Products without prices go last
+        .map_or(i64::MAX, round_cents) // Products without prices go last
 }
 
-/// Select an experiment variant based on exposure percentages
-/// Returns the selected experiment or None if no experiment should be selected
-fn select_experiment<'a>(
-    experiments: &[&'a moneymq_types::Product],
-) -> Option<&'a moneymq_types::Product> {
-    if experiments.is_empty() {
-        return None;
+/// Expansions supported by GET /v1/products
+const LIST_EXPANDABLE: &[&str] = &["data.default_price"];
+
+/// Expansions supported by GET /v1/products/:id
+const RETRIEVE_EXPANDABLE: &[&str] = &["default_price"];
+
+/// Replace the ID of `product`'s default price with the price object
+fn expand_default_price(
+    product: &mut StripeProduct,
+    price: Option<&Price>,
+    parent: &Product,
+    use_sandbox: bool,
+) {
+    if let Some(price) = price {
+        product.default_price = Some(Expandable::Object(Box::new(
+            StripePrice::from_price_and_product(price, parent, use_sandbox),
+        )));
     }
+}
 
-    let mut rng = rand::rng();
-    let roll: f64 = rng.random(); // 0.0 to 1.0
-
-    // Sort experiments by exposure (deterministic selection)
-    let mut sorted_experiments: Vec<_> = experiments.to_vec();
-    sorted_experiments.sort_by(|a, b| {
-        let exp_a = a.experiment.as_ref().map(|e| e.exposure).unwrap_or(0.0);
-        let exp_b = b.experiment.as_ref().map(|e| e.exposure).unwrap_or(0.0);
-        exp_a
-            .partial_cmp(&exp_b)
-            .unwrap_or(std::cmp::Ordering::Equal)
-    });
+/// Catalog product (not an experiment variant) with the ID clients use for it, or its
+/// catalog ID
+fn find_product<'a>(state: &CatalogState, catalog: &'a Catalog, id: &str) -> Option<&'a Product> {
+    catalog
+        .products
+        .iter()
+        .filter(|p| p.experiment.is_none())
+        .find(|p| p.id == id || product_id(p, state.use_sandbox) == id)
+}
+
+/// Position in the catalog of the product (not an experiment variant) with the ID clients
+/// use for it, or its catalog ID
+pub(crate) fn find_product_index(
+    state: &CatalogState,
+    catalog: &Catalog,
+    id: &str,
+) -> Option<usize> {
+    catalog.products.iter().position(|p| {
+        p.experiment.is_none() && (p.id == id || product_id(p, state.use_sandbox) == id)
+    })
+}
 
-    // Cumulative probability selection
-    let mut cumulative = 0.0;
-    for exp in &sorted_experiments {
-        let exposure = exp.experiment.as_ref().map(|e| e.exposure).unwrap_or(0.0);
-        cumulative += exposure;
-        if roll < cumulative {
-            return Some(exp);
+/// 404 for a product that isn't in the catalog, named by `param`
+pub(crate) fn no_such_product(id: &str, param: &str) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(serde_json::json!({
+            "error": {
+                "code": "resource_missing",
+                "message": format!("No such product: '{}'", id),
+                "param": param,
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// Apply Stripe metadata params: keys are set to their value, or removed when it is empty
+pub(crate) fn apply_metadata(
+    metadata: &mut IndexMap<String, String>,
+    updates: HashMap<String, String>,
+) {
+    for (key, value) in updates {
+        if value.is_empty() {
+            metadata.shift_remove(&key);
+        } else {
+            metadata.insert(key, value);
         }
     }
-
-    // If total exposure < 1.0, there's a chance no experiment is selected
-    // In that case, return the first experiment as fallback
-    sorted_experiments.first().copied()
 }
 
 /// GET /v1/products - List products (sorted by price ASC)
 ///
 /// Experiment variants are materialized: instead of returning all experiment variants,
-/// the server randomly selects one based on exposure percentages and returns it with:
+/// the server assigns one based on exposure percentages and returns it with:
 /// - id: the parent product ID (e.g., "surfnet-lite")
 /// - experiment_id: the selected experiment variant ID (e.g., "surfnet-lite#a")
+///
+/// The assignment is deterministic for a `customer`, `wallet` or `anonymous_id`, and random
+/// without one. Subjects outside every variant's exposure see the parent product.
+///
+/// Products can be filtered by `active`, `type` and `created`, and their default price
+/// expanded with `expand[]=data.default_price`.
 pub async fn list_products(
     Extension(state): Extension<CatalogState>,
-    axum::extract::Query(params): axum::extract::Query<ListParams>,
+    RawQuery(query): RawQuery,
 ) -> impl IntoResponse {
-    let limit = params.limit.unwrap_or(10).min(100) as usize;
-
-    // Separate products into:
-    // 1. Regular products (no experiment config)
-    // 2. Experiment variants (have experiment config and parent_id)
-    let mut regular_products: Vec<&moneymq_types::Product> = Vec::new();
-    let mut experiments_by_parent: HashMap<String, Vec<&moneymq_types::Product>> = HashMap::new();
-
-    for product in state.products.iter() {
-        if product.experiment.is_some() {
-            // This is an experiment variant - group by parent_id
-            if let Some(ref parent_id) = product.parent_id {
-                experiments_by_parent
-                    .entry(parent_id.clone())
-                    .or_default()
-                    .push(product);
-            }
-        } else {
-            // Regular product
-            regular_products.push(product);
-        }
-    }
+    let query = match CatalogQuery::parse(query.as_deref(), LIST_EXPANDABLE) {
+        Ok(query) => query,
+        Err(e) => return e.into_response(),
+    };
+    let active = match query.bool_param("active") {
+        Ok(active) => active,
+        Err(e) => return e.into_response(),
+    };
+    let subject = query
+        .param("customer")
+        .or(query.param("wallet"))
+        .or(query.param("anonymous_id"))
+        .map(str::to_string);
 
-    // Build final product list with materialized experiments
-    let mut final_products: Vec<StripeProduct> = Vec::new();
+    // Experiment variants (have experiment config and parent_id) are materialized in
+    // their parent product
+    let catalog = state.catalog();
+    let regular_products = catalog.products.iter().filter(|p| {
+        p.experiment.is_none()
+            && active.is_none_or(|active| p.active == active)
+            && query
+                .param("type")
+                .is_none_or(|t| p.product_type.as_deref() == Some(t))
+            && query.created.contains(p.created_at.timestamp())
+    });
+
+    // Build final product list with materialized experiments, along with the product
+    // and price their default price is expanded from
+    let mut final_products: Vec<(StripeProduct, &Product, Option<&Price>)> = Vec::new();
+
+    for product in regular_products {
+        let variants = experiment_variants(&catalog.products, &product.id);
+        if variants.is_empty() {
+            // No experiments for this product
+            final_products.push((
+                StripeProduct::from_product(product, state.use_sandbox),
+                product,
+                product.prices.first(),
+            ));
+            continue;
+        }
 
-    for product in &regular_products {
-        // Check if this product has experiment variants
-        if let Some(experiments) = experiments_by_parent.get(&product.id) {
-            // Select an experiment variant
-            if let Some(selected_experiment) = select_experiment(experiments) {
-                // Return materialized product with experiment_id
-                final_products.push(StripeProduct::from_product_with_experiment(
+        // Assign an experiment variant to the subject (a random one-off subject without one)
+        let assignee = subject
+            .clone()
+            .unwrap_or_else(|| generate_stripe_id("anon"));
+        let assigned = assign_variant(&variants, &product.id, &assignee);
+        let variant_id = assigned.map_or(&product.id, |variant| &variant.id);
+        record_exposure(&state, &product.id, variant_id, subject.as_deref());
+        match assigned {
+            // Return materialized product with experiment_id
+            Some(selected_experiment) => final_products.push((
+                StripeProduct::from_product_with_experiment(
                     product,
                     selected_experiment,
                     state.use_sandbox,
-                ));
-            } else {
-                // No experiment selected, return regular product
-                final_products.push(StripeProduct::from_product(product, state.use_sandbox));
-            }
-        } else {
-            // No experiments for this product
-            final_products.push(StripeProduct::from_product(product, state.use_sandbox));
+                ),
+                product,
+                selected_experiment
+                    .prices
+                    .first()
+                    .or_else(|| product.prices.first()),
+            )),
+            // Control group, return regular product
+            None => final_products.push((
+                StripeProduct::from_product(product, state.use_sandbox),
+                product,
+                product.prices.first(),
+            )),
         }
     }
 
     // Sort by minimum price (ascending)
-    final_products.sort_by_key(|p| {
-        // Find original product to get price
-        state
-            .products
-            .iter()
-            .find(|prod| {
-                let external_id = if state.use_sandbox {
-                    prod.sandboxes.get("default")
-                } else {
-                    prod.deployed_id.as_ref()
-                };
-                external_id.map(|id| id == &p.id).unwrap_or(false) || prod.id == p.id
-            })
-            .map(get_min_price)
-            .unwrap_or(i64::MAX)
-    });
-
-    // Find starting position
-    let start_idx = if let Some(starting_after) = params.starting_after {
-        final_products
-            .iter()
-            .position(|p| p.id == starting_after)
-            .map(|idx| idx + 1)
-            .unwrap_or(0)
-    } else {
-        0
-    };
-
-    let end_idx = (start_idx + limit).min(final_products.len());
-    let products_slice = final_products[start_idx..end_idx].to_vec();
+    final_products.sort_by_key(|(_, product, _)| get_min_price(product));
 
-    let has_more = end_idx < final_products.len();
+    let (page, has_more) =
+        match query.paginate(final_products, "product", |(p, _, _)| p.id.as_str()) {
+            Ok(page) => page,
+            Err(e) => return e.into_response(),
+        };
+    let expand = query.expands("data.default_price");
+    let data = page
+        .into_iter()
+        .map(|(mut stripe_product, product, price)| {
+            if expand {
+                expand_default_price(&mut stripe_product, price, product, state.use_sandbox);
+            }
+            stripe_product
+        })
+        .collect();
 
     Json(ListResponse {
         object: "list".to_string(),
-        data: products_slice,
+        data,
         has_more,
         url: "/v1/products".to_string(),
     })
+    .into_response()
+}
+
+/// GET /v1/products/:id - Retrieve a product
+///
+/// Its default price is expanded with `expand[]=default_price`.
+pub async fn retrieve_product(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    RawQuery(query): RawQuery,
+) -> impl IntoResponse {
+    let query = match CatalogQuery::parse(query.as_deref(), RETRIEVE_EXPANDABLE) {
+        Ok(query) => query,
+        Err(e) => return e.into_response(),
+    };
+    let catalog = state.catalog();
+    let Some(product) = find_product(&state, &catalog, &id) else {
+        return no_such_product(&id, "id");
+    };
+
+    let mut stripe_product = StripeProduct::from_product(product, state.use_sandbox);
+    if query.expands("default_price") {
+        expand_default_price(
+            &mut stripe_product,
+            product.prices.first(),
+            product,
+            state.use_sandbox,
+        );
+    }
+    (StatusCode::OK, Json(stripe_product)).into_response()
+}
+
+/// Params of POST /v1/products and POST /v1/products/:id
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductRequest {
+    /// ID of the new product (create only, generated when not set)
+    pub id: Option<String>,
+    pub name: Option<String>,
+    /// Description, empty when it is being unset
+    pub description: Option<String>,
+    pub active: Option<bool>,
+    pub unit_label: Option<String>,
+    /// Product type (e.g., "service", "good")
+    pub product_type: Option<String>,
+    pub metadata: HashMap<String, String>,
+    /// Price created along with the product as its default price (create only)
+    pub default_price_data: Option<PriceData>,
+}
+
+impl ProductRequest {
+    pub fn parse(body: &Bytes) -> Result<ProductRequest, FormError> {
+        let form = StripeForm::decode(body)?;
+        Ok(ProductRequest {
+            id: form.string("id")?.filter(|id| !id.is_empty()),
+            name: form.string("name")?,
+            description: form.string("description")?,
+            active: form.boolean("active")?,
+            unit_label: form.string("unit_label")?,
+            product_type: form.string("type")?,
+            metadata: form.map("metadata")?,
+            default_price_data: form
+                .object("default_price_data")?
+                .map(|data| PriceData::parse(&data))
+                .transpose()?,
+        })
+    }
+
+    /// Apply the params to `product`, empty strings unsetting optional fields
+    fn apply(self, product: &mut Product) {
+        if let Some(name) = self.name.filter(|name| !name.is_empty()) {
+            product.name = Some(name);
+        }
+        if let Some(description) = self.description {
+            product.description = Some(description).filter(|d| !d.is_empty());
+        }
+        if let Some(active) = self.active {
+            product.active = active;
+        }
+        if let Some(unit_label) = self.unit_label {
+            product.unit_label = Some(unit_label).filter(|l| !l.is_empty());
+        }
+        if let Some(product_type) = self.product_type.filter(|t| !t.is_empty()) {
+            product.product_type = Some(product_type);
+        }
+        apply_metadata(&mut product.metadata, self.metadata);
+    }
+}
+
+/// POST /v1/products - Create a product
+///
+/// A default price is created along with it from `default_price_data[currency]`,
+/// `default_price_data[unit_amount]` and `default_price_data[recurring][interval]`.
+pub async fn create_product(Extension(state): Extension<CatalogState>, body: Bytes) -> Response {
+    let mut request = match ProductRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    if request.name.as_deref().is_none_or(str::is_empty) {
+        return FormError::Missing("name".to_string()).into_response();
+    }
+
+    let created = state.edit_catalog(|catalog| {
+        let id = request
+            .id
+            .take()
+            .unwrap_or_else(|| generate_stripe_id("prod"));
+        if catalog
+            .products
+            .iter()
+            .any(|p| p.id == id || product_id(p, state.use_sandbox) == id)
+        {
+            return Err(FormError::Invalid {
+                param: "id".to_string(),
+                message: format!("Product already exists: '{}'", id),
+            }
+            .into_response());
+        }
+
+        let mut product = Product::new().with_some_product_type(Some("service".to_string()));
+        product.id = id;
+        if let Some(data) = request.default_price_data.take() {
+            product.prices.push(data.into_price());
+        }
+        request.apply(&mut product);
+        save_product(&state, &product, true).map_err(IntoResponse::into_response)?;
+        catalog.products.push(product.clone());
+        Ok(product)
+    });
+
+    match created {
+        Ok(product) => {
+            let created_at = product.created_at.timestamp();
+            let stripe_product = StripeProduct::from_product(&product, state.use_sandbox);
+            emit_stripe_event(&state, "product.created", &stripe_product, created_at);
+            for price in &product.prices {
+                emit_stripe_event(
+                    &state,
+                    "price.created",
+                    &StripePrice::from_price_and_product(price, &product, state.use_sandbox),
+                    created_at,
+                );
+            }
+            (StatusCode::OK, Json(stripe_product)).into_response()
+        }
+        Err(response) => response,
+    }
+}
+
+/// POST /v1/products/:id - Update a product
+///
+/// Archive a product with `active=false`. `description=` and `unit_label=` unset those
+/// fields, and `metadata[key]=` removes a key.
+pub async fn update_product(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Response {
+    let request = match ProductRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+
+    let updated = state.edit_catalog(|catalog| {
+        let index =
+            find_product_index(&state, catalog, &id).ok_or_else(|| no_such_product(&id, "id"))?;
+        let product = &mut catalog.products[index];
+        let before = StripeProduct::from_product(product, state.use_sandbox);
+        request.apply(product);
+        product.updated_at = Some(chrono::Utc::now());
+        save_product(&state, product, false).map_err(IntoResponse::into_response)?;
+        Ok((before, product.clone()))
+    });
+
+    match updated {
+        Ok((before, product)) => {
+            let after = StripeProduct::from_product(&product, state.use_sandbox);
+            emit_stripe_update(
+                &state,
+                "product.updated",
+                &before,
+                &after,
+                chrono::Utc::now().timestamp(),
+            );
+            (StatusCode::OK, Json(after)).into_response()
+        }
+        Err(response) => response,
+    }
 }
 
 /// Response for product access endpoint
@@ -162,29 +422,58 @@ pub struct ProductAccessResponse {
     pub product_id: String,
     pub access_granted: bool,
     pub message: String,
+    /// Entitlements granting access without a payment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entitlements: Option<Vec<StripeActiveEntitlement>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProductAccessParams {
+    /// Customer whose entitlements may grant access, proven by a receipt of one of its
+    /// payments in the `X-Payment-Receipt` header
+    pub customer: Option<String>,
 }
 
 /// GET /v1/products/{id}/access - Access a product (x402 gated)
 ///
 /// This endpoint is gated by x402 payment. The client must include an X-Payment header
-/// with a valid payment. If no payment is provided, returns 402 with payment requirements.
+/// with a valid payment, echoing the requirements it accepted with their signed quote. If no
+/// payment is provided, returns 402 with payment requirements.
+/// A `customer` entitled to every feature of the product is granted access without paying
+/// when the request carries a receipt of one of its payments as `X-Payment-Receipt`.
 ///
 /// After successful payment, returns access confirmation.
 pub async fn get_product_access(
     Extension(state): Extension<CatalogState>,
     Path(product_id): Path<String>,
+    headers: HeaderMap,
+    StripeQuery(params): StripeQuery<ProductAccessParams>,
 ) -> impl IntoResponse {
     // Find the product
-    let product = state.products.iter().find(|p| p.id == product_id);
+    let catalog = state.catalog();
+    let product = catalog.products.iter().find(|p| p.id == product_id);
 
     match product {
         Some(product) => {
             let product_name = product.name.clone().unwrap_or_else(|| product_id.clone());
+            // The middleware only lets unpaid requests through for entitled customers
+            let receipt = headers
+                .get(RECEIPT_HEADER)
+                .and_then(|value| value.to_str().ok());
+            let entitlements = params
+                .customer
+                .as_deref()
+                .and_then(|customer| product_entitlements(&state, customer, receipt, &product_id));
+            let message = match entitlements {
+                Some(_) => format!("Access granted to {} by entitlements", product_name),
+                None => format!("Access granted to {}", product_name),
+            };
             Json(ProductAccessResponse {
                 object: "product_access".to_string(),
                 product_id: product_id.clone(),
                 access_granted: true,
-                message: format!("Access granted to {}", product_name),
+                message,
+                entitlements,
             })
         }
         None => Json(ProductAccessResponse {
@@ -192,6 +481,7 @@ pub async fn get_product_access(
             product_id: product_id.clone(),
             access_granted: false,
             message: "Product not found".to_string(),
+            entitlements: None,
         }),
     }
 }
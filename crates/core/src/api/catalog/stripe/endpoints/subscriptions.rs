@@ -1,96 +1,1544 @@
-use axum::{Extension, Json, body::Bytes, http::StatusCode, response::IntoResponse};
+//! Subscriptions to recurring catalog prices
+//!
+//! The first period of a subscription is paid through x402 when it is created (or skipped
+//! for a trial). At the end of each period the renewal task advances the period and
+//! invoices it, with the usage of the period that ended: payments are signed by the
+//! customer, so the subscription stays `past_due` until the invoice is paid, then becomes
+//! `active` again.
 
-use crate::api::catalog::{
-    CatalogState,
-    stripe::{
-        types::{StripeSubscription, SubscriptionItemData, SubscriptionItems, SubscriptionPrice},
-        utils::generate_stripe_id,
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Months, Utc};
+use moneymq_types::{CouponDuration, Price, Product, iac::RecurringInterval};
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::{
+    api::{
+        catalog::{
+            Catalog, CatalogState,
+            stripe::{
+                endpoints::{
+                    coupons::{record_redemption, resolve_discount},
+                    invoices::{
+                        invoice_subscription_period, record_first_period_invoice, void_open_invoice,
+                    },
+                    tax::{check_tax_location, tax_location},
+                    test_clocks::{clock_time, current_time},
+                },
+                form::{FormError, StripeForm, StripeQuery},
+                types::{
+                    AppliedDiscount, ListResponse, StripeSubscription, SubscriptionDiscount,
+                    SubscriptionItemData, SubscriptionItems, SubscriptionPrice,
+                },
+                utils::generate_stripe_id,
+            },
+            webhooks::{emit_stripe_event, emit_stripe_update},
+        },
+        payment::db::{
+            DbManager, SubscriptionModel, invoice,
+            subscription::{
+                NewSubscription, STATUS_ACTIVE, STATUS_CANCELED, STATUS_PAST_DUE, STATUS_PAUSED,
+                STATUS_TRIALING, SubscriptionItem, UpdateSubscription,
+            },
+        },
     },
+    events::{CloudEvent, SubscriptionEventData},
+    taxes::TaxLocation,
 };
 
-#[allow(dead_code)]
+/// How often subscriptions are checked for renewal
+pub const DEFAULT_RENEWAL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Metadata key linking a renewal payment intent to its subscription
+pub const SUBSCRIPTION_METADATA_KEY: &str = "subscription";
+
+/// One `items[N]` entry of a subscription request
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SubscriptionItemRequest {
+    /// Existing item to modify (updates only)
+    pub id: Option<String>,
+    pub price: Option<String>,
+    pub quantity: Option<i64>,
+    /// Remove the item (updates only)
+    pub deleted: bool,
+}
+
+/// Form-encoded subscription create/update request
+#[derive(Debug, Default)]
 pub struct SubscriptionRequest {
     pub customer: Option<String>,
-    pub price_ids: Vec<String>,
+    pub items: Vec<SubscriptionItemRequest>,
+    /// Top-level quantity, applied to single-item subscriptions
+    pub quantity: Option<i64>,
+    pub trial_period_days: Option<i64>,
+    pub cancel_at_period_end: Option<bool>,
+    pub metadata: HashMap<String, String>,
+    /// Sandbox test clock to attach to (defaults to the customer's)
+    pub test_clock: Option<String>,
+    /// Coupon or promotion code discounting the subscription
+    pub discount: AppliedDiscount,
+    /// Country of the customer's address, to charge automatic tax for
+    pub tax_country: Option<String>,
+    pub tax_state: Option<String>,
 }
 
 impl SubscriptionRequest {
-    pub const DEFAULT_CUSTOMER: &str = "unknown_customer";
-    pub fn parse(body: &Bytes) -> SubscriptionRequest {
-        let body_str = String::from_utf8_lossy(body);
-
-        // Extract customer field
-        let customer = body_str
-            .split('&')
-            .find(|part| part.starts_with("customer="))
-            .and_then(|part| part.strip_prefix("customer="))
-            .map(|c| c.to_string());
-
-        // Extract price IDs from items[N][price] fields
-        let price_ids: Vec<String> = body_str
-            .split('&')
-            .filter(|part| part.contains("[price]="))
-            .filter_map(|part| {
-                part.split('=')
-                    .nth(1)
-                    .map(|id| urlencoding::decode(id).unwrap_or_default().to_string())
+    pub fn parse(body: &Bytes) -> Result<SubscriptionRequest, FormError> {
+        let form = StripeForm::decode(body)?;
+        let items = form
+            .list("items")?
+            .iter()
+            .map(|item| {
+                Ok(SubscriptionItemRequest {
+                    id: item.string("id")?,
+                    price: item.string("price")?,
+                    quantity: item.integer("quantity")?,
+                    deleted: item.boolean("deleted")?.unwrap_or(false),
+                })
             })
-            .collect();
+            .collect::<Result<Vec<_>, FormError>>()?;
+
+        Ok(SubscriptionRequest {
+            customer: form.string("customer")?,
+            items,
+            quantity: form.integer("quantity")?,
+            trial_period_days: form.integer("trial_period_days")?,
+            cancel_at_period_end: form.boolean("cancel_at_period_end")?,
+            metadata: form.map("metadata")?,
+            test_clock: form.string("test_clock")?,
+            discount: AppliedDiscount::requested(&form)?,
+            tax_country: form.string("customer_details[address][country]")?,
+            tax_state: form.string("customer_details[address][state]")?,
+        })
+    }
+
+    /// Location automatic tax is charged for, if an address was given
+    pub fn tax_location(&self) -> Option<TaxLocation> {
+        tax_location(self.tax_country.as_deref(), self.tax_state.as_deref())
+    }
+
+    /// Price IDs of the requested items
+    pub fn price_ids(&self) -> Vec<String> {
+        self.items
+            .iter()
+            .filter_map(|item| item.price.clone())
+            .collect()
+    }
+
+    /// Whether the subscription starts with a free trial
+    pub fn has_trial(&self) -> bool {
+        self.trial_period_days.is_some_and(|days| days > 0)
+    }
+}
+
+/// Find an active catalog price by the ID clients use for it (deployed or sandbox ID),
+/// falling back to the catalog ID
+pub(crate) fn find_catalog_price<'a>(
+    state: &CatalogState,
+    catalog: &'a Catalog,
+    price_id: &str,
+) -> Option<(&'a Product, &'a Price)> {
+    catalog.products.iter().find_map(|product| {
+        product.prices.iter().find_map(|price| {
+            let external_id = if state.use_sandbox {
+                price.sandboxes.get("default")
+            } else {
+                price.deployed_id.as_ref()
+            };
+            (price.active && (external_id == Some(&price_id.to_string()) || price.id == price_id))
+                .then_some((product, price))
+        })
+    })
+}
+
+/// End of a billing period starting at `start` (Unix seconds)
+pub fn advance_period(start: i64, interval: RecurringInterval, interval_count: i32) -> i64 {
+    let count = interval_count.max(1) as u32;
+    let start_at = DateTime::<Utc>::from_timestamp(start, 0).unwrap_or_else(Utc::now);
+    let end_at = match interval {
+        RecurringInterval::Day => Some(start_at + chrono::Duration::days(count as i64)),
+        RecurringInterval::Week => Some(start_at + chrono::Duration::weeks(count as i64)),
+        RecurringInterval::Month => start_at.checked_add_months(Months::new(count)),
+        RecurringInterval::Year => start_at.checked_add_months(Months::new(count * 12)),
+    };
+    end_at.map(|end| end.timestamp()).unwrap_or(start)
+}
+
+/// Billing terms shared by every item of a subscription
+#[derive(Debug, Clone, PartialEq)]
+struct BillingTerms {
+    currency: String,
+    interval: RecurringInterval,
+    interval_count: i32,
+}
+
+/// Check that every price exists, is recurring and bills on the same terms
+fn resolve_billing_terms(
+    state: &CatalogState,
+    items: &[SubscriptionItem],
+) -> Result<BillingTerms, String> {
+    let catalog = state.catalog();
+    let mut terms: Option<BillingTerms> = None;
+    for item in items {
+        let (_, price) = find_catalog_price(state, &catalog, &item.price)
+            .ok_or_else(|| format!("No such price: '{}'", item.price))?;
+        let interval = price
+            .recurring_interval
+            .ok_or_else(|| format!("Price '{}' is not a recurring price", item.price))?;
+        if price
+            .amount_for(item.quantity, price.currency.as_str())
+            .is_none()
+        {
+            return Err(format!(
+                "Price '{}' can't be charged for a quantity of {}",
+                item.price, item.quantity
+            ));
+        }
+        let item_terms = BillingTerms {
+            currency: price.currency.as_str().to_string(),
+            interval,
+            interval_count: price.recurring_interval_count.unwrap_or(1).max(1) as i32,
+        };
+        match &terms {
+            Some(terms) if *terms != item_terms => {
+                return Err(
+                    "All prices of a subscription must have the same currency and billing interval"
+                        .to_string(),
+                );
+            }
+            Some(_) => {}
+            None => terms = Some(item_terms),
+        }
+    }
+    terms.ok_or_else(|| "A subscription needs at least one item".to_string())
+}
 
-        SubscriptionRequest {
-            customer,
-            price_ids,
+impl From<&SubscriptionModel> for StripeSubscription {
+    fn from(model: &SubscriptionModel) -> Self {
+        StripeSubscription {
+            id: model.subscription_id.clone(),
+            object: "subscription".to_string(),
+            customer: model.customer.clone(),
+            status: model.status.clone(),
+            created: model.created_at / 1000,
+            current_period_start: model.current_period_start,
+            current_period_end: model.current_period_end,
+            items: SubscriptionItems {
+                object: "list".to_string(),
+                data: model
+                    .items()
+                    .into_iter()
+                    .map(|item| SubscriptionItemData {
+                        id: item.id,
+                        object: "subscription_item".to_string(),
+                        price: SubscriptionPrice {
+                            id: item.price,
+                            object: "price".to_string(),
+                        },
+                        quantity: item.quantity,
+                    })
+                    .collect(),
+            },
+            latest_invoice: model.latest_invoice.clone(),
+            latest_payment_intent: model.latest_payment_intent.clone(),
+            cancel_at_period_end: model.cancel_at_period_end,
+            canceled_at: model.canceled_at,
+            trial_end: model.trial_end,
+            paused_at: model.paused_at,
+            metadata: model.metadata(),
+            test_clock: model.test_clock.clone(),
+            discount: model.coupon.clone().map(|coupon| SubscriptionDiscount {
+                coupon,
+                promotion_code: model.promotion_code.clone(),
+                end: model.discount_end,
+            }),
+        }
+    }
+}
+
+// ==================== Lifecycle ====================
+
+/// Lifecycle event payload for a subscription
+fn subscription_event_data(subscription: &SubscriptionModel) -> SubscriptionEventData {
+    SubscriptionEventData {
+        subscription_id: subscription.subscription_id.clone(),
+        customer: subscription.customer.clone(),
+        status: subscription.status.clone(),
+        current_period_start: subscription.current_period_start,
+        current_period_end: subscription.current_period_end,
+        payment_intent_id: subscription.latest_payment_intent.clone(),
+        test_clock: subscription.test_clock.clone(),
+    }
+}
+
+/// Void the unpaid invoice of a subscription's previous period, if any
+fn void_open_charge(
+    state: &CatalogState,
+    db: &DbManager,
+    subscription: &SubscriptionModel,
+    at: i64,
+) {
+    let Some(invoice_id) = &subscription.latest_invoice else {
+        return;
+    };
+    match db.find_invoice(invoice_id, &state.payment_stack_id, state.use_sandbox) {
+        Ok(Some(invoice)) if invoice.status == invoice::STATUS_OPEN => {
+            if let Err(e) = void_open_invoice(state, db, &invoice, at) {
+                error!("Failed to void invoice {}: {}", invoice_id, e);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => error!("Failed to find invoice {}: {}", invoice_id, e),
+    }
+}
+
+/// Start a new billing period at `period_start` and invoice it.
+///
+/// The subscription is `past_due` until the invoice is paid, unless nothing is due.
+fn start_charged_period(
+    state: &CatalogState,
+    db: &DbManager,
+    subscription: &SubscriptionModel,
+    period_start: i64,
+    mut changes: UpdateSubscription,
+) -> Result<Option<SubscriptionModel>, String> {
+    let interval = RecurringInterval::parse(&subscription.recurring_interval)
+        .ok_or_else(|| format!("Invalid interval '{}'", subscription.recurring_interval))?;
+    let period_end = advance_period(period_start, interval, subscription.interval_count);
+
+    void_open_charge(state, db, subscription, period_start);
+    let invoice = invoice_subscription_period(
+        state,
+        db,
+        subscription,
+        invoice::BILLING_REASON_SUBSCRIPTION_CYCLE,
+        period_start,
+        period_end,
+        period_start,
+    )?;
+
+    changes.status = Some(if invoice.status == invoice::STATUS_PAID {
+        STATUS_ACTIVE.to_string()
+    } else {
+        STATUS_PAST_DUE.to_string()
+    });
+    changes.current_period_start = Some(period_start);
+    changes.current_period_end = Some(period_end);
+    changes.latest_payment_intent = Some(invoice.payment_intent.clone());
+    changes.latest_invoice = Some(Some(invoice.invoice_id.clone()));
+    let renewed = db
+        .update_subscription(&subscription.subscription_id, &subscription.status, changes)
+        .map_err(|e| e.to_string())?;
+    if let Some(renewed) = &renewed {
+        emit_subscription_updated(state, subscription, renewed, period_start);
+    } else {
+        // The subscription changed concurrently: don't leave its invoice payable
+        void_open_invoice(state, db, &invoice, period_start)?;
+    }
+    Ok(renewed)
+}
+
+/// Cancel a subscription at `at` (Unix seconds), voiding the unpaid charge of its period
+pub(crate) fn end_subscription(
+    state: &CatalogState,
+    db: &DbManager,
+    subscription: &SubscriptionModel,
+    at: i64,
+) -> Result<Option<SubscriptionModel>, String> {
+    void_open_charge(state, db, subscription, at);
+    let changes = UpdateSubscription {
+        status: Some(STATUS_CANCELED.to_string()),
+        canceled_at: Some(Some(at)),
+        ..Default::default()
+    };
+    let canceled = db
+        .update_subscription(&subscription.subscription_id, &subscription.status, changes)
+        .map_err(|e| e.to_string())?;
+    if let Some(canceled) = &canceled {
+        state.record_event(
+            CloudEvent::SubscriptionCanceled(subscription_event_data(canceled)),
+            at,
+        );
+        emit_stripe_event(
+            state,
+            "customer.subscription.deleted",
+            &StripeSubscription::from(canceled),
+            at,
+        );
+    }
+    Ok(canceled)
+}
+
+/// Emit `customer.subscription.updated`, with the attributes changed from `before`
+fn emit_subscription_updated(
+    state: &CatalogState,
+    before: &SubscriptionModel,
+    after: &SubscriptionModel,
+    at: i64,
+) {
+    emit_stripe_update(
+        state,
+        "customer.subscription.updated",
+        &StripeSubscription::from(before),
+        &StripeSubscription::from(after),
+        at,
+    );
+}
+
+/// Renew or end every subscription whose period is over at `now` (Unix seconds).
+///
+/// Only subscriptions on `test_clock` are processed, or those without a test clock when it
+/// is None. Returns the number of subscriptions due.
+pub fn run_renewal_round(state: &CatalogState, now: i64, test_clock: Option<&str>) -> usize {
+    let Some(db) = state.payment_db.as_ref() else {
+        return 0;
+    };
+    let due = match db.list_due_subscriptions(
+        now,
+        test_clock,
+        &state.payment_stack_id,
+        state.use_sandbox,
+    ) {
+        Ok(due) => due,
+        Err(e) => {
+            error!("Failed to list subscriptions due for renewal: {}", e);
+            return 0;
+        }
+    };
+    let due_count = due.len();
+
+    for subscription in due {
+        if subscription.cancel_at_period_end {
+            match end_subscription(state, db, &subscription, subscription.current_period_end) {
+                Ok(Some(canceled)) => info!(
+                    "Subscription {} canceled at period end",
+                    canceled.subscription_id
+                ),
+                Ok(None) => {}
+                Err(e) => error!(
+                    "Failed to cancel subscription {}: {}",
+                    subscription.subscription_id, e
+                ),
+            }
+            continue;
+        }
+
+        // After downtime, skip to the period containing `now` and charge it once
+        let Some(interval) = RecurringInterval::parse(&subscription.recurring_interval) else {
+            warn!(
+                "Subscription {} has an invalid interval '{}'",
+                subscription.subscription_id, subscription.recurring_interval
+            );
+            continue;
+        };
+        let mut period_start = subscription.current_period_end;
+        loop {
+            let period_end = advance_period(period_start, interval, subscription.interval_count);
+            if period_end > now || period_end <= period_start {
+                break;
+            }
+            period_start = period_end;
+        }
+
+        match start_charged_period(
+            state,
+            db,
+            &subscription,
+            period_start,
+            UpdateSubscription::default(),
+        ) {
+            Ok(Some(renewed)) => {
+                info!(
+                    "Subscription {} renewed until {}, awaiting payment {:?}",
+                    renewed.subscription_id,
+                    renewed.current_period_end,
+                    renewed.latest_payment_intent
+                );
+                let data = subscription_event_data(&renewed);
+                let event = if subscription.status == STATUS_TRIALING {
+                    CloudEvent::SubscriptionTrialEnded(data)
+                } else {
+                    CloudEvent::SubscriptionRenewed(data)
+                };
+                state.record_event(event, renewed.current_period_start);
+            }
+            Ok(None) => {}
+            Err(e) => error!(
+                "Failed to renew subscription {}: {}",
+                subscription.subscription_id, e
+            ),
+        }
+    }
+    due_count
+}
+
+/// Periodically renew subscriptions.
+///
+/// Returns `None` when no database is attached to the catalog.
+pub fn spawn_renewal_task(state: CatalogState) -> Option<JoinHandle<()>> {
+    state.payment_db.as_ref()?;
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(DEFAULT_RENEWAL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            run_renewal_round(&state, Utc::now().timestamp(), None);
+        }
+    }))
+}
+
+/// Mark the subscription charged by `payment_intent_id` as paid
+pub(crate) fn record_subscription_payment(state: &CatalogState, payment_intent_id: &str) {
+    let Some(db) = state.payment_db.as_ref() else {
+        return;
+    };
+    let subscription = match db.find_subscription_by_payment_intent(payment_intent_id) {
+        Ok(Some(subscription)) => subscription,
+        Ok(None) => return,
+        Err(e) => {
+            error!(
+                "Failed to look up subscription for {}: {}",
+                payment_intent_id, e
+            );
+            return;
+        }
+    };
+    if subscription.status != STATUS_PAST_DUE {
+        return;
+    }
+    let changes = UpdateSubscription {
+        status: Some(STATUS_ACTIVE.to_string()),
+        ..Default::default()
+    };
+    match db.update_subscription(&subscription.subscription_id, STATUS_PAST_DUE, changes) {
+        Ok(Some(paid)) => {
+            info!(
+                "Subscription {} paid by {}",
+                subscription.subscription_id, payment_intent_id
+            );
+            let at = clock_time(state, paid.test_clock.as_deref());
+            emit_subscription_updated(state, &subscription, &paid, at);
+        }
+        Ok(None) => {}
+        Err(e) => error!(
+            "Failed to activate subscription {}: {}",
+            subscription.subscription_id, e
+        ),
+    }
+}
+
+// ==================== Handlers ====================
+
+fn subscription_error(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(serde_json::json!({
+            "error": {
+                "code": code,
+                "message": message.into(),
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+fn payment_db(state: &CatalogState) -> Result<&Arc<DbManager>, Response> {
+    state.payment_db.as_ref().ok_or_else(|| {
+        subscription_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "subscriptions_not_configured",
+            "Subscriptions require a database",
+        )
+    })
+}
+
+fn find_subscription_or_404(
+    state: &CatalogState,
+    db: &DbManager,
+    id: &str,
+) -> Result<SubscriptionModel, Response> {
+    match db.find_subscription(id, &state.payment_stack_id, state.use_sandbox) {
+        Ok(Some(subscription)) => Ok(subscription),
+        Ok(None) => Err(subscription_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such subscription: '{}'", id),
+        )),
+        Err(e) => {
+            error!("Failed to find subscription {}: {}", id, e);
+            Err(subscription_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "api_error",
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+/// Respond with the updated subscription, or a conflict if it changed concurrently
+fn updated_response(result: Result<Option<SubscriptionModel>, String>) -> Response {
+    match result {
+        Ok(Some(subscription)) => (
+            StatusCode::OK,
+            Json(StripeSubscription::from(&subscription)),
+        )
+            .into_response(),
+        Ok(None) => subscription_error(
+            StatusCode::CONFLICT,
+            "lock_timeout",
+            "The subscription was modified by another request, please retry",
+        ),
+        Err(e) => {
+            error!("Failed to update subscription: {}", e);
+            subscription_error(StatusCode::INTERNAL_SERVER_ERROR, "api_error", e)
+        }
+    }
+}
+
+fn encode_metadata(metadata: &HashMap<String, String>) -> Option<String> {
+    (!metadata.is_empty())
+        .then(|| serde_json::to_string(metadata).ok())
+        .flatten()
+}
+
+/// Test clock a new subscription is attached to, and its current time
+fn resolve_test_clock(
+    state: &CatalogState,
+    customer: &str,
+    requested: Option<String>,
+) -> Result<(i64, Option<String>), String> {
+    let (now, customer_clock) = current_time(state, Some(customer));
+    match (requested, customer_clock) {
+        (None, clock) => Ok((now, clock)),
+        (Some(requested), Some(clock)) if requested != clock => Err(format!(
+            "Customer '{}' is attached to test clock '{}'",
+            customer, clock
+        )),
+        (Some(requested), _) => {
+            let frozen_time = state
+                .test_clocks
+                .lock()
+                .unwrap()
+                .get(&requested)
+                .filter(|_| state.use_sandbox)
+                .map(|clock| clock.frozen_time)
+                .ok_or_else(|| format!("No such test clock: '{}'", requested))?;
+            Ok((frozen_time, Some(requested)))
+        }
+    }
+}
+
+/// Record the paid invoice of a subscription's first period as its latest invoice
+fn record_first_invoice(
+    state: &CatalogState,
+    db: &DbManager,
+    subscription: SubscriptionModel,
+) -> SubscriptionModel {
+    let invoice = match record_first_period_invoice(
+        state,
+        db,
+        &subscription,
+        invoice::BILLING_REASON_SUBSCRIPTION_CREATE,
+    ) {
+        Ok(invoice) => invoice,
+        Err(e) => {
+            error!(
+                "Failed to invoice subscription {}: {}",
+                subscription.subscription_id, e
+            );
+            return subscription;
+        }
+    };
+    let changes = UpdateSubscription {
+        latest_invoice: Some(Some(invoice.invoice_id)),
+        ..Default::default()
+    };
+    match db.update_subscription(&subscription.subscription_id, &subscription.status, changes) {
+        Ok(Some(updated)) => updated,
+        Ok(None) => subscription,
+        Err(e) => {
+            error!(
+                "Failed to link invoice to subscription {}: {}",
+                subscription.subscription_id, e
+            );
+            subscription
         }
     }
 }
 
 /// POST /v1/subscriptions - Create a subscription
+///
+/// The first period is paid through x402 before this handler runs, unless the
+/// subscription starts with a trial.
 pub async fn create_subscription(
-    Extension(_state): Extension<CatalogState>,
+    Extension(state): Extension<CatalogState>,
     body: Bytes,
 ) -> impl IntoResponse {
-    let SubscriptionRequest {
-        customer,
-        price_ids,
-    } = SubscriptionRequest::parse(&body);
-
-    let customer = customer.unwrap_or_else(|| SubscriptionRequest::DEFAULT_CUSTOMER.to_string());
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let request = match SubscriptionRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
 
-    // Generate a mock subscription ID
-    let sub_id = generate_stripe_id("sub");
-    let created = chrono::Utc::now().timestamp();
-    let current_period_end = created + 30 * 24 * 60 * 60; // 30 days from now
+    let Some(customer) = request.customer.clone() else {
+        return FormError::Missing("customer".to_string()).into_response();
+    };
 
-    // Mock subscription items
-    let items_data: Vec<SubscriptionItemData> = price_ids
+    let single_item = request.items.len() == 1;
+    let items = request
+        .items
         .iter()
-        .map(|price_id| {
-            let item_id = generate_stripe_id("si");
-            SubscriptionItemData {
-                id: item_id,
-                object: "subscription_item".to_string(),
-                price: SubscriptionPrice {
-                    id: price_id.clone(),
-                    object: "price".to_string(),
-                },
-            }
+        .filter_map(|item| {
+            Some(SubscriptionItem {
+                id: generate_stripe_id("si"),
+                price: item.price.clone()?,
+                quantity: item
+                    .quantity
+                    .or(request.quantity.filter(|_| single_item))
+                    .unwrap_or(1),
+            })
         })
-        .collect();
+        .collect::<Vec<_>>();
+    if items.iter().any(|item| item.quantity < 1) {
+        return subscription_error(
+            StatusCode::BAD_REQUEST,
+            "parameter_invalid",
+            "Item quantities must be at least 1",
+        );
+    }
+    let terms = match resolve_billing_terms(&state, &items) {
+        Ok(terms) => terms,
+        Err(message) => {
+            return subscription_error(StatusCode::BAD_REQUEST, "parameter_invalid", message);
+        }
+    };
+
+    let (now, test_clock) = match resolve_test_clock(&state, &customer, request.test_clock) {
+        Ok(clock) => clock,
+        Err(message) => {
+            return subscription_error(StatusCode::BAD_REQUEST, "parameter_invalid", message);
+        }
+    };
+    let (status, current_period_end, trial_end) = match request.trial_period_days {
+        Some(days) if days > 0 => {
+            let trial_end = now + days * 24 * 60 * 60;
+            (STATUS_TRIALING, trial_end, Some(trial_end))
+        }
+        _ => (
+            STATUS_ACTIVE,
+            advance_period(now, terms.interval, terms.interval_count),
+            None,
+        ),
+    };
+
+    let location = request.tax_location();
+    if let Some(location) = &location
+        && let Err(e) = check_tax_location(&state, location)
+    {
+        return e.into_response();
+    }
 
-    let subscription = StripeSubscription {
-        id: sub_id,
-        object: "subscription".to_string(),
+    // The discount's end is fixed when it's redeemed: a `once` coupon covers the first
+    // charged period, a `repeating` one a number of months
+    let catalog = state.catalog();
+    let discount = match resolve_discount(&state, &catalog, &request.discount, Some(&customer), now)
+    {
+        Ok(discount) => discount,
+        Err(e) => return e.into_response(),
+    };
+    let first_period_discount = match &discount {
+        Some(discount) => {
+            let lines = items
+                .iter()
+                .filter_map(|item| {
+                    let (product, price) = find_catalog_price(&state, &catalog, &item.price)?;
+                    Some((
+                        Some(product.id.as_str()),
+                        price
+                            .amount_for(item.quantity, price.currency.as_str())
+                            .unwrap_or(0),
+                    ))
+                })
+                .collect::<Vec<_>>();
+            match discount.apply(&lines, &terms.currency) {
+                Ok(amounts) => amounts.iter().sum::<i64>(),
+                Err(e) => return e.into_response(),
+            }
+        }
+        None => 0,
+    };
+    let discount_end = discount.and_then(|discount| match discount.coupon.duration {
+        CouponDuration::Once => Some(advance_period(
+            trial_end.unwrap_or(now),
+            terms.interval,
+            terms.interval_count,
+        )),
+        CouponDuration::Repeating => Some(
+            DateTime::<Utc>::from_timestamp(now, 0)
+                .and_then(|start| {
+                    start.checked_add_months(Months::new(
+                        discount.coupon.duration_in_months.unwrap_or(1),
+                    ))
+                })
+                .map(|end| end.timestamp())
+                .unwrap_or(now),
+        ),
+        CouponDuration::Forever => None,
+    });
+
+    let subscription_id = generate_stripe_id("sub");
+    let new_subscription = NewSubscription::new(
+        subscription_id.clone(),
         customer,
-        status: "active".to_string(),
-        created,
-        current_period_start: created,
+        &items,
+        terms.currency,
+        terms.interval.as_str().to_string(),
+        terms.interval_count,
+        status.to_string(),
+        now,
         current_period_end,
-        items: SubscriptionItems {
-            object: "list".to_string(),
-            data: items_data,
+        trial_end,
+        request.cancel_at_period_end.unwrap_or(false),
+        encode_metadata(&request.metadata),
+        state.payment_stack_id.clone(),
+        state.use_sandbox,
+    )
+    .with_test_clock(test_clock)
+    .with_discount(
+        discount.map(|d| d.coupon.id.clone()),
+        discount.and_then(|d| d.promotion_code.map(|p| p.id.clone())),
+        discount_end,
+    )
+    .with_tax_location(
+        location.as_ref().map(|location| location.country.clone()),
+        location.and_then(|location| location.state),
+    );
+
+    match db.insert_subscription(&new_subscription) {
+        Ok(subscription) => {
+            info!(
+                "Created subscription {} for {}",
+                subscription.subscription_id, subscription.customer
+            );
+            if let Some(discount) = discount {
+                record_redemption(
+                    &state,
+                    &discount.coupon.id,
+                    discount.promotion_code.map(|p| p.id.as_str()),
+                    Some(&subscription.customer),
+                    &subscription_id,
+                    first_period_discount,
+                    &subscription.currency,
+                );
+            }
+            let subscription = if subscription.status == STATUS_TRIALING {
+                subscription
+            } else {
+                record_first_invoice(&state, db, subscription)
+            };
+            emit_stripe_event(
+                &state,
+                "customer.subscription.created",
+                &StripeSubscription::from(&subscription),
+                subscription.created_at / 1000,
+            );
+            (
+                StatusCode::OK,
+                Json(StripeSubscription::from(&subscription)),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            error!("Failed to create subscription: {}", e);
+            subscription_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "api_error",
+                e.to_string(),
+            )
+        }
+    }
+}
+
+/// GET /v1/subscriptions/:id - Retrieve a subscription
+pub async fn retrieve_subscription(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    match find_subscription_or_404(&state, db, &id) {
+        Ok(subscription) => (
+            StatusCode::OK,
+            Json(StripeSubscription::from(&subscription)),
+        )
+            .into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Query parameters of GET /v1/subscriptions
+#[derive(Debug, Deserialize)]
+pub struct ListSubscriptionsParams {
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub starting_after: Option<String>,
+    #[serde(default)]
+    pub customer: Option<String>,
+    /// A status, or `all` to include canceled subscriptions
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+/// GET /v1/subscriptions - List subscriptions
+pub async fn list_subscriptions(
+    Extension(state): Extension<CatalogState>,
+    StripeQuery(params): StripeQuery<ListSubscriptionsParams>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let limit = params.limit.unwrap_or(10).clamp(1, 100) as usize;
+
+    let starting_after = match params.starting_after.as_deref() {
+        Some(cursor) => match find_subscription_or_404(&state, db, cursor) {
+            Ok(subscription) => Some(subscription.id),
+            Err(response) => return response,
         },
-        latest_invoice: Some(generate_stripe_id("in")),
+        None => None,
+    };
+
+    match db.list_subscriptions(
+        limit,
+        starting_after,
+        params.customer.as_deref(),
+        params.status.as_deref(),
+        &state.payment_stack_id,
+        state.use_sandbox,
+    ) {
+        Ok((subscriptions, has_more)) => {
+            let response = ListResponse {
+                object: "list".to_string(),
+                data: subscriptions
+                    .iter()
+                    .map(StripeSubscription::from)
+                    .collect::<Vec<_>>(),
+                has_more,
+                url: "/v1/subscriptions".to_string(),
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            error!("Failed to list subscriptions: {}", e);
+            subscription_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "api_error",
+                e.to_string(),
+            )
+        }
+    }
+}
+
+/// Apply the `items` of an update request to the current items
+fn apply_item_changes(
+    current: &[SubscriptionItem],
+    request: &SubscriptionRequest,
+) -> Result<Vec<SubscriptionItem>, String> {
+    let mut items = current.to_vec();
+
+    for change in &request.items {
+        match &change.id {
+            Some(id) => {
+                let position = items
+                    .iter()
+                    .position(|item| &item.id == id)
+                    .ok_or_else(|| format!("No such subscription item: '{}'", id))?;
+                if change.deleted {
+                    items.remove(position);
+                    continue;
+                }
+                let item = &mut items[position];
+                if let Some(price) = &change.price {
+                    item.price = price.clone();
+                }
+                if let Some(quantity) = change.quantity {
+                    item.quantity = quantity;
+                }
+            }
+            None => {
+                let price = change
+                    .price
+                    .clone()
+                    .ok_or_else(|| "New subscription items need a price".to_string())?;
+                items.push(SubscriptionItem {
+                    id: generate_stripe_id("si"),
+                    price,
+                    quantity: change.quantity.unwrap_or(1),
+                });
+            }
+        }
+    }
+
+    if let Some(quantity) = request.quantity {
+        match items.as_mut_slice() {
+            [item] => item.quantity = quantity,
+            _ => {
+                return Err(
+                    "quantity can only be set on subscriptions with a single item".to_string(),
+                );
+            }
+        }
+    }
+
+    if items.iter().any(|item| item.quantity < 1) {
+        return Err("Item quantities must be at least 1".to_string());
+    }
+    Ok(items)
+}
+
+/// POST /v1/subscriptions/:id - Update a subscription
+///
+/// Price and quantity changes apply from the next renewal; the current period is not
+/// prorated.
+pub async fn update_subscription(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let subscription = match find_subscription_or_404(&state, db, &id) {
+        Ok(subscription) => subscription,
+        Err(response) => return response,
+    };
+    if subscription.status == STATUS_CANCELED {
+        return subscription_error(
+            StatusCode::BAD_REQUEST,
+            "resource_invalid_state",
+            "A canceled subscription can't be updated",
+        );
+    }
+
+    let request = match SubscriptionRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    let mut changes = UpdateSubscription::default();
+
+    if !request.items.is_empty() || request.quantity.is_some() {
+        let items = match apply_item_changes(&subscription.items(), &request) {
+            Ok(items) => items,
+            Err(message) => {
+                return subscription_error(StatusCode::BAD_REQUEST, "parameter_invalid", message);
+            }
+        };
+        match resolve_billing_terms(&state, &items) {
+            Ok(terms)
+                if terms.currency == subscription.currency
+                    && terms.interval.as_str() == subscription.recurring_interval
+                    && terms.interval_count == subscription.interval_count => {}
+            Ok(_) => {
+                return subscription_error(
+                    StatusCode::BAD_REQUEST,
+                    "parameter_invalid",
+                    "New prices must use the subscription's currency and billing interval",
+                );
+            }
+            Err(message) => {
+                return subscription_error(StatusCode::BAD_REQUEST, "parameter_invalid", message);
+            }
+        }
+        changes = changes.with_items(&items);
+    }
+    if let Some(cancel_at_period_end) = request.cancel_at_period_end {
+        changes.cancel_at_period_end = Some(cancel_at_period_end);
+    }
+    if !request.metadata.is_empty() {
+        let mut metadata = subscription.metadata();
+        for (key, value) in request.metadata {
+            // Stripe removes metadata keys set to an empty string
+            if value.is_empty() {
+                metadata.remove(&key);
+            } else {
+                metadata.insert(key, value);
+            }
+        }
+        changes.metadata = Some(encode_metadata(&metadata));
+    }
+
+    let result = db
+        .update_subscription(&id, &subscription.status, changes)
+        .map_err(|e| e.to_string());
+    if let Ok(Some(updated)) = &result {
+        let at = clock_time(&state, subscription.test_clock.as_deref());
+        emit_subscription_updated(&state, &subscription, updated, at);
+    }
+    updated_response(result)
+}
+
+/// DELETE /v1/subscriptions/:id - Cancel a subscription immediately
+///
+/// To cancel at the end of the current period instead, update the subscription with
+/// `cancel_at_period_end=true`.
+pub async fn cancel_subscription(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let subscription = match find_subscription_or_404(&state, db, &id) {
+        Ok(subscription) => subscription,
+        Err(response) => return response,
+    };
+    if subscription.status == STATUS_CANCELED {
+        return subscription_error(
+            StatusCode::BAD_REQUEST,
+            "resource_invalid_state",
+            "This subscription is already canceled",
+        );
+    }
+
+    let now = clock_time(&state, subscription.test_clock.as_deref());
+    updated_response(end_subscription(&state, db, &subscription, now))
+}
+
+/// POST /v1/subscriptions/:id/pause - Pause a subscription
+///
+/// A paused subscription is not renewed. Resuming it starts a new billing period.
+pub async fn pause_subscription(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let subscription = match find_subscription_or_404(&state, db, &id) {
+        Ok(subscription) => subscription,
+        Err(response) => return response,
+    };
+    if !matches!(
+        subscription.status.as_str(),
+        STATUS_ACTIVE | STATUS_TRIALING | STATUS_PAST_DUE
+    ) {
+        return subscription_error(
+            StatusCode::BAD_REQUEST,
+            "resource_invalid_state",
+            format!(
+                "A subscription with status {} can't be paused",
+                subscription.status
+            ),
+        );
+    }
+
+    let now = clock_time(&state, subscription.test_clock.as_deref());
+    let changes = UpdateSubscription {
+        status: Some(STATUS_PAUSED.to_string()),
+        paused_at: Some(Some(now)),
+        ..Default::default()
+    };
+    let result = db
+        .update_subscription(&id, &subscription.status, changes)
+        .map_err(|e| e.to_string());
+    if let Ok(Some(paused)) = &result {
+        emit_subscription_updated(&state, &subscription, paused, now);
+    }
+    updated_response(result)
+}
+
+/// POST /v1/subscriptions/:id/resume - Resume a paused subscription
+///
+/// Starts a new billing period now and creates the payment intent charging it.
+pub async fn resume_subscription(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let db = match payment_db(&state) {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let subscription = match find_subscription_or_404(&state, db, &id) {
+        Ok(subscription) => subscription,
+        Err(response) => return response,
+    };
+    if subscription.status != STATUS_PAUSED {
+        return subscription_error(
+            StatusCode::BAD_REQUEST,
+            "resource_invalid_state",
+            "Only paused subscriptions can be resumed",
+        );
+    }
+
+    let changes = UpdateSubscription {
+        paused_at: Some(None),
+        trial_end: Some(None),
+        ..Default::default()
     };
+    updated_response(start_charged_period(
+        &state,
+        db,
+        &subscription,
+        clock_time(&state, subscription.test_clock.as_deref()),
+        changes,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use moneymq_types::{Currency, PricingType};
+
+    use super::*;
+    use crate::api::payment::db::InvoiceModel;
+
+    // 2025-01-01, 2025-02-01, 2025-03-01 and 2025-04-01 (UTC)
+    const JAN_1: i64 = 1_735_689_600;
+    const FEB_1: i64 = 1_738_368_000;
+    const MAR_1: i64 = 1_740_787_200;
+    const APR_1: i64 = 1_743_465_600;
+
+    /// Catalog selling $10 a month, with an in-memory payment database
+    fn db_state() -> CatalogState {
+        let mut price = Price::new(Currency::Usd, PricingType::Recurring)
+            .with_some_amount(Some(1000))
+            .with_some_interval(Some(RecurringInterval::Month));
+        price.id = "price_monthly".to_string();
+        let mut product = Product::new()
+            .with_some_name(Some("Pro".to_string()))
+            .add_price(price);
+        product.id = "pro".to_string();
+        CatalogState::new(
+            vec![product],
+            Vec::new(),
+            true,
+            "http://localhost:8488".parse().unwrap(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+        .with_payment_db(Arc::new(DbManager::local(":memory:").unwrap()), "test")
+    }
+
+    /// Insert an active monthly subscription whose current period is `start..end`
+    fn subscribe(state: &CatalogState, start: i64, end: i64) -> SubscriptionModel {
+        let items = [SubscriptionItem {
+            id: "si_1".to_string(),
+            price: "price_monthly".to_string(),
+            quantity: 1,
+        }];
+        let subscription = NewSubscription::new(
+            generate_stripe_id("sub"),
+            "cus_1".to_string(),
+            &items,
+            "usd".to_string(),
+            "month".to_string(),
+            1,
+            STATUS_ACTIVE.to_string(),
+            start,
+            end,
+            None,
+            false,
+            None,
+            state.payment_stack_id.clone(),
+            state.use_sandbox,
+        );
+        state
+            .payment_db
+            .as_ref()
+            .unwrap()
+            .insert_subscription(&subscription)
+            .unwrap()
+    }
+
+    fn reload(state: &CatalogState, subscription: &SubscriptionModel) -> SubscriptionModel {
+        state
+            .payment_db
+            .as_ref()
+            .unwrap()
+            .find_subscription(&subscription.subscription_id, "test", true)
+            .unwrap()
+            .unwrap()
+    }
+
+    fn invoices(state: &CatalogState, subscription: &SubscriptionModel) -> Vec<InvoiceModel> {
+        let (invoices, _) = state
+            .payment_db
+            .as_ref()
+            .unwrap()
+            .list_invoices(
+                100,
+                None,
+                None,
+                Some(&subscription.subscription_id),
+                None,
+                "test",
+                true,
+            )
+            .unwrap();
+        invoices
+    }
+
+    #[test]
+    fn test_parse_subscription_request() {
+        let body = Bytes::from(
+            "customer=cus_123&items[0][price]=price_a&items[0][quantity]=2\
+             &items[1][price]=price_b&trial_period_days=7&metadata[plan]=pro",
+        );
+        let request = SubscriptionRequest::parse(&body).unwrap();
+
+        assert_eq!(request.customer.as_deref(), Some("cus_123"));
+        assert_eq!(request.price_ids(), vec!["price_a", "price_b"]);
+        assert_eq!(request.items[0].quantity, Some(2));
+        assert_eq!(request.items[1].quantity, None);
+        assert!(request.has_trial());
+        assert_eq!(
+            request.metadata.get("plan").map(String::as_str),
+            Some("pro")
+        );
+    }
+
+    #[test]
+    fn test_parse_percent_encoded_item_keys() {
+        let body = Bytes::from("items%5B0%5D%5Bid%5D=si_1&items%5B0%5D%5Bdeleted%5D=true");
+        let request = SubscriptionRequest::parse(&body).unwrap();
 
-    (StatusCode::OK, Json(subscription)).into_response()
+        assert_eq!(request.items.len(), 1);
+        assert_eq!(request.items[0].id.as_deref(), Some("si_1"));
+        assert!(request.items[0].deleted);
+    }
+
+    #[test]
+    fn test_advance_period() {
+        // 2025-01-31T00:00:00Z
+        let start = 1_738_281_600;
+        assert_eq!(
+            advance_period(start, RecurringInterval::Day, 1),
+            start + 86_400
+        );
+        assert_eq!(
+            advance_period(start, RecurringInterval::Week, 2),
+            start + 14 * 86_400
+        );
+        // Month-end dates clamp to the end of the shorter month (2025-02-28)
+        assert_eq!(
+            advance_period(start, RecurringInterval::Month, 1),
+            1_740_700_800
+        );
+        // 2026-01-31T00:00:00Z
+        assert_eq!(
+            advance_period(start, RecurringInterval::Year, 1),
+            1_769_817_600
+        );
+    }
+
+    #[test]
+    fn test_apply_item_changes() {
+        let current = vec![SubscriptionItem {
+            id: "si_1".to_string(),
+            price: "price_a".to_string(),
+            quantity: 1,
+        }];
+
+        let request =
+            SubscriptionRequest::parse(&Bytes::from("items[0][id]=si_1&items[0][price]=price_b"))
+                .unwrap();
+        let items = apply_item_changes(&current, &request).unwrap();
+        assert_eq!(items[0].price, "price_b");
+        assert_eq!(items[0].quantity, 1);
+
+        let request = SubscriptionRequest::parse(&Bytes::from("quantity=5")).unwrap();
+        assert_eq!(
+            apply_item_changes(&current, &request).unwrap()[0].quantity,
+            5
+        );
+
+        let request =
+            SubscriptionRequest::parse(&Bytes::from("items[0][id]=si_1&items[0][deleted]=true"))
+                .unwrap();
+        assert!(apply_item_changes(&current, &request).unwrap().is_empty());
+
+        let request = SubscriptionRequest::parse(&Bytes::from("items[0][id]=si_unknown")).unwrap();
+        assert!(apply_item_changes(&current, &request).is_err());
+
+        let request = SubscriptionRequest::parse(&Bytes::from("quantity=0")).unwrap();
+        assert!(apply_item_changes(&current, &request).is_err());
+    }
+
+    #[test]
+    fn test_renewal_invoices_the_new_period() {
+        let state = db_state();
+        let subscription = subscribe(&state, JAN_1, FEB_1);
+
+        assert_eq!(run_renewal_round(&state, FEB_1 - 1, None), 0);
+        assert_eq!(run_renewal_round(&state, FEB_1 + 60, None), 1);
+
+        let renewed = reload(&state, &subscription);
+        assert_eq!(renewed.status, STATUS_PAST_DUE);
+        assert_eq!(
+            (renewed.current_period_start, renewed.current_period_end),
+            (FEB_1, MAR_1)
+        );
+        let invoices = invoices(&state, &subscription);
+        assert_eq!(invoices.len(), 1);
+        let invoice = &invoices[0];
+        assert_eq!(renewed.latest_invoice.as_ref(), Some(&invoice.invoice_id));
+        assert_eq!(invoice.status, invoice::STATUS_OPEN);
+        assert_eq!(
+            invoice.billing_reason,
+            invoice::BILLING_REASON_SUBSCRIPTION_CYCLE
+        );
+        assert_eq!(invoice.total, 1000);
+        assert_eq!((invoice.period_start, invoice.period_end), (FEB_1, MAR_1));
+        assert_eq!(renewed.latest_payment_intent, invoice.payment_intent);
+
+        // The renewed period isn't due again
+        assert_eq!(run_renewal_round(&state, FEB_1 + 120, None), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_at_period_end() {
+        let state = db_state();
+        let subscription = subscribe(&state, JAN_1, FEB_1);
+        let response = update_subscription(
+            Extension(state.clone()),
+            Path(subscription.subscription_id.clone()),
+            Bytes::from("cancel_at_period_end=true"),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(run_renewal_round(&state, FEB_1 - 1, None), 0);
+        assert_eq!(reload(&state, &subscription).status, STATUS_ACTIVE);
+
+        assert_eq!(run_renewal_round(&state, FEB_1 + 60, None), 1);
+        let canceled = reload(&state, &subscription);
+        assert_eq!(canceled.status, STATUS_CANCELED);
+        // Canceled at the period boundary, not when the renewal task noticed
+        assert_eq!(canceled.canceled_at, Some(FEB_1));
+        assert_eq!(canceled.current_period_end, FEB_1);
+        assert!(invoices(&state, &subscription).is_empty());
+        assert_eq!(run_renewal_round(&state, MAR_1, None), 0);
+    }
+
+    #[test]
+    fn test_catch_up_after_downtime_charges_once() {
+        let state = db_state();
+        let subscription = subscribe(&state, JAN_1, FEB_1);
+
+        // Down from January to mid-April: only the period containing now is charged
+        assert_eq!(run_renewal_round(&state, APR_1 + 10 * 86_400, None), 1);
+        let renewed = reload(&state, &subscription);
+        assert_eq!(renewed.current_period_start, APR_1);
+        assert_eq!(
+            renewed.current_period_end,
+            advance_period(APR_1, RecurringInterval::Month, 1)
+        );
+        let invoices = invoices(&state, &subscription);
+        assert_eq!(invoices.len(), 1);
+        assert_eq!(invoices[0].period_start, APR_1);
+        assert_eq!(invoices[0].total, 1000);
+    }
+
+    #[test]
+    fn test_concurrent_status_change_voids_the_renewal_invoice() {
+        let state = db_state();
+        let db = state.payment_db.clone().unwrap();
+        let stale = subscribe(&state, JAN_1, FEB_1);
+
+        // Paused after the renewal task read it
+        let paused = db
+            .update_subscription(
+                &stale.subscription_id,
+                STATUS_ACTIVE,
+                UpdateSubscription {
+                    status: Some(STATUS_PAUSED.to_string()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(paused.is_some());
+
+        let renewed =
+            start_charged_period(&state, &db, &stale, FEB_1, UpdateSubscription::default())
+                .unwrap();
+        assert!(renewed.is_none());
+        let current = reload(&state, &stale);
+        assert_eq!(current.status, STATUS_PAUSED);
+        assert_eq!(current.current_period_start, JAN_1);
+        let invoices = invoices(&state, &stale);
+        assert_eq!(invoices.len(), 1);
+        assert_eq!(invoices[0].status, invoice::STATUS_VOID);
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume() {
+        let state = db_state();
+        let subscription = subscribe(&state, JAN_1, FEB_1);
+        let id = subscription.subscription_id.clone();
+        let pause =
+            |state: &CatalogState| pause_subscription(Extension(state.clone()), Path(id.clone()));
+        let resume =
+            |state: &CatalogState| resume_subscription(Extension(state.clone()), Path(id.clone()));
+
+        assert_eq!(
+            resume(&state).await.into_response().status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(pause(&state).await.into_response().status(), StatusCode::OK);
+        let paused = reload(&state, &subscription);
+        assert_eq!(paused.status, STATUS_PAUSED);
+        assert!(paused.paused_at.is_some());
+        assert_eq!(
+            pause(&state).await.into_response().status(),
+            StatusCode::BAD_REQUEST
+        );
+
+        // Paused subscriptions aren't renewed
+        assert_eq!(run_renewal_round(&state, MAR_1, None), 0);
+        assert!(invoices(&state, &subscription).is_empty());
+
+        // Resuming starts a new period now and invoices it
+        let before = Utc::now().timestamp();
+        assert_eq!(
+            resume(&state).await.into_response().status(),
+            StatusCode::OK
+        );
+        let resumed = reload(&state, &subscription);
+        assert_eq!(resumed.status, STATUS_PAST_DUE);
+        assert_eq!(resumed.paused_at, None);
+        assert!(resumed.current_period_start >= before);
+        assert_eq!(
+            resumed.current_period_end,
+            advance_period(resumed.current_period_start, RecurringInterval::Month, 1)
+        );
+        let invoices = invoices(&state, &subscription);
+        assert_eq!(invoices.len(), 1);
+        assert_eq!(invoices[0].status, invoice::STATUS_OPEN);
+        assert_eq!(
+            resumed.latest_invoice.as_ref(),
+            Some(&invoices[0].invoice_id)
+        );
+    }
 }
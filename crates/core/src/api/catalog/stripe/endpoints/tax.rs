@@ -0,0 +1,385 @@
+//! Tax calculations for the buyer's jurisdiction
+//!
+//! Rates come from the embedded tax rate database (`taxes/`), and apply to digital goods
+//! only where the jurisdiction taxes them (EU VAT applies everywhere in the EU). Each
+//! price is tax-exclusive (tax added on top, the default) or tax-inclusive (tax taken
+//! out of its amount) through its `tax_behavior`.
+//!
+//! The same calculation prices checkout sessions, invoices and x402 payment requirements
+//! when a customer address is known; the tax charged is broken out in payment intent
+//! metadata, receipts and transactions as a [`PaymentTax`].
+
+use std::collections::HashMap;
+
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use moneymq_types::{PaymentTax, Price, TaxBehavior};
+
+use crate::{
+    api::catalog::{
+        CatalogState,
+        stripe::{
+            form::{FormError, StripeForm},
+            types::{
+                StripeTaxCalculation, TaxAddress, TaxBreakdown, TaxCalculationLineItem,
+                TaxCalculationLineItems, TaxCustomerDetails, TaxRateDetails,
+            },
+            utils::generate_stripe_id,
+        },
+    },
+    taxes::{TaxCalculation, TaxLocation},
+};
+
+/// Metadata key of the tax charged by a payment intent ([`PaymentTax`] JSON)
+pub const TAX_METADATA_KEY: &str = "tax";
+
+/// How long a tax calculation can be referenced (seconds)
+const CALCULATION_LIFETIME: i64 = 90 * 24 * 3600;
+
+/// Why a purchase can't be taxed
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TaxError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl TaxError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for TaxError {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": {
+                    "code": self.code,
+                    "message": self.message,
+                    "type": "invalid_request_error"
+                }
+            })),
+        )
+            .into_response()
+    }
+}
+
+/// Tax behavior of a catalog price, exclusive unless it says otherwise
+pub(crate) fn price_tax_behavior(price: &Price) -> TaxBehavior {
+    price.tax_behavior.unwrap_or_default()
+}
+
+/// Location a purchase is taxed for, from a customer address; None without a country
+pub(crate) fn tax_location(country: Option<&str>, state: Option<&str>) -> Option<TaxLocation> {
+    country
+        .filter(|country| !country.trim().is_empty())
+        .map(|country| TaxLocation::new(country, state))
+}
+
+/// Tax on `lines` of (amount in cents after discounts, tax behavior) sold to `location`
+pub(crate) fn calculate_tax(
+    state: &CatalogState,
+    location: &TaxLocation,
+    lines: &[(i64, TaxBehavior)],
+) -> Result<TaxCalculation, TaxError> {
+    state.tax_data.calculate(location, lines).ok_or_else(|| {
+        TaxError::new(
+            "customer_tax_location_invalid",
+            match &location.state {
+                Some(subdivision) => format!(
+                    "No tax rate is known for '{}' in '{}'",
+                    subdivision, location.country
+                ),
+                None => format!("No tax rate is known for country '{}'", location.country),
+            },
+        )
+    })
+}
+
+/// Fail unless purchases can be taxed for `location`
+pub(crate) fn check_tax_location(
+    state: &CatalogState,
+    location: &TaxLocation,
+) -> Result<(), TaxError> {
+    calculate_tax(state, location, &[]).map(|_| ())
+}
+
+/// Rate details of the tax charged by a payment
+pub(crate) fn tax_rate_details(tax: &PaymentTax) -> TaxRateDetails {
+    TaxRateDetails {
+        country: tax.country.clone(),
+        state: tax.state.clone(),
+        percentage_decimal: tax.rate.to_string(),
+        tax_type: tax.tax_type.to_lowercase(),
+    }
+}
+
+/// Record the tax charged by a payment intent in its metadata
+pub(crate) fn insert_tax_metadata(metadata: &mut HashMap<String, String>, tax: &PaymentTax) {
+    if let Ok(tax) = serde_json::to_string(tax) {
+        metadata.insert(TAX_METADATA_KEY.to_string(), tax);
+    }
+}
+
+/// Tax charged by a payment intent, from its metadata
+pub(crate) fn metadata_tax(metadata: &HashMap<String, String>) -> Option<PaymentTax> {
+    metadata
+        .get(TAX_METADATA_KEY)
+        .and_then(|tax| serde_json::from_str(tax).ok())
+}
+
+/// Form-encoded tax calculation request
+#[derive(Debug, Default)]
+pub struct TaxCalculationRequest {
+    pub currency: Option<String>,
+    pub address: TaxAddress,
+    pub line_items: Vec<TaxCalculationLineRequest>,
+}
+
+/// `line_items[N]` of a tax calculation request
+#[derive(Debug, Default, Clone)]
+pub struct TaxCalculationLineRequest {
+    /// Line amount in cents (unit amount times quantity)
+    pub amount: Option<i64>,
+    pub reference: Option<String>,
+    pub product: Option<String>,
+    pub quantity: Option<i64>,
+    /// Raw `tax_behavior`; defaults to the catalog price's behavior
+    pub tax_behavior: Option<String>,
+}
+
+impl TaxCalculationRequest {
+    pub fn parse(body: &Bytes) -> Result<TaxCalculationRequest, FormError> {
+        let form = StripeForm::decode(body)?;
+        let line_items = form
+            .list("line_items")?
+            .iter()
+            .map(|line| {
+                Ok(TaxCalculationLineRequest {
+                    amount: line.integer("amount")?,
+                    reference: line.string("reference")?,
+                    product: line.string("product")?,
+                    quantity: line.integer("quantity")?,
+                    tax_behavior: line.string("tax_behavior")?,
+                })
+            })
+            .collect::<Result<Vec<_>, FormError>>()?;
+        Ok(TaxCalculationRequest {
+            currency: form.string("currency")?.map(|c| c.to_lowercase()),
+            address: TaxAddress {
+                country: form
+                    .string("customer_details[address][country]")?
+                    .unwrap_or_default(),
+                state: form.string("customer_details[address][state]")?,
+                postal_code: form.string("customer_details[address][postal_code]")?,
+            },
+            line_items,
+        })
+    }
+}
+
+/// Tax behavior of a calculation line: requested, or the one of its product's price
+fn line_tax_behavior(
+    state: &CatalogState,
+    line: &TaxCalculationLineRequest,
+) -> Result<TaxBehavior, TaxError> {
+    if let Some(tax_behavior) = &line.tax_behavior {
+        return TaxBehavior::parse(tax_behavior).ok_or_else(|| {
+            TaxError::new(
+                "parameter_invalid",
+                format!(
+                    "Invalid tax_behavior '{}', expected one of: {}",
+                    tax_behavior,
+                    TaxBehavior::valid_values()
+                ),
+            )
+        });
+    }
+    let catalog = state.catalog();
+    Ok(line
+        .product
+        .as_deref()
+        .and_then(|product_id| catalog.products.iter().find(|p| p.id == product_id))
+        .and_then(|product| product.prices.first())
+        .map(price_tax_behavior)
+        .unwrap_or_default())
+}
+
+/// Tax summed over the lines with the same behavior
+fn tax_breakdown(calculation: &TaxCalculation, inclusive: bool) -> Option<TaxBreakdown> {
+    let lines = calculation
+        .lines
+        .iter()
+        .filter(|line| line.inclusive == inclusive)
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        return None;
+    }
+    let rate = &calculation.rate;
+    Some(TaxBreakdown {
+        amount: lines.iter().map(|line| line.amount_tax).sum(),
+        inclusive,
+        taxable_amount: lines.iter().map(|line| line.taxable_amount).sum(),
+        taxability_reason: if rate.rate > 0.0 {
+            "standard_rated"
+        } else {
+            "not_subject_to_tax"
+        }
+        .to_string(),
+        tax_rate_details: TaxRateDetails {
+            country: rate.country_code.clone(),
+            state: rate.state_code.clone(),
+            percentage_decimal: rate.rate.to_string(),
+            tax_type: rate.tax_type.to_lowercase(),
+        },
+    })
+}
+
+/// POST /v1/tax/calculations - Calculate the tax on line items for a customer address
+pub async fn create_tax_calculation(
+    Extension(state): Extension<CatalogState>,
+    body: Bytes,
+) -> Response {
+    let request = match TaxCalculationRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+    let Some(currency) = request.currency.filter(|c| !c.is_empty()) else {
+        return FormError::Missing("currency".to_string()).into_response();
+    };
+    let Some(location) = tax_location(
+        Some(request.address.country.as_str()),
+        request.address.state.as_deref(),
+    ) else {
+        return FormError::Missing("customer_details[address][country]".to_string())
+            .into_response();
+    };
+    if request.line_items.is_empty() {
+        return FormError::Missing("line_items".to_string()).into_response();
+    }
+
+    let mut lines = Vec::with_capacity(request.line_items.len());
+    for (index, line) in request.line_items.iter().enumerate() {
+        let param = format!("line_items[{}][amount]", index);
+        let amount = match line.amount {
+            Some(amount) if amount >= 0 => amount,
+            Some(_) => {
+                return FormError::Invalid {
+                    message: format!("{} must be zero or more", param),
+                    param,
+                }
+                .into_response();
+            }
+            None => return FormError::Missing(param).into_response(),
+        };
+        match line_tax_behavior(&state, line) {
+            Ok(tax_behavior) => lines.push((amount, tax_behavior)),
+            Err(e) => return e.into_response(),
+        }
+    }
+    let calculation = match calculate_tax(&state, &location, &lines) {
+        Ok(calculation) => calculation,
+        Err(e) => return e.into_response(),
+    };
+
+    let id = generate_stripe_id("taxcalc");
+    let line_items = request
+        .line_items
+        .iter()
+        .zip(&calculation.lines)
+        .enumerate()
+        .map(|(index, (line, tax))| TaxCalculationLineItem {
+            id: generate_stripe_id("tax_li"),
+            object: "tax.calculation_line_item".to_string(),
+            amount: line.amount.unwrap_or_default(),
+            amount_tax: tax.amount_tax,
+            product: line.product.clone(),
+            quantity: line.quantity.unwrap_or(1),
+            reference: line
+                .reference
+                .clone()
+                .unwrap_or_else(|| format!("line_{}", index)),
+            tax_behavior: if tax.inclusive {
+                TaxBehavior::Inclusive
+            } else {
+                TaxBehavior::Exclusive
+            }
+            .as_str()
+            .to_string(),
+        })
+        .collect();
+    let now = chrono::Utc::now().timestamp();
+
+    let response = StripeTaxCalculation {
+        id,
+        object: "tax.calculation".to_string(),
+        amount_total: calculation.amount_total(),
+        currency,
+        customer_details: TaxCustomerDetails {
+            address: TaxAddress {
+                country: location.country.clone(),
+                state: location.state.clone(),
+                postal_code: request.address.postal_code,
+            },
+            address_source: "billing".to_string(),
+        },
+        line_items: TaxCalculationLineItems {
+            object: "list".to_string(),
+            data: line_items,
+            has_more: false,
+        },
+        tax_amount_exclusive: calculation.amount_exclusive(),
+        tax_amount_inclusive: calculation.amount_inclusive(),
+        tax_breakdown: [false, true]
+            .into_iter()
+            .filter_map(|inclusive| tax_breakdown(&calculation, inclusive))
+            .collect(),
+        expires_at: now + CALCULATION_LIFETIME,
+        livemode: !state.use_sandbox,
+    };
+
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tax_calculation_request() {
+        let body = Bytes::from(
+            "currency=EUR&customer_details%5Baddress%5D%5Bcountry%5D=de\
+             &line_items[1][amount]=500&line_items[1][tax_behavior]=inclusive\
+             &line_items[0][amount]=1000&line_items[0][reference]=pro",
+        );
+        let request = TaxCalculationRequest::parse(&body).unwrap();
+        assert_eq!(request.currency.as_deref(), Some("eur"));
+        assert_eq!(request.address.country, "de");
+        assert_eq!(request.line_items.len(), 2);
+        assert_eq!(request.line_items[0].amount, Some(1000));
+        assert_eq!(request.line_items[0].reference.as_deref(), Some("pro"));
+        assert_eq!(request.line_items[1].amount, Some(500));
+        assert_eq!(
+            request.line_items[1].tax_behavior.as_deref(),
+            Some("inclusive")
+        );
+    }
+
+    #[test]
+    fn test_tax_location() {
+        assert_eq!(tax_location(None, Some("CA")), None);
+        assert_eq!(tax_location(Some(" "), None), None);
+        assert_eq!(
+            tax_location(Some("us"), Some("ca")),
+            Some(TaxLocation::new("US", Some("CA")))
+        );
+    }
+}
@@ -0,0 +1,466 @@
+//! Sandbox test clocks
+//!
+//! Customers (and their subscriptions and checkout sessions) attached to a test clock see
+//! the clock's frozen time instead of the wall clock. Advancing a clock synchronously runs
+//! everything that became due in between, so billing scenarios can be tested without
+//! waiting.
+
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::api::catalog::{
+    CatalogState,
+    stripe::{
+        endpoints::{
+            checkout_sessions::expire_checkout_sessions,
+            subscriptions::{end_subscription, run_renewal_round},
+        },
+        form::StripeQuery,
+        types::{
+            AdvanceTestClockRequest, CreateTestClockRequest, DeletedTestClock, ListResponse,
+            StripeTestClock, TestClockStatus,
+        },
+        utils::generate_stripe_id,
+    },
+};
+
+/// Upper bound on billing periods processed by a single advance, so a clock advanced far
+/// into the future can't run unbounded
+pub const MAX_ADVANCE_ROUNDS: usize = 1000;
+
+/// Test clock the customer is attached to, if any
+pub(crate) fn customer_test_clock(state: &CatalogState, customer: &str) -> Option<String> {
+    state
+        .customer_test_clocks
+        .lock()
+        .unwrap()
+        .get(customer)
+        .cloned()
+}
+
+/// Current time (Unix seconds) of `test_clock`, or the wall clock without one
+pub(crate) fn clock_time(state: &CatalogState, test_clock: Option<&str>) -> i64 {
+    test_clock
+        .and_then(|id| {
+            state
+                .test_clocks
+                .lock()
+                .unwrap()
+                .get(id)
+                .map(|c| c.frozen_time)
+        })
+        .unwrap_or_else(|| Utc::now().timestamp())
+}
+
+/// Current time (Unix seconds) seen by `customer`, and the test clock it comes from
+pub(crate) fn current_time(state: &CatalogState, customer: Option<&str>) -> (i64, Option<String>) {
+    let test_clock = customer.and_then(|customer| customer_test_clock(state, customer));
+    (clock_time(state, test_clock.as_deref()), test_clock)
+}
+
+/// Attach a customer to a test clock, returning the clock's frozen time.
+///
+/// Returns None if the clock doesn't exist or the catalog isn't in sandbox mode.
+pub(crate) fn attach_customer(
+    state: &CatalogState,
+    customer: &str,
+    test_clock: &str,
+) -> Option<i64> {
+    if !state.use_sandbox {
+        return None;
+    }
+    let frozen_time = state
+        .test_clocks
+        .lock()
+        .unwrap()
+        .get(test_clock)
+        .map(|clock| clock.frozen_time)?;
+    state
+        .customer_test_clocks
+        .lock()
+        .unwrap()
+        .insert(customer.to_string(), test_clock.to_string());
+    Some(frozen_time)
+}
+
+/// Run everything attached to `test_clock` that became due by `frozen_time`.
+///
+/// Subscriptions are renewed one billing period at a time, so each period gets its own
+/// charge and event, as if the clock had been advanced step by step.
+pub fn run_test_clock(state: &CatalogState, test_clock: &str, frozen_time: i64) -> bool {
+    let mut complete = true;
+    if let Some(db) = state.payment_db.as_ref() {
+        let mut rounds = 0;
+        loop {
+            let due = match db.list_due_subscriptions(
+                frozen_time,
+                Some(test_clock),
+                &state.payment_stack_id,
+                state.use_sandbox,
+            ) {
+                Ok(due) => due,
+                Err(e) => {
+                    warn!("Failed to list subscriptions on {}: {}", test_clock, e);
+                    complete = false;
+                    break;
+                }
+            };
+            let Some(next) = due.first() else {
+                break;
+            };
+            if rounds == MAX_ADVANCE_ROUNDS {
+                warn!(
+                    "Test clock {} stopped after {} billing rounds",
+                    test_clock, MAX_ADVANCE_ROUNDS
+                );
+                complete = false;
+                break;
+            }
+            run_renewal_round(state, next.current_period_end, Some(test_clock));
+            rounds += 1;
+        }
+    }
+
+    expire_checkout_sessions(state, frozen_time, Some(test_clock));
+    complete
+}
+
+fn test_clock_error(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(serde_json::json!({
+            "error": {
+                "code": code,
+                "message": message.into(),
+                "type": "invalid_request_error"
+            }
+        })),
+    )
+        .into_response()
+}
+
+fn require_sandbox(state: &CatalogState) -> Result<(), Response> {
+    if state.use_sandbox {
+        Ok(())
+    } else {
+        Err(test_clock_error(
+            StatusCode::BAD_REQUEST,
+            "sandbox_only",
+            "Test clocks are only available in sandbox mode",
+        ))
+    }
+}
+
+fn test_clock_missing(id: &str) -> Response {
+    test_clock_error(
+        StatusCode::NOT_FOUND,
+        "resource_missing",
+        format!("No such test clock: '{}'", id),
+    )
+}
+
+/// POST /v1/test_helpers/test_clocks - Create a test clock
+pub async fn create_test_clock(
+    Extension(state): Extension<CatalogState>,
+    body: Bytes,
+) -> impl IntoResponse {
+    if let Err(response) = require_sandbox(&state) {
+        return response;
+    }
+    let request = match CreateTestClockRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+
+    let test_clock = StripeTestClock {
+        id: generate_stripe_id("clock"),
+        object: "test_helpers.test_clock".to_string(),
+        created: Utc::now().timestamp(),
+        frozen_time: request.frozen_time,
+        livemode: false,
+        name: request.name,
+        status: TestClockStatus::Ready,
+    };
+    state
+        .test_clocks
+        .lock()
+        .unwrap()
+        .insert(test_clock.id.clone(), test_clock.clone());
+
+    (StatusCode::OK, Json(test_clock)).into_response()
+}
+
+/// GET /v1/test_helpers/test_clocks/:id - Retrieve a test clock
+pub async fn retrieve_test_clock(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    if let Err(response) = require_sandbox(&state) {
+        return response;
+    }
+
+    match state.test_clocks.lock().unwrap().get(&id) {
+        Some(test_clock) => (StatusCode::OK, Json(test_clock.clone())).into_response(),
+        None => test_clock_missing(&id),
+    }
+}
+
+/// Query parameters of GET /v1/test_helpers/test_clocks
+#[derive(Debug, Deserialize)]
+pub struct ListTestClocksParams {
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// GET /v1/test_helpers/test_clocks - List test clocks, newest first
+pub async fn list_test_clocks(
+    Extension(state): Extension<CatalogState>,
+    StripeQuery(params): StripeQuery<ListTestClocksParams>,
+) -> impl IntoResponse {
+    if let Err(response) = require_sandbox(&state) {
+        return response;
+    }
+
+    let limit = params.limit.unwrap_or(10).clamp(1, 100);
+    let mut test_clocks = state
+        .test_clocks
+        .lock()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    test_clocks.sort_by(|a, b| b.created.cmp(&a.created).then(b.id.cmp(&a.id)));
+    let has_more = test_clocks.len() > limit;
+    test_clocks.truncate(limit);
+
+    let response = ListResponse {
+        object: "list".to_string(),
+        data: test_clocks,
+        has_more,
+        url: "/v1/test_helpers/test_clocks".to_string(),
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// POST /v1/test_helpers/test_clocks/:id/advance - Advance a test clock
+///
+/// Renewals, trial ends and checkout session expiries that fall before the new frozen time
+/// are run before responding.
+pub async fn advance_test_clock(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> impl IntoResponse {
+    if let Err(response) = require_sandbox(&state) {
+        return response;
+    }
+    let request = match AdvanceTestClockRequest::parse(&body) {
+        Ok(request) => request,
+        Err(e) => return e.into_response(),
+    };
+
+    {
+        let mut test_clocks = state.test_clocks.lock().unwrap();
+        let Some(test_clock) = test_clocks.get_mut(&id) else {
+            return test_clock_missing(&id);
+        };
+        if test_clock.status == TestClockStatus::Advancing {
+            return test_clock_error(
+                StatusCode::BAD_REQUEST,
+                "test_clock_advancing",
+                "The test clock is already advancing",
+            );
+        }
+        if request.frozen_time <= test_clock.frozen_time {
+            return test_clock_error(
+                StatusCode::BAD_REQUEST,
+                "parameter_invalid",
+                format!(
+                    "frozen_time must be after the current frozen time ({})",
+                    test_clock.frozen_time
+                ),
+            );
+        }
+        test_clock.status = TestClockStatus::Advancing;
+        test_clock.frozen_time = request.frozen_time;
+    }
+
+    info!("Advancing test clock {} to {}", id, request.frozen_time);
+    let complete = run_test_clock(&state, &id, request.frozen_time);
+
+    let mut test_clocks = state.test_clocks.lock().unwrap();
+    let Some(test_clock) = test_clocks.get_mut(&id) else {
+        // Deleted while advancing
+        return test_clock_missing(&id);
+    };
+    test_clock.status = if complete {
+        TestClockStatus::Ready
+    } else {
+        TestClockStatus::InternalFailure
+    };
+    (StatusCode::OK, Json(test_clock.clone())).into_response()
+}
+
+/// DELETE /v1/test_helpers/test_clocks/:id - Delete a test clock
+///
+/// Subscriptions on the clock are canceled and its customers are detached from it.
+pub async fn delete_test_clock(
+    Extension(state): Extension<CatalogState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    if let Err(response) = require_sandbox(&state) {
+        return response;
+    }
+
+    let Some(test_clock) = state.test_clocks.lock().unwrap().remove(&id) else {
+        return test_clock_missing(&id);
+    };
+    state
+        .customer_test_clocks
+        .lock()
+        .unwrap()
+        .retain(|_, clock| *clock != id);
+
+    if let Some(db) = state.payment_db.as_ref() {
+        match db.list_test_clock_subscriptions(&id, &state.payment_stack_id, state.use_sandbox) {
+            Ok(subscriptions) => {
+                for subscription in subscriptions {
+                    if let Err(e) =
+                        end_subscription(&state, db, &subscription, test_clock.frozen_time)
+                    {
+                        warn!(
+                            "Failed to cancel subscription {} of deleted test clock {}: {}",
+                            subscription.subscription_id, id, e
+                        );
+                    }
+                }
+            }
+            Err(e) => warn!("Failed to list subscriptions of test clock {}: {}", id, e),
+        }
+    }
+
+    let deleted = DeletedTestClock {
+        id,
+        object: "test_helpers.test_clock".to_string(),
+        deleted: true,
+    };
+    (StatusCode::OK, Json(deleted)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_clock(id: &str, frozen_time: i64) -> StripeTestClock {
+        StripeTestClock {
+            id: id.to_string(),
+            object: "test_helpers.test_clock".to_string(),
+            created: 0,
+            frozen_time,
+            livemode: false,
+            name: None,
+            status: TestClockStatus::Ready,
+        }
+    }
+
+    fn sandbox_state() -> CatalogState {
+        CatalogState::new(
+            vec![],
+            vec![],
+            true,
+            "http://localhost:8080".parse().unwrap(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+        )
+    }
+
+    #[test]
+    fn test_customer_time_follows_test_clock() {
+        let state = sandbox_state();
+        state
+            .test_clocks
+            .lock()
+            .unwrap()
+            .insert("clock_1".to_string(), test_clock("clock_1", 1_000));
+
+        assert_eq!(attach_customer(&state, "cus_1", "clock_1"), Some(1_000));
+        assert_eq!(attach_customer(&state, "cus_2", "clock_unknown"), None);
+        assert_eq!(
+            current_time(&state, Some("cus_1")),
+            (1_000, Some("clock_1".to_string()))
+        );
+
+        let (now, test_clock) = current_time(&state, Some("cus_2"));
+        assert!(now > 1_000);
+        assert_eq!(test_clock, None);
+    }
+
+    #[test]
+    fn test_advance_expires_clock_checkout_sessions_only() {
+        use crate::api::catalog::stripe::types::{
+            CheckoutLineItemList, CheckoutSessionStatus, PaymentStatus, StripeCheckoutSession,
+        };
+
+        let state = sandbox_state();
+        state
+            .test_clocks
+            .lock()
+            .unwrap()
+            .insert("clock_1".to_string(), test_clock("clock_1", 1_000));
+        attach_customer(&state, "cus_clock", "clock_1");
+
+        let session = |id: &str, customer: &str| StripeCheckoutSession {
+            id: id.to_string(),
+            object: "checkout.session".to_string(),
+            status: CheckoutSessionStatus::Open,
+            payment_status: PaymentStatus::Unpaid,
+            currency: "usd".to_string(),
+            amount_total: 100,
+            amount_subtotal: 100,
+            discounts: Vec::new(),
+            total_details: Default::default(),
+            created: 1_000,
+            expires_at: Some(2_800),
+            customer: Some(customer.to_string()),
+            customer_email: None,
+            customer_details: None,
+            payment_intent: None,
+            client_secret: None,
+            line_items: CheckoutLineItemList::default(),
+            metadata: Default::default(),
+            success_url: None,
+            cancel_url: None,
+            url: None,
+            solana_pay: None,
+            payment_link: None,
+            custom_fields: Vec::new(),
+        };
+        {
+            let mut sessions = state.checkout_sessions.lock().unwrap();
+            sessions.insert("cs_clock".to_string(), session("cs_clock", "cus_clock"));
+            sessions.insert("cs_wall".to_string(), session("cs_wall", "cus_wall"));
+        }
+
+        assert!(run_test_clock(&state, "clock_1", 2_000));
+        assert!(
+            state.checkout_sessions.lock().unwrap()["cs_clock"].status
+                == CheckoutSessionStatus::Open
+        );
+
+        assert!(run_test_clock(&state, "clock_1", 3_000));
+        let sessions = state.checkout_sessions.lock().unwrap();
+        assert!(sessions["cs_clock"].status == CheckoutSessionStatus::Expired);
+        assert!(sessions["cs_wall"].status == CheckoutSessionStatus::Open);
+    }
+}
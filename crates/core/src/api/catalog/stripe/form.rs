@@ -0,0 +1,557 @@
+//! Decoder for request bodies in Stripe's form encoding
+//!
+//! Stripe clients send `application/x-www-form-urlencoded` bodies where nested params are
+//! written with brackets: `metadata[order]=42`, `expand[]=customer` or
+//! `line_items[0][price_data][unit_amount]=500`. [`StripeForm`] decodes them into a tree
+//! that request types read their params from by the same bracketed keys. JSON bodies are
+//! decoded into the same tree, and may use camelCase keys.
+//!
+//! Missing and invalid params are reported as [`FormError`]s, which respond with a 400 in
+//! Stripe's error format, naming the param.
+
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use axum::{
+    Json,
+    extract::{FromRequestParts, Query},
+    http::{StatusCode, request::Parts},
+    response::{IntoResponse, Response},
+};
+use indexmap::IndexMap;
+use serde::de::DeserializeOwned;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum FormError {
+    #[error("Invalid request: {0}")]
+    Malformed(String),
+    #[error("Missing required param: {0}.")]
+    Missing(String),
+    #[error("{message}")]
+    Invalid { param: String, message: String },
+}
+
+impl FormError {
+    /// Param the error is about, if any
+    pub fn param(&self) -> Option<&str> {
+        match self {
+            FormError::Malformed(_) => None,
+            FormError::Missing(param) | FormError::Invalid { param, .. } => Some(param),
+        }
+    }
+}
+
+impl IntoResponse for FormError {
+    fn into_response(self) -> Response {
+        let code = match &self {
+            FormError::Missing(_) => "parameter_missing",
+            FormError::Malformed(_) | FormError::Invalid { .. } => "parameter_invalid",
+        };
+        let mut error = serde_json::json!({
+            "code": code,
+            "message": self.to_string(),
+            "type": "invalid_request_error"
+        });
+        if let Some(param) = self.param() {
+            error["param"] = param.into();
+        }
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": error })),
+        )
+            .into_response()
+    }
+}
+
+/// Query string extractor for flat params, rejecting invalid values with a [`FormError`]
+/// rather than axum's plain-text 400
+#[derive(Debug, Clone)]
+pub struct StripeQuery<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for StripeQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = FormError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Query::try_from_uri(&parts.uri)
+            .map(|Query(params)| StripeQuery(params))
+            .map_err(|e| FormError::Malformed(e.body_text()))
+    }
+}
+
+/// Decoded `key=value` pairs of a form-encoded body or query string, in order
+pub fn form_pairs(input: &[u8]) -> Result<Vec<(String, String)>, FormError> {
+    let input = std::str::from_utf8(input)
+        .map_err(|_| FormError::Malformed("it is not valid UTF-8".to_string()))?;
+    input
+        .split('&')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let (key, value) = part.split_once('=').unwrap_or((part, ""));
+            Ok((percent_decode(key)?, percent_decode(value)?))
+        })
+        .collect()
+}
+
+fn percent_decode(encoded: &str) -> Result<String, FormError> {
+    urlencoding::decode(&encoded.replace('+', " "))
+        .map(|decoded| decoded.into_owned())
+        .map_err(|_| {
+            FormError::Malformed(format!("'{}' is not valid percent-encoded UTF-8", encoded))
+        })
+}
+
+/// Segments of a bracketed key: `a[b][]` is `["a", "b", ""]`
+fn key_segments(key: &str) -> Option<Vec<&str>> {
+    let (name, mut rest) = key.split_at(key.find('[').unwrap_or(key.len()));
+    if name.is_empty() {
+        return None;
+    }
+    let mut segments = vec![name];
+    while !rest.is_empty() {
+        let (segment, after) = rest.strip_prefix('[')?.split_once(']')?;
+        segments.push(segment);
+        rest = after;
+    }
+    Some(segments)
+}
+
+/// Key of the first `depth` segments: `a[b]` for `["a", "b", "c"]` and 2
+fn segments_key(segments: &[&str], depth: usize) -> String {
+    let mut key = segments[0].to_string();
+    for segment in &segments[1..depth] {
+        key.push_str(&format!("[{}]", segment));
+    }
+    key
+}
+
+/// camelCase spelling of a snake_case key, as sent in JSON bodies
+fn camel_case(key: &str) -> String {
+    let mut camel = String::with_capacity(key.len());
+    let mut upper = false;
+    for c in key.chars() {
+        match c {
+            '_' => upper = true,
+            c if upper => {
+                camel.extend(c.to_uppercase());
+                upper = false;
+            }
+            c => camel.push(c),
+        }
+    }
+    camel
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Value(String),
+    Map(IndexMap<String, Node>),
+}
+
+fn insert(
+    fields: &mut IndexMap<String, Node>,
+    segments: &[&str],
+    value: String,
+    key: &str,
+) -> Result<(), FormError> {
+    let conflict = || FormError::Invalid {
+        param: key.to_string(),
+        message: format!("{} can't be both a value and a hash", key),
+    };
+    let Some((first, rest)) = segments.split_first() else {
+        return Ok(());
+    };
+    // `[]` appends to an array
+    let name = if first.is_empty() {
+        (fields.len()..)
+            .map(|index| index.to_string())
+            .find(|index| !fields.contains_key(index))
+            .unwrap_or_default()
+    } else {
+        first.to_string()
+    };
+
+    if rest.is_empty() {
+        if let Some(Node::Map(_)) = fields.get(&name) {
+            return Err(conflict());
+        }
+        fields.insert(name, Node::Value(value));
+        return Ok(());
+    }
+    match fields
+        .entry(name)
+        .or_insert_with(|| Node::Map(IndexMap::new()))
+    {
+        Node::Map(children) => insert(children, rest, value, key),
+        Node::Value(_) => Err(conflict()),
+    }
+}
+
+fn json_node(value: serde_json::Value) -> Option<Node> {
+    use serde_json::Value;
+    match value {
+        Value::Null => None,
+        Value::Bool(value) => Some(Node::Value(value.to_string())),
+        Value::Number(value) => Some(Node::Value(value.to_string())),
+        Value::String(value) => Some(Node::Value(value)),
+        Value::Array(items) => Some(Node::Map(
+            items
+                .into_iter()
+                .filter_map(json_node)
+                .enumerate()
+                .map(|(index, node)| (index.to_string(), node))
+                .collect(),
+        )),
+        Value::Object(entries) => Some(Node::Map(
+            entries
+                .into_iter()
+                .filter_map(|(key, value)| Some((key, json_node(value)?)))
+                .collect(),
+        )),
+    }
+}
+
+/// Decoded request body, or one of its nested objects
+///
+/// Params are read by bracketed key (`customer_details[address][country]`). Empty strings
+/// read as unset, except through [`StripeForm::string`] where they clear a field.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StripeForm {
+    /// Param path of these fields, empty for the body itself
+    path: String,
+    fields: IndexMap<String, Node>,
+}
+
+impl StripeForm {
+    /// Decode a form-encoded or JSON body
+    pub fn decode(body: &[u8]) -> Result<Self, FormError> {
+        if body.trim_ascii_start().starts_with(b"{") {
+            let value = serde_json::from_slice::<serde_json::Value>(body)
+                .map_err(|e| FormError::Malformed(format!("invalid JSON: {}", e)))?;
+            let Some(Node::Map(fields)) = json_node(value) else {
+                return Err(FormError::Malformed("expected a JSON object".to_string()));
+            };
+            return Ok(Self {
+                path: String::new(),
+                fields,
+            });
+        }
+
+        let mut fields = IndexMap::new();
+        for (key, value) in form_pairs(body)? {
+            let segments = key_segments(&key)
+                .ok_or_else(|| FormError::Malformed(format!("invalid param name '{}'", key)))?;
+            insert(&mut fields, &segments, value, &key)?;
+        }
+        Ok(Self {
+            path: String::new(),
+            fields,
+        })
+    }
+
+    /// Full param path of `key`, e.g. `items[0][price]` for `price` in `items[0]`
+    pub fn param(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            return key.to_string();
+        }
+        let (name, rest) = key.split_at(key.find('[').unwrap_or(key.len()));
+        format!("{}[{}]{}", self.path, name, rest)
+    }
+
+    /// Error for a missing required param
+    pub fn missing(&self, key: &str) -> FormError {
+        FormError::Missing(self.param(key))
+    }
+
+    /// Error for an invalid param
+    pub fn invalid(&self, key: &str, message: impl Into<String>) -> FormError {
+        FormError::Invalid {
+            param: self.param(key),
+            message: message.into(),
+        }
+    }
+
+    fn node(&self, key: &str) -> Result<Option<&Node>, FormError> {
+        let segments = key_segments(key)
+            .ok_or_else(|| self.invalid(key, format!("Invalid param name: {}", key)))?;
+        let mut fields = &self.fields;
+        let mut node = None;
+        for (depth, segment) in segments.iter().enumerate() {
+            if let Some(Node::Value(value)) = node {
+                // An empty string unsets the whole hash
+                if value.is_empty() {
+                    return Ok(None);
+                }
+                let parent = segments_key(&segments, depth);
+                return Err(self.invalid(&parent, format!("Invalid hash: {}", parent)));
+            }
+            node = fields
+                .get(*segment)
+                .or_else(|| fields.get(&camel_case(segment)));
+            match node {
+                None => return Ok(None),
+                Some(Node::Map(children)) => fields = children,
+                Some(Node::Value(_)) => {}
+            }
+        }
+        Ok(node)
+    }
+
+    /// Whether `key` was sent, even empty
+    pub fn contains(&self, key: &str) -> bool {
+        matches!(self.node(key), Ok(Some(_)))
+    }
+
+    /// String param, empty when it is being unset
+    pub fn string(&self, key: &str) -> Result<Option<String>, FormError> {
+        match self.node(key)? {
+            None => Ok(None),
+            Some(Node::Value(value)) => Ok(Some(value.clone())),
+            Some(Node::Map(_)) => Err(self.invalid(key, format!("Invalid string: {}", key))),
+        }
+    }
+
+    /// Non-empty string param
+    pub fn required_string(&self, key: &str) -> Result<String, FormError> {
+        self.string(key)?
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| self.missing(key))
+    }
+
+    /// Param parsed with [`FromStr`], whose error is the message
+    pub fn parse<T>(&self, key: &str) -> Result<Option<T>, FormError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.string(key)? {
+            Some(value) if !value.is_empty() => value
+                .parse()
+                .map(Some)
+                .map_err(|e| self.invalid(key, e.to_string())),
+            _ => Ok(None),
+        }
+    }
+
+    fn parse_as<T: FromStr>(&self, key: &str, kind: &str) -> Result<Option<T>, FormError> {
+        match self.string(key)? {
+            Some(value) if !value.is_empty() => value
+                .parse()
+                .map(Some)
+                .map_err(|_| self.invalid(key, format!("Invalid {}: {}", kind, value))),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn integer(&self, key: &str) -> Result<Option<i64>, FormError> {
+        self.parse_as(key, "integer")
+    }
+
+    pub fn decimal(&self, key: &str) -> Result<Option<f64>, FormError> {
+        match self.parse_as::<f64>(key, "decimal")? {
+            Some(value) if !value.is_finite() => {
+                Err(self.invalid(key, format!("Invalid decimal: {}", value)))
+            }
+            value => Ok(value),
+        }
+    }
+
+    /// `true` or `false`
+    pub fn boolean(&self, key: &str) -> Result<Option<bool>, FormError> {
+        self.parse_as(key, "boolean")
+    }
+
+    /// String values of a hash param such as `metadata[key]=value`
+    pub fn map(&self, key: &str) -> Result<HashMap<String, String>, FormError> {
+        match self.node(key)? {
+            None => Ok(HashMap::new()),
+            Some(Node::Value(value)) if value.is_empty() => Ok(HashMap::new()),
+            Some(Node::Value(_)) => Err(self.invalid(key, format!("Invalid hash: {}", key))),
+            Some(Node::Map(entries)) => entries
+                .iter()
+                .map(|(name, value)| match value {
+                    Node::Value(value) => Ok((name.clone(), value.clone())),
+                    Node::Map(_) => {
+                        let key = format!("{}[{}]", key, name);
+                        Err(self.invalid(&key, format!("Invalid string: {}", key)))
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Entries of an array param, in index order
+    fn array(&self, key: &str) -> Result<Vec<(usize, &Node)>, FormError> {
+        let invalid_array = || self.invalid(key, format!("Invalid array: {}", key));
+        match self.node(key)? {
+            None => Ok(Vec::new()),
+            Some(Node::Value(value)) if value.is_empty() => Ok(Vec::new()),
+            Some(Node::Value(_)) => Err(invalid_array()),
+            Some(Node::Map(entries)) => {
+                let mut items = entries
+                    .iter()
+                    .map(|(index, node)| {
+                        index
+                            .parse::<usize>()
+                            .map(|index| (index, node))
+                            .map_err(|_| invalid_array())
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                items.sort_by_key(|(index, _)| *index);
+                Ok(items)
+            }
+        }
+    }
+
+    /// Strings of an array param (`wallets[]=...` or `wallets[0]=...`)
+    pub fn strings(&self, key: &str) -> Result<Vec<String>, FormError> {
+        self.array(key)?
+            .into_iter()
+            .map(|(index, node)| match node {
+                Node::Value(value) => Ok(value.clone()),
+                Node::Map(_) => {
+                    let key = format!("{}[{}]", key, index);
+                    Err(self.invalid(&key, format!("Invalid string: {}", key)))
+                }
+            })
+            .collect()
+    }
+
+    /// Hash param holding an object (`price_data[currency]=...`), read with its own param
+    /// paths
+    pub fn object(&self, key: &str) -> Result<Option<StripeForm>, FormError> {
+        match self.node(key)? {
+            None => Ok(None),
+            Some(Node::Value(value)) if value.is_empty() => Ok(None),
+            Some(Node::Value(_)) => Err(self.invalid(key, format!("Invalid hash: {}", key))),
+            Some(Node::Map(fields)) => Ok(Some(StripeForm {
+                path: self.param(key),
+                fields: fields.clone(),
+            })),
+        }
+    }
+
+    /// Objects of an array param (`items[0][price]=...`), read with their own param paths
+    pub fn list(&self, key: &str) -> Result<Vec<StripeForm>, FormError> {
+        self.array(key)?
+            .into_iter()
+            .map(|(index, node)| {
+                let key = format!("{}[{}]", key, index);
+                match node {
+                    Node::Map(fields) => Ok(StripeForm {
+                        path: self.param(&key),
+                        fields: fields.clone(),
+                    }),
+                    Node::Value(_) => Err(self.invalid(&key, format!("Invalid hash: {}", key))),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_nested_params() {
+        let form = StripeForm::decode(
+            b"customer=cus_1&metadata%5Border%5D=42&metadata[note]=a+b%26c\
+              &items[0][price]=price_a&items[1][price]=price_b&items[1][quantity]=2\
+              &wallets[]=w1&wallets[]=w2\
+              &line_items[0][price_data][unit_amount]=500&description=",
+        )
+        .unwrap();
+
+        assert_eq!(form.string("customer"), Ok(Some("cus_1".to_string())));
+        assert_eq!(form.string("description"), Ok(Some(String::new())));
+        assert_eq!(form.string("name"), Ok(None));
+        let metadata = form.map("metadata").unwrap();
+        assert_eq!(metadata.get("order").map(String::as_str), Some("42"));
+        assert_eq!(metadata.get("note").map(String::as_str), Some("a b&c"));
+        assert_eq!(
+            form.strings("wallets"),
+            Ok(vec!["w1".to_string(), "w2".to_string()])
+        );
+
+        let items = form.list("items").unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].string("price"), Ok(Some("price_b".to_string())));
+        assert_eq!(items[1].integer("quantity"), Ok(Some(2)));
+        assert_eq!(items[0].integer("quantity"), Ok(None));
+        assert_eq!(items[0].param("quantity"), "items[0][quantity]");
+
+        let line_items = form.list("line_items").unwrap();
+        assert_eq!(
+            line_items[0].integer("price_data[unit_amount]"),
+            Ok(Some(500))
+        );
+        let price_data = line_items[0].object("price_data").unwrap().unwrap();
+        assert_eq!(price_data.integer("unit_amount"), Ok(Some(500)));
+        assert_eq!(
+            price_data.param("unit_amount"),
+            "line_items[0][price_data][unit_amount]"
+        );
+        assert_eq!(form.object("price_data"), Ok(None));
+        assert!(form.object("customer").is_err());
+    }
+
+    #[test]
+    fn test_decode_json_body() {
+        let form = StripeForm::decode(
+            br#"{"amount": 1000, "confirm": true, "paymentMethod": "pm_1",
+                "metadata": {"order": 42}, "lineItems": [{"productId": "prod_1"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(form.integer("amount"), Ok(Some(1000)));
+        assert_eq!(form.boolean("confirm"), Ok(Some(true)));
+        assert_eq!(form.string("payment_method"), Ok(Some("pm_1".to_string())));
+        assert_eq!(
+            form.map("metadata")
+                .unwrap()
+                .get("order")
+                .map(String::as_str),
+            Some("42")
+        );
+        let line_items = form.list("line_items").unwrap();
+        assert_eq!(
+            line_items[0].string("product_id"),
+            Ok(Some("prod_1".to_string()))
+        );
+
+        assert!(matches!(
+            StripeForm::decode(b"{\"amount\": "),
+            Err(FormError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        let form = StripeForm::decode(b"amount=ten&confirm=yes&items[0][quantity]=x&metadata=abc")
+            .unwrap();
+        assert_eq!(
+            form.integer("amount"),
+            Err(FormError::Invalid {
+                param: "amount".to_string(),
+                message: "Invalid integer: ten".to_string(),
+            })
+        );
+        assert!(form.boolean("confirm").is_err());
+        assert!(form.map("metadata").is_err());
+        let items = form.list("items").unwrap();
+        assert_eq!(
+            items[0].integer("quantity").unwrap_err().param(),
+            Some("items[0][quantity]")
+        );
+        assert_eq!(
+            form.required_string("currency"),
+            Err(FormError::Missing("currency".to_string()))
+        );
+
+        assert!(StripeForm::decode(b"a=1&a[b]=2").is_err());
+        assert!(StripeForm::decode(b"a[b=1").is_err());
+        assert!(StripeForm::decode(b"%FF=1").is_err());
+    }
+}
@@ -1,12 +1,17 @@
 use anyhow::Result;
 use indexmap::IndexMap;
 use moneymq_types::{
-    Price as MoneymqPrice,
-    iac::{Currency as MoneymqCurrency, PricingType, RecurringInterval},
+    Decimal, Price as MoneymqPrice, PriceTier as MoneymqPriceTier,
+    iac::{
+        Currency as MoneymqCurrency, PricingType, QuantityRounding, RecurringInterval, TaxBehavior,
+        TiersMode, TransformQuantity,
+    },
 };
 use stripe::{
-    Client, CreatePrice, CreatePriceRecurring, CreatePriceRecurringInterval, Currency, ListPrices,
-    Price as StripePrice, PriceId, ProductId,
+    Client, CreatePrice, CreatePriceRecurring, CreatePriceRecurringInterval, CreatePriceTiers,
+    CreatePriceTransformQuantity, CreatePriceTransformQuantityRound, Currency, ListPrices,
+    Price as StripePrice, PriceBillingScheme, PriceId, PriceTaxBehavior, PriceTiersMode, ProductId,
+    TransformQuantityRound, UpTo, UpToOther,
 };
 
 use super::{
@@ -33,6 +38,24 @@ fn stripe_currency_to_moneymq(currency_str: &str) -> MoneymqCurrency {
     }
 }
 
+/// Convert the tiers of a Stripe price, in its currency, to MoneyMQ tiers
+fn stripe_tiers_to_moneymq(tiers: Vec<stripe::PriceTier>, currency: &str) -> Vec<MoneymqPriceTier> {
+    let amount = |amount: Option<i64>| -> IndexMap<String, i64> {
+        amount
+            .map(|amount| (currency.to_string(), amount))
+            .into_iter()
+            .collect()
+    };
+    tiers
+        .into_iter()
+        .map(|tier| MoneymqPriceTier {
+            up_to: tier.up_to,
+            unit_amounts: amount(tier.unit_amount),
+            flat_amounts: amount(tier.flat_amount),
+        })
+        .collect()
+}
+
 /// Convert Stripe Price to MoneyMQ Price
 pub fn convert_price(stripe_price: StripePrice, is_production: bool) -> MoneymqPrice {
     let created_at = timestamp_to_datetime(stripe_price.created);
@@ -54,6 +77,37 @@ pub fn convert_price(stripe_price: StripePrice, is_production: bool) -> MoneymqP
     let currency =
         stripe_currency_to_moneymq(&stripe_price.currency.unwrap_or_default().to_string());
 
+    // Tiers are only listed when expanded, see `fetch_product_prices`
+    let tiered = stripe_price.billing_scheme == Some(PriceBillingScheme::Tiered);
+    let tiers = if tiered {
+        stripe_tiers_to_moneymq(stripe_price.tiers.unwrap_or_default(), currency.as_str())
+    } else {
+        Vec::new()
+    };
+    let tiers_mode =
+        stripe_price
+            .tiers_mode
+            .filter(|_| tiered)
+            .map(|tiers_mode| match tiers_mode {
+                PriceTiersMode::Graduated => TiersMode::Graduated,
+                PriceTiersMode::Volume => TiersMode::Volume,
+            });
+    let transform_quantity = stripe_price
+        .transform_quantity
+        .map(|transform| TransformQuantity {
+            divide_by: transform.divide_by,
+            round: match transform.round {
+                TransformQuantityRound::Down => QuantityRounding::Down,
+                TransformQuantityRound::Up => QuantityRounding::Up,
+            },
+        });
+    // Sub-cent amounts only come through the decimal field
+    let unit_amount_decimal = stripe_price
+        .unit_amount_decimal
+        .filter(|_| stripe_price.unit_amount.is_none())
+        .and_then(|amount| amount.parse::<Decimal>().ok())
+        .map(|amount| amount.normalize());
+
     let (deployed_id, sandboxes) = if is_production {
         (Some(stripe_id), IndexMap::new())
     } else {
@@ -70,10 +124,23 @@ pub fn convert_price(stripe_price: StripePrice, is_production: bool) -> MoneymqP
         active: stripe_price.active.unwrap_or(true),
         currency,
         unit_amount: stripe_price.unit_amount,
+        unit_amount_decimal,
+        currency_options: IndexMap::new(),
         pricing_type,
         recurring_interval,
         recurring_interval_count,
         nickname: stripe_price.nickname,
+        overage: None,
+        tiers,
+        tiers_mode,
+        transform_quantity,
+        tax_behavior: stripe_price
+            .tax_behavior
+            .and_then(|tax_behavior| match tax_behavior {
+                PriceTaxBehavior::Exclusive => Some(TaxBehavior::Exclusive),
+                PriceTaxBehavior::Inclusive => Some(TaxBehavior::Inclusive),
+                PriceTaxBehavior::Unspecified => None,
+            }),
         metadata: metadata_to_sorted_indexmap(stripe_price.metadata.unwrap_or_default()),
         created_at,
     }
@@ -92,6 +159,7 @@ pub async fn fetch_product_prices(
         let mut price_params = ListPrices::new();
         price_params.product = Some(stripe::IdOrCreate::Id(product_id));
         price_params.limit = Some(100);
+        price_params.expand = &["data.tiers"];
 
         if let Some(ref last_id) = price_starting_after {
             price_params.starting_after = Some(last_id.clone());
@@ -164,6 +232,47 @@ pub async fn create_price(
     if let Some(amount) = local_price.unit_amount {
         params.unit_amount = Some(amount);
     }
+    let unit_amount_decimal = local_price
+        .unit_amount_decimal
+        .map(|amount| amount.to_string());
+    params.unit_amount_decimal = unit_amount_decimal.as_deref();
+
+    // Set tiers, in the price's currency, charged instead of a unit amount
+    if local_price.is_tiered() {
+        let currency = local_price.currency.as_str();
+        params.billing_scheme = Some(PriceBillingScheme::Tiered);
+        params.tiers_mode = local_price.tiers_mode.map(|tiers_mode| match tiers_mode {
+            TiersMode::Graduated => PriceTiersMode::Graduated,
+            TiersMode::Volume => PriceTiersMode::Volume,
+        });
+        params.tiers = Some(
+            local_price
+                .tiers
+                .iter()
+                .map(|tier| CreatePriceTiers {
+                    up_to: Some(match tier.up_to {
+                        Some(up_to) => UpTo::Max(up_to as u64),
+                        None => UpTo::Inf(UpToOther::Inf),
+                    }),
+                    unit_amount: tier.unit_amounts.get(currency).copied(),
+                    flat_amount: tier.flat_amounts.get(currency).copied(),
+                    ..Default::default()
+                })
+                .collect(),
+        );
+    }
+
+    // Set package pricing
+    params.transform_quantity =
+        local_price
+            .transform_quantity
+            .map(|transform| CreatePriceTransformQuantity {
+                divide_by: transform.divide_by,
+                round: match transform.round {
+                    QuantityRounding::Down => CreatePriceTransformQuantityRound::Down,
+                    QuantityRounding::Up => CreatePriceTransformQuantityRound::Up,
+                },
+            });
 
     // Set recurring interval if this is a recurring price
     if let Some(interval) = &local_price.recurring_interval {
@@ -184,6 +293,14 @@ pub async fn create_price(
         params.recurring = Some(recurring);
     }
 
+    // Set whether the amount includes tax
+    params.tax_behavior = local_price
+        .tax_behavior
+        .map(|tax_behavior| match tax_behavior {
+            TaxBehavior::Exclusive => PriceTaxBehavior::Exclusive,
+            TaxBehavior::Inclusive => PriceTaxBehavior::Inclusive,
+        });
+
     // Set active status
     params.active = Some(local_price.active);
 
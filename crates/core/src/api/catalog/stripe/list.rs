@@ -0,0 +1,313 @@
+//! Query parameters shared by the catalog's list and retrieve endpoints
+//!
+//! List endpoints take Stripe's `limit`, `starting_after` / `ending_before` cursors,
+//! `created` ranges (`created=<ts>` or `created[gte]=<ts>`, with `gt`, `gte`, `lt` and `lte`)
+//! and `expand[]`, along with their own filters (`active=true`, `product=prod_...`, ...).
+//! Retrieve endpoints take `expand[]`. Invalid values are rejected with a 400, like Stripe.
+
+use std::collections::HashMap;
+
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+
+use crate::api::catalog::stripe::form::{FormError, form_pairs};
+
+/// Page size when `limit` is not set
+const DEFAULT_LIMIT: usize = 10;
+
+/// Largest page size
+const MAX_LIMIT: usize = 100;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum CatalogQueryError {
+    #[error("Invalid {param}: {message}")]
+    InvalidParameter { param: String, message: String },
+    #[error("No such {object}: '{id}'")]
+    CursorMissing {
+        param: String,
+        object: String,
+        id: String,
+    },
+    #[error("This property cannot be expanded ({0}).")]
+    NotExpandable(String),
+    #[error(transparent)]
+    Form(#[from] FormError),
+}
+
+impl IntoResponse for CatalogQueryError {
+    fn into_response(self) -> Response {
+        let (code, param) = match &self {
+            CatalogQueryError::InvalidParameter { param, .. } => ("parameter_invalid", param),
+            CatalogQueryError::CursorMissing { param, .. } => ("resource_missing", param),
+            CatalogQueryError::NotExpandable(path) => ("parameter_invalid", path),
+            CatalogQueryError::Form(e) => return e.clone().into_response(),
+        };
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": {
+                    "code": code,
+                    "message": self.to_string(),
+                    "param": param,
+                    "type": "invalid_request_error"
+                }
+            })),
+        )
+            .into_response()
+    }
+}
+
+/// Bounds on a Unix timestamp, from `created[gt]`, `created[gte]`, `created[lt]` and
+/// `created[lte]`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampRange {
+    pub gt: Option<i64>,
+    pub gte: Option<i64>,
+    pub lt: Option<i64>,
+    pub lte: Option<i64>,
+}
+
+impl TimestampRange {
+    pub fn contains(&self, timestamp: i64) -> bool {
+        self.gt.is_none_or(|gt| timestamp > gt)
+            && self.gte.is_none_or(|gte| timestamp >= gte)
+            && self.lt.is_none_or(|lt| timestamp < lt)
+            && self.lte.is_none_or(|lte| timestamp <= lte)
+    }
+}
+
+/// Decoded query string of a list or retrieve request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogQuery {
+    pub limit: usize,
+    pub starting_after: Option<String>,
+    pub ending_before: Option<String>,
+    pub created: TimestampRange,
+    /// Paths of the related objects to expand, e.g. `data.default_price`
+    pub expand: Vec<String>,
+    /// Endpoint-specific filters
+    params: HashMap<String, String>,
+}
+
+fn invalid(param: &str, message: impl Into<String>) -> CatalogQueryError {
+    CatalogQueryError::InvalidParameter {
+        param: param.to_string(),
+        message: message.into(),
+    }
+}
+
+fn parse_timestamp(param: &str, value: &str) -> Result<i64, CatalogQueryError> {
+    value
+        .parse()
+        .map_err(|_| invalid(param, format!("'{}' is not a Unix timestamp", value)))
+}
+
+impl CatalogQuery {
+    /// Decode a query string, accepting only the `expandable` paths in `expand[]`
+    pub fn parse(query: Option<&str>, expandable: &[&str]) -> Result<Self, CatalogQueryError> {
+        let mut parsed = Self {
+            limit: DEFAULT_LIMIT,
+            starting_after: None,
+            ending_before: None,
+            created: TimestampRange::default(),
+            expand: Vec::new(),
+            params: HashMap::new(),
+        };
+        let pairs = match query {
+            Some(query) => form_pairs(query.as_bytes())?,
+            None => Vec::new(),
+        };
+
+        for (key, value) in pairs {
+            match key.as_str() {
+                "limit" => {
+                    let limit = value
+                        .parse::<i64>()
+                        .map_err(|_| invalid("limit", format!("'{}' is not an integer", value)))?;
+                    parsed.limit = limit.clamp(1, MAX_LIMIT as i64) as usize;
+                }
+                "starting_after" => parsed.starting_after = Some(value).filter(|v| !v.is_empty()),
+                "ending_before" => parsed.ending_before = Some(value).filter(|v| !v.is_empty()),
+                "created" => {
+                    let created = parse_timestamp("created", &value)?;
+                    parsed.created.gte = Some(created);
+                    parsed.created.lte = Some(created);
+                }
+                "created[gt]" => parsed.created.gt = Some(parse_timestamp(&key, &value)?),
+                "created[gte]" => parsed.created.gte = Some(parse_timestamp(&key, &value)?),
+                "created[lt]" => parsed.created.lt = Some(parse_timestamp(&key, &value)?),
+                "created[lte]" => parsed.created.lte = Some(parse_timestamp(&key, &value)?),
+                _ if key == "expand" || key.starts_with("expand[") => {
+                    if !expandable.contains(&value.as_str()) {
+                        return Err(CatalogQueryError::NotExpandable(value));
+                    }
+                    parsed.expand.push(value);
+                }
+                _ => {
+                    parsed.params.insert(key, value);
+                }
+            }
+        }
+
+        if parsed.starting_after.is_some() && parsed.ending_before.is_some() {
+            return Err(invalid(
+                "ending_before",
+                "starting_after and ending_before can't be used together",
+            ));
+        }
+        Ok(parsed)
+    }
+
+    /// Value of an endpoint-specific filter
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params.get(key).map(String::as_str)
+    }
+
+    /// Value of a boolean filter such as `active`
+    pub fn bool_param(&self, key: &str) -> Result<Option<bool>, CatalogQueryError> {
+        match self.param(key) {
+            None => Ok(None),
+            Some("true") => Ok(Some(true)),
+            Some("false") => Ok(Some(false)),
+            Some(value) => Err(invalid(key, format!("'{}' is not a boolean", value))),
+        }
+    }
+
+    /// Whether `path` was requested in `expand[]`
+    pub fn expands(&self, path: &str) -> bool {
+        self.expand.iter().any(|expand| expand == path)
+    }
+
+    /// Page of `items` (in list order) after `starting_after` or before `ending_before`,
+    /// and whether there are more items in that direction.
+    ///
+    /// `object` names the listed objects in the error for an unknown cursor.
+    pub fn paginate<T>(
+        &self,
+        mut items: Vec<T>,
+        object: &str,
+        id: impl Fn(&T) -> &str,
+    ) -> Result<(Vec<T>, bool), CatalogQueryError> {
+        let position = |param: &str, cursor: &str, items: &[T]| {
+            items
+                .iter()
+                .position(|item| id(item) == cursor)
+                .ok_or_else(|| CatalogQueryError::CursorMissing {
+                    param: param.to_string(),
+                    object: object.to_string(),
+                    id: cursor.to_string(),
+                })
+        };
+
+        if let Some(cursor) = &self.ending_before {
+            let end = position("ending_before", cursor, &items)?;
+            items.truncate(end);
+            let has_more = items.len() > self.limit;
+            let page = items.split_off(items.len().saturating_sub(self.limit));
+            return Ok((page, has_more));
+        }
+
+        let start = match &self.starting_after {
+            Some(cursor) => position("starting_after", cursor, &items)? + 1,
+            None => 0,
+        };
+        let mut page = items.split_off(start);
+        let has_more = page.len() > self.limit;
+        page.truncate(self.limit);
+        Ok((page, has_more))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(page: &[&str]) -> Vec<String> {
+        page.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_catalog_query() {
+        let query = CatalogQuery::parse(
+            Some(
+                "limit=2&active=true&product=prod_1&created%5Bgte%5D=100&created[lt]=200\
+                 &expand%5B%5D=data.default_price",
+            ),
+            &["data.default_price"],
+        )
+        .unwrap();
+        assert_eq!(query.limit, 2);
+        assert_eq!(query.bool_param("active"), Ok(Some(true)));
+        assert_eq!(query.param("product"), Some("prod_1"));
+        assert!(query.created.contains(100) && query.created.contains(199));
+        assert!(!query.created.contains(99) && !query.created.contains(200));
+        assert!(query.expands("data.default_price"));
+
+        let default = CatalogQuery::parse(None, &[]).unwrap();
+        assert_eq!(default.limit, DEFAULT_LIMIT);
+        assert_eq!(default.bool_param("active"), Ok(None));
+        assert_eq!(
+            CatalogQuery::parse(Some("limit=500"), &[]).unwrap().limit,
+            MAX_LIMIT
+        );
+
+        assert!(matches!(
+            CatalogQuery::parse(Some("limit=ten"), &[]),
+            Err(CatalogQueryError::InvalidParameter { .. })
+        ));
+        assert!(matches!(
+            CatalogQuery::parse(Some("created[gte]=yesterday"), &[]),
+            Err(CatalogQueryError::InvalidParameter { .. })
+        ));
+        assert_eq!(
+            CatalogQuery::parse(Some("expand[]=data.product"), &["data.default_price"]),
+            Err(CatalogQueryError::NotExpandable("data.product".to_string()))
+        );
+        assert!(CatalogQuery::parse(Some("starting_after=a&ending_before=b"), &[]).is_err());
+        assert!(
+            CatalogQuery::parse(Some("active=yes"), &[])
+                .unwrap()
+                .bool_param("active")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_paginate() {
+        let items = ids(&["a", "b", "c", "d", "e"]);
+        let paginate = |query: &str| {
+            CatalogQuery::parse(Some(query), &[])
+                .unwrap()
+                .paginate(items.clone(), "item", |s| s.as_str())
+        };
+
+        assert_eq!(paginate("limit=2"), Ok((ids(&["a", "b"]), true)));
+        assert_eq!(
+            paginate("limit=2&starting_after=b"),
+            Ok((ids(&["c", "d"]), true))
+        );
+        assert_eq!(
+            paginate("limit=2&starting_after=c"),
+            Ok((ids(&["d", "e"]), false))
+        );
+        assert_eq!(
+            paginate("limit=2&ending_before=d"),
+            Ok((ids(&["b", "c"]), true))
+        );
+        assert_eq!(
+            paginate("limit=2&ending_before=c"),
+            Ok((ids(&["a", "b"]), false))
+        );
+        assert_eq!(
+            paginate("starting_after=z"),
+            Err(CatalogQueryError::CursorMissing {
+                param: "starting_after".to_string(),
+                object: "item".to_string(),
+                id: "z".to_string(),
+            })
+        );
+    }
+}
@@ -1,13 +1,27 @@
 pub mod endpoints;
+pub mod form;
 pub mod iac;
+pub mod list;
 pub mod types;
 pub mod utils;
 
 // Re-export handlers for convenience
 pub use endpoints::{
-    attach_payment_method, cancel_payment_intent, confirm_payment_intent, create_checkout_session,
-    create_customer, create_meter_event, create_payment_intent, create_payment_method,
-    create_subscription, expire_checkout_session, get_product_access,
-    list_checkout_session_line_items, list_meters, list_prices, list_products,
-    retrieve_checkout_session, retrieve_payment_intent, update_customer,
+    advance_test_clock, attach_customer_wallet, attach_payment_method, cancel_payment_intent,
+    cancel_subscription, capture_payment_intent, confirm_payment_intent, create_checkout_session,
+    create_customer, create_invoice, create_invoice_item, create_meter_event,
+    create_payment_intent, create_payment_link, create_payment_method, create_price,
+    create_product, create_subscription, create_tax_calculation, create_test_clock,
+    delete_customer, delete_invoice_item, delete_test_clock, detach_customer_wallet,
+    expire_checkout_session, finalize_invoice, get_product_access, list_active_entitlements,
+    list_checkout_session_line_items, list_coupons, list_customers, list_entitlement_features,
+    list_events, list_invoice_items, list_invoices, list_meter_event_summaries, list_meters,
+    list_payment_link_line_items, list_payment_links, list_prices, list_products,
+    list_promotion_codes, list_subscriptions, list_test_clocks, pause_subscription, pay_invoice,
+    payment_link_stats, resume_subscription, retrieve_checkout_session, retrieve_coupon,
+    retrieve_customer, retrieve_entitlement_feature, retrieve_event, retrieve_invoice,
+    retrieve_invoice_item, retrieve_meter, retrieve_payment_intent, retrieve_payment_link,
+    retrieve_price, retrieve_product, retrieve_promotion_code, retrieve_subscription,
+    retrieve_test_clock, search_customers, update_customer, update_invoice, update_payment_link,
+    update_price, update_product, update_subscription, void_invoice,
 };
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use moneymq_types::Meter;
 use serde::Serialize;
 
@@ -53,6 +55,23 @@ pub struct StripeMeterEvent {
     pub created: i64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub identifier: Option<String>,
+    /// When the usage occurred (Unix seconds)
+    pub timestamp: i64,
+    #[serde(default)]
+    pub payload: HashMap<String, String>,
+}
+
+/// Stripe-compatible meter event summary: a customer's aggregated usage over a window
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripeMeterEventSummary {
+    pub id: String,
+    pub object: String,
+    pub aggregated_value: f64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub livemode: bool,
+    pub meter: String,
 }
 
 impl StripeBillingMeter {
@@ -2,6 +2,9 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
+use super::{AppliedDiscount, SolanaPayTransfer, TaxAddress, TaxCustomerDetails, TotalDetails};
+use crate::api::catalog::stripe::form::{FormError, StripeForm};
+
 /// Stripe-compatible checkout session response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -13,6 +16,11 @@ pub struct StripeCheckoutSession {
     pub currency: String,
     pub amount_total: i64,
     pub amount_subtotal: i64,
+    /// Coupon applied to the session
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub discounts: Vec<AppliedDiscount>,
+    #[serde(default)]
+    pub total_details: TotalDetails,
     pub created: i64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expires_at: Option<i64>,
@@ -20,6 +28,9 @@ pub struct StripeCheckoutSession {
     pub customer: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub customer_email: Option<String>,
+    /// Address the session is taxed for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub customer_details: Option<TaxCustomerDetails>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub payment_intent: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -31,6 +42,32 @@ pub struct StripeCheckoutSession {
     pub success_url: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cancel_url: Option<String>,
+    /// Hosted checkout page of the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Solana Pay transfer request of the session's payment intent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solana_pay: Option<SolanaPayTransfer>,
+    /// Payment link the session was opened from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_link: Option<String>,
+    /// Values the buyer entered in the payment link's custom fields
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_fields: Vec<CheckoutCustomField>,
+}
+
+/// Value of a payment link's custom field, entered by the buyer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckoutCustomField {
+    pub key: String,
+    pub label: String,
+    /// text, numeric or dropdown
+    #[serde(rename = "type")]
+    pub field_type: String,
+    pub optional: bool,
+    /// None when an optional field was left empty
+    pub value: Option<String>,
 }
 
 /// Checkout session status
@@ -108,25 +145,102 @@ pub struct CheckoutLineItemPrice {
     pub nickname: Option<String>,
     #[serde(rename = "type")]
     pub price_type: String,
+    /// exclusive or inclusive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_behavior: Option<String>,
 }
 
 /// Create checkout session request
-#[derive(Debug, Clone, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone)]
 pub struct CreateCheckoutSessionRequest {
     pub line_items: Vec<CreateLineItem>,
-    #[serde(default)]
     pub customer: Option<String>,
-    #[serde(default)]
     pub customer_email: Option<String>,
-    #[serde(default)]
+    /// Address to charge tax for, untaxed when missing
+    pub customer_details: Option<TaxCustomerDetails>,
     pub success_url: Option<String>,
-    #[serde(default)]
     pub cancel_url: Option<String>,
-    #[serde(default)]
     pub metadata: HashMap<String, String>,
-    #[serde(default = "default_mode")]
     pub mode: String,
+    /// Currency the buyer pays in, for prices with an amount in it
+    pub currency: Option<String>,
+    /// Coupon or promotion code to apply (one at most)
+    pub discounts: Vec<AppliedDiscount>,
+    /// Payment link the session is opened from
+    pub payment_link: Option<String>,
+    /// Values entered in the payment link's custom fields
+    pub custom_fields: Vec<CheckoutCustomField>,
+}
+
+impl CreateCheckoutSessionRequest {
+    pub fn parse(body: &[u8]) -> Result<Self, FormError> {
+        let form = StripeForm::decode(body)?;
+        let line_items = form
+            .list("line_items")?
+            .iter()
+            .map(|item| {
+                let quantity = item.integer("quantity")?.unwrap_or_else(default_quantity);
+                if quantity < 1 {
+                    return Err(item.invalid("quantity", "quantity must be at least 1"));
+                }
+                Ok(CreateLineItem {
+                    product_id: item.required_string("product_id")?,
+                    experiment_id: item.string("experiment_id")?.filter(|id| !id.is_empty()),
+                    price: item.string("price")?.filter(|id| !id.is_empty()),
+                    quantity,
+                })
+            })
+            .collect::<Result<Vec<_>, FormError>>()?;
+        if line_items.is_empty() {
+            return Err(form.missing("line_items"));
+        }
+
+        let customer_details = if form.contains("customer_details") {
+            Some(TaxCustomerDetails {
+                address: TaxAddress {
+                    country: form.required_string("customer_details[address][country]")?,
+                    state: form.string("customer_details[address][state]")?,
+                    postal_code: form.string("customer_details[address][postal_code]")?,
+                },
+                address_source: form
+                    .string("customer_details[address_source]")?
+                    .unwrap_or_default(),
+            })
+        } else {
+            None
+        };
+        let discounts = form
+            .list("discounts")?
+            .iter()
+            .map(|discount| {
+                Ok(AppliedDiscount {
+                    coupon: discount.string("coupon")?,
+                    promotion_code: discount.string("promotion_code")?,
+                })
+            })
+            .collect::<Result<Vec<_>, FormError>>()?;
+
+        Ok(CreateCheckoutSessionRequest {
+            line_items,
+            customer: form.string("customer")?.filter(|c| !c.is_empty()),
+            customer_email: form.string("customer_email")?.filter(|e| !e.is_empty()),
+            customer_details,
+            success_url: form.string("success_url")?.filter(|url| !url.is_empty()),
+            cancel_url: form.string("cancel_url")?.filter(|url| !url.is_empty()),
+            metadata: form.map("metadata")?,
+            mode: form
+                .string("mode")?
+                .filter(|mode| !mode.is_empty())
+                .unwrap_or_else(default_mode),
+            currency: form
+                .string("currency")?
+                .filter(|currency| !currency.is_empty())
+                .map(|currency| currency.to_lowercase()),
+            discounts,
+            payment_link: None,
+            custom_fields: Vec::new(),
+        })
+    }
 }
 
 fn default_mode() -> String {
@@ -142,6 +256,9 @@ pub struct CreateLineItem {
     /// Experiment variant ID (e.g., "surfnet-lite#a") - for A/B test tracking
     #[serde(default)]
     pub experiment_id: Option<String>,
+    /// Price of the product to charge (its first price when missing)
+    #[serde(default)]
+    pub price: Option<String>,
     /// Quantity of the item
     #[serde(default = "default_quantity")]
     pub quantity: i64,
@@ -19,3 +19,11 @@ pub struct ListParams {
     #[serde(default)]
     pub product: Option<String>,
 }
+
+/// Related object: its ID, or the object itself when requested with `expand[]`
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Expandable<T> {
+    Id(String),
+    Object(Box<T>),
+}
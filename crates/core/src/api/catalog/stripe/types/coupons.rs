@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::catalog::stripe::form::{FormError, StripeForm};
+
+/// Stripe-compatible coupon response
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripeCoupon {
+    pub id: String,
+    pub object: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent_off: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount_off: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// once, repeating or forever
+    pub duration: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_in_months: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub applies_to: Option<CouponAppliesTo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_redemptions: Option<i64>,
+    pub times_redeemed: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redeem_by: Option<i64>,
+    /// Whether the coupon can still be redeemed
+    pub valid: bool,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    pub livemode: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CouponAppliesTo {
+    pub products: Vec<String>,
+}
+
+/// Stripe-compatible promotion code response
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripePromotionCode {
+    pub id: String,
+    pub object: String,
+    pub code: String,
+    pub coupon: StripeCoupon,
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub customer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_redemptions: Option<i64>,
+    pub times_redeemed: i64,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    pub livemode: bool,
+}
+
+/// Discount applied to a purchase, or requested in a create request
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedDiscount {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coupon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub promotion_code: Option<String>,
+}
+
+impl AppliedDiscount {
+    /// Discount requested with `coupon` or `promotion_code`, or with the first of `discounts`
+    pub fn requested(form: &StripeForm) -> Result<Self, FormError> {
+        let discounts = form.list("discounts")?;
+        let requested = |key: &str| -> Result<Option<String>, FormError> {
+            let value = match form.string(key)? {
+                Some(value) => Some(value),
+                None => discounts
+                    .first()
+                    .map(|discount| discount.string(key))
+                    .transpose()?
+                    .flatten(),
+            };
+            Ok(value.filter(|value| !value.is_empty()))
+        };
+        Ok(AppliedDiscount {
+            coupon: requested("coupon")?,
+            promotion_code: requested("promotion_code")?,
+        })
+    }
+}
+
+/// Amounts taken off or added to a purchase's subtotal
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TotalDetails {
+    pub amount_discount: i64,
+    pub amount_tax: i64,
+}
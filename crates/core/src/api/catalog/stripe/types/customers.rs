@@ -1,15 +1,7 @@
-use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-/// Request body for creating a customer
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct CreateCustomerRequest {
-    pub email: String,
-    #[serde(default)]
-    pub name: Option<String>,
-    #[serde(default)]
-    pub metadata: Option<std::collections::HashMap<String, String>>,
-}
+use moneymq_types::x402::transactions::FacilitatedTransaction;
+use serde::Serialize;
 
 /// Stripe-compatible customer response
 #[derive(Debug, Serialize)]
@@ -17,14 +9,63 @@ pub struct CreateCustomerRequest {
 pub struct StripeCustomer {
     pub id: String,
     pub object: String,
-    pub email: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub name: Option<String>,
+    pub email: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub metadata: Option<serde_json::Value>,
+    pub name: Option<String>,
+    pub metadata: HashMap<String, String>,
     pub created: i64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub phone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_clock: Option<String>,
+    pub livemode: bool,
+    /// Wallet addresses whose payments are attributed to the customer
+    pub wallets: Vec<String>,
+    /// Payments from the customer's wallets, newest first (on retrieve only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transactions: Option<Vec<FacilitatedTransaction>>,
+    /// Receipts of the customer's completed payments, newest first (on retrieve only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipts: Option<Vec<CustomerReceipt>>,
+}
+
+/// Receipt of a completed payment from one of a customer's wallets
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerReceipt {
+    pub transaction_id: String,
+    /// JWT receipt token containing the payment claims
+    pub receipt: String,
+    pub payer: String,
+    pub amount: String,
+    pub currency: String,
+    pub network: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_id: Option<String>,
+    /// When the payment completed (Unix seconds)
+    pub created: i64,
+}
+
+/// Response of DELETE /v1/customers/:id
+#[derive(Debug, Serialize)]
+pub struct DeletedCustomer {
+    pub id: String,
+    pub object: String,
+    pub deleted: bool,
+}
+
+/// Stripe-compatible search result page
+#[derive(Debug, Serialize)]
+pub struct SearchResponse<T> {
+    pub object: String,
+    pub data: Vec<T>,
+    pub has_more: bool,
+    /// Token of the next page, passed back as `page`
+    pub next_page: Option<String>,
+    pub url: String,
 }
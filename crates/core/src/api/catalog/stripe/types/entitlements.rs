@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+/// Stripe-compatible entitlement feature, defined by the catalog's product features
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripeEntitlementFeature {
+    pub id: String,
+    pub object: String,
+    pub active: bool,
+    pub livemode: bool,
+    /// Key of the feature in the products' `features`
+    pub lookup_key: String,
+    pub metadata: HashMap<String, String>,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Products granting the feature
+    pub products: Vec<String>,
+}
+
+/// Stripe-compatible active entitlement of a customer to a feature
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripeActiveEntitlement {
+    pub id: String,
+    pub object: String,
+    /// ID of the entitlement feature
+    pub feature: String,
+    pub livemode: bool,
+    pub lookup_key: String,
+    /// Value of the feature in the granting product
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<serde_json::Value>,
+    /// Product granting the entitlement
+    pub product: String,
+    /// When the entitlement lapses (Unix seconds): the end of the subscription's
+    /// current period, or the expiry of the receipt
+    pub expires_at: i64,
+    /// Subscription granting the entitlement
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription: Option<String>,
+    /// Transaction whose receipt grants the entitlement
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<String>,
+}
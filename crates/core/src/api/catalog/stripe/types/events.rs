@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+/// Stripe-compatible event, as delivered to webhook endpoints and listed on `/v1/events`.
+///
+/// Unlike the other API objects, events keep Stripe's snake_case field names (including in
+/// `data.object`) so they can be parsed by the official Stripe libraries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StripeEvent {
+    pub id: String,
+    pub object: String,
+    pub api_version: String,
+    /// When the event occurred (Unix seconds)
+    pub created: i64,
+    pub data: StripeEventData,
+    pub livemode: bool,
+    pub pending_webhooks: i64,
+    pub request: StripeEventRequest,
+    /// Event type, e.g. `payment_intent.succeeded`
+    #[serde(rename = "type")]
+    pub event_type: String,
+}
+
+/// Object an event is about
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StripeEventData {
+    /// The object after the change
+    pub object: serde_json::Value,
+    /// Values of the attributes that changed, for `*.updated` events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_attributes: Option<serde_json::Value>,
+}
+
+/// API request that caused an event; both fields are null for automatic changes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StripeEventRequest {
+    pub id: Option<String>,
+    pub idempotency_key: Option<String>,
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use super::{SolanaPayTransfer, SubscriptionPrice, TaxAddress, TaxRateDetails};
+
+/// Stripe-compatible invoice response
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripeInvoice {
+    pub id: String,
+    pub object: String,
+    pub customer: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription: Option<String>,
+    pub billing_reason: String,
+    /// draft, open, paid or void
+    pub status: String,
+    pub currency: String,
+    pub created: i64,
+    /// Sum of the lines (cents)
+    pub subtotal: i64,
+    pub total_discount_amounts: Vec<InvoiceDiscountAmount>,
+    pub total_excluding_tax: i64,
+    pub tax: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tax_percent: Option<f64>,
+    /// Automatic tax charged for the customer's address
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub total_tax_amounts: Vec<InvoiceTaxAmount>,
+    /// Address automatic tax is charged for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub customer_address: Option<TaxAddress>,
+    pub total: i64,
+    pub amount_due: i64,
+    pub amount_paid: i64,
+    pub amount_remaining: i64,
+    pub period_start: i64,
+    pub period_end: i64,
+    pub lines: InvoiceLines,
+    /// Payment intent created when the invoice was finalized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_intent: Option<String>,
+    /// Solana Pay transfer request paying the invoice while it is open
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub solana_pay: Option<SolanaPayTransfer>,
+    pub status_transitions: InvoiceStatusTransitions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    pub livemode: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_clock: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceDiscountAmount {
+    pub amount: i64,
+    /// Discount the amount comes from, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discount: Option<String>,
+}
+
+/// Tax charged at a rate, included in the amount or added on top of it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceTaxAmount {
+    pub amount: i64,
+    pub inclusive: bool,
+    pub tax_rate_details: TaxRateDetails,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceStatusTransitions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finalized_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub paid_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voided_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceLines {
+    pub object: String,
+    pub data: Vec<StripeInvoiceLineItem>,
+    pub has_more: bool,
+    pub url: String,
+}
+
+/// One line of an invoice
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripeInvoiceLineItem {
+    pub id: String,
+    pub object: String,
+    /// Line amount (cents), negative for credits
+    pub amount: i64,
+    pub currency: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Invoice item the line was created from, for one-off items
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invoice_item: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<SubscriptionPrice>,
+    pub quantity: i64,
+    pub unit_amount_decimal: String,
+    pub period: InvoicePeriod,
+    /// subscription (including metered usage) or invoiceitem
+    #[serde(rename = "type")]
+    pub line_type: String,
+    /// Automatic tax charged on the line
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tax_amounts: Vec<InvoiceTaxAmount>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoicePeriod {
+    pub start: i64,
+    pub end: i64,
+}
+
+/// Stripe-compatible invoice item response
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripeInvoiceItem {
+    pub id: String,
+    pub object: String,
+    pub customer: String,
+    pub amount: i64,
+    pub currency: String,
+    /// When the item was created (Unix seconds)
+    pub date: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Invoice the item is billed on; None while pending
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invoice: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<SubscriptionPrice>,
+    pub quantity: i64,
+    pub unit_amount_decimal: String,
+    pub period: InvoicePeriod,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    pub livemode: bool,
+}
+
+/// Response of a deleted invoice item
+#[derive(Debug, Serialize)]
+pub struct DeletedInvoiceItem {
+    pub id: String,
+    pub object: String,
+    pub deleted: bool,
+}
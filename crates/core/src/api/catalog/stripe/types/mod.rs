@@ -1,33 +1,65 @@
 pub mod billing;
 pub mod checkout_sessions;
 pub mod common;
+pub mod coupons;
 pub mod customers;
+pub mod entitlements;
+pub mod events;
+pub mod invoices;
 pub mod payment_intents;
+pub mod payment_links;
 pub mod payment_methods;
 pub mod prices;
 pub mod products;
 pub mod subscriptions;
+pub mod tax;
+pub mod test_clocks;
 
 // Re-export common catalog types from moneymq-types
 // Re-export types specific to moneymq-core (not in moneymq-types)
-pub use billing::StripeMeterEvent;
+pub use billing::{StripeMeterEvent, StripeMeterEventSummary};
 pub use checkout_sessions::{
-    CheckoutLineItem, CheckoutLineItemList, CheckoutLineItemPrice, CheckoutSessionStatus,
-    CreateCheckoutSessionRequest, CreateLineItem, PaymentStatus, StripeCheckoutSession,
+    CheckoutCustomField, CheckoutLineItem, CheckoutLineItemList, CheckoutLineItemPrice,
+    CheckoutSessionStatus, CreateCheckoutSessionRequest, CreateLineItem, PaymentStatus,
+    StripeCheckoutSession,
+};
+pub use common::Expandable;
+pub use coupons::{
+    AppliedDiscount, CouponAppliesTo, StripeCoupon, StripePromotionCode, TotalDetails,
+};
+pub use customers::{CustomerReceipt, DeletedCustomer, SearchResponse, StripeCustomer};
+pub use entitlements::{StripeActiveEntitlement, StripeEntitlementFeature};
+pub use events::{StripeEvent, StripeEventData, StripeEventRequest};
+pub use invoices::{
+    DeletedInvoiceItem, InvoiceDiscountAmount, InvoiceLines, InvoicePeriod,
+    InvoiceStatusTransitions, InvoiceTaxAmount, StripeInvoice, StripeInvoiceItem,
+    StripeInvoiceLineItem,
 };
-pub use customers::{CreateCustomerRequest, StripeCustomer};
 pub use moneymq_types::stripe::{
     ListParams, ListResponse, StripeBillingMeter, StripePrice, StripeRecurring,
 };
 // Use local enhanced StripeProduct with experiment support
 pub use payment_intents::{
-    ConfirmPaymentIntentRequest, CreatePaymentIntentRequest, PaymentIntentStatus,
-    StripePaymentIntent,
+    CaptureMethod, CapturePaymentIntentRequest, ConfirmPaymentIntentRequest,
+    CreatePaymentIntentRequest, PaymentIntentStatus, SolanaPayTransfer, StripePaymentIntent,
+};
+pub use payment_links::{
+    AfterCompletion, CreatePaymentLinkRequest, PaymentLinkLineItems, PaymentLinkStats,
+    StripePaymentLink, StripePaymentLinkLineItem, UpdatePaymentLinkRequest,
 };
 pub use payment_methods::{
     AttachPaymentMethodRequest, CreatePaymentMethodRequest, StripeCard, StripePaymentMethod,
 };
-pub use products::{StripeExperimentConfig, StripeProduct};
+pub use products::{StripeExperimentConfig, StripeProduct, price_id, product_id};
 pub use subscriptions::{
-    StripeSubscription, SubscriptionItemData, SubscriptionItems, SubscriptionPrice,
+    StripeSubscription, SubscriptionDiscount, SubscriptionItemData, SubscriptionItems,
+    SubscriptionPrice,
+};
+pub use tax::{
+    StripeTaxCalculation, TaxAddress, TaxBreakdown, TaxCalculationLineItem,
+    TaxCalculationLineItems, TaxCustomerDetails, TaxRateDetails,
+};
+pub use test_clocks::{
+    AdvanceTestClockRequest, CreateTestClockRequest, DeletedTestClock, StripeTestClock,
+    TestClockStatus,
 };
@@ -2,6 +2,9 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
+use super::AppliedDiscount;
+use crate::api::catalog::stripe::form::{FormError, StripeForm};
+
 /// Stripe-compatible payment intent response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -11,6 +14,15 @@ pub struct StripePaymentIntent {
     pub amount: i64,
     pub currency: String,
     pub status: PaymentIntentStatus,
+    /// Whether confirmation settles immediately or only authorizes the payment
+    #[serde(default)]
+    pub capture_method: CaptureMethod,
+    /// Amount that can still be captured (manual capture only)
+    #[serde(default)]
+    pub amount_capturable: i64,
+    /// Amount that has been settled
+    #[serde(default)]
+    pub amount_received: i64,
     pub created: i64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub customer: Option<String>,
@@ -24,6 +36,30 @@ pub struct StripePaymentIntent {
     pub latest_charge: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub client_secret: Option<String>,
+    /// Why the payment intent was canceled (`automatic` when an authorization expired)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancellation_reason: Option<String>,
+    /// Solana Pay transfer request paying the intent from any wallet, outside x402
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solana_pay: Option<SolanaPayTransfer>,
+}
+
+/// Solana Pay transfer request (`solana:` URL) of a payment intent
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SolanaPayTransfer {
+    pub url: String,
+    /// Random key the wallet adds to the transfer, unique to the payment intent
+    pub reference: String,
+    /// Payout address receiving the transfer
+    pub recipient: String,
+    /// Amount in units of the token (e.g. `12.50`)
+    pub amount: String,
+    /// Mint of the token to transfer
+    pub spl_token: String,
+    /// Transaction that paid the transfer request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 /// Payment Intent status
@@ -34,32 +70,99 @@ pub enum PaymentIntentStatus {
     RequiresConfirmation,
     RequiresAction,
     Processing,
+    RequiresCapture,
     Succeeded,
     Canceled,
 }
 
+/// Payment Intent capture method
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureMethod {
+    /// Settle as soon as the payment is confirmed
+    #[default]
+    Automatic,
+    /// Reserve the payment on confirmation and settle on capture
+    Manual,
+}
+
+impl std::str::FromStr for CaptureMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "automatic" | "automatic_async" => Ok(CaptureMethod::Automatic),
+            "manual" => Ok(CaptureMethod::Manual),
+            other => Err(format!("Invalid capture_method: {}", other)),
+        }
+    }
+}
+
 /// Create payment intent request
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
 pub struct CreatePaymentIntentRequest {
     pub amount: i64,
     pub currency: String,
-    #[serde(default)]
     pub customer: Option<String>,
-    #[serde(default)]
     pub payment_method: Option<String>,
-    #[serde(default)]
     pub description: Option<String>,
-    #[serde(default)]
     pub metadata: HashMap<String, String>,
-    #[serde(default)]
-    pub confirm: Option<bool>,
+    pub confirm: bool,
+    pub capture_method: CaptureMethod,
+    /// Coupon or promotion code taken off the amount
+    pub discount: AppliedDiscount,
+}
+
+impl CreatePaymentIntentRequest {
+    pub fn parse(body: &[u8]) -> Result<Self, FormError> {
+        let form = StripeForm::decode(body)?;
+        let amount = form
+            .integer("amount")?
+            .ok_or_else(|| form.missing("amount"))?;
+        if amount < 0 {
+            return Err(form.invalid("amount", "amount must be zero or more"));
+        }
+        Ok(CreatePaymentIntentRequest {
+            amount,
+            currency: form.required_string("currency")?.to_lowercase(),
+            customer: form.string("customer")?.filter(|c| !c.is_empty()),
+            payment_method: form.string("payment_method")?.filter(|pm| !pm.is_empty()),
+            description: form.string("description")?.filter(|d| !d.is_empty()),
+            metadata: form.map("metadata")?,
+            confirm: form.boolean("confirm")?.unwrap_or(false),
+            capture_method: form.parse("capture_method")?.unwrap_or_default(),
+            discount: AppliedDiscount::requested(&form)?,
+        })
+    }
 }
 
 /// Confirm payment intent request
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
 pub struct ConfirmPaymentIntentRequest {
-    #[serde(default)]
     pub payment_method: Option<String>,
 }
+
+impl ConfirmPaymentIntentRequest {
+    pub fn parse(body: &[u8]) -> Result<Self, FormError> {
+        let form = StripeForm::decode(body)?;
+        Ok(ConfirmPaymentIntentRequest {
+            payment_method: form.string("payment_method")?.filter(|pm| !pm.is_empty()),
+        })
+    }
+}
+
+/// Capture payment intent request
+#[derive(Debug)]
+pub struct CapturePaymentIntentRequest {
+    /// Defaults to the full `amount_capturable`
+    pub amount_to_capture: Option<i64>,
+}
+
+impl CapturePaymentIntentRequest {
+    pub fn parse(body: &[u8]) -> Result<Self, FormError> {
+        let form = StripeForm::decode(body)?;
+        Ok(CapturePaymentIntentRequest {
+            amount_to_capture: form.integer("amount_to_capture")?,
+        })
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::{BTreeMap, HashMap};
+
+use moneymq_types::{
+    AdjustableQuantity, CustomField, CustomFieldOption, CustomFieldType, PaymentLink,
+    PaymentLinkLineItem,
+};
+use serde::Serialize;
+
+use crate::api::catalog::stripe::form::{FormError, StripeForm};
+
+/// Stripe-compatible payment link response
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripePaymentLink {
+    pub id: String,
+    pub object: String,
+    pub active: bool,
+    /// Shareable URL opening a fresh checkout session per visit
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    pub line_items: PaymentLinkLineItems,
+    pub custom_fields: Vec<CustomField>,
+    pub after_completion: AfterCompletion,
+    /// Whether the link is declared in the catalog files, which it can't be updated through
+    pub declared: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<i64>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    pub livemode: bool,
+}
+
+/// Line items list wrapper of a payment link
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentLinkLineItems {
+    pub object: String,
+    pub data: Vec<StripePaymentLinkLineItem>,
+    pub has_more: bool,
+    pub url: String,
+}
+
+/// Price sold by a payment link
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripePaymentLinkLineItem {
+    pub id: String,
+    pub object: String,
+    pub price: String,
+    pub product: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub currency: String,
+    /// None for prices charged by their tiers, or with a fraction of a cent
+    pub unit_amount: Option<i64>,
+    /// Unit amount in cents, with its fraction of a cent for micropayment prices
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit_amount_decimal: Option<String>,
+    pub quantity: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adjustable_quantity: Option<AdjustableQuantity>,
+}
+
+/// What buyers see once they paid
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AfterCompletion {
+    /// redirect or hosted_confirmation
+    #[serde(rename = "type")]
+    pub completion_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect: Option<AfterCompletionRedirect>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AfterCompletionRedirect {
+    pub url: String,
+}
+
+impl AfterCompletion {
+    pub fn new(success_url: Option<&str>) -> Self {
+        match success_url {
+            Some(url) => Self {
+                completion_type: "redirect".to_string(),
+                redirect: Some(AfterCompletionRedirect {
+                    url: url.to_string(),
+                }),
+            },
+            None => Self {
+                completion_type: "hosted_confirmation".to_string(),
+                redirect: None,
+            },
+        }
+    }
+}
+
+/// Visits and payments of a payment link
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentLinkStats {
+    pub object: String,
+    pub payment_link: String,
+    /// Checkout sessions opened from the link
+    pub visits: i64,
+    /// Checkout sessions opened from the link that were paid
+    pub payments: i64,
+    pub conversion_rate: f64,
+    /// Amount paid through the link per currency (cents)
+    pub revenue: BTreeMap<String, i64>,
+    pub livemode: bool,
+}
+
+/// Create payment link request
+#[derive(Debug, Clone)]
+pub struct CreatePaymentLinkRequest {
+    pub line_items: Vec<PaymentLinkLineItem>,
+    pub custom_fields: Vec<CustomField>,
+    pub success_url: Option<String>,
+    pub currency: Option<String>,
+    pub metadata: HashMap<String, String>,
+}
+
+impl CreatePaymentLinkRequest {
+    pub fn parse(body: &[u8]) -> Result<Self, FormError> {
+        let form = StripeForm::decode(body)?;
+        let line_items = form
+            .list("line_items")?
+            .iter()
+            .map(|item| {
+                let adjustable_quantity = match item.object("adjustable_quantity")? {
+                    Some(adjustable) => Some(AdjustableQuantity {
+                        enabled: adjustable
+                            .boolean("enabled")?
+                            .ok_or_else(|| adjustable.missing("enabled"))?,
+                        minimum: adjustable.integer("minimum")?,
+                        maximum: adjustable.integer("maximum")?,
+                    }),
+                    None => None,
+                };
+                Ok(PaymentLinkLineItem {
+                    price: item.string("price")?.filter(|id| !id.is_empty()),
+                    product: item.string("product")?.filter(|id| !id.is_empty()),
+                    quantity: item.integer("quantity")?.unwrap_or(1),
+                    adjustable_quantity,
+                })
+            })
+            .collect::<Result<Vec<_>, FormError>>()?;
+        if line_items.is_empty() {
+            return Err(form.missing("line_items"));
+        }
+
+        let custom_fields = form
+            .list("custom_fields")?
+            .iter()
+            .map(|field| {
+                let field_type = match field.string("type")? {
+                    Some(field_type) => CustomFieldType::parse(&field_type).ok_or_else(|| {
+                        field.invalid("type", format!("Invalid custom field type: {}", field_type))
+                    })?,
+                    None => CustomFieldType::Text,
+                };
+                let options = match field.object("dropdown")? {
+                    Some(dropdown) => dropdown
+                        .list("options")?
+                        .iter()
+                        .map(|option| {
+                            Ok(CustomFieldOption {
+                                label: option.required_string("label")?,
+                                value: option.required_string("value")?,
+                            })
+                        })
+                        .collect::<Result<Vec<_>, FormError>>()?,
+                    None => Vec::new(),
+                };
+                Ok(CustomField {
+                    key: field.required_string("key")?,
+                    label: field.required_string("label[custom]")?,
+                    field_type,
+                    optional: field.boolean("optional")?.unwrap_or(false),
+                    options,
+                })
+            })
+            .collect::<Result<Vec<_>, FormError>>()?;
+
+        let request = CreatePaymentLinkRequest {
+            line_items,
+            custom_fields,
+            success_url: form
+                .string("after_completion[redirect][url]")?
+                .filter(|url| !url.is_empty()),
+            currency: form
+                .string("currency")?
+                .filter(|currency| !currency.is_empty())
+                .map(|currency| currency.to_lowercase()),
+            metadata: form.map("metadata")?,
+        };
+        request
+            .payment_link(String::new(), 0)
+            .validate()
+            .map_err(|message| form.invalid("line_items", message))?;
+        Ok(request)
+    }
+
+    /// Payment link `id` created at `created` (Unix seconds) from the request
+    pub fn payment_link(&self, id: String, created: i64) -> PaymentLink {
+        PaymentLink {
+            id,
+            active: true,
+            line_items: self.line_items.clone(),
+            custom_fields: self.custom_fields.clone(),
+            success_url: self.success_url.clone(),
+            currency: self.currency.clone(),
+            created: Some(created),
+            metadata: self
+                .metadata
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Update payment link request
+#[derive(Debug, Clone, Default)]
+pub struct UpdatePaymentLinkRequest {
+    pub active: Option<bool>,
+    pub metadata: HashMap<String, String>,
+}
+
+impl UpdatePaymentLinkRequest {
+    pub fn parse(body: &[u8]) -> Result<Self, FormError> {
+        let form = StripeForm::decode(body)?;
+        Ok(UpdatePaymentLinkRequest {
+            active: form.boolean("active")?,
+            metadata: form.map("metadata")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_create_payment_link_request() {
+        let request = CreatePaymentLinkRequest::parse(
+            b"line_items[0][price]=price_pro&line_items[0][quantity]=2\
+              &line_items[0][adjustable_quantity][enabled]=true\
+              &line_items[0][adjustable_quantity][maximum]=5\
+              &custom_fields[0][key]=size&custom_fields[0][label][custom]=Size\
+              &custom_fields[0][type]=dropdown\
+              &custom_fields[0][dropdown][options][0][label]=Large\
+              &custom_fields[0][dropdown][options][0][value]=l\
+              &after_completion[type]=redirect\
+              &after_completion[redirect][url]=https%3A%2F%2Fexample.com%2Fthanks\
+              &metadata[campaign]=launch",
+        )
+        .unwrap();
+        assert_eq!(request.line_items[0].price.as_deref(), Some("price_pro"));
+        assert_eq!(request.line_items[0].quantity, 2);
+        assert_eq!(
+            request.line_items[0].adjustable_quantity.unwrap().maximum(),
+            5
+        );
+        assert_eq!(request.custom_fields[0].options[0].value, "l");
+        assert_eq!(
+            request.success_url.as_deref(),
+            Some("https://example.com/thanks")
+        );
+
+        let link = request.payment_link("plink_1".to_string(), 100);
+        assert_eq!(
+            link.metadata.get("campaign").map(String::as_str),
+            Some("launch")
+        );
+
+        let error = CreatePaymentLinkRequest::parse(
+            b"line_items[0][price]=price_pro&line_items[0][quantity]=9\
+              &line_items[0][adjustable_quantity][enabled]=true\
+              &line_items[0][adjustable_quantity][maximum]=5",
+        )
+        .unwrap_err();
+        assert_eq!(error.param(), Some("line_items"));
+        assert!(CreatePaymentLinkRequest::parse(b"currency=usd").is_err());
+    }
+}
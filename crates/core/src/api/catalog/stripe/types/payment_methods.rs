@@ -1,22 +1,36 @@
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+
+use crate::api::catalog::stripe::form::{FormError, StripeForm};
 
 /// Request body for creating a payment method
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
 pub struct CreatePaymentMethodRequest {
-    #[serde(rename = "type")]
     pub payment_type: String,
-    #[serde(default)]
     pub card: Option<CardDetails>,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
 pub struct CardDetails {
-    #[serde(default)]
     pub token: Option<String>,
 }
 
+impl CreatePaymentMethodRequest {
+    pub fn parse(body: &[u8]) -> Result<Self, FormError> {
+        let form = StripeForm::decode(body)?;
+        let card = if form.contains("card") {
+            Some(CardDetails {
+                token: form.string("card[token]")?,
+            })
+        } else {
+            None
+        };
+        Ok(CreatePaymentMethodRequest {
+            payment_type: form.required_string("type")?,
+            card,
+        })
+    }
+}
+
 /// Stripe-compatible payment method response
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -42,8 +56,16 @@ pub struct StripeCard {
 }
 
 /// Request body for attaching a payment method
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
 pub struct AttachPaymentMethodRequest {
     pub customer: String,
 }
+
+impl AttachPaymentMethodRequest {
+    pub fn parse(body: &[u8]) -> Result<Self, FormError> {
+        let form = StripeForm::decode(body)?;
+        Ok(AttachPaymentMethodRequest {
+            customer: form.required_string("customer")?,
+        })
+    }
+}
@@ -1,7 +1,9 @@
 use indexmap::IndexMap;
-use moneymq_types::{Price, Product, ProductFeature};
+use moneymq_types::{Price, Product, ProductFeature, stripe::StripePrice};
 use serde::Serialize;
 
+use super::common::Expandable;
+
 /// Experiment configuration for A/B testing (Stripe API representation)
 #[derive(Debug, Serialize, Clone)]
 pub struct StripeExperimentConfig {
@@ -9,69 +11,8 @@ pub struct StripeExperimentConfig {
     pub exposure: f64,
 }
 
-/// Recurring interval configuration for subscription prices
-#[derive(Debug, Serialize, Clone)]
-#[serde(rename_all = "camelCase")]
-pub struct StripeProductPriceRecurring {
-    pub interval: String,
-    pub interval_count: i64,
-}
-
-/// Inline price for product response (simplified version of StripePrice)
+/// Stripe-compatible product response, with Stripe's field names
 #[derive(Debug, Serialize, Clone)]
-#[serde(rename_all = "camelCase")]
-pub struct StripeProductPrice {
-    pub id: String,
-    pub object: String,
-    pub active: bool,
-    pub currency: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub unit_amount: Option<i64>,
-    #[serde(rename = "type")]
-    pub pricing_type: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub recurring: Option<StripeProductPriceRecurring>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub nickname: Option<String>,
-}
-
-impl StripeProductPrice {
-    pub fn from_price(price: &Price, use_sandbox: bool) -> Self {
-        let price_id = if use_sandbox {
-            price.sandboxes.get("default")
-        } else {
-            price.deployed_id.as_ref()
-        };
-
-        let recurring = if price.pricing_type == moneymq_types::iac::PricingType::Recurring {
-            Some(StripeProductPriceRecurring {
-                interval: price
-                    .recurring_interval
-                    .as_ref()
-                    .map(|i| i.as_str().to_string())
-                    .unwrap_or_else(|| "month".to_string()),
-                interval_count: price.recurring_interval_count.unwrap_or(1),
-            })
-        } else {
-            None
-        };
-
-        Self {
-            id: price_id.cloned().unwrap_or_else(|| price.id.clone()),
-            object: "price".to_string(),
-            active: price.active,
-            currency: price.currency.as_str().to_string(),
-            unit_amount: price.unit_amount,
-            pricing_type: price.pricing_type.as_str().to_string(),
-            recurring,
-            nickname: price.nickname.clone(),
-        }
-    }
-}
-
-/// Stripe-compatible product response
-#[derive(Debug, Serialize, Clone)]
-#[serde(rename_all = "camelCase")]
 pub struct StripeProduct {
     pub id: String,
     pub object: String,
@@ -102,9 +43,10 @@ pub struct StripeProduct {
     /// Product features
     #[serde(skip_serializing_if = "Option::is_none")]
     pub features: Option<IndexMap<String, ProductFeature>>,
-    /// Default price for this product
+    /// Default price for this product: its ID, or the price with
+    /// `expand[]=default_price`
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub default_price: Option<StripeProductPrice>,
+    pub default_price: Option<Expandable<StripePrice>>,
 }
 
 impl StripeProduct {
@@ -149,7 +91,7 @@ impl StripeProduct {
             default_price: product
                 .prices
                 .first()
-                .map(|p| StripeProductPrice::from_price(p, use_sandbox)),
+                .map(|p| Expandable::Id(price_id(p, use_sandbox))),
         }
     }
 
@@ -236,13 +178,28 @@ impl StripeProduct {
             default_price: experiment
                 .prices
                 .first()
-                .map(|p| StripeProductPrice::from_price(p, use_sandbox))
-                .or_else(|| {
-                    parent
-                        .prices
-                        .first()
-                        .map(|p| StripeProductPrice::from_price(p, use_sandbox))
-                }),
+                .or_else(|| parent.prices.first())
+                .map(|p| Expandable::Id(price_id(p, use_sandbox))),
         }
     }
 }
+
+/// ID clients use for a price: its deployed or sandbox ID, falling back to the catalog ID
+pub fn price_id(price: &Price, use_sandbox: bool) -> String {
+    let external_id = if use_sandbox {
+        price.sandboxes.get("default")
+    } else {
+        price.deployed_id.as_ref()
+    };
+    external_id.cloned().unwrap_or_else(|| price.id.clone())
+}
+
+/// ID clients use for a product: its deployed or sandbox ID, falling back to the catalog ID
+pub fn product_id(product: &Product, use_sandbox: bool) -> String {
+    let external_id = if use_sandbox {
+        product.sandboxes.get("default")
+    } else {
+        product.deployed_id.as_ref()
+    };
+    external_id.cloned().unwrap_or_else(|| product.id.clone())
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::Serialize;
 
 /// Stripe-compatible subscription response
@@ -14,6 +16,34 @@ pub struct StripeSubscription {
     pub items: SubscriptionItems,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub latest_invoice: Option<String>,
+    /// Payment intent charging the current period
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest_payment_intent: Option<String>,
+    pub cancel_at_period_end: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canceled_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trial_end: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub paused_at: Option<i64>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_clock: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discount: Option<SubscriptionDiscount>,
+}
+
+/// Coupon discounting a subscription's invoices
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionDiscount {
+    pub coupon: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub promotion_code: Option<String>,
+    /// When the discount stops applying, None if it applies forever
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<i64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -29,6 +59,7 @@ pub struct SubscriptionItemData {
     pub id: String,
     pub object: String,
     pub price: SubscriptionPrice,
+    pub quantity: i64,
 }
 
 #[derive(Debug, Serialize)]
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+/// Address a purchase is taxed for
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxAddress {
+    pub country: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postal_code: Option<String>,
+}
+
+/// Customer details a tax calculation is made for
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxCustomerDetails {
+    pub address: TaxAddress,
+    /// Always `billing`
+    #[serde(default)]
+    pub address_source: String,
+}
+
+/// Stripe-compatible tax calculation response
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripeTaxCalculation {
+    pub id: String,
+    pub object: String,
+    /// Total of the lines, tax included
+    pub amount_total: i64,
+    pub currency: String,
+    pub customer_details: TaxCustomerDetails,
+    pub line_items: TaxCalculationLineItems,
+    /// Tax added on top of tax-exclusive prices
+    pub tax_amount_exclusive: i64,
+    /// Tax included in tax-inclusive prices
+    pub tax_amount_inclusive: i64,
+    pub tax_breakdown: Vec<TaxBreakdown>,
+    pub expires_at: i64,
+    pub livemode: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxCalculationLineItems {
+    pub object: String,
+    pub data: Vec<TaxCalculationLineItem>,
+    pub has_more: bool,
+}
+
+/// One line of a tax calculation
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxCalculationLineItem {
+    pub id: String,
+    pub object: String,
+    /// Line amount, tax included for tax-inclusive prices
+    pub amount: i64,
+    pub amount_tax: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+    pub quantity: i64,
+    pub reference: String,
+    /// exclusive or inclusive
+    pub tax_behavior: String,
+}
+
+/// Tax charged at one rate, summed over the lines
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxBreakdown {
+    pub amount: i64,
+    pub inclusive: bool,
+    pub taxable_amount: i64,
+    /// `not_subject_to_tax` where digital goods aren't taxed, `standard_rated` otherwise
+    pub taxability_reason: String,
+    pub tax_rate_details: TaxRateDetails,
+}
+
+/// Rate of a jurisdiction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxRateDetails {
+    pub country: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    /// Rate in percent, as a decimal string (e.g. "19.0")
+    pub percentage_decimal: String,
+    /// vat, gst, sales_tax, ...
+    pub tax_type: String,
+}
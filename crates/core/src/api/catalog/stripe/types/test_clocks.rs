@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+
+use crate::api::catalog::stripe::form::{FormError, StripeForm};
+
+/// Stripe-compatible test clock (sandbox only)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripeTestClock {
+    pub id: String,
+    pub object: String,
+    pub created: i64,
+    /// Time the attached customers and subscriptions currently see (Unix seconds)
+    pub frozen_time: i64,
+    pub livemode: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub status: TestClockStatus,
+}
+
+/// Test clock status
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TestClockStatus {
+    /// Objects attached to the clock are up to date with its frozen time
+    Ready,
+    /// An advance is in progress
+    Advancing,
+    /// The last advance failed part way
+    InternalFailure,
+}
+
+/// Response of a deleted test clock
+#[derive(Debug, Serialize)]
+pub struct DeletedTestClock {
+    pub id: String,
+    pub object: String,
+    pub deleted: bool,
+}
+
+/// Request body for creating a test clock
+#[derive(Debug)]
+pub struct CreateTestClockRequest {
+    /// Initial time of the clock (Unix seconds)
+    pub frozen_time: i64,
+    pub name: Option<String>,
+}
+
+impl CreateTestClockRequest {
+    pub fn parse(body: &[u8]) -> Result<Self, FormError> {
+        let form = StripeForm::decode(body)?;
+        Ok(CreateTestClockRequest {
+            frozen_time: form
+                .integer("frozen_time")?
+                .ok_or_else(|| form.missing("frozen_time"))?,
+            name: form.string("name")?.filter(|name| !name.is_empty()),
+        })
+    }
+}
+
+/// Request body for advancing a test clock
+#[derive(Debug)]
+pub struct AdvanceTestClockRequest {
+    /// Time to move the clock to (Unix seconds), later than its current frozen time
+    pub frozen_time: i64,
+}
+
+impl AdvanceTestClockRequest {
+    pub fn parse(body: &[u8]) -> Result<Self, FormError> {
+        let form = StripeForm::decode(body)?;
+        Ok(AdvanceTestClockRequest {
+            frozen_time: form
+                .integer("frozen_time")?
+                .ok_or_else(|| form.missing("frozen_time"))?,
+        })
+    }
+}
@@ -0,0 +1,306 @@
+//! Stripe-format webhook events.
+//!
+//! State changes are recorded as Stripe `event` objects, listed on `/v1/events`, and
+//! delivered to the catalog's webhook endpoint when one is configured. Deliveries carry a
+//! `Stripe-Signature` header (`t=<timestamp>,v1=<HMAC-SHA256 of "<timestamp>.<payload>">`)
+//! so handlers written against Stripe can verify them with the official libraries and the
+//! endpoint's signing secret. Failed deliveries are retried with exponential backoff.
+
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sha2::Sha256;
+use tracing::{debug, error, warn};
+use url::Url;
+
+use crate::api::{
+    catalog::{
+        CatalogState,
+        stripe::{
+            types::{StripeEvent, StripeEventData, StripeEventRequest},
+            utils::generate_stripe_id,
+        },
+    },
+    payment::db::stripe_event::NewStripeEvent,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// API version stamped on events when the manifest doesn't set one
+pub const DEFAULT_API_VERSION: &str = "2024-06-20";
+
+/// Delivery attempts of an event before giving up
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry, doubled after every failed attempt
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Endpoint receiving the catalog's events
+#[derive(Clone)]
+pub struct WebhookEndpoint {
+    pub url: Url,
+    secret: String,
+    pub api_version: String,
+    client: reqwest::Client,
+}
+
+impl std::fmt::Debug for WebhookEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookEndpoint")
+            .field("url", &self.url)
+            .field("api_version", &self.api_version)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WebhookEndpoint {
+    /// Deliver events to `url`, signed with `secret` (the endpoint's `whsec_...` secret)
+    pub fn new(url: Url, secret: String, api_version: Option<String>) -> Self {
+        Self {
+            url,
+            secret,
+            api_version: api_version.unwrap_or_else(|| DEFAULT_API_VERSION.to_string()),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// `Stripe-Signature` header of `payload` sent at `timestamp` (Unix seconds)
+    pub fn signature(&self, timestamp: i64, payload: &str) -> String {
+        stripe_signature(&self.secret, timestamp, payload)
+    }
+}
+
+/// `Stripe-Signature` header value of `payload` signed with `secret` at `timestamp`
+pub fn stripe_signature(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+    format!(
+        "t={},v1={}",
+        timestamp,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+fn snake_case(key: &str) -> String {
+    let mut snake = String::with_capacity(key.len() + 4);
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            snake.push('_');
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// Rename the fields of an API object to Stripe's snake_case, leaving the keys of
+/// `metadata` as they were set
+pub fn stripe_object(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = if key == "metadata" {
+                        value
+                    } else {
+                        stripe_object(value)
+                    };
+                    (snake_case(&key), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(stripe_object).collect()),
+        value => value,
+    }
+}
+
+/// Previous values of the top-level attributes that differ between `before` and `after`
+pub fn previous_attributes(before: &Value, after: &Value) -> Option<Value> {
+    let (Value::Object(before), Value::Object(after)) = (before, after) else {
+        return None;
+    };
+    let changed = after
+        .iter()
+        .filter(|(key, value)| before.get(*key) != Some(value))
+        .map(|(key, _)| (key.clone(), before.get(key).cloned().unwrap_or(Value::Null)))
+        .collect::<Map<_, _>>();
+    (!changed.is_empty()).then_some(Value::Object(changed))
+}
+
+/// Record a `event_type` event about `object` that occurred at `at` (Unix seconds), and
+/// deliver it to the webhook endpoint
+pub(crate) fn emit_stripe_event(
+    state: &CatalogState,
+    event_type: &str,
+    object: &impl Serialize,
+    at: i64,
+) {
+    let Ok(object) = serde_json::to_value(object) else {
+        return;
+    };
+    emit(state, event_type, stripe_object(object), None, at);
+}
+
+/// Record and deliver an update of an object, with the attributes changed from `before`
+pub(crate) fn emit_stripe_update<T: Serialize>(
+    state: &CatalogState,
+    event_type: &str,
+    before: &T,
+    after: &T,
+    at: i64,
+) {
+    let (Ok(before), Ok(after)) = (serde_json::to_value(before), serde_json::to_value(after))
+    else {
+        return;
+    };
+    let (before, after) = (stripe_object(before), stripe_object(after));
+    let previous = previous_attributes(&before, &after);
+    emit(state, event_type, after, previous, at);
+}
+
+fn emit(state: &CatalogState, event_type: &str, object: Value, previous: Option<Value>, at: i64) {
+    let webhook = state.webhook.as_ref();
+    let event = StripeEvent {
+        id: generate_stripe_id("evt"),
+        object: "event".to_string(),
+        api_version: webhook
+            .map(|w| w.api_version.clone())
+            .unwrap_or_else(|| DEFAULT_API_VERSION.to_string()),
+        created: at,
+        data: StripeEventData {
+            object,
+            previous_attributes: previous,
+        },
+        livemode: !state.use_sandbox,
+        pending_webhooks: webhook.is_some() as i64,
+        request: StripeEventRequest::default(),
+        event_type: event_type.to_string(),
+    };
+    let Ok(payload) = serde_json::to_string(&event) else {
+        return;
+    };
+
+    if let Some(db) = state.payment_db.as_ref()
+        && let Err(e) = db.insert_stripe_event(&NewStripeEvent::new(
+            event.id.clone(),
+            event.event_type.clone(),
+            payload.clone(),
+            state.payment_stack_id.clone(),
+            state.use_sandbox,
+        ))
+    {
+        error!("Failed to persist {} event to DB: {}", event_type, e);
+    }
+
+    let Some(webhook) = webhook.cloned() else {
+        return;
+    };
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        warn!(
+            "Not delivering {} event {}: no async runtime",
+            event_type, event.id
+        );
+        return;
+    };
+    runtime.spawn(deliver(webhook, event.id, event.event_type, payload));
+}
+
+/// POST an event to the webhook endpoint, retrying until it answers with a 2xx status
+async fn deliver(webhook: WebhookEndpoint, event_id: String, event_type: String, payload: String) {
+    let mut delay = INITIAL_RETRY_DELAY;
+    for attempt in 1..=MAX_DELIVERY_ATTEMPTS {
+        let timestamp = chrono::Utc::now().timestamp();
+        let result = webhook
+            .client
+            .post(webhook.url.clone())
+            .header("Content-Type", "application/json")
+            .header("Stripe-Signature", webhook.signature(timestamp, &payload))
+            .body(payload.clone())
+            .send()
+            .await;
+        match result {
+            Ok(response) if response.status().is_success() => {
+                debug!("Delivered {} event {}", event_type, event_id);
+                return;
+            }
+            Ok(response) => warn!(
+                "Webhook endpoint answered {} to {} event {} (attempt {}/{})",
+                response.status(),
+                event_type,
+                event_id,
+                attempt,
+                MAX_DELIVERY_ATTEMPTS
+            ),
+            Err(e) => warn!(
+                "Failed to deliver {} event {} (attempt {}/{}): {}",
+                event_type, event_id, attempt, MAX_DELIVERY_ATTEMPTS, e
+            ),
+        }
+        if attempt < MAX_DELIVERY_ATTEMPTS {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+    error!(
+        "Gave up delivering {} event {} to {}",
+        event_type, event_id, webhook.url
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_stripe_signature() {
+        let payload = r#"{"id":"evt_test","object":"event"}"#;
+        let header = stripe_signature("whsec_test", 1_700_000_000, payload);
+        let (timestamp, signature) = header.split_once(",v1=").unwrap();
+        assert_eq!(timestamp, "t=1700000000");
+
+        // What the Stripe libraries compute to verify the header
+        let mut mac = HmacSha256::new_from_slice(b"whsec_test").unwrap();
+        mac.update(b"1700000000.");
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&hex::decode(signature).unwrap()).unwrap();
+
+        assert_ne!(
+            header,
+            stripe_signature("whsec_other", 1_700_000_000, payload)
+        );
+        assert_ne!(
+            header,
+            stripe_signature("whsec_test", 1_700_000_001, payload)
+        );
+    }
+
+    #[test]
+    fn test_stripe_object_and_previous_attributes() {
+        let before = stripe_object(json!({
+            "id": "sub_1",
+            "cancelAtPeriodEnd": false,
+            "status": "active",
+            "metadata": { "orderId": "1" },
+            "items": { "data": [{ "currentPeriodEnd": 10 }] },
+        }));
+        assert_eq!(before["cancel_at_period_end"], json!(false));
+        assert_eq!(before["metadata"], json!({ "orderId": "1" }));
+        assert_eq!(before["items"]["data"][0]["current_period_end"], json!(10));
+
+        let mut after = before.clone();
+        after["cancel_at_period_end"] = json!(true);
+        after["canceled_at"] = json!(5);
+        assert_eq!(
+            previous_attributes(&before, &after),
+            Some(json!({ "cancel_at_period_end": false, "canceled_at": null }))
+        );
+        assert_eq!(previous_attributes(&before, &before), None);
+    }
+}
@@ -4,6 +4,7 @@ pub mod sandbox;
 
 use axum::{Extension, Router, routing::get};
 use catalog::CatalogState;
+use moneymq_types::x402::Network;
 use payment::PaymentApiConfig;
 // Backwards compatibility re-export
 #[allow(deprecated)]
@@ -30,6 +31,12 @@ pub fn create_combined_router(
         .allow_methods(Any)
         .allow_headers(Any);
 
+    let catalog_state = attach_payment_db(catalog_state, &payment_api_config);
+    // Serve the products stored for the payment stack, now that it is known
+    catalog_state.replace_catalog(catalog::Catalog::clone(&catalog_state.catalog()));
+    let catalog_state = attach_stack_branding(catalog_state, &payment_api_config);
+    let catalog_state = attach_rpc_pool(catalog_state, &payment_api_config);
+
     // Create the catalog router (uses Extension layer internally)
     let catalog_router = catalog::create_router(catalog_state.clone());
 
@@ -55,6 +62,51 @@ pub fn create_combined_router(
     app.layer(cors_layer)
 }
 
+/// Persist catalog records in the payment database unless the catalog already has one
+fn attach_payment_db(
+    catalog_state: CatalogState,
+    payment_api_config: &PaymentApiConfig,
+) -> CatalogState {
+    if catalog_state.payment_db.is_some() {
+        return catalog_state;
+    }
+    catalog_state.with_payment_db(
+        payment_api_config.db_manager.clone(),
+        &payment_api_config.payment_stack_id,
+    )
+}
+
+/// Brand hosted catalog pages like the payment stack unless the catalog already is
+fn attach_stack_branding(
+    catalog_state: CatalogState,
+    payment_api_config: &PaymentApiConfig,
+) -> CatalogState {
+    if catalog_state.stack_name.is_some() || catalog_state.stack_image_url.is_some() {
+        return catalog_state;
+    }
+    catalog_state.with_stack_branding(
+        payment_api_config.stack_name.clone(),
+        payment_api_config.stack_image_url.clone(),
+    )
+}
+
+/// Send catalog transactions through the RPC pool of the facilitator's Solana network
+fn attach_rpc_pool(
+    catalog_state: CatalogState,
+    payment_api_config: &PaymentApiConfig,
+) -> CatalogState {
+    let pool = payment_api_config
+        .facilitator_config
+        .networks
+        .iter()
+        .find(|(_, network_config)| network_config.network() == Network::Solana)
+        .and_then(|(name, _)| payment_api_config.rpc_pools.get(name));
+    match pool {
+        Some(pool) if catalog_state.rpc_pool.is_none() => catalog_state.with_rpc_pool(pool),
+        _ => catalog_state,
+    }
+}
+
 /// Start the combined API server on the specified port
 pub async fn start_server(
     catalog_state: CatalogState,
@@ -62,6 +114,12 @@ pub async fn start_server(
     extra_routes: Option<Router<()>>,
     port: u16,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    payment::endpoints::payment_channels::spawn_settlement_task(payment_api_config.clone());
+    payment_api_config.rpc_pools.spawn_health_checks();
+    let catalog_state = attach_payment_db(catalog_state, &payment_api_config);
+    catalog::stripe::endpoints::subscriptions::spawn_renewal_task(catalog_state.clone());
+    let catalog_state = attach_stack_branding(catalog_state, &payment_api_config);
+    catalog::solana_pay::spawn_watcher(catalog_state.clone(), payment_api_config.clone());
     let app = create_combined_router(catalog_state, payment_api_config, extra_routes);
 
     let addr = format!("0.0.0.0:{}", port);
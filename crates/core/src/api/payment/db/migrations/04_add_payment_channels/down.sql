@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_payment_channels_stack;
+DROP TABLE IF EXISTS payment_channels;
@@ -0,0 +1,36 @@
+------------------------------------------------------------
+-- payment_channels: Off-chain payment channels backed by an escrowed deposit
+------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS payment_channels (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- Public identifier (pch_...)
+    channel_id TEXT NOT NULL UNIQUE,
+    -- Parties
+    payer TEXT NOT NULL,                    -- Payer pubkey, signs vouchers
+    operator_id TEXT NOT NULL,              -- Operator actor controlling the escrow
+    escrow TEXT NOT NULL,                   -- Escrow owner address (operator pubkey)
+    recipient TEXT NOT NULL,                -- Payout address for settlements
+    -- Token
+    asset TEXT NOT NULL,                    -- Token mint
+    token_program TEXT NOT NULL,
+    decimals INTEGER NOT NULL,
+    -- Amounts (token base units)
+    deposit_amount BIGINT NOT NULL,
+    cumulative_amount BIGINT NOT NULL DEFAULT 0,  -- Latest voucher amount
+    settled_amount BIGINT NOT NULL DEFAULT 0,     -- Amount already paid out on-chain
+    -- Latest voucher and on-chain references
+    voucher_signature TEXT,
+    deposit_signature TEXT,
+    last_settlement_signature TEXT,
+    -- Lifecycle
+    status TEXT NOT NULL,                   -- open | closing | closed
+    expires_at TIMESTAMP NOT NULL,
+    -- Context
+    payment_stack_id TEXT NOT NULL,
+    is_sandbox BOOL NOT NULL DEFAULT FALSE,
+    -- Timestamps
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_payment_channels_stack ON payment_channels(payment_stack_id, is_sandbox, status);
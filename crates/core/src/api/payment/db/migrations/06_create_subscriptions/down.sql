@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_subscriptions_period_end;
+DROP INDEX IF EXISTS idx_subscriptions_stack;
+DROP TABLE IF EXISTS subscriptions;
@@ -0,0 +1,35 @@
+------------------------------------------------------------
+-- subscriptions: Recurring subscriptions to catalog prices
+------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS subscriptions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- Public identifier (sub_...)
+    subscription_id TEXT NOT NULL UNIQUE,
+    customer TEXT NOT NULL,
+    -- Items as a JSON array of {id, price, quantity}
+    items TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    -- Billing cadence, from the subscribed prices
+    recurring_interval TEXT NOT NULL,       -- day | week | month | year
+    interval_count INTEGER NOT NULL DEFAULT 1,
+    -- Lifecycle
+    status TEXT NOT NULL,                   -- trialing | active | past_due | paused | canceled
+    current_period_start BIGINT NOT NULL,   -- Unix seconds
+    current_period_end BIGINT NOT NULL,     -- Unix seconds
+    trial_end BIGINT,                       -- Unix seconds
+    cancel_at_period_end BOOL NOT NULL DEFAULT FALSE,
+    canceled_at BIGINT,                     -- Unix seconds
+    paused_at BIGINT,                       -- Unix seconds
+    -- Charge created for the current period, paid when the customer confirms it
+    latest_payment_intent TEXT,
+    metadata TEXT,                          -- JSON object
+    -- Context
+    payment_stack_id TEXT NOT NULL,
+    is_sandbox BOOL NOT NULL DEFAULT FALSE,
+    -- Timestamps
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_subscriptions_stack ON subscriptions(payment_stack_id, is_sandbox, status);
+CREATE INDEX idx_subscriptions_period_end ON subscriptions(current_period_end);
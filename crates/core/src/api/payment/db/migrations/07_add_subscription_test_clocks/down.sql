@@ -0,0 +1,5 @@
+-- Remove the test clock index
+DROP INDEX IF EXISTS idx_subscriptions_test_clock;
+
+-- Remove the test_clock column
+ALTER TABLE subscriptions DROP COLUMN test_clock;
@@ -0,0 +1,6 @@
+-- Add test_clock column to subscriptions
+-- Sandbox subscriptions attached to a test clock are renewed when the clock is advanced,
+-- never by the real-time renewal task
+ALTER TABLE subscriptions ADD COLUMN test_clock TEXT;
+
+CREATE INDEX idx_subscriptions_test_clock ON subscriptions(test_clock);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_meter_events_usage;
+DROP INDEX IF EXISTS idx_meter_events_identifier;
+DROP TABLE IF EXISTS meter_events;
@@ -0,0 +1,28 @@
+------------------------------------------------------------
+-- meter_events: Usage reported against billing meters
+------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS meter_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- Client-provided (or generated) identifier, used for dedup
+    identifier TEXT NOT NULL,
+    -- Catalog meter the event was recorded against
+    meter_id TEXT NOT NULL,
+    event_name TEXT NOT NULL,
+    -- Customer resolved through the meter's customer mapping
+    customer TEXT NOT NULL,
+    -- Value resolved through the meter's value settings
+    value DOUBLE NOT NULL,
+    -- When the usage occurred (Unix seconds)
+    timestamp BIGINT NOT NULL,
+    payload TEXT NOT NULL,                  -- JSON object
+    -- Context
+    payment_stack_id TEXT NOT NULL,
+    is_sandbox BOOL NOT NULL DEFAULT FALSE,
+    -- Timestamps
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE UNIQUE INDEX idx_meter_events_identifier
+ON meter_events(payment_stack_id, is_sandbox, identifier);
+CREATE INDEX idx_meter_events_usage
+ON meter_events(payment_stack_id, is_sandbox, meter_id, customer, timestamp);
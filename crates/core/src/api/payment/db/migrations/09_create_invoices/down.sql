@@ -0,0 +1,10 @@
+ALTER TABLE subscriptions DROP COLUMN latest_invoice;
+
+DROP INDEX IF EXISTS idx_invoice_items_customer;
+DROP INDEX IF EXISTS idx_invoice_items_invoice;
+DROP TABLE IF EXISTS invoice_items;
+
+DROP INDEX IF EXISTS idx_invoices_payment_intent;
+DROP INDEX IF EXISTS idx_invoices_customer;
+DROP INDEX IF EXISTS idx_invoices_stack;
+DROP TABLE IF EXISTS invoices;
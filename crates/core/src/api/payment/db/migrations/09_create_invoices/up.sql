@@ -0,0 +1,84 @@
+------------------------------------------------------------
+-- invoices: Bills for subscription periods, usage and one-off items
+------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS invoices (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- Public identifier (in_...)
+    invoice_id TEXT NOT NULL UNIQUE,
+    customer TEXT NOT NULL,
+    -- Subscription billed by the invoice, if any
+    subscription TEXT,
+    -- subscription_create | subscription_cycle | manual
+    billing_reason TEXT NOT NULL,
+    -- Lifecycle
+    status TEXT NOT NULL,                   -- draft | open | paid | void
+    currency TEXT NOT NULL,
+    -- Amounts (cents), computed from the invoice's lines when it is created or updated
+    subtotal BIGINT NOT NULL DEFAULT 0,
+    discount BIGINT NOT NULL DEFAULT 0,
+    tax BIGINT NOT NULL DEFAULT 0,
+    total BIGINT NOT NULL DEFAULT 0,
+    amount_paid BIGINT NOT NULL DEFAULT 0,
+    -- Tax applied to the discounted subtotal, in percent
+    tax_percent DOUBLE,
+    -- Billed period (Unix seconds)
+    period_start BIGINT NOT NULL,
+    period_end BIGINT NOT NULL,
+    -- Payment intent created when the invoice is finalized
+    payment_intent TEXT,
+    description TEXT,
+    metadata TEXT,                          -- JSON object
+    -- Sandbox test clock of the customer or subscription
+    test_clock TEXT,
+    -- Context
+    payment_stack_id TEXT NOT NULL,
+    is_sandbox BOOL NOT NULL DEFAULT FALSE,
+    -- Timestamps
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    finalized_at BIGINT,                    -- Unix seconds
+    paid_at BIGINT,                         -- Unix seconds
+    voided_at BIGINT                        -- Unix seconds
+);
+
+CREATE INDEX idx_invoices_stack ON invoices(payment_stack_id, is_sandbox, status);
+CREATE INDEX idx_invoices_customer ON invoices(customer);
+CREATE INDEX idx_invoices_payment_intent ON invoices(payment_intent);
+
+------------------------------------------------------------
+-- invoice_items: Lines of an invoice, or pending one-off items not invoiced yet
+------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS invoice_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- Public identifier (ii_...)
+    item_id TEXT NOT NULL UNIQUE,
+    customer TEXT NOT NULL,
+    -- Invoice the item is billed on; pending items have none
+    invoice TEXT,
+    subscription TEXT,
+    price TEXT,
+    -- subscription | overage | invoiceitem
+    kind TEXT NOT NULL,
+    description TEXT,
+    quantity BIGINT NOT NULL DEFAULT 1,
+    -- Unit amount in cents, fractional for usage priced below a cent
+    unit_amount_decimal DOUBLE NOT NULL,
+    -- Line amount (cents), negative for credits
+    amount BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    -- Period the item covers (Unix seconds)
+    period_start BIGINT NOT NULL,
+    period_end BIGINT NOT NULL,
+    metadata TEXT,                          -- JSON object
+    -- Context
+    payment_stack_id TEXT NOT NULL,
+    is_sandbox BOOL NOT NULL DEFAULT FALSE,
+    -- Timestamps
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_invoice_items_invoice ON invoice_items(invoice);
+CREATE INDEX idx_invoice_items_customer ON invoice_items(payment_stack_id, is_sandbox, customer);
+
+-- Invoice billing the current period of a subscription
+ALTER TABLE subscriptions ADD COLUMN latest_invoice TEXT;
@@ -0,0 +1,10 @@
+ALTER TABLE invoices DROP COLUMN coupon;
+
+ALTER TABLE subscriptions DROP COLUMN discount_end;
+ALTER TABLE subscriptions DROP COLUMN promotion_code;
+ALTER TABLE subscriptions DROP COLUMN coupon;
+
+DROP INDEX IF EXISTS idx_coupon_redemptions_promotion_code;
+DROP INDEX IF EXISTS idx_coupon_redemptions_coupon;
+DROP INDEX IF EXISTS idx_coupon_redemptions_reference;
+DROP TABLE IF EXISTS coupon_redemptions;
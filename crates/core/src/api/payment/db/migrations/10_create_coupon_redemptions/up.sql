@@ -0,0 +1,36 @@
+------------------------------------------------------------
+-- coupon_redemptions: Catalog coupons redeemed by payments and subscriptions
+------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS coupon_redemptions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    coupon TEXT NOT NULL,
+    -- Promotion code the coupon was redeemed with, if any
+    promotion_code TEXT,
+    customer TEXT,
+    -- Payment intent, subscription or x402 transaction the coupon was redeemed by
+    reference TEXT NOT NULL,
+    -- Amount taken off (cents)
+    amount_discount BIGINT NOT NULL DEFAULT 0,
+    currency TEXT NOT NULL,
+    -- Context
+    payment_stack_id TEXT NOT NULL,
+    is_sandbox BOOL NOT NULL DEFAULT FALSE,
+    -- Timestamps
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE UNIQUE INDEX idx_coupon_redemptions_reference
+ON coupon_redemptions(payment_stack_id, is_sandbox, reference);
+CREATE INDEX idx_coupon_redemptions_coupon
+ON coupon_redemptions(payment_stack_id, is_sandbox, coupon);
+CREATE INDEX idx_coupon_redemptions_promotion_code
+ON coupon_redemptions(payment_stack_id, is_sandbox, promotion_code);
+
+-- Coupon applied to a subscription's invoices until discount_end (Unix seconds, forever
+-- when NULL)
+ALTER TABLE subscriptions ADD COLUMN coupon TEXT;
+ALTER TABLE subscriptions ADD COLUMN promotion_code TEXT;
+ALTER TABLE subscriptions ADD COLUMN discount_end BIGINT;
+
+-- Coupon discounting an invoice
+ALTER TABLE invoices ADD COLUMN coupon TEXT;
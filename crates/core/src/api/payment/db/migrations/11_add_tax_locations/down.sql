@@ -0,0 +1,11 @@
+ALTER TABLE facilitated_transactions DROP COLUMN tax;
+
+ALTER TABLE invoice_items DROP COLUMN tax_inclusive;
+ALTER TABLE invoice_items DROP COLUMN amount_tax;
+
+ALTER TABLE invoices DROP COLUMN tax_details;
+ALTER TABLE invoices DROP COLUMN tax_state;
+ALTER TABLE invoices DROP COLUMN tax_country;
+
+ALTER TABLE subscriptions DROP COLUMN tax_state;
+ALTER TABLE subscriptions DROP COLUMN tax_country;
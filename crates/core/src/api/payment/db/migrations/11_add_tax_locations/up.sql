@@ -0,0 +1,16 @@
+-- Jurisdiction automatic tax is charged for (ISO country code, and state or province
+-- for the US and Canada); manual tax_percent applies when NULL
+ALTER TABLE subscriptions ADD COLUMN tax_country TEXT;
+ALTER TABLE subscriptions ADD COLUMN tax_state TEXT;
+
+ALTER TABLE invoices ADD COLUMN tax_country TEXT;
+ALTER TABLE invoices ADD COLUMN tax_state TEXT;
+-- Tax broken out of the invoice (JSON: amount, amountInclusive, rate, taxType, country)
+ALTER TABLE invoices ADD COLUMN tax_details TEXT;
+
+-- Tax charged on each line (cents), included in its amount for tax-inclusive prices
+ALTER TABLE invoice_items ADD COLUMN amount_tax BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE invoice_items ADD COLUMN tax_inclusive BOOL NOT NULL DEFAULT FALSE;
+
+-- Tax broken out of a settled payment (same JSON as invoices.tax_details)
+ALTER TABLE facilitated_transactions ADD COLUMN tax TEXT;
@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS idx_customer_wallets_customer;
+DROP INDEX IF EXISTS idx_customer_wallets_address;
+DROP TABLE IF EXISTS customer_wallets;
+
+DROP INDEX IF EXISTS idx_customers_email;
+DROP TABLE IF EXISTS customers;
@@ -0,0 +1,41 @@
+------------------------------------------------------------
+-- customers: Stripe-style customers of a payment stack
+------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS customers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    customer_id TEXT NOT NULL UNIQUE,
+    email TEXT,
+    name TEXT,
+    description TEXT,
+    phone TEXT,
+    -- JSON object of string values
+    metadata TEXT,
+    test_clock TEXT,
+    -- Context
+    payment_stack_id TEXT NOT NULL,
+    is_sandbox BOOL NOT NULL DEFAULT FALSE,
+    -- Timestamps
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_customers_email ON customers(payment_stack_id, is_sandbox, email);
+
+------------------------------------------------------------
+-- customer_wallets: Wallet addresses payments are attributed to a customer from
+------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS customer_wallets (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    customer_id TEXT NOT NULL,
+    address TEXT NOT NULL,
+    -- Context
+    payment_stack_id TEXT NOT NULL,
+    is_sandbox BOOL NOT NULL DEFAULT FALSE,
+    -- Timestamps
+    created_at TIMESTAMP NOT NULL
+);
+
+-- A wallet belongs to at most one customer of a stack
+CREATE UNIQUE INDEX idx_customer_wallets_address
+ON customer_wallets(payment_stack_id, is_sandbox, address);
+CREATE INDEX idx_customer_wallets_customer ON customer_wallets(customer_id);
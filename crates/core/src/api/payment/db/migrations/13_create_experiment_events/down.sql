@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS idx_experiment_events_experiment;
+DROP INDEX IF EXISTS idx_experiment_events_conversion;
+DROP INDEX IF EXISTS idx_experiment_events_exposure;
+DROP TABLE IF EXISTS experiment_events;
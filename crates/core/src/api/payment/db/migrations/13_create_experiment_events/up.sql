@@ -0,0 +1,34 @@
+------------------------------------------------------------
+-- experiment_events: Exposures to and conversions of experiment variants
+------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS experiment_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- Parent product of the variants
+    experiment_id TEXT NOT NULL,
+    -- Variant product, or the parent product for the control group
+    variant_id TEXT NOT NULL,
+    -- 'exposure' or 'conversion'
+    kind TEXT NOT NULL,
+    -- Customer, wallet or anonymous id the variant was assigned to
+    subject TEXT,
+    -- x402 transaction or payment intent of a conversion
+    reference TEXT,
+    -- Amount paid by a conversion (cents)
+    amount BIGINT NOT NULL DEFAULT 0,
+    currency TEXT,
+    -- Context
+    payment_stack_id TEXT NOT NULL,
+    is_sandbox BOOL NOT NULL DEFAULT FALSE,
+    -- Timestamps
+    created_at TIMESTAMP NOT NULL
+);
+
+-- A subject is exposed to an experiment once; a payment converts a variant once
+CREATE UNIQUE INDEX idx_experiment_events_exposure
+ON experiment_events(payment_stack_id, is_sandbox, experiment_id, subject)
+WHERE kind = 'exposure';
+CREATE UNIQUE INDEX idx_experiment_events_conversion
+ON experiment_events(payment_stack_id, is_sandbox, variant_id, reference)
+WHERE kind = 'conversion';
+CREATE INDEX idx_experiment_events_experiment
+ON experiment_events(payment_stack_id, is_sandbox, experiment_id);
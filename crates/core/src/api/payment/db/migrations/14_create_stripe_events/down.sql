@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_stripe_events_type;
+DROP TABLE IF EXISTS stripe_events;
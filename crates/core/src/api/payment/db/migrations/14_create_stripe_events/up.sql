@@ -0,0 +1,20 @@
+------------------------------------------------------------
+-- stripe_events: Stripe-format events of catalog state changes, sent to the webhook
+-- endpoint and listed on /v1/events
+------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS stripe_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_id TEXT NOT NULL UNIQUE,
+    -- e.g. invoice.paid
+    event_type TEXT NOT NULL,
+    -- The event object, as sent to the webhook endpoint
+    data_json TEXT NOT NULL,
+    -- Context
+    payment_stack_id TEXT NOT NULL,
+    is_sandbox BOOL NOT NULL DEFAULT FALSE,
+    -- Timestamps
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_stripe_events_type
+ON stripe_events(payment_stack_id, is_sandbox, event_type);
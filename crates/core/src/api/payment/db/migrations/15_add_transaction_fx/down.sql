@@ -0,0 +1 @@
+ALTER TABLE facilitated_transactions DROP COLUMN fx;
@@ -0,0 +1,3 @@
+-- Conversion of a payment priced in another currency than its settlement stablecoin
+-- tracks (JSON: currency, amount, settlementCurrency, settlementAmount, rate, source)
+ALTER TABLE facilitated_transactions ADD COLUMN fx TEXT;
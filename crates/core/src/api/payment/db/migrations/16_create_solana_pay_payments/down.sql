@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_solana_pay_payments_payment_intent;
+DROP TABLE IF EXISTS solana_pay_payments;
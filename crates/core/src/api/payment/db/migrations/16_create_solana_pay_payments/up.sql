@@ -0,0 +1,21 @@
+------------------------------------------------------------
+-- solana_pay_payments: Reference keys of payment intents' Solana Pay transfer requests,
+-- and the transaction that paid each of them
+------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS solana_pay_payments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    payment_intent_id TEXT NOT NULL,
+    -- Random public key the wallet adds to the transfer
+    reference TEXT NOT NULL UNIQUE,
+    -- Transaction that paid the payment intent; a transaction pays a single intent
+    signature TEXT UNIQUE,
+    -- Context
+    payment_stack_id TEXT NOT NULL,
+    is_sandbox BOOL NOT NULL DEFAULT FALSE,
+    -- Timestamps
+    created_at TIMESTAMP NOT NULL,
+    paid_at TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_solana_pay_payments_payment_intent
+ON solana_pay_payments(payment_stack_id, is_sandbox, payment_intent_id);
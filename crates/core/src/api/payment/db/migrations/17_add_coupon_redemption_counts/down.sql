@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_coupon_redemption_counts_object;
+DROP TABLE IF EXISTS coupon_redemption_counts;
@@ -0,0 +1,30 @@
+------------------------------------------------------------
+-- coupon_redemption_counts: Redemptions counted against a coupon's or a promotion code's
+-- max_redemptions, incremented with a conditional UPDATE so concurrent purchases can't
+-- exceed it
+------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS coupon_redemption_counts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- 'coupon' or 'promotion_code'
+    object TEXT NOT NULL,
+    object_id TEXT NOT NULL,
+    times_redeemed BIGINT NOT NULL DEFAULT 0,
+    -- Context
+    payment_stack_id TEXT NOT NULL,
+    is_sandbox BOOL NOT NULL DEFAULT FALSE
+);
+
+CREATE UNIQUE INDEX idx_coupon_redemption_counts_object
+ON coupon_redemption_counts(payment_stack_id, is_sandbox, object, object_id);
+
+-- Redemptions recorded before the counts
+INSERT INTO coupon_redemption_counts (object, object_id, times_redeemed, payment_stack_id, is_sandbox)
+SELECT 'coupon', coupon, COUNT(*), payment_stack_id, is_sandbox
+FROM coupon_redemptions
+GROUP BY payment_stack_id, is_sandbox, coupon;
+
+INSERT INTO coupon_redemption_counts (object, object_id, times_redeemed, payment_stack_id, is_sandbox)
+SELECT 'promotion_code', promotion_code, COUNT(*), payment_stack_id, is_sandbox
+FROM coupon_redemptions
+WHERE promotion_code IS NOT NULL
+GROUP BY payment_stack_id, is_sandbox, promotion_code;
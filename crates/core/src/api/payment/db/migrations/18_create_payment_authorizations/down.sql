@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_payment_authorizations_payment_intent;
+DROP TABLE IF EXISTS payment_authorizations;
@@ -0,0 +1,22 @@
+------------------------------------------------------------
+-- payment_authorizations: Payments verified for manual-capture payment intents and held
+-- until they are captured, canceled or expire
+------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS payment_authorizations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    payment_intent_id TEXT NOT NULL,
+    -- Payment intent awaiting capture (JSON), restored with its authorization
+    payment_intent TEXT NOT NULL,
+    -- Signed x402 payload and the requirements it was verified against (JSON)
+    payment_payload TEXT NOT NULL,
+    payment_requirements TEXT NOT NULL,
+    -- Unix seconds
+    authorized_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    -- Context
+    payment_stack_id TEXT NOT NULL,
+    is_sandbox BOOL NOT NULL DEFAULT FALSE
+);
+
+CREATE UNIQUE INDEX idx_payment_authorizations_payment_intent
+ON payment_authorizations(payment_stack_id, is_sandbox, payment_intent_id);
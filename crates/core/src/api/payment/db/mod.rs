@@ -12,6 +12,16 @@ use crate::api::payment::endpoints::FacilitatorExtraContext;
 mod models;
 pub mod schema;
 
+pub use models::{
+    CouponRedemptionModel, CustomerModel, ExperimentEventModel, InvoiceItemModel, InvoiceModel,
+    MeterEventModel, PaymentAuthorizationModel, PaymentChannelModel, SolanaPayPaymentModel,
+    StripeEventModel, SubscriptionModel, coupon_redemption, customer, experiment_event, invoice,
+    meter_event, payment_authorization, payment_channel, solana_pay_payment, stripe_event,
+    subscription,
+};
+
+/// Migrations run in the order of their version (the directory name's prefix) compared as
+/// strings
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./src/api/payment/db/migrations");
 
 #[cfg(feature = "sqlite")]
@@ -73,6 +83,26 @@ pub enum DbError {
     QueryEventError(diesel::result::Error),
     #[error("Failed to manage event stream: {0}")]
     EventStreamError(diesel::result::Error),
+    #[error("Failed to manage payment channel: {0}")]
+    PaymentChannelError(diesel::result::Error),
+    #[error("Failed to manage subscription: {0}")]
+    SubscriptionError(diesel::result::Error),
+    #[error("Failed to manage meter event: {0}")]
+    MeterEventError(diesel::result::Error),
+    #[error("Failed to manage invoice: {0}")]
+    InvoiceError(diesel::result::Error),
+    #[error("Failed to manage coupon redemption: {0}")]
+    CouponRedemptionError(diesel::result::Error),
+    #[error("Failed to manage customer: {0}")]
+    CustomerError(diesel::result::Error),
+    #[error("Failed to manage experiment event: {0}")]
+    ExperimentEventError(diesel::result::Error),
+    #[error("Failed to manage Stripe event: {0}")]
+    StripeEventError(diesel::result::Error),
+    #[error("Failed to manage Solana Pay payment: {0}")]
+    SolanaPayPaymentError(diesel::result::Error),
+    #[error("Failed to manage payment authorization: {0}")]
+    PaymentAuthorizationError(diesel::result::Error),
 }
 
 fn run_migrations(conn: &mut PooledConnection) -> Result<(), DbError> {
@@ -283,7 +313,9 @@ impl DbManager {
             payment_hash,
             payment_stack_id.to_string(),
             is_sandbox,
-        );
+        )
+        .with_tax(extra_ctx.as_ref().and_then(|ctx| ctx.tax.as_ref()))
+        .with_fx(extra_ctx.as_ref().and_then(|ctx| ctx.fx.as_ref()));
 
         // Handle idempotent inserts - if payment_hash already exists, treat as success
         match new_transaction.insert(&mut conn) {
@@ -313,6 +345,36 @@ impl DbManager {
             .map_err(DbError::FindTxError)
     }
 
+    /// Find the payment requirements a transaction payload was first verified against
+    /// Used to detect payloads replayed against a different resource
+    pub fn find_payment_requirements_by_transaction(
+        &self,
+        x402_transaction: &str,
+    ) -> DbResult<Option<moneymq_types::x402::PaymentRequirements>> {
+        use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        let payment_hash =
+            calculate_payment_hash(x402_transaction).map_err(DbError::ConnectionError)?;
+
+        let transaction = models::facilitated_transaction::find_transaction_by_payment_hash(
+            &mut conn,
+            &payment_hash,
+        )
+        .map_err(DbError::FindTxError)?;
+
+        Ok(transaction.and_then(|tx| {
+            BASE64
+                .decode(&tx.facilitated.x402_payment_requirement)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        }))
+    }
+
     /// Find transaction ID by payment_hash for settlement updates
     /// This is the preferred method for finding transactions to settle
     pub fn find_transaction_id_by_payment_hash(
@@ -376,10 +438,14 @@ impl DbManager {
         Ok(())
     }
 
+    /// List transactions, newest first, optionally only those paid from one of `payers`
+    ///
+    /// Payers are attributed to the customer their wallet is attached to.
     pub fn list_transactions(
         &self,
         limit: usize,
         starting_after: Option<i32>,
+        payers: Option<&[String]>,
         payment_stack_id: &str,
         is_sandbox: bool,
     ) -> DbResult<(Vec<FacilitatedTransaction>, bool)> {
@@ -388,23 +454,38 @@ impl DbManager {
             .get()
             .map_err(|e| DbError::ConnectionError(e.to_string()))?;
 
-        models::facilitated_transaction::FacilitatedTransactionWithCustomer::list(
-            &mut conn,
-            limit,
-            starting_after,
-            payment_stack_id,
-            is_sandbox,
-        )
-        .map_err(DbError::ListTxError)
-        .map(|(txs_with_customer, has_more)| {
-            (
-                txs_with_customer
-                    .into_iter()
-                    .map(|tx_with_customer| tx_with_customer.into())
-                    .collect(),
-                has_more,
+        let (txs_with_customer, has_more) =
+            models::facilitated_transaction::FacilitatedTransactionWithCustomer::list(
+                &mut conn,
+                limit,
+                starting_after,
+                payers,
+                payment_stack_id,
+                is_sandbox,
             )
-        })
+            .map_err(DbError::ListTxError)?;
+        let mut transactions: Vec<FacilitatedTransaction> = txs_with_customer
+            .into_iter()
+            .map(|tx_with_customer| tx_with_customer.into())
+            .collect();
+
+        let addresses: Vec<String> = transactions
+            .iter()
+            .filter_map(|tx| tx.customer.as_ref().map(|c| c.address.clone()))
+            .collect();
+        let owners: std::collections::HashMap<String, String> =
+            customer::find_wallet_customers(&mut conn, &addresses, payment_stack_id, is_sandbox)
+                .map_err(DbError::CustomerError)?
+                .into_iter()
+                .collect();
+        for payer in transactions
+            .iter_mut()
+            .filter_map(|tx| tx.customer.as_mut())
+        {
+            payer.customer = owners.get(&payer.address).cloned();
+        }
+
+        Ok((transactions, has_more))
     }
 
     // ==================== Event Stream Methods ====================
@@ -464,6 +545,41 @@ impl DbManager {
         .map_err(DbError::QueryEventError)
     }
 
+    /// Events of `event_type` mentioning any of `needles` (e.g. wallet addresses), newest
+    /// first
+    pub fn list_events_mentioning(
+        &self,
+        event_type: &str,
+        needles: &[String],
+        payment_stack_id: &str,
+        is_sandbox: bool,
+        limit: i64,
+    ) -> DbResult<Vec<models::CloudEventModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        let mut events = Vec::new();
+        for needle in needles {
+            events.extend(
+                models::cloud_event::list_events_mentioning(
+                    &mut conn,
+                    event_type,
+                    needle,
+                    payment_stack_id,
+                    is_sandbox,
+                    limit,
+                )
+                .map_err(DbError::QueryEventError)?,
+            );
+        }
+        events.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        events.dedup_by(|a, b| a.event_id == b.event_id);
+        events.truncate(limit.max(0) as usize);
+        Ok(events)
+    }
+
     /// Get the last N events for initial replay
     pub fn get_last_events(
         &self,
@@ -542,4 +658,937 @@ impl DbManager {
         models::event_stream::find_stream(&mut conn, stream_id, payment_stack_id, is_sandbox)
             .map_err(DbError::EventStreamError)
     }
+
+    // ==================== Payment Channel Methods ====================
+
+    /// Persist a newly opened payment channel
+    pub fn insert_payment_channel(
+        &self,
+        new_channel: &payment_channel::NewPaymentChannel,
+    ) -> DbResult<PaymentChannelModel> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        new_channel
+            .insert(&mut conn)
+            .map_err(DbError::PaymentChannelError)
+    }
+
+    /// Find a payment channel by its public identifier
+    pub fn find_payment_channel(
+        &self,
+        channel_id: &str,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Option<PaymentChannelModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        models::payment_channel::find_channel(&mut conn, channel_id, payment_stack_id, is_sandbox)
+            .map_err(DbError::PaymentChannelError)
+    }
+
+    /// Record a voucher if the channel balance is still `previous_amount`
+    /// Returns false if another voucher was recorded first or the channel is closed
+    pub fn record_payment_channel_voucher(
+        &self,
+        channel_id: &str,
+        previous_amount: i64,
+        cumulative_amount: i64,
+        voucher_signature: &str,
+    ) -> DbResult<bool> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        models::payment_channel::record_voucher(
+            &mut conn,
+            channel_id,
+            previous_amount,
+            cumulative_amount,
+            voucher_signature,
+        )
+        .map_err(DbError::PaymentChannelError)
+        .map(|updated| updated == 1)
+    }
+
+    /// Reserve a channel payout by moving its settled amount from `previous_amount`
+    /// Returns false if another settlement changed the settled amount first
+    pub fn update_payment_channel_settled_amount(
+        &self,
+        channel_id: &str,
+        previous_amount: i64,
+        settled_amount: i64,
+    ) -> DbResult<bool> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        models::payment_channel::update_settled_amount(
+            &mut conn,
+            channel_id,
+            previous_amount,
+            settled_amount,
+        )
+        .map_err(DbError::PaymentChannelError)
+        .map(|updated| updated == 1)
+    }
+
+    /// Record the signature of a channel's latest on-chain payout
+    pub fn record_payment_channel_settlement(
+        &self,
+        channel_id: &str,
+        signature: &str,
+    ) -> DbResult<()> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        models::payment_channel::record_settlement_signature(&mut conn, channel_id, signature)
+            .map_err(DbError::PaymentChannelError)?;
+        Ok(())
+    }
+
+    /// Move a payment channel from status `from` to status `to`
+    /// Returns false if the channel was not in status `from`
+    pub fn transition_payment_channel_status(
+        &self,
+        channel_id: &str,
+        from: &str,
+        to: &str,
+    ) -> DbResult<bool> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        models::payment_channel::transition_status(&mut conn, channel_id, from, to)
+            .map_err(DbError::PaymentChannelError)
+            .map(|updated| updated == 1)
+    }
+
+    /// List open payment channels (used by the background settlement task)
+    pub fn list_open_payment_channels(
+        &self,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Vec<PaymentChannelModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        models::payment_channel::list_open_channels(&mut conn, payment_stack_id, is_sandbox)
+            .map_err(DbError::PaymentChannelError)
+    }
+
+    pub fn list_payment_channels(
+        &self,
+        limit: usize,
+        starting_after: Option<i32>,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<(Vec<PaymentChannelModel>, bool)> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        models::payment_channel::list_channels(
+            &mut conn,
+            limit,
+            starting_after,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::PaymentChannelError)
+    }
+
+    // ==================== Subscription Methods ====================
+
+    /// Persist a new subscription
+    pub fn insert_subscription(
+        &self,
+        new_subscription: &subscription::NewSubscription,
+    ) -> DbResult<SubscriptionModel> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        new_subscription
+            .insert(&mut conn)
+            .map_err(DbError::SubscriptionError)
+    }
+
+    /// Find a subscription by its public identifier
+    pub fn find_subscription(
+        &self,
+        subscription_id: &str,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Option<SubscriptionModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        models::subscription::find_subscription(
+            &mut conn,
+            subscription_id,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::SubscriptionError)
+    }
+
+    /// Find the subscription charged by a payment intent
+    pub fn find_subscription_by_payment_intent(
+        &self,
+        payment_intent_id: &str,
+    ) -> DbResult<Option<SubscriptionModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        models::subscription::find_by_payment_intent(&mut conn, payment_intent_id)
+            .map_err(DbError::SubscriptionError)
+    }
+
+    /// Update a subscription that is still in status `expected_status`
+    /// Returns None if the subscription moved to another status first
+    pub fn update_subscription(
+        &self,
+        subscription_id: &str,
+        expected_status: &str,
+        changes: subscription::UpdateSubscription,
+    ) -> DbResult<Option<SubscriptionModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        models::subscription::update_subscription(
+            &mut conn,
+            subscription_id,
+            expected_status,
+            changes,
+        )
+        .map_err(DbError::SubscriptionError)
+    }
+
+    /// List subscriptions whose current period has ended (used by the renewal task and
+    /// when advancing a test clock)
+    pub fn list_due_subscriptions(
+        &self,
+        now: i64,
+        test_clock: Option<&str>,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Vec<SubscriptionModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        models::subscription::list_due_subscriptions(
+            &mut conn,
+            now,
+            test_clock,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::SubscriptionError)
+    }
+
+    /// List the live subscriptions attached to a test clock
+    pub fn list_test_clock_subscriptions(
+        &self,
+        test_clock: &str,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Vec<SubscriptionModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        models::subscription::list_test_clock_subscriptions(
+            &mut conn,
+            test_clock,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::SubscriptionError)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn list_subscriptions(
+        &self,
+        limit: usize,
+        starting_after: Option<i32>,
+        customer: Option<&str>,
+        status: Option<&str>,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<(Vec<SubscriptionModel>, bool)> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        models::subscription::list_subscriptions(
+            &mut conn,
+            limit,
+            starting_after,
+            customer,
+            status,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::SubscriptionError)
+    }
+
+    // ==================== Meter Event Methods ====================
+
+    /// Record a meter event, or return the one already recorded with the same identifier
+    pub fn record_meter_event(
+        &self,
+        new_event: &meter_event::NewMeterEvent,
+    ) -> DbResult<(MeterEventModel, bool)> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        meter_event::insert_or_find(&mut conn, new_event).map_err(DbError::MeterEventError)
+    }
+
+    /// Find a meter event by its identifier
+    pub fn find_meter_event(
+        &self,
+        identifier: &str,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Option<MeterEventModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        meter_event::find_by_identifier(&mut conn, identifier, payment_stack_id, is_sandbox)
+            .map_err(DbError::MeterEventError)
+    }
+
+    /// List a customer's events for a meter within `[start_time, end_time)`
+    pub fn list_customer_meter_events(
+        &self,
+        meter_id: &str,
+        customer: &str,
+        start_time: i64,
+        end_time: i64,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Vec<MeterEventModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        meter_event::list_customer_events(
+            &mut conn,
+            meter_id,
+            customer,
+            start_time,
+            end_time,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::MeterEventError)
+    }
+
+    // ==================== Invoice Methods ====================
+
+    /// Persist an invoice with its lines, attaching the customer's pending invoice items
+    /// when `include_pending` is set
+    pub fn insert_invoice(
+        &self,
+        new_invoice: &invoice::NewInvoice,
+        lines: &[invoice::NewInvoiceItem],
+        include_pending: bool,
+    ) -> DbResult<(InvoiceModel, Vec<InvoiceItemModel>)> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        invoice::insert_invoice(&mut conn, new_invoice, lines, include_pending)
+            .map_err(DbError::InvoiceError)
+    }
+
+    /// Find an invoice by its public identifier
+    pub fn find_invoice(
+        &self,
+        invoice_id: &str,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Option<InvoiceModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        invoice::find_invoice(&mut conn, invoice_id, payment_stack_id, is_sandbox)
+            .map_err(DbError::InvoiceError)
+    }
+
+    /// Find the invoice paid by a payment intent
+    pub fn find_invoice_by_payment_intent(
+        &self,
+        payment_intent_id: &str,
+    ) -> DbResult<Option<InvoiceModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        invoice::find_by_payment_intent(&mut conn, payment_intent_id).map_err(DbError::InvoiceError)
+    }
+
+    /// Update an invoice that is still in status `expected_status`
+    /// Returns None if the invoice moved to another status first
+    pub fn update_invoice(
+        &self,
+        invoice_id: &str,
+        expected_status: &str,
+        changes: invoice::UpdateInvoice,
+    ) -> DbResult<Option<InvoiceModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        invoice::update_invoice(&mut conn, invoice_id, expected_status, changes)
+            .map_err(DbError::InvoiceError)
+    }
+
+    /// Record the tax computed on each line of an invoice, as (item ID, tax in cents,
+    /// inclusive)
+    pub fn update_invoice_line_taxes(&self, line_taxes: &[(String, i64, bool)]) -> DbResult<()> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        invoice::update_line_taxes(&mut conn, line_taxes).map_err(DbError::InvoiceError)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn list_invoices(
+        &self,
+        limit: usize,
+        starting_after: Option<i32>,
+        customer: Option<&str>,
+        subscription: Option<&str>,
+        status: Option<&str>,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<(Vec<InvoiceModel>, bool)> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        invoice::list_invoices(
+            &mut conn,
+            limit,
+            starting_after,
+            customer,
+            subscription,
+            status,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::InvoiceError)
+    }
+
+    /// Lines of an invoice
+    pub fn list_invoice_lines(&self, invoice_id: &str) -> DbResult<Vec<InvoiceItemModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        invoice::list_invoice_lines(&mut conn, invoice_id).map_err(DbError::InvoiceError)
+    }
+
+    /// Overage units of a price already billed for a subscription period
+    pub fn billed_overage_units(
+        &self,
+        subscription: &str,
+        price: &str,
+        period_start: i64,
+    ) -> DbResult<i64> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        invoice::billed_overage_units(&mut conn, subscription, price, period_start)
+            .map_err(DbError::InvoiceError)
+    }
+
+    /// Persist an invoice item, pending or on a draft invoice
+    pub fn insert_invoice_item(
+        &self,
+        new_item: &invoice::NewInvoiceItem,
+    ) -> DbResult<InvoiceItemModel> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        new_item.insert(&mut conn).map_err(DbError::InvoiceError)
+    }
+
+    /// Find an invoice item by its public identifier
+    pub fn find_invoice_item(
+        &self,
+        item_id: &str,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Option<InvoiceItemModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        invoice::find_invoice_item(&mut conn, item_id, payment_stack_id, is_sandbox)
+            .map_err(DbError::InvoiceError)
+    }
+
+    /// Delete an invoice item that is not billed on a finalized invoice
+    pub fn delete_invoice_item(&self, item_id: &str) -> DbResult<bool> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        invoice::delete_invoice_item(&mut conn, item_id).map_err(DbError::InvoiceError)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn list_invoice_items(
+        &self,
+        limit: usize,
+        starting_after: Option<i32>,
+        customer: Option<&str>,
+        invoice: Option<&str>,
+        pending: Option<bool>,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<(Vec<InvoiceItemModel>, bool)> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        invoice::list_invoice_items(
+            &mut conn,
+            limit,
+            starting_after,
+            customer,
+            invoice,
+            pending,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::InvoiceError)
+    }
+
+    /// Record a coupon redemption once per reference, counted against the coupon's and the
+    /// promotion code's maximum redemptions
+    pub fn redeem_coupon(
+        &self,
+        new_redemption: &coupon_redemption::NewCouponRedemption,
+        coupon_max_redemptions: Option<i64>,
+        promotion_code_max_redemptions: Option<i64>,
+    ) -> DbResult<coupon_redemption::Redemption> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        new_redemption
+            .redeem(
+                &mut conn,
+                coupon_max_redemptions,
+                promotion_code_max_redemptions,
+            )
+            .map_err(DbError::CouponRedemptionError)
+    }
+
+    /// Times a coupon was redeemed
+    pub fn count_coupon_redemptions(
+        &self,
+        coupon: &str,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<i64> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        coupon_redemption::count_coupon_redemptions(&mut conn, coupon, payment_stack_id, is_sandbox)
+            .map_err(DbError::CouponRedemptionError)
+    }
+
+    /// Times a promotion code was redeemed
+    pub fn count_promotion_code_redemptions(
+        &self,
+        promotion_code: &str,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<i64> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        coupon_redemption::count_promotion_code_redemptions(
+            &mut conn,
+            promotion_code,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::CouponRedemptionError)
+    }
+
+    // ==================== Customer Methods ====================
+
+    pub fn insert_customer(&self, new_customer: &customer::NewCustomer) -> DbResult<CustomerModel> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        new_customer
+            .insert(&mut conn)
+            .map_err(DbError::CustomerError)
+    }
+
+    pub fn find_customer(
+        &self,
+        customer_id: &str,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Option<CustomerModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        customer::find_customer(&mut conn, customer_id, payment_stack_id, is_sandbox)
+            .map_err(DbError::CustomerError)
+    }
+
+    pub fn update_customer(
+        &self,
+        customer_id: &str,
+        changes: customer::UpdateCustomer,
+    ) -> DbResult<Option<CustomerModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        customer::update_customer(&mut conn, customer_id, changes).map_err(DbError::CustomerError)
+    }
+
+    /// Delete a customer and detach its wallets; returns whether it existed
+    pub fn delete_customer(&self, customer_id: &str) -> DbResult<bool> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        customer::delete_customer(&mut conn, customer_id).map_err(DbError::CustomerError)
+    }
+
+    pub fn list_customers(
+        &self,
+        limit: usize,
+        starting_after: Option<i32>,
+        email: Option<&str>,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<(Vec<CustomerModel>, bool)> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        customer::list_customers(
+            &mut conn,
+            limit,
+            starting_after,
+            email,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::CustomerError)
+    }
+
+    pub fn search_customers(
+        &self,
+        clauses: &[customer::CustomerSearchClause],
+        limit: usize,
+        starting_after: Option<i32>,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<(Vec<CustomerModel>, bool)> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        customer::search_customers(
+            &mut conn,
+            clauses,
+            limit,
+            starting_after,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::CustomerError)
+    }
+
+    /// Attach a wallet to a customer; returns false when the wallet is already attached to
+    /// a customer of the stack
+    pub fn attach_customer_wallet(&self, wallet: &customer::NewCustomerWallet) -> DbResult<bool> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        wallet
+            .insert_once(&mut conn)
+            .map_err(DbError::CustomerError)
+    }
+
+    /// Detach a wallet from a customer; returns whether it was attached
+    pub fn detach_customer_wallet(&self, customer_id: &str, address: &str) -> DbResult<bool> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        customer::detach_wallet(&mut conn, customer_id, address).map_err(DbError::CustomerError)
+    }
+
+    /// Wallet addresses attached to a customer
+    pub fn list_customer_wallets(&self, customer_id: &str) -> DbResult<Vec<String>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        customer::list_customer_wallets(&mut conn, customer_id).map_err(DbError::CustomerError)
+    }
+
+    /// Customer a wallet address is attached to
+    pub fn find_wallet_customer(
+        &self,
+        address: &str,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Option<String>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        customer::find_wallet_customer(&mut conn, address, payment_stack_id, is_sandbox)
+            .map_err(DbError::CustomerError)
+    }
+
+    /// Record an experiment exposure or conversion once; returns whether it was recorded
+    pub fn insert_experiment_event(
+        &self,
+        new_event: &experiment_event::NewExperimentEvent,
+    ) -> DbResult<bool> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        new_event
+            .insert_once(&mut conn)
+            .map_err(DbError::ExperimentEventError)
+    }
+
+    /// Exposures and conversions of an experiment, oldest first
+    pub fn list_experiment_events(
+        &self,
+        experiment_id: &str,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Vec<ExperimentEventModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        experiment_event::list_experiment_events(
+            &mut conn,
+            experiment_id,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::ExperimentEventError)
+    }
+
+    pub fn insert_stripe_event(
+        &self,
+        new_event: &stripe_event::NewStripeEvent,
+    ) -> DbResult<StripeEventModel> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        new_event
+            .insert(&mut conn)
+            .map_err(DbError::StripeEventError)
+    }
+
+    pub fn find_stripe_event(
+        &self,
+        event_id: &str,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Option<StripeEventModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        stripe_event::find_stripe_event(&mut conn, event_id, payment_stack_id, is_sandbox)
+            .map_err(DbError::StripeEventError)
+    }
+
+    pub fn list_stripe_events(
+        &self,
+        limit: usize,
+        starting_after: Option<i32>,
+        event_type: Option<&str>,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<(Vec<StripeEventModel>, bool)> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        stripe_event::list_stripe_events(
+            &mut conn,
+            limit,
+            starting_after,
+            event_type,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::StripeEventError)
+    }
+
+    // ==================== Solana Pay Methods ====================
+
+    /// Store the reference of a payment intent's transfer requests, or return the one
+    /// already stored
+    pub fn insert_solana_pay_reference(
+        &self,
+        new_payment: &solana_pay_payment::NewSolanaPayPayment,
+    ) -> DbResult<SolanaPayPaymentModel> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        new_payment
+            .insert_or_find(&mut conn)
+            .map_err(DbError::SolanaPayPaymentError)
+    }
+
+    /// Record the transaction paying a reference; returns false when the reference or
+    /// the transaction was already used
+    pub fn record_solana_pay_signature(&self, reference: &str, signature: &str) -> DbResult<bool> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        solana_pay_payment::record_signature(&mut conn, reference, signature)
+            .map_err(DbError::SolanaPayPaymentError)
+    }
+
+    // ==================== Payment Authorization Methods ====================
+
+    /// Store the authorization held for a manual-capture payment intent
+    pub fn upsert_payment_authorization(
+        &self,
+        new_authorization: &payment_authorization::NewPaymentAuthorization,
+    ) -> DbResult<()> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        new_authorization
+            .upsert(&mut conn)
+            .map_err(DbError::PaymentAuthorizationError)
+    }
+
+    pub fn list_payment_authorizations(
+        &self,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<Vec<PaymentAuthorizationModel>> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        payment_authorization::list_payment_authorizations(&mut conn, payment_stack_id, is_sandbox)
+            .map_err(DbError::PaymentAuthorizationError)
+    }
+
+    /// Delete the authorization held for a payment intent; returns whether there was one
+    pub fn delete_payment_authorization(
+        &self,
+        payment_intent_id: &str,
+        payment_stack_id: &str,
+        is_sandbox: bool,
+    ) -> DbResult<bool> {
+        let mut conn = self
+            .payment_db_conn
+            .get()
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+
+        payment_authorization::delete_payment_authorization(
+            &mut conn,
+            payment_intent_id,
+            payment_stack_id,
+            is_sandbox,
+        )
+        .map_err(DbError::PaymentAuthorizationError)
+    }
 }
@@ -130,3 +130,24 @@ pub fn find_by_event_id(
         .first(conn)
         .optional()
 }
+
+/// Events of `event_type` whose data mentions `needle`, newest first
+///
+/// Matching is done on the serialized event, so callers should check the parsed data.
+pub fn list_events_mentioning(
+    conn: &mut PooledConnection,
+    event_type: &str,
+    needle: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+    limit: i64,
+) -> QueryResult<Vec<CloudEventModel>> {
+    cloud_events::table
+        .filter(cloud_events::event_type.eq(event_type))
+        .filter(cloud_events::payment_stack_id.eq(payment_stack_id))
+        .filter(cloud_events::is_sandbox.eq(is_sandbox))
+        .filter(cloud_events::data_json.like(format!("%{}%", needle)))
+        .order(cloud_events::created_at.desc())
+        .limit(limit)
+        .load(conn)
+}
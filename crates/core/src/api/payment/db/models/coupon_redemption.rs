@@ -0,0 +1,209 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::api::payment::db::{
+    PooledConnection,
+    schema::{coupon_redemption_counts, coupon_redemptions},
+};
+
+/// `coupon_redemption_counts.object` of the counts kept for coupons
+const COUPON: &str = "coupon";
+/// `coupon_redemption_counts.object` of the counts kept for promotion codes
+const PROMOTION_CODE: &str = "promotion_code";
+
+/// Outcome of recording a coupon redemption
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redemption {
+    /// Recorded and counted by this call
+    Recorded,
+    /// Already recorded for the same reference
+    AlreadyRecorded,
+    /// The coupon or the promotion code already reached its maximum redemptions
+    MaxRedemptionsReached,
+}
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = coupon_redemptions)]
+pub struct CouponRedemptionModel {
+    pub id: i32,
+    pub coupon: String,
+    pub promotion_code: Option<String>,
+    pub customer: Option<String>,
+    pub reference: String,
+    pub amount_discount: i64,
+    pub currency: String,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = coupon_redemptions)]
+pub struct NewCouponRedemption {
+    pub coupon: String,
+    pub promotion_code: Option<String>,
+    pub customer: Option<String>,
+    pub reference: String,
+    pub amount_discount: i64,
+    pub currency: String,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+}
+
+impl NewCouponRedemption {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        coupon: String,
+        promotion_code: Option<String>,
+        customer: Option<String>,
+        reference: String,
+        amount_discount: i64,
+        currency: String,
+        payment_stack_id: String,
+        is_sandbox: bool,
+    ) -> Self {
+        Self {
+            coupon,
+            promotion_code,
+            customer,
+            reference,
+            amount_discount,
+            currency,
+            payment_stack_id,
+            is_sandbox,
+            created_at: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// Record the redemption, unless one was already recorded for the same reference, and
+    /// count it against the coupon's and the promotion code's maximum redemptions.
+    ///
+    /// Each count is taken with a single conditional UPDATE, so concurrent redemptions can't
+    /// go past a maximum; when one is reached nothing is recorded.
+    pub fn redeem(
+        &self,
+        conn: &mut PooledConnection,
+        coupon_max_redemptions: Option<i64>,
+        promotion_code_max_redemptions: Option<i64>,
+    ) -> QueryResult<Redemption> {
+        let redeemed = conn.transaction(|conn| {
+            let inserted = diesel::insert_into(coupon_redemptions::table)
+                .values(self)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+            if inserted == 0 {
+                return Ok(Redemption::AlreadyRecorded);
+            }
+            let counted = count_redemption(
+                conn,
+                COUPON,
+                &self.coupon,
+                coupon_max_redemptions,
+                &self.payment_stack_id,
+                self.is_sandbox,
+            )? && match &self.promotion_code {
+                Some(promotion_code) => count_redemption(
+                    conn,
+                    PROMOTION_CODE,
+                    promotion_code,
+                    promotion_code_max_redemptions,
+                    &self.payment_stack_id,
+                    self.is_sandbox,
+                )?,
+                None => true,
+            };
+            if counted {
+                Ok(Redemption::Recorded)
+            } else {
+                Err(diesel::result::Error::RollbackTransaction)
+            }
+        });
+        match redeemed {
+            Err(diesel::result::Error::RollbackTransaction) => {
+                Ok(Redemption::MaxRedemptionsReached)
+            }
+            redeemed => redeemed,
+        }
+    }
+}
+
+/// Count one more redemption of a coupon or a promotion code, unless it already reached
+/// `max_redemptions`; returns whether it was counted
+fn count_redemption(
+    conn: &mut PooledConnection,
+    object: &str,
+    object_id: &str,
+    max_redemptions: Option<i64>,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<bool> {
+    diesel::insert_into(coupon_redemption_counts::table)
+        .values((
+            coupon_redemption_counts::object.eq(object),
+            coupon_redemption_counts::object_id.eq(object_id),
+            coupon_redemption_counts::times_redeemed.eq(0),
+            coupon_redemption_counts::payment_stack_id.eq(payment_stack_id),
+            coupon_redemption_counts::is_sandbox.eq(is_sandbox),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    diesel::update(
+        coupon_redemption_counts::table
+            .filter(coupon_redemption_counts::object.eq(object))
+            .filter(coupon_redemption_counts::object_id.eq(object_id))
+            .filter(coupon_redemption_counts::payment_stack_id.eq(payment_stack_id))
+            .filter(coupon_redemption_counts::is_sandbox.eq(is_sandbox))
+            .filter(
+                coupon_redemption_counts::times_redeemed.lt(max_redemptions.unwrap_or(i64::MAX)),
+            ),
+    )
+    .set(coupon_redemption_counts::times_redeemed.eq(coupon_redemption_counts::times_redeemed + 1))
+    .execute(conn)
+    .map(|updated| updated > 0)
+}
+
+/// Redemptions counted for a coupon or a promotion code
+fn times_redeemed(
+    conn: &mut PooledConnection,
+    object: &str,
+    object_id: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<i64> {
+    coupon_redemption_counts::table
+        .filter(coupon_redemption_counts::object.eq(object))
+        .filter(coupon_redemption_counts::object_id.eq(object_id))
+        .filter(coupon_redemption_counts::payment_stack_id.eq(payment_stack_id))
+        .filter(coupon_redemption_counts::is_sandbox.eq(is_sandbox))
+        .select(coupon_redemption_counts::times_redeemed)
+        .first(conn)
+        .optional()
+        .map(Option::unwrap_or_default)
+}
+
+/// Times a coupon was redeemed
+pub fn count_coupon_redemptions(
+    conn: &mut PooledConnection,
+    coupon: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<i64> {
+    times_redeemed(conn, COUPON, coupon, payment_stack_id, is_sandbox)
+}
+
+/// Times a promotion code was redeemed
+pub fn count_promotion_code_redemptions(
+    conn: &mut PooledConnection,
+    promotion_code: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<i64> {
+    times_redeemed(
+        conn,
+        PROMOTION_CODE,
+        promotion_code,
+        payment_stack_id,
+        is_sandbox,
+    )
+}
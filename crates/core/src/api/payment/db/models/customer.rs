@@ -0,0 +1,389 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::api::payment::db::{
+    PooledConnection,
+    schema::{customer_wallets, customers},
+};
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = customers)]
+pub struct CustomerModel {
+    pub id: i32,
+    pub customer_id: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub phone: Option<String>,
+    pub metadata: Option<String>,
+    pub test_clock: Option<String>,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl CustomerModel {
+    pub fn metadata(&self) -> std::collections::HashMap<String, String> {
+        self.metadata
+            .as_deref()
+            .and_then(|m| serde_json::from_str(m).ok())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = customers)]
+pub struct NewCustomer {
+    pub customer_id: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub phone: Option<String>,
+    pub metadata: Option<String>,
+    pub test_clock: Option<String>,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl NewCustomer {
+    pub fn new(customer_id: String, payment_stack_id: String, is_sandbox: bool) -> Self {
+        let now = chrono::Utc::now().timestamp_millis();
+        Self {
+            customer_id,
+            email: None,
+            name: None,
+            description: None,
+            phone: None,
+            metadata: None,
+            test_clock: None,
+            payment_stack_id,
+            is_sandbox,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn with_email(mut self, email: Option<String>) -> Self {
+        self.email = email;
+        self
+    }
+
+    pub fn with_name(mut self, name: Option<String>) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn with_description(mut self, description: Option<String>) -> Self {
+        self.description = description;
+        self
+    }
+
+    pub fn with_phone(mut self, phone: Option<String>) -> Self {
+        self.phone = phone;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: Option<String>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Attach the customer to a test clock, created at the clock's frozen time (Unix seconds)
+    pub fn with_test_clock(mut self, test_clock: String, frozen_time: i64) -> Self {
+        self.test_clock = Some(test_clock);
+        self.created_at = frozen_time * 1000;
+        self
+    }
+
+    pub fn insert(&self, conn: &mut PooledConnection) -> QueryResult<CustomerModel> {
+        diesel::insert_into(customers::table)
+            .values(self)
+            .returning(CustomerModel::as_returning())
+            .get_result(conn)
+    }
+}
+
+/// Changes to a customer; fields left to `None` are not updated, nullable fields use
+/// `Some(None)` to be cleared.
+#[derive(Debug, Default, AsChangeset)]
+#[diesel(table_name = customers)]
+pub struct UpdateCustomer {
+    pub email: Option<Option<String>>,
+    pub name: Option<Option<String>>,
+    pub description: Option<Option<String>>,
+    pub phone: Option<Option<String>>,
+    pub metadata: Option<Option<String>>,
+    pub updated_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = customer_wallets)]
+pub struct CustomerWalletModel {
+    pub id: i32,
+    pub customer_id: String,
+    pub address: String,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = customer_wallets)]
+pub struct NewCustomerWallet {
+    pub customer_id: String,
+    pub address: String,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+}
+
+impl NewCustomerWallet {
+    pub fn new(
+        customer_id: String,
+        address: String,
+        payment_stack_id: String,
+        is_sandbox: bool,
+    ) -> Self {
+        Self {
+            customer_id,
+            address,
+            payment_stack_id,
+            is_sandbox,
+            created_at: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// Attach the wallet, unless it is already attached to a customer of the stack.
+    ///
+    /// Returns whether it was attached by this call.
+    pub fn insert_once(&self, conn: &mut PooledConnection) -> QueryResult<bool> {
+        match diesel::insert_into(customer_wallets::table)
+            .values(self)
+            .execute(conn)
+        {
+            Ok(_) => Ok(true),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Field a customer search clause matches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CustomerSearchField {
+    Email,
+    Name,
+    Phone,
+    /// Value of a metadata key
+    Metadata(String),
+}
+
+/// One `field:'value'` (exact) or `field~'value'` (substring) clause of a customer search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomerSearchClause {
+    pub field: CustomerSearchField,
+    pub value: String,
+    pub substring: bool,
+}
+
+/// Escape the LIKE wildcards of `value`, with `\` as the escape character
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+pub fn find_customer(
+    conn: &mut PooledConnection,
+    customer_id: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Option<CustomerModel>> {
+    customers::table
+        .filter(customers::customer_id.eq(customer_id))
+        .filter(customers::payment_stack_id.eq(payment_stack_id))
+        .filter(customers::is_sandbox.eq(is_sandbox))
+        .first(conn)
+        .optional()
+}
+
+pub fn update_customer(
+    conn: &mut PooledConnection,
+    customer_id: &str,
+    mut changes: UpdateCustomer,
+) -> QueryResult<Option<CustomerModel>> {
+    changes.updated_at = Some(chrono::Utc::now().timestamp_millis());
+    diesel::update(customers::table.filter(customers::customer_id.eq(customer_id)))
+        .set(&changes)
+        .returning(CustomerModel::as_returning())
+        .get_result(conn)
+        .optional()
+}
+
+/// Delete a customer and detach its wallets; returns whether it existed
+pub fn delete_customer(conn: &mut PooledConnection, customer_id: &str) -> QueryResult<bool> {
+    conn.transaction(|conn| {
+        diesel::delete(
+            customer_wallets::table.filter(customer_wallets::customer_id.eq(customer_id)),
+        )
+        .execute(conn)?;
+        diesel::delete(customers::table.filter(customers::customer_id.eq(customer_id)))
+            .execute(conn)
+            .map(|deleted| deleted > 0)
+    })
+}
+
+/// List customers for a stack, newest first, with cursor pagination on the row ID
+pub fn list_customers(
+    conn: &mut PooledConnection,
+    limit: usize,
+    starting_after: Option<i32>,
+    email: Option<&str>,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<(Vec<CustomerModel>, bool)> {
+    let raw_limit = (limit + 1) as i64;
+
+    let mut query = customers::table
+        .filter(customers::payment_stack_id.eq(payment_stack_id))
+        .filter(customers::is_sandbox.eq(is_sandbox))
+        .order(customers::id.desc())
+        .into_boxed();
+
+    if let Some(after_id) = starting_after {
+        query = query.filter(customers::id.lt(after_id));
+    }
+    if let Some(email) = email {
+        query = query.filter(customers::email.eq(email.to_string()));
+    }
+
+    let mut rows: Vec<CustomerModel> = query.limit(raw_limit).load(conn)?;
+
+    let has_more = rows.len() > limit;
+    if has_more {
+        rows.pop();
+    }
+
+    Ok((rows, has_more))
+}
+
+/// Customers matching all `clauses`, newest first, with cursor pagination on the row ID
+pub fn search_customers(
+    conn: &mut PooledConnection,
+    clauses: &[CustomerSearchClause],
+    limit: usize,
+    starting_after: Option<i32>,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<(Vec<CustomerModel>, bool)> {
+    let raw_limit = (limit + 1) as i64;
+
+    let mut query = customers::table
+        .filter(customers::payment_stack_id.eq(payment_stack_id))
+        .filter(customers::is_sandbox.eq(is_sandbox))
+        .order(customers::id.desc())
+        .into_boxed();
+
+    if let Some(after_id) = starting_after {
+        query = query.filter(customers::id.lt(after_id));
+    }
+    for clause in clauses {
+        let pattern = if clause.substring {
+            format!("%{}%", escape_like(&clause.value))
+        } else {
+            escape_like(&clause.value)
+        };
+        query = match &clause.field {
+            CustomerSearchField::Email => query.filter(customers::email.like(pattern).escape('\\')),
+            CustomerSearchField::Name => query.filter(customers::name.like(pattern).escape('\\')),
+            CustomerSearchField::Phone => query.filter(customers::phone.like(pattern).escape('\\')),
+            CustomerSearchField::Metadata(key) => {
+                // Metadata is stored as a JSON object of strings
+                let entry = format!(
+                    "{}:{}",
+                    serde_json::Value::from(key.as_str()),
+                    serde_json::Value::from(clause.value.as_str())
+                );
+                query.filter(
+                    customers::metadata
+                        .like(format!("%{}%", escape_like(&entry)))
+                        .escape('\\'),
+                )
+            }
+        };
+    }
+
+    let mut rows: Vec<CustomerModel> = query.limit(raw_limit).load(conn)?;
+
+    let has_more = rows.len() > limit;
+    if has_more {
+        rows.pop();
+    }
+
+    Ok((rows, has_more))
+}
+
+/// Wallet addresses attached to a customer, in the order they were attached
+pub fn list_customer_wallets(
+    conn: &mut PooledConnection,
+    customer_id: &str,
+) -> QueryResult<Vec<String>> {
+    customer_wallets::table
+        .filter(customer_wallets::customer_id.eq(customer_id))
+        .order(customer_wallets::id.asc())
+        .select(customer_wallets::address)
+        .load(conn)
+}
+
+/// Customer a wallet address is attached to
+pub fn find_wallet_customer(
+    conn: &mut PooledConnection,
+    address: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Option<String>> {
+    customer_wallets::table
+        .filter(customer_wallets::address.eq(address))
+        .filter(customer_wallets::payment_stack_id.eq(payment_stack_id))
+        .filter(customer_wallets::is_sandbox.eq(is_sandbox))
+        .select(customer_wallets::customer_id)
+        .first(conn)
+        .optional()
+}
+
+/// Customers the given wallet addresses are attached to, as (address, customer ID)
+pub fn find_wallet_customers(
+    conn: &mut PooledConnection,
+    addresses: &[String],
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Vec<(String, String)>> {
+    customer_wallets::table
+        .filter(customer_wallets::address.eq_any(addresses))
+        .filter(customer_wallets::payment_stack_id.eq(payment_stack_id))
+        .filter(customer_wallets::is_sandbox.eq(is_sandbox))
+        .select((customer_wallets::address, customer_wallets::customer_id))
+        .load(conn)
+}
+
+/// Detach a wallet from a customer; returns whether it was attached
+pub fn detach_wallet(
+    conn: &mut PooledConnection,
+    customer_id: &str,
+    address: &str,
+) -> QueryResult<bool> {
+    diesel::delete(
+        customer_wallets::table
+            .filter(customer_wallets::customer_id.eq(customer_id))
+            .filter(customer_wallets::address.eq(address)),
+    )
+    .execute(conn)
+    .map(|deleted| deleted > 0)
+}
@@ -0,0 +1,124 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::api::payment::db::{PooledConnection, schema::experiment_events};
+
+/// A subject was shown a variant
+pub const KIND_EXPOSURE: &str = "exposure";
+/// A payment bought a variant
+pub const KIND_CONVERSION: &str = "conversion";
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = experiment_events)]
+pub struct ExperimentEventModel {
+    pub id: i32,
+    pub experiment_id: String,
+    pub variant_id: String,
+    pub kind: String,
+    pub subject: Option<String>,
+    pub reference: Option<String>,
+    pub amount: i64,
+    pub currency: Option<String>,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = experiment_events)]
+pub struct NewExperimentEvent {
+    pub experiment_id: String,
+    pub variant_id: String,
+    pub kind: String,
+    pub subject: Option<String>,
+    pub reference: Option<String>,
+    pub amount: i64,
+    pub currency: Option<String>,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+}
+
+impl NewExperimentEvent {
+    /// Exposure of `subject` to a variant
+    pub fn exposure(
+        experiment_id: String,
+        variant_id: String,
+        subject: Option<String>,
+        payment_stack_id: String,
+        is_sandbox: bool,
+    ) -> Self {
+        Self {
+            experiment_id,
+            variant_id,
+            kind: KIND_EXPOSURE.to_string(),
+            subject,
+            reference: None,
+            amount: 0,
+            currency: None,
+            payment_stack_id,
+            is_sandbox,
+            created_at: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// Conversion of a variant by the payment `reference`
+    #[allow(clippy::too_many_arguments)]
+    pub fn conversion(
+        experiment_id: String,
+        variant_id: String,
+        subject: Option<String>,
+        reference: String,
+        amount: i64,
+        currency: String,
+        payment_stack_id: String,
+        is_sandbox: bool,
+    ) -> Self {
+        Self {
+            experiment_id,
+            variant_id,
+            kind: KIND_CONVERSION.to_string(),
+            subject,
+            reference: Some(reference),
+            amount,
+            currency: Some(currency),
+            payment_stack_id,
+            is_sandbox,
+            created_at: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// Record the event, unless the subject was already exposed to the experiment or the
+    /// payment already converted the variant.
+    ///
+    /// Returns whether it was recorded by this call.
+    pub fn insert_once(&self, conn: &mut PooledConnection) -> QueryResult<bool> {
+        match diesel::insert_into(experiment_events::table)
+            .values(self)
+            .execute(conn)
+        {
+            Ok(_) => Ok(true),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Exposures and conversions of an experiment, oldest first
+pub fn list_experiment_events(
+    conn: &mut PooledConnection,
+    experiment_id: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Vec<ExperimentEventModel>> {
+    experiment_events::table
+        .filter(experiment_events::experiment_id.eq(experiment_id))
+        .filter(experiment_events::payment_stack_id.eq(payment_stack_id))
+        .filter(experiment_events::is_sandbox.eq(is_sandbox))
+        .order(experiment_events::id.asc())
+        .select(ExperimentEventModel::as_select())
+        .load(conn)
+}
@@ -97,6 +97,10 @@ pub struct FacilitatedTransactionModel {
     pub payment_stack_id: String,
     /// Whether this transaction was processed in sandbox mode
     pub is_sandbox: bool,
+    /// Tax broken out of the payment ([`moneymq_types::PaymentTax`] JSON)
+    pub tax: Option<String>,
+    /// Conversion into the settlement currency ([`moneymq_types::PaymentFx`] JSON)
+    pub fx: Option<String>,
 }
 
 #[derive(Debug, Queryable)]
@@ -106,10 +110,13 @@ pub struct FacilitatedTransactionWithCustomer {
 }
 
 impl FacilitatedTransactionWithCustomer {
+    /// List transactions for a stack, newest first, optionally only those paid from one of
+    /// `payers`
     pub fn list(
         conn: &mut PooledConnection,
         limit: usize,
         starting_after: Option<i32>,
+        payers: Option<&[String]>,
         payment_stack_id: &str,
         is_sandbox: bool,
     ) -> QueryResult<(Vec<FacilitatedTransactionWithCustomer>, bool)> {
@@ -126,6 +133,9 @@ impl FacilitatedTransactionWithCustomer {
         if let Some(after_id) = starting_after {
             query = query.filter(facilitated_transactions::id.lt(after_id));
         }
+        if let Some(payers) = payers {
+            query = query.filter(transaction_customers::address.eq_any(payers));
+        }
 
         let mut rows: Vec<(
             FacilitatedTransactionModel,
@@ -170,6 +180,16 @@ impl From<FacilitatedTransactionWithCustomer> for FacilitatedTransaction {
             x402_settle_response: val.facilitated.x402_settle_response,
             payment_stack_id: val.facilitated.payment_stack_id,
             is_sandbox: val.facilitated.is_sandbox,
+            tax: val
+                .facilitated
+                .tax
+                .as_deref()
+                .and_then(|tax| serde_json::from_str(tax).ok()),
+            fx: val
+                .facilitated
+                .fx
+                .as_deref()
+                .and_then(|fx| serde_json::from_str(fx).ok()),
         }
     }
 }
@@ -189,6 +209,8 @@ pub struct NewFacilitatedTransaction {
     pub payment_hash: Option<String>,
     pub payment_stack_id: String,
     pub is_sandbox: bool,
+    pub tax: Option<String>,
+    pub fx: Option<String>,
 }
 
 impl NewFacilitatedTransaction {
@@ -218,8 +240,23 @@ impl NewFacilitatedTransaction {
             payment_hash,
             payment_stack_id,
             is_sandbox,
+            tax: None,
+            fx: None,
         }
     }
+
+    /// Record the tax broken out of the payment
+    pub fn with_tax(mut self, tax: Option<&moneymq_types::PaymentTax>) -> Self {
+        self.tax = tax.and_then(|tax| serde_json::to_string(tax).ok());
+        self
+    }
+
+    /// Record the conversion of the priced amount into the settlement currency
+    pub fn with_fx(mut self, fx: Option<&moneymq_types::PaymentFx>) -> Self {
+        self.fx = fx.and_then(|fx| serde_json::to_string(fx).ok());
+        self
+    }
+
     pub fn insert(&self, conn: &mut PooledConnection) -> QueryResult<usize> {
         debug!(
             "Inserting facilitated transaction with amount: {}, currency: {:?}, product: {:?}, customer_id: {:?}",
@@ -0,0 +1,608 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::api::payment::db::{
+    PooledConnection,
+    schema::{invoice_items, invoices},
+};
+
+pub const STATUS_DRAFT: &str = "draft";
+/// Open: finalized and awaiting payment
+pub const STATUS_OPEN: &str = "open";
+pub const STATUS_PAID: &str = "paid";
+pub const STATUS_VOID: &str = "void";
+
+pub const BILLING_REASON_SUBSCRIPTION_CREATE: &str = "subscription_create";
+pub const BILLING_REASON_SUBSCRIPTION_CYCLE: &str = "subscription_cycle";
+pub const BILLING_REASON_MANUAL: &str = "manual";
+
+/// Line billing a subscribed price for a period
+pub const KIND_SUBSCRIPTION: &str = "subscription";
+/// Line billing metered usage above a price's included units
+pub const KIND_OVERAGE: &str = "overage";
+/// One-off item added through `/v1/invoiceitems`
+pub const KIND_INVOICE_ITEM: &str = "invoiceitem";
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = invoices)]
+pub struct InvoiceModel {
+    pub id: i32,
+    pub invoice_id: String,
+    pub customer: String,
+    pub subscription: Option<String>,
+    pub billing_reason: String,
+    pub status: String,
+    pub currency: String,
+    pub subtotal: i64,
+    pub discount: i64,
+    pub tax: i64,
+    pub total: i64,
+    pub amount_paid: i64,
+    pub tax_percent: Option<f64>,
+    pub period_start: i64,
+    pub period_end: i64,
+    pub payment_intent: Option<String>,
+    pub description: Option<String>,
+    pub metadata: Option<String>,
+    pub test_clock: Option<String>,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub finalized_at: Option<i64>,
+    pub paid_at: Option<i64>,
+    pub voided_at: Option<i64>,
+    /// Catalog coupon the discount is computed from
+    pub coupon: Option<String>,
+    /// Country automatic tax is charged for; `tax_percent` applies when unset
+    pub tax_country: Option<String>,
+    pub tax_state: Option<String>,
+    /// Tax broken out of the invoice ([`moneymq_types::PaymentTax`] JSON)
+    pub tax_details: Option<String>,
+}
+
+impl InvoiceModel {
+    /// Amount left to pay, in cents
+    pub fn amount_due(&self) -> i64 {
+        (self.total - self.amount_paid).max(0)
+    }
+
+    pub fn metadata(&self) -> std::collections::HashMap<String, String> {
+        self.metadata
+            .as_deref()
+            .and_then(|metadata| serde_json::from_str(metadata).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn tax_details(&self) -> Option<moneymq_types::PaymentTax> {
+        self.tax_details
+            .as_deref()
+            .and_then(|tax| serde_json::from_str(tax).ok())
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = invoices)]
+pub struct NewInvoice {
+    pub invoice_id: String,
+    pub customer: String,
+    pub subscription: Option<String>,
+    pub billing_reason: String,
+    pub status: String,
+    pub currency: String,
+    pub tax_percent: Option<f64>,
+    pub period_start: i64,
+    pub period_end: i64,
+    pub description: Option<String>,
+    pub metadata: Option<String>,
+    pub test_clock: Option<String>,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub coupon: Option<String>,
+    pub tax_country: Option<String>,
+    pub tax_state: Option<String>,
+}
+
+impl NewInvoice {
+    /// A draft invoice; its amounts are computed once its lines are attached
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        invoice_id: String,
+        customer: String,
+        billing_reason: &str,
+        currency: String,
+        period_start: i64,
+        period_end: i64,
+        payment_stack_id: String,
+        is_sandbox: bool,
+    ) -> Self {
+        let now = chrono::Utc::now().timestamp_millis();
+        Self {
+            invoice_id,
+            customer,
+            subscription: None,
+            billing_reason: billing_reason.to_string(),
+            status: STATUS_DRAFT.to_string(),
+            currency,
+            tax_percent: None,
+            period_start,
+            period_end,
+            description: None,
+            metadata: None,
+            test_clock: None,
+            payment_stack_id,
+            is_sandbox,
+            created_at: now,
+            updated_at: now,
+            coupon: None,
+            tax_country: None,
+            tax_state: None,
+        }
+    }
+
+    /// Bill a subscription
+    pub fn with_subscription(mut self, subscription: Option<String>) -> Self {
+        self.subscription = subscription;
+        self
+    }
+
+    /// Apply tax to the discounted subtotal, in percent
+    pub fn with_tax_percent(mut self, tax_percent: Option<f64>) -> Self {
+        self.tax_percent = tax_percent;
+        self
+    }
+
+    pub fn with_description(mut self, description: Option<String>) -> Self {
+        self.description = description;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: Option<String>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Attach the invoice to a sandbox test clock
+    pub fn with_test_clock(mut self, test_clock: Option<String>) -> Self {
+        self.test_clock = test_clock;
+        self
+    }
+
+    /// Discount the invoice with a catalog coupon
+    pub fn with_coupon(mut self, coupon: Option<String>) -> Self {
+        self.coupon = coupon;
+        self
+    }
+
+    /// Charge automatic tax for a jurisdiction instead of `tax_percent`
+    pub fn with_tax_location(mut self, country: Option<String>, state: Option<String>) -> Self {
+        self.tax_country = country;
+        self.tax_state = state;
+        self
+    }
+
+    pub fn insert(&self, conn: &mut PooledConnection) -> QueryResult<InvoiceModel> {
+        diesel::insert_into(invoices::table)
+            .values(self)
+            .returning(InvoiceModel::as_returning())
+            .get_result(conn)
+    }
+}
+
+/// Changes applied to an invoice. `None` fields are left untouched; nullable columns
+/// use `Some(None)` to be cleared.
+#[derive(Debug, Default, AsChangeset)]
+#[diesel(table_name = invoices)]
+pub struct UpdateInvoice {
+    pub status: Option<String>,
+    pub subtotal: Option<i64>,
+    pub discount: Option<i64>,
+    pub tax: Option<i64>,
+    pub total: Option<i64>,
+    pub amount_paid: Option<i64>,
+    pub tax_percent: Option<Option<f64>>,
+    pub tax_country: Option<Option<String>>,
+    pub tax_state: Option<Option<String>>,
+    pub tax_details: Option<Option<String>>,
+    pub payment_intent: Option<Option<String>>,
+    pub description: Option<Option<String>>,
+    pub metadata: Option<Option<String>>,
+    pub updated_at: Option<i64>,
+    pub finalized_at: Option<Option<i64>>,
+    pub paid_at: Option<Option<i64>>,
+    pub voided_at: Option<Option<i64>>,
+}
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = invoice_items)]
+pub struct InvoiceItemModel {
+    pub id: i32,
+    pub item_id: String,
+    pub customer: String,
+    pub invoice: Option<String>,
+    pub subscription: Option<String>,
+    pub price: Option<String>,
+    pub kind: String,
+    pub description: Option<String>,
+    pub quantity: i64,
+    pub unit_amount_decimal: f64,
+    pub amount: i64,
+    pub currency: String,
+    pub period_start: i64,
+    pub period_end: i64,
+    pub metadata: Option<String>,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+    /// Tax charged on the line (cents), computed with the invoice totals
+    pub amount_tax: i64,
+    /// Whether `amount_tax` is included in `amount`
+    pub tax_inclusive: bool,
+}
+
+impl InvoiceItemModel {
+    pub fn metadata(&self) -> std::collections::HashMap<String, String> {
+        self.metadata
+            .as_deref()
+            .and_then(|metadata| serde_json::from_str(metadata).ok())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = invoice_items)]
+pub struct NewInvoiceItem {
+    pub item_id: String,
+    pub customer: String,
+    pub invoice: Option<String>,
+    pub subscription: Option<String>,
+    pub price: Option<String>,
+    pub kind: String,
+    pub description: Option<String>,
+    pub quantity: i64,
+    pub unit_amount_decimal: f64,
+    pub amount: i64,
+    pub currency: String,
+    pub period_start: i64,
+    pub period_end: i64,
+    pub metadata: Option<String>,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+}
+
+impl NewInvoiceItem {
+    /// A pending item of `quantity` units at `unit_amount_decimal` cents, not attached to
+    /// an invoice yet
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        item_id: String,
+        customer: String,
+        kind: &str,
+        quantity: i64,
+        unit_amount_decimal: f64,
+        currency: String,
+        period_start: i64,
+        period_end: i64,
+        payment_stack_id: String,
+        is_sandbox: bool,
+    ) -> Self {
+        Self {
+            item_id,
+            customer,
+            invoice: None,
+            subscription: None,
+            price: None,
+            kind: kind.to_string(),
+            description: None,
+            quantity,
+            unit_amount_decimal,
+            amount: (quantity as f64 * unit_amount_decimal).round() as i64,
+            currency,
+            period_start,
+            period_end,
+            metadata: None,
+            payment_stack_id,
+            is_sandbox,
+            created_at: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// Set the line amount instead of deriving it from the unit amount
+    pub fn with_amount(mut self, amount: i64) -> Self {
+        self.amount = amount;
+        self
+    }
+
+    pub fn with_invoice(mut self, invoice: Option<String>) -> Self {
+        self.invoice = invoice;
+        self
+    }
+
+    pub fn with_subscription(mut self, subscription: Option<String>) -> Self {
+        self.subscription = subscription;
+        self
+    }
+
+    pub fn with_price(mut self, price: Option<String>) -> Self {
+        self.price = price;
+        self
+    }
+
+    pub fn with_description(mut self, description: Option<String>) -> Self {
+        self.description = description;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: Option<String>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn insert(&self, conn: &mut PooledConnection) -> QueryResult<InvoiceItemModel> {
+        diesel::insert_into(invoice_items::table)
+            .values(self)
+            .returning(InvoiceItemModel::as_returning())
+            .get_result(conn)
+    }
+}
+
+/// Insert an invoice with its lines, and attach the customer's pending items to it.
+///
+/// The invoice amounts are left to the caller: see [`update_invoice`].
+pub fn insert_invoice(
+    conn: &mut PooledConnection,
+    new_invoice: &NewInvoice,
+    lines: &[NewInvoiceItem],
+    include_pending: bool,
+) -> QueryResult<(InvoiceModel, Vec<InvoiceItemModel>)> {
+    conn.transaction(|conn| {
+        let invoice = new_invoice.insert(conn)?;
+        for line in lines {
+            line.clone()
+                .with_invoice(Some(invoice.invoice_id.clone()))
+                .insert(conn)?;
+        }
+        if include_pending {
+            diesel::update(
+                invoice_items::table
+                    .filter(invoice_items::invoice.is_null())
+                    .filter(invoice_items::customer.eq(&invoice.customer))
+                    .filter(invoice_items::currency.eq(&invoice.currency))
+                    .filter(invoice_items::payment_stack_id.eq(&invoice.payment_stack_id))
+                    .filter(invoice_items::is_sandbox.eq(invoice.is_sandbox)),
+            )
+            .set(invoice_items::invoice.eq(Some(invoice.invoice_id.clone())))
+            .execute(conn)?;
+        }
+        let lines = list_invoice_lines(conn, &invoice.invoice_id)?;
+        Ok((invoice, lines))
+    })
+}
+
+/// Record the tax computed on each line of an invoice, as (item ID, tax in cents,
+/// inclusive)
+pub fn update_line_taxes(
+    conn: &mut PooledConnection,
+    line_taxes: &[(String, i64, bool)],
+) -> QueryResult<()> {
+    conn.transaction(|conn| {
+        for (item_id, amount_tax, inclusive) in line_taxes {
+            diesel::update(invoice_items::table.filter(invoice_items::item_id.eq(item_id)))
+                .set((
+                    invoice_items::amount_tax.eq(amount_tax),
+                    invoice_items::tax_inclusive.eq(inclusive),
+                ))
+                .execute(conn)?;
+        }
+        Ok(())
+    })
+}
+
+/// Find an invoice by its public identifier
+pub fn find_invoice(
+    conn: &mut PooledConnection,
+    invoice_id: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Option<InvoiceModel>> {
+    invoices::table
+        .filter(invoices::invoice_id.eq(invoice_id))
+        .filter(invoices::payment_stack_id.eq(payment_stack_id))
+        .filter(invoices::is_sandbox.eq(is_sandbox))
+        .first::<InvoiceModel>(conn)
+        .optional()
+}
+
+/// Find the invoice paid by `payment_intent_id`
+pub fn find_by_payment_intent(
+    conn: &mut PooledConnection,
+    payment_intent_id: &str,
+) -> QueryResult<Option<InvoiceModel>> {
+    invoices::table
+        .filter(invoices::payment_intent.eq(payment_intent_id))
+        .first::<InvoiceModel>(conn)
+        .optional()
+}
+
+/// Apply `changes` to an invoice, only if it is still in status `expected_status`.
+///
+/// Returns the updated invoice, or None if the status changed.
+pub fn update_invoice(
+    conn: &mut PooledConnection,
+    invoice_id: &str,
+    expected_status: &str,
+    mut changes: UpdateInvoice,
+) -> QueryResult<Option<InvoiceModel>> {
+    changes.updated_at = Some(chrono::Utc::now().timestamp_millis());
+    diesel::update(
+        invoices::table
+            .filter(invoices::invoice_id.eq(invoice_id))
+            .filter(invoices::status.eq(expected_status)),
+    )
+    .set(&changes)
+    .returning(InvoiceModel::as_returning())
+    .get_result(conn)
+    .optional()
+}
+
+/// List invoices for a stack, newest first, with cursor pagination on the row ID
+#[allow(clippy::too_many_arguments)]
+pub fn list_invoices(
+    conn: &mut PooledConnection,
+    limit: usize,
+    starting_after: Option<i32>,
+    customer: Option<&str>,
+    subscription: Option<&str>,
+    status: Option<&str>,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<(Vec<InvoiceModel>, bool)> {
+    let raw_limit = (limit + 1) as i64;
+
+    let mut query = invoices::table
+        .filter(invoices::payment_stack_id.eq(payment_stack_id))
+        .filter(invoices::is_sandbox.eq(is_sandbox))
+        .order(invoices::id.desc())
+        .into_boxed();
+
+    if let Some(after_id) = starting_after {
+        query = query.filter(invoices::id.lt(after_id));
+    }
+    if let Some(customer) = customer {
+        query = query.filter(invoices::customer.eq(customer.to_string()));
+    }
+    if let Some(subscription) = subscription {
+        query = query.filter(invoices::subscription.eq(subscription.to_string()));
+    }
+    if let Some(status) = status {
+        query = query.filter(invoices::status.eq(status.to_string()));
+    }
+
+    let mut rows: Vec<InvoiceModel> = query.limit(raw_limit).load(conn)?;
+
+    let has_more = rows.len() > limit;
+    if has_more {
+        rows.pop();
+    }
+
+    Ok((rows, has_more))
+}
+
+/// Lines of an invoice, in the order they were added
+pub fn list_invoice_lines(
+    conn: &mut PooledConnection,
+    invoice_id: &str,
+) -> QueryResult<Vec<InvoiceItemModel>> {
+    invoice_items::table
+        .filter(invoice_items::invoice.eq(invoice_id))
+        .order(invoice_items::id.asc())
+        .load(conn)
+}
+
+/// Overage units of `price` already billed for the subscription period starting at
+/// `period_start`, on invoices that were not voided
+pub fn billed_overage_units(
+    conn: &mut PooledConnection,
+    subscription: &str,
+    price: &str,
+    period_start: i64,
+) -> QueryResult<i64> {
+    let void_invoices = invoices::table
+        .filter(invoices::status.eq(STATUS_VOID))
+        .select(invoices::invoice_id.nullable());
+    let quantities: Vec<i64> = invoice_items::table
+        .filter(invoice_items::kind.eq(KIND_OVERAGE))
+        .filter(invoice_items::subscription.eq(subscription))
+        .filter(invoice_items::price.eq(price))
+        .filter(invoice_items::period_start.eq(period_start))
+        .filter(diesel::dsl::not(
+            invoice_items::invoice.eq_any(void_invoices),
+        ))
+        .select(invoice_items::quantity)
+        .load(conn)?;
+    Ok(quantities.iter().sum())
+}
+
+/// Find an invoice item by its public identifier
+pub fn find_invoice_item(
+    conn: &mut PooledConnection,
+    item_id: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Option<InvoiceItemModel>> {
+    invoice_items::table
+        .filter(invoice_items::item_id.eq(item_id))
+        .filter(invoice_items::payment_stack_id.eq(payment_stack_id))
+        .filter(invoice_items::is_sandbox.eq(is_sandbox))
+        .first::<InvoiceItemModel>(conn)
+        .optional()
+}
+
+/// Delete an invoice item that is still pending or on a draft invoice.
+///
+/// Returns whether the item was deleted.
+pub fn delete_invoice_item(conn: &mut PooledConnection, item_id: &str) -> QueryResult<bool> {
+    let draft_invoices = invoices::table
+        .filter(invoices::status.eq(STATUS_DRAFT))
+        .select(invoices::invoice_id.nullable());
+    let deleted = diesel::delete(
+        invoice_items::table
+            .filter(invoice_items::item_id.eq(item_id))
+            .filter(
+                invoice_items::invoice
+                    .is_null()
+                    .or(invoice_items::invoice.eq_any(draft_invoices)),
+            ),
+    )
+    .execute(conn)?;
+    Ok(deleted > 0)
+}
+
+/// List invoice items for a stack, newest first, with cursor pagination on the row ID
+#[allow(clippy::too_many_arguments)]
+pub fn list_invoice_items(
+    conn: &mut PooledConnection,
+    limit: usize,
+    starting_after: Option<i32>,
+    customer: Option<&str>,
+    invoice: Option<&str>,
+    pending: Option<bool>,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<(Vec<InvoiceItemModel>, bool)> {
+    let raw_limit = (limit + 1) as i64;
+
+    let mut query = invoice_items::table
+        .filter(invoice_items::kind.eq(KIND_INVOICE_ITEM))
+        .filter(invoice_items::payment_stack_id.eq(payment_stack_id))
+        .filter(invoice_items::is_sandbox.eq(is_sandbox))
+        .order(invoice_items::id.desc())
+        .into_boxed();
+
+    if let Some(after_id) = starting_after {
+        query = query.filter(invoice_items::id.lt(after_id));
+    }
+    if let Some(customer) = customer {
+        query = query.filter(invoice_items::customer.eq(customer.to_string()));
+    }
+    if let Some(invoice) = invoice {
+        query = query.filter(invoice_items::invoice.eq(invoice.to_string()));
+    }
+    match pending {
+        Some(true) => query = query.filter(invoice_items::invoice.is_null()),
+        Some(false) => query = query.filter(invoice_items::invoice.is_not_null()),
+        None => {}
+    }
+
+    let mut rows: Vec<InvoiceItemModel> = query.limit(raw_limit).load(conn)?;
+
+    let has_more = rows.len() > limit;
+    if has_more {
+        rows.pop();
+    }
+
+    Ok((rows, has_more))
+}
@@ -0,0 +1,144 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::api::payment::db::{PooledConnection, schema::meter_events};
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = meter_events)]
+pub struct MeterEventModel {
+    pub id: i32,
+    pub identifier: String,
+    pub meter_id: String,
+    pub event_name: String,
+    pub customer: String,
+    pub value: f64,
+    pub timestamp: i64,
+    pub payload: String,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+}
+
+impl MeterEventModel {
+    pub fn payload(&self) -> std::collections::HashMap<String, String> {
+        serde_json::from_str(&self.payload).unwrap_or_default()
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = meter_events)]
+pub struct NewMeterEvent {
+    pub identifier: String,
+    pub meter_id: String,
+    pub event_name: String,
+    pub customer: String,
+    pub value: f64,
+    pub timestamp: i64,
+    pub payload: String,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+}
+
+impl NewMeterEvent {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        identifier: String,
+        meter_id: String,
+        event_name: String,
+        customer: String,
+        value: f64,
+        timestamp: i64,
+        payload: &std::collections::HashMap<String, String>,
+        payment_stack_id: String,
+        is_sandbox: bool,
+    ) -> Self {
+        Self {
+            identifier,
+            meter_id,
+            event_name,
+            customer,
+            value,
+            timestamp,
+            payload: serde_json::to_string(payload).unwrap_or_else(|_| "{}".to_string()),
+            payment_stack_id,
+            is_sandbox,
+            created_at: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    pub fn insert(&self, conn: &mut PooledConnection) -> QueryResult<MeterEventModel> {
+        diesel::insert_into(meter_events::table)
+            .values(self)
+            .returning(MeterEventModel::as_returning())
+            .get_result(conn)
+    }
+}
+
+/// Find a meter event by its identifier
+pub fn find_by_identifier(
+    conn: &mut PooledConnection,
+    identifier: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Option<MeterEventModel>> {
+    meter_events::table
+        .filter(meter_events::identifier.eq(identifier))
+        .filter(meter_events::payment_stack_id.eq(payment_stack_id))
+        .filter(meter_events::is_sandbox.eq(is_sandbox))
+        .first::<MeterEventModel>(conn)
+        .optional()
+}
+
+/// Insert a meter event unless one with the same identifier was already recorded.
+///
+/// Returns the stored event and whether it was created by this call.
+pub fn insert_or_find(
+    conn: &mut PooledConnection,
+    new_event: &NewMeterEvent,
+) -> QueryResult<(MeterEventModel, bool)> {
+    let find = |conn: &mut PooledConnection| {
+        find_by_identifier(
+            conn,
+            &new_event.identifier,
+            &new_event.payment_stack_id,
+            new_event.is_sandbox,
+        )
+    };
+    if let Some(existing) = find(conn)? {
+        return Ok((existing, false));
+    }
+    match new_event.insert(conn) {
+        Ok(created) => Ok((created, true)),
+        // Recorded concurrently by another request
+        Err(diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            _,
+        )) => find(conn)?
+            .map(|existing| (existing, false))
+            .ok_or(diesel::result::Error::NotFound),
+        Err(e) => Err(e),
+    }
+}
+
+/// List a customer's events for a meter with `start_time <= timestamp < end_time`,
+/// oldest first
+pub fn list_customer_events(
+    conn: &mut PooledConnection,
+    meter_id: &str,
+    customer: &str,
+    start_time: i64,
+    end_time: i64,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Vec<MeterEventModel>> {
+    meter_events::table
+        .filter(meter_events::meter_id.eq(meter_id))
+        .filter(meter_events::customer.eq(customer))
+        .filter(meter_events::timestamp.ge(start_time))
+        .filter(meter_events::timestamp.lt(end_time))
+        .filter(meter_events::payment_stack_id.eq(payment_stack_id))
+        .filter(meter_events::is_sandbox.eq(is_sandbox))
+        .order((meter_events::timestamp.asc(), meter_events::id.asc()))
+        .load(conn)
+}
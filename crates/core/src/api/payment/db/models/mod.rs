@@ -1,8 +1,28 @@
 pub mod cloud_event;
+pub mod coupon_redemption;
+pub mod customer;
 pub mod event_stream;
+pub mod experiment_event;
 pub mod facilitated_transaction;
+pub mod invoice;
+pub mod meter_event;
+pub mod payment_authorization;
+pub mod payment_channel;
+pub mod solana_pay_payment;
+pub mod stripe_event;
+pub mod subscription;
 pub mod transaction_customer;
 
 pub use cloud_event::CloudEventModel;
+pub use coupon_redemption::CouponRedemptionModel;
+pub use customer::CustomerModel;
 pub use event_stream::EventStreamModel;
+pub use experiment_event::ExperimentEventModel;
+pub use invoice::{InvoiceItemModel, InvoiceModel};
+pub use meter_event::MeterEventModel;
+pub use payment_authorization::PaymentAuthorizationModel;
+pub use payment_channel::PaymentChannelModel;
+pub use solana_pay_payment::SolanaPayPaymentModel;
+pub use stripe_event::StripeEventModel;
+pub use subscription::SubscriptionModel;
 pub use transaction_customer::TransactionCustomerModel;
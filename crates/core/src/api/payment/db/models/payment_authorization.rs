@@ -0,0 +1,79 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::api::payment::db::{PooledConnection, schema::payment_authorizations};
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = payment_authorizations)]
+pub struct PaymentAuthorizationModel {
+    pub id: i32,
+    pub payment_intent_id: String,
+    pub payment_intent: String,
+    pub payment_payload: String,
+    pub payment_requirements: String,
+    pub authorized_at: i64,
+    pub expires_at: i64,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = payment_authorizations)]
+pub struct NewPaymentAuthorization {
+    pub payment_intent_id: String,
+    pub payment_intent: String,
+    pub payment_payload: String,
+    pub payment_requirements: String,
+    pub authorized_at: i64,
+    pub expires_at: i64,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+}
+
+impl NewPaymentAuthorization {
+    /// Store the authorization, replacing one already held for the payment intent
+    pub fn upsert(&self, conn: &mut PooledConnection) -> QueryResult<()> {
+        diesel::insert_into(payment_authorizations::table)
+            .values(self)
+            .on_conflict((
+                payment_authorizations::payment_stack_id,
+                payment_authorizations::is_sandbox,
+                payment_authorizations::payment_intent_id,
+            ))
+            .do_update()
+            .set(self)
+            .execute(conn)
+            .map(|_| ())
+    }
+}
+
+/// Authorizations held for a stack
+pub fn list_payment_authorizations(
+    conn: &mut PooledConnection,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Vec<PaymentAuthorizationModel>> {
+    payment_authorizations::table
+        .filter(payment_authorizations::payment_stack_id.eq(payment_stack_id))
+        .filter(payment_authorizations::is_sandbox.eq(is_sandbox))
+        .order(payment_authorizations::id.asc())
+        .select(PaymentAuthorizationModel::as_select())
+        .load(conn)
+}
+
+/// Delete the authorization held for a payment intent; returns whether there was one
+pub fn delete_payment_authorization(
+    conn: &mut PooledConnection,
+    payment_intent_id: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<bool> {
+    diesel::delete(
+        payment_authorizations::table
+            .filter(payment_authorizations::payment_intent_id.eq(payment_intent_id))
+            .filter(payment_authorizations::payment_stack_id.eq(payment_stack_id))
+            .filter(payment_authorizations::is_sandbox.eq(is_sandbox)),
+    )
+    .execute(conn)
+    .map(|deleted| deleted > 0)
+}
@@ -0,0 +1,260 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::api::payment::db::{PooledConnection, schema::payment_channels};
+
+pub const STATUS_OPEN: &str = "open";
+/// Closing: no more vouchers are accepted, final payout in progress
+pub const STATUS_CLOSING: &str = "closing";
+pub const STATUS_CLOSED: &str = "closed";
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = payment_channels)]
+pub struct PaymentChannelModel {
+    pub id: i32,
+    pub channel_id: String,
+    pub payer: String,
+    pub operator_id: String,
+    pub escrow: String,
+    pub recipient: String,
+    pub asset: String,
+    pub token_program: String,
+    pub decimals: i32,
+    pub deposit_amount: i64,
+    pub cumulative_amount: i64,
+    pub settled_amount: i64,
+    pub voucher_signature: Option<String>,
+    pub deposit_signature: Option<String>,
+    pub last_settlement_signature: Option<String>,
+    pub status: String,
+    pub expires_at: i64,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl PaymentChannelModel {
+    pub fn is_open(&self) -> bool {
+        self.status == STATUS_OPEN
+    }
+
+    /// Amount covered by vouchers but not yet paid out on-chain
+    pub fn unsettled_amount(&self) -> i64 {
+        self.cumulative_amount - self.settled_amount
+    }
+
+    /// Amount of the deposit not yet spent by vouchers
+    pub fn remaining_amount(&self) -> i64 {
+        self.deposit_amount - self.cumulative_amount
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = payment_channels)]
+pub struct NewPaymentChannel {
+    pub channel_id: String,
+    pub payer: String,
+    pub operator_id: String,
+    pub escrow: String,
+    pub recipient: String,
+    pub asset: String,
+    pub token_program: String,
+    pub decimals: i32,
+    pub deposit_amount: i64,
+    pub deposit_signature: Option<String>,
+    pub status: String,
+    pub expires_at: i64,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl NewPaymentChannel {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        channel_id: String,
+        payer: String,
+        operator_id: String,
+        escrow: String,
+        recipient: String,
+        asset: String,
+        token_program: String,
+        decimals: i32,
+        deposit_amount: i64,
+        deposit_signature: Option<String>,
+        expires_at: i64,
+        payment_stack_id: String,
+        is_sandbox: bool,
+    ) -> Self {
+        let now = chrono::Utc::now().timestamp_millis();
+        Self {
+            channel_id,
+            payer,
+            operator_id,
+            escrow,
+            recipient,
+            asset,
+            token_program,
+            decimals,
+            deposit_amount,
+            deposit_signature,
+            status: STATUS_OPEN.to_string(),
+            expires_at,
+            payment_stack_id,
+            is_sandbox,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn insert(&self, conn: &mut PooledConnection) -> QueryResult<PaymentChannelModel> {
+        diesel::insert_into(payment_channels::table)
+            .values(self)
+            .returning(PaymentChannelModel::as_returning())
+            .get_result(conn)
+    }
+}
+
+/// Find a channel by its public identifier
+pub fn find_channel(
+    conn: &mut PooledConnection,
+    channel_id: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Option<PaymentChannelModel>> {
+    payment_channels::table
+        .filter(payment_channels::channel_id.eq(channel_id))
+        .filter(payment_channels::payment_stack_id.eq(payment_stack_id))
+        .filter(payment_channels::is_sandbox.eq(is_sandbox))
+        .first::<PaymentChannelModel>(conn)
+        .optional()
+}
+
+/// Record a newer voucher for an open channel.
+///
+/// The update only applies if the stored cumulative amount still equals `previous_amount`,
+/// so two requests racing with vouchers built on the same balance can't both be accepted.
+/// Returns the number of rows updated (0 if the channel moved on or is no longer open).
+pub fn record_voucher(
+    conn: &mut PooledConnection,
+    channel_id: &str,
+    previous_amount: i64,
+    cumulative_amount: i64,
+    voucher_signature: &str,
+) -> QueryResult<usize> {
+    diesel::update(
+        payment_channels::table
+            .filter(payment_channels::channel_id.eq(channel_id))
+            .filter(payment_channels::status.eq(STATUS_OPEN))
+            .filter(payment_channels::cumulative_amount.eq(previous_amount)),
+    )
+    .set((
+        payment_channels::cumulative_amount.eq(cumulative_amount),
+        payment_channels::voucher_signature.eq(voucher_signature),
+        payment_channels::updated_at.eq(chrono::Utc::now().timestamp_millis()),
+    ))
+    .execute(conn)
+}
+
+/// Move the settled amount of a channel from `previous_amount` to `settled_amount`.
+///
+/// Payouts reserve the amount they are about to transfer with this compare-and-swap before
+/// sending anything on-chain, so concurrent settlements can't pay the same balance twice.
+/// Returns 0 if the settled amount is no longer `previous_amount`.
+pub fn update_settled_amount(
+    conn: &mut PooledConnection,
+    channel_id: &str,
+    previous_amount: i64,
+    settled_amount: i64,
+) -> QueryResult<usize> {
+    diesel::update(
+        payment_channels::table
+            .filter(payment_channels::channel_id.eq(channel_id))
+            .filter(payment_channels::settled_amount.eq(previous_amount)),
+    )
+    .set((
+        payment_channels::settled_amount.eq(settled_amount),
+        payment_channels::updated_at.eq(chrono::Utc::now().timestamp_millis()),
+    ))
+    .execute(conn)
+}
+
+/// Record the signature of the latest on-chain payout
+pub fn record_settlement_signature(
+    conn: &mut PooledConnection,
+    channel_id: &str,
+    signature: &str,
+) -> QueryResult<usize> {
+    diesel::update(payment_channels::table.filter(payment_channels::channel_id.eq(channel_id)))
+        .set((
+            payment_channels::last_settlement_signature.eq(signature),
+            payment_channels::updated_at.eq(chrono::Utc::now().timestamp_millis()),
+        ))
+        .execute(conn)
+}
+
+/// Move a channel from status `from` to status `to`.
+/// Returns 0 if the channel was not in status `from`.
+pub fn transition_status(
+    conn: &mut PooledConnection,
+    channel_id: &str,
+    from: &str,
+    to: &str,
+) -> QueryResult<usize> {
+    diesel::update(
+        payment_channels::table
+            .filter(payment_channels::channel_id.eq(channel_id))
+            .filter(payment_channels::status.eq(from)),
+    )
+    .set((
+        payment_channels::status.eq(to),
+        payment_channels::updated_at.eq(chrono::Utc::now().timestamp_millis()),
+    ))
+    .execute(conn)
+}
+
+/// List open channels for a stack, for the background settlement task
+pub fn list_open_channels(
+    conn: &mut PooledConnection,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Vec<PaymentChannelModel>> {
+    payment_channels::table
+        .filter(payment_channels::status.eq(STATUS_OPEN))
+        .filter(payment_channels::payment_stack_id.eq(payment_stack_id))
+        .filter(payment_channels::is_sandbox.eq(is_sandbox))
+        .order(payment_channels::id.asc())
+        .load(conn)
+}
+
+/// List channels for a stack, newest first, with cursor pagination on the row ID
+pub fn list_channels(
+    conn: &mut PooledConnection,
+    limit: usize,
+    starting_after: Option<i32>,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<(Vec<PaymentChannelModel>, bool)> {
+    let raw_limit = (limit + 1) as i64;
+
+    let mut query = payment_channels::table
+        .filter(payment_channels::payment_stack_id.eq(payment_stack_id))
+        .filter(payment_channels::is_sandbox.eq(is_sandbox))
+        .order(payment_channels::id.desc())
+        .into_boxed();
+
+    if let Some(after_id) = starting_after {
+        query = query.filter(payment_channels::id.lt(after_id));
+    }
+
+    let mut rows: Vec<PaymentChannelModel> = query.limit(raw_limit).load(conn)?;
+
+    let has_more = rows.len() > limit;
+    if has_more {
+        rows.pop();
+    }
+
+    Ok((rows, has_more))
+}
@@ -0,0 +1,119 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::api::payment::db::{PooledConnection, schema::solana_pay_payments};
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = solana_pay_payments)]
+pub struct SolanaPayPaymentModel {
+    pub id: i32,
+    pub payment_intent_id: String,
+    pub reference: String,
+    pub signature: Option<String>,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+    pub paid_at: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = solana_pay_payments)]
+pub struct NewSolanaPayPayment {
+    pub payment_intent_id: String,
+    pub reference: String,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+}
+
+impl NewSolanaPayPayment {
+    pub fn new(
+        payment_intent_id: String,
+        reference: String,
+        payment_stack_id: String,
+        is_sandbox: bool,
+    ) -> Self {
+        Self {
+            payment_intent_id,
+            reference,
+            payment_stack_id,
+            is_sandbox,
+            created_at: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// Store the reference of the payment intent, or return the one already stored
+    pub fn insert_or_find(
+        &self,
+        conn: &mut PooledConnection,
+    ) -> QueryResult<SolanaPayPaymentModel> {
+        let find = |conn: &mut PooledConnection| {
+            find_by_payment_intent(
+                conn,
+                &self.payment_intent_id,
+                &self.payment_stack_id,
+                self.is_sandbox,
+            )
+        };
+        if let Some(existing) = find(conn)? {
+            return Ok(existing);
+        }
+        match diesel::insert_into(solana_pay_payments::table)
+            .values(self)
+            .returning(SolanaPayPaymentModel::as_returning())
+            .get_result(conn)
+        {
+            Ok(created) => Ok(created),
+            // Stored concurrently by another request
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => find(conn)?.ok_or(diesel::result::Error::NotFound),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+pub fn find_by_payment_intent(
+    conn: &mut PooledConnection,
+    payment_intent_id: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Option<SolanaPayPaymentModel>> {
+    solana_pay_payments::table
+        .filter(solana_pay_payments::payment_intent_id.eq(payment_intent_id))
+        .filter(solana_pay_payments::payment_stack_id.eq(payment_stack_id))
+        .filter(solana_pay_payments::is_sandbox.eq(is_sandbox))
+        .select(SolanaPayPaymentModel::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Record the transaction paying the payment intent of `reference`.
+///
+/// Returns false when the reference was already paid or the transaction already paid
+/// another payment intent.
+pub fn record_signature(
+    conn: &mut PooledConnection,
+    reference: &str,
+    signature: &str,
+) -> QueryResult<bool> {
+    match diesel::update(
+        solana_pay_payments::table
+            .filter(solana_pay_payments::reference.eq(reference))
+            .filter(solana_pay_payments::signature.is_null()),
+    )
+    .set((
+        solana_pay_payments::signature.eq(signature),
+        solana_pay_payments::paid_at.eq(chrono::Utc::now().timestamp_millis()),
+    ))
+    .execute(conn)
+    {
+        Ok(updated) => Ok(updated == 1),
+        Err(diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            _,
+        )) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,103 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::api::payment::db::{PooledConnection, schema::stripe_events};
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = stripe_events)]
+pub struct StripeEventModel {
+    pub id: i32,
+    pub event_id: String,
+    pub event_type: String,
+    pub data_json: String,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = stripe_events)]
+pub struct NewStripeEvent {
+    pub event_id: String,
+    pub event_type: String,
+    pub data_json: String,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+}
+
+impl NewStripeEvent {
+    pub fn new(
+        event_id: String,
+        event_type: String,
+        data_json: String,
+        payment_stack_id: String,
+        is_sandbox: bool,
+    ) -> Self {
+        Self {
+            event_id,
+            event_type,
+            data_json,
+            payment_stack_id,
+            is_sandbox,
+            created_at: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    pub fn insert(&self, conn: &mut PooledConnection) -> QueryResult<StripeEventModel> {
+        diesel::insert_into(stripe_events::table)
+            .values(self)
+            .returning(StripeEventModel::as_returning())
+            .get_result(conn)
+    }
+}
+
+pub fn find_stripe_event(
+    conn: &mut PooledConnection,
+    event_id: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Option<StripeEventModel>> {
+    stripe_events::table
+        .filter(stripe_events::event_id.eq(event_id))
+        .filter(stripe_events::payment_stack_id.eq(payment_stack_id))
+        .filter(stripe_events::is_sandbox.eq(is_sandbox))
+        .select(StripeEventModel::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Events newest first, with cursor pagination on the row ID
+pub fn list_stripe_events(
+    conn: &mut PooledConnection,
+    limit: usize,
+    starting_after: Option<i32>,
+    event_type: Option<&str>,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<(Vec<StripeEventModel>, bool)> {
+    let raw_limit = (limit + 1) as i64;
+
+    let mut query = stripe_events::table
+        .filter(stripe_events::payment_stack_id.eq(payment_stack_id))
+        .filter(stripe_events::is_sandbox.eq(is_sandbox))
+        .order(stripe_events::id.desc())
+        .select(StripeEventModel::as_select())
+        .into_boxed();
+
+    if let Some(after_id) = starting_after {
+        query = query.filter(stripe_events::id.lt(after_id));
+    }
+    if let Some(event_type) = event_type {
+        query = query.filter(stripe_events::event_type.eq(event_type.to_string()));
+    }
+
+    let mut rows: Vec<StripeEventModel> = query.limit(raw_limit).load(conn)?;
+
+    let has_more = rows.len() > limit;
+    if has_more {
+        rows.pop();
+    }
+
+    Ok((rows, has_more))
+}
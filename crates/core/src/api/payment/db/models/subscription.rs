@@ -0,0 +1,344 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::api::payment::db::{PooledConnection, schema::subscriptions};
+
+pub const STATUS_TRIALING: &str = "trialing";
+pub const STATUS_ACTIVE: &str = "active";
+/// Past due: the charge for the current period has not been paid yet
+pub const STATUS_PAST_DUE: &str = "past_due";
+/// Paused: no renewal charges are created until the subscription is resumed
+pub const STATUS_PAUSED: &str = "paused";
+pub const STATUS_CANCELED: &str = "canceled";
+
+/// Statuses that renew at the end of their period
+pub const RENEWABLE_STATUSES: [&str; 3] = [STATUS_TRIALING, STATUS_ACTIVE, STATUS_PAST_DUE];
+
+/// One subscribed price, stored as JSON in `subscriptions.items`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubscriptionItem {
+    pub id: String,
+    pub price: String,
+    pub quantity: i64,
+}
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = subscriptions)]
+pub struct SubscriptionModel {
+    pub id: i32,
+    pub subscription_id: String,
+    pub customer: String,
+    pub items: String,
+    pub currency: String,
+    pub recurring_interval: String,
+    pub interval_count: i32,
+    pub status: String,
+    pub current_period_start: i64,
+    pub current_period_end: i64,
+    pub trial_end: Option<i64>,
+    pub cancel_at_period_end: bool,
+    pub canceled_at: Option<i64>,
+    pub paused_at: Option<i64>,
+    pub latest_payment_intent: Option<String>,
+    pub metadata: Option<String>,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Sandbox test clock driving this subscription's time
+    pub test_clock: Option<String>,
+    /// Invoice billing the current period
+    pub latest_invoice: Option<String>,
+    /// Catalog coupon discounting the subscription's invoices
+    pub coupon: Option<String>,
+    /// Promotion code the coupon was redeemed with
+    pub promotion_code: Option<String>,
+    /// When the coupon stops applying (Unix seconds), None while it applies forever
+    pub discount_end: Option<i64>,
+    /// Country the subscription's invoices are taxed for
+    pub tax_country: Option<String>,
+    pub tax_state: Option<String>,
+}
+
+impl SubscriptionModel {
+    pub fn items(&self) -> Vec<SubscriptionItem> {
+        serde_json::from_str(&self.items).unwrap_or_default()
+    }
+
+    pub fn metadata(&self) -> std::collections::HashMap<String, String> {
+        self.metadata
+            .as_deref()
+            .and_then(|metadata| serde_json::from_str(metadata).ok())
+            .unwrap_or_default()
+    }
+
+    /// Coupon discounting the invoice of the period starting at `period_start`
+    pub fn coupon_for_period(&self, period_start: i64) -> Option<&str> {
+        self.coupon
+            .as_deref()
+            .filter(|_| self.discount_end.is_none_or(|end| period_start < end))
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = subscriptions)]
+pub struct NewSubscription {
+    pub subscription_id: String,
+    pub customer: String,
+    pub items: String,
+    pub currency: String,
+    pub recurring_interval: String,
+    pub interval_count: i32,
+    pub status: String,
+    pub current_period_start: i64,
+    pub current_period_end: i64,
+    pub trial_end: Option<i64>,
+    pub cancel_at_period_end: bool,
+    pub metadata: Option<String>,
+    pub payment_stack_id: String,
+    pub is_sandbox: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub test_clock: Option<String>,
+    pub coupon: Option<String>,
+    pub promotion_code: Option<String>,
+    pub discount_end: Option<i64>,
+    pub tax_country: Option<String>,
+    pub tax_state: Option<String>,
+}
+
+impl NewSubscription {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        subscription_id: String,
+        customer: String,
+        items: &[SubscriptionItem],
+        currency: String,
+        recurring_interval: String,
+        interval_count: i32,
+        status: String,
+        current_period_start: i64,
+        current_period_end: i64,
+        trial_end: Option<i64>,
+        cancel_at_period_end: bool,
+        metadata: Option<String>,
+        payment_stack_id: String,
+        is_sandbox: bool,
+    ) -> Self {
+        let now = chrono::Utc::now().timestamp_millis();
+        Self {
+            subscription_id,
+            customer,
+            items: serde_json::to_string(items).unwrap_or_else(|_| "[]".to_string()),
+            currency,
+            recurring_interval,
+            interval_count,
+            status,
+            current_period_start,
+            current_period_end,
+            trial_end,
+            cancel_at_period_end,
+            metadata,
+            payment_stack_id,
+            is_sandbox,
+            created_at: now,
+            updated_at: now,
+            test_clock: None,
+            coupon: None,
+            promotion_code: None,
+            discount_end: None,
+            tax_country: None,
+            tax_state: None,
+        }
+    }
+
+    /// Attach the subscription to a sandbox test clock
+    pub fn with_test_clock(mut self, test_clock: Option<String>) -> Self {
+        self.test_clock = test_clock;
+        self
+    }
+
+    /// Discount the subscription's invoices with `coupon` until `discount_end`
+    pub fn with_discount(
+        mut self,
+        coupon: Option<String>,
+        promotion_code: Option<String>,
+        discount_end: Option<i64>,
+    ) -> Self {
+        self.coupon = coupon;
+        self.promotion_code = promotion_code;
+        self.discount_end = discount_end;
+        self
+    }
+
+    /// Charge automatic tax for a jurisdiction on the subscription's invoices
+    pub fn with_tax_location(mut self, country: Option<String>, state: Option<String>) -> Self {
+        self.tax_country = country;
+        self.tax_state = state;
+        self
+    }
+
+    pub fn insert(&self, conn: &mut PooledConnection) -> QueryResult<SubscriptionModel> {
+        diesel::insert_into(subscriptions::table)
+            .values(self)
+            .returning(SubscriptionModel::as_returning())
+            .get_result(conn)
+    }
+}
+
+/// Changes applied to a subscription. `None` fields are left untouched; nullable columns
+/// use `Some(None)` to be cleared.
+#[derive(Debug, Default, AsChangeset)]
+#[diesel(table_name = subscriptions)]
+pub struct UpdateSubscription {
+    pub items: Option<String>,
+    pub status: Option<String>,
+    pub current_period_start: Option<i64>,
+    pub current_period_end: Option<i64>,
+    pub trial_end: Option<Option<i64>>,
+    pub cancel_at_period_end: Option<bool>,
+    pub canceled_at: Option<Option<i64>>,
+    pub paused_at: Option<Option<i64>>,
+    pub latest_payment_intent: Option<Option<String>>,
+    pub latest_invoice: Option<Option<String>>,
+    pub metadata: Option<Option<String>>,
+    pub updated_at: Option<i64>,
+}
+
+impl UpdateSubscription {
+    pub fn with_items(mut self, items: &[SubscriptionItem]) -> Self {
+        self.items = serde_json::to_string(items).ok();
+        self
+    }
+}
+
+/// Find a subscription by its public identifier
+pub fn find_subscription(
+    conn: &mut PooledConnection,
+    subscription_id: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Option<SubscriptionModel>> {
+    subscriptions::table
+        .filter(subscriptions::subscription_id.eq(subscription_id))
+        .filter(subscriptions::payment_stack_id.eq(payment_stack_id))
+        .filter(subscriptions::is_sandbox.eq(is_sandbox))
+        .first::<SubscriptionModel>(conn)
+        .optional()
+}
+
+/// Apply `changes` to a subscription, only if it is still in status `expected_status`.
+///
+/// The status check keeps the renewal task and API requests from overwriting each
+/// other's transitions. Returns the updated subscription, or None if the status changed.
+pub fn update_subscription(
+    conn: &mut PooledConnection,
+    subscription_id: &str,
+    expected_status: &str,
+    mut changes: UpdateSubscription,
+) -> QueryResult<Option<SubscriptionModel>> {
+    changes.updated_at = Some(chrono::Utc::now().timestamp_millis());
+    diesel::update(
+        subscriptions::table
+            .filter(subscriptions::subscription_id.eq(subscription_id))
+            .filter(subscriptions::status.eq(expected_status)),
+    )
+    .set(&changes)
+    .returning(SubscriptionModel::as_returning())
+    .get_result(conn)
+    .optional()
+}
+
+/// Find the subscription whose current period is charged by `payment_intent_id`
+pub fn find_by_payment_intent(
+    conn: &mut PooledConnection,
+    payment_intent_id: &str,
+) -> QueryResult<Option<SubscriptionModel>> {
+    subscriptions::table
+        .filter(subscriptions::latest_payment_intent.eq(payment_intent_id))
+        .first::<SubscriptionModel>(conn)
+        .optional()
+}
+
+/// List subscriptions whose period ended at or before `now` (Unix seconds).
+///
+/// Only subscriptions attached to `test_clock` are listed, or none attached to a test
+/// clock when it is None.
+pub fn list_due_subscriptions(
+    conn: &mut PooledConnection,
+    now: i64,
+    test_clock: Option<&str>,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Vec<SubscriptionModel>> {
+    let mut query = subscriptions::table
+        .filter(subscriptions::status.eq_any(RENEWABLE_STATUSES))
+        .filter(subscriptions::current_period_end.le(now))
+        .filter(subscriptions::payment_stack_id.eq(payment_stack_id))
+        .filter(subscriptions::is_sandbox.eq(is_sandbox))
+        .order(subscriptions::current_period_end.asc())
+        .into_boxed();
+
+    query = match test_clock {
+        Some(test_clock) => query.filter(subscriptions::test_clock.eq(test_clock.to_string())),
+        None => query.filter(subscriptions::test_clock.is_null()),
+    };
+
+    query.load(conn)
+}
+
+/// List the subscriptions attached to a test clock that are not canceled yet
+pub fn list_test_clock_subscriptions(
+    conn: &mut PooledConnection,
+    test_clock: &str,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<Vec<SubscriptionModel>> {
+    subscriptions::table
+        .filter(subscriptions::test_clock.eq(test_clock))
+        .filter(subscriptions::status.ne(STATUS_CANCELED))
+        .filter(subscriptions::payment_stack_id.eq(payment_stack_id))
+        .filter(subscriptions::is_sandbox.eq(is_sandbox))
+        .load(conn)
+}
+
+/// List subscriptions for a stack, newest first, with cursor pagination on the row ID
+pub fn list_subscriptions(
+    conn: &mut PooledConnection,
+    limit: usize,
+    starting_after: Option<i32>,
+    customer: Option<&str>,
+    status: Option<&str>,
+    payment_stack_id: &str,
+    is_sandbox: bool,
+) -> QueryResult<(Vec<SubscriptionModel>, bool)> {
+    let raw_limit = (limit + 1) as i64;
+
+    let mut query = subscriptions::table
+        .filter(subscriptions::payment_stack_id.eq(payment_stack_id))
+        .filter(subscriptions::is_sandbox.eq(is_sandbox))
+        .order(subscriptions::id.desc())
+        .into_boxed();
+
+    if let Some(after_id) = starting_after {
+        query = query.filter(subscriptions::id.lt(after_id));
+    }
+    if let Some(customer) = customer {
+        query = query.filter(subscriptions::customer.eq(customer.to_string()));
+    }
+    match status {
+        // Stripe lists every status except canceled by default
+        None => query = query.filter(subscriptions::status.ne(STATUS_CANCELED)),
+        Some("all") => {}
+        Some(status) => query = query.filter(subscriptions::status.eq(status.to_string())),
+    }
+
+    let mut rows: Vec<SubscriptionModel> = query.limit(raw_limit).load(conn)?;
+
+    let has_more = rows.len() > limit;
+    if has_more {
+        rows.pop();
+    }
+
+    Ok((rows, has_more))
+}
@@ -20,6 +20,7 @@ impl From<TransactionCustomerModel> for TransactionCustomer {
         TransactionCustomer {
             label: val.label,
             address: val.address,
+            customer: None,
         }
     }
 }
@@ -17,6 +17,8 @@ diesel::table! {
         payment_hash -> Nullable<Text>,
         payment_stack_id -> Text,
         is_sandbox -> Bool,
+        tax -> Nullable<Text>,
+        fx -> Nullable<Text>,
     }
 }
 
@@ -57,11 +59,267 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    payment_channels (id) {
+        id -> Int4,
+        channel_id -> Text,
+        payer -> Text,
+        operator_id -> Text,
+        escrow -> Text,
+        recipient -> Text,
+        asset -> Text,
+        token_program -> Text,
+        decimals -> Int4,
+        deposit_amount -> Int8,
+        cumulative_amount -> Int8,
+        settled_amount -> Int8,
+        voucher_signature -> Nullable<Text>,
+        deposit_signature -> Nullable<Text>,
+        last_settlement_signature -> Nullable<Text>,
+        status -> Text,
+        expires_at -> Int8,
+        payment_stack_id -> Text,
+        is_sandbox -> Bool,
+        created_at -> Int8,
+        updated_at -> Int8,
+    }
+}
+
+diesel::table! {
+    subscriptions (id) {
+        id -> Int4,
+        subscription_id -> Text,
+        customer -> Text,
+        items -> Text,
+        currency -> Text,
+        recurring_interval -> Text,
+        interval_count -> Int4,
+        status -> Text,
+        current_period_start -> Int8,
+        current_period_end -> Int8,
+        trial_end -> Nullable<Int8>,
+        cancel_at_period_end -> Bool,
+        canceled_at -> Nullable<Int8>,
+        paused_at -> Nullable<Int8>,
+        latest_payment_intent -> Nullable<Text>,
+        metadata -> Nullable<Text>,
+        payment_stack_id -> Text,
+        is_sandbox -> Bool,
+        created_at -> Int8,
+        updated_at -> Int8,
+        test_clock -> Nullable<Text>,
+        latest_invoice -> Nullable<Text>,
+        coupon -> Nullable<Text>,
+        promotion_code -> Nullable<Text>,
+        discount_end -> Nullable<Int8>,
+        tax_country -> Nullable<Text>,
+        tax_state -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    meter_events (id) {
+        id -> Int4,
+        identifier -> Text,
+        meter_id -> Text,
+        event_name -> Text,
+        customer -> Text,
+        value -> Double,
+        timestamp -> Int8,
+        payload -> Text,
+        payment_stack_id -> Text,
+        is_sandbox -> Bool,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    invoices (id) {
+        id -> Int4,
+        invoice_id -> Text,
+        customer -> Text,
+        subscription -> Nullable<Text>,
+        billing_reason -> Text,
+        status -> Text,
+        currency -> Text,
+        subtotal -> Int8,
+        discount -> Int8,
+        tax -> Int8,
+        total -> Int8,
+        amount_paid -> Int8,
+        tax_percent -> Nullable<Double>,
+        period_start -> Int8,
+        period_end -> Int8,
+        payment_intent -> Nullable<Text>,
+        description -> Nullable<Text>,
+        metadata -> Nullable<Text>,
+        test_clock -> Nullable<Text>,
+        payment_stack_id -> Text,
+        is_sandbox -> Bool,
+        created_at -> Int8,
+        updated_at -> Int8,
+        finalized_at -> Nullable<Int8>,
+        paid_at -> Nullable<Int8>,
+        voided_at -> Nullable<Int8>,
+        coupon -> Nullable<Text>,
+        tax_country -> Nullable<Text>,
+        tax_state -> Nullable<Text>,
+        tax_details -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    invoice_items (id) {
+        id -> Int4,
+        item_id -> Text,
+        customer -> Text,
+        invoice -> Nullable<Text>,
+        subscription -> Nullable<Text>,
+        price -> Nullable<Text>,
+        kind -> Text,
+        description -> Nullable<Text>,
+        quantity -> Int8,
+        unit_amount_decimal -> Double,
+        amount -> Int8,
+        currency -> Text,
+        period_start -> Int8,
+        period_end -> Int8,
+        metadata -> Nullable<Text>,
+        payment_stack_id -> Text,
+        is_sandbox -> Bool,
+        created_at -> Int8,
+        amount_tax -> Int8,
+        tax_inclusive -> Bool,
+    }
+}
+
 diesel::joinable!(facilitated_transactions -> transaction_customers (customer_id));
 
+diesel::table! {
+    coupon_redemptions (id) {
+        id -> Int4,
+        coupon -> Text,
+        promotion_code -> Nullable<Text>,
+        customer -> Nullable<Text>,
+        reference -> Text,
+        amount_discount -> Int8,
+        currency -> Text,
+        payment_stack_id -> Text,
+        is_sandbox -> Bool,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    coupon_redemption_counts (id) {
+        id -> Int4,
+        object -> Text,
+        object_id -> Text,
+        times_redeemed -> Int8,
+        payment_stack_id -> Text,
+        is_sandbox -> Bool,
+    }
+}
+
+diesel::table! {
+    customers (id) {
+        id -> Int4,
+        customer_id -> Text,
+        email -> Nullable<Text>,
+        name -> Nullable<Text>,
+        description -> Nullable<Text>,
+        phone -> Nullable<Text>,
+        metadata -> Nullable<Text>,
+        test_clock -> Nullable<Text>,
+        payment_stack_id -> Text,
+        is_sandbox -> Bool,
+        created_at -> Int8,
+        updated_at -> Int8,
+    }
+}
+
+diesel::table! {
+    customer_wallets (id) {
+        id -> Int4,
+        customer_id -> Text,
+        address -> Text,
+        payment_stack_id -> Text,
+        is_sandbox -> Bool,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    experiment_events (id) {
+        id -> Int4,
+        experiment_id -> Text,
+        variant_id -> Text,
+        kind -> Text,
+        subject -> Nullable<Text>,
+        reference -> Nullable<Text>,
+        amount -> Int8,
+        currency -> Nullable<Text>,
+        payment_stack_id -> Text,
+        is_sandbox -> Bool,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    stripe_events (id) {
+        id -> Int4,
+        event_id -> Text,
+        event_type -> Text,
+        data_json -> Text,
+        payment_stack_id -> Text,
+        is_sandbox -> Bool,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    solana_pay_payments (id) {
+        id -> Int4,
+        payment_intent_id -> Text,
+        reference -> Text,
+        signature -> Nullable<Text>,
+        payment_stack_id -> Text,
+        is_sandbox -> Bool,
+        created_at -> Int8,
+        paid_at -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    payment_authorizations (id) {
+        id -> Int4,
+        payment_intent_id -> Text,
+        payment_intent -> Text,
+        payment_payload -> Text,
+        payment_requirements -> Text,
+        authorized_at -> Int8,
+        expires_at -> Int8,
+        payment_stack_id -> Text,
+        is_sandbox -> Bool,
+    }
+}
+
 diesel::allow_tables_to_appear_in_same_query!(
     facilitated_transactions,
     transaction_customers,
     cloud_events,
     event_streams,
+    payment_channels,
+    subscriptions,
+    meter_events,
+    invoices,
+    invoice_items,
+    coupon_redemptions,
+    coupon_redemption_counts,
+    customers,
+    customer_wallets,
+    experiment_events,
+    stripe_events,
+    solana_pay_payments,
+    payment_authorizations,
 );
@@ -21,6 +21,7 @@ pub async fn list_transactions(
         .list_transactions(
             limit,
             starting_after,
+            None,
             &state.payment_stack_id,
             state.is_sandbox,
         )
@@ -676,11 +676,15 @@ fn create_completion_event(manager: &Arc<ChannelManager>, channel_id: &str) -> C
                                     let quantity =
                                         item.get("quantity").and_then(|v| v.as_i64()).unwrap_or(1)
                                             as u32;
+                                    let discount = item
+                                        .get("discount")
+                                        .and_then(|v| serde_json::from_value(v.clone()).ok());
                                     Some(super::jwt::BasketItem {
                                         product_id,
                                         experiment_id: None,
                                         features: features_from_req.clone(),
                                         quantity,
+                                        discount,
                                     })
                                 })
                                 .collect()
@@ -717,7 +721,9 @@ fn create_completion_event(manager: &Arc<ChannelManager>, channel_id: &str) -> C
                         ctx.payment_stack_id.clone(),
                         defaults::JWT_EXPIRATION_HOURS,
                     )
-                    .with_attachments(attachments_map);
+                    .with_attachments(attachments_map)
+                    .with_tax(tx.tax.clone())
+                    .with_fx(tx.fx.clone());
 
                     // Sign the JWT
                     let currency = tx
@@ -1062,6 +1068,7 @@ mod tests {
                 experiment_id: None,
                 features: serde_json::Value::default(),
                 quantity: 1,
+                discount: None,
             }],
             payment: Some(PaymentDetails {
                 payer: "wallet123".to_string(),
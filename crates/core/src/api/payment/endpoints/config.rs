@@ -1,9 +1,10 @@
 use axum::{Extension, Json, extract::Query, response::IntoResponse};
 use moneymq_types::x402::USDC_MINT;
 use serde::{Deserialize, Serialize};
+use solana_keypair::Signer;
 use solana_pubkey::Pubkey;
 
-use crate::api::payment::PaymentApiConfig;
+use crate::api::payment::{PaymentApiConfig, endpoints::payment_channels::resolve_escrow_operator};
 
 /// Payout configuration for x402 payments
 #[derive(Debug, Clone, Serialize)]
@@ -28,6 +29,16 @@ pub struct FacilitatorConfig {
     pub address: Option<String>,
 }
 
+/// Payment channel configuration
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentChannelsConfig {
+    /// Operator actor holding channel deposits
+    pub operator: String,
+    /// Escrow address channel deposits must be paid to
+    pub escrow_address: String,
+}
+
 /// Solana network configuration for x402
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -36,6 +47,9 @@ pub struct SolanaX402Config {
     pub payout: PayoutConfig,
     /// Facilitator configuration
     pub facilitator: FacilitatorConfig,
+    /// Payment channel configuration (present when an escrow operator is configured)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_channels: Option<PaymentChannelsConfig>,
 }
 
 /// x402 protocol configuration
@@ -127,6 +141,12 @@ pub async fn handler(
         })
     });
 
+    let payment_channels =
+        resolve_escrow_operator(&state.actors(), None).map(|operator| PaymentChannelsConfig {
+            escrow_address: operator.keypair.pubkey().to_string(),
+            operator: operator.id,
+        });
+
     Json(ConfigResponse {
         is_sandbox: state.is_sandbox,
         x402: X402Config {
@@ -139,6 +159,7 @@ pub async fn handler(
                 facilitator: FacilitatorConfig {
                     address: facilitator_address,
                 },
+                payment_channels,
             },
         },
         stack: StackConfig {
@@ -11,7 +11,7 @@ use std::sync::Arc;
 
 use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
 // Re-export types from moneymq-types
-pub use moneymq_types::{BasketItem, defaults};
+pub use moneymq_types::{BasketItem, PaymentFx, PaymentTax, defaults};
 use p256::ecdsa::{SigningKey, VerifyingKey};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
@@ -33,6 +33,12 @@ pub struct PaymentDetails {
     /// On-chain transaction signature
     #[serde(skip_serializing_if = "Option::is_none")]
     pub signature: Option<String>,
+    /// Tax included in the amount
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax: Option<PaymentTax>,
+    /// Original amount and rate, when the payment was priced in another currency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fx: Option<PaymentFx>,
 }
 
 /// Attachments containing processor-provided data
@@ -88,6 +94,7 @@ impl PaymentReceiptClaims {
                 experiment_id: None,
                 features: features.unwrap_or_default(),
                 quantity: 1,
+                discount: None,
             }]
         } else {
             vec![]
@@ -100,6 +107,8 @@ impl PaymentReceiptClaims {
             currency,
             network: network.to_lowercase(),
             signature: transaction_signature,
+            tax: None,
+            fx: None,
         };
 
         Self {
@@ -135,6 +144,8 @@ impl PaymentReceiptClaims {
             currency,
             network: network.to_lowercase(),
             signature: transaction_signature,
+            tax: None,
+            fx: None,
         };
 
         Self {
@@ -154,6 +165,18 @@ impl PaymentReceiptClaims {
         self
     }
 
+    /// Break out the tax included in the payment amount
+    pub fn with_tax(mut self, tax: Option<PaymentTax>) -> Self {
+        self.payment.tax = tax;
+        self
+    }
+
+    /// Record the conversion of the priced amount into the settlement currency
+    pub fn with_fx(mut self, fx: Option<PaymentFx>) -> Self {
+        self.payment.fx = fx;
+        self
+    }
+
     /// Add features to all basket items
     pub fn with_features(mut self, features: serde_json::Value) -> Self {
         for item in &mut self.basket {
@@ -275,6 +298,49 @@ impl JwtKeyPair {
 
         Ok(format!("{}.{}", signing_input, signature_b64))
     }
+
+    /// Verify a payment receipt JWT signed with this key pair and return its claims.
+    ///
+    /// Fails for receipts signed by another key and for expired receipts.
+    pub fn verify(&self, token: &str) -> Result<PaymentReceiptClaims, String> {
+        use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+        use p256::ecdsa::signature::Verifier;
+
+        let (signing_input, signature_b64) = token
+            .rsplit_once('.')
+            .ok_or_else(|| "Malformed JWT".to_string())?;
+        let (header_b64, payload_b64) = signing_input
+            .split_once('.')
+            .ok_or_else(|| "Malformed JWT".to_string())?;
+
+        let header: serde_json::Value = URL_SAFE_NO_PAD
+            .decode(header_b64)
+            .ok()
+            .and_then(|header| serde_json::from_slice(&header).ok())
+            .ok_or_else(|| "Malformed JWT header".to_string())?;
+        if header["alg"] != "ES256" {
+            return Err("Unsupported JWT algorithm".to_string());
+        }
+
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature_b64)
+            .ok()
+            .and_then(|signature| p256::ecdsa::Signature::from_slice(&signature).ok())
+            .ok_or_else(|| "Malformed JWT signature".to_string())?;
+        self.verifying_key
+            .verify(signing_input.as_bytes(), &signature)
+            .map_err(|_| "Invalid JWT signature".to_string())?;
+
+        let claims: PaymentReceiptClaims = URL_SAFE_NO_PAD
+            .decode(payload_b64)
+            .ok()
+            .and_then(|payload| serde_json::from_slice(&payload).ok())
+            .ok_or_else(|| "Malformed JWT claims".to_string())?;
+        if claims.exp <= chrono::Utc::now().timestamp() {
+            return Err("Expired JWT".to_string());
+        }
+        Ok(claims)
+    }
 }
 
 /// JWT signer for payment receipts (legacy HS256 compatibility)
@@ -419,6 +485,53 @@ mod tests {
         assert_eq!(token.matches('.').count(), 2); // JWT has 3 parts
     }
 
+    #[test]
+    fn test_verify_payment_receipt_jwt() {
+        let key_pair = JwtKeyPair::from_secret("test-secret");
+        let claims = PaymentReceiptClaims::new(
+            "tx_123".to_string(),
+            "payer".to_string(),
+            "1000".to_string(),
+            defaults::CURRENCY.to_string(),
+            defaults::NETWORK.to_string(),
+            Some("prod_abc".to_string()),
+            None,
+            None,
+            "issuer".to_string(),
+            defaults::JWT_EXPIRATION_HOURS,
+        );
+        let token = key_pair.sign(&claims).unwrap();
+
+        let verified = key_pair.verify(&token).unwrap();
+        assert_eq!(verified.sub, "tx_123");
+        assert_eq!(verified.payment.payer, "payer");
+
+        // Receipts of another key
+        assert!(
+            JwtKeyPair::from_secret("other-secret")
+                .verify(&token)
+                .is_err()
+        );
+
+        // Tampered claims
+        let mut forged = claims.clone();
+        forged.payment.payer = "someone_else".to_string();
+        let forged_payload = base64::Engine::encode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+            serde_json::to_string(&forged).unwrap(),
+        );
+        let parts = token.split('.').collect::<Vec<_>>();
+        let tampered = format!("{}.{}.{}", parts[0], forged_payload, parts[2]);
+        assert!(key_pair.verify(&tampered).is_err());
+
+        // Expired receipts
+        let mut expired = claims;
+        expired.exp = expired.iat - 1;
+        assert!(key_pair.verify(&key_pair.sign(&expired).unwrap()).is_err());
+
+        assert!(key_pair.verify("not-a-jwt").is_err());
+    }
+
     #[test]
     fn test_deterministic_key_derivation() {
         let key_pair1 = JwtKeyPair::from_secret("same-secret");
@@ -7,6 +7,7 @@ pub mod config;
 pub mod events;
 pub mod health;
 pub mod jwt;
+pub mod payment_channels;
 pub mod settle;
 pub mod supported;
 pub mod verify;
@@ -29,6 +30,46 @@ pub struct FacilitatorExtraContext {
     /// Product features (capabilities and limits from the purchased product)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub features: Option<serde_json::Value>,
+    /// Signed quote binding these requirements to the resource they were issued for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quote: Option<PaymentQuote>,
+    /// Tax included in the amount, broken out for receipts and transactions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax: Option<moneymq_types::PaymentTax>,
+    /// Conversion of the priced amount into the settlement currency, for receipts and
+    /// transactions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fx: Option<moneymq_types::PaymentFx>,
+}
+
+/// Quote attached to issued payment requirements (`extra.quote`)
+///
+/// The signature is produced by the catalog middleware and covers the resource URL, HTTP
+/// method, basket, asset, amount, recipient, expiry and nonce.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentQuote {
+    /// Random hex nonce, unique per issued quote
+    pub nonce: String,
+    /// HTTP method the quote was issued for
+    pub method: String,
+    /// Unix timestamp (seconds) after which the quote is no longer accepted
+    pub expires_at: i64,
+    /// Hex-encoded HMAC-SHA256 signature
+    pub signature: String,
+}
+
+impl PaymentQuote {
+    /// Read the quote from payment requirements `extra`, if present
+    pub fn from_extra(extra: Option<&serde_json::Value>) -> Option<Self> {
+        extra
+            .and_then(|extra| extra.get("quote"))
+            .and_then(|quote| serde_json::from_value(quote.clone()).ok())
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        now > self.expires_at
+    }
 }
 
 pub fn serialize_to_base64<T: serde::Serialize>(data: &T) -> String {
@@ -0,0 +1,1192 @@
+//! Off-chain payment channels for high-frequency micropayments
+//!
+//! A payer opens a channel by depositing tokens into an escrow owned by an operator actor.
+//! Every paid request then carries a voucher instead of an on-chain transaction: the
+//! cumulative amount spent on the channel so far, signed with the payer's key. Vouchers are
+//! verified off-chain, and only the net balance is paid out on-chain, periodically and when
+//! the channel closes. Whatever the vouchers did not spend is refunded to the payer on close.
+//!
+//! ## Endpoints
+//! - `POST /payment/v1/payment_channels` - Open a channel with an x402 deposit to the escrow
+//! - `GET /payment/v1/payment_channels/{channelId}` - Retrieve a channel
+//! - `POST /payment/v1/payment_channels/{channelId}/vouchers/verify` - Check a voucher
+//! - `POST /payment/v1/payment_channels/{channelId}/vouchers` - Redeem a voucher
+//! - `POST /payment/v1/payment_channels/{channelId}/close` - Close a channel (payer-signed)
+//! - `GET /payment/v1/admin/payment_channels` - List channels
+//! - `POST /payment/v1/admin/payment_channels/{channelId}/settle` - Pay out the balance now
+//! - `POST /payment/v1/admin/payment_channels/{channelId}/close` - Close a channel
+
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use axum::{
+    Extension,
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use kora_lib::transaction::TransactionUtil;
+use moneymq_types::{
+    ActorRole, ActorsConfig, ActorsConfigExt, Keychain, event_types,
+    x402::{
+        ExactPaymentPayload, MixedAddress, Network, PaymentPayload, PaymentRequirements,
+        SettleRequest, X402Version, config::facilitator::FacilitatorNetworkConfig,
+    },
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_keypair::{Keypair, Signer};
+use solana_pubkey::Pubkey;
+use solana_signature::Signature;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::api::{
+    catalog::stripe::types::{ListParams, ListResponse},
+    payment::{
+        PaymentApiConfig,
+        db::{
+            DbError, PaymentChannelModel,
+            payment_channel::{NewPaymentChannel, STATUS_CLOSED, STATUS_CLOSING, STATUS_OPEN},
+        },
+        endpoints::channels::ChannelEvent,
+        networks::solana::{
+            extract_customer_from_transaction, settle_solana_payment, transfer_checked_details,
+            transfer_from_token_account, transferred_amount_to,
+        },
+        rpc::RpcPool,
+    },
+};
+
+/// x402 scheme name used by `X-Payment` payloads carrying a channel voucher
+pub const CHANNEL_SCHEME: &str = "channel";
+
+/// Domain separator for messages signed by the payer
+const SIGNING_DOMAIN: &str = "moneymq:payment_channel:v1";
+
+/// Default channel lifetime when the open request does not specify one (24 hours)
+pub const DEFAULT_CHANNEL_TTL_SECONDS: i64 = 24 * 60 * 60;
+
+/// Default interval between background settlements of open channels
+pub const DEFAULT_SETTLEMENT_INTERVAL: Duration = Duration::from_secs(60);
+
+// ==================== Types ====================
+
+/// A payer-signed claim that `cumulative_amount` has been spent on a channel in total
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentChannelVoucher {
+    pub channel_id: String,
+    /// Total amount spent on the channel, in token base units
+    pub cumulative_amount: String,
+    /// Base58 ed25519 signature of [`voucher_message`] by the channel payer
+    pub signature: String,
+}
+
+/// `X-Payment` payload using the `channel` scheme
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelPaymentPayload {
+    pub x402_version: u8,
+    pub scheme: String,
+    pub network: Network,
+    pub payload: PaymentChannelVoucher,
+}
+
+/// Request body for POST /payment_channels
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenPaymentChannelRequest {
+    /// Signed deposit transaction transferring tokens to the escrow
+    pub payment_payload: PaymentPayload,
+    /// Deposit requirements (`payTo` must be the escrow address)
+    pub payment_requirements: PaymentRequirements,
+    /// Channel lifetime in seconds
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+}
+
+/// Request body for the voucher endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentChannelVoucherRequest {
+    pub voucher: PaymentChannelVoucher,
+    /// Requirements of the request being paid for
+    pub payment_requirements: PaymentRequirements,
+}
+
+/// Response of the voucher endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentChannelVoucherResponse {
+    pub channel_id: String,
+    pub cumulative_amount: String,
+    /// Amount paid by this voucher
+    pub amount: String,
+    /// Deposit left to spend after this voucher
+    pub remaining_amount: String,
+}
+
+/// Request body for POST /payment_channels/{channelId}/close
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClosePaymentChannelRequest {
+    /// Base58 ed25519 signature of [`close_message`] by the channel payer
+    pub signature: String,
+}
+
+/// Payment channel as returned by the API
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentChannel {
+    pub id: String,
+    pub object: String,
+    pub status: String,
+    pub payer: String,
+    pub operator: String,
+    pub escrow: String,
+    pub recipient: String,
+    pub asset: String,
+    pub decimals: i32,
+    pub deposit_amount: String,
+    pub cumulative_amount: String,
+    pub settled_amount: String,
+    pub remaining_amount: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deposit_signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_settlement_signature: Option<String>,
+    /// Unix timestamp (milliseconds)
+    pub expires_at: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl From<&PaymentChannelModel> for PaymentChannel {
+    fn from(model: &PaymentChannelModel) -> Self {
+        Self {
+            id: model.channel_id.clone(),
+            object: "payment_channel".to_string(),
+            status: model.status.clone(),
+            payer: model.payer.clone(),
+            operator: model.operator_id.clone(),
+            escrow: model.escrow.clone(),
+            recipient: model.recipient.clone(),
+            asset: model.asset.clone(),
+            decimals: model.decimals,
+            deposit_amount: model.deposit_amount.to_string(),
+            cumulative_amount: model.cumulative_amount.to_string(),
+            settled_amount: model.settled_amount.to_string(),
+            remaining_amount: model.remaining_amount().to_string(),
+            deposit_signature: model.deposit_signature.clone(),
+            last_settlement_signature: model.last_settlement_signature.clone(),
+            expires_at: model.expires_at,
+            created_at: model.created_at,
+            updated_at: model.updated_at,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum VoucherError {
+    #[error("Payment channel is {0}")]
+    NotOpen(String),
+    #[error("Payment channel has expired")]
+    Expired,
+    #[error("Payment channel was opened for a different asset or recipient")]
+    RequirementsMismatch,
+    #[error("Invalid voucher amount: {0}")]
+    InvalidAmount(String),
+    #[error("Voucher pays {0}, but {1} is required")]
+    InsufficientAmount(i64, i64),
+    #[error("Voucher amount {0} exceeds the channel deposit of {1}")]
+    ExceedsDeposit(i64, i64),
+    #[error("Invalid voucher signature")]
+    InvalidSignature,
+}
+
+impl VoucherError {
+    fn code(&self) -> &'static str {
+        match self {
+            VoucherError::NotOpen(_) => "payment_channel_closed",
+            VoucherError::Expired => "payment_channel_expired",
+            VoucherError::RequirementsMismatch => "payment_channel_mismatch",
+            VoucherError::InvalidAmount(_) => "voucher_invalid_amount",
+            VoucherError::InsufficientAmount(_, _) => "voucher_insufficient_amount",
+            VoucherError::ExceedsDeposit(_, _) => "payment_channel_insufficient_funds",
+            VoucherError::InvalidSignature => "voucher_invalid_signature",
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PaymentChannelError {
+    #[error("Payment channel is {0}")]
+    NotOpen(String),
+    #[error("Payment channel settlement is already in progress")]
+    Conflict,
+    #[error("Escrow operator '{0}' is not configured with a base58 keychain")]
+    OperatorUnavailable(String),
+    #[error("No Solana network is configured")]
+    NetworkUnavailable,
+    #[error("Invalid payment channel data: {0}")]
+    InvalidChannel(String),
+    #[error("On-chain transfer failed: {0}")]
+    TransferFailed(anyhow::Error),
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
+
+// ==================== Signing ====================
+
+/// Message signed by the payer to authorize `cumulative_amount` of total spend
+pub fn voucher_message(channel_id: &str, cumulative_amount: i64) -> String {
+    format!("{}:{}:{}", SIGNING_DOMAIN, channel_id, cumulative_amount)
+}
+
+/// Message signed by the payer to close a channel
+pub fn close_message(channel_id: &str) -> String {
+    format!("{}:{}:close", SIGNING_DOMAIN, channel_id)
+}
+
+/// Check a base58 ed25519 `signature` of `message` by `signer`
+fn verify_signature(signer: &str, message: &str, signature: &str) -> bool {
+    let (Ok(signer), Ok(signature)) = (Pubkey::from_str(signer), Signature::from_str(signature))
+    else {
+        return false;
+    };
+    signature.verify(signer.as_ref(), message.as_bytes())
+}
+
+/// Check that `voucher` can pay for `requirements` on `channel` at `now` (milliseconds).
+///
+/// Returns the new cumulative amount. The voucher must be signed by the channel payer,
+/// move the cumulative amount up by at least the required price, and stay within the deposit.
+pub fn check_voucher(
+    channel: &PaymentChannelModel,
+    voucher: &PaymentChannelVoucher,
+    requirements: &PaymentRequirements,
+    now: i64,
+) -> Result<i64, VoucherError> {
+    if !channel.is_open() {
+        return Err(VoucherError::NotOpen(channel.status.clone()));
+    }
+    if now > channel.expires_at {
+        return Err(VoucherError::Expired);
+    }
+    if requirements.asset.to_string() != channel.asset
+        || requirements.pay_to.to_string() != channel.recipient
+    {
+        return Err(VoucherError::RequirementsMismatch);
+    }
+
+    let cumulative_amount: i64 = voucher
+        .cumulative_amount
+        .parse()
+        .map_err(|_| VoucherError::InvalidAmount(voucher.cumulative_amount.clone()))?;
+    let required: i64 = requirements
+        .max_amount_required
+        .0
+        .parse()
+        .map_err(|_| VoucherError::InvalidAmount(requirements.max_amount_required.0.clone()))?;
+
+    let paid = cumulative_amount - channel.cumulative_amount;
+    if paid < required || paid <= 0 {
+        return Err(VoucherError::InsufficientAmount(paid, required));
+    }
+    if cumulative_amount > channel.deposit_amount {
+        return Err(VoucherError::ExceedsDeposit(
+            cumulative_amount,
+            channel.deposit_amount,
+        ));
+    }
+
+    let message = voucher_message(&channel.channel_id, cumulative_amount);
+    if !verify_signature(&channel.payer, &message, &voucher.signature) {
+        return Err(VoucherError::InvalidSignature);
+    }
+
+    Ok(cumulative_amount)
+}
+
+// ==================== Escrow ====================
+
+/// Operator actor holding payment channel deposits
+pub struct EscrowOperator {
+    pub id: String,
+    pub keypair: Keypair,
+}
+
+/// Resolve the operator that holds channel deposits.
+///
+/// With `operator_id`, resolve that operator; otherwise use the first operator with a
+/// base58 keychain. The secret may be the key itself or the name of an environment
+/// variable holding it.
+pub fn resolve_escrow_operator(
+    actors: &ActorsConfig,
+    operator_id: Option<&str>,
+) -> Option<EscrowOperator> {
+    let candidates = match operator_id {
+        Some(id) => actors.get_by_id(id).into_iter().collect::<Vec<_>>(),
+        None => actors.operators(),
+    };
+
+    candidates.into_iter().find_map(|actor| {
+        let ActorRole::Operator(operator) = &actor.role else {
+            return None;
+        };
+        let Keychain::Base58(keychain) = &operator.keychain else {
+            return None;
+        };
+        let secret = std::env::var(&keychain.secret).unwrap_or_else(|_| keychain.secret.clone());
+        let bytes = bs58::decode(secret.trim()).into_vec().ok()?;
+        let keypair = Keypair::try_from(&bytes[..]).ok()?;
+        Some(EscrowOperator {
+            id: actor.id.clone(),
+            keypair,
+        })
+    })
+}
+
+/// The Solana network config and its RPC pool
+fn solana_network(state: &PaymentApiConfig) -> Option<(&FacilitatorNetworkConfig, Arc<RpcPool>)> {
+    state
+        .facilitator_config
+        .networks
+        .iter()
+        .find(|(_, config)| config.network() == Network::Solana)
+        .and_then(|(name, config)| Some((config, state.rpc_pools.get(name)?)))
+}
+
+/// Transfer `payouts` out of the channel escrow
+async fn transfer_from_escrow(
+    state: &PaymentApiConfig,
+    channel: &PaymentChannelModel,
+    payouts: &[(Pubkey, i64)],
+) -> Result<String, PaymentChannelError> {
+    let operator = resolve_escrow_operator(&state.actors(), Some(&channel.operator_id))
+        .ok_or_else(|| PaymentChannelError::OperatorUnavailable(channel.operator_id.clone()))?;
+    let (_, rpc_pool) = solana_network(state).ok_or(PaymentChannelError::NetworkUnavailable)?;
+    let mint = Pubkey::from_str(&channel.asset)
+        .map_err(|e| PaymentChannelError::InvalidChannel(e.to_string()))?;
+    let token_program = Pubkey::from_str(&channel.token_program)
+        .map_err(|e| PaymentChannelError::InvalidChannel(e.to_string()))?;
+    let payouts = payouts
+        .iter()
+        .map(|(recipient, amount)| (*recipient, *amount as u64))
+        .collect::<Vec<_>>();
+
+    // Each attempt would build a new transaction, so payouts are never retried on another RPC
+    let endpoint = rpc_pool.preferred();
+    let result = transfer_from_token_account(
+        endpoint.client(),
+        &operator.keypair,
+        &mint,
+        &token_program,
+        channel.decimals as u8,
+        &payouts,
+    )
+    .await;
+    rpc_pool.record_outcome(&endpoint, &result);
+    result.map_err(PaymentChannelError::TransferFailed)
+}
+
+// ==================== Settlement ====================
+
+fn publish(
+    state: &PaymentApiConfig,
+    event_type: &str,
+    channel: &PaymentChannelModel,
+    extra: Option<serde_json::Value>,
+) {
+    let Some(channel_manager) = &state.channel_manager else {
+        return;
+    };
+    let mut data = json!(PaymentChannel::from(channel));
+    if let (Some(serde_json::Value::Object(extra)), Some(data)) = (extra, data.as_object_mut()) {
+        data.extend(extra);
+    }
+    channel_manager.publish(&channel.channel_id, ChannelEvent::custom(event_type, data));
+}
+
+fn reload(
+    state: &PaymentApiConfig,
+    channel_id: &str,
+) -> Result<PaymentChannelModel, PaymentChannelError> {
+    state
+        .db_manager
+        .find_payment_channel(channel_id, &state.payment_stack_id, state.is_sandbox)?
+        .ok_or_else(|| PaymentChannelError::InvalidChannel(format!("{} not found", channel_id)))
+}
+
+/// Pay out the voucher balance of an open channel that has not been settled yet.
+///
+/// Returns the transaction signature, or `None` if there was nothing to settle.
+pub async fn settle_channel(
+    state: &PaymentApiConfig,
+    channel_id: &str,
+) -> Result<Option<String>, PaymentChannelError> {
+    let channel = reload(state, channel_id)?;
+    if !channel.is_open() {
+        return Err(PaymentChannelError::NotOpen(channel.status));
+    }
+    let amount = channel.unsettled_amount();
+    if amount <= 0 {
+        return Ok(None);
+    }
+
+    // Reserve the balance before transferring so that concurrent settlements can't pay it twice
+    if !state.db_manager.update_payment_channel_settled_amount(
+        &channel.channel_id,
+        channel.settled_amount,
+        channel.cumulative_amount,
+    )? {
+        return Err(PaymentChannelError::Conflict);
+    }
+
+    let recipient = Pubkey::from_str(&channel.recipient)
+        .map_err(|e| PaymentChannelError::InvalidChannel(e.to_string()))?;
+    match transfer_from_escrow(state, &channel, &[(recipient, amount)]).await {
+        Ok(signature) => {
+            info!(
+                "Settled {} on payment channel {} ({})",
+                amount, channel.channel_id, signature
+            );
+            state
+                .db_manager
+                .record_payment_channel_settlement(&channel.channel_id, &signature)?;
+            let channel = reload(state, &channel.channel_id)?;
+            publish(
+                state,
+                event_types::PAYMENT_CHANNEL_SETTLED,
+                &channel,
+                Some(json!({ "amount": amount.to_string(), "signature": signature })),
+            );
+            Ok(Some(signature))
+        }
+        Err(e) => {
+            // Release the reservation so the balance is picked up by the next settlement
+            state.db_manager.update_payment_channel_settled_amount(
+                &channel.channel_id,
+                channel.cumulative_amount,
+                channel.settled_amount,
+            )?;
+            Err(e)
+        }
+    }
+}
+
+/// Close a channel: stop accepting vouchers, pay out the unsettled balance to the
+/// recipient and refund the unspent deposit to the payer, in a single transaction.
+pub async fn close_channel(
+    state: &PaymentApiConfig,
+    channel_id: &str,
+) -> Result<PaymentChannelModel, PaymentChannelError> {
+    let channel = reload(state, channel_id)?;
+    if !state.db_manager.transition_payment_channel_status(
+        &channel.channel_id,
+        STATUS_OPEN,
+        STATUS_CLOSING,
+    )? {
+        return Err(PaymentChannelError::NotOpen(channel.status));
+    }
+
+    // Re-read now that no more vouchers can be recorded
+    let channel = reload(state, &channel.channel_id)?;
+    let reopen = |e: PaymentChannelError| -> PaymentChannelError {
+        if let Err(db_error) = state.db_manager.transition_payment_channel_status(
+            &channel.channel_id,
+            STATUS_CLOSING,
+            STATUS_OPEN,
+        ) {
+            error!(
+                "Failed to reopen payment channel {}: {}",
+                channel.channel_id, db_error
+            );
+        }
+        e
+    };
+
+    let unsettled = channel.unsettled_amount();
+    let refund = channel.remaining_amount();
+    if unsettled > 0 || refund > 0 {
+        let recipient = Pubkey::from_str(&channel.recipient)
+            .map_err(|e| reopen(PaymentChannelError::InvalidChannel(e.to_string())))?;
+        let payer = Pubkey::from_str(&channel.payer)
+            .map_err(|e| reopen(PaymentChannelError::InvalidChannel(e.to_string())))?;
+
+        if !state
+            .db_manager
+            .update_payment_channel_settled_amount(
+                &channel.channel_id,
+                channel.settled_amount,
+                channel.cumulative_amount,
+            )
+            .map_err(|e| reopen(e.into()))?
+        {
+            return Err(reopen(PaymentChannelError::Conflict));
+        }
+
+        match transfer_from_escrow(state, &channel, &[(recipient, unsettled), (payer, refund)])
+            .await
+        {
+            Ok(signature) => {
+                info!(
+                    "Closed payment channel {}: settled {}, refunded {} ({})",
+                    channel.channel_id, unsettled, refund, signature
+                );
+                state
+                    .db_manager
+                    .record_payment_channel_settlement(&channel.channel_id, &signature)?;
+            }
+            Err(e) => {
+                state.db_manager.update_payment_channel_settled_amount(
+                    &channel.channel_id,
+                    channel.cumulative_amount,
+                    channel.settled_amount,
+                )?;
+                return Err(reopen(e));
+            }
+        }
+    }
+
+    state.db_manager.transition_payment_channel_status(
+        &channel.channel_id,
+        STATUS_CLOSING,
+        STATUS_CLOSED,
+    )?;
+    let channel = reload(state, &channel.channel_id)?;
+    publish(
+        state,
+        event_types::PAYMENT_CHANNEL_CLOSED,
+        &channel,
+        Some(
+            json!({ "settledAmount": unsettled.to_string(), "refundedAmount": refund.to_string() }),
+        ),
+    );
+    Ok(channel)
+}
+
+/// Settle every open channel with an outstanding balance, and close expired channels
+pub async fn run_settlement_round(state: &PaymentApiConfig) {
+    let channels = match state
+        .db_manager
+        .list_open_payment_channels(&state.payment_stack_id, state.is_sandbox)
+    {
+        Ok(channels) => channels,
+        Err(e) => {
+            error!("Failed to list open payment channels: {}", e);
+            return;
+        }
+    };
+
+    let now = chrono::Utc::now().timestamp_millis();
+    for channel in channels {
+        if now > channel.expires_at {
+            debug!("Closing expired payment channel {}", channel.channel_id);
+            if let Err(e) = close_channel(state, &channel.channel_id).await {
+                warn!(
+                    "Failed to close expired payment channel {}: {}",
+                    channel.channel_id, e
+                );
+            }
+        } else if channel.unsettled_amount() > 0 {
+            if let Err(e) = settle_channel(state, &channel.channel_id).await {
+                warn!(
+                    "Failed to settle payment channel {}: {}",
+                    channel.channel_id, e
+                );
+            }
+        }
+    }
+}
+
+/// Periodically settle open payment channels.
+///
+/// Returns `None` when no operator actor can hold channel deposits.
+pub fn spawn_settlement_task(state: PaymentApiConfig) -> Option<JoinHandle<()>> {
+    resolve_escrow_operator(&state.actors(), None)?;
+    let period = state.payment_channel_settlement_interval;
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            run_settlement_round(&state).await;
+        }
+    }))
+}
+
+// ==================== Handlers ====================
+
+fn channel_error(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    let body = json!({
+        "error": {
+            "code": code,
+            "message": message.into(),
+            "type": "invalid_request_error",
+        }
+    });
+    (status, Json(body)).into_response()
+}
+
+fn channel_operation_error(e: PaymentChannelError) -> Response {
+    let (status, code) = match &e {
+        PaymentChannelError::NotOpen(_) => (StatusCode::BAD_REQUEST, "payment_channel_closed"),
+        PaymentChannelError::Conflict => (StatusCode::CONFLICT, "payment_channel_busy"),
+        PaymentChannelError::OperatorUnavailable(_) | PaymentChannelError::NetworkUnavailable => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "payment_channels_not_configured",
+        ),
+        PaymentChannelError::InvalidChannel(_)
+        | PaymentChannelError::TransferFailed(_)
+        | PaymentChannelError::Db(_) => (StatusCode::INTERNAL_SERVER_ERROR, "api_error"),
+    };
+    error!("Payment channel operation failed: {}", e);
+    channel_error(status, code, e.to_string())
+}
+
+fn find_channel_or_404(
+    state: &PaymentApiConfig,
+    channel_id: &str,
+) -> Result<PaymentChannelModel, Response> {
+    match state.db_manager.find_payment_channel(
+        channel_id,
+        &state.payment_stack_id,
+        state.is_sandbox,
+    ) {
+        Ok(Some(channel)) => Ok(channel),
+        Ok(None) => Err(channel_error(
+            StatusCode::NOT_FOUND,
+            "resource_missing",
+            format!("No such payment channel: '{}'", channel_id),
+        )),
+        Err(e) => Err(channel_operation_error(e.into())),
+    }
+}
+
+/// POST /payment_channels - open a channel by settling a deposit to the escrow
+pub async fn open_payment_channel(
+    Extension(state): Extension<PaymentApiConfig>,
+    Json(request): Json<OpenPaymentChannelRequest>,
+) -> Response {
+    let Some(operator) = resolve_escrow_operator(&state.actors(), None) else {
+        return channel_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "payment_channels_not_configured",
+            "Payment channels require an operator actor with a base58 keychain",
+        );
+    };
+    let Some(recipient) = state.payout_recipient_address.clone() else {
+        return channel_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "payment_channels_not_configured",
+            "Payment channels require a payout recipient",
+        );
+    };
+    let Some((network_config, rpc_pool)) = solana_network(&state) else {
+        return channel_operation_error(PaymentChannelError::NetworkUnavailable);
+    };
+
+    let requirements = &request.payment_requirements;
+    let escrow = operator.keypair.pubkey();
+    if requirements.pay_to != MixedAddress::Solana(escrow) {
+        return channel_error(
+            StatusCode::BAD_REQUEST,
+            "parameter_invalid",
+            format!("Channel deposits must be paid to the escrow {}", escrow),
+        );
+    }
+    let MixedAddress::Solana(mint) = requirements.asset else {
+        return channel_error(
+            StatusCode::BAD_REQUEST,
+            "parameter_invalid",
+            "Channel deposits must use a Solana token",
+        );
+    };
+
+    let ExactPaymentPayload::Solana(solana_payload) = &request.payment_payload.payload;
+    let transaction = match TransactionUtil::decode_b64_transaction(&solana_payload.transaction) {
+        Ok(transaction) => transaction,
+        Err(e) => {
+            return channel_error(
+                StatusCode::BAD_REQUEST,
+                "parameter_invalid",
+                format!("Invalid deposit transaction: {}", e),
+            );
+        }
+    };
+    let Some((token_program, decimals)) = transfer_checked_details(&transaction, &mint) else {
+        return channel_error(
+            StatusCode::BAD_REQUEST,
+            "parameter_invalid",
+            format!("Deposit transaction does not transfer {}", mint),
+        );
+    };
+    let Ok(deposit_amount) = i64::try_from(transferred_amount_to(&transaction, &mint, &escrow))
+    else {
+        return channel_error(
+            StatusCode::BAD_REQUEST,
+            "parameter_invalid",
+            "Deposit amount is too large",
+        );
+    };
+    let payer = match extract_customer_from_transaction(&solana_payload.transaction) {
+        Ok(payer) => payer,
+        Err(e) => {
+            return channel_error(
+                StatusCode::BAD_REQUEST,
+                "parameter_invalid",
+                format!("Could not determine the payer: {}", e),
+            );
+        }
+    };
+
+    // Settle the deposit through the regular facilitator path
+    let settle_request = SettleRequest {
+        x402_version: X402Version::V1,
+        payment_payload: request.payment_payload.clone(),
+        payment_requirements: request.payment_requirements.clone(),
+    };
+    let deposit_signature = match rpc_pool
+        .call("settle", |rpc_client| {
+            let (settle_request, state) = (&settle_request, &state);
+            async move {
+                settle_solana_payment(
+                    settle_request,
+                    network_config,
+                    &rpc_client,
+                    &state.kora_config,
+                    &state.signer_pool,
+                )
+                .await
+            }
+        })
+        .await
+    {
+        Ok(response) if response.success => response.transaction.map(|tx| tx.to_string()),
+        Ok(response) => {
+            return channel_error(
+                StatusCode::PAYMENT_REQUIRED,
+                "payment_channel_deposit_failed",
+                format!("Deposit settlement failed: {:?}", response.error_reason),
+            );
+        }
+        Err(e) => {
+            return channel_error(
+                StatusCode::PAYMENT_REQUIRED,
+                "payment_channel_deposit_failed",
+                format!("Deposit settlement failed: {}", e),
+            );
+        }
+    };
+
+    let ttl = request
+        .expires_in
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(DEFAULT_CHANNEL_TTL_SECONDS);
+    let channel_id = format!("pch_{}", &Uuid::new_v4().to_string().replace("-", "")[..24]);
+    let new_channel = NewPaymentChannel::new(
+        channel_id,
+        payer.to_string(),
+        operator.id,
+        escrow.to_string(),
+        recipient,
+        mint.to_string(),
+        token_program.to_string(),
+        decimals as i32,
+        deposit_amount,
+        deposit_signature,
+        chrono::Utc::now().timestamp_millis() + ttl * 1000,
+        state.payment_stack_id.clone(),
+        state.is_sandbox,
+    );
+
+    match state.db_manager.insert_payment_channel(&new_channel) {
+        Ok(channel) => {
+            info!(
+                "Opened payment channel {} for {} with a deposit of {}",
+                channel.channel_id, channel.payer, channel.deposit_amount
+            );
+            publish(&state, event_types::PAYMENT_CHANNEL_OPENED, &channel, None);
+            (StatusCode::OK, Json(PaymentChannel::from(&channel))).into_response()
+        }
+        Err(e) => channel_operation_error(e.into()),
+    }
+}
+
+/// GET /payment_channels/{channelId} - retrieve a channel
+pub async fn get_payment_channel(
+    Extension(state): Extension<PaymentApiConfig>,
+    Path(channel_id): Path<String>,
+) -> Response {
+    match find_channel_or_404(&state, &channel_id) {
+        Ok(channel) => Json(PaymentChannel::from(&channel)).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// POST /payment_channels/{channelId}/vouchers/verify - check a voucher without redeeming it
+pub async fn verify_voucher(
+    Extension(state): Extension<PaymentApiConfig>,
+    Path(channel_id): Path<String>,
+    Json(request): Json<PaymentChannelVoucherRequest>,
+) -> Response {
+    let channel = match find_channel_or_404(&state, &channel_id) {
+        Ok(channel) => channel,
+        Err(response) => return response,
+    };
+    if request.voucher.channel_id != channel.channel_id {
+        return channel_error(
+            StatusCode::BAD_REQUEST,
+            "parameter_invalid",
+            "Voucher was issued for a different channel",
+        );
+    }
+
+    let now = chrono::Utc::now().timestamp_millis();
+    match check_voucher(
+        &channel,
+        &request.voucher,
+        &request.payment_requirements,
+        now,
+    ) {
+        Ok(cumulative_amount) => {
+            Json(voucher_response(&channel, cumulative_amount)).into_response()
+        }
+        Err(e) => channel_error(StatusCode::PAYMENT_REQUIRED, e.code(), e.to_string()),
+    }
+}
+
+/// POST /payment_channels/{channelId}/vouchers - redeem a voucher
+pub async fn redeem_voucher(
+    Extension(state): Extension<PaymentApiConfig>,
+    Path(channel_id): Path<String>,
+    Json(request): Json<PaymentChannelVoucherRequest>,
+) -> Response {
+    let channel = match find_channel_or_404(&state, &channel_id) {
+        Ok(channel) => channel,
+        Err(response) => return response,
+    };
+    if request.voucher.channel_id != channel.channel_id {
+        return channel_error(
+            StatusCode::BAD_REQUEST,
+            "parameter_invalid",
+            "Voucher was issued for a different channel",
+        );
+    }
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let cumulative_amount = match check_voucher(
+        &channel,
+        &request.voucher,
+        &request.payment_requirements,
+        now,
+    ) {
+        Ok(cumulative_amount) => cumulative_amount,
+        Err(e) => return channel_error(StatusCode::PAYMENT_REQUIRED, e.code(), e.to_string()),
+    };
+
+    match state.db_manager.record_payment_channel_voucher(
+        &channel.channel_id,
+        channel.cumulative_amount,
+        cumulative_amount,
+        &request.voucher.signature,
+    ) {
+        Ok(true) => {
+            let response = voucher_response(&channel, cumulative_amount);
+            if let Ok(channel) = reload(&state, &channel.channel_id) {
+                publish(
+                    &state,
+                    event_types::PAYMENT_CHANNEL_VOUCHER,
+                    &channel,
+                    Some(json!({ "amount": response.amount })),
+                );
+            }
+            Json(response).into_response()
+        }
+        Ok(false) => channel_error(
+            StatusCode::CONFLICT,
+            "voucher_stale",
+            "Another voucher was redeemed on this channel first; sign a voucher on the new balance",
+        ),
+        Err(e) => channel_operation_error(e.into()),
+    }
+}
+
+fn voucher_response(
+    channel: &PaymentChannelModel,
+    cumulative_amount: i64,
+) -> PaymentChannelVoucherResponse {
+    PaymentChannelVoucherResponse {
+        channel_id: channel.channel_id.clone(),
+        cumulative_amount: cumulative_amount.to_string(),
+        amount: (cumulative_amount - channel.cumulative_amount).to_string(),
+        remaining_amount: (channel.deposit_amount - cumulative_amount).to_string(),
+    }
+}
+
+/// POST /payment_channels/{channelId}/close - close a channel on behalf of its payer
+pub async fn close_payment_channel(
+    Extension(state): Extension<PaymentApiConfig>,
+    Path(channel_id): Path<String>,
+    Json(request): Json<ClosePaymentChannelRequest>,
+) -> Response {
+    let channel = match find_channel_or_404(&state, &channel_id) {
+        Ok(channel) => channel,
+        Err(response) => return response,
+    };
+    if !verify_signature(
+        &channel.payer,
+        &close_message(&channel.channel_id),
+        &request.signature,
+    ) {
+        return channel_error(
+            StatusCode::UNAUTHORIZED,
+            "invalid_signature",
+            "Close request must be signed by the channel payer",
+        );
+    }
+
+    match close_channel(&state, &channel.channel_id).await {
+        Ok(channel) => Json(PaymentChannel::from(&channel)).into_response(),
+        Err(e) => channel_operation_error(e),
+    }
+}
+
+/// GET /admin/payment_channels - list channels
+pub async fn list_payment_channels(
+    Extension(state): Extension<PaymentApiConfig>,
+    Query(params): Query<ListParams>,
+) -> impl IntoResponse {
+    let limit = params.limit.unwrap_or(10).min(100) as usize;
+
+    let starting_after = params.starting_after.and_then(|id| {
+        state
+            .db_manager
+            .find_payment_channel(&id, &state.payment_stack_id, state.is_sandbox)
+            .ok()
+            .flatten()
+            .map(|channel| channel.id)
+    });
+
+    let (channels, has_more) = state
+        .db_manager
+        .list_payment_channels(
+            limit,
+            starting_after,
+            &state.payment_stack_id,
+            state.is_sandbox,
+        )
+        .unwrap_or((vec![], false));
+
+    Json(ListResponse {
+        object: "list".to_string(),
+        data: channels.iter().map(PaymentChannel::from).collect(),
+        has_more,
+        url: "/v1/admin/payment_channels".to_string(),
+    })
+}
+
+/// POST /admin/payment_channels/{channelId}/settle - pay out the channel balance now
+pub async fn admin_settle_payment_channel(
+    Extension(state): Extension<PaymentApiConfig>,
+    Path(channel_id): Path<String>,
+) -> Response {
+    if let Err(response) = find_channel_or_404(&state, &channel_id) {
+        return response;
+    }
+    if let Err(e) = settle_channel(&state, &channel_id).await {
+        return channel_operation_error(e);
+    }
+    match find_channel_or_404(&state, &channel_id) {
+        Ok(channel) => Json(PaymentChannel::from(&channel)).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// POST /admin/payment_channels/{channelId}/close - close a channel without the payer
+pub async fn admin_close_payment_channel(
+    Extension(state): Extension<PaymentApiConfig>,
+    Path(channel_id): Path<String>,
+) -> Response {
+    if let Err(response) = find_channel_or_404(&state, &channel_id) {
+        return response;
+    }
+    match close_channel(&state, &channel_id).await {
+        Ok(channel) => Json(PaymentChannel::from(&channel)).into_response(),
+        Err(e) => channel_operation_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use moneymq_types::x402::{Scheme, TokenAmount};
+
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000_000;
+
+    fn make_channel(payer: &Keypair, cumulative_amount: i64) -> PaymentChannelModel {
+        PaymentChannelModel {
+            id: 1,
+            channel_id: "pch_test".to_string(),
+            payer: payer.pubkey().to_string(),
+            operator_id: "operator".to_string(),
+            escrow: Pubkey::new_from_array([3; 32]).to_string(),
+            recipient: Pubkey::new_from_array([1; 32]).to_string(),
+            asset: Pubkey::new_from_array([2; 32]).to_string(),
+            token_program: Pubkey::new_from_array([4; 32]).to_string(),
+            decimals: 6,
+            deposit_amount: 10_000,
+            cumulative_amount,
+            settled_amount: 0,
+            voucher_signature: None,
+            deposit_signature: None,
+            last_settlement_signature: None,
+            status: STATUS_OPEN.to_string(),
+            expires_at: NOW + 60_000,
+            payment_stack_id: "local".to_string(),
+            is_sandbox: true,
+            created_at: NOW,
+            updated_at: NOW,
+        }
+    }
+
+    fn make_requirements(amount: &str) -> PaymentRequirements {
+        PaymentRequirements {
+            scheme: Scheme::Exact,
+            network: Network::Solana,
+            max_amount_required: TokenAmount(amount.to_string()),
+            resource: "http://localhost/products/basic/access".parse().unwrap(),
+            description: "Payment for test".to_string(),
+            mime_type: "application/json".to_string(),
+            output_schema: None,
+            pay_to: MixedAddress::Solana(Pubkey::new_from_array([1; 32])),
+            max_timeout_seconds: 300,
+            asset: MixedAddress::Solana(Pubkey::new_from_array([2; 32])),
+            extra: None,
+        }
+    }
+
+    fn sign_voucher(payer: &Keypair, cumulative_amount: i64) -> PaymentChannelVoucher {
+        let message = voucher_message("pch_test", cumulative_amount);
+        PaymentChannelVoucher {
+            channel_id: "pch_test".to_string(),
+            cumulative_amount: cumulative_amount.to_string(),
+            signature: payer.sign_message(message.as_bytes()).to_string(),
+        }
+    }
+
+    #[test]
+    fn test_valid_voucher_is_accepted() {
+        let payer = Keypair::new();
+        let channel = make_channel(&payer, 1_000);
+        let voucher = sign_voucher(&payer, 1_100);
+
+        assert_eq!(
+            check_voucher(&channel, &voucher, &make_requirements("100"), NOW),
+            Ok(1_100)
+        );
+    }
+
+    #[test]
+    fn test_voucher_must_cover_the_price() {
+        let payer = Keypair::new();
+        let channel = make_channel(&payer, 1_000);
+
+        assert_eq!(
+            check_voucher(
+                &channel,
+                &sign_voucher(&payer, 1_050),
+                &make_requirements("100"),
+                NOW
+            ),
+            Err(VoucherError::InsufficientAmount(50, 100))
+        );
+        // Replaying an already redeemed voucher pays nothing
+        assert_eq!(
+            check_voucher(
+                &channel,
+                &sign_voucher(&payer, 1_000),
+                &make_requirements("0"),
+                NOW
+            ),
+            Err(VoucherError::InsufficientAmount(0, 0))
+        );
+    }
+
+    #[test]
+    fn test_voucher_cannot_exceed_deposit() {
+        let payer = Keypair::new();
+        let channel = make_channel(&payer, 9_950);
+
+        assert_eq!(
+            check_voucher(
+                &channel,
+                &sign_voucher(&payer, 10_050),
+                &make_requirements("100"),
+                NOW
+            ),
+            Err(VoucherError::ExceedsDeposit(10_050, 10_000))
+        );
+    }
+
+    #[test]
+    fn test_voucher_must_be_signed_by_payer() {
+        let payer = Keypair::new();
+        let channel = make_channel(&payer, 0);
+        let forged = sign_voucher(&Keypair::new(), 100);
+
+        assert_eq!(
+            check_voucher(&channel, &forged, &make_requirements("100"), NOW),
+            Err(VoucherError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_voucher_signature_covers_amount() {
+        let payer = Keypair::new();
+        let channel = make_channel(&payer, 0);
+        let mut voucher = sign_voucher(&payer, 100);
+        voucher.cumulative_amount = "5000".to_string();
+
+        assert_eq!(
+            check_voucher(&channel, &voucher, &make_requirements("100"), NOW),
+            Err(VoucherError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_voucher_rejected_on_closed_or_expired_channel() {
+        let payer = Keypair::new();
+        let voucher = sign_voucher(&payer, 100);
+        let requirements = make_requirements("100");
+
+        let mut closing = make_channel(&payer, 0);
+        closing.status = STATUS_CLOSING.to_string();
+        assert_eq!(
+            check_voucher(&closing, &voucher, &requirements, NOW),
+            Err(VoucherError::NotOpen(STATUS_CLOSING.to_string()))
+        );
+
+        let channel = make_channel(&payer, 0);
+        assert_eq!(
+            check_voucher(&channel, &voucher, &requirements, channel.expires_at + 1),
+            Err(VoucherError::Expired)
+        );
+    }
+
+    #[test]
+    fn test_voucher_rejected_for_other_recipient() {
+        let payer = Keypair::new();
+        let channel = make_channel(&payer, 0);
+        let mut requirements = make_requirements("100");
+        requirements.pay_to = MixedAddress::Solana(Pubkey::new_from_array([9; 32]));
+
+        assert_eq!(
+            check_voucher(&channel, &sign_voucher(&payer, 100), &requirements, NOW),
+            Err(VoucherError::RequirementsMismatch)
+        );
+    }
+}
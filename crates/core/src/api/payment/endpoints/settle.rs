@@ -1,5 +1,3 @@
-use std::sync::Arc;
-
 use axum::{
     Extension,
     http::StatusCode,
@@ -7,11 +5,9 @@ use axum::{
 };
 use cloudevents::AttributesReader;
 use moneymq_types::{
-    defaults,
+    PaymentFx, PaymentTax, defaults,
     x402::{FacilitatorErrorReason, Network, SettleRequest, SettleResponse},
 };
-use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_commitment_config::CommitmentConfig;
 use tracing::{error, info};
 
 use crate::{
@@ -46,16 +42,16 @@ pub async fn handler(
     );
 
     // Verify network matches
-    let Some(network_config) =
+    let Some((network_name, network_config)) =
         state
             .facilitator_config
             .networks
             .iter()
-            .find_map(|(_, network_config)| {
+            .find_map(|(name, network_config)| {
                 network_config
                     .network()
                     .eq(&request.payment_requirements.network)
-                    .then_some(network_config)
+                    .then_some((name, network_config))
             })
     else {
         return (
@@ -87,19 +83,27 @@ pub async fn handler(
     // Delegate to network-specific settlement
     let (status_code, response) = match network_config.network() {
         Network::Solana => {
-            let rpc_client = Arc::new(RpcClient::new_with_commitment(
-                network_config.rpc_url().to_string(),
-                CommitmentConfig::confirmed(),
-            ));
-            match networks::solana::settle_solana_payment(
-                &request,
-                network_config,
-                &rpc_client,
-                &state.kora_config,
-                &state.signer_pool,
-            )
-            .await
-            {
+            let result = match state.rpc_pools.get(network_name) {
+                Some(rpc_pool) => {
+                    rpc_pool
+                        .call("settle", |rpc_client| {
+                            let (request, state) = (&request, &state);
+                            async move {
+                                networks::solana::settle_solana_payment(
+                                    request,
+                                    network_config,
+                                    &rpc_client,
+                                    &state.kora_config,
+                                    &state.signer_pool,
+                                )
+                                .await
+                            }
+                        })
+                        .await
+                }
+                None => Err(anyhow::anyhow!("Network is not configured")),
+            };
+            match result {
                 Ok(response) => (StatusCode::OK, response),
                 Err(e) => {
                     error!("Settlement failed: {}", e);
@@ -263,6 +267,18 @@ pub async fn handler(
                 .and_then(|v| v.as_str())
                 .unwrap_or("USDC")
                 .to_string();
+            let tax = request
+                .payment_requirements
+                .extra
+                .as_ref()
+                .and_then(|extra| extra.get("tax"))
+                .and_then(|v| serde_json::from_value::<PaymentTax>(v.clone()).ok());
+            let fx = request
+                .payment_requirements
+                .extra
+                .as_ref()
+                .and_then(|extra| extra.get("fx"))
+                .and_then(|v| serde_json::from_value::<PaymentFx>(v.clone()).ok());
 
             // Emit payment:settled first - this notifies processors
             // The processor will send transaction:attach, which triggers transaction:completed with attachments
@@ -308,7 +324,9 @@ pub async fn handler(
                         Some(signature.clone().unwrap_or_default()),
                         state.payment_stack_id.clone(),
                         defaults::JWT_EXPIRATION_HOURS,
-                    );
+                    )
+                    .with_tax(tax)
+                    .with_fx(fx);
 
                     match jwt_key_pair.sign(&claims) {
                         Ok(jwt) => {
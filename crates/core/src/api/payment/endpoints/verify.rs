@@ -1,5 +1,3 @@
-use std::sync::Arc;
-
 use axum::{
     Extension,
     http::StatusCode,
@@ -8,16 +6,15 @@ use axum::{
 use cloudevents::AttributesReader;
 use moneymq_types::{
     ActorsConfigExt,
-    x402::{FacilitatorErrorReason, Network, VerifyRequest, VerifyResponse},
+    x402::{ExactPaymentPayload, FacilitatorErrorReason, Network, VerifyRequest, VerifyResponse},
 };
-use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_commitment_config::CommitmentConfig;
 use tracing::{debug, error, info, warn};
 
 use crate::{
     api::payment::{
         PaymentApiConfig,
         endpoints::{
+            PaymentQuote,
             channels::{
                 BasketItem, ChannelEvent, PaymentDetails, PaymentFailedData, PaymentVerifiedData,
                 TransactionNotification,
@@ -33,6 +30,54 @@ use crate::{
     },
 };
 
+/// Check that the payment payload is still fresh and bound to the requested resource
+///
+/// - the quote in `extra.quote` (when present) must not be expired
+/// - a payload that was already settled cannot be presented again
+/// - a payload first verified for one resource cannot be reused for another
+fn check_payment_binding(state: &PaymentApiConfig, request: &VerifyRequest) -> Result<(), String> {
+    let requirements = &request.payment_requirements;
+    let quote = PaymentQuote::from_extra(requirements.extra.as_ref());
+
+    if let Some(quote) = &quote {
+        if quote.is_expired(chrono::Utc::now().timestamp()) {
+            return Err(format!("Payment quote expired at {}", quote.expires_at));
+        }
+    }
+
+    let ExactPaymentPayload::Solana(solana_payload) = &request.payment_payload.payload;
+
+    match state
+        .db_manager
+        .is_transaction_already_settled(&solana_payload.transaction)
+    {
+        Ok(true) => return Err("Payment has already been settled".to_string()),
+        Ok(false) => {}
+        Err(e) => warn!("Failed to check settlement status: {}", e),
+    }
+
+    match state
+        .db_manager
+        .find_payment_requirements_by_transaction(&solana_payload.transaction)
+    {
+        Ok(Some(previous)) => {
+            let previous_method =
+                PaymentQuote::from_extra(previous.extra.as_ref()).map(|q| q.method);
+            let method = quote.map(|q| q.method);
+            if previous.resource != requirements.resource || previous_method != method {
+                return Err(format!(
+                    "Payment was issued for {} and cannot be used for {}",
+                    previous.resource, requirements.resource
+                ));
+            }
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Failed to look up previous payment requirements: {}", e),
+    }
+
+    Ok(())
+}
+
 /// POST /verify endpoint - verify a payment payload
 pub async fn handler(
     Extension(state): Extension<PaymentApiConfig>,
@@ -51,16 +96,16 @@ pub async fn handler(
     );
 
     // Verify network matches
-    let Some(network_config) =
+    let Some((network_name, network_config)) =
         state
             .facilitator_config
             .networks
             .iter()
-            .find_map(|(_, network_config)| {
+            .find_map(|(name, network_config)| {
                 network_config
                     .network()
                     .eq(&request.payment_requirements.network)
-                    .then_some(network_config)
+                    .then_some((name, network_config))
             })
     else {
         debug!(
@@ -91,21 +136,41 @@ pub async fn handler(
         );
     }
 
+    // Reject stale quotes and payloads replayed against another resource
+    if let Err(reason) = check_payment_binding(&state, &request) {
+        warn!("Rejecting payment payload: {}", reason);
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(VerifyResponse::Invalid {
+                reason: FacilitatorErrorReason::FreeForm(reason),
+                payer: None,
+            }),
+        );
+    }
+
     // Delegate to network-specific verification
     let (status, response) = match network_config.network() {
         Network::Solana => {
-            let rpc_client = Arc::new(RpcClient::new_with_commitment(
-                network_config.rpc_url().to_string(),
-                CommitmentConfig::confirmed(),
-            ));
-            match networks::solana::verify_solana_payment(
-                &request,
-                &rpc_client,
-                &state.kora_config,
-                &state.signer_pool,
-            )
-            .await
-            {
+            let result = match state.rpc_pools.get(network_name) {
+                Some(rpc_pool) => {
+                    rpc_pool
+                        .call("verify", |rpc_client| {
+                            let (request, state) = (&request, &state);
+                            async move {
+                                networks::solana::verify_solana_payment(
+                                    request,
+                                    &rpc_client,
+                                    &state.kora_config,
+                                    &state.signer_pool,
+                                )
+                                .await
+                            }
+                        })
+                        .await
+                }
+                None => Err(anyhow::anyhow!("Network is not configured")),
+            };
+            match result {
                 Ok(response) => (StatusCode::OK, response),
                 Err(e) => {
                     error!("Verification failed: {}", e);
@@ -123,7 +188,8 @@ pub async fn handler(
 
     // Ping hook actors on successful verification (fire-and-forget)
     if matches!(&response, VerifyResponse::Valid { .. }) {
-        let hooks = state.actors.hooks();
+        let actors = state.actors();
+        let hooks = actors.hooks();
         for hook_actor in hooks {
             if let Some(hook_role) = hook_actor.hook_role() {
                 if let Some(ping_url) = &hook_role.ping {
@@ -296,12 +362,16 @@ pub async fn handler(
                                 let quantity =
                                     item.get("quantity").and_then(|v| v.as_u64()).unwrap_or(1)
                                         as u32;
+                                let discount = item
+                                    .get("discount")
+                                    .and_then(|v| serde_json::from_value(v.clone()).ok());
 
                                 Some(BasketItem {
                                     product_id,
                                     experiment_id,
                                     features: features_value.clone(),
                                     quantity,
+                                    discount,
                                 })
                             })
                             .collect()
@@ -1,8 +1,9 @@
 pub mod db;
 pub mod endpoints;
 pub mod networks;
+pub mod rpc;
 
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 use sha2::{Digest, Sha256};
 
@@ -76,8 +77,12 @@ pub struct PaymentApiConfig {
     pub stack_name: Option<String>,
     /// Stack/merchant image URL for branding
     pub stack_image_url: Option<String>,
-    /// Sandbox operator accounts
-    pub actors: Arc<moneymq_types::ActorsConfig>,
+    /// Sandbox operator accounts, swapped as a whole when the actor files are reloaded
+    actors: Arc<RwLock<Arc<moneymq_types::ActorsConfig>>>,
+    /// How often open payment channels are settled on-chain
+    pub payment_channel_settlement_interval: std::time::Duration,
+    /// Shared RPC clients with failover, one pool per facilitator network
+    pub rpc_pools: Arc<rpc::RpcPools>,
 }
 
 impl PaymentApiConfig {
@@ -96,6 +101,8 @@ impl PaymentApiConfig {
             .unwrap_or("local")
             .to_string();
 
+        let rpc_pools =
+            rpc::RpcPools::from_facilitator_config(&facilitator_config, rpc::RpcPolicy::default());
         Self {
             facilitator_config: Arc::new(facilitator_config),
             validators: Arc::new(validators),
@@ -110,7 +117,10 @@ impl PaymentApiConfig {
             facilitator_address: None,
             stack_name: None,
             stack_image_url: None,
-            actors: Arc::new(indexmap::IndexMap::new()),
+            actors: Arc::new(RwLock::new(Arc::new(indexmap::IndexMap::new()))),
+            payment_channel_settlement_interval:
+                endpoints::payment_channels::DEFAULT_SETTLEMENT_INTERVAL,
+            rpc_pools: Arc::new(rpc_pools),
         }
     }
 
@@ -123,6 +133,8 @@ impl PaymentApiConfig {
         payment_stack_id: String,
         is_sandbox: bool,
     ) -> Self {
+        let rpc_pools =
+            rpc::RpcPools::from_facilitator_config(&facilitator_config, rpc::RpcPolicy::default());
         Self {
             facilitator_config: Arc::new(facilitator_config),
             validators: Arc::new(validators),
@@ -137,7 +149,10 @@ impl PaymentApiConfig {
             facilitator_address: None,
             stack_name: None,
             stack_image_url: None,
-            actors: Arc::new(indexmap::IndexMap::new()),
+            actors: Arc::new(RwLock::new(Arc::new(indexmap::IndexMap::new()))),
+            payment_channel_settlement_interval:
+                endpoints::payment_channels::DEFAULT_SETTLEMENT_INTERVAL,
+            rpc_pools: Arc::new(rpc_pools),
         }
     }
 
@@ -177,15 +192,44 @@ impl PaymentApiConfig {
     }
 
     /// Set the sandbox actors
-    pub fn with_actors(mut self, actors: moneymq_types::ActorsConfig) -> Self {
-        self.actors = Arc::new(actors);
+    pub fn with_actors(self, actors: moneymq_types::ActorsConfig) -> Self {
+        self.replace_actors(actors);
+        self
+    }
+
+    /// Sandbox actors. A request keeps the actors it started with when they are replaced
+    /// meanwhile.
+    pub fn actors(&self) -> Arc<moneymq_types::ActorsConfig> {
+        self.actors.read().unwrap().clone()
+    }
+
+    /// Use `actors` from now on, from this state and all its clones
+    pub fn replace_actors(&self, actors: moneymq_types::ActorsConfig) {
+        *self.actors.write().unwrap() = Arc::new(actors);
+    }
+
+    /// Set how often open payment channels are settled on-chain
+    pub fn with_payment_channel_settlement_interval(
+        mut self,
+        interval: std::time::Duration,
+    ) -> Self {
+        self.payment_channel_settlement_interval = interval;
+        self
+    }
+
+    /// Set the RPC retry and circuit breaker policy
+    pub fn with_rpc_policy(mut self, policy: rpc::RpcPolicy) -> Self {
+        self.rpc_pools = Arc::new(rpc::RpcPools::from_facilitator_config(
+            &self.facilitator_config,
+            policy,
+        ));
         self
     }
 
     /// Backwards compatibility alias
     #[deprecated(since = "0.2.0", note = "Use with_actors instead")]
-    pub fn with_accounts(mut self, accounts: moneymq_types::ActorsConfig) -> Self {
-        self.actors = Arc::new(accounts);
+    pub fn with_accounts(self, accounts: moneymq_types::ActorsConfig) -> Self {
+        self.replace_actors(accounts);
         self
     }
 }
@@ -229,6 +273,38 @@ pub fn create_routes() -> Router {
             get(endpoints::admin::list_transactions),
         )
         .route("/events", get(endpoints::events::handler))
+        .route(
+            "/payment_channels",
+            post(endpoints::payment_channels::open_payment_channel),
+        )
+        .route(
+            "/payment_channels/{channel_id}",
+            get(endpoints::payment_channels::get_payment_channel),
+        )
+        .route(
+            "/payment_channels/{channel_id}/vouchers",
+            post(endpoints::payment_channels::redeem_voucher),
+        )
+        .route(
+            "/payment_channels/{channel_id}/vouchers/verify",
+            post(endpoints::payment_channels::verify_voucher),
+        )
+        .route(
+            "/payment_channels/{channel_id}/close",
+            post(endpoints::payment_channels::close_payment_channel),
+        )
+        .route(
+            "/admin/payment_channels",
+            get(endpoints::payment_channels::list_payment_channels),
+        )
+        .route(
+            "/admin/payment_channels/{channel_id}/settle",
+            post(endpoints::payment_channels::admin_settle_payment_channel),
+        )
+        .route(
+            "/admin/payment_channels/{channel_id}/close",
+            post(endpoints::payment_channels::admin_close_payment_channel),
+        )
 }
 
 /// Create the facilitator router with state and optional channel manager.
@@ -329,6 +405,8 @@ pub async fn start_facilitator(
 > {
     let url = config.url.clone();
     let state = create_payment_api_config(config, validators, sandbox).await?;
+    endpoints::payment_channels::spawn_settlement_task(state.clone());
+    state.rpc_pools.spawn_health_checks();
     let app = create_router(state);
 
     let addr = format!("0.0.0.0:{}", url.port().expect("URL must have a port"));
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{str::FromStr, sync::Arc};
 
 use anyhow::{Context, Result};
 use kora_lib::{
@@ -7,14 +7,20 @@ use kora_lib::{
     transaction::{TransactionUtil, VersionedTransactionOps, VersionedTransactionResolved},
 };
 use moneymq_types::x402::{
-    ExactPaymentPayload, MixedAddress, SettleRequest, SettleResponse, TransactionHash,
-    VerifyRequest, VerifyResponse, config::facilitator::FacilitatorNetworkConfig,
+    ExactPaymentPayload, MixedAddress, PaymentRequirements, SettleRequest, SettleResponse,
+    TransactionHash, VerifyRequest, VerifyResponse, config::facilitator::FacilitatorNetworkConfig,
 };
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_keypair::Keypair;
 use solana_pubkey::Pubkey;
 use solana_transaction::{Transaction, versioned::VersionedTransaction};
 use tracing::info;
 
+use crate::api::payment::{SPL_TOKEN_2022_PROGRAM_ID, SPL_TOKEN_PROGRAM_ID, SYSTEM_PROGRAM_ID};
+
+/// SPL token `TransferChecked` instruction discriminator
+const TRANSFER_CHECKED_DISCRIMINATOR: u8 = 12;
+
 /// Helper function to extract transaction message (without signatures) for hashing
 /// This ensures verify and settle operations can be matched even if signatures differ
 pub fn extract_transaction_message_bytes(transaction_str: &str) -> Result<Vec<u8>> {
@@ -87,6 +93,198 @@ pub fn extract_customer_from_transaction(transaction_str: &str) -> Result<Pubkey
     Ok(payer_pubkey)
 }
 
+/// Check whether a transaction uses a durable nonce instead of a recent blockhash.
+///
+/// Durable nonce transactions start with a System Program `AdvanceNonceAccount`
+/// instruction and stay valid until the nonce is advanced, so they can be held
+/// for later settlement without racing blockhash expiry.
+pub fn uses_durable_nonce(transaction: &VersionedTransaction) -> bool {
+    /// System Program `AdvanceNonceAccount` instruction index
+    const ADVANCE_NONCE_ACCOUNT: u32 = 4;
+
+    let account_keys = transaction.message.static_account_keys();
+    let Ok(system_program) = Pubkey::from_str(SYSTEM_PROGRAM_ID) else {
+        return false;
+    };
+
+    transaction
+        .message
+        .instructions()
+        .first()
+        .filter(|ix| account_keys.get(ix.program_id_index as usize) == Some(&system_program))
+        .and_then(|ix| ix.data.get(0..4))
+        .is_some_and(|data| data == ADVANCE_NONCE_ACCOUNT.to_le_bytes())
+}
+
+/// Sum the SPL `TransferChecked` amounts in `transaction` that move `mint` to `owner`.
+///
+/// A transfer counts when its destination is `owner`'s associated token account for the
+/// token program being invoked, or `owner` itself when the recipient is a token account.
+pub fn transferred_amount_to(
+    transaction: &VersionedTransaction,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> u64 {
+    let account_keys = transaction.message.static_account_keys();
+    let token_programs = [SPL_TOKEN_PROGRAM_ID, SPL_TOKEN_2022_PROGRAM_ID]
+        .map(|id| Pubkey::from_str(id).expect("valid token program id"));
+
+    transaction
+        .message
+        .instructions()
+        .iter()
+        .filter_map(|ix| {
+            let program_id = account_keys.get(ix.program_id_index as usize)?;
+            if !token_programs.contains(program_id) {
+                return None;
+            }
+            // TransferChecked: [discriminator, amount (u64 LE), decimals]
+            // accounts: [source, mint, destination, authority, ..]
+            if ix.data.len() != 10 || ix.data[0] != TRANSFER_CHECKED_DISCRIMINATOR {
+                return None;
+            }
+            let ix_mint = account_keys.get(*ix.accounts.get(1)? as usize)?;
+            let destination = account_keys.get(*ix.accounts.get(2)? as usize)?;
+            if ix_mint != mint {
+                return None;
+            }
+            let expected_ata =
+                spl_associated_token_account::get_associated_token_address_with_program_id(
+                    owner, mint, program_id,
+                );
+            if destination != &expected_ata && destination != owner {
+                return None;
+            }
+            let amount = u64::from_le_bytes(ix.data[1..9].try_into().ok()?);
+            Some(amount)
+        })
+        .fold(0u64, |total, amount| total.saturating_add(amount))
+}
+
+/// Find the token program and decimals used by the first `TransferChecked` of `mint`
+pub fn transfer_checked_details(
+    transaction: &VersionedTransaction,
+    mint: &Pubkey,
+) -> Option<(Pubkey, u8)> {
+    let account_keys = transaction.message.static_account_keys();
+    let token_programs = [SPL_TOKEN_PROGRAM_ID, SPL_TOKEN_2022_PROGRAM_ID]
+        .map(|id| Pubkey::from_str(id).expect("valid token program id"));
+
+    transaction.message.instructions().iter().find_map(|ix| {
+        let program_id = account_keys.get(ix.program_id_index as usize)?;
+        if !token_programs.contains(program_id)
+            || ix.data.len() != 10
+            || ix.data[0] != TRANSFER_CHECKED_DISCRIMINATOR
+        {
+            return None;
+        }
+        let ix_mint = account_keys.get(*ix.accounts.get(1)? as usize)?;
+        (ix_mint == mint).then_some((*program_id, ix.data[9]))
+    })
+}
+
+/// Pay out tokens held by `owner`'s associated token account in a single transaction.
+///
+/// Recipient token accounts are created if needed. `owner` signs as token authority and
+/// fee payer. Returns the base58 transaction signature.
+pub async fn transfer_from_token_account(
+    rpc_client: &RpcClient,
+    owner: &Keypair,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    decimals: u8,
+    payouts: &[(Pubkey, u64)],
+) -> Result<String> {
+    // Call through the trait explicitly: kora's `SolanaSigner` also provides `pubkey()`
+    let owner_pubkey = solana_keypair::Signer::pubkey(owner);
+    let source = spl_associated_token_account::get_associated_token_address_with_program_id(
+        &owner_pubkey,
+        mint,
+        token_program,
+    );
+
+    let mut instructions = Vec::with_capacity(payouts.len() * 2);
+    for (recipient, amount) in payouts.iter().filter(|(_, amount)| *amount > 0) {
+        let destination =
+            spl_associated_token_account::get_associated_token_address_with_program_id(
+                recipient,
+                mint,
+                token_program,
+            );
+        instructions.push(
+            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                &owner_pubkey,
+                recipient,
+                mint,
+                token_program,
+            ),
+        );
+        instructions.push(
+            spl_token_2022_interface::instruction::transfer_checked(
+                token_program,
+                &source,
+                mint,
+                &destination,
+                &owner_pubkey,
+                &[],
+                *amount,
+                decimals,
+            )
+            .context("Failed to build transfer instruction")?,
+        );
+    }
+    if instructions.is_empty() {
+        return Err(anyhow::anyhow!("Nothing to transfer"));
+    }
+
+    let blockhash = rpc_client
+        .get_latest_blockhash()
+        .await
+        .context("Failed to fetch latest blockhash")?;
+    let transaction =
+        Transaction::new_signed_with_payer(&instructions, Some(&owner_pubkey), &[owner], blockhash);
+    let signature = rpc_client
+        .send_and_confirm_transaction(&transaction)
+        .await
+        .context("Failed to send transfer transaction")?;
+
+    Ok(signature.to_string())
+}
+
+/// Ensure `transaction` pays at least `max_amount_required` of the required asset to `pay_to`.
+///
+/// Without this check any transaction the fee payer is willing to sign would satisfy any
+/// payment requirement, letting a cheap payment unlock an expensive resource.
+pub fn check_transfer_matches_requirements(
+    transaction: &VersionedTransaction,
+    requirements: &PaymentRequirements,
+) -> Result<()> {
+    let (MixedAddress::Solana(mint), MixedAddress::Solana(pay_to)) =
+        (&requirements.asset, &requirements.pay_to)
+    else {
+        return Err(anyhow::anyhow!(
+            "Payment requirements must use Solana addresses for asset and recipient"
+        ));
+    };
+    let required: u64 = requirements
+        .max_amount_required
+        .0
+        .parse()
+        .context("Invalid max_amount_required")?;
+
+    let transferred = transferred_amount_to(transaction, mint, pay_to);
+    if transferred < required {
+        return Err(anyhow::anyhow!(
+            "Transaction transfers {} of {} to {}, but {} is required",
+            transferred,
+            mint,
+            pay_to,
+            required
+        ));
+    }
+    Ok(())
+}
+
 /// Verify a Solana payment payload
 pub async fn verify_solana_payment(
     request: &VerifyRequest,
@@ -105,6 +303,8 @@ pub async fn verify_solana_payment(
     info!("Transaction blockhash: {:?}", recent_blockhash);
     info!("Verifying with rpc client: {}", rpc_client.url());
 
+    check_transfer_matches_requirements(&transaction, &request.payment_requirements)?;
+
     // TODO: Check usage limit for transaction sender
     // UsageTracker::check_transaction_usage_limit(&config, &transaction).await?;
 
@@ -144,6 +344,8 @@ pub async fn settle_solana_payment(
     let recent_blockhash = transaction.message.recent_blockhash();
     info!("Transaction blockhash: {:?}", recent_blockhash);
     info!("Settling with rpc client: {}", rpc_client.url());
+
+    check_transfer_matches_requirements(&transaction, &request.payment_requirements)?;
     // TODO: Check usage limit for transaction sender
     // UsageTracker::check_transaction_usage_limit(&config, &transaction).await?;
 
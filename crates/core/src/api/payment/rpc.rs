@@ -0,0 +1,487 @@
+//! Shared Solana RPC clients with failover
+//!
+//! Each facilitator network gets one [`RpcPool`] holding a long-lived client per configured
+//! RPC URL (the primary `rpc_url` first, then the fallback URLs in order). Calls go to the
+//! first endpoint whose circuit breaker is closed and move on to the next endpoint when the
+//! RPC itself fails (connection refused, timeouts, 5xx...). Errors returned by a healthy RPC,
+//! such as a transaction failing validation, are returned as-is and never trigger failover.
+//!
+//! A background task probes every endpoint with `getHealth` so a tripped breaker closes again
+//! once its provider recovers.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use moneymq_types::x402::config::facilitator::{FacilitatorConfig, FacilitatorNetworkConfig};
+use parking_lot::Mutex;
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    nonblocking::rpc_client::RpcClient,
+};
+use solana_commitment_config::CommitmentConfig;
+use tokio::task::JoinHandle;
+use tracing::{Instrument, debug, info, info_span, warn};
+
+/// Default number of attempts for a call, across all endpoints
+pub const DEFAULT_MAX_ATTEMPTS: usize = 3;
+
+/// Default delay between two attempts (multiplied by the attempt number)
+pub const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Default number of consecutive failures that opens an endpoint's circuit breaker
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+
+/// Default time an open circuit breaker keeps an endpoint out of rotation
+pub const DEFAULT_BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Default interval between two health checks of every endpoint
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Retry and circuit breaker policy shared by the endpoints of a pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RpcPolicy {
+    /// Maximum attempts per call, across all endpoints
+    pub max_attempts: usize,
+    /// Delay before retrying, multiplied by the attempt number
+    pub retry_backoff: Duration,
+    /// Consecutive failures after which an endpoint is taken out of rotation
+    pub failure_threshold: u32,
+    /// How long an endpoint stays out of rotation once its breaker opens
+    pub breaker_cooldown: Duration,
+    /// How often endpoints are probed with `getHealth`
+    pub health_check_interval: Duration,
+}
+
+impl Default for RpcPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            breaker_cooldown: DEFAULT_BREAKER_COOLDOWN,
+            health_check_interval: DEFAULT_HEALTH_CHECK_INTERVAL,
+        }
+    }
+}
+
+/// Consecutive-failure circuit breaker.
+///
+/// Once open, the breaker lets a call through again after the cooldown (half-open); a
+/// success closes it, a failure re-opens it for another cooldown.
+#[derive(Debug, Default)]
+struct CircuitBreaker {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn is_available(&self, now: Instant) -> bool {
+        self.open_until.is_none_or(|until| now >= until)
+    }
+
+    fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.open_until = None;
+    }
+
+    /// Returns true if this failure opened the breaker
+    fn record_failure(&mut self, now: Instant, threshold: u32, cooldown: Duration) -> bool {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if self.consecutive_failures >= threshold.max(1) {
+            self.open_until = Some(now + cooldown);
+            return true;
+        }
+        false
+    }
+}
+
+/// One RPC URL with its shared client and circuit breaker
+pub struct RpcEndpoint {
+    url: String,
+    client: Arc<RpcClient>,
+    breaker: Mutex<CircuitBreaker>,
+}
+
+impl std::fmt::Debug for RpcEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RpcEndpoint")
+            .field("url", &self.url)
+            .field("breaker", &self.breaker)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RpcEndpoint {
+    fn new(url: String) -> Self {
+        let client = Arc::new(RpcClient::new_with_commitment(
+            url.clone(),
+            CommitmentConfig::confirmed(),
+        ));
+        Self {
+            url,
+            client,
+            breaker: Mutex::new(CircuitBreaker::default()),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn client(&self) -> &Arc<RpcClient> {
+        &self.client
+    }
+
+    /// Whether the endpoint's circuit breaker currently lets calls through
+    pub fn is_available(&self) -> bool {
+        self.breaker.lock().is_available(Instant::now())
+    }
+}
+
+/// Whether an error means the RPC endpoint itself failed, as opposed to the RPC
+/// rejecting the request
+pub fn is_rpc_unavailable(error: &anyhow::Error) -> bool {
+    let transport_failure = error.chain().any(|cause| {
+        cause.downcast_ref::<ClientError>().is_some_and(|e| {
+            matches!(
+                e.kind(),
+                ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_)
+            )
+        }) || cause
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|e| e.is_connect() || e.is_timeout() || e.is_request())
+            || cause.downcast_ref::<std::io::Error>().is_some()
+    });
+    if transport_failure {
+        return true;
+    }
+
+    // Kora flattens client errors into strings, so fall back to the message
+    let message = format!("{:#}", error).to_lowercase();
+    [
+        "error sending request",
+        "connection refused",
+        "connection reset",
+        "timed out",
+        "dns error",
+        "502 bad gateway",
+        "503 service unavailable",
+        "504 gateway timeout",
+        "429 too many requests",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+/// Pooled RPC clients of one network, in order of preference
+#[derive(Debug)]
+pub struct RpcPool {
+    endpoints: Vec<Arc<RpcEndpoint>>,
+    policy: RpcPolicy,
+}
+
+impl RpcPool {
+    /// Create a pool from RPC URLs in order of preference. Panics if `urls` is empty.
+    pub fn new<I, S>(urls: I, policy: RpcPolicy) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let endpoints = urls
+            .into_iter()
+            .map(|url| Arc::new(RpcEndpoint::new(url.into())))
+            .collect::<Vec<_>>();
+        assert!(!endpoints.is_empty(), "RPC pool needs at least one URL");
+        Self { endpoints, policy }
+    }
+
+    pub fn from_network_config(
+        network_config: &FacilitatorNetworkConfig,
+        policy: RpcPolicy,
+    ) -> Self {
+        Self::new(
+            network_config
+                .rpc_urls()
+                .into_iter()
+                .map(|url| url.to_string()),
+            policy,
+        )
+    }
+
+    pub fn endpoints(&self) -> &[Arc<RpcEndpoint>] {
+        &self.endpoints
+    }
+
+    pub fn policy(&self) -> RpcPolicy {
+        self.policy
+    }
+
+    /// Endpoints to try, in order: the available ones, or every endpoint if all breakers
+    /// are open so a full outage is still probed rather than failing without a call
+    fn candidates(&self) -> Vec<Arc<RpcEndpoint>> {
+        let available = self
+            .endpoints
+            .iter()
+            .filter(|endpoint| endpoint.is_available())
+            .cloned()
+            .collect::<Vec<_>>();
+        if available.is_empty() {
+            self.endpoints.clone()
+        } else {
+            available
+        }
+    }
+
+    /// The endpoint calls currently go to first
+    pub fn preferred(&self) -> Arc<RpcEndpoint> {
+        self.candidates()
+            .into_iter()
+            .next()
+            .expect("RPC pool has at least one endpoint")
+    }
+
+    /// Record the outcome of a call made directly on an endpoint's client
+    pub fn record_outcome<T>(&self, endpoint: &RpcEndpoint, result: &anyhow::Result<T>) {
+        match result {
+            Ok(_) => endpoint.breaker.lock().record_success(),
+            Err(error) if is_rpc_unavailable(error) => self.record_failure(endpoint),
+            // The RPC answered: the endpoint is healthy even if the request was rejected
+            Err(_) => endpoint.breaker.lock().record_success(),
+        }
+    }
+
+    fn record_failure(&self, endpoint: &RpcEndpoint) {
+        let opened = endpoint.breaker.lock().record_failure(
+            Instant::now(),
+            self.policy.failure_threshold,
+            self.policy.breaker_cooldown,
+        );
+        if opened {
+            warn!(
+                rpc_url = %endpoint.url,
+                cooldown_secs = self.policy.breaker_cooldown.as_secs(),
+                "RPC endpoint circuit breaker opened"
+            );
+        }
+    }
+
+    /// Run `operation` against the preferred endpoint, failing over to the next endpoints
+    /// when the RPC is unavailable.
+    ///
+    /// Each attempt runs in an `rpc_call` span carrying the endpoint URL, so traces show which
+    /// RPC served the call. Only use this for operations that are safe to repeat: a signed
+    /// transaction resubmitted to another endpoint keeps its signature, but a freshly built
+    /// transaction would not.
+    pub async fn call<T, F, Fut>(&self, operation: &str, f: F) -> anyhow::Result<T>
+    where
+        F: Fn(Arc<RpcClient>) -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let candidates = self.candidates();
+        let max_attempts = self.policy.max_attempts.max(1);
+        let mut attempt = 0;
+
+        loop {
+            let endpoint = &candidates[attempt % candidates.len()];
+            let span = info_span!(
+                "rpc_call",
+                operation,
+                rpc_url = %endpoint.url,
+                attempt = attempt + 1
+            );
+            let result = f(endpoint.client.clone()).instrument(span).await;
+            self.record_outcome(endpoint, &result);
+
+            match result {
+                Ok(value) => {
+                    debug!(rpc_url = %endpoint.url, operation, "RPC call served");
+                    return Ok(value);
+                }
+                Err(error) if is_rpc_unavailable(&error) && attempt + 1 < max_attempts => {
+                    warn!(
+                        rpc_url = %endpoint.url,
+                        operation,
+                        error = %error,
+                        "RPC endpoint unavailable, retrying"
+                    );
+                    attempt += 1;
+                    tokio::time::sleep(self.policy.retry_backoff * attempt as u32).await;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Probe every endpoint with `getHealth` and update its circuit breaker
+    pub async fn check_health(&self) {
+        for endpoint in &self.endpoints {
+            match endpoint.client.get_health().await {
+                Ok(()) => {
+                    let mut breaker = endpoint.breaker.lock();
+                    if !breaker.is_available(Instant::now()) {
+                        info!(rpc_url = %endpoint.url, "RPC endpoint recovered");
+                    }
+                    breaker.record_success();
+                }
+                Err(e) => {
+                    debug!(rpc_url = %endpoint.url, error = %e, "RPC health check failed");
+                    self.record_failure(endpoint);
+                }
+            }
+        }
+    }
+}
+
+/// RPC pools of every facilitator network, keyed by network name
+#[derive(Debug, Default)]
+pub struct RpcPools {
+    pools: HashMap<String, Arc<RpcPool>>,
+}
+
+impl RpcPools {
+    pub fn from_facilitator_config(config: &FacilitatorConfig, policy: RpcPolicy) -> Self {
+        let pools = config
+            .networks
+            .iter()
+            .map(|(name, network_config)| {
+                (
+                    name.clone(),
+                    Arc::new(RpcPool::from_network_config(network_config, policy)),
+                )
+            })
+            .collect();
+        Self { pools }
+    }
+
+    pub fn get(&self, network_name: &str) -> Option<Arc<RpcPool>> {
+        self.pools.get(network_name).cloned()
+    }
+
+    /// Spawn the background task probing every endpoint. Returns None when there is
+    /// nothing to fall back to.
+    pub fn spawn_health_checks(self: &Arc<Self>) -> Option<JoinHandle<()>> {
+        let interval = self
+            .pools
+            .values()
+            .filter(|pool| pool.endpoints.len() > 1)
+            .map(|pool| pool.policy.health_check_interval)
+            .min()?;
+        let pools = self.clone();
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                for pool in pools.pools.values().filter(|pool| pool.endpoints.len() > 1) {
+                    pool.check_health().await;
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_policy() -> RpcPolicy {
+        RpcPolicy {
+            max_attempts: 3,
+            retry_backoff: Duration::ZERO,
+            failure_threshold: 2,
+            breaker_cooldown: Duration::from_secs(60),
+            health_check_interval: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn test_breaker_opens_after_threshold_and_half_opens_after_cooldown() {
+        let now = Instant::now();
+        let cooldown = Duration::from_secs(30);
+        let mut breaker = CircuitBreaker::default();
+
+        assert!(!breaker.record_failure(now, 2, cooldown));
+        assert!(breaker.is_available(now));
+        assert!(breaker.record_failure(now, 2, cooldown));
+        assert!(!breaker.is_available(now));
+        assert!(breaker.is_available(now + cooldown));
+
+        breaker.record_success();
+        assert!(breaker.is_available(now));
+        assert_eq!(breaker.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_transport_errors_are_classified() {
+        assert!(is_rpc_unavailable(&anyhow::anyhow!(
+            "RPC error: error sending request for url (http://localhost:8899/)"
+        )));
+        assert!(is_rpc_unavailable(&anyhow::Error::new(
+            std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused")
+        )));
+        assert!(!is_rpc_unavailable(&anyhow::anyhow!(
+            "Transaction transfers 10 to the wrong recipient"
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_call_fails_over_to_next_endpoint() {
+        let pool = RpcPool::new(
+            ["http://primary.invalid", "http://fallback.invalid"],
+            test_policy(),
+        );
+
+        let served_by = pool
+            .call("test", |client| async move {
+                if client.url().contains("primary") {
+                    Err(anyhow::anyhow!("connection refused"))
+                } else {
+                    Ok(client.url())
+                }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(served_by, "http://fallback.invalid");
+        assert_eq!(pool.endpoints()[0].breaker.lock().consecutive_failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_call_does_not_retry_rejections() {
+        let pool = RpcPool::new(
+            ["http://primary.invalid", "http://fallback.invalid"],
+            test_policy(),
+        );
+        let attempts = std::sync::atomic::AtomicUsize::new(0);
+
+        let result: anyhow::Result<()> = pool
+            .call("test", |_| {
+                attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                async { Err(anyhow::anyhow!("Invalid payment amount")) }
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(pool.endpoints()[0].is_available());
+    }
+
+    #[tokio::test]
+    async fn test_open_breaker_skips_endpoint() {
+        let pool = RpcPool::new(
+            ["http://primary.invalid", "http://fallback.invalid"],
+            test_policy(),
+        );
+        let primary = pool.endpoints()[0].clone();
+        let unavailable: anyhow::Result<()> = Err(anyhow::anyhow!("operation timed out"));
+        pool.record_outcome(&primary, &unavailable);
+        pool.record_outcome(&primary, &unavailable);
+
+        assert!(!primary.is_available());
+        assert_eq!(pool.preferred().url(), "http://fallback.invalid");
+    }
+}
@@ -19,7 +19,7 @@ pub async fn list_accounts(
     Extension(payment_config): Extension<PaymentApiConfig>,
     Extension(networks_config): Extension<NetworksConfig>,
 ) -> Result<Json<Value>, Json<Value>> {
-    let actors_config = &payment_config.actors;
+    let actors_config = payment_config.actors();
 
     let mut res = json!({});
 
@@ -31,6 +31,13 @@ pub enum PaymentFlow {
         /// The payment intent ID
         intent_id: String,
     },
+    /// Payment via a Solana Pay transfer request (plain wallet transfer)
+    SolanaPay {
+        /// The payment intent ID
+        intent_id: String,
+        /// Reference key the transfer was found with
+        reference: String,
+    },
 }
 
 /// Data payload for payment verification succeeded event
@@ -103,6 +110,57 @@ pub struct TransactionCompletedData {
     pub product_id: Option<String>,
 }
 
+/// Data payload for subscription lifecycle events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionEventData {
+    pub subscription_id: String,
+    pub customer: String,
+    /// Status after the transition
+    pub status: String,
+    pub current_period_start: i64,
+    pub current_period_end: i64,
+    /// Payment intent charging the new period, if one was created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_intent_id: Option<String>,
+    /// Sandbox test clock that triggered the transition
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_clock: Option<String>,
+}
+
+/// Data payload for checkout session expired event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckoutSessionExpiredData {
+    pub session_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub customer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_intent_id: Option<String>,
+    /// Sandbox test clock that triggered the expiry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_clock: Option<String>,
+}
+
+/// Data payload for invoice lifecycle events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvoiceEventData {
+    pub invoice_id: String,
+    pub customer: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription_id: Option<String>,
+    /// Status after the transition
+    pub status: String,
+    pub currency: String,
+    /// Total of the invoice (cents)
+    pub total: i64,
+    pub amount_due: i64,
+    /// Payment intent charging the invoice, if one was created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_intent_id: Option<String>,
+    /// Sandbox test clock that triggered the transition
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_clock: Option<String>,
+}
+
 /// Enum of all possible CloudEvent types emitted by MoneyMQ
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
@@ -117,6 +175,20 @@ pub enum CloudEvent {
     PaymentSettlementFailed(PaymentSettlementFailedData),
     #[serde(rename = "mq.money.transaction.completed")]
     TransactionCompleted(TransactionCompletedData),
+    #[serde(rename = "mq.money.subscription.renewed")]
+    SubscriptionRenewed(SubscriptionEventData),
+    #[serde(rename = "mq.money.subscription.trial_ended")]
+    SubscriptionTrialEnded(SubscriptionEventData),
+    #[serde(rename = "mq.money.subscription.canceled")]
+    SubscriptionCanceled(SubscriptionEventData),
+    #[serde(rename = "mq.money.checkout.session.expired")]
+    CheckoutSessionExpired(CheckoutSessionExpiredData),
+    #[serde(rename = "mq.money.invoice.finalized")]
+    InvoiceFinalized(InvoiceEventData),
+    #[serde(rename = "mq.money.invoice.paid")]
+    InvoicePaid(InvoiceEventData),
+    #[serde(rename = "mq.money.invoice.voided")]
+    InvoiceVoided(InvoiceEventData),
 }
 
 impl CloudEvent {
@@ -130,6 +202,13 @@ impl CloudEvent {
             CloudEvent::PaymentSettlementSucceeded(_) => "mq.money.payment.settlement.succeeded",
             CloudEvent::PaymentSettlementFailed(_) => "mq.money.payment.settlement.failed",
             CloudEvent::TransactionCompleted(_) => "mq.money.transaction.completed",
+            CloudEvent::SubscriptionRenewed(_) => "mq.money.subscription.renewed",
+            CloudEvent::SubscriptionTrialEnded(_) => "mq.money.subscription.trial_ended",
+            CloudEvent::SubscriptionCanceled(_) => "mq.money.subscription.canceled",
+            CloudEvent::CheckoutSessionExpired(_) => "mq.money.checkout.session.expired",
+            CloudEvent::InvoiceFinalized(_) => "mq.money.invoice.finalized",
+            CloudEvent::InvoicePaid(_) => "mq.money.invoice.paid",
+            CloudEvent::InvoiceVoided(_) => "mq.money.invoice.voided",
         }
     }
 
@@ -141,6 +220,13 @@ impl CloudEvent {
             CloudEvent::PaymentSettlementSucceeded(_) => "moneymq/payment/settle",
             CloudEvent::PaymentSettlementFailed(_) => "moneymq/payment/settle",
             CloudEvent::TransactionCompleted(_) => "moneymq/transaction/complete",
+            CloudEvent::SubscriptionRenewed(_)
+            | CloudEvent::SubscriptionTrialEnded(_)
+            | CloudEvent::SubscriptionCanceled(_) => "moneymq/catalog/subscriptions",
+            CloudEvent::CheckoutSessionExpired(_) => "moneymq/catalog/checkout",
+            CloudEvent::InvoiceFinalized(_)
+            | CloudEvent::InvoicePaid(_)
+            | CloudEvent::InvoiceVoided(_) => "moneymq/catalog/invoices",
         }
     }
 
@@ -152,6 +238,13 @@ impl CloudEvent {
             CloudEvent::PaymentSettlementSucceeded(_) => "payment.settlement.succeeded",
             CloudEvent::PaymentSettlementFailed(_) => "payment.settlement.failed",
             CloudEvent::TransactionCompleted(_) => "transaction.completed",
+            CloudEvent::SubscriptionRenewed(_) => "subscription.renewed",
+            CloudEvent::SubscriptionTrialEnded(_) => "subscription.trial_ended",
+            CloudEvent::SubscriptionCanceled(_) => "subscription.canceled",
+            CloudEvent::CheckoutSessionExpired(_) => "checkout.session.expired",
+            CloudEvent::InvoiceFinalized(_) => "invoice.finalized",
+            CloudEvent::InvoicePaid(_) => "invoice.paid",
+            CloudEvent::InvoiceVoided(_) => "invoice.voided",
         }
     }
 }
@@ -281,6 +374,11 @@ pub struct EventStreamQuery {
 
 /// Creates a CloudEvent from event data using the official SDK
 pub fn create_event(data: CloudEvent) -> Event {
+    create_event_at(data, chrono::Utc::now())
+}
+
+/// Creates a CloudEvent that occurred at `time` (e.g. the frozen time of a test clock)
+pub fn create_event_at(data: CloudEvent, time: DateTime<Utc>) -> Event {
     let event_type = data.event_type();
     let source = data.source();
 
@@ -301,13 +399,26 @@ pub fn create_event(data: CloudEvent) -> Event {
         CloudEvent::TransactionCompleted(d) => {
             serde_json::to_value(d).expect("Failed to serialize event data")
         }
+        CloudEvent::SubscriptionRenewed(d)
+        | CloudEvent::SubscriptionTrialEnded(d)
+        | CloudEvent::SubscriptionCanceled(d) => {
+            serde_json::to_value(d).expect("Failed to serialize event data")
+        }
+        CloudEvent::CheckoutSessionExpired(d) => {
+            serde_json::to_value(d).expect("Failed to serialize event data")
+        }
+        CloudEvent::InvoiceFinalized(d)
+        | CloudEvent::InvoicePaid(d)
+        | CloudEvent::InvoiceVoided(d) => {
+            serde_json::to_value(d).expect("Failed to serialize event data")
+        }
     };
 
     EventBuilderV10::new()
         .id(uuid::Uuid::new_v4().to_string())
         .ty(event_type)
         .source(source)
-        .time(chrono::Utc::now())
+        .time(time)
         .data("application/json", data_value)
         .build()
         .expect("Failed to build CloudEvent")
@@ -0,0 +1,201 @@
+// Tax calculation for purchases
+// Applies the rate of the buyer's jurisdiction to digital goods, on top of or out of
+// each line's amount depending on the price's tax behavior.
+
+use moneymq_types::{PaymentTax, TaxBehavior};
+use serde::{Deserialize, Serialize};
+
+use super::{TaxData, TaxRate};
+
+/// Where a purchase is taxed: a country (ISO 3166-1 alpha-2) and, for the US and Canada,
+/// a state or province
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaxLocation {
+    pub country: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+}
+
+impl TaxLocation {
+    pub fn new(country: &str, state: Option<&str>) -> Self {
+        Self {
+            country: country.trim().to_uppercase(),
+            state: state
+                .map(|s| s.trim().to_uppercase())
+                .filter(|s| !s.is_empty()),
+        }
+    }
+}
+
+/// Tax on one line of a purchase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineTax {
+    /// Amount charged for the line, tax included (cents)
+    pub amount: i64,
+    /// Tax charged on the line (cents)
+    pub amount_tax: i64,
+    /// Amount the tax is computed on (cents)
+    pub taxable_amount: i64,
+    /// Whether the tax is included in the line's price
+    pub inclusive: bool,
+}
+
+impl LineTax {
+    /// Tax on a line of `amount` cents (after discounts) at `rate` percent
+    pub fn compute(amount: i64, rate: f64, tax_behavior: TaxBehavior) -> Self {
+        match tax_behavior {
+            TaxBehavior::Exclusive => {
+                let amount_tax = (amount as f64 * rate / 100.0).round() as i64;
+                Self {
+                    amount: amount + amount_tax,
+                    amount_tax,
+                    taxable_amount: amount,
+                    inclusive: false,
+                }
+            }
+            TaxBehavior::Inclusive => {
+                let taxable_amount = (amount as f64 / (1.0 + rate / 100.0)).round() as i64;
+                Self {
+                    amount,
+                    amount_tax: amount - taxable_amount,
+                    taxable_amount,
+                    inclusive: true,
+                }
+            }
+        }
+    }
+}
+
+/// Tax on the lines of a purchase, at the rate of the buyer's jurisdiction
+#[derive(Debug, Clone)]
+pub struct TaxCalculation {
+    pub rate: TaxRate,
+    pub lines: Vec<LineTax>,
+}
+
+impl TaxCalculation {
+    /// Tax `lines` of (amount in cents after discounts, tax behavior) at `rate`
+    pub fn compute(rate: TaxRate, lines: &[(i64, TaxBehavior)]) -> Self {
+        let lines = lines
+            .iter()
+            .map(|(amount, tax_behavior)| LineTax::compute(*amount, rate.rate, *tax_behavior))
+            .collect();
+        Self { rate, lines }
+    }
+
+    /// Tax charged on all lines (cents)
+    pub fn amount_tax(&self) -> i64 {
+        self.lines.iter().map(|line| line.amount_tax).sum()
+    }
+
+    /// Tax included in tax-inclusive prices (cents)
+    pub fn amount_inclusive(&self) -> i64 {
+        self.lines
+            .iter()
+            .filter(|line| line.inclusive)
+            .map(|line| line.amount_tax)
+            .sum()
+    }
+
+    /// Tax added on top of tax-exclusive prices (cents)
+    pub fn amount_exclusive(&self) -> i64 {
+        self.amount_tax() - self.amount_inclusive()
+    }
+
+    /// Amount charged for all lines, tax included (cents)
+    pub fn amount_total(&self) -> i64 {
+        self.lines.iter().map(|line| line.amount).sum()
+    }
+
+    /// Tax broken out of a payment, for receipts and transactions
+    pub fn payment_tax(&self) -> PaymentTax {
+        PaymentTax {
+            amount: self.amount_tax(),
+            amount_inclusive: self.amount_inclusive(),
+            rate: self.rate.rate,
+            tax_type: self.rate.tax_type.clone(),
+            country: self.rate.country_code.clone(),
+            state: self.rate.state_code.clone(),
+        }
+    }
+}
+
+impl TaxData {
+    /// Rate charged on digital goods sold to `location`, 0 where they aren't taxed.
+    ///
+    /// Returns None for jurisdictions missing from the dataset.
+    pub fn digital_goods_rate(&self, location: &TaxLocation) -> Option<TaxRate> {
+        let mut rate = self.get_rate(&location.country, location.state.as_deref())?;
+        if !rate.digital_goods_taxable {
+            rate.rate = 0.0;
+        }
+        Some(rate)
+    }
+
+    /// Tax on `lines` of (amount in cents after discounts, tax behavior) sold to `location`
+    pub fn calculate(
+        &self,
+        location: &TaxLocation,
+        lines: &[(i64, TaxBehavior)],
+    ) -> Option<TaxCalculation> {
+        self.digital_goods_rate(location)
+            .map(|rate| TaxCalculation::compute(rate, lines))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_tax_exclusive_and_inclusive() {
+        let exclusive = LineTax::compute(1000, 19.0, TaxBehavior::Exclusive);
+        assert_eq!(exclusive.amount_tax, 190);
+        assert_eq!(exclusive.amount, 1190);
+        assert_eq!(exclusive.taxable_amount, 1000);
+
+        let inclusive = LineTax::compute(1190, 19.0, TaxBehavior::Inclusive);
+        assert_eq!(inclusive.amount_tax, 190);
+        assert_eq!(inclusive.amount, 1190);
+        assert_eq!(inclusive.taxable_amount, 1000);
+    }
+
+    #[test]
+    fn test_eu_vat_on_digital_goods() {
+        let data = TaxData::load().unwrap();
+        let calculation = data
+            .calculate(
+                &TaxLocation::new("de", None),
+                &[
+                    (1000, TaxBehavior::Exclusive),
+                    (1190, TaxBehavior::Inclusive),
+                ],
+            )
+            .unwrap();
+        assert_eq!(calculation.rate.tax_type, "VAT");
+        assert_eq!(calculation.amount_tax(), 380);
+        assert_eq!(calculation.amount_inclusive(), 190);
+        assert_eq!(calculation.amount_exclusive(), 190);
+        assert_eq!(calculation.amount_total(), 2380);
+        assert_eq!(calculation.payment_tax().country, "DE");
+    }
+
+    #[test]
+    fn test_untaxed_digital_goods() {
+        let data = TaxData::load().unwrap();
+        let state = data
+            .get_us_states_with_tax()
+            .into_iter()
+            .map(|(code, _)| code)
+            .find(|code| !data.get_us_digital_tax_states().contains(code))
+            .unwrap();
+        let calculation = data
+            .calculate(
+                &TaxLocation::new("US", Some(state)),
+                &[(1000, TaxBehavior::Exclusive)],
+            )
+            .unwrap();
+        assert_eq!(calculation.amount_tax(), 0);
+        assert!(data.calculate(&TaxLocation::new("ZZ", None), &[]).is_none());
+    }
+}
@@ -1,9 +1,13 @@
 // Tax configuration module for MoneyMQ
 // Provides tax rate lookups for sales tax, VAT, GST, and digital services taxes.
 
+pub mod calculation;
+
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
+pub use self::calculation::{LineTax, TaxCalculation, TaxLocation};
+
 /// Tax rate lookup result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaxRate {
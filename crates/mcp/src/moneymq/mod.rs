@@ -1,7 +1,11 @@
 use std::{fs, path::PathBuf};
 
 use indexmap::IndexMap;
-use moneymq_types::iac::{ProductSchema, ValidationDiagnostic, ValidationResult};
+use moneymq_types::{
+    Decimal, Price,
+    iac::{ProductSchema, ValidationDiagnostic, ValidationResult},
+    x402::STABLECOIN_DECIMALS,
+};
 use rmcp::{
     ErrorData as McpError, RoleServer, ServerHandler,
     handler::server::{
@@ -117,14 +121,14 @@ fn validate_product_schema(product: &ProductSchema, index: usize) -> Vec<Validat
                 .with_suggestion("Add a price with amounts: { usd: 9.99 }"),
             );
         }
-        Some(price) if price.amounts.is_empty() => {
+        Some(price) if price.amounts.is_empty() && price.tiers.is_empty() => {
             diagnostics.push(
                 ValidationDiagnostic::error(
                     "empty-amounts",
                     format!("Product at index {} has empty 'amounts' map", index),
                 )
                 .with_field(format!("{}.price.amounts", prefix))
-                .with_expected("At least one currency/amount pair")
+                .with_expected("At least one currency/amount pair, or tiers")
                 .with_suggestion("Add at least one amount: { usd: 9.99 }"),
             );
         }
@@ -132,7 +136,7 @@ fn validate_product_schema(product: &ProductSchema, index: usize) -> Vec<Validat
             let price_prefix = format!("{}.price", prefix);
             // Validate amounts (ensure no negative values)
             for (currency, amount) in &price.amounts {
-                if *amount < 0.0 {
+                if *amount < Decimal::ZERO {
                     diagnostics.push(
                         ValidationDiagnostic::error(
                             "invalid-amount",
@@ -159,6 +163,28 @@ fn validate_product_schema(product: &ProductSchema, index: usize) -> Vec<Validat
                     .with_received(count.to_string()),
                 );
             }
+            // Validate tiers and package pricing
+            let parsed = Price::from(price.clone());
+            if let Err(e) = parsed.validate_pricing() {
+                diagnostics.push(
+                    ValidationDiagnostic::error("invalid-tiers", e)
+                        .with_field(format!("{}.tiers", price_prefix))
+                        .with_expected(
+                            "Ascending up_to bounds with an unbounded last tier and a tiers_mode",
+                        )
+                        .with_suggestion(
+                            "Use tiers: [{ up_to: 1000, unit_amounts: { usd: 0.01 } }, { unit_amounts: { usd: 0.008 } }] with tiers_mode: graduated",
+                        ),
+                );
+            }
+            // Validate amounts below a cent can be settled in stablecoins
+            if let Err(e) = parsed.validate_token_decimals(STABLECOIN_DECIMALS) {
+                diagnostics.push(
+                    ValidationDiagnostic::error("invalid-amount-precision", e)
+                        .with_field(format!("{}.amounts", price_prefix))
+                        .with_expected("At most 6 decimals (e.g., 0.000001)"),
+                );
+            }
         }
     }
 
@@ -256,10 +282,25 @@ Prices use `pricing_type` as the discriminator tag:
 }
 ```
 
+### Tiered Price (charged by quantity instead of a unit amount):
+```json
+{
+    "pricing_type": "one_time",
+    "tiers_mode": "graduated",
+    "tiers": [
+        {"up_to": 1000, "unit_amounts": {"usd": 0.01}},
+        {"unit_amounts": {"usd": 0.008}, "flat_amounts": {"usd": 5.0}}
+    ]
+}
+```
+The last tier has no `up_to`. Package pricing uses `"transform_quantity": {"divide_by": 1000, "round": "up"}` with a unit amount.
+
 ## Valid Enum Values
 - `currency`: "usd", "eur", "gbp"
 - `pricing_type`: "one_time", "recurring"
 - `interval`: "day", "week", "month", "year"
+- `tiers_mode`: "graduated", "volume"
+- `round`: "up", "down"
 
 ## Complete Example (Recurring Subscription):
 ```json
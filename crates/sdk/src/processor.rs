@@ -541,6 +541,7 @@ mod tests {
                 experiment_id: None,
                 features: serde_json::Value::default(),
                 quantity: 1,
+                discount: None,
             }],
             payment: Some(PaymentDetails {
                 payer: "payer-address".to_string(),
@@ -0,0 +1,414 @@
+//! Coupon and promotion code types for MoneyMQ.
+//!
+//! Coupons take a percentage or a fixed amount off a purchase, optionally only on some
+//! products. Customers redeem them through promotion codes, the codes they type in at
+//! checkout.
+//!
+//! # Loading Coupons
+//!
+//! Coupons are loaded from YAML files in the `coupons/` directory of the catalog:
+//!
+//! ```text
+//! billing/v1/coupons/
+//! ├── launch.yaml        # id: "launch"
+//! └── summer-25.yaml     # id: "summer-25"
+//! ```
+//!
+//! The `id` field defaults to the filename if not specified.
+//!
+//! # Example YAML
+//!
+//! ```yaml
+//! # billing/v1/coupons/summer-25.yaml
+//! name: Summer sale
+//! percent_off: 25
+//! duration: repeating
+//! duration_in_months: 3
+//! applies_to:
+//!   - surfnet-pro
+//! max_redemptions: 100
+//! promotion_codes:
+//!   - code: SUMMER25
+//!     expires_at: 1790000000
+//! ```
+
+use std::path::Path;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+/// How long a coupon applies to a subscription
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CouponDuration {
+    /// Only the first invoice
+    #[default]
+    Once,
+    /// Every invoice for `duration_in_months`
+    Repeating,
+    /// Every invoice
+    Forever,
+}
+
+impl CouponDuration {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CouponDuration::Once => "once",
+            CouponDuration::Repeating => "repeating",
+            CouponDuration::Forever => "forever",
+        }
+    }
+}
+
+/// Coupon declared in the catalog
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Coupon {
+    /// Coupon ID (defaults to the filename)
+    #[serde(default)]
+    pub id: String,
+
+    /// Name displayed to customers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Percentage taken off, between 0 and 100
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percent_off: Option<f64>,
+
+    /// Amount taken off (cents of `currency`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount_off: Option<i64>,
+
+    /// Currency of `amount_off`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+
+    #[serde(default)]
+    pub duration: CouponDuration,
+
+    /// Months a `repeating` coupon applies for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_in_months: Option<u32>,
+
+    /// Products the coupon is restricted to (every product when empty)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub applies_to: Vec<String>,
+
+    /// Times the coupon can be redeemed across all customers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_redemptions: Option<i64>,
+
+    /// Last time the coupon can be redeemed (Unix seconds)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redeem_by: Option<i64>,
+
+    #[serde(default = "default_active")]
+    pub active: bool,
+
+    /// Codes customers redeem the coupon with
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub promotion_codes: Vec<PromotionCode>,
+
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub metadata: IndexMap<String, String>,
+}
+
+/// Customer-facing code redeeming a coupon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromotionCode {
+    /// Promotion code ID (defaults to `promo_` followed by the lowercased code)
+    #[serde(default)]
+    pub id: String,
+
+    /// Code customers enter, matched case-insensitively
+    pub code: String,
+
+    #[serde(default = "default_active")]
+    pub active: bool,
+
+    /// Times the code can be redeemed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_redemptions: Option<i64>,
+
+    /// When the code stops being redeemable (Unix seconds)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+
+    /// Only this customer can redeem the code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub customer: Option<String>,
+
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub metadata: IndexMap<String, String>,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+impl Coupon {
+    /// Set the coupon and promotion code IDs that were not declared, from the coupon's
+    /// filename
+    pub fn with_id_from_filename(mut self, filename: &str) -> Self {
+        if self.id.is_empty() {
+            self.id = filename.to_string();
+        }
+        for promotion_code in self.promotion_codes.iter_mut() {
+            if promotion_code.id.is_empty() {
+                promotion_code.id = format!("promo_{}", promotion_code.code.to_lowercase());
+            }
+        }
+        self
+    }
+
+    /// Check that the coupon takes exactly one kind of discount off, for a valid duration
+    pub fn validate(&self) -> Result<(), String> {
+        match (self.percent_off, self.amount_off) {
+            (Some(percent_off), None) => {
+                if !(percent_off > 0.0 && percent_off <= 100.0) {
+                    return Err(format!(
+                        "Coupon '{}': percent_off must be between 0 and 100",
+                        self.id
+                    ));
+                }
+            }
+            (None, Some(amount_off)) => {
+                if amount_off <= 0 {
+                    return Err(format!("Coupon '{}': amount_off must be positive", self.id));
+                }
+                if self.currency.is_none() {
+                    return Err(format!(
+                        "Coupon '{}': amount_off requires a currency",
+                        self.id
+                    ));
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Coupon '{}' needs exactly one of percent_off or amount_off",
+                    self.id
+                ));
+            }
+        }
+        if self.duration == CouponDuration::Repeating
+            && self.duration_in_months.is_none_or(|months| months == 0)
+        {
+            return Err(format!(
+                "Coupon '{}': repeating coupons need duration_in_months",
+                self.id
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the coupon can discount `product_id` (None for amounts not tied to a
+    /// product, which only unrestricted coupons discount)
+    pub fn applies_to_product(&self, product_id: Option<&str>) -> bool {
+        self.applies_to.is_empty()
+            || product_id.is_some_and(|id| self.applies_to.iter().any(|p| p == id))
+    }
+
+    /// Whether the coupon can discount amounts in `currency`
+    pub fn applies_to_currency(&self, currency: &str) -> bool {
+        match (&self.amount_off, &self.currency) {
+            (Some(_), Some(coupon_currency)) => coupon_currency.eq_ignore_ascii_case(currency),
+            _ => true,
+        }
+    }
+
+    /// Amount taken off each line of a purchase in `currency`, in cents.
+    ///
+    /// `lines` are the product and amount of each line. Percentages are taken off each
+    /// line the coupon applies to; a fixed amount is spread over those lines in proportion
+    /// to their amount. A line is never discounted below zero.
+    pub fn discount_amounts(&self, lines: &[(Option<&str>, i64)], currency: &str) -> Vec<i64> {
+        let eligible = lines
+            .iter()
+            .map(|(product_id, amount)| {
+                if *amount > 0 && self.applies_to_product(*product_id) {
+                    *amount
+                } else {
+                    0
+                }
+            })
+            .collect::<Vec<_>>();
+        if !self.applies_to_currency(currency) {
+            return vec![0; lines.len()];
+        }
+
+        if let Some(percent_off) = self.percent_off {
+            return eligible
+                .iter()
+                .map(|amount| ((*amount as f64 * percent_off / 100.0).round() as i64).min(*amount))
+                .collect();
+        }
+
+        let eligible_total = eligible.iter().sum::<i64>();
+        let amount_off = self.amount_off.unwrap_or(0).clamp(0, eligible_total);
+        if amount_off == 0 {
+            return vec![0; lines.len()];
+        }
+        let mut discounts = eligible
+            .iter()
+            .map(|amount| ((*amount as i128 * amount_off as i128) / eligible_total as i128) as i64)
+            .collect::<Vec<_>>();
+        // Hand out the cents lost to rounding down, in line order
+        let mut remainder = amount_off - discounts.iter().sum::<i64>();
+        for (discount, amount) in discounts.iter_mut().zip(&eligible) {
+            let extra = remainder.min(amount - *discount);
+            *discount += extra;
+            remainder -= extra;
+        }
+        discounts
+    }
+
+    /// Find a promotion code of this coupon by ID or code
+    pub fn find_promotion_code(&self, id_or_code: &str) -> Option<&PromotionCode> {
+        self.promotion_codes
+            .iter()
+            .find(|p| p.id == id_or_code || p.code.eq_ignore_ascii_case(id_or_code))
+    }
+}
+
+/// Load coupons from a directory of YAML files
+///
+/// Each `.yaml` or `.yml` file in the directory is loaded as a coupon. Coupons that fail
+/// validation are rejected.
+///
+/// # Arguments
+/// * `coupons_dir` - Path to the coupons directory (e.g., `billing/v1/coupons`)
+pub fn load_coupons_from_dir(coupons_dir: &Path) -> Result<Vec<Coupon>, String> {
+    let mut coupons: Vec<Coupon> = Vec::new();
+
+    if !coupons_dir.exists() {
+        return Ok(coupons);
+    }
+
+    let mut paths = std::fs::read_dir(coupons_dir)
+        .map_err(|e| format!("Failed to read coupons directory: {}", e))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read entry: {}", e))?;
+    paths.sort();
+
+    for path in paths {
+        if !path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml")
+        {
+            continue;
+        }
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let coupon: Coupon = serde_yml::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+        let filename = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| format!("Invalid filename: {}", path.display()))?;
+        let coupon = coupon.with_id_from_filename(filename);
+        coupon.validate()?;
+
+        if coupons.iter().any(|c| c.id == coupon.id) {
+            return Err(format!("Duplicate coupon ID '{}'", coupon.id));
+        }
+        for promotion_code in &coupon.promotion_codes {
+            let taken = coupons.iter().chain(std::iter::once(&coupon)).any(|c| {
+                c.promotion_codes.iter().any(|p| {
+                    !std::ptr::eq(p, promotion_code)
+                        && p.code.eq_ignore_ascii_case(&promotion_code.code)
+                })
+            });
+            if taken {
+                return Err(format!(
+                    "Duplicate promotion code '{}'",
+                    promotion_code.code
+                ));
+            }
+        }
+        coupons.push(coupon);
+    }
+
+    Ok(coupons)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coupon(yaml: &str) -> Coupon {
+        serde_yml::from_str::<Coupon>(yaml)
+            .unwrap()
+            .with_id_from_filename("test")
+    }
+
+    #[test]
+    fn test_parse_coupon() {
+        let coupon = coupon(
+            r#"
+name: Summer sale
+percent_off: 25
+duration: repeating
+duration_in_months: 3
+applies_to:
+  - surfnet-pro
+promotion_codes:
+  - code: SUMMER25
+"#,
+        );
+        assert_eq!(coupon.id, "test");
+        assert_eq!(coupon.duration, CouponDuration::Repeating);
+        assert!(coupon.active);
+        assert!(coupon.validate().is_ok());
+        assert_eq!(coupon.promotion_codes[0].id, "promo_summer25");
+        assert!(coupon.find_promotion_code("summer25").is_some());
+        assert!(coupon.applies_to_product(Some("surfnet-pro")));
+        assert!(!coupon.applies_to_product(Some("surfnet-lite")));
+        assert!(!coupon.applies_to_product(None));
+    }
+
+    #[test]
+    fn test_validate_coupon() {
+        assert!(
+            coupon("percent_off: 10\namount_off: 100")
+                .validate()
+                .is_err()
+        );
+        assert!(coupon("amount_off: 100").validate().is_err());
+        assert!(coupon("percent_off: 120").validate().is_err());
+        assert!(
+            coupon("percent_off: 10\nduration: repeating")
+                .validate()
+                .is_err()
+        );
+        assert!(coupon("amount_off: 100\ncurrency: usd").validate().is_ok());
+    }
+
+    #[test]
+    fn test_percent_discount() {
+        let coupon = coupon("percent_off: 25\napplies_to: [pro]");
+        let lines = [(Some("pro"), 999), (Some("lite"), 500), (Some("pro"), -100)];
+        assert_eq!(coupon.discount_amounts(&lines, "usd"), vec![250, 0, 0]);
+    }
+
+    #[test]
+    fn test_amount_discount_is_spread_over_lines() {
+        let coupon = coupon("amount_off: 1000\ncurrency: USD");
+        let lines = [(Some("a"), 3000), (None, 1500), (Some("b"), 1500)];
+        assert_eq!(coupon.discount_amounts(&lines, "usd"), vec![500, 250, 250]);
+
+        // Rounding cents go to the first lines, never more than the purchase
+        let coupon = Coupon {
+            amount_off: Some(100),
+            ..coupon
+        };
+        let lines = [(Some("a"), 1), (Some("b"), 1), (Some("c"), 1)];
+        assert_eq!(coupon.discount_amounts(&lines, "usd"), vec![1, 1, 1]);
+        let lines = [(Some("a"), 100), (Some("b"), 100), (Some("c"), 100)];
+        assert_eq!(coupon.discount_amounts(&lines, "usd"), vec![34, 33, 33]);
+
+        // Fixed amounts only discount their currency
+        assert_eq!(coupon.discount_amounts(&lines, "eur"), vec![0, 0, 0]);
+    }
+}
@@ -5,9 +5,10 @@
 //! the CLI (for IAC endpoints) and MCP (for LLM tool calls).
 
 use indexmap::IndexMap;
+use rust_decimal::{Decimal, prelude::ToPrimitive};
 #[cfg(feature = "schemars")]
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 
 // ============================================================================
 // Enum Types
@@ -171,6 +172,42 @@ impl RecurringInterval {
     }
 }
 
+/// Whether a price includes tax or has tax added on top of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum TaxBehavior {
+    /// Tax is added on top of the price
+    #[default]
+    Exclusive,
+    /// The price already includes tax
+    Inclusive,
+}
+
+impl TaxBehavior {
+    /// Parse from string (case-insensitive)
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "exclusive" => Some(TaxBehavior::Exclusive),
+            "inclusive" => Some(TaxBehavior::Inclusive),
+            _ => None,
+        }
+    }
+
+    /// Get all valid values as a string (for validation messages)
+    pub fn valid_values() -> &'static str {
+        "'exclusive', 'inclusive'"
+    }
+
+    /// Get the string representation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaxBehavior::Exclusive => "exclusive",
+            TaxBehavior::Inclusive => "inclusive",
+        }
+    }
+}
+
 /// Meter aggregation formula
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
@@ -186,6 +223,34 @@ pub enum AggregationFormula {
     Last,
 }
 
+impl AggregationFormula {
+    /// Parse from string (case-insensitive)
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "sum" => Some(AggregationFormula::Sum),
+            "count" => Some(AggregationFormula::Count),
+            "max" => Some(AggregationFormula::Max),
+            "last" => Some(AggregationFormula::Last),
+            _ => None,
+        }
+    }
+
+    /// Get all valid values as a string (for validation messages)
+    pub fn valid_values() -> &'static str {
+        "'sum', 'count', 'max', 'last'"
+    }
+
+    /// Get the string representation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AggregationFormula::Sum => "sum",
+            AggregationFormula::Count => "count",
+            AggregationFormula::Max => "max",
+            AggregationFormula::Last => "last",
+        }
+    }
+}
+
 // ============================================================================
 // Feature Schema Types
 // ============================================================================
@@ -305,17 +370,17 @@ impl ProductSchema {
         self._variant.is_some()
     }
 
-    /// Check if this product has a valid price with at least one amount
+    /// Check if this product has a valid price with at least one amount or tier
     pub fn has_price(&self) -> bool {
         self.price
             .as_ref()
-            .map(|p| !p.amounts.is_empty())
+            .map(|p| !p.amounts.is_empty() || !p.tiers.is_empty())
             .unwrap_or(false)
     }
 }
 
 /// Price schema - defines how a product is priced
-/// Uses float amounts (e.g., 49.00 for $49) instead of integer cents
+/// Uses amounts in dollars (e.g., 49.00 for $49) instead of integer cents
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
 pub struct PriceSchema {
@@ -324,8 +389,26 @@ pub struct PriceSchema {
     pub id: Option<String>,
 
     /// Price amounts by currency (e.g., { "usd": 49.00, "eur": 45.00 })
-    /// Uses float values representing the full amount (not cents)
-    pub amounts: IndexMap<String, f64>,
+    /// Values represent the full amount (not cents), read exactly: 0.001 is a tenth of a cent
+    #[serde(
+        default,
+        skip_serializing_if = "IndexMap::is_empty",
+        serialize_with = "serialize_amounts"
+    )]
+    #[cfg_attr(feature = "schemars", schemars(with = "IndexMap<String, f64>"))]
+    pub amounts: IndexMap<String, Decimal>,
+
+    /// Tiers charged instead of `amounts` (Stripe's `billing_scheme: tiered`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiers: Vec<PriceTierSchema>,
+
+    /// How quantities are charged across `tiers` (required with tiers)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tiers_mode: Option<TiersMode>,
+
+    /// Package pricing: the quantity is divided before it is charged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform_quantity: Option<TransformQuantity>,
 
     /// Pricing type: one_time (default) or recurring
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -343,6 +426,11 @@ pub struct PriceSchema {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub trial: Option<TrialConfig>,
 
+    /// Whether the amounts include tax (inclusive) or tax is added on top (exclusive,
+    /// the default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_behavior: Option<TaxBehavior>,
+
     /// Whether the price is active. Default: true
     #[serde(skip_serializing_if = "Option::is_none")]
     pub active: Option<bool>,
@@ -363,13 +451,21 @@ impl PriceSchema {
     }
 
     /// Get the primary amount (first currency in the map)
-    pub fn primary_amount(&self) -> Option<(String, f64)> {
+    pub fn primary_amount(&self) -> Option<(String, Decimal)> {
         self.amounts.iter().next().map(|(k, v)| (k.clone(), *v))
     }
 
-    /// Get the primary currency
+    /// Get the primary currency (the first of the amounts, or of the tiers)
     pub fn primary_currency(&self) -> Option<Currency> {
-        self.amounts.keys().next().and_then(|c| Currency::parse(c))
+        self.amounts
+            .keys()
+            .chain(
+                self.tiers
+                    .iter()
+                    .flat_map(|tier| tier.unit_amounts.keys().chain(tier.flat_amounts.keys())),
+            )
+            .next()
+            .and_then(|c| Currency::parse(c))
     }
 }
 
@@ -400,6 +496,118 @@ pub struct OverageConfig {
     pub included: Option<i64>,
 }
 
+/// How a tiered price charges a quantity across its tiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum TiersMode {
+    /// Each unit is charged at the tier it falls in, and every tier reached adds its flat fee
+    Graduated,
+    /// Every unit is charged at the tier the whole quantity falls in
+    Volume,
+}
+
+impl TiersMode {
+    /// Parse from string (case-insensitive)
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "graduated" => Some(TiersMode::Graduated),
+            "volume" => Some(TiersMode::Volume),
+            _ => None,
+        }
+    }
+
+    /// Get all valid values as a string (for validation messages)
+    pub fn valid_values() -> &'static str {
+        "'graduated', 'volume'"
+    }
+
+    /// Get the string representation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TiersMode::Graduated => "graduated",
+            TiersMode::Volume => "volume",
+        }
+    }
+}
+
+/// Tier of a tiered price
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct PriceTierSchema {
+    /// Highest quantity of the tier; the last tier has none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub up_to: Option<i64>,
+
+    /// Price per unit by currency (e.g., { "usd": 0.01 })
+    #[serde(
+        default,
+        skip_serializing_if = "IndexMap::is_empty",
+        serialize_with = "serialize_amounts"
+    )]
+    #[cfg_attr(feature = "schemars", schemars(with = "IndexMap<String, f64>"))]
+    pub unit_amounts: IndexMap<String, Decimal>,
+
+    /// Flat fee of the tier by currency (e.g., { "usd": 5.00 })
+    #[serde(
+        default,
+        skip_serializing_if = "IndexMap::is_empty",
+        serialize_with = "serialize_amounts"
+    )]
+    #[cfg_attr(feature = "schemars", schemars(with = "IndexMap<String, f64>"))]
+    pub flat_amounts: IndexMap<String, Decimal>,
+}
+
+/// Package pricing, such as "$5 per 1,000 calls"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct TransformQuantity {
+    /// Units per package
+    pub divide_by: i64,
+
+    /// How partial packages are rounded. Default: up
+    #[serde(default)]
+    pub round: QuantityRounding,
+}
+
+impl TransformQuantity {
+    /// Number of packages charged for `quantity` units
+    pub fn apply(&self, quantity: i64) -> i64 {
+        if self.divide_by <= 0 {
+            return quantity;
+        }
+        match self.round {
+            // `div_ceil` is unstable for signed integers; this form can't overflow
+            QuantityRounding::Up => {
+                quantity / self.divide_by + i64::from(quantity % self.divide_by > 0)
+            }
+            QuantityRounding::Down => quantity / self.divide_by,
+        }
+    }
+}
+
+/// Rounding of partial packages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum QuantityRounding {
+    /// A partial package is charged as a whole one
+    #[default]
+    Up,
+    /// A partial package is not charged
+    Down,
+}
+
+impl QuantityRounding {
+    /// Get the string representation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuantityRounding::Up => "up",
+            QuantityRounding::Down => "down",
+        }
+    }
+}
+
 /// Trial period configuration
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
@@ -1012,18 +1220,67 @@ fn merge_metadata(
 // Conversions from Schema Types to Runtime Types
 // ============================================================================
 
+/// Exact amount in cents of an amount in dollars (e.g., 0.001 is 0.1 cent)
+pub fn dollars_to_cents(amount: Decimal) -> Option<Decimal> {
+    amount.checked_mul(Decimal::ONE_HUNDRED)
+}
+
+/// Amount in dollars of an exact amount in cents, as written in YAML
+pub fn cents_to_dollars(cents: Decimal) -> Decimal {
+    (cents / Decimal::ONE_HUNDRED).normalize()
+}
+
+/// Write amounts in dollars as numbers, as they are in YAML, unless a float can't hold them
+/// exactly. YAML numbers are read as floats, whose shortest representation is the amount as
+/// written; amounts needing more digits than a float has are read exactly from strings.
+fn serialize_amounts<S: Serializer>(
+    amounts: &IndexMap<String, Decimal>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Amount {
+        Number(f64),
+        Exact(String),
+    }
+
+    serializer.collect_map(amounts.iter().map(|(currency, amount)| {
+        let number = amount
+            .to_f64()
+            .filter(|number| number.to_string().parse::<Decimal>().ok() == Some(*amount));
+        let amount = match number {
+            Some(number) => Amount::Number(number),
+            None => Amount::Exact(amount.to_string()),
+        };
+        (currency, amount)
+    }))
+}
+
 impl From<PriceSchema> for crate::Price {
     fn from(schema: PriceSchema) -> Self {
         // Get primary currency (first in map) or default to USD
         let currency = schema.primary_currency().unwrap_or(Currency::Usd);
         let pricing_type = schema.get_pricing_type();
 
-        // Convert float amount to cents (i64)
-        let unit_amount = schema
-            .primary_amount()
-            .map(|(_, amount)| (amount * 100.0).round() as i64);
+        let tiers = schema
+            .tiers
+            .into_iter()
+            .map(crate::PriceTier::from)
+            .collect();
 
-        let mut price = crate::Price::new(currency, pricing_type).with_some_amount(unit_amount);
+        let mut price = crate::Price::new(currency, pricing_type)
+            .with_overage(schema.overage)
+            .with_tax_behavior(schema.tax_behavior)
+            .with_tiers(tiers, schema.tiers_mode)
+            .with_transform_quantity(schema.transform_quantity);
+
+        // Convert the amount to exact cents, keeping a fraction of a cent
+        if let Some(cents) = schema
+            .primary_amount()
+            .and_then(|(_, amount)| dollars_to_cents(amount))
+        {
+            price = price.with_exact_amount(cents);
+        }
 
         // Set recurring interval if applicable
         if let Some(recurring) = &schema.recurring {
@@ -1035,6 +1292,28 @@ impl From<PriceSchema> for crate::Price {
     }
 }
 
+impl From<PriceTierSchema> for crate::PriceTier {
+    fn from(schema: PriceTierSchema) -> Self {
+        // Convert amounts to whole cents, by lowercase currency
+        let cents = |amounts: IndexMap<String, Decimal>| -> IndexMap<String, i64> {
+            amounts
+                .into_iter()
+                .filter_map(|(currency, amount)| {
+                    Some((
+                        currency.to_lowercase(),
+                        crate::round_cents(dollars_to_cents(amount)?),
+                    ))
+                })
+                .collect()
+        };
+        crate::PriceTier {
+            up_to: schema.up_to,
+            unit_amounts: cents(schema.unit_amounts),
+            flat_amounts: cents(schema.flat_amounts),
+        }
+    }
+}
+
 impl From<ProductSchema> for crate::Product {
     fn from(schema: ProductSchema) -> Self {
         let mut product = crate::Product::new();
@@ -1072,10 +1351,10 @@ impl From<ProductSchema> for crate::Product {
 mod tests {
     use super::*;
 
-    /// Create a price with the given amount in dollars (float)
-    fn make_price(amount: f64) -> PriceSchema {
+    /// Create a price with the given amount in dollars
+    fn make_price(amount: &str) -> PriceSchema {
         let mut amounts = IndexMap::new();
-        amounts.insert("usd".to_string(), amount);
+        amounts.insert("usd".to_string(), amount.parse().unwrap());
         PriceSchema {
             id: None,
             amounts,
@@ -1083,6 +1362,10 @@ mod tests {
             recurring: None,
             overage: None,
             trial: None,
+            tax_behavior: None,
+            tiers: Vec::new(),
+            tiers_mode: None,
+            transform_quantity: None,
             active: Some(true),
             nickname: None,
             metadata: None,
@@ -1108,7 +1391,7 @@ mod tests {
         }
     }
 
-    fn make_variant(product_dir: &str, variant_name: &str, amount: f64) -> ProductSchema {
+    fn make_variant(product_dir: &str, variant_name: &str, amount: &str) -> ProductSchema {
         ProductSchema {
             id: String::new(),
             name: format!("Product {}", variant_name),
@@ -1131,7 +1414,7 @@ mod tests {
     fn test_consolidate_single_variant() {
         let products = vec![
             make_base_product("surfnet"),
-            make_variant("surfnet", "pro", 9.99), // $9.99
+            make_variant("surfnet", "pro", "9.99"), // $9.99
         ];
 
         let consolidated = consolidate_products(&products);
@@ -1157,9 +1440,9 @@ mod tests {
     fn test_consolidate_multiple_variants() {
         let products = vec![
             make_base_product("surfnet"),
-            make_variant("surfnet", "light", 3.99),
-            make_variant("surfnet", "pro", 9.99),
-            make_variant("surfnet", "max", 19.99),
+            make_variant("surfnet", "light", "3.99"),
+            make_variant("surfnet", "pro", "9.99"),
+            make_variant("surfnet", "max", "19.99"),
         ];
 
         let consolidated = consolidate_products(&products);
@@ -1177,7 +1460,7 @@ mod tests {
     fn test_base_product_not_included() {
         let products = vec![
             make_base_product("surfnet"),
-            make_variant("surfnet", "pro", 9.99),
+            make_variant("surfnet", "pro", "9.99"),
         ];
 
         let consolidated = consolidate_products(&products);
@@ -1191,14 +1474,14 @@ mod tests {
 
     #[test]
     fn test_variant_without_price_excluded() {
-        let mut variant_no_price = make_variant("surfnet", "empty", 0.0);
+        let mut variant_no_price = make_variant("surfnet", "empty", "0.0");
         // Set price to None to represent a product without price
         variant_no_price.price = None;
 
         let products = vec![
             make_base_product("surfnet"),
             variant_no_price,
-            make_variant("surfnet", "pro", 9.99),
+            make_variant("surfnet", "pro", "9.99"),
         ];
 
         let consolidated = consolidate_products(&products);
@@ -1220,7 +1503,7 @@ mod tests {
             images: None,
             metadata: None,
             features: None,
-            price: Some(make_price(5.99)),
+            price: Some(make_price("5.99")),
             _source_file: Some("standalone".to_string()),
             _product_dir: None, // No product_dir = standalone
             _variant: None,
@@ -1237,7 +1520,7 @@ mod tests {
     #[test]
     fn test_variant_overrides_base_description() {
         let base = make_base_product("myproduct");
-        let mut variant = make_variant("myproduct", "custom", 7.99);
+        let mut variant = make_variant("myproduct", "custom", "7.99");
         variant.description = Some("Custom description overrides base".to_string());
 
         let products = vec![base, variant];
@@ -1257,7 +1540,7 @@ mod tests {
         base.product_type = Some("subscription".to_string());
         base.unit_label = Some("per user".to_string());
 
-        let mut variant = make_variant("myproduct", "basic", 4.99);
+        let mut variant = make_variant("myproduct", "basic", "4.99");
         variant.product_type = None; // Should inherit
         variant.unit_label = None; // Should inherit
 
@@ -1287,7 +1570,7 @@ mod tests {
         let mut base = make_base_product("myproduct");
         base.metadata = Some(base_metadata);
 
-        let mut variant = make_variant("myproduct", "pro", 9.99);
+        let mut variant = make_variant("myproduct", "pro", "9.99");
         variant.metadata = Some(variant_metadata);
 
         let products = vec![base, variant];
@@ -1304,11 +1587,11 @@ mod tests {
     fn test_multiple_product_dirs() {
         let products = vec![
             make_base_product("product-a"),
-            make_variant("product-a", "lite", 1.99),
-            make_variant("product-a", "pro", 4.99),
+            make_variant("product-a", "lite", "1.99"),
+            make_variant("product-a", "pro", "4.99"),
             make_base_product("product-b"),
-            make_variant("product-b", "starter", 0.99),
-            make_variant("product-b", "enterprise", 9.99),
+            make_variant("product-b", "starter", "0.99"),
+            make_variant("product-b", "enterprise", "9.99"),
         ];
 
         let consolidated = consolidate_products(&products);
@@ -1325,7 +1608,7 @@ mod tests {
     #[test]
     fn test_is_base_product() {
         let base = make_base_product("surfnet");
-        let variant = make_variant("surfnet", "pro", 9.99);
+        let variant = make_variant("surfnet", "pro", "9.99");
 
         assert!(base.is_base_product());
         assert!(!variant.is_base_product());
@@ -1334,7 +1617,7 @@ mod tests {
     #[test]
     fn test_is_variant() {
         let base = make_base_product("surfnet");
-        let variant = make_variant("surfnet", "pro", 9.99);
+        let variant = make_variant("surfnet", "pro", "9.99");
 
         assert!(!base.is_variant());
         assert!(variant.is_variant());
@@ -1342,8 +1625,8 @@ mod tests {
 
     #[test]
     fn test_has_price() {
-        let with_price = make_variant("surfnet", "pro", 9.99);
-        let mut without_price = make_variant("surfnet", "empty", 0.0);
+        let with_price = make_variant("surfnet", "pro", "9.99");
+        let mut without_price = make_variant("surfnet", "empty", "0.0");
         without_price.price = None;
 
         assert!(with_price.has_price());
@@ -1355,7 +1638,7 @@ mod tests {
         let mut base = make_base_product("myproduct");
         base.active = Some(false); // Base is inactive
 
-        let variant = make_variant("myproduct", "pro", 9.99);
+        let variant = make_variant("myproduct", "pro", "9.99");
 
         let products = vec![base, variant];
         let consolidated = consolidate_products(&products);
@@ -1573,4 +1856,109 @@ metadata:
         assert_eq!(tx_limit["description"], "Total transactions");
         assert_eq!(tx_limit["value"], 500);
     }
+
+    #[test]
+    fn test_tiered_and_package_prices() {
+        let schema: PriceSchema = serde_yml::from_str(
+            r#"
+tiers_mode: graduated
+tiers:
+  - up_to: 1000
+    unit_amounts: { usd: 0.02 }
+    flat_amounts: { usd: 5.00 }
+  - unit_amounts: { usd: 0.01 }
+"#,
+        )
+        .unwrap();
+        let mut price = crate::Price::from(schema);
+        assert!(price.is_tiered());
+        assert_eq!(price.unit_amount, None);
+        price.validate_pricing().unwrap();
+
+        // 5.00 + 1000 × 0.02, then 500 × 0.01
+        assert_eq!(price.amount_for(1500, "usd"), Some(3000));
+        assert_eq!(price.amount_for(0, "usd"), Some(500));
+        assert_eq!(price.amount_for(10, "eur"), None);
+
+        // Every unit at the tier 1500 falls in
+        price.tiers_mode = Some(TiersMode::Volume);
+        assert_eq!(price.amount_for(1500, "usd"), Some(1500));
+        assert_eq!(price.amount_for(1000, "usd"), Some(2500));
+
+        price.tiers.reverse();
+        assert!(price.validate_pricing().is_err());
+
+        // $5 per 1,000 calls, partial packages charged in full
+        let package: PriceSchema = serde_yml::from_str(
+            "amounts: { usd: 5.00 }\ntransform_quantity: { divide_by: 1000 }\n",
+        )
+        .unwrap();
+        let package = crate::Price::from(package);
+        assert_eq!(package.amount_for(2001, "usd"), Some(1500));
+        assert_eq!(
+            TransformQuantity {
+                divide_by: 1000,
+                round: QuantityRounding::Down
+            }
+            .apply(2001),
+            2
+        );
+    }
+
+    #[test]
+    fn test_amounts_overflowing_quantities() {
+        // Huge quantities have no amount rather than a wrapped one
+        let price = crate::Price::from(make_price("9.99"));
+        assert_eq!(price.amount_for(i64::MAX, "usd"), None);
+        assert_eq!(price.exact_amount_for(i64::MAX, "usd"), None);
+        let price = crate::Price::from(make_price("0.015"));
+        assert_eq!(price.exact_amount_for(i64::MAX, "usd"), None);
+        assert_eq!(price.amount_for(i64::MAX, "usd"), None);
+
+        let schema: PriceSchema = serde_yml::from_str(
+            r#"
+tiers_mode: graduated
+tiers:
+  - up_to: 10
+    unit_amounts: { usd: 1.00 }
+  - unit_amounts: { usd: 0.50 }
+"#,
+        )
+        .unwrap();
+        let mut price = crate::Price::from(schema);
+        assert_eq!(price.amount_for(i64::MAX, "usd"), None);
+        price.tiers_mode = Some(TiersMode::Volume);
+        assert_eq!(price.amount_for(i64::MAX, "usd"), None);
+
+        let package = TransformQuantity {
+            divide_by: 1000,
+            round: QuantityRounding::Up,
+        };
+        assert_eq!(package.apply(i64::MAX), i64::MAX / 1000 + 1);
+    }
+
+    #[test]
+    fn test_sub_cent_amounts() {
+        // $0.001 per call is a tenth of a cent
+        let price = crate::Price::from(make_price("0.001"));
+        assert_eq!(price.unit_amount, None);
+        assert_eq!(price.unit_amount_decimal, Some(Decimal::new(1, 1)));
+        assert_eq!(price.exact_amount_for(3, "usd"), Some(Decimal::new(3, 1)));
+        assert_eq!(price.amount_for(1000, "usd"), Some(100));
+        assert_eq!(price.amount_for(5, "usd"), Some(1));
+        price.validate_pricing().unwrap();
+
+        // USDC's 6 decimals settle it, a 2-decimal token can't
+        price.validate_token_decimals(6).unwrap();
+        assert!(price.validate_token_decimals(2).is_err());
+
+        // Whole cents stay integer amounts
+        let price = crate::Price::from(make_price("9.99"));
+        assert_eq!(price.unit_amount, Some(999));
+        assert_eq!(price.unit_amount_decimal, None);
+        assert_eq!(
+            dollars_to_cents(Decimal::new(1, 6)),
+            Some(Decimal::new(1, 4))
+        );
+    }
 }
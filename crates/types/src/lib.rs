@@ -1,13 +1,19 @@
 use chrono::{DateTime, Utc};
 use indexmap::IndexMap;
+use rust_decimal::{RoundingStrategy, prelude::ToPrimitive};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value as JsonValue;
 
 pub mod actors;
+pub mod coupons;
 pub mod iac;
+pub mod payment_links;
 pub mod stripe;
 pub mod x402;
 
+// Exact decimal amounts, such as prices below a cent
+pub use rust_decimal::Decimal;
+
 // Re-export actor types (new names)
 // Backwards compatibility re-exports (deprecated)
 #[allow(deprecated)]
@@ -20,6 +26,11 @@ pub use actors::{
     Keychain, OperatedRole, OperatorRole, PayoutRole, TurnkeyKeychain, load_actors_from_dir,
     to_snake_case,
 };
+pub use coupons::{Coupon, CouponDuration, PromotionCode, load_coupons_from_dir};
+pub use payment_links::{
+    AdjustableQuantity, CustomField, CustomFieldOption, CustomFieldType, PaymentLink,
+    PaymentLinkLineItem, load_payment_links_from_dir,
+};
 // Re-export commonly used IAC types at crate root for convenience
 pub use iac::{
     // Schema types (for JSON/API)
@@ -31,13 +42,18 @@ pub use iac::{
     OverageConfig,
     PriceDefaults,
     PriceSchema,
+    PriceTierSchema,
     PricingType,
     // YAML parsing types (for product.yaml and variants/*.yaml)
     ProductBase,
     ProductSchema,
     ProductVariant,
+    QuantityRounding,
     RecurringConfig,
     RecurringInterval,
+    TaxBehavior,
+    TiersMode,
+    TransformQuantity,
     TrialConfig,
     ValidationDiagnostic,
     ValidationResult,
@@ -163,6 +179,16 @@ pub struct Price {
     /// The unit amount (in cents for currencies like USD)
     pub unit_amount: Option<i64>,
 
+    /// Exact unit amount in cents with a fraction of a cent (e.g., `0.1` for $0.001), set
+    /// instead of the unit amount for micropayment prices (Stripe's `unit_amount_decimal`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit_amount_decimal: Option<Decimal>,
+
+    /// Unit amounts in other currencies, by lowercase currency code (e.g., `eur`), for
+    /// buyers paying in those currencies
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub currency_options: IndexMap<String, i64>,
+
     /// Pricing type: one_time or recurring
     pub pricing_type: iac::PricingType,
 
@@ -175,6 +201,26 @@ pub struct Price {
     /// Nickname for the price
     pub nickname: Option<String>,
 
+    /// Usage billed on top of the recurring amount, against a meter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overage: Option<iac::OverageConfig>,
+
+    /// Whether the unit amount includes tax (exclusive when not set)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_behavior: Option<iac::TaxBehavior>,
+
+    /// Tiers charged instead of the unit amount (Stripe's `billing_scheme=tiered`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiers: Vec<PriceTier>,
+
+    /// How quantities are charged across the tiers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tiers_mode: Option<iac::TiersMode>,
+
+    /// Package pricing: the quantity is divided before it is charged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform_quantity: Option<iac::TransformQuantity>,
+
     /// Additional metadata
     #[serde(
         serialize_with = "serialize_metadata",
@@ -196,10 +242,17 @@ impl Price {
             active: true,
             currency,
             unit_amount: None,
+            unit_amount_decimal: None,
+            currency_options: IndexMap::new(),
             pricing_type,
             recurring_interval: None,
             recurring_interval_count: None,
             nickname: None,
+            overage: None,
+            tax_behavior: None,
+            tiers: Vec::new(),
+            tiers_mode: None,
+            transform_quantity: None,
             metadata: IndexMap::new(),
             created_at: Utc::now(),
         }
@@ -211,6 +264,29 @@ impl Price {
         self
     }
 
+    /// Set the unit amount from an exact amount in cents, kept as a decimal amount when it
+    /// has a fraction of a cent
+    pub fn with_exact_amount(mut self, cents: Decimal) -> Self {
+        match cents.fract().is_zero().then(|| cents.to_i64()).flatten() {
+            Some(amount) => {
+                self.unit_amount = Some(amount);
+                self.unit_amount_decimal = None;
+            }
+            None => {
+                self.unit_amount = None;
+                self.unit_amount_decimal = Some(cents.normalize());
+            }
+        }
+        self
+    }
+
+    /// Unit amount in cents in the price's own currency, with its fraction of a cent for
+    /// decimal amounts. None for prices charged by their tiers.
+    pub fn exact_unit_amount(&self) -> Option<Decimal> {
+        self.unit_amount_decimal
+            .or_else(|| self.unit_amount.map(Decimal::from))
+    }
+
     /// Set the recurring interval
     pub fn with_some_interval(mut self, interval: Option<iac::RecurringInterval>) -> Self {
         self.recurring_interval = interval;
@@ -223,6 +299,229 @@ impl Price {
         self
     }
 
+    /// Set the overage billed against a meter
+    pub fn with_overage(mut self, overage: Option<iac::OverageConfig>) -> Self {
+        self.overage = overage;
+        self
+    }
+
+    /// Set whether the unit amount includes tax
+    pub fn with_tax_behavior(mut self, tax_behavior: Option<iac::TaxBehavior>) -> Self {
+        self.tax_behavior = tax_behavior;
+        self
+    }
+
+    /// Set the unit amounts in other currencies
+    pub fn with_currency_options(mut self, currency_options: IndexMap<String, i64>) -> Self {
+        self.currency_options = currency_options;
+        self
+    }
+
+    /// Set the tiers charged instead of the unit amount, and how quantities are charged
+    /// across them
+    pub fn with_tiers(mut self, tiers: Vec<PriceTier>, tiers_mode: Option<iac::TiersMode>) -> Self {
+        self.tiers = tiers;
+        self.tiers_mode = tiers_mode;
+        self
+    }
+
+    /// Set the package pricing
+    pub fn with_transform_quantity(
+        mut self,
+        transform_quantity: Option<iac::TransformQuantity>,
+    ) -> Self {
+        self.transform_quantity = transform_quantity;
+        self
+    }
+
+    /// Unit amount of the price in `currency`, if it is priced in that currency
+    pub fn amount_in(&self, currency: &str) -> Option<i64> {
+        if self.currency.as_str().eq_ignore_ascii_case(currency) {
+            return self.unit_amount;
+        }
+        self.currency_options
+            .iter()
+            .find(|(option, _)| option.eq_ignore_ascii_case(currency))
+            .map(|(_, amount)| *amount)
+    }
+
+    /// Whether the price charges quantities across tiers rather than per unit
+    pub fn is_tiered(&self) -> bool {
+        !self.tiers.is_empty()
+    }
+
+    /// Amount charged for `quantity` units in `currency` (in cents), if the price is priced
+    /// in that currency and the amount fits in an `i64`
+    ///
+    /// Package prices charge whole packages of units, and tiered prices charge the quantity
+    /// across their tiers: graduated tiers charge each unit at the tier it falls in, volume
+    /// tiers charge every unit at the tier the whole quantity falls in.
+    pub fn amount_for(&self, quantity: i64, currency: &str) -> Option<i64> {
+        // Decimal amounts are charged exactly, then rounded to the cent
+        if self.unit_amount_decimal.is_some()
+            && self.currency.as_str().eq_ignore_ascii_case(currency)
+        {
+            return self.exact_amount_for(quantity, currency).map(round_cents);
+        }
+        let quantity = self
+            .transform_quantity
+            .map_or(quantity, |transform| transform.apply(quantity));
+        if self.tiers.is_empty() {
+            return self.amount_in(currency)?.checked_mul(quantity);
+        }
+
+        match self.tiers_mode.unwrap_or(iac::TiersMode::Graduated) {
+            iac::TiersMode::Volume => {
+                let tier = self
+                    .tiers
+                    .iter()
+                    .find(|tier| tier.up_to.is_none_or(|up_to| quantity <= up_to))?;
+                let (unit_amount, flat_amount) = tier.amounts_in(currency)?;
+                unit_amount.checked_mul(quantity)?.checked_add(flat_amount)
+            }
+            iac::TiersMode::Graduated => {
+                let mut total: i64 = 0;
+                let mut charged = 0;
+                for tier in &self.tiers {
+                    let (unit_amount, flat_amount) = tier.amounts_in(currency)?;
+                    let up_to = tier.up_to.unwrap_or(i64::MAX);
+                    total = unit_amount
+                        .checked_mul(quantity.min(up_to) - charged)?
+                        .checked_add(flat_amount)?
+                        .checked_add(total)?;
+                    if quantity <= up_to {
+                        return Some(total);
+                    }
+                    charged = up_to;
+                }
+                // The last tier is bounded and the quantity is above it
+                None
+            }
+        }
+    }
+
+    /// Exact amount charged for `quantity` units in `currency` (in cents, with a fraction of
+    /// a cent for decimal amounts), if the price is priced in that currency and the amount
+    /// fits in an `i64` of cents
+    pub fn exact_amount_for(&self, quantity: i64, currency: &str) -> Option<Decimal> {
+        if let Some(unit_amount) = self.unit_amount_decimal
+            && self.currency.as_str().eq_ignore_ascii_case(currency)
+        {
+            let quantity = self
+                .transform_quantity
+                .map_or(quantity, |transform| transform.apply(quantity));
+            return unit_amount
+                .checked_mul(Decimal::from(quantity))
+                .filter(|amount| amount.abs() <= Decimal::from(i64::MAX));
+        }
+        self.amount_for(quantity, currency).map(Decimal::from)
+    }
+
+    /// Check that the decimal amount can be settled in a token with `decimals` decimals
+    /// (6 for USDC): a fraction of a cent below its base unit can't be paid
+    pub fn validate_token_decimals(&self, decimals: u8) -> Result<(), String> {
+        match self.unit_amount_decimal {
+            // Cents have 2 decimals less than the currency
+            Some(amount) if amount.normalize().scale() + 2 > decimals as u32 => Err(format!(
+                "Price {}: {} cents has more decimals than a token with {} decimals can settle",
+                self.id, amount, decimals
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that the price can be charged: tiers need a mode and ascending bounds, the last
+    /// one unbounded, and as on Stripe they can't be combined with a unit amount or package
+    /// pricing
+    pub fn validate_pricing(&self) -> Result<(), String> {
+        if let Some(transform) = &self.transform_quantity
+            && transform.divide_by < 1
+        {
+            return Err(format!(
+                "Price {}: transform_quantity.divide_by must be at least 1",
+                self.id
+            ));
+        }
+        if let Some(amount) = self.unit_amount_decimal {
+            if self.unit_amount.is_some() {
+                return Err(format!(
+                    "Price {}: set either unit_amount or unit_amount_decimal",
+                    self.id
+                ));
+            }
+            if amount.is_sign_negative() {
+                return Err(format!(
+                    "Price {}: unit_amount_decimal can't be negative",
+                    self.id
+                ));
+            }
+            // As on Stripe, at most 12 decimals of a cent
+            if amount.normalize().scale() > 12 {
+                return Err(format!(
+                    "Price {}: unit_amount_decimal has more than 12 decimals",
+                    self.id
+                ));
+            }
+        }
+        if self.tiers.is_empty() {
+            return Ok(());
+        }
+
+        if self.tiers_mode.is_none() {
+            return Err(format!(
+                "Price {}: tiered prices need a tiers_mode ({})",
+                self.id,
+                iac::TiersMode::valid_values()
+            ));
+        }
+        if self.unit_amount.is_some()
+            || self.unit_amount_decimal.is_some()
+            || !self.currency_options.is_empty()
+        {
+            return Err(format!(
+                "Price {}: tiered prices are charged by their tiers and can't have amounts",
+                self.id
+            ));
+        }
+        if self.transform_quantity.is_some() {
+            return Err(format!(
+                "Price {}: tiered prices can't use transform_quantity",
+                self.id
+            ));
+        }
+        let (last, bounded) = self.tiers.split_last().expect("tiers are not empty");
+        if last.up_to.is_some() {
+            return Err(format!(
+                "Price {}: the last tier must have no up_to",
+                self.id
+            ));
+        }
+        let mut previous = 0;
+        for tier in bounded {
+            match tier.up_to {
+                Some(up_to) if up_to > previous => previous = up_to,
+                _ => {
+                    return Err(format!(
+                        "Price {}: tiers must have ascending up_to bounds, only the last one unbounded",
+                        self.id
+                    ));
+                }
+            }
+        }
+        let currency = self.currency.as_str();
+        if self
+            .tiers
+            .iter()
+            .any(|tier| tier.amounts_in(currency).is_none())
+        {
+            return Err(format!(
+                "Price {}: every tier needs a unit or flat amount in {}",
+                self.id, currency
+            ));
+        }
+        Ok(())
+    }
+
     /// Get the provider ID for a given sandbox name ("default" for primary sandbox)
     pub fn get_sandbox_id(&self, sandbox_name: &str) -> Option<&String> {
         self.sandboxes.get(sandbox_name)
@@ -239,6 +538,39 @@ impl Price {
     }
 }
 
+/// Tier of a tiered price
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceTier {
+    /// Highest quantity of the tier; the last tier has none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub up_to: Option<i64>,
+
+    /// Amount per unit by lowercase currency code (in cents)
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub unit_amounts: IndexMap<String, i64>,
+
+    /// Flat fee of the tier by lowercase currency code (in cents)
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub flat_amounts: IndexMap<String, i64>,
+}
+
+impl PriceTier {
+    /// Unit and flat amounts of the tier in `currency`, if it has either in that currency
+    pub fn amounts_in(&self, currency: &str) -> Option<(i64, i64)> {
+        let amount = |amounts: &IndexMap<String, i64>| {
+            amounts
+                .iter()
+                .find(|(option, _)| option.eq_ignore_ascii_case(currency))
+                .map(|(_, amount)| *amount)
+        };
+        let (unit_amount, flat_amount) = (amount(&self.unit_amounts), amount(&self.flat_amounts));
+        if unit_amount.is_none() && flat_amount.is_none() {
+            return None;
+        }
+        Some((unit_amount.unwrap_or(0), flat_amount.unwrap_or(0)))
+    }
+}
+
 /// MoneyMQ Product - provider-agnostic product representation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Product {
@@ -318,6 +650,16 @@ pub struct ExperimentConfig {
     pub exposure: f64,
 }
 
+/// `amount` cents rounded to the cent, halves away from zero
+pub fn round_cents(amount: Decimal) -> i64 {
+    let rounded = amount.round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero);
+    rounded.to_i64().unwrap_or(if rounded.is_sign_negative() {
+        i64::MIN
+    } else {
+        i64::MAX
+    })
+}
+
 fn random_id() -> String {
     use rand::{Rng, distr::Alphanumeric};
     let random_part: String = rand::rng()
@@ -614,6 +956,9 @@ pub struct LineItem {
     /// Quantity of items
     #[serde(default = "default_quantity")]
     pub quantity: u32,
+    /// Amount taken off the line by a coupon (cents)
+    #[serde(default, rename = "amountDiscount")]
+    pub amount_discount: Option<i64>,
 }
 
 fn default_quantity() -> u32 {
@@ -635,6 +980,61 @@ pub struct BasketItem {
     /// Quantity of items
     #[serde(default = "default_quantity")]
     pub quantity: u32,
+    /// Coupon discount applied to the item
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discount: Option<BasketDiscount>,
+}
+
+/// Coupon discount applied to a basket item
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BasketDiscount {
+    /// Coupon ID
+    pub coupon: String,
+    /// Promotion code the coupon was redeemed with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub promotion_code: Option<String>,
+    /// Amount taken off the item (cents)
+    pub amount: i64,
+}
+
+/// Tax charged on a payment, broken out of its amount
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentTax {
+    /// Tax charged (cents), including tax already included in tax-inclusive prices
+    pub amount: i64,
+    /// Part of `amount` included in tax-inclusive prices (cents)
+    #[serde(default)]
+    pub amount_inclusive: i64,
+    /// Rate applied, in percent
+    pub rate: f64,
+    /// Kind of tax (e.g., "VAT", "GST", "sales_tax")
+    pub tax_type: String,
+    /// Country the tax is due in (ISO 3166-1 alpha-2)
+    pub country: String,
+    /// State or province the tax is due in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+}
+
+/// Currency conversion applied to a payment priced in a currency no accepted stablecoin
+/// tracks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentFx {
+    /// Currency the payment was priced in (e.g., "eur")
+    pub currency: String,
+    /// Amount the payment was priced at, in `currency` (cents)
+    pub amount: i64,
+    /// Currency tracked by the stablecoin the payment settled in (e.g., "usd")
+    pub settlement_currency: String,
+    /// Amount settled, in `settlement_currency` (cents)
+    pub settlement_amount: i64,
+    /// Units of `settlement_currency` per unit of `currency`
+    pub rate: f64,
+    /// Provider the rate was taken from (e.g., "static", "mock")
+    pub source: String,
 }
 
 fn is_features_empty(v: &serde_json::Value) -> bool {
@@ -965,6 +1365,21 @@ pub mod event_types {
 
     /// Transaction completed with receipt
     pub const TRANSACTION_COMPLETED: &str = "transaction:completed";
+
+    /// Payment channel deposit settled and channel opened
+    pub const PAYMENT_CHANNEL_OPENED: &str = "payment_channel:opened";
+
+    /// Payment channel voucher redeemed
+    pub const PAYMENT_CHANNEL_VOUCHER: &str = "payment_channel:voucher";
+
+    /// Payment channel balance settled on-chain
+    pub const PAYMENT_CHANNEL_SETTLED: &str = "payment_channel:settled";
+
+    /// Payment channel closed and remaining deposit refunded
+    pub const PAYMENT_CHANNEL_CLOSED: &str = "payment_channel:closed";
+
+    /// Catalog and actors reloaded after their files changed
+    pub const CATALOG_RELOADED: &str = "catalog:reloaded";
 }
 
 /// Payment defaults
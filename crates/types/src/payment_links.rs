@@ -0,0 +1,452 @@
+//! Payment link types for MoneyMQ.
+//!
+//! A payment link is a reusable, shareable URL selling a fixed set of prices. Every visit
+//! of the link opens a fresh checkout session for its line items, so selling a product
+//! needs no code. Buyers may adjust the quantity of line items that allow it, and fill in
+//! the custom fields the link asks for.
+//!
+//! # Loading Payment Links
+//!
+//! Payment links are loaded from YAML files in the `payment_links/` directory of the
+//! catalog:
+//!
+//! ```text
+//! billing/v1/payment_links/
+//! ├── ebook.yaml         # id: "ebook"
+//! └── workshop.yaml      # id: "workshop"
+//! ```
+//!
+//! The `id` field defaults to the filename if not specified.
+//!
+//! # Example YAML
+//!
+//! ```yaml
+//! # billing/v1/payment_links/workshop.yaml
+//! line_items:
+//!   - product: workshop
+//!     quantity: 1
+//!     adjustable_quantity:
+//!       enabled: true
+//!       maximum: 10
+//! custom_fields:
+//!   - key: company
+//!     label: Company name
+//!     optional: true
+//!   - key: tshirt
+//!     label: T-shirt size
+//!     type: dropdown
+//!     options:
+//!       - label: Medium
+//!         value: m
+//!       - label: Large
+//!         value: l
+//! success_url: https://example.com/thanks
+//! ```
+
+use std::path::Path;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+/// Most custom fields a payment link can ask for, as on Stripe
+pub const MAX_CUSTOM_FIELDS: usize = 3;
+
+/// Highest quantity buyers can pick unless the line item sets its own maximum
+pub const DEFAULT_MAX_QUANTITY: i64 = 99;
+
+/// Payment link declared in the catalog or created through the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentLink {
+    /// Payment link ID (defaults to the filename)
+    #[serde(default)]
+    pub id: String,
+
+    #[serde(default = "default_active")]
+    pub active: bool,
+
+    /// Prices sold by the link
+    pub line_items: Vec<PaymentLinkLineItem>,
+
+    /// Fields buyers fill in before paying
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_fields: Vec<CustomField>,
+
+    /// Where buyers are sent once they paid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success_url: Option<String>,
+
+    /// Currency buyers pay in, for prices with an amount in it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+
+    /// When the link was created (Unix seconds), for links created through the API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<i64>,
+
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub metadata: IndexMap<String, String>,
+}
+
+/// Price sold by a payment link
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentLinkLineItem {
+    /// Price sold
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price: Option<String>,
+
+    /// Product sold at its first active price, when no price is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+
+    #[serde(default = "default_quantity")]
+    pub quantity: i64,
+
+    /// Whether and how far buyers can change the quantity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adjustable_quantity: Option<AdjustableQuantity>,
+}
+
+/// Quantity range buyers can pick from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AdjustableQuantity {
+    #[serde(default = "default_active")]
+    pub enabled: bool,
+
+    /// Lowest quantity (defaults to 1)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<i64>,
+
+    /// Highest quantity (defaults to 99)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<i64>,
+}
+
+impl AdjustableQuantity {
+    pub fn minimum(&self) -> i64 {
+        self.minimum.unwrap_or(1)
+    }
+
+    pub fn maximum(&self) -> i64 {
+        self.maximum.unwrap_or(DEFAULT_MAX_QUANTITY)
+    }
+}
+
+/// Kind of value a custom field takes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomFieldType {
+    /// Free text
+    #[default]
+    Text,
+    /// Digits only
+    Numeric,
+    /// One of the field's options
+    Dropdown,
+}
+
+impl CustomFieldType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CustomFieldType::Text => "text",
+            CustomFieldType::Numeric => "numeric",
+            CustomFieldType::Dropdown => "dropdown",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "text" => Some(CustomFieldType::Text),
+            "numeric" => Some(CustomFieldType::Numeric),
+            "dropdown" => Some(CustomFieldType::Dropdown),
+            _ => None,
+        }
+    }
+}
+
+/// Field buyers fill in before paying
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomField {
+    /// Key the value is stored under, alphanumeric
+    pub key: String,
+
+    /// Label shown to buyers
+    pub label: String,
+
+    #[serde(rename = "type", default)]
+    pub field_type: CustomFieldType,
+
+    /// Whether buyers can leave the field empty
+    #[serde(default)]
+    pub optional: bool,
+
+    /// Values of a dropdown field
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<CustomFieldOption>,
+}
+
+/// Value of a dropdown custom field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomFieldOption {
+    pub label: String,
+    pub value: String,
+}
+
+impl CustomField {
+    /// Check the value a buyer entered, returning it trimmed, or None for an empty
+    /// optional field
+    pub fn check_value(&self, value: Option<&str>) -> Result<Option<String>, String> {
+        let value = value.map(str::trim).filter(|value| !value.is_empty());
+        let Some(value) = value else {
+            if self.optional {
+                return Ok(None);
+            }
+            return Err(format!("{} is required", self.label));
+        };
+        match self.field_type {
+            CustomFieldType::Text => {}
+            CustomFieldType::Numeric => {
+                if !value.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(format!("{} must be a number", self.label));
+                }
+            }
+            CustomFieldType::Dropdown => {
+                if !self.options.iter().any(|option| option.value == value) {
+                    return Err(format!("{} must be one of its options", self.label));
+                }
+            }
+        }
+        Ok(Some(value.to_string()))
+    }
+}
+
+fn default_active() -> bool {
+    true
+}
+
+fn default_quantity() -> i64 {
+    1
+}
+
+impl PaymentLink {
+    /// Set the payment link ID if it was not declared, from the link's filename
+    pub fn with_id_from_filename(mut self, filename: &str) -> Self {
+        if self.id.is_empty() {
+            self.id = filename.to_string();
+        }
+        self
+    }
+
+    /// Check that the link sells something, in quantities buyers can pick, and asks for
+    /// well-formed custom fields
+    pub fn validate(&self) -> Result<(), String> {
+        if self.line_items.is_empty() {
+            return Err(format!("Payment link '{}' has no line items", self.id));
+        }
+        for item in &self.line_items {
+            if item.price.is_some() == item.product.is_some() {
+                return Err(format!(
+                    "Payment link '{}': line items need exactly one of price or product",
+                    self.id
+                ));
+            }
+            if item.quantity < 1 {
+                return Err(format!(
+                    "Payment link '{}': quantity must be at least 1",
+                    self.id
+                ));
+            }
+            if let Some(adjustable) = item.adjustable_quantity.filter(|a| a.enabled)
+                && !(adjustable.minimum() >= 1
+                    && adjustable.minimum() <= item.quantity
+                    && item.quantity <= adjustable.maximum())
+            {
+                return Err(format!(
+                    "Payment link '{}': quantity must be between the adjustable minimum and maximum",
+                    self.id
+                ));
+            }
+        }
+
+        if self.custom_fields.len() > MAX_CUSTOM_FIELDS {
+            return Err(format!(
+                "Payment link '{}' can ask for at most {} custom fields",
+                self.id, MAX_CUSTOM_FIELDS
+            ));
+        }
+        for (i, field) in self.custom_fields.iter().enumerate() {
+            if field.key.is_empty() || !field.key.bytes().all(|b| b.is_ascii_alphanumeric()) {
+                return Err(format!(
+                    "Payment link '{}': custom field keys must be alphanumeric",
+                    self.id
+                ));
+            }
+            if self.custom_fields[..i].iter().any(|f| f.key == field.key) {
+                return Err(format!(
+                    "Payment link '{}': duplicate custom field '{}'",
+                    self.id, field.key
+                ));
+            }
+            if (field.field_type == CustomFieldType::Dropdown) == field.options.is_empty() {
+                return Err(format!(
+                    "Payment link '{}': only dropdown custom fields have options, and they need some",
+                    self.id
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether buyers pick quantities or fill in fields before a checkout session opens
+    pub fn needs_buyer_input(&self) -> bool {
+        !self.custom_fields.is_empty()
+            || self
+                .line_items
+                .iter()
+                .any(|item| item.adjustable_quantity.is_some_and(|a| a.enabled))
+    }
+}
+
+/// Load payment links from a directory of YAML files
+///
+/// Each `.yaml` or `.yml` file in the directory is loaded as a payment link. Links that
+/// fail validation are rejected.
+///
+/// # Arguments
+/// * `payment_links_dir` - Path to the payment links directory (e.g., `billing/v1/payment_links`)
+pub fn load_payment_links_from_dir(payment_links_dir: &Path) -> Result<Vec<PaymentLink>, String> {
+    let mut payment_links: Vec<PaymentLink> = Vec::new();
+
+    if !payment_links_dir.exists() {
+        return Ok(payment_links);
+    }
+
+    let mut paths = std::fs::read_dir(payment_links_dir)
+        .map_err(|e| format!("Failed to read payment links directory: {}", e))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read entry: {}", e))?;
+    paths.sort();
+
+    for path in paths {
+        if !path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml")
+        {
+            continue;
+        }
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let payment_link: PaymentLink = serde_yml::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+        let filename = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| format!("Invalid filename: {}", path.display()))?;
+        let payment_link = payment_link.with_id_from_filename(filename);
+        payment_link.validate()?;
+
+        if payment_links.iter().any(|l| l.id == payment_link.id) {
+            return Err(format!("Duplicate payment link ID '{}'", payment_link.id));
+        }
+        payment_links.push(payment_link);
+    }
+
+    Ok(payment_links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment_link(yaml: &str) -> PaymentLink {
+        serde_yml::from_str::<PaymentLink>(yaml)
+            .unwrap()
+            .with_id_from_filename("test")
+    }
+
+    #[test]
+    fn test_parse_payment_link() {
+        let link = payment_link(
+            r#"
+line_items:
+  - product: workshop
+    adjustable_quantity:
+      enabled: true
+      maximum: 10
+custom_fields:
+  - key: tshirt
+    label: T-shirt size
+    type: dropdown
+    options:
+      - label: Medium
+        value: m
+"#,
+        );
+        assert_eq!(link.id, "test");
+        assert!(link.active);
+        assert_eq!(link.line_items[0].quantity, 1);
+        let adjustable = link.line_items[0].adjustable_quantity.unwrap();
+        assert_eq!((adjustable.minimum(), adjustable.maximum()), (1, 10));
+        assert_eq!(link.custom_fields[0].field_type, CustomFieldType::Dropdown);
+        assert!(link.validate().is_ok());
+        assert!(link.needs_buyer_input());
+    }
+
+    #[test]
+    fn test_validate_payment_link() {
+        assert!(payment_link("line_items: []").validate().is_err());
+        assert!(
+            payment_link("line_items: [{price: price_1, product: pro}]")
+                .validate()
+                .is_err()
+        );
+        assert!(
+            payment_link("line_items: [{price: price_1, quantity: 0}]")
+                .validate()
+                .is_err()
+        );
+        assert!(
+            payment_link(
+                "line_items: [{price: price_1, quantity: 5, adjustable_quantity: {enabled: true, maximum: 3}}]"
+            )
+            .validate()
+            .is_err()
+        );
+        assert!(
+            payment_link(
+                "line_items: [{price: price_1}]\ncustom_fields: [{key: first-name, label: Name}]"
+            )
+            .validate()
+            .is_err()
+        );
+        assert!(
+            payment_link("line_items: [{price: price_1}]\ncustom_fields: [{key: size, label: Size, type: dropdown}]")
+                .validate()
+                .is_err()
+        );
+        let link = payment_link("line_items: [{price: price_1, quantity: 2}]");
+        assert!(link.validate().is_ok());
+        assert!(!link.needs_buyer_input());
+    }
+
+    #[test]
+    fn test_check_custom_field_value() {
+        let field = CustomField {
+            key: "seats".to_string(),
+            label: "Seats".to_string(),
+            field_type: CustomFieldType::Numeric,
+            optional: false,
+            options: Vec::new(),
+        };
+        assert_eq!(field.check_value(Some(" 12 ")), Ok(Some("12".to_string())));
+        assert!(field.check_value(Some("twelve")).is_err());
+        assert!(field.check_value(Some("")).is_err());
+
+        let field = CustomField {
+            optional: true,
+            ..field
+        };
+        assert_eq!(field.check_value(None), Ok(None));
+    }
+}
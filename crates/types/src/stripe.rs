@@ -88,6 +88,9 @@ pub struct StripePrice {
     pub currency: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub unit_amount: Option<i64>,
+    /// Unit amount in cents as a decimal string, exact for amounts below a cent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit_amount_decimal: Option<String>,
     pub product: String,
     #[serde(rename = "type")]
     pub pricing_type: String,
@@ -97,6 +100,42 @@ pub struct StripePrice {
     pub created: i64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub recurring: Option<StripeRecurring>,
+    /// Whether the amount includes tax (exclusive, inclusive or unspecified)
+    pub tax_behavior: String,
+    /// Amounts in other currencies, by currency code
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    pub currency_options: IndexMap<String, StripeCurrencyOption>,
+    /// per_unit, or tiered when charged by its tiers
+    pub billing_scheme: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tiers: Vec<StripePriceTier>,
+    /// graduated or volume
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tiers_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transform_quantity: Option<StripeTransformQuantity>,
+}
+
+/// Amount of a price in one of its other currencies
+#[derive(Debug, Clone, Serialize)]
+pub struct StripeCurrencyOption {
+    pub unit_amount: i64,
+}
+
+/// Tier of a tiered price, the last one having no `up_to`
+#[derive(Debug, Clone, Serialize)]
+pub struct StripePriceTier {
+    pub flat_amount: Option<i64>,
+    pub unit_amount: Option<i64>,
+    pub up_to: Option<i64>,
+}
+
+/// Package pricing: quantities are divided before being charged
+#[derive(Debug, Clone, Serialize)]
+pub struct StripeTransformQuantity {
+    pub divide_by: i64,
+    /// up or down
+    pub round: String,
 }
 
 /// Stripe-compatible recurring configuration
@@ -139,12 +178,58 @@ impl StripePrice {
             active: price.active,
             currency: price.currency.as_str().to_string(),
             unit_amount: price.unit_amount,
+            unit_amount_decimal: price
+                .unit_amount_decimal
+                .map(|amount| amount.to_string())
+                .or_else(|| price.unit_amount.map(|amount| amount.to_string())),
             product: product_id.cloned().unwrap_or_else(|| product.id.clone()),
             pricing_type: price.pricing_type.as_str().to_string(),
             nickname: price.nickname.clone(),
             metadata: price.metadata.clone(),
             created: price.created_at.timestamp(),
             recurring,
+            tax_behavior: price
+                .tax_behavior
+                .map(|t| t.as_str())
+                .unwrap_or("unspecified")
+                .to_string(),
+            currency_options: price
+                .currency_options
+                .iter()
+                .map(|(currency, unit_amount)| {
+                    (
+                        currency.clone(),
+                        StripeCurrencyOption {
+                            unit_amount: *unit_amount,
+                        },
+                    )
+                })
+                .collect(),
+            billing_scheme: if price.is_tiered() {
+                "tiered".to_string()
+            } else {
+                "per_unit".to_string()
+            },
+            tiers: price
+                .tiers
+                .iter()
+                .map(|tier| {
+                    let (unit_amount, flat_amount) =
+                        tier.amounts_in(price.currency.as_str()).unwrap_or_default();
+                    StripePriceTier {
+                        flat_amount: Some(flat_amount),
+                        unit_amount: Some(unit_amount),
+                        up_to: tier.up_to,
+                    }
+                })
+                .collect(),
+            tiers_mode: price.tiers_mode.map(|mode| mode.as_str().to_string()),
+            transform_quantity: price
+                .transform_quantity
+                .map(|transform| StripeTransformQuantity {
+                    divide_by: transform.divide_by,
+                    round: transform.round.as_str().to_string(),
+                }),
         }
     }
 }
@@ -61,11 +61,21 @@ impl FacilitatorNetworkConfig {
             FacilitatorNetworkConfig::SolanaMainnet(cfg) => &cfg.rpc_url,
         }
     }
+    /// Primary RPC URL followed by the fallback URLs, in order of preference
+    pub fn rpc_urls(&self) -> Vec<&Url> {
+        let (primary, fallbacks) = match self {
+            FacilitatorNetworkConfig::SolanaSurfnet(cfg) => (&cfg.rpc_url, &cfg.fallback_rpc_urls),
+            FacilitatorNetworkConfig::SolanaMainnet(cfg) => (&cfg.rpc_url, &cfg.fallback_rpc_urls),
+        };
+        std::iter::once(primary).chain(fallbacks.iter()).collect()
+    }
 }
 
 #[derive(Debug)]
 pub struct SolanaSurfnetFacilitatorConfig {
     pub rpc_url: Url,
+    /// RPC URLs tried in order when `rpc_url` is unavailable
+    pub fallback_rpc_urls: Vec<Url>,
     pub payer_pubkey: Option<Pubkey>,
 }
 
@@ -78,6 +88,7 @@ impl Default for SolanaSurfnetFacilitatorConfig {
             )
             .parse::<Url>()
             .expect("Failed to parse default RPC URL"),
+            fallback_rpc_urls: vec![],
             payer_pubkey: None,
         }
     }
@@ -155,6 +166,8 @@ impl SolanaSurfnetFacilitatorConfig {
 #[derive(Debug)]
 pub struct SolanaMainnetFacilitatorConfig {
     pub rpc_url: Url,
+    /// RPC URLs tried in order when `rpc_url` is unavailable
+    pub fallback_rpc_urls: Vec<Url>,
     pub payer_pubkey: Option<Pubkey>,
 }
 
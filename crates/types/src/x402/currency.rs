@@ -6,6 +6,17 @@ use crate::x402::{MixedAddress, Network};
 pub const USDC_MINT: Pubkey =
     Pubkey::from_str_const("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
 
+/// EURC mint address on Solana mainnet
+pub const EURC_MINT: Pubkey =
+    Pubkey::from_str_const("HzwqbKZw8HxMN6bF2yFZNrht3c2iXXzpKcFu7uBEDKtr");
+
+/// Stablecoins with a known mint, and the fiat currency each one tracks
+const STABLECOINS: &[(&str, Pubkey, &str)] =
+    &[("USDC", USDC_MINT, "usd"), ("EURC", EURC_MINT, "eur")];
+
+/// Decimals of the stablecoins payments settle in (USDC, EURC)
+pub const STABLECOIN_DECIMALS: u8 = 6;
+
 /// SPL Token program ID
 pub const SPL_TOKEN_PROGRAM_ID: Pubkey =
     Pubkey::from_str_const("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
@@ -44,6 +55,13 @@ impl Currency {
             Currency::Solana(solana_currency) => Some(solana_currency),
         }
     }
+
+    /// Fiat currency the stablecoin tracks (e.g., "usd" for USDC)
+    pub fn fiat_currency(&self) -> &'static str {
+        match self {
+            Currency::Solana(solana_currency) => solana_currency.fiat_currency(),
+        }
+    }
 }
 
 /// Represents a currency on the Solana blockchain for billing purposes
@@ -61,15 +79,30 @@ pub struct SolanaCurrency {
 
 impl SolanaCurrency {
     pub fn from_symbol(symbol: &str) -> Result<Self, String> {
-        // TODO Placeholder implementation - in real code, this would look up the mint and token program
+        // TODO Placeholder implementation - other symbols fall back to the USDC mint until
+        // mints and token programs are looked up
+        let mint = STABLECOINS
+            .iter()
+            .find(|(known, _, _)| known.eq_ignore_ascii_case(symbol))
+            .map(|(_, mint, _)| *mint)
+            .unwrap_or(USDC_MINT);
         Ok(SolanaCurrency {
             symbol: symbol.to_string(),
-            mint: USDC_MINT,
+            mint,
             token_program: SPL_TOKEN_PROGRAM_ID,
-            decimals: 6,
+            decimals: STABLECOIN_DECIMALS,
         })
     }
 
+    /// Fiat currency the stablecoin tracks, by its mint (USD for unknown mints)
+    pub fn fiat_currency(&self) -> &'static str {
+        STABLECOINS
+            .iter()
+            .find(|(_, mint, _)| *mint == self.mint)
+            .map(|(_, _, fiat)| *fiat)
+            .unwrap_or("usd")
+    }
+
     pub fn mixed_address(&self) -> MixedAddress {
         MixedAddress::Solana(self.mint)
     }
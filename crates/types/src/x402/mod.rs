@@ -12,7 +12,9 @@ mod currency;
 mod recipient;
 pub mod transactions;
 
-pub use currency::{Currency, SPL_TOKEN_PROGRAM_ID, SolanaCurrency, USDC_MINT};
+pub use currency::{
+    Currency, EURC_MINT, SPL_TOKEN_PROGRAM_ID, STABLECOIN_DECIMALS, SolanaCurrency, USDC_MINT,
+};
 pub use recipient::{
     LocalManagedRecipient, MoneyMqManagedRecipient, Recipient, RemoteManagedRecipient,
 };
@@ -12,6 +12,8 @@ pub enum TransactionStatus {
 pub struct TransactionCustomer {
     pub label: Option<String>,
     pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub customer: Option<String>, // Customer the paying wallet is attached to
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -42,4 +44,8 @@ pub struct FacilitatedTransaction {
     // Payment stack context
     pub payment_stack_id: String, // The payment stack ID (subdomain) that processed this transaction
     pub is_sandbox: bool,         // Whether this transaction was processed in sandbox mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax: Option<crate::PaymentTax>, // Tax broken out of the amount, when charged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fx: Option<crate::PaymentFx>, // Conversion into the settlement currency, when applied
 }
@@ -129,6 +129,7 @@ export async function payUpon402<T>(
           payload: {
             transaction: "mock_base58_encoded_transaction",
           },
+          accepted: accepts[0],
         };
         paymentHeaderValue = Buffer.from(JSON.stringify(mockPayload)).toString(
           "base64",
@@ -159,6 +160,14 @@ export async function payUpon402<T>(
           selectedPaymentRequirement,
           config,
         );
+
+        // Echo the accepted requirements, which carry the signed quote in extra.quote
+        const payment = JSON.parse(
+          Buffer.from(paymentHeaderValue, "base64").toString(),
+        );
+        paymentHeaderValue = Buffer.from(
+          JSON.stringify({ ...payment, accepted: selectedPaymentRequirement }),
+        ).toString("base64");
       }
 
       console.log("✅ Payment header created, retrying request...");